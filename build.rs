@@ -0,0 +1,21 @@
+use std::env;
+
+/// FFmpeg versions that the crate has version-specific code paths for.
+const FFMPEG_VERSIONS: &[&str] = &["ffmpeg_4_0", "ffmpeg_5_0", "ffmpeg_5_1", "ffmpeg_6_0"];
+
+fn main() {
+    for version in FFMPEG_VERSIONS {
+        println!("cargo:rustc-check-cfg=cfg({version})");
+    }
+
+    // `ffmpeg-sys-next` reports the FFmpeg version it was built against as `ffmpeg_X_Y` metadata
+    // for each version that is at least `X.Y`. Forward those as cfgs, like `ffmpeg-next` does.
+    for (name, _value) in env::vars() {
+        if let Some(version) = name.strip_prefix("DEP_FFMPEG_FFMPEG_") {
+            let version = format!("ffmpeg_{}", version.to_lowercase());
+            if FFMPEG_VERSIONS.contains(&version.as_str()) {
+                println!("cargo:rustc-cfg={version}");
+            }
+        }
+    }
+}
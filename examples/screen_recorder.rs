@@ -0,0 +1,35 @@
+//! Records the screen to `screen.mp4` for ten seconds.
+//!
+//! ```sh
+//! cargo run --example screen_recorder
+//! ```
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use video_rs::{Decoder, Encoder, EncoderSettings, ScreenCaptureConfig, Timestamper};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    video_rs::init()?;
+
+    let config = ScreenCaptureConfig::default();
+    let frame_rate = config.frame_rate;
+    let mut decoder = Decoder::new_screen_capture(config)?;
+
+    let (width, height) = decoder.size_out();
+    let settings = EncoderSettings::for_h264_yuv420p(width as usize, height as usize, true);
+    let mut encoder = Encoder::new(&PathBuf::from("screen.mp4").into(), settings)?;
+    let mut timestamper =
+        Timestamper::new(Some(frame_rate as f64)).with_time_base(encoder.time_base());
+
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(10) {
+        let mut frame = decoder.decode_raw_bgra()?;
+        frame.set_pts(timestamper.next().into_value());
+        encoder.encode_raw(frame)?;
+    }
+
+    encoder.finish()?;
+
+    Ok(())
+}
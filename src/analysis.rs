@@ -0,0 +1,350 @@
+use crate::error::Error;
+use crate::scene::{luma_difference, mean_luma, LumaSampler};
+use crate::time::Time;
+use crate::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What to look for in [`crate::Decoder::analyze`]. Each detection is off when its parameters are
+/// `None`.
+///
+/// # Example
+///
+/// ```ignore
+/// let config = AnalysisConfig {
+///     detect_black: Some(BlackParams::default()),
+///     detect_freeze: Some(FreezeParams {
+///         difference_threshold: 0.002,
+///         min_duration: Time::from_secs(5.0),
+///     }),
+/// };
+/// ```
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalysisConfig {
+    /// Find black segments.
+    pub detect_black: Option<BlackParams>,
+    /// Find frozen segments.
+    pub detect_freeze: Option<FreezeParams>,
+}
+
+/// Parameters of the detection of black segments.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlackParams {
+    /// Mean luma of the frame, as a fraction of the full range, below which it is black. Defaults
+    /// to `0.1`.
+    pub luma_threshold: f32,
+    /// Shortest run of black frames that is reported. Defaults to two seconds.
+    pub min_duration: Time,
+}
+
+impl Default for BlackParams {
+    fn default() -> Self {
+        Self {
+            luma_threshold: 0.1,
+            min_duration: Time::from_secs(2.0),
+        }
+    }
+}
+
+/// Parameters of the detection of frozen segments.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FreezeParams {
+    /// Mean absolute luma difference with the frame before, as a fraction of the full range,
+    /// below which a frame is the same as the frame before. This is the same score as the scene
+    /// change score of [`crate::Decoder::detect_scene_changes`]. Defaults to `0.003`, which
+    /// leaves room for coding noise.
+    pub difference_threshold: f32,
+    /// Shortest run of frozen frames that is reported. Defaults to two seconds.
+    pub min_duration: Time,
+}
+
+impl Default for FreezeParams {
+    fn default() -> Self {
+        Self {
+            difference_threshold: 0.003,
+            min_duration: Time::from_secs(2.0),
+        }
+    }
+}
+
+/// Segment of a video, from the timestamp of its first frame up to (not including) the timestamp
+/// of the frame after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeSpan {
+    /// Timestamp of the first frame.
+    pub start: Time,
+    /// End of the last frame.
+    pub end: Time,
+}
+
+impl TimeSpan {
+    /// Get the duration of the segment.
+    pub fn duration(&self) -> Time {
+        self.end.clone() - self.start.clone()
+    }
+}
+
+/// Result of [`crate::Decoder::analyze`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnalysisReport {
+    /// Black segments, in order. Empty if black frames were not looked for.
+    pub black: Vec<TimeSpan>,
+    /// Frozen segments, in order. Empty if frozen frames were not looked for.
+    pub frozen: Vec<TimeSpan>,
+}
+
+/// Keeps track of a run of frames that match a condition, and reports the runs that are long
+/// enough.
+struct RunTracker {
+    min_duration: Time,
+    start: Option<Time>,
+    spans: Vec<TimeSpan>,
+}
+
+impl RunTracker {
+    /// Create a run tracker.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_duration` - Shortest run that is kept.
+    fn new(min_duration: Time) -> Self {
+        Self {
+            min_duration,
+            start: None,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Pass whether the next frame matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `matches` - Whether the frame matches.
+    /// * `start` - Where the run starts if this frame starts it.
+    /// * `timestamp` - Timestamp of the frame, where the run ends if this frame ends it.
+    fn push(&mut self, matches: bool, start: &Time, timestamp: &Time) {
+        match (matches, self.start.is_some()) {
+            (true, false) => self.start = Some(start.clone()),
+            (false, true) => self.end(timestamp),
+            _ => {}
+        }
+    }
+
+    /// End the run (if any) at some time, and keep it if it is long enough.
+    ///
+    /// # Arguments
+    ///
+    /// * `end` - End of the run.
+    fn end(&mut self, end: &Time) {
+        if let Some(start) = self.start.take() {
+            let span = TimeSpan {
+                start,
+                end: end.clone(),
+            };
+            if span.duration() >= self.min_duration {
+                self.spans.push(span);
+            }
+        }
+    }
+}
+
+/// Finds black and frozen segments in a stream of frames (see [`crate::Decoder::analyze`]).
+///
+/// Frames are scaled down to the luma plane that scene changes are scored on as well, once for
+/// both detections.
+pub(crate) struct Analyzer {
+    sampler: LumaSampler,
+    black: Option<(f32, RunTracker)>,
+    freeze: Option<(f32, RunTracker)>,
+    /// Luma and timestamp of the frame before.
+    previous: Option<(Vec<u8>, Time)>,
+    /// Distance between the timestamps of the last two frames.
+    last_duration: Option<Time>,
+}
+
+impl Analyzer {
+    /// Create an analyzer.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - What to look for.
+    pub(crate) fn new(config: AnalysisConfig) -> Result<Self> {
+        let thresholds = [
+            config
+                .detect_black
+                .as_ref()
+                .map(|black| black.luma_threshold),
+            config
+                .detect_freeze
+                .as_ref()
+                .map(|freeze| freeze.difference_threshold),
+        ];
+        if let Some(threshold) = thresholds
+            .into_iter()
+            .flatten()
+            .find(|threshold| !(0.0..=1.0).contains(threshold))
+        {
+            return Err(Error::InvalidOptions(format!(
+                "invalid analysis threshold: {threshold}"
+            )));
+        }
+
+        Ok(Self {
+            sampler: LumaSampler::new(),
+            black: config
+                .detect_black
+                .map(|black| (black.luma_threshold, RunTracker::new(black.min_duration))),
+            freeze: config.detect_freeze.map(|freeze| {
+                (
+                    freeze.difference_threshold,
+                    RunTracker::new(freeze.min_duration),
+                )
+            }),
+            previous: None,
+            last_duration: None,
+        })
+    }
+
+    /// Pass the next frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Next frame.
+    /// * `timestamp` - Timestamp of the frame.
+    pub(crate) fn push(&mut self, frame: &RawFrame, timestamp: Time) -> Result<()> {
+        if self.black.is_none() && self.freeze.is_none() {
+            return Ok(());
+        }
+        let luma = self.sampler.sample(frame)?;
+        self.push_luma(luma, timestamp);
+
+        Ok(())
+    }
+
+    /// Get the segments that were found. The segments that last up to the last frame end one
+    /// frame duration (the distance between the timestamps of the last two frames) after it.
+    pub(crate) fn finish(mut self) -> AnalysisReport {
+        if let Some((_, timestamp)) = self.previous.take() {
+            let end = match self.last_duration.take() {
+                Some(duration) => timestamp + duration,
+                None => timestamp,
+            };
+            for (_, tracker) in self.black.iter_mut().chain(self.freeze.iter_mut()) {
+                tracker.end(&end);
+            }
+        }
+
+        AnalysisReport {
+            black: self
+                .black
+                .map(|(_, tracker)| tracker.spans)
+                .unwrap_or_default(),
+            frozen: self
+                .freeze
+                .map(|(_, tracker)| tracker.spans)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Pass the luma samples of the next frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `luma` - Luma samples of the frame, scaled down to the analysis size.
+    /// * `timestamp` - Timestamp of the frame.
+    fn push_luma(&mut self, luma: Vec<u8>, timestamp: Time) {
+        if let Some((threshold, tracker)) = self.black.as_mut() {
+            tracker.push(mean_luma(&luma) < *threshold, &timestamp, &timestamp);
+        }
+        if let Some((threshold, tracker)) = self.freeze.as_mut() {
+            // A frozen run starts at the frame that the first frozen frame is the same as.
+            match self.previous.as_ref() {
+                Some((previous, previous_timestamp)) => tracker.push(
+                    luma_difference(previous, &luma) < *threshold,
+                    previous_timestamp,
+                    &timestamp,
+                ),
+                None => tracker.push(false, &timestamp, &timestamp),
+            }
+        }
+        if let Some((_, previous_timestamp)) = self.previous.as_ref() {
+            self.last_duration = Some(timestamp.clone() - previous_timestamp.clone());
+        }
+        self.previous = Some((luma, timestamp));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg_next::Rational as AvRational;
+
+    fn at(frame: i64) -> Time {
+        Time::new(Some(frame), AvRational::new(1, 10))
+    }
+
+    fn span(start: i64, end: i64) -> TimeSpan {
+        TimeSpan {
+            start: at(start),
+            end: at(end),
+        }
+    }
+
+    #[test]
+    fn test_analyze_luma() {
+        let mut analyzer = Analyzer::new(AnalysisConfig {
+            detect_black: Some(BlackParams {
+                luma_threshold: 0.1,
+                min_duration: Time::from_millis(300),
+            }),
+            detect_freeze: Some(FreezeParams {
+                difference_threshold: 0.01,
+                min_duration: Time::from_millis(400),
+            }),
+        })
+        .unwrap();
+        // Black at 0-3 (long enough), moving at 4-9, black at 10-11 (too short), still at 12-19
+        // up to the end.
+        for i in 0..20u8 {
+            let luma = match i {
+                0..=3 | 10..=11 => vec![5; 16],
+                4..=9 => vec![100 + i * 10; 16],
+                _ => vec![200; 16],
+            };
+            analyzer.push_luma(luma, at(i as i64));
+        }
+        let report = analyzer.finish();
+        assert_eq!(report.black, vec![span(0, 4)]);
+        // Black frames are still as well.
+        assert_eq!(report.frozen, vec![span(0, 4), span(12, 20)]);
+    }
+
+    #[test]
+    fn test_analyze_nothing() {
+        let mut analyzer = Analyzer::new(AnalysisConfig {
+            detect_black: Some(BlackParams::default()),
+            detect_freeze: None,
+        })
+        .unwrap();
+        analyzer.push_luma(vec![0; 16], at(0));
+        let report = analyzer.finish();
+        // A single frame has no duration.
+        assert!(report.black.is_empty());
+        assert!(report.frozen.is_empty());
+
+        assert!(Analyzer::new(AnalysisConfig {
+            detect_black: None,
+            detect_freeze: Some(FreezeParams {
+                difference_threshold: -1.0,
+                min_duration: Time::zero(),
+            }),
+        })
+        .is_err());
+    }
+}
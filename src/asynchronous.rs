@@ -0,0 +1,385 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::decode::Decoder;
+use crate::encode::Encoder;
+use crate::error::Error;
+use crate::frame::RawFrame;
+use crate::interrupt::InterruptHandle;
+
+#[cfg(feature = "ndarray")]
+use crate::{frame::Frame, time::Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Default number of frames that can be queued between the worker thread and the caller.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Decoder that decodes on a dedicated worker thread so that it does not block the async runtime.
+///
+/// The worker decodes ahead of the caller, up to a small, bounded number of frames. Dropping the
+/// decoder cancels reading and stops the worker thread.
+///
+/// Decoded frames can be received through [`AsyncDecoder::decode`] or through the [`Stream`]
+/// implementation. The stream ends when the input is exhausted, or after the first error.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = Decoder::new(&PathBuf::from("video.mp4").into()).unwrap();
+/// let mut decoder = AsyncDecoder::new(decoder);
+/// while let Some(frame) = decoder.next().await {
+///     let (timestamp, frame) = frame.unwrap();
+///     println!("Got frame at {timestamp}!");
+/// }
+/// ```
+pub struct AsyncDecoder<T> {
+    receiver: mpsc::Receiver<Result<T>>,
+    interrupt_handle: Option<InterruptHandle>,
+    done: bool,
+}
+
+#[cfg(feature = "ndarray")]
+impl AsyncDecoder<(Time, Frame)> {
+    /// Create a new async decoder that decodes frames with [`Decoder::decode`].
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to move to the worker thread.
+    pub fn new(decoder: Decoder<'static>) -> Self {
+        Self::new_with_capacity(decoder, CHANNEL_CAPACITY)
+    }
+
+    /// Create a new async decoder that decodes frames with [`Decoder::decode`], and queues at
+    /// most `capacity` decoded frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to move to the worker thread.
+    /// * `capacity` - Maximum number of decoded frames to queue. Must be at least 1.
+    pub fn new_with_capacity(decoder: Decoder<'static>, capacity: usize) -> Self {
+        let interrupt_handle = decoder.interrupt_handle();
+        Self::spawn(decoder, Some(interrupt_handle), capacity, Decoder::decode)
+    }
+}
+
+impl AsyncDecoder<RawFrame> {
+    /// Create a new async decoder that decodes frames with [`Decoder::decode_raw`].
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to move to the worker thread.
+    pub fn new_raw(decoder: Decoder<'static>) -> Self {
+        Self::new_raw_with_capacity(decoder, CHANNEL_CAPACITY)
+    }
+
+    /// Create a new async decoder that decodes frames with [`Decoder::decode_raw`], and queues at
+    /// most `capacity` decoded frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to move to the worker thread.
+    /// * `capacity` - Maximum number of decoded frames to queue. Must be at least 1.
+    pub fn new_raw_with_capacity(decoder: Decoder<'static>, capacity: usize) -> Self {
+        let interrupt_handle = decoder.interrupt_handle();
+        Self::spawn(
+            decoder,
+            Some(interrupt_handle),
+            capacity,
+            Decoder::decode_raw,
+        )
+    }
+}
+
+impl<T: Send + 'static> AsyncDecoder<T> {
+    /// Decode a single frame. Once the input is exhausted, this returns
+    /// [`Error::ReadExhausted`], just like the blocking decoder.
+    pub async fn decode(&mut self) -> Result<T> {
+        self.receiver
+            .recv()
+            .await
+            .unwrap_or(Err(Error::ReadExhausted))
+    }
+
+    /// Spawn the worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - State to move to the worker thread (usually the decoder).
+    /// * `interrupt_handle` - Handle to cancel blocking reads with when the decoder is dropped.
+    /// * `capacity` - Maximum number of decoded frames to queue.
+    /// * `decode` - Function that decodes a single frame.
+    fn spawn<S: Send + 'static>(
+        mut state: S,
+        interrupt_handle: Option<InterruptHandle>,
+        capacity: usize,
+        mut decode: impl FnMut(&mut S) -> Result<T> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        spawn_worker("video-rs-decoder", move || loop {
+            let result = decode(&mut state);
+            let is_err = result.is_err();
+            // Sending fails when the receiving end was dropped, in which case there is nobody
+            // left to decode for.
+            if sender.blocking_send(result).is_err() || is_err {
+                break;
+            }
+        });
+
+        Self {
+            receiver,
+            interrupt_handle,
+            done: false,
+        }
+    }
+}
+
+impl<T: Send + 'static> Stream for AsyncDecoder<T> {
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(Err(Error::ReadExhausted))) | Poll::Ready(None) => {
+                self.done = true;
+                Poll::Ready(None)
+            }
+            Poll::Ready(Some(Err(err))) => {
+                self.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl<T> Drop for AsyncDecoder<T> {
+    fn drop(&mut self) {
+        // Abort any read the worker is blocked on. The worker stops once it notices that the
+        // receiving end is gone.
+        if let Some(interrupt_handle) = self.interrupt_handle.as_ref() {
+            interrupt_handle.cancel();
+        }
+    }
+}
+
+/// Command sent to the encoder worker thread.
+enum Command {
+    #[cfg(feature = "ndarray")]
+    Encode(Frame, Time),
+    EncodeRaw(RawFrame),
+}
+
+/// Encoder that encodes on a dedicated worker thread so that it does not block the async runtime.
+///
+/// Frames are queued to the worker, up to a small, bounded number of frames. Encoding calls only
+/// wait when the queue is full. Since frames are encoded in the background, an encoding error is
+/// returned by the next call after it occurred (or by [`AsyncEncoder::finish`]).
+///
+/// Dropping the encoder without calling [`AsyncEncoder::finish`] lets the worker encode the
+/// frames that are still queued and finish the encoder in the background.
+///
+/// # Example
+///
+/// ```ignore
+/// let encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings).unwrap();
+/// let mut encoder = AsyncEncoder::new(encoder);
+/// encoder.encode(frame, timestamp).await.unwrap();
+/// encoder.finish().await.unwrap();
+/// ```
+pub struct AsyncEncoder {
+    sender: Option<mpsc::Sender<Command>>,
+    done: Option<oneshot::Receiver<Result<()>>>,
+}
+
+impl AsyncEncoder {
+    /// Create a new async encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder to move to the worker thread.
+    pub fn new(encoder: Encoder) -> Self {
+        Self::new_with_capacity(encoder, CHANNEL_CAPACITY)
+    }
+
+    /// Create a new async encoder that queues at most `capacity` frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder to move to the worker thread.
+    /// * `capacity` - Maximum number of frames to queue. Must be at least 1.
+    pub fn new_with_capacity(mut encoder: Encoder, capacity: usize) -> Self {
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        let (done_sender, done) = oneshot::channel();
+        spawn_worker("video-rs-encoder", move || {
+            let mut run = || {
+                // This returns `None` once the sending end is dropped, either by `finish` or
+                // because the async encoder itself was dropped.
+                while let Some(command) = receiver.blocking_recv() {
+                    match command {
+                        #[cfg(feature = "ndarray")]
+                        Command::Encode(frame, timestamp) => encoder.encode(&frame, &timestamp)?,
+                        Command::EncodeRaw(frame) => encoder.encode_raw(frame)?,
+                    }
+                }
+
+                encoder.finish()
+            };
+
+            let _ = done_sender.send(run());
+        });
+
+        Self {
+            sender: Some(sender),
+            done: Some(done),
+        }
+    }
+
+    /// Encode a single `ndarray` frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode in `HWC` format and standard layout.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub async fn encode(&mut self, frame: Frame, source_timestamp: Time) -> Result<()> {
+        self.send(Command::Encode(frame, source_timestamp)).await
+    }
+
+    /// Encode a single raw frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    pub async fn encode_raw(&mut self, frame: RawFrame) -> Result<()> {
+        self.send(Command::EncodeRaw(frame)).await
+    }
+
+    /// Encode the frames that are still queued and finish the encoder. This flushes the encoder
+    /// and writes the trailer.
+    pub async fn finish(mut self) -> Result<()> {
+        // Dropping the sending end makes the worker finish the encoder after the queued frames.
+        self.sender = None;
+        self.wait().await
+    }
+
+    /// Queue a command to the worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - Command to queue.
+    async fn send(&mut self, command: Command) -> Result<()> {
+        let sender = self.sender.as_ref().ok_or(Error::WorkerStopped)?;
+        if sender.send(command).await.is_err() {
+            // The worker only stops early if encoding failed.
+            self.sender = None;
+            return match self.wait().await {
+                Err(err) => Err(err),
+                Ok(()) => Err(Error::WorkerStopped),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the worker thread to stop and get its result.
+    async fn wait(&mut self) -> Result<()> {
+        match self.done.take() {
+            Some(done) => done.await.unwrap_or(Err(Error::WorkerStopped)),
+            None => Err(Error::WorkerStopped),
+        }
+    }
+}
+
+/// Spawn a detached worker thread. The worker is responsible for stopping by itself once the
+/// channels it uses are closed.
+///
+/// # Arguments
+///
+/// * `name` - Name of the thread.
+/// * `f` - Function to run on the thread.
+fn spawn_worker(name: &str, f: impl FnOnce() + Send + 'static) {
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(f)
+        .expect("failed to spawn worker thread");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// Sets a flag when dropped, to find out whether the worker thread stopped.
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    async fn next<T: Send + 'static>(decoder: &mut AsyncDecoder<T>) -> Option<Result<T>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *decoder).poll_next(cx)).await
+    }
+
+    fn wait_for(flag: &AtomicBool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !flag.load(Ordering::SeqCst) {
+            if Instant::now() > deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    #[tokio::test]
+    async fn test_decoder_yields_frames_until_exhausted() {
+        let mut decoder = AsyncDecoder::spawn(0, None, 2, |count| {
+            *count += 1;
+            if *count <= 3 {
+                Ok(*count)
+            } else {
+                Err(Error::ReadExhausted)
+            }
+        });
+
+        assert_eq!(decoder.decode().await.unwrap(), 1);
+        assert_eq!(next(&mut decoder).await.unwrap().unwrap(), 2);
+        assert_eq!(decoder.decode().await.unwrap(), 3);
+        assert!(matches!(decoder.decode().await, Err(Error::ReadExhausted)));
+        assert!(matches!(decoder.decode().await, Err(Error::ReadExhausted)));
+        assert!(next(&mut decoder).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_decoder_stream_ends_after_error() {
+        let mut decoder =
+            AsyncDecoder::<()>::spawn((), None, 2, |_| Err(Error::MissingCodecParameters));
+        assert!(matches!(
+            next(&mut decoder).await,
+            Some(Err(Error::MissingCodecParameters))
+        ));
+        assert!(next(&mut decoder).await.is_none());
+    }
+
+    #[test]
+    fn test_decoder_drop_stops_worker() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let state = DropFlag(stopped.clone());
+        let decoder = AsyncDecoder::spawn(state, None, 1, |_| Ok(()));
+        drop(decoder);
+        assert!(wait_for(&stopped));
+    }
+}
@@ -0,0 +1,364 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::codec::packet::{Flags as AvPacketFlags, Packet as AvPacket};
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::format::context::{Input as AvInput, Output as AvOutput};
+use ffmpeg::format::stream::Disposition as AvDisposition;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::Dictionary as AvDictionary;
+
+use crate::ffi::{
+    parameters_extradata, set_stream_attached_pic, set_stream_attachment, stream_attached_pic,
+};
+use crate::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Represents a file embedded in a container, like a font or cover art.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    /// Name of the file. Empty if the container does not store one.
+    pub filename: String,
+    /// MIME type of the file, like `image/jpeg`. Empty if it is not known.
+    pub mime_type: String,
+    /// Contents of the file.
+    pub data: Vec<u8>,
+}
+
+/// Read the attachments of an input format context. This includes both attachment streams (as in
+/// Matroska) and attached pictures (as in MP4).
+///
+/// # Arguments
+///
+/// * `input` - Input format context.
+pub(crate) fn read_attachments(input: &AvInput) -> Vec<Attachment> {
+    input
+        .streams()
+        .filter_map(|stream| {
+            let parameters = stream.parameters();
+            let metadata = stream.metadata();
+            let image_format = ImageFormat::from_codec_id(parameters.id());
+            let mime_type = metadata
+                .get("mimetype")
+                .map(str::to_string)
+                .or_else(|| image_format.map(|format| format.mime_type().to_string()))
+                .unwrap_or_default();
+            let filename = metadata.get("filename").map(str::to_string);
+
+            if parameters.medium() == AvMediaType::Attachment {
+                Some(Attachment {
+                    filename: filename.unwrap_or_default(),
+                    mime_type,
+                    data: parameters_extradata(&parameters),
+                })
+            } else if stream.disposition().contains(AvDisposition::ATTACHED_PIC) {
+                Some(Attachment {
+                    filename: filename
+                        .or_else(|| {
+                            image_format.map(|format| format!("cover.{}", format.extension()))
+                        })
+                        .unwrap_or_default(),
+                    mime_type,
+                    data: stream_attached_pic(input, stream.index()),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Add a file (like a font for subtitles) to an output format context as an attachment stream.
+/// This must be done before the header is written. Only Matroska (and WebM) output supports
+/// attachments.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `filename` - Name of the file.
+/// * `mime_type` - MIME type of the file, like `font/ttf`.
+/// * `data` - Contents of the file.
+pub(crate) fn add_attachment(
+    output: &mut AvOutput,
+    filename: &str,
+    mime_type: &str,
+    data: &[u8],
+) -> Result<()> {
+    let format = output.format().name().to_string();
+    if !format.split(',').any(|name| name == "matroska") {
+        return Err(Error::AttachmentsNotSupported(format));
+    }
+    if filename.is_empty() || mime_type.is_empty() {
+        return Err(Error::InvalidOptions(
+            "attachments require a filename and a MIME type".to_string(),
+        ));
+    }
+
+    let mut stream = output.add_stream(ffmpeg::encoder::find(AvCodecId::None))?;
+    let mut metadata = AvDictionary::new();
+    metadata.set("filename", filename);
+    metadata.set("mimetype", mime_type);
+    stream.set_metadata(metadata);
+    let stream_index = stream.index();
+
+    set_stream_attachment(
+        output,
+        stream_index,
+        attachment_codec_id(mime_type).into(),
+        data,
+    )?;
+    Ok(())
+}
+
+/// Get the codec to store an attachment as. The Matroska muxer identifies attachments by their
+/// MIME type, so this only matters to readers that look at the codec.
+///
+/// # Arguments
+///
+/// * `mime_type` - MIME type of the attachment.
+fn attachment_codec_id(mime_type: &str) -> AvCodecId {
+    match mime_type {
+        "font/ttf" | "font/sfnt" | "application/x-truetype-font" => AvCodecId::TTF,
+        "font/otf" | "application/vnd.ms-opentype" => AvCodecId::OTF,
+        mime_type => ImageFormat::from_mime_type(mime_type)
+            .map(ImageFormat::codec_id)
+            .unwrap_or(AvCodecId::BIN_DATA),
+    }
+}
+
+/// Add cover art to an output format context. This must be done before the header is written.
+///
+/// Matroska stores cover art as an attachment. MP4 and QuickTime store it as an attached picture
+/// stream, which holds the picture in its only packet.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `data` - Encoded picture.
+/// * `mime_type` - MIME type of the picture (`image/jpeg` or `image/png`).
+///
+/// # Return value
+///
+/// Packet to write after the header, if the container format requires one.
+pub(crate) fn add_cover_art(
+    output: &mut AvOutput,
+    data: &[u8],
+    mime_type: &str,
+) -> Result<Option<AvPacket>> {
+    let image_format = ImageFormat::from_mime_type(mime_type)
+        .ok_or_else(|| Error::CoverArtNotSupported(format!("image type {mime_type}")))?;
+    let format = output.format().name().to_string();
+    let is_format = |names: &[&str]| format.split(',').any(|name| names.contains(&name));
+
+    if is_format(&["matroska"]) {
+        add_attachment(
+            output,
+            &format!("cover.{}", image_format.extension()),
+            image_format.mime_type(),
+            data,
+        )?;
+        Ok(None)
+    } else if is_format(&["mp4", "mov"]) {
+        let (width, height) = image_format
+            .size(data)
+            .ok_or(Error::CoverArtNotSupported("invalid image".to_string()))?;
+        let stream_index = output
+            .add_stream(ffmpeg::encoder::find(image_format.codec_id()))?
+            .index();
+        set_stream_attached_pic(
+            output,
+            stream_index,
+            image_format.codec_id().into(),
+            width,
+            height,
+        );
+
+        let mut packet = AvPacket::copy(data);
+        packet.set_stream(stream_index);
+        packet.set_pts(Some(0));
+        packet.set_dts(Some(0));
+        packet.set_flags(AvPacketFlags::KEY);
+        Ok(Some(packet))
+    } else {
+        Err(Error::CoverArtNotSupported(format!(
+            "container format {format}"
+        )))
+    }
+}
+
+/// Image formats that can be used as cover art.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    fn from_codec_id(codec_id: AvCodecId) -> Option<Self> {
+        match codec_id {
+            AvCodecId::MJPEG => Some(ImageFormat::Jpeg),
+            AvCodecId::PNG => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    /// Get the image format of a file from its extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file.
+    pub(crate) fn from_path(path: &std::path::Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    fn codec_id(self) -> AvCodecId {
+        match self {
+            ImageFormat::Jpeg => AvCodecId::MJPEG,
+            ImageFormat::Png => AvCodecId::PNG,
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+        }
+    }
+
+    /// Read the size of an image from its header.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Encoded image.
+    ///
+    /// # Return value
+    ///
+    /// Width and height of the image, or `None` if the image is not valid.
+    fn size(self, data: &[u8]) -> Option<(u32, u32)> {
+        let read_u16 = |offset: usize| {
+            data.get(offset..offset + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as u32)
+        };
+        let read_u32 = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+
+        match self {
+            ImageFormat::Png => {
+                // The signature is followed by the `IHDR` chunk, which starts with the size.
+                if !data.starts_with(b"\x89PNG\r\n\x1a\n") || data.get(12..16) != Some(b"IHDR") {
+                    return None;
+                }
+                Some((read_u32(16)?, read_u32(20)?))
+            }
+            ImageFormat::Jpeg => {
+                if !data.starts_with(&[0xff, 0xd8]) {
+                    return None;
+                }
+                // Walk the segments until the start of frame, which holds the size.
+                let mut offset = 2;
+                loop {
+                    if *data.get(offset)? != 0xff {
+                        return None;
+                    }
+                    let marker = *data.get(offset + 1)?;
+                    match marker {
+                        // Fill bytes.
+                        0xff => offset += 1,
+                        // Start of frame markers (excluding DHT, JPG and DAC).
+                        0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                            return Some((read_u16(offset + 7)?, read_u16(offset + 5)?));
+                        }
+                        _ => offset += 2 + read_u16(offset + 2)? as usize,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+    use crate::{Decoder, Encoder};
+
+    /// Header of a PNG image of 320 by 240 pixels.
+    const PNG_HEADER: &[u8] =
+        b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\x00\x00\x01\x40\x00\x00\x00\xf0";
+
+    /// Header of a JPEG image of 320 by 240 pixels, with an APP0 segment before the start of
+    /// frame.
+    const JPEG_HEADER: &[u8] = &[
+        0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00, 0xf0,
+        0x01, 0x40,
+    ];
+
+    #[test]
+    fn test_image_size() {
+        assert_eq!(ImageFormat::Png.size(PNG_HEADER), Some((320, 240)));
+        assert_eq!(ImageFormat::Jpeg.size(JPEG_HEADER), Some((320, 240)));
+        assert_eq!(ImageFormat::Png.size(JPEG_HEADER), None);
+        assert_eq!(ImageFormat::Jpeg.size(PNG_HEADER), None);
+        assert_eq!(ImageFormat::Jpeg.size(&JPEG_HEADER[..12]), None);
+    }
+
+    #[test]
+    fn test_image_format_from_mime_type() {
+        assert_eq!(
+            ImageFormat::from_mime_type("image/jpeg"),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageFormat::from_mime_type("image/png"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(ImageFormat::from_mime_type("image/gif"), None);
+    }
+
+    #[test]
+    fn test_attachment_codec_id() {
+        assert_eq!(attachment_codec_id("font/ttf"), AvCodecId::TTF);
+        assert_eq!(attachment_codec_id("image/png"), AvCodecId::PNG);
+        assert_eq!(attachment_codec_id("text/plain"), AvCodecId::BIN_DATA);
+    }
+
+    #[test]
+    fn test_cover_art_round_trip() {
+        for name in ["cover.mkv", "cover.mp4"] {
+            let path = TempPath::new(name);
+            let mut encoder = Encoder::new(&path.locator(), testing::settings()).unwrap();
+            encoder.attach_cover_art(PNG_HEADER, "image/png").unwrap();
+            testing::encode_frames(&mut encoder, 0, testing::frames(10));
+            encoder.finish().unwrap();
+            drop(encoder);
+
+            let attachments = Decoder::new(&path.locator()).unwrap().attachments();
+            assert_eq!(attachments.len(), 1);
+            assert_eq!(attachments[0].mime_type, "image/png");
+            assert_eq!(attachments[0].data, PNG_HEADER);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::codec::decoder::Audio as AvAudioDecoder;
+use ffmpeg::codec::Context as AvContext;
+use ffmpeg::software::resampling::context::Context as AvResampler;
+use ffmpeg::util::channel_layout::ChannelLayout as AvChannelLayout;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::format::sample::{Sample as AvSample, Type as AvSampleType};
+use ffmpeg::util::frame::Audio as AvAudioFrame;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::{copy_audio_frame_props, parameters_padding, set_decoder_context_time_base};
+use crate::io::Reader;
+use crate::options::Options;
+use crate::packet::Packet;
+use crate::resample::SampleSpec;
+use crate::{Error, Locator};
+
+#[cfg(feature = "ndarray")]
+use crate::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Re-export internal `AvAudioFrame` for caller to use.
+pub type RawSamples = AvAudioFrame;
+
+/// Audio samples as ndarray. It is a 2-dimensional array with dims `(N, C)` (number of samples,
+/// channels) holding interleaved `f32` samples.
+#[cfg(feature = "ndarray")]
+pub type Samples = ndarray::Array2<f32>;
+
+/// Default sample format. Samples are always converted to packed (interleaved) `f32`.
+pub(crate) const SAMPLE_FORMAT: AvSample = AvSample::F32(AvSampleType::Packed);
+
+/// Decode audio streams in files and streams.
+///
+/// Priming samples that the container marks as such (like the encoder delay in the edit list of
+/// MP4 or the codec delay of Matroska) are skipped by the decoder, and the samples that the
+/// decoder still holds at the end of the stream are drained, so the decoder yields exactly the
+/// samples that were encoded.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = AudioDecoder::new(&PathBuf::from("video.mp4").into()).unwrap();
+/// decoder
+///     .decode_iter()
+///     .take_while(Result::is_ok)
+///     .for_each(|samples| println!("Got samples!"),
+/// );
+/// ```
+pub struct AudioDecoder {
+    decoder: AudioDecoderSplit,
+    reader: Reader,
+    reader_stream_index: usize,
+    draining: bool,
+}
+
+impl AudioDecoder {
+    /// Create a new audio decoder for the specified file.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    pub fn new(source: &Locator) -> Result<Self> {
+        Self::from_reader(Reader::new(source)?)
+    }
+
+    /// Create a new audio decoder for the specified file with input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    /// * `options` - The input options.
+    pub fn new_with_options(source: &Locator, options: &Options) -> Result<Self> {
+        Self::from_reader(Reader::new_with_options(source, options)?)
+    }
+
+    /// Get decoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.decoder.time_base()
+    }
+
+    /// Decode samples through iterator interface. This is similar to `decode` but it returns
+    /// samples through an infinite iterator.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<(Time, Samples)>> + '_ {
+        std::iter::from_fn(move || Some(self.decode()))
+    }
+
+    /// Decode a single audio frame worth of samples.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the samples.
+    #[cfg(feature = "ndarray")]
+    pub fn decode(&mut self) -> Result<(Time, Samples)> {
+        let frame = self.decode_raw()?;
+        let timestamp = Time::new(frame.timestamp(), self.decoder.decoder_time_base);
+
+        Ok((timestamp, convert_samples_to_ndarray(&frame)?))
+    }
+
+    /// Decode samples through iterator interface. This is similar to `decode_raw` but it returns
+    /// samples through an infinite iterator.
+    pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawSamples>> + '_ {
+        std::iter::from_fn(move || Some(self.decode_raw()))
+    }
+
+    /// Decode a single audio frame and return the raw ffmpeg `AvAudioFrame`.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw samples as [`RawSamples`]. At the end of the stream, the samples that the
+    /// decoder still holds are returned first, and then [`Error::ReadExhausted`].
+    pub fn decode_raw(&mut self) -> Result<RawSamples> {
+        loop {
+            if self.draining {
+                return self.decoder.drain_raw()?.ok_or(Error::ReadExhausted);
+            }
+
+            match self.reader.read(self.reader_stream_index) {
+                Ok(packet) => {
+                    if let Some(samples) = self.decoder.decode_raw(packet)? {
+                        return Ok(samples);
+                    }
+                }
+                Err(Error::ReadExhausted) => self.draining = true,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Split the decoder into a decoder (of type [`AudioDecoderSplit`]) and a [`Reader`].
+    ///
+    /// # Return value
+    ///
+    /// Tuple of the [`AudioDecoderSplit`], [`Reader`] and the reader stream index.
+    #[inline]
+    pub fn into_parts(self) -> (AudioDecoderSplit, Reader, usize) {
+        (self.decoder, self.reader, self.reader_stream_index)
+    }
+
+    /// Get the decoders sample rate.
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate
+    }
+
+    /// Get the decoders number of channels.
+    #[inline(always)]
+    pub fn channels(&self) -> u16 {
+        self.decoder.channels
+    }
+
+    /// Get the sample rate and channels of the decoded samples, to set up a
+    /// [`crate::Resampler`] with.
+    pub fn sample_spec(&self) -> SampleSpec {
+        self.decoder.sample_spec()
+    }
+
+    /// Get the number of priming samples at the start of the stream, as the container reports
+    /// them. These samples are skipped by the decoder.
+    #[inline]
+    pub fn initial_padding(&self) -> u32 {
+        self.decoder.initial_padding
+    }
+
+    /// Get the number of padding samples at the end of the stream, as the container reports them.
+    #[inline]
+    pub fn trailing_padding(&self) -> u32 {
+        self.decoder.trailing_padding
+    }
+
+    fn from_reader(reader: Reader) -> Result<Self> {
+        let reader_stream_index = reader.best_audio_stream_index()?;
+        Ok(Self {
+            decoder: AudioDecoderSplit::new(&reader, reader_stream_index)?,
+            reader,
+            reader_stream_index,
+            draining: false,
+        })
+    }
+}
+
+/// Audio decoder part of a split [`AudioDecoder`] and [`Reader`].
+pub struct AudioDecoderSplit {
+    decoder: AvAudioDecoder,
+    decoder_time_base: AvRational,
+    resampler: AvResampler,
+    channel_layout: AvChannelLayout,
+    sample_rate: u32,
+    channels: u16,
+    initial_padding: u32,
+    trailing_padding: u32,
+}
+
+impl AudioDecoderSplit {
+    /// Get decoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.decoder_time_base
+    }
+
+    /// Decode a [`Packet`].
+    ///
+    /// Feeds the packet to the decoder and returns samples if there are any available. The caller
+    /// should keep feeding packets until the decoder returns samples.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the [`Samples`] if the decoder has
+    /// samples available, [`None`] if not.
+    #[cfg(feature = "ndarray")]
+    pub fn decode(&mut self, packet: Packet) -> Result<Option<(Time, Samples)>> {
+        match self.decode_raw(packet)? {
+            Some(frame) => {
+                let timestamp = Time::new(frame.timestamp(), self.decoder_time_base);
+                let samples = convert_samples_to_ndarray(&frame)?;
+
+                Ok(Some((timestamp, samples)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decode a [`Packet`].
+    ///
+    /// Feeds the packet to the decoder and returns samples if there are any available. The caller
+    /// should keep feeding packets until the decoder returns samples.
+    ///
+    /// # Return value
+    ///
+    /// The decoded samples as [`RawSamples`] in packed `f32` format if the decoder has samples
+    /// available, [`None`] if not.
+    pub fn decode_raw(&mut self, packet: Packet) -> Result<Option<RawSamples>> {
+        let (mut packet, packet_time_base) = packet.into_inner_parts();
+        packet.rescale_ts(packet_time_base, self.decoder_time_base);
+
+        self.decoder.send_packet(&packet).context(
+            ErrorContext::new("decoder.send_packet")
+                .with_stream_index(packet.stream())
+                .with_pts(packet.pts()),
+        )?;
+
+        match self.decoder_receive_frame()? {
+            Some(frame) => Ok(Some(self.resample(frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Signal the end of the stream to the decoder, and pull samples that it still holds. The
+    /// caller should keep draining until the decoder returns [`None`].
+    ///
+    /// # Return value
+    ///
+    /// The decoded samples as [`RawSamples`] in packed `f32` format if the decoder had samples
+    /// left, [`None`] if not.
+    pub fn drain_raw(&mut self) -> Result<Option<RawSamples>> {
+        // This fails if the end of the stream was signalled before, which is fine.
+        let _ = self.decoder.send_eof();
+        match self.decoder_receive_frame() {
+            Ok(Some(frame)) => Ok(Some(self.resample(frame)?)),
+            Ok(None) => Ok(None),
+            Err(err) if err.backend_error() == Some(AvError::Eof) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get the decoders sample rate.
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Get the decoders number of channels.
+    #[inline(always)]
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Get the sample rate and channels of the decoded samples, to set up a
+    /// [`crate::Resampler`] with.
+    pub fn sample_spec(&self) -> SampleSpec {
+        SampleSpec {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+        }
+    }
+
+    /// Create a new [`AudioDecoderSplit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    /// * `reader_stream_index` - Index of the audio stream to decode.
+    pub fn new(reader: &Reader, reader_stream_index: usize) -> Result<Self> {
+        let reader_stream = reader
+            .input
+            .stream(reader_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+
+        let mut decoder = AvContext::new();
+        set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
+        decoder.set_parameters(reader_stream.parameters())?;
+        let decoder = decoder.decoder().audio().map_err(|err| {
+            ErrorContext::new("decoder.open")
+                .with_stream_index(reader_stream_index)
+                .wrap(Error::from_codec_error(
+                    err,
+                    reader_stream.parameters().id(),
+                ))
+        })?;
+        let decoder_time_base = decoder.time_base();
+
+        if decoder.format() == AvSample::None || decoder.rate() == 0 || decoder.channels() == 0 {
+            return Err(Error::MissingCodecParameters);
+        }
+
+        let channel_layout = if decoder.channel_layout().is_empty() {
+            AvChannelLayout::default(decoder.channels() as i32)
+        } else {
+            decoder.channel_layout()
+        };
+
+        let resampler = AvResampler::get(
+            decoder.format(),
+            channel_layout,
+            decoder.rate(),
+            SAMPLE_FORMAT,
+            channel_layout,
+            decoder.rate(),
+        )?;
+
+        let sample_rate = decoder.rate();
+        let channels = decoder.channels();
+        let (initial_padding, trailing_padding) = parameters_padding(&reader_stream.parameters());
+
+        Ok(Self {
+            decoder,
+            decoder_time_base,
+            resampler,
+            channel_layout,
+            sample_rate,
+            channels,
+            initial_padding,
+            trailing_padding,
+        })
+    }
+
+    /// Convert a decoded frame to packed `f32` samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Decoded frame.
+    fn resample(&mut self, mut frame: RawSamples) -> Result<RawSamples> {
+        // Some decoders do not set the channel layout, in which case we fall back to the default
+        // layout that the resampler was configured with.
+        if frame.channel_layout().is_empty() {
+            frame.set_channel_layout(self.channel_layout);
+        }
+
+        let mut frame_resampled = RawSamples::empty();
+        self.resampler
+            .run(&frame, &mut frame_resampled)
+            .context(ErrorContext::new("resampler.run").with_pts(frame.pts()))?;
+
+        copy_audio_frame_props(&frame, &mut frame_resampled);
+
+        Ok(frame_resampled)
+    }
+
+    /// Pull a decoded frame from the decoder. This function also implements retry mechanism in case
+    /// the decoder signals `EAGAIN`.
+    fn decoder_receive_frame(&mut self) -> Result<Option<RawSamples>> {
+        let mut frame = RawSamples::empty();
+        let decode_result = self.decoder.receive_frame(&mut frame);
+        match decode_result {
+            Ok(()) => Ok(Some(frame)),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(err) => Err(ErrorContext::new("decoder.receive_frame").wrap(err)),
+        }
+    }
+}
+
+impl Drop for AudioDecoderSplit {
+    fn drop(&mut self) {
+        // Maximum number of invocations to `decoder_receive_frame` to drain the items still on the
+        // queue before giving up.
+        const MAX_DRAIN_ITERATIONS: u32 = 100;
+
+        // We need to drain the items still in the decoders queue.
+        if let Ok(()) = self.decoder.send_eof() {
+            for _ in 0..MAX_DRAIN_ITERATIONS {
+                if self.decoder_receive_frame().is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+unsafe impl Send for AudioDecoderSplit {}
+unsafe impl Sync for AudioDecoderSplit {}
+
+/// Converts packed `f32` samples to an `ndarray` of dims `(N, C)`.
+///
+/// # Arguments
+///
+/// * `frame` - Audio frame in packed `f32` format.
+#[cfg(feature = "ndarray")]
+pub(crate) fn convert_samples_to_ndarray(frame: &RawSamples) -> Result<Samples> {
+    if frame.format() != SAMPLE_FORMAT {
+        return Err(Error::invalid_frame_format(
+            format!("{SAMPLE_FORMAT:?}"),
+            format!("{:?}", frame.format()),
+        ));
+    }
+
+    let num_samples = frame.samples();
+    let num_channels = frame.channels() as usize;
+    let samples = frame
+        .data(0)
+        .chunks_exact(std::mem::size_of::<f32>())
+        .take(num_samples * num_channels)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<_>>();
+
+    Samples::from_shape_vec((num_samples, num_channels), samples).map_err(|err| {
+        Error::invalid_frame_format(
+            format!("{num_samples} samples of {num_channels} channels"),
+            err,
+        )
+    })
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempPath;
+
+    #[test]
+    fn test_audio_decoder_sine_round_trip() {
+        const SAMPLE_RATE: u32 = 44100;
+        const NUM_SAMPLES: usize = 44100 + 123;
+
+        // A mono 16-bit PCM WAV file with a 440 Hz tone.
+        let tone = (0..NUM_SAMPLES)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                ((t * 440.0 * std::f32::consts::TAU).sin() * 0.5 * i16::MAX as f32) as i16
+            })
+            .collect::<Vec<_>>();
+        let data_size = (tone.len() * 2) as u32;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_size.to_le_bytes());
+        for sample in &tone {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        let path = TempPath::new("sine.wav");
+        std::fs::write(&path, wav).unwrap();
+
+        let mut decoder = AudioDecoder::new(&path.locator()).unwrap();
+        assert_eq!(decoder.initial_padding(), 0);
+        let mut decoded = Vec::new();
+        loop {
+            match decoder.decode() {
+                Ok((_, samples)) => decoded.extend(samples.iter().copied()),
+                Err(Error::ReadExhausted) => break,
+                Err(err) => panic!("{err}"),
+            }
+        }
+        assert_eq!(decoded.len(), NUM_SAMPLES);
+        for (decoded, sample) in decoded.iter().zip(&tone) {
+            assert!((decoded - *sample as f32 / 32768.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Backend abstraction for the core decode and encode surface.
+//!
+//! [`VideoBackend`] describes the narrow surface that code needs to decode frames from a source
+//! and encode frames to a destination. Code written against it works with any backend that
+//! supports the formats in question. The ffmpeg backend ([`FfmpegBackend`]) is enabled by the
+//! default `backend-ffmpeg` feature.
+
+use crate::{Error, Locator, Time};
+
+#[cfg(feature = "backend-ffmpeg")]
+use crate::{Decoder, Encoder, EncoderSettings, RawFrame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Backend that provides decoders and encoders.
+///
+/// # Example
+///
+/// ```ignore
+/// fn count_frames<B: VideoBackend>(source: &Locator) -> usize {
+///     let mut decoder = B::decoder(source).unwrap();
+///     std::iter::from_fn(|| decoder.decode().ok()).count()
+/// }
+///
+/// let num_frames = count_frames::<FfmpegBackend>(&PathBuf::from("video.mp4").into());
+/// ```
+pub trait VideoBackend {
+    /// Frames that the decoders of the backend produce and its encoders consume.
+    type Frame;
+    /// Settings for encoders of the backend.
+    type Settings;
+    /// Decoder of the backend.
+    type Decoder: BackendDecoder<Frame = Self::Frame>;
+    /// Encoder of the backend.
+    type Encoder: BackendEncoder<Frame = Self::Frame>;
+
+    /// Create a decoder for a source.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to decode.
+    fn decoder(source: &Locator) -> Result<Self::Decoder>;
+
+    /// Create an encoder that writes to a destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - Where to write the encoded video to.
+    /// * `settings` - Encoder settings.
+    fn encoder(dest: &Locator, settings: Self::Settings) -> Result<Self::Encoder>;
+}
+
+/// Decoder of a [`VideoBackend`].
+pub trait BackendDecoder {
+    /// Frames that the decoder produces.
+    type Frame;
+
+    /// Decode the next frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    fn decode(&mut self) -> Result<(Time, Self::Frame)>;
+}
+
+/// Encoder of a [`VideoBackend`].
+pub trait BackendEncoder {
+    /// Frames that the encoder consumes.
+    type Frame;
+
+    /// Encode a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Frame timestamp.
+    /// * `frame` - Frame to encode.
+    fn encode(&mut self, timestamp: Time, frame: Self::Frame) -> Result<()>;
+
+    /// Flush the encoder and finish writing the destination.
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// Backend that decodes and encodes with ffmpeg, which supports all formats of the crate.
+#[cfg(feature = "backend-ffmpeg")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FfmpegBackend;
+
+#[cfg(feature = "backend-ffmpeg")]
+impl VideoBackend for FfmpegBackend {
+    type Frame = RawFrame;
+    type Settings = EncoderSettings<'static>;
+    type Decoder = Decoder<'static>;
+    type Encoder = Encoder;
+
+    fn decoder(source: &Locator) -> Result<Decoder<'static>> {
+        Decoder::new(source)
+    }
+
+    fn encoder(dest: &Locator, settings: EncoderSettings<'static>) -> Result<Encoder> {
+        Encoder::new(dest, settings)
+    }
+}
+
+#[cfg(feature = "backend-ffmpeg")]
+impl BackendDecoder for Decoder<'_> {
+    type Frame = RawFrame;
+
+    fn decode(&mut self) -> Result<(Time, RawFrame)> {
+        let frame = self.decode_raw()?;
+        let timestamp = Time::new(frame.pts().or_else(|| frame.timestamp()), self.time_base());
+        Ok((timestamp, frame))
+    }
+}
+
+#[cfg(feature = "backend-ffmpeg")]
+impl BackendEncoder for Encoder {
+    type Frame = RawFrame;
+
+    fn encode(&mut self, timestamp: Time, mut frame: RawFrame) -> Result<()> {
+        frame.set_pts(
+            timestamp
+                .aligned_with_rational(self.time_base())
+                .into_value(),
+        );
+        self.encode_raw(frame)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Encoder::finish(self)
+    }
+}
+
+#[cfg(all(test, feature = "backend-ffmpeg"))]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    /// Encode frames with a backend, decode them back and return the decoded timestamps in
+    /// seconds.
+    fn round_trip<B: VideoBackend>(
+        dest: &Locator,
+        settings: B::Settings,
+        frames: impl IntoIterator<Item = (Time, B::Frame)>,
+    ) -> Vec<f64> {
+        let mut encoder = B::encoder(dest, settings).unwrap();
+        for (timestamp, frame) in frames {
+            encoder.encode(timestamp, frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = B::decoder(dest).unwrap();
+        std::iter::from_fn(|| decoder.decode().ok())
+            .map(|(timestamp, _)| timestamp.as_secs_f64())
+            .collect()
+    }
+
+    #[test]
+    fn test_ffmpeg_backend_round_trip() {
+        let path = TempPath::new("backend.mp4");
+        let timestamps = round_trip::<FfmpegBackend>(
+            &path.locator(),
+            testing::settings(),
+            (0..10).map(|i| (Time::from_secs_f64(i as f64 / 30.0), testing::frame())),
+        );
+        assert_eq!(timestamps.len(), 10);
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            assert!((timestamp - timestamps[0] - i as f64 / 30.0).abs() < 0.002);
+        }
+    }
+}
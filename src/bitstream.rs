@@ -0,0 +1,162 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::ffi;
+use crate::{Error, Packet, StreamInfo};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Represents a bitstream filter. A bitstream filter changes the packets of a stream without
+/// decoding them, for example to convert H.264 from the MP4 format (length prefixes) to the Annex B
+/// format (start codes) with `h264_mp4toannexb`.
+///
+/// Packets are sent to the filter with [`BitstreamFilter::send`] and filtered packets are taken
+/// out with [`BitstreamFilter::receive`]. A filter may hold on to packets, so one sent packet does
+/// not necessarily correspond to one received packet.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut reader = Reader::new(&PathBuf::from("my_video.mp4").into()).unwrap();
+/// let stream_index = reader.best_video_stream_index().unwrap();
+/// let mut filter =
+///     BitstreamFilter::new("h264_mp4toannexb", &reader.stream_info(stream_index).unwrap())
+///         .unwrap();
+///
+/// while let Ok(packet) = reader.read(stream_index) {
+///     filter.send(packet).unwrap();
+///     while let Some(packet) = filter.receive().unwrap() {
+///         // Do something with the Annex B packet.
+///     }
+/// }
+/// ```
+pub struct BitstreamFilter {
+    filter: ffi::BitstreamFilter,
+    /// Time base of the packets going into the filter.
+    time_base_in: AvRational,
+    /// Information of the filtered stream.
+    stream_info: StreamInfo,
+}
+
+impl BitstreamFilter {
+    /// Create a bitstream filter for a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the bitstream filter, like `h264_mp4toannexb`, `hevc_mp4toannexb` or
+    ///   `extract_extradata`.
+    /// * `stream_info` - Information of the stream to filter.
+    pub fn new(name: &str, stream_info: &StreamInfo) -> Result<Self> {
+        let (_, codec_parameters, time_base_in) = stream_info.clone().into_parts();
+        let filter = ffi::BitstreamFilter::new(name, &codec_parameters, time_base_in)?;
+        let stream_info = stream_info
+            .clone()
+            .with_codec_parameters(filter.parameters_out(), filter.time_base_out());
+
+        Ok(Self {
+            filter,
+            time_base_in,
+            stream_info,
+        })
+    }
+
+    /// Send a packet to the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to filter.
+    pub fn send(&mut self, packet: Packet) -> Result<()> {
+        let (mut packet, time_base) = packet.into_inner_parts();
+        packet.rescale_ts(time_base, self.time_base_in);
+        self.filter.send(Some(&mut packet))?;
+        Ok(())
+    }
+
+    /// Signal the end of the stream to the filter, so that it hands out any packets it is still
+    /// holding on to through [`BitstreamFilter::receive`].
+    pub fn flush(&mut self) -> Result<()> {
+        self.filter.send(None)?;
+        Ok(())
+    }
+
+    /// Receive a filtered packet.
+    ///
+    /// # Return value
+    ///
+    /// The filtered packet, or `None` if the filter needs more packets (or has been flushed
+    /// completely).
+    pub fn receive(&mut self) -> Result<Option<Packet>> {
+        let mut packet = AvPacket::empty();
+        match self.filter.receive(&mut packet) {
+            Ok(()) => {
+                packet.set_stream(self.stream_info.index);
+                Ok(Some(Packet::new(packet, self.filter.time_base_out())))
+            }
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(AvError::Eof) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Information of the filtered stream. Filters that change the codec extradata (like
+    /// `h264_mp4toannexb`) report the new extradata here, so this should be used to set up the
+    /// stream the filtered packets are written to.
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    /// Send a packet to the filter and receive all packets that are ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to filter, or `None` to flush the filter.
+    pub(crate) fn filter(&mut self, packet: Option<Packet>) -> Result<Vec<Packet>> {
+        match packet {
+            Some(packet) => self.send(packet)?,
+            None => self.flush()?,
+        }
+
+        let mut filtered = Vec::new();
+        while let Some(packet) = self.receive()? {
+            filtered.push(packet);
+        }
+
+        Ok(filtered)
+    }
+
+    /// Reset the filter, dropping any packets it is holding on to. This should be done after
+    /// seeking.
+    pub(crate) fn reset(&mut self) {
+        self.filter.reset();
+    }
+}
+
+unsafe impl Send for BitstreamFilter {}
+unsafe impl Sync for BitstreamFilter {}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, TempPath};
+    use crate::Reader;
+
+    #[test]
+    fn test_bitstream_filter_annex_b() {
+        let source = TempPath::new("bitstream.mp4");
+        testing::write_test_video(&source.locator(), testing::frames(10));
+
+        let mut reader = Reader::new(&source.locator())
+            .unwrap()
+            .with_bitstream_filter(0, "h264_mp4toannexb")
+            .unwrap();
+        let mut packet_count = 0;
+        while let Ok(packet) = reader.read(0) {
+            let data = packet.into_inner();
+            assert!(data.data().unwrap().starts_with(&[0x00, 0x00, 0x00, 0x01]));
+            packet_count += 1;
+        }
+        assert_eq!(packet_count, 10);
+    }
+}
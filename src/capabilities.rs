@@ -0,0 +1,641 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::codec::codec::Codec as AvCodec;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::ffi::AVOptionType;
+use ffmpeg::format::flag::Flags as AvFormatFlags;
+
+use crate::ffi::{self, CodecOptionRaw};
+use crate::options::Options;
+use crate::{Error, PixelFormat};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Re-export internal `AvCodecId` as `CodecId` for callers.
+pub type CodecId = AvCodecId;
+
+/// Describes what the linked ffmpeg build can do. Which encoders, decoders and formats are
+/// available depends entirely on how ffmpeg was built, so this can be used to find out up front
+/// whether, for example, `libx264` or `hevc_nvenc` can be used.
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    /// Available encoders, in the order ffmpeg prefers them.
+    pub encoders: Vec<EncoderInfo>,
+    /// Available decoders, in the order ffmpeg prefers them.
+    pub decoders: Vec<DecoderInfo>,
+    /// Names of the available muxers (output formats), like `mp4`.
+    pub muxers: Vec<String>,
+    /// Names of the available demuxers (input formats). Some demuxers have multiple
+    /// comma-separated names, like `mov,mp4,m4a,3gp,3g2,mj2`.
+    pub demuxers: Vec<String>,
+}
+
+/// Describes an available encoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderInfo {
+    /// Name of the encoder, like `libx264`.
+    pub name: String,
+    /// Codec the encoder produces.
+    pub codec_id: CodecId,
+    /// Whether or not the encoder is (partially) backed by hardware. Note that a hardware encoder
+    /// being available only means ffmpeg was built with it: the hardware itself may still be
+    /// missing.
+    pub hardware: bool,
+    /// Pixel formats the encoder accepts. Empty for audio encoders and for encoders that do not
+    /// report their pixel formats.
+    pub pixel_formats: Vec<PixelFormat>,
+}
+
+impl EncoderInfo {
+    /// Describe an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec` - Encoder to describe.
+    pub(crate) fn new(codec: AvCodec) -> Self {
+        let pixel_formats = codec
+            .video()
+            .ok()
+            .and_then(|video| video.formats())
+            .map(|formats| formats.collect())
+            .unwrap_or_default();
+
+        Self {
+            name: codec.name().to_string(),
+            codec_id: codec.id(),
+            hardware: ffi::codec_is_hardware(&codec),
+            pixel_formats,
+        }
+    }
+
+    /// Get the private options of an encoder: the options that are specific to it, like `preset`
+    /// and `tune` for `libx264`, as opposed to the options that all encoders share (like `b` and
+    /// `g`). These can be used to present the options of an encoder in a user interface, and are
+    /// what encoder options are checked against when opening an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_name` - Name of the encoder, like `libx264`.
+    ///
+    /// # Return value
+    ///
+    /// The options of the encoder in the order the encoder declares them, or none if there is no
+    /// such encoder.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for option in EncoderInfo::options("h264_nvenc") {
+    ///     let values = option.values.iter().map(|value| value.name.as_str());
+    ///     println!("{} ({:?}): {}", option.name, values.collect::<Vec<_>>(), option.help);
+    /// }
+    /// ```
+    pub fn options(codec_name: &str) -> Vec<OptionSpec> {
+        ffmpeg::encoder::find_by_name(codec_name)
+            .map(|codec| option_specs(ffi::codec_private_options(&codec)))
+            .unwrap_or_default()
+    }
+}
+
+/// Describes an option of an encoder (see [`EncoderInfo::options`]).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionSpec {
+    /// Name of the option, as used as key in [`Options`].
+    pub name: String,
+    /// Type of the option.
+    pub kind: OptionKind,
+    /// Smallest valid value for numeric options.
+    pub min: f64,
+    /// Largest valid value for numeric options.
+    pub max: f64,
+    /// Default value, if the option has one.
+    pub default: Option<OptionValue>,
+    /// Short description of the option.
+    pub help: String,
+    /// Named values that the option can be set to, like the presets of `h264_nvenc`. For
+    /// [`OptionKind::Flags`], these are the flags that can be combined with `+`.
+    pub values: Vec<NamedConstant>,
+}
+
+impl OptionSpec {
+    /// Check whether the option can be set to a value. Numbers must lie in the declared range,
+    /// and options with named values must be set to one of them or to a number. Values of other
+    /// options are left for the encoder to check.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value to check.
+    ///
+    /// # Return value
+    ///
+    /// `Err` with the reason if the value is not valid.
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        match self.kind {
+            OptionKind::Flags => value
+                .split(['+', '-'])
+                .filter(|flag| !flag.is_empty())
+                .try_for_each(|flag| self.check_number_or_constant(flag)),
+            OptionKind::Int
+            | OptionKind::Int64
+            | OptionKind::UInt64
+            | OptionKind::Double
+            | OptionKind::Float
+            | OptionKind::Bool => self.check_number_or_constant(value),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether a value is a named value of the option, or a number in range.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value to check.
+    fn check_number_or_constant(&self, value: &str) -> std::result::Result<(), String> {
+        if self.values.iter().any(|constant| constant.name == value) {
+            return Ok(());
+        }
+        match value.trim().parse::<f64>() {
+            Ok(number)
+                if self.kind != OptionKind::Flags && (number < self.min || number > self.max) =>
+            {
+                Err(format!(
+                    "value {value} of `{}` is out of range [{}, {}]",
+                    self.name, self.min, self.max
+                ))
+            }
+            Ok(_) => Ok(()),
+            Err(_) if !self.values.is_empty() => Err(format!(
+                "value {value} of `{}` is not one of: {}",
+                self.name,
+                self.values
+                    .iter()
+                    .map(|constant| constant.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            // Booleans take words like `true`, and numbers can be expressions like `2M`, which
+            // the encoder parses itself.
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Type of an encoder option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    /// Set of flags, combined with `+` (and removed with `-`).
+    Flags,
+    Int,
+    Int64,
+    UInt64,
+    Double,
+    Float,
+    Bool,
+    String,
+    Rational,
+    /// Binary data, written in hexadecimal.
+    Binary,
+    /// Key-value pairs, like `key1=value1:key2=value2`.
+    Dictionary,
+    /// Size like `1280x720`.
+    ImageSize,
+    PixelFormat,
+    SampleFormat,
+    /// Frame rate like `30000/1001` or `ntsc`.
+    VideoRate,
+    Duration,
+    Color,
+    ChannelLayout,
+}
+
+impl OptionKind {
+    /// Get the kind of an option from its ffmpeg type, or `None` for named constants.
+    fn from_av(kind: AVOptionType) -> Option<Self> {
+        Some(match kind {
+            AVOptionType::AV_OPT_TYPE_FLAGS => Self::Flags,
+            AVOptionType::AV_OPT_TYPE_INT => Self::Int,
+            AVOptionType::AV_OPT_TYPE_INT64 => Self::Int64,
+            AVOptionType::AV_OPT_TYPE_UINT64 => Self::UInt64,
+            AVOptionType::AV_OPT_TYPE_DOUBLE => Self::Double,
+            AVOptionType::AV_OPT_TYPE_FLOAT => Self::Float,
+            AVOptionType::AV_OPT_TYPE_BOOL => Self::Bool,
+            AVOptionType::AV_OPT_TYPE_STRING => Self::String,
+            AVOptionType::AV_OPT_TYPE_RATIONAL => Self::Rational,
+            AVOptionType::AV_OPT_TYPE_BINARY => Self::Binary,
+            AVOptionType::AV_OPT_TYPE_DICT => Self::Dictionary,
+            AVOptionType::AV_OPT_TYPE_IMAGE_SIZE => Self::ImageSize,
+            AVOptionType::AV_OPT_TYPE_PIXEL_FMT => Self::PixelFormat,
+            AVOptionType::AV_OPT_TYPE_SAMPLE_FMT => Self::SampleFormat,
+            AVOptionType::AV_OPT_TYPE_VIDEO_RATE => Self::VideoRate,
+            AVOptionType::AV_OPT_TYPE_DURATION => Self::Duration,
+            AVOptionType::AV_OPT_TYPE_COLOR => Self::Color,
+            AVOptionType::AV_OPT_TYPE_CHANNEL_LAYOUT | AVOptionType::AV_OPT_TYPE_CHLAYOUT => {
+                Self::ChannelLayout
+            }
+            AVOptionType::AV_OPT_TYPE_CONST => return None,
+        })
+    }
+
+    /// Whether or not the value of this kind of option is a floating point number.
+    fn is_floating_point(self) -> bool {
+        matches!(self, Self::Double | Self::Float)
+    }
+}
+
+/// Value of an encoder option.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionValue {
+    /// Integer value. Also used for flags, booleans, pixel formats, sample formats and durations
+    /// (in microseconds).
+    Int(i64),
+    /// Floating point value.
+    Float(f64),
+    /// Rational value as numerator and denominator.
+    Rational(i32, i32),
+    /// Value of options that are parsed from a string, like strings, image sizes and colors.
+    String(String),
+}
+
+/// Named value of an encoder option, like `p7` for the `preset` of `h264_nvenc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedConstant {
+    /// Name to set the option to.
+    pub name: String,
+    /// Value the name stands for.
+    pub value: OptionValue,
+    /// Short description of the value.
+    pub help: String,
+}
+
+/// Turn the raw options of a class into option specifications, with the named constants listed
+/// under the options that share their unit.
+///
+/// # Arguments
+///
+/// * `raw` - Options and named constants in declaration order.
+fn option_specs(raw: Vec<CodecOptionRaw>) -> Vec<OptionSpec> {
+    let (constants, options): (Vec<_>, Vec<_>) = raw
+        .into_iter()
+        .partition(|option| option.kind == AVOptionType::AV_OPT_TYPE_CONST);
+
+    options
+        .into_iter()
+        .filter_map(|option| {
+            let kind = OptionKind::from_av(option.kind)?;
+            let values = option
+                .unit
+                .as_ref()
+                .map(|unit| {
+                    constants
+                        .iter()
+                        .filter(|constant| constant.unit.as_ref() == Some(unit))
+                        .map(|constant| NamedConstant {
+                            name: constant.name.clone(),
+                            value: if kind.is_floating_point() {
+                                OptionValue::Float(constant.default_double)
+                            } else {
+                                OptionValue::Int(constant.default_int)
+                            },
+                            help: constant.help.clone(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let default = match option.kind {
+                AVOptionType::AV_OPT_TYPE_DOUBLE | AVOptionType::AV_OPT_TYPE_FLOAT => {
+                    Some(OptionValue::Float(option.default_double))
+                }
+                AVOptionType::AV_OPT_TYPE_RATIONAL => Some(OptionValue::Rational(
+                    option.default_rational.numerator(),
+                    option.default_rational.denominator(),
+                )),
+                AVOptionType::AV_OPT_TYPE_STRING
+                | AVOptionType::AV_OPT_TYPE_BINARY
+                | AVOptionType::AV_OPT_TYPE_DICT
+                | AVOptionType::AV_OPT_TYPE_IMAGE_SIZE
+                | AVOptionType::AV_OPT_TYPE_VIDEO_RATE
+                | AVOptionType::AV_OPT_TYPE_COLOR
+                | AVOptionType::AV_OPT_TYPE_CHLAYOUT => {
+                    option.default_string.map(OptionValue::String)
+                }
+                _ => Some(OptionValue::Int(option.default_int)),
+            };
+
+            Some(OptionSpec {
+                name: option.name,
+                kind,
+                min: option.min,
+                max: option.max,
+                default,
+                help: option.help,
+                values,
+            })
+        })
+        .collect()
+}
+
+/// Check encoder options against the private options that the encoder declares (see
+/// [`EncoderInfo::options`]). Keys that are not private options of the encoder are not checked.
+///
+/// # Arguments
+///
+/// * `codec_name` - Name of the encoder.
+/// * `options` - Options to check.
+pub(crate) fn validate_encoder_options(codec_name: &str, options: &Options) -> Result<()> {
+    check_options(&EncoderInfo::options(codec_name), codec_name, options)
+}
+
+/// Check options against option specifications.
+///
+/// # Arguments
+///
+/// * `specs` - Specifications to check against.
+/// * `codec_name` - Name of the encoder, for error messages.
+/// * `options` - Options to check.
+fn check_options(specs: &[OptionSpec], codec_name: &str, options: &Options) -> Result<()> {
+    for (key, value) in options.iter() {
+        if let Some(spec) = specs.iter().find(|spec| spec.name == key) {
+            spec.check(value)
+                .map_err(|reason| Error::InvalidOptions(format!("{codec_name}: {reason}")))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes an available decoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecoderInfo {
+    /// Name of the decoder, like `h264`.
+    pub name: String,
+    /// Codec the decoder reads.
+    pub codec_id: CodecId,
+    /// Whether or not the decoder is (partially) backed by hardware.
+    pub hardware: bool,
+}
+
+/// Describes what an output format (muxer) supports, so that applications can check their
+/// configuration up front (see [`crate::Writer::format_capabilities`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatCaps {
+    /// Whether or not the format carries the codec headers (like the SPS and PPS of H.264) once in
+    /// the container, instead of in the stream. Encoders are opened with global headers for these
+    /// formats.
+    pub needs_global_header: bool,
+    /// Whether or not the format writes data at the end that the output is incomplete without,
+    /// like the index of MP4 or the cues of Matroska. Formats without a trailer (like MPEG-TS, raw
+    /// streams and image sequences) are usable up to the last packet written. Finishing still
+    /// closes the muxer for these formats, which writes nothing. ffmpeg does not expose this, so
+    /// it is known for the common formats only.
+    pub has_trailer: bool,
+    /// Whether or not the format can hold more than one stream. Raw streams (like `h264`) and
+    /// image sequences hold a single stream.
+    pub supports_multiple_streams: bool,
+    /// Whether or not the format supports frames with varying durations, instead of assuming a
+    /// constant frame rate.
+    pub variable_fps_ok: bool,
+    /// Whether or not the format needs to seek back in the output when finishing, so that it
+    /// cannot be written to pipes and most network destinations. This is the case for MP4 and
+    /// QuickTime unless they are fragmented.
+    pub seekable_output_required: bool,
+}
+
+impl FormatCaps {
+    /// Formats that write a trailer.
+    const TRAILER_FORMATS: &'static [&'static str] = &[
+        "mov", "mp4", "ismv", "ipod", "3gp", "3g2", "psp", "f4v", "matroska", "webm", "avi", "flv",
+        "nut", "asf", "wav", "w64", "mp3", "ogg", "mxf", "gif", "hls", "dash",
+    ];
+
+    /// Formats that can hold a single stream only, even though they have timestamps.
+    const SINGLE_STREAM_FORMATS: &'static [&'static str] = &["gif", "apng", "wav", "w64"];
+
+    /// Formats that require a seekable output unless they are fragmented.
+    const MOV_FORMATS: &'static [&'static str] =
+        &["mov", "mp4", "ipod", "3gp", "3g2", "psp", "f4v"];
+
+    /// `movflags` that make MP4 and QuickTime fragmented.
+    const FRAGMENT_MOVFLAGS: &'static [&'static str] = &[
+        "frag_keyframe",
+        "frag_custom",
+        "frag_every_frame",
+        "empty_moov",
+    ];
+
+    /// Describe an output format.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Name of the format. Names can be comma-separated, like `mov,mp4,m4a`.
+    /// * `flags` - Flags of the format.
+    /// * `movflags` - The `movflags` option that the muxer is opened with, if any.
+    pub(crate) fn new(format: &str, flags: AvFormatFlags, movflags: Option<&str>) -> Self {
+        let is_format = |names: &[&str]| format.split(',').any(|name| names.contains(&name));
+        let fragmented = movflags.is_some_and(|movflags| {
+            movflags
+                .split('+')
+                .any(|flag| Self::FRAGMENT_MOVFLAGS.contains(&flag))
+        });
+
+        Self {
+            needs_global_header: flags.contains(AvFormatFlags::GLOBAL_HEADER),
+            has_trailer: is_format(Self::TRAILER_FORMATS),
+            supports_multiple_streams: !flags
+                .intersects(AvFormatFlags::NO_TIMESTAMPS | AvFormatFlags::NO_STREAMS)
+                && !is_format(Self::SINGLE_STREAM_FORMATS),
+            variable_fps_ok: flags.contains(AvFormatFlags::VARIABLE_FPS),
+            seekable_output_required: is_format(Self::MOV_FORMATS) && !fragmented,
+        }
+    }
+}
+
+/// Get the encoders, decoders and formats that the linked ffmpeg build provides.
+///
+/// # Example
+///
+/// ```ignore
+/// let capabilities = video_rs::capabilities();
+/// for encoder in capabilities.encoders {
+///     println!("{} ({:?}, hardware: {})", encoder.name, encoder.codec_id, encoder.hardware);
+/// }
+/// ```
+pub fn capabilities() -> Capabilities {
+    let (encoders, decoders): (Vec<_>, Vec<_>) =
+        ffi::codecs().into_iter().partition(AvCodec::is_encoder);
+
+    Capabilities {
+        encoders: encoders.into_iter().map(EncoderInfo::new).collect(),
+        decoders: decoders
+            .into_iter()
+            .map(|codec| DecoderInfo {
+                name: codec.name().to_string(),
+                codec_id: codec.id(),
+                hardware: ffi::codec_is_hardware(&codec),
+            })
+            .collect(),
+        muxers: ffi::muxer_names(),
+        demuxers: ffi::demuxer_names(),
+    }
+}
+
+/// Get the available encoders for a codec, in the order ffmpeg prefers them.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec to find encoders for.
+pub(crate) fn encoders_for(codec_id: CodecId) -> Vec<EncoderInfo> {
+    ffi::codecs()
+        .into_iter()
+        .filter(|codec| codec.is_encoder() && codec.id() == codec_id)
+        .map(EncoderInfo::new)
+        .collect()
+}
+
+/// Pick the best encoder among the given encoders. Encoders that match the hardware preference
+/// come first, and otherwise the order of the encoders decides.
+///
+/// # Arguments
+///
+/// * `encoders` - Encoders to pick from, in order of preference.
+/// * `prefer_hardware` - Whether to prefer hardware encoders over software encoders.
+pub(crate) fn pick_encoder(
+    mut encoders: Vec<EncoderInfo>,
+    prefer_hardware: bool,
+) -> Option<EncoderInfo> {
+    let preferred = encoders
+        .iter()
+        .position(|encoder| encoder.hardware == prefer_hardware)
+        .unwrap_or(0);
+    (preferred < encoders.len()).then(|| encoders.swap_remove(preferred))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(name: &str, hardware: bool) -> EncoderInfo {
+        EncoderInfo {
+            name: name.to_string(),
+            codec_id: CodecId::H264,
+            hardware,
+            pixel_formats: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_pick_encoder() {
+        let encoders = vec![
+            encoder("libx264", false),
+            encoder("libopenh264", false),
+            encoder("h264_nvenc", true),
+            encoder("h264_vaapi", true),
+        ];
+        assert_eq!(
+            pick_encoder(encoders.clone(), false).unwrap().name,
+            "libx264"
+        );
+        assert_eq!(pick_encoder(encoders, true).unwrap().name, "h264_nvenc");
+        assert_eq!(
+            pick_encoder(vec![encoder("libx264", false)], true)
+                .unwrap()
+                .name,
+            "libx264"
+        );
+        assert!(pick_encoder(Vec::new(), false).is_none());
+    }
+
+    #[test]
+    fn test_check_options() {
+        let spec = |name: &str, kind, min, max, values: &[&str]| OptionSpec {
+            name: name.to_string(),
+            kind,
+            min,
+            max,
+            default: None,
+            help: String::new(),
+            values: values
+                .iter()
+                .enumerate()
+                .map(|(i, name)| NamedConstant {
+                    name: name.to_string(),
+                    value: OptionValue::Int(1 << i),
+                    help: String::new(),
+                })
+                .collect(),
+        };
+        let specs = [
+            spec("crf", OptionKind::Float, -1.0, 51.0, &[]),
+            spec("preset", OptionKind::Int, 0.0, 18.0, &["p1", "p7"]),
+            spec("flags", OptionKind::Flags, 0.0, f64::MAX, &["a", "b"]),
+            spec("forced-idr", OptionKind::Bool, -1.0, 1.0, &[]),
+        ];
+        let check = |options: &str| check_options(&specs, "test", &options.parse().unwrap());
+
+        assert!(check("crf=23:preset=p7:flags=+a-b:forced-idr=true:g=120").is_ok());
+        assert!(check("crf=-1:preset=3:flags=3").is_ok());
+        assert!(matches!(check("crf=52"), Err(Error::InvalidOptions(_))));
+        assert!(matches!(check("preset=19"), Err(Error::InvalidOptions(_))));
+        assert!(matches!(
+            check("preset=slow"),
+            Err(Error::InvalidOptions(_))
+        ));
+        assert!(matches!(check("flags=a+c"), Err(Error::InvalidOptions(_))));
+        assert!(matches!(
+            check("forced-idr=2"),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_encoder_options() {
+        let options = EncoderInfo::options("libx264");
+        let crf = options.iter().find(|option| option.name == "crf").unwrap();
+        assert_eq!(crf.kind, OptionKind::Float);
+        assert_eq!((crf.min, crf.max), (-1.0, 51.0));
+        let aq_mode = options
+            .iter()
+            .find(|option| option.name == "aq-mode")
+            .unwrap();
+        assert!(aq_mode.values.iter().any(|value| value.name == "variance"));
+        assert!(EncoderInfo::options("no-such-encoder").is_empty());
+
+        let options: Options = "crf=70".parse().unwrap();
+        assert!(matches!(
+            options.validate_for_encoder("libx264"),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_caps() {
+        let mp4 = FormatCaps::new(
+            "mp4",
+            AvFormatFlags::GLOBAL_HEADER | AvFormatFlags::VARIABLE_FPS,
+            None,
+        );
+        assert!(mp4.needs_global_header && mp4.has_trailer && mp4.supports_multiple_streams);
+        assert!(mp4.variable_fps_ok && mp4.seekable_output_required);
+        let fragmented = FormatCaps::new(
+            "mp4",
+            AvFormatFlags::GLOBAL_HEADER | AvFormatFlags::VARIABLE_FPS,
+            Some("frag_keyframe+empty_moov"),
+        );
+        assert!(!fragmented.seekable_output_required);
+
+        let image2 = FormatCaps::new(
+            "image2",
+            AvFormatFlags::NO_TIMESTAMPS | AvFormatFlags::NO_DIMENSIONS | AvFormatFlags::NO_FILE,
+            None,
+        );
+        assert_eq!(
+            image2,
+            FormatCaps {
+                needs_global_header: false,
+                has_trailer: false,
+                supports_multiple_streams: false,
+                variable_fps_ok: false,
+                seekable_output_required: false,
+            }
+        );
+    }
+}
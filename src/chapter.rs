@@ -0,0 +1,145 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::context::{Input as AvInput, Output as AvOutput};
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use ffmpeg::Error as AvError;
+
+use crate::ffi::output_clear_chapters;
+use crate::{Error, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Container formats that can store chapters.
+///
+/// Matroska (and WebM) stores chapters directly. The MP4 and QuickTime muxers write them as a
+/// chapter text track.
+const CHAPTER_FORMATS: &[&str] = &["matroska", "webm", "mp4", "mov"];
+
+/// Represents a chapter of a container, like a chapter of an audiobook or a section of a screen
+/// recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// Start of the chapter.
+    pub start: Time,
+    /// End of the chapter.
+    pub end: Time,
+    /// Title of the chapter. Empty if the chapter has no title.
+    pub title: String,
+}
+
+/// Read the chapters of an input format context.
+///
+/// # Arguments
+///
+/// * `input` - Input format context.
+pub(crate) fn read_chapters(input: &AvInput) -> Vec<Chapter> {
+    input
+        .chapters()
+        .map(|chapter| Chapter {
+            start: Time::new(Some(chapter.start()), chapter.time_base()),
+            end: Time::new(Some(chapter.end()), chapter.time_base()),
+            title: chapter
+                .metadata()
+                .get("title")
+                .unwrap_or_default()
+                .to_string(),
+        })
+        .collect()
+}
+
+/// Replace the chapters of an output format context. This must be done before the header is
+/// written.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `chapters` - Chapters to write.
+pub(crate) fn write_chapters(output: &mut AvOutput, chapters: &[Chapter]) -> Result<()> {
+    let format = output.format().name().to_string();
+    if !supports_chapters(&format) {
+        return Err(Error::ChaptersNotSupported(format));
+    }
+
+    output_clear_chapters(output);
+    for (id, chapter) in chapters.iter().enumerate() {
+        let to_time_base = |time: &Time| {
+            time.aligned_with_rational(TIME_BASE)
+                .into_value()
+                .ok_or(AvError::InvalidData)
+        };
+        output.add_chapter(
+            id as i64,
+            TIME_BASE,
+            to_time_base(&chapter.start)?,
+            to_time_base(&chapter.end)?,
+            &chapter.title,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Whether or not a container format can store chapters.
+///
+/// # Arguments
+///
+/// * `format` - Name of the container format.
+fn supports_chapters(format: &str) -> bool {
+    // Some formats have multiple comma-separated names, like "mov,mp4,m4a,3gp,3g2,mj2".
+    format
+        .split(',')
+        .any(|name| CHAPTER_FORMATS.contains(&name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn test_supports_chapters() {
+        assert!(supports_chapters("matroska"));
+        assert!(supports_chapters("mp4"));
+        assert!(supports_chapters("mov,mp4,m4a,3gp,3g2,mj2"));
+        assert!(!supports_chapters("mpegts"));
+        assert!(!supports_chapters("flv"));
+    }
+
+    #[test]
+    fn test_chapters_round_trip() {
+        let path = TempPath::new("chapters.mkv");
+
+        let chapters = vec![
+            Chapter {
+                start: Time::from_secs(0.0),
+                end: Time::from_secs(0.5),
+                title: "Introduction".to_string(),
+            },
+            Chapter {
+                start: Time::from_secs(0.5),
+                end: Time::from_secs(1.0),
+                title: "Conclusion".to_string(),
+            },
+        ];
+
+        let mut encoder = Encoder::new(&path.locator(), testing::settings()).unwrap();
+        encoder.set_chapters(&chapters).unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(30));
+        assert!(matches!(
+            encoder.set_chapters(&chapters),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let decoded = Decoder::new(&path.locator()).unwrap().chapters();
+        assert_eq!(decoded.len(), chapters.len());
+        for (decoded, chapter) in decoded.iter().zip(&chapters) {
+            assert_eq!(decoded.title, chapter.title);
+            assert!((decoded.start.as_secs_f64() - chapter.start.as_secs_f64()).abs() < 0.001);
+            assert!((decoded.end.as_secs_f64() - chapter.end.as_secs_f64()).abs() < 0.001);
+        }
+    }
+}
@@ -0,0 +1,732 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::HashMap;
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::ffi::{
+    clear_codec_tag, input_start_time, parameters_extradata, parameters_properties,
+    ParametersProperties,
+};
+use crate::io::{private::Write, Reader, Writer};
+use crate::options::Options;
+use crate::packet::Packet;
+use crate::{Error, Locator, RawFrame, Resize};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Indices of the video and audio streams in the timeline when re-encoding.
+const VIDEO_STREAM: usize = 0;
+const AUDIO_STREAM: usize = 1;
+
+/// Join multiple sources into one destination, one after the other, without re-encoding.
+///
+/// All video and audio streams are copied. This requires the sources to have the same streams,
+/// with identical codec parameters (codec, size, pixel format, sample rate, and so on). If they do
+/// not, [`Error::IncompatibleInputs`] is returned, naming the first parameter that differs. Use
+/// [`concat_with_settings`] to re-encode instead in that case.
+///
+/// Timestamps of each source are shifted to start where the previous source ended, so that video
+/// and audio stay in sync across joins.
+///
+/// # Arguments
+///
+/// * `sources` - Locators to read from, in order.
+/// * `dest` - Locator to write to.
+///
+/// # Example
+///
+/// ```ignore
+/// concat(
+///     &[
+///         PathBuf::from("part1.mp4").into(),
+///         PathBuf::from("part2.mp4").into(),
+///     ],
+///     &PathBuf::from("joined.mp4").into(),
+/// )
+/// .unwrap();
+/// ```
+pub fn concat(sources: &[Locator], dest: &Locator) -> Result<()> {
+    let inputs = open_inputs(sources)?;
+    check_compatible(&signatures(&inputs))?;
+    concat_copy(inputs, dest)
+}
+
+/// Join multiple sources into one destination, one after the other. If the sources can be joined
+/// without re-encoding (see [`concat`]), their streams are copied. Otherwise, video is re-encoded
+/// with the provided encoder settings, and frames are resized to the size in the settings.
+///
+/// Audio cannot be re-encoded, so the best audio stream of each source is copied. This requires
+/// either all or none of the sources to have audio, with identical codec parameters.
+///
+/// # Arguments
+///
+/// * `sources` - Locators to read from, in order.
+/// * `dest` - Locator to write to.
+/// * `settings` - Encoder settings to use when re-encoding.
+pub fn concat_with_settings(sources: &[Locator], dest: &Locator, settings: Settings) -> Result<()> {
+    let inputs = open_inputs(sources)?;
+    if check_compatible(&signatures(&inputs)).is_ok() {
+        return concat_copy(inputs, dest);
+    }
+
+    let audio = inputs
+        .iter()
+        .map(|input| {
+            input
+                .reader
+                .best_audio_stream_index()
+                .ok()
+                .and_then(|stream_index| input.stream(stream_index))
+        })
+        .collect::<Vec<_>>();
+    let audio = match audio.iter().all(Option::is_some) {
+        true => {
+            let signatures = audio
+                .iter()
+                .flatten()
+                .map(|(_, signature)| vec![signature.clone()])
+                .collect::<Vec<_>>();
+            check_compatible(&signatures)?;
+            true
+        }
+        false if audio.iter().all(Option::is_none) => false,
+        false => {
+            let index = audio.iter().position(Option::is_none).unwrap_or(0);
+            return Err(Error::IncompatibleInputs(format!(
+                "input {index} has no audio stream"
+            )));
+        }
+    };
+    drop(inputs);
+
+    concat_reencode(sources, dest, settings, audio)
+}
+
+/// Source that is being joined.
+struct ConcatInput {
+    reader: Reader,
+    /// Video and audio streams in the source, in order.
+    streams: Vec<(usize, StreamSignature)>,
+}
+
+impl ConcatInput {
+    /// Find the stream with the given index.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream in the source.
+    fn stream(&self, stream_index: usize) -> Option<(usize, StreamSignature)> {
+        self.streams
+            .iter()
+            .find(|(index, _)| *index == stream_index)
+            .cloned()
+    }
+
+    /// Signatures of the video and audio streams in the source.
+    fn signatures(&self) -> Vec<StreamSignature> {
+        self.streams
+            .iter()
+            .map(|(_, signature)| signature.clone())
+            .collect()
+    }
+}
+
+/// Open all sources and collect their video and audio streams.
+///
+/// # Arguments
+///
+/// * `sources` - Locators to read from.
+fn open_inputs(sources: &[Locator]) -> Result<Vec<ConcatInput>> {
+    if sources.is_empty() {
+        return Err(Error::NoInputs);
+    }
+
+    sources
+        .iter()
+        .map(|source| {
+            let reader = Reader::new(source)?;
+            let streams = reader
+                .input
+                .streams()
+                .filter(|stream| {
+                    matches!(
+                        stream.parameters().medium(),
+                        AvMediaType::Video | AvMediaType::Audio
+                    )
+                })
+                .map(|stream| (stream.index(), StreamSignature::new(&stream.parameters())))
+                .collect();
+            Ok(ConcatInput { reader, streams })
+        })
+        .collect()
+}
+
+/// Signatures of the video and audio streams of each source.
+///
+/// # Arguments
+///
+/// * `inputs` - Sources to get signatures of.
+fn signatures(inputs: &[ConcatInput]) -> Vec<Vec<StreamSignature>> {
+    inputs.iter().map(ConcatInput::signatures).collect()
+}
+
+/// Join sources by copying their streams.
+///
+/// # Arguments
+///
+/// * `inputs` - Sources to join, with identical streams.
+/// * `dest` - Locator to write to.
+fn concat_copy(inputs: Vec<ConcatInput>, dest: &Locator) -> Result<()> {
+    let mut writer = Writer::new(dest)?;
+    let mut writer_stream_indices = Vec::new();
+    for (stream_index, _) in &inputs[0].streams {
+        let mut parameters = inputs[0]
+            .reader
+            .input
+            .stream(*stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .parameters();
+        clear_codec_tag(&mut parameters);
+
+        let mut writer_stream = writer
+            .output
+            .add_stream(ffmpeg::encoder::find(parameters.id()))?;
+        writer_stream.set_parameters(parameters);
+        writer_stream_indices.push(writer_stream.index());
+    }
+
+    writer.write_header()?;
+    // The muxer may change the time base while writing the header.
+    let writer_time_bases = writer_stream_indices
+        .iter()
+        .map(|&index| {
+            writer
+                .output
+                .stream(index)
+                .map(|stream| stream.time_base())
+                .ok_or(AvError::StreamNotFound)
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    let mut timeline = Timeline::default();
+    for mut input in inputs {
+        timeline.start_segment(source_start(&input.reader));
+        loop {
+            match input.reader.read_any() {
+                Ok((stream_index, packet)) => {
+                    let Some(position) = input
+                        .streams
+                        .iter()
+                        .position(|(index, _)| *index == stream_index)
+                    else {
+                        continue;
+                    };
+
+                    let (mut packet, time_base) = packet.into_inner_parts();
+                    let writer_time_base = writer_time_bases[position];
+                    packet.rescale_ts(time_base, writer_time_base);
+                    timeline.shift_packet(position, &mut packet, writer_time_base);
+                    packet.set_stream(writer_stream_indices[position]);
+                    packet.set_position(-1);
+                    writer.write_interleaved(&mut packet)?;
+                }
+                Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    writer.write_trailer()?;
+    Ok(())
+}
+
+/// Join sources by re-encoding their video streams. The best audio stream of each source is
+/// copied if `audio` is set.
+///
+/// # Arguments
+///
+/// * `sources` - Locators to read from.
+/// * `dest` - Locator to write to.
+/// * `settings` - Encoder settings to use.
+/// * `audio` - Whether or not to copy audio.
+fn concat_reencode(
+    sources: &[Locator],
+    dest: &Locator,
+    settings: Settings,
+    audio: bool,
+) -> Result<()> {
+    let (width, height) = settings.size();
+    let mut encoder = Encoder::new(dest, settings)?.interleaved();
+    // Output stream index and time base of the copied audio stream.
+    let mut audio_output: Option<(usize, AvRational)> = None;
+
+    let mut timeline = Timeline::default();
+    for source in sources {
+        let (mut decoder, mut reader, video_stream_index) = Decoder::new_with_options_and_resize(
+            source,
+            &Options::default(),
+            Resize::Exact(width, height),
+        )?
+        .into_parts();
+        let decoder_time_base = decoder.time_base();
+        let audio_stream_index = match audio {
+            true => Some(reader.best_audio_stream_index()?),
+            false => None,
+        };
+        if let (Some(audio_stream_index), None) = (audio_stream_index, audio_output) {
+            let time_base = reader
+                .input
+                .stream(audio_stream_index)
+                .ok_or(AvError::StreamNotFound)?
+                .time_base();
+            let stream_info = reader.stream_info(audio_stream_index)?;
+            audio_output = Some((encoder.add_copy_stream(stream_info)?, time_base));
+        }
+
+        timeline.start_segment(source_start(&reader));
+        loop {
+            match reader.read_any() {
+                Ok((stream_index, packet)) if stream_index == video_stream_index => {
+                    if let Some(frame) = decoder.decode_raw(packet)? {
+                        encode_frame(&mut encoder, &mut timeline, frame, decoder_time_base)?;
+                    }
+                }
+                Ok((stream_index, packet)) if Some(stream_index) == audio_stream_index => {
+                    if let Some((writer_stream_index, audio_time_base)) = audio_output {
+                        let (mut packet, time_base) = packet.into_inner_parts();
+                        packet.rescale_ts(time_base, audio_time_base);
+                        timeline.shift_packet(AUDIO_STREAM, &mut packet, audio_time_base);
+                        encoder.write_copy(
+                            Packet::new(packet, audio_time_base),
+                            writer_stream_index,
+                        )?;
+                    }
+                }
+                Ok(_) => {}
+                Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        while let Some(frame) = decoder.drain_raw()? {
+            encode_frame(&mut encoder, &mut timeline, frame, decoder_time_base)?;
+        }
+    }
+
+    encoder.finish()
+}
+
+/// Shift a decoded frame into the current segment and encode it.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to encode with.
+/// * `timeline` - Timeline of the destination.
+/// * `frame` - Decoded frame.
+/// * `time_base` - Time base of the frame timestamp.
+fn encode_frame(
+    encoder: &mut Encoder,
+    timeline: &mut Timeline,
+    mut frame: RawFrame,
+    time_base: AvRational,
+) -> Result<()> {
+    let encoder_time_base = encoder.time_base();
+    let pts = frame
+        .pts()
+        .map(|pts| pts.rescale(time_base, encoder_time_base));
+    // Frames are in presentation order, so the presentation timestamp doubles as decoding
+    // timestamp to keep it increasing.
+    let (pts, _) = timeline.shift(VIDEO_STREAM, pts, pts, 0, encoder_time_base);
+    frame.set_pts(pts);
+    encoder.encode_raw(frame)
+}
+
+/// Get the start time of a source in `TIME_BASE` units, or zero if it is not known.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+fn source_start(reader: &Reader) -> i64 {
+    match input_start_time(&reader.input) {
+        ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+        start => start,
+    }
+}
+
+/// Codec parameters of a stream that must be identical to join streams without re-encoding.
+#[derive(Debug, Clone, PartialEq)]
+struct StreamSignature {
+    medium: AvMediaType,
+    codec_id: AvCodecId,
+    properties: ParametersProperties,
+    extradata: Vec<u8>,
+}
+
+impl StreamSignature {
+    /// Create a signature from codec parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Codec parameters of the stream.
+    fn new(parameters: &AvCodecParameters) -> Self {
+        Self {
+            medium: parameters.medium(),
+            codec_id: parameters.id(),
+            properties: parameters_properties(parameters),
+            extradata: parameters_extradata(parameters),
+        }
+    }
+
+    /// Describe the first parameter that differs between two signatures.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Signature to compare with.
+    ///
+    /// # Return value
+    ///
+    /// Name of the parameter with both values, or `None` if the signatures are identical.
+    fn mismatch(&self, other: &Self) -> Option<String> {
+        if self.medium != other.medium {
+            return Some(format!(
+                "media type ({:?} vs {:?})",
+                self.medium, other.medium
+            ));
+        }
+        if self.codec_id != other.codec_id {
+            return Some(format!(
+                "codec ({:?} vs {:?})",
+                self.codec_id, other.codec_id
+            ));
+        }
+
+        let (a, b) = (&self.properties, &other.properties);
+        for (name, a, b) in [
+            ("width", a.width, b.width),
+            ("height", a.height, b.height),
+            ("sample rate", a.sample_rate, b.sample_rate),
+            ("channels", a.channels, b.channels),
+        ] {
+            if a != b {
+                return Some(format!("{name} ({a} vs {b})"));
+            }
+        }
+        if a.format != b.format {
+            let name = match self.medium {
+                AvMediaType::Audio => "sample format",
+                _ => "pixel format",
+            };
+            let unknown = || "unknown".to_string();
+            return Some(format!(
+                "{name} ({} vs {})",
+                a.format.clone().unwrap_or_else(unknown),
+                b.format.clone().unwrap_or_else(unknown),
+            ));
+        }
+        if self.extradata != other.extradata {
+            return Some("codec extradata".to_string());
+        }
+
+        None
+    }
+}
+
+/// Check that the streams of all inputs can be joined without re-encoding.
+///
+/// # Arguments
+///
+/// * `inputs` - Stream signatures of each input.
+fn check_compatible(inputs: &[Vec<StreamSignature>]) -> Result<()> {
+    let Some((first, rest)) = inputs.split_first() else {
+        return Err(Error::NoInputs);
+    };
+
+    for (index, streams) in rest.iter().enumerate() {
+        let index = index + 1;
+        if streams.len() != first.len() {
+            return Err(Error::IncompatibleInputs(format!(
+                "input {index}: number of streams ({} vs {})",
+                first.len(),
+                streams.len()
+            )));
+        }
+        for (stream, (expected, actual)) in first.iter().zip(streams).enumerate() {
+            if let Some(mismatch) = expected.mismatch(actual) {
+                return Err(Error::IncompatibleInputs(format!(
+                    "input {index}, stream {stream}: {mismatch}"
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shifts the timestamps of consecutive segments so that each segment starts where the previous
+/// one ended. All streams are shifted by the same amount, so streams stay in sync across joins.
+#[derive(Default)]
+struct Timeline {
+    /// Where the current segment starts in the destination, in `TIME_BASE` units.
+    offset: i64,
+    /// Where the current segment starts in its source, in `TIME_BASE` units.
+    segment_start: i64,
+    /// End of the latest frame so far in the destination, in `TIME_BASE` units.
+    end: i64,
+    streams: HashMap<usize, TimelineStream>,
+}
+
+/// Per-stream state of a [`Timeline`], in the time base of the stream.
+#[derive(Default)]
+struct TimelineStream {
+    last_dts: Option<i64>,
+    last_duration: i64,
+}
+
+impl Timeline {
+    /// Start the next segment, right after the end of the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_start` - Start time of the segment in its source, in `TIME_BASE` units.
+    fn start_segment(&mut self, segment_start: i64) {
+        self.offset = self.end;
+        self.segment_start = segment_start;
+    }
+
+    /// Shift the timestamps of a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream.
+    /// * `packet` - Packet to shift.
+    /// * `time_base` - Time base of the packet, which must be the same for all packets of the
+    ///   stream.
+    fn shift_packet(&mut self, stream_index: usize, packet: &mut AvPacket, time_base: AvRational) {
+        let (pts, dts) = self.shift(
+            stream_index,
+            packet.pts(),
+            packet.dts(),
+            packet.duration(),
+            time_base,
+        );
+        packet.set_pts(pts);
+        packet.set_dts(dts);
+    }
+
+    /// Shift timestamps into the current segment. Decoding timestamps are kept strictly
+    /// increasing per stream, since segments may overlap slightly.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream.
+    /// * `pts` - Presentation timestamp.
+    /// * `dts` - Decoding timestamp.
+    /// * `duration` - Duration, or zero if it is not known.
+    /// * `time_base` - Time base of the timestamps, which must be the same for all timestamps of
+    ///   the stream.
+    ///
+    /// # Return value
+    ///
+    /// Shifted presentation and decoding timestamps.
+    fn shift(
+        &mut self,
+        stream_index: usize,
+        pts: Option<i64>,
+        dts: Option<i64>,
+        duration: i64,
+        time_base: AvRational,
+    ) -> (Option<i64>, Option<i64>) {
+        let shift = (self.offset - self.segment_start).rescale(TIME_BASE, time_base);
+        let mut pts = pts.map(|pts| pts + shift);
+        let mut dts = dts.map(|dts| dts + shift);
+
+        let stream = self.streams.entry(stream_index).or_default();
+        if let (Some(current), Some(last)) = (dts, stream.last_dts) {
+            // Without a duration, the distance to the previous packet is the best estimate.
+            if duration <= 0 && current > last {
+                stream.last_duration = current - last;
+            }
+            if current <= last {
+                dts = Some(last + 1);
+            }
+        }
+        if duration > 0 {
+            stream.last_duration = duration;
+        }
+        if let (Some(current), Some(dts)) = (pts, dts) {
+            pts = Some(current.max(dts));
+        }
+        stream.last_dts = dts.or(stream.last_dts);
+
+        if let Some(timestamp) = pts.or(dts) {
+            let end = (timestamp + stream.last_duration).rescale(time_base, TIME_BASE);
+            self.end = self.end.max(end);
+        }
+
+        (pts, dts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg::util::format::Pixel as AvPixel;
+
+    use crate::testing::{self, TempPath};
+    use crate::EncoderSettings;
+
+    fn video_signature(width: u32, height: u32) -> StreamSignature {
+        StreamSignature {
+            medium: AvMediaType::Video,
+            codec_id: AvCodecId::H264,
+            properties: ParametersProperties {
+                width,
+                height,
+                format: Some("yuv420p".to_string()),
+                sample_rate: 0,
+                channels: 0,
+            },
+            extradata: vec![1, 100, 0, 31],
+        }
+    }
+
+    #[test]
+    fn test_check_compatible() {
+        let video = video_signature(64, 48);
+        assert!(check_compatible(&[vec![video.clone()], vec![video.clone()]]).is_ok());
+        assert!(matches!(check_compatible(&[]), Err(Error::NoInputs)));
+
+        let err = check_compatible(&[vec![video.clone()], vec![video_signature(32, 48)]]);
+        assert!(
+            matches!(err, Err(Error::IncompatibleInputs(ref mismatch)) if mismatch == "input 1, stream 0: width (64 vs 32)")
+        );
+
+        let mut yuv444p = video.clone();
+        yuv444p.properties.format = Some("yuv444p".to_string());
+        let err = check_compatible(&[vec![video.clone()], vec![video.clone()], vec![yuv444p]]);
+        assert!(
+            matches!(err, Err(Error::IncompatibleInputs(ref mismatch)) if mismatch == "input 2, stream 0: pixel format (yuv420p vs yuv444p)")
+        );
+
+        let err = check_compatible(&[vec![video.clone()], vec![]]);
+        assert!(
+            matches!(err, Err(Error::IncompatibleInputs(ref mismatch)) if mismatch.contains("number of streams"))
+        );
+    }
+
+    #[test]
+    fn test_timeline_joins_segments() {
+        const TIME_BASE_MS: AvRational = AvRational(1, 1000);
+
+        let mut timeline = Timeline::default();
+        let mut shifted = Vec::new();
+        // Two one second segments, the second one starting at 5 s in its source. Video packets
+        // (stream 0) have no duration.
+        for segment_start in [0, 5000] {
+            timeline.start_segment(segment_start * 1000);
+            for ms in (0..1000).step_by(50) {
+                let ts = Some(segment_start + ms);
+                if ms % 100 == 0 {
+                    shifted.push((0, timeline.shift(0, ts, ts, 0, TIME_BASE_MS)));
+                }
+                shifted.push((1, timeline.shift(1, ts, ts, 50, TIME_BASE_MS)));
+            }
+        }
+
+        let video = shifted
+            .iter()
+            .filter(|(index, _)| *index == 0)
+            .map(|(_, (pts, _))| pts.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(video, (0..2000).step_by(100).collect::<Vec<_>>());
+        let audio = shifted
+            .iter()
+            .filter(|(index, _)| *index == 1)
+            .map(|(_, (pts, _))| pts.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(audio, (0..2000).step_by(50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_timeline_keeps_dts_increasing() {
+        const TIME_BASE_MS: AvRational = AvRational(1, 1000);
+
+        let mut timeline = Timeline::default();
+        timeline.start_segment(0);
+        assert_eq!(
+            timeline.shift(0, Some(0), Some(0), 0, TIME_BASE_MS),
+            (Some(0), Some(0))
+        );
+        assert_eq!(
+            timeline.shift(0, Some(0), Some(0), 0, TIME_BASE_MS),
+            (Some(1), Some(1))
+        );
+    }
+
+    #[test]
+    fn test_concat_two_encoded_files() {
+        const NUM_FRAMES: usize = 30;
+
+        let encode = |name: &str, width: usize, height: usize| {
+            let path = TempPath::new(name);
+            testing::write_test_video_with_settings(
+                &path.locator(),
+                EncoderSettings::for_h264_yuv420p(width, height, false),
+                (0..NUM_FRAMES).map(|i| {
+                    let mut frame = RawFrame::new(AvPixel::RGB24, width as u32, height as u32);
+                    frame.data_mut(0).fill(i as u8);
+                    frame
+                }),
+            );
+            path
+        };
+        let paths = [
+            encode("concat-first.mp4", 64, 48),
+            encode("concat-second.mp4", 64, 48),
+            encode("concat-smaller.mp4", 32, 24),
+        ];
+        let [first, second, smaller] = paths.each_ref().map(|path| path.locator());
+        let joined_path = TempPath::new("concat-joined.mp4");
+        let joined = joined_path.locator();
+
+        concat(&[first.clone(), second], &joined).unwrap();
+        let mut decoder = Decoder::new(&joined).unwrap();
+        let timestamps = decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .map(|frame| frame.unwrap().pts().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 2 * NUM_FRAMES);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+        let reader = Reader::new(&joined).unwrap();
+        let duration = reader.input.duration() as f64 / TIME_BASE.denominator() as f64;
+        assert!((duration - 2.0).abs() < 0.1);
+
+        let err = concat(&[first.clone(), smaller.clone()], &joined);
+        assert!(
+            matches!(err, Err(Error::IncompatibleInputs(ref mismatch)) if mismatch.contains("width"))
+        );
+
+        concat_with_settings(
+            &[first, smaller],
+            &joined,
+            EncoderSettings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        let mut decoder = Decoder::new(&joined).unwrap();
+        assert_eq!(decoder.size(), (64, 48));
+        assert_eq!(
+            decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+            2 * NUM_FRAMES
+        );
+    }
+}
@@ -0,0 +1,721 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::software::scaling::{context::Context as AvScaler, flag::Flags as AvScalerFlags};
+
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::{copy_frame_props, pixel_format_layout, set_scaler_color_spaces};
+use crate::{ColorSpace, Error, PixelFormat, RawFrame};
+
+#[cfg(feature = "ndarray")]
+use crate::{
+    ffi::{convert_frame_to_ndarray, convert_ndarray_to_frame},
+    frame::pack_rows,
+    Frame,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Represents the possible scaling algorithms, from fastest to highest quality.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ScaleQuality {
+    /// Fast bilinear scaling. This is the fastest, at the cost of some quality.
+    Fast,
+    /// Bilinear scaling.
+    Bilinear,
+    /// Bicubic scaling. This is the default scaling algorithm of ffmpeg.
+    #[default]
+    Bicubic,
+    /// Area averaging. This gives good results when downscaling by a large factor.
+    Area,
+    /// Lanczos scaling. This is the slowest but sharpest.
+    Lanczos,
+}
+
+impl ScaleQuality {
+    /// Get the scaler flags for the scaling algorithm.
+    pub(crate) fn flags(self) -> AvScalerFlags {
+        match self {
+            ScaleQuality::Fast => AvScalerFlags::FAST_BILINEAR,
+            ScaleQuality::Bilinear => AvScalerFlags::BILINEAR,
+            ScaleQuality::Bicubic => AvScalerFlags::BICUBIC,
+            ScaleQuality::Area => AvScalerFlags::AREA,
+            ScaleQuality::Lanczos => AvScalerFlags::LANCZOS,
+        }
+    }
+}
+
+/// Largest height of standard definition video, up to which video without a declared color space
+/// is taken to be BT.601 (and BT.709 above it).
+const SD_MAX_HEIGHT: u32 = 576;
+
+/// Color spaces (the matrices between RGB and YUV) that frames are converted between when they are
+/// scaled, for example by [`crate::DecoderBuilder::scale_color_spaces`] and
+/// [`Converter::with_color_spaces`].
+///
+/// By default, each side uses the color space that it declares, and if it does not declare one,
+/// the color space is inferred from its height the way players do: BT.601 for up to 576 lines
+/// (standard definition), and BT.709 above that (high definition). So converting 1080p video to an
+/// SD size in YUV also converts it from BT.709 to BT.601, and converting it to RGB uses the BT.709
+/// matrix at any size.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ScaleColorSpaces {
+    /// Color space of the frames that are scaled, or `None` to use the declared or inferred one.
+    pub input: Option<ColorSpace>,
+    /// Color space of the scaled frames, or `None` to use the declared or inferred one.
+    pub output: Option<ColorSpace>,
+}
+
+impl ScaleColorSpaces {
+    /// Get the color spaces to convert between.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Color space that the frames that are scaled declare, and their height.
+    /// * `output` - Color space that the scaled frames declare, and their height.
+    ///
+    /// # Return value
+    ///
+    /// The color spaces of the frames that are scaled and of the scaled frames.
+    pub(crate) fn resolve(
+        &self,
+        input: (ColorSpace, u32),
+        output: (ColorSpace, u32),
+    ) -> (ColorSpace, ColorSpace) {
+        (
+            self.input
+                .unwrap_or_else(|| declared_or_inferred(input.0, input.1)),
+            self.output
+                .unwrap_or_else(|| declared_or_inferred(output.0, output.1)),
+        )
+    }
+}
+
+/// Get the color space that frames declare, or infer it from their height if they do not declare
+/// one (see [`ScaleColorSpaces`]).
+///
+/// # Arguments
+///
+/// * `declared` - Color space that the frames declare.
+/// * `height` - Height of the frames.
+fn declared_or_inferred(declared: ColorSpace, height: u32) -> ColorSpace {
+    match declared {
+        // RGB frames are often tagged as such, which says nothing about the matrix to YUV.
+        ColorSpace::Unspecified | ColorSpace::Reserved | ColorSpace::RGB => {
+            if height <= SD_MAX_HEIGHT {
+                ColorSpace::BT470BG
+            } else {
+                ColorSpace::BT709
+            }
+        }
+        declared => declared,
+    }
+}
+
+/// Tag a scaled frame with the color space that it was converted to, if it is a YUV frame. RGB and
+/// gray frames keep the tag that they got from the frame they were scaled from.
+///
+/// # Arguments
+///
+/// * `frame` - Scaled frame.
+/// * `space` - Color space that the frame was converted to.
+pub(crate) fn set_scaled_color_space(frame: &mut RawFrame, space: ColorSpace) {
+    if pixel_format_layout(frame.format())
+        .is_some_and(|layout| !layout.rgb && layout.components.len() >= 3)
+    {
+        frame.set_color_space(space);
+    }
+}
+
+/// Converts frames from one pixel format and size to another, outside of any decoder or encoder.
+///
+/// The underlying scaler is set up once when the converter is created and is reused for each
+/// frame, so converting a frame is cheap. Converters can be moved to other threads, for example to
+/// convert frames on worker threads before sending them to an encoder.
+///
+/// Frames are converted between color spaces as well, see [`Converter::with_color_spaces`].
+///
+/// # Example
+///
+/// ```ignore
+/// let mut converter = Converter::new(
+///     PixelFormat::YUV420P,
+///     (1920, 1080),
+///     PixelFormat::RGB24,
+///     (640, 360),
+///     ScaleQuality::Area,
+/// )
+/// .unwrap();
+/// let frame = converter.convert(&frame).unwrap();
+/// ```
+pub struct Converter {
+    scaler: AvScaler,
+    color_spaces: ScaleColorSpaces,
+    /// Color spaces that the scaler is set up to convert between.
+    scaler_color_spaces: (ColorSpace, ColorSpace),
+}
+
+impl Converter {
+    /// Create a converter.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_format` - Pixel format of the frames to convert.
+    /// * `src_size` - Dimensions of the frames to convert: width and height.
+    /// * `dst_format` - Pixel format to convert to.
+    /// * `dst_size` - Dimensions to resize to: width and height.
+    /// * `quality` - Scaling algorithm to use.
+    pub fn new(
+        src_format: PixelFormat,
+        src_size: (u32, u32),
+        dst_format: PixelFormat,
+        dst_size: (u32, u32),
+        quality: ScaleQuality,
+    ) -> Result<Self> {
+        if src_size.0 == 0 || src_size.1 == 0 || dst_size.0 == 0 || dst_size.1 == 0 {
+            return Err(Error::InvalidResizeParameters);
+        }
+
+        let scaler = AvScaler::get(
+            src_format,
+            src_size.0,
+            src_size.1,
+            dst_format,
+            dst_size.0,
+            dst_size.1,
+            quality.flags(),
+        )?;
+
+        let mut converter = Self {
+            scaler,
+            color_spaces: ScaleColorSpaces::default(),
+            scaler_color_spaces: (ColorSpace::Unspecified, ColorSpace::Unspecified),
+        };
+        converter.set_color_spaces(ColorSpace::Unspecified)?;
+
+        Ok(converter)
+    }
+
+    /// Set the color spaces (the matrices between RGB and YUV) to convert between. By default,
+    /// the color space of the frames to convert is the one they declare (see
+    /// [`RawFrame::color_space`]), and that of the converted frames is inferred from their height
+    /// (see [`ScaleColorSpaces`]). Converted YUV frames are tagged with their color space.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_spaces` - Color spaces to use instead of the declared or inferred ones.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Frames of a 1080p BT.601 source, converted to 720p BT.709.
+    /// let converter = Converter::new(
+    ///     PixelFormat::YUV420P,
+    ///     (1920, 1080),
+    ///     PixelFormat::YUV420P,
+    ///     (1280, 720),
+    ///     ScaleQuality::Area,
+    /// )?
+    /// .with_color_spaces(ScaleColorSpaces {
+    ///     input: Some(ColorSpace::BT470BG),
+    ///     output: None,
+    /// })?;
+    /// ```
+    pub fn with_color_spaces(mut self, color_spaces: ScaleColorSpaces) -> Result<Self> {
+        self.color_spaces = color_spaces;
+        self.set_color_spaces(ColorSpace::Unspecified)?;
+        Ok(self)
+    }
+
+    /// Convert a frame. Frame properties such as the timestamp are copied over to the converted
+    /// frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to convert. It must have the source pixel format and size of the
+    ///   converter.
+    ///
+    /// # Return value
+    ///
+    /// The converted frame, or [`Error::InvalidFrameFormat`] if the frame does not have the
+    /// source pixel format and size.
+    pub fn convert(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        if frame.format() != self.src_format() || (frame.width(), frame.height()) != self.src_size()
+        {
+            return Err(Error::invalid_frame_format(
+                format!(
+                    "{:?} frame of {}x{}",
+                    self.src_format(),
+                    self.src_size().0,
+                    self.src_size().1
+                ),
+                format!(
+                    "{:?} frame of {}x{}",
+                    frame.format(),
+                    frame.width(),
+                    frame.height()
+                ),
+            ));
+        }
+
+        self.set_color_spaces(frame.color_space())?;
+        let mut frame_converted = RawFrame::empty();
+        self.scaler
+            .run(frame, &mut frame_converted)
+            .context(ErrorContext::new("converter.convert").with_pts(frame.pts()))?;
+        copy_frame_props(frame, &mut frame_converted);
+        set_scaled_color_space(&mut frame_converted, self.scaler_color_spaces.1);
+
+        Ok(frame_converted)
+    }
+
+    /// Set up the scaler to convert between the color spaces for frames that declare a color
+    /// space, if it is not set up for them already.
+    ///
+    /// # Arguments
+    ///
+    /// * `declared` - Color space that the frames to convert declare.
+    fn set_color_spaces(&mut self, declared: ColorSpace) -> Result<()> {
+        let color_spaces = self.color_spaces.resolve(
+            (declared, self.src_size().1),
+            (ColorSpace::Unspecified, self.dst_size().1),
+        );
+        if color_spaces != self.scaler_color_spaces {
+            set_scaler_color_spaces(&mut self.scaler, color_spaces.0, color_spaces.1)?;
+            self.scaler_color_spaces = color_spaces;
+        }
+
+        Ok(())
+    }
+
+    /// Get the pixel format of the frames to convert.
+    pub fn src_format(&self) -> PixelFormat {
+        self.scaler.input().format
+    }
+
+    /// Get the dimensions of the frames to convert: width and height.
+    pub fn src_size(&self) -> (u32, u32) {
+        (self.scaler.input().width, self.scaler.input().height)
+    }
+
+    /// Get the pixel format that frames are converted to.
+    pub fn dst_format(&self) -> PixelFormat {
+        self.scaler.output().format
+    }
+
+    /// Get the dimensions that frames are resized to: width and height.
+    pub fn dst_size(&self) -> (u32, u32) {
+        (self.scaler.output().width, self.scaler.output().height)
+    }
+}
+
+unsafe impl Send for Converter {}
+unsafe impl Sync for Converter {}
+
+/// Convert a frame to another pixel format, keeping its size.
+///
+/// This sets up a new scaler for each call. Use a [`Converter`] instead to convert many frames of
+/// the same format and size.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to convert.
+/// * `target` - Pixel format to convert to.
+///
+/// # Example
+///
+/// ```ignore
+/// let frame = decoder.decode_raw().unwrap();
+/// let frame = convert_frame(&frame, PixelFormat::NV12).unwrap();
+/// ```
+pub fn convert_frame(frame: &RawFrame, target: PixelFormat) -> Result<RawFrame> {
+    let size = (frame.width(), frame.height());
+    Converter::new(frame.format(), size, target, size, ScaleQuality::default())?.convert(frame)
+}
+
+/// Copy a frame into an `ndarray`, without converting it.
+///
+/// Packed formats (RGB24, BGRA, RGBA and GRAY8) become an array with dimensions `(H, W, C)`, where
+/// `C` is the number of bytes per pixel. Planar YUV420p and NV12 frames become an array with
+/// dimensions `(H * 3 / 2, W, 1)`, with the planes stacked on top of each other as they are laid
+/// out in memory (like the I420 and NV12 layouts of OpenCV). These formats require even
+/// dimensions.
+///
+/// Padding at the end of each row of the frame is left out. Frames with other pixel formats fail
+/// with [`Error::InvalidFrameFormat`], and must be converted with [`convert_frame`] first.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to copy.
+#[cfg(feature = "ndarray")]
+pub fn frame_to_ndarray(frame: &RawFrame) -> Result<Frame> {
+    let (planes, shape) = ndarray_layout(frame.format(), frame.width(), frame.height())
+        .ok_or_else(|| {
+            Error::invalid_frame_format(
+                "packed RGB, gray or 4:2:0 frame (with even dimensions)",
+                format!(
+                    "{:?} frame of {}x{}",
+                    frame.format(),
+                    frame.width(),
+                    frame.height()
+                ),
+            )
+        })?;
+
+    let mut data = Vec::with_capacity(shape.0 * shape.1 * shape.2);
+    for (plane, row_len, rows) in planes {
+        data.extend(pack_rows(
+            frame.data(plane),
+            frame.stride(plane),
+            row_len,
+            rows,
+        ));
+    }
+
+    Frame::from_shape_vec(shape, data)
+        .map_err(|err| Error::invalid_frame_format(format!("frame of shape {shape:?}"), err))
+}
+
+/// Get how the planes of a frame are laid out in an `ndarray`.
+///
+/// # Arguments
+///
+/// * `format` - Pixel format of the frame.
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+///
+/// # Return value
+///
+/// The plane index, number of bytes per row and number of rows of each plane, and the dimensions
+/// of the `ndarray`. `None` if the pixel format or dimensions are not supported.
+#[cfg(feature = "ndarray")]
+fn ndarray_layout(
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+) -> Option<(Vec<PlaneLayout>, (usize, usize, usize))> {
+    let (width, height) = (width as usize, height as usize);
+    let packed = |channels: usize| {
+        Some((
+            vec![(0, width * channels, height)],
+            (height, width, channels),
+        ))
+    };
+    let is_even = width % 2 == 0 && height % 2 == 0;
+
+    match format {
+        PixelFormat::RGB24 => packed(3),
+        PixelFormat::BGRA | PixelFormat::RGBA => packed(4),
+        PixelFormat::GRAY8 => packed(1),
+        PixelFormat::YUV420P if is_even => Some((
+            vec![
+                (0, width, height),
+                (1, width / 2, height / 2),
+                (2, width / 2, height / 2),
+            ],
+            (height * 3 / 2, width, 1),
+        )),
+        PixelFormat::NV12 if is_even => Some((
+            vec![(0, width, height), (1, width, height / 2)],
+            (height * 3 / 2, width, 1),
+        )),
+        _ => None,
+    }
+}
+
+/// Plane index, number of bytes per row and number of rows of a frame plane.
+#[cfg(feature = "ndarray")]
+type PlaneLayout = (usize, usize, usize);
+
+/// Resize an `ndarray` frame.
+///
+/// This sets up a new scaler for each call. Use a [`Converter`] instead to resize many frames of
+/// the same size.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to resize in `HWC` format and standard layout. Frames with three channels are
+///   interpreted as RGB and frames with four channels as BGRA.
+/// * `width` - Width to resize to.
+/// * `height` - Height to resize to.
+///
+/// # Return value
+///
+/// The resized frame, with the same number of channels.
+#[cfg(feature = "ndarray")]
+pub fn resize_frame(frame: &Frame, width: u32, height: u32) -> Result<Frame> {
+    let (frame_height, frame_width, channels) = frame.dim();
+    let pixel_format = match channels {
+        3 => PixelFormat::RGB24,
+        4 => PixelFormat::BGRA,
+        _ => {
+            return Err(Error::invalid_frame_format(
+                "3 or 4 channels",
+                format!("{channels} channels"),
+            ))
+        }
+    };
+
+    let frame = convert_ndarray_to_frame(frame, pixel_format.into()).map_err(Error::from)?;
+    let mut converter = Converter::new(
+        pixel_format,
+        (frame_width as u32, frame_height as u32),
+        pixel_format,
+        (width, height),
+        ScaleQuality::default(),
+    )?;
+    let mut frame = converter.convert(&frame)?;
+
+    convert_frame_to_ndarray(&mut frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_converter_rejects_empty_size() {
+        assert!(matches!(
+            Converter::new(
+                PixelFormat::YUV420P,
+                (0, 48),
+                PixelFormat::RGB24,
+                (64, 48),
+                ScaleQuality::default(),
+            ),
+            Err(Error::InvalidResizeParameters)
+        ));
+    }
+
+    #[test]
+    fn test_converter_convert() {
+        let mut converter = Converter::new(
+            PixelFormat::RGB24,
+            (64, 48),
+            PixelFormat::YUV420P,
+            (32, 24),
+            ScaleQuality::Area,
+        )
+        .unwrap();
+        assert_eq!(converter.src_size(), (64, 48));
+        assert_eq!(converter.dst_size(), (32, 24));
+        assert_eq!(converter.dst_format(), PixelFormat::YUV420P);
+
+        let mut frame = RawFrame::new(PixelFormat::RGB24, 64, 48);
+        frame.set_pts(Some(42));
+        let frame_converted = converter.convert(&frame).unwrap();
+        assert_eq!(frame_converted.format(), PixelFormat::YUV420P);
+        assert_eq!(
+            (frame_converted.width(), frame_converted.height()),
+            (32, 24)
+        );
+        assert_eq!(frame_converted.pts(), Some(42));
+
+        let frame = RawFrame::new(PixelFormat::BGRA, 64, 48);
+        assert!(matches!(
+            converter.convert(&frame),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_scale_color_spaces_resolve() {
+        let inferred = ScaleColorSpaces::default();
+        assert_eq!(
+            inferred.resolve(
+                (ColorSpace::Unspecified, 1080),
+                (ColorSpace::Unspecified, 480)
+            ),
+            (ColorSpace::BT709, ColorSpace::BT470BG)
+        );
+        assert_eq!(
+            inferred.resolve((ColorSpace::RGB, 576), (ColorSpace::Unspecified, 720)),
+            (ColorSpace::BT470BG, ColorSpace::BT709)
+        );
+        // Declared color spaces are kept, at any height.
+        assert_eq!(
+            inferred.resolve((ColorSpace::SMPTE170M, 1080), (ColorSpace::BT2020NCL, 480)),
+            (ColorSpace::SMPTE170M, ColorSpace::BT2020NCL)
+        );
+
+        let overridden = ScaleColorSpaces {
+            input: Some(ColorSpace::BT470BG),
+            output: None,
+        };
+        assert_eq!(
+            overridden.resolve((ColorSpace::BT709, 1080), (ColorSpace::Unspecified, 1080)),
+            (ColorSpace::BT470BG, ColorSpace::BT709)
+        );
+    }
+
+    #[test]
+    fn test_converter_color_spaces() {
+        // Pure green in 8-bit limited range YUV, with BT.601 and with BT.709.
+        const GREEN_601: [u8; 3] = [145, 54, 34];
+        const GREEN_709: [u8; 3] = [173, 42, 26];
+
+        let yuv_frame = |(width, height), yuv: [u8; 3]| {
+            let mut frame = RawFrame::new(PixelFormat::YUV420P, width, height);
+            for (plane, value) in yuv.into_iter().enumerate() {
+                frame.data_mut(plane).fill(value);
+            }
+            frame
+        };
+        let center = |frame: &RawFrame| {
+            (0..frame.planes())
+                .map(|plane| {
+                    let (width, height) = (frame.plane_width(plane), frame.plane_height(plane));
+                    frame.data(plane)
+                        [height as usize / 2 * frame.stride(plane) + width as usize / 2]
+                })
+                .collect::<Vec<_>>()
+        };
+        let assert_close = |actual: &[u8], expected: &[u8], tolerance: u8| {
+            assert_eq!(actual.len(), expected.len());
+            for (actual_value, expected_value) in actual.iter().zip(expected) {
+                assert!(
+                    actual_value.abs_diff(*expected_value) <= tolerance,
+                    "{actual:?} is not close to {expected:?}"
+                );
+            }
+        };
+
+        // Upscaling SD to HD converts from BT.601 to BT.709.
+        let mut converter = Converter::new(
+            PixelFormat::YUV420P,
+            (720, 480),
+            PixelFormat::YUV420P,
+            (1920, 1080),
+            ScaleQuality::Bicubic,
+        )
+        .unwrap();
+        let frame = converter
+            .convert(&yuv_frame((720, 480), GREEN_601))
+            .unwrap();
+        assert_close(&center(&frame), &GREEN_709, 2);
+        assert_eq!(frame.color_space(), ColorSpace::BT709);
+
+        // Downscaling HD to SD converts back.
+        let mut converter = Converter::new(
+            PixelFormat::YUV420P,
+            (1920, 1080),
+            PixelFormat::YUV420P,
+            (640, 360),
+            ScaleQuality::Area,
+        )
+        .unwrap();
+        let frame = converter
+            .convert(&yuv_frame((1920, 1080), GREEN_709))
+            .unwrap();
+        assert_close(&center(&frame), &GREEN_601, 2);
+        assert_eq!(frame.color_space(), ColorSpace::BT470BG);
+
+        // HD and SD green are both green in RGB.
+        for (size, green) in [((1920, 1080), GREEN_709), ((720, 480), GREEN_601)] {
+            let mut converter = Converter::new(
+                PixelFormat::YUV420P,
+                size,
+                PixelFormat::RGB24,
+                (320, 180),
+                ScaleQuality::Area,
+            )
+            .unwrap();
+            let frame = converter.convert(&yuv_frame(size, green)).unwrap();
+            let offset = 90 * frame.stride(0) + 160 * 3;
+            assert_close(&frame.data(0)[offset..offset + 3], &[0, 255, 0], 3);
+        }
+
+        // Color spaces that the frames declare and overrides take precedence.
+        let mut frame = yuv_frame((1920, 1080), GREEN_601);
+        frame.set_color_space(ColorSpace::SMPTE170M);
+        let mut converter = Converter::new(
+            PixelFormat::YUV420P,
+            (1920, 1080),
+            PixelFormat::YUV420P,
+            (640, 360),
+            ScaleQuality::Area,
+        )
+        .unwrap();
+        assert_close(&center(&converter.convert(&frame).unwrap()), &GREEN_601, 2);
+        let mut converter = converter
+            .with_color_spaces(ScaleColorSpaces {
+                input: Some(ColorSpace::BT470BG),
+                output: Some(ColorSpace::BT709),
+            })
+            .unwrap();
+        assert_close(&center(&converter.convert(&frame).unwrap()), &GREEN_709, 2);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_ndarray_layout() {
+        let (planes, shape) = ndarray_layout(PixelFormat::BGRA, 64, 48).unwrap();
+        assert_eq!(planes, [(0, 256, 48)]);
+        assert_eq!(shape, (48, 64, 4));
+
+        let (planes, shape) = ndarray_layout(PixelFormat::YUV420P, 64, 48).unwrap();
+        assert_eq!(planes, [(0, 64, 48), (1, 32, 24), (2, 32, 24)]);
+        assert_eq!(shape, (72, 64, 1));
+        // The planes fill the array exactly.
+        let len = planes
+            .iter()
+            .map(|(_, row_len, rows)| row_len * rows)
+            .sum::<usize>();
+        assert_eq!(len, shape.0 * shape.1 * shape.2);
+
+        let (planes, shape) = ndarray_layout(PixelFormat::NV12, 64, 48).unwrap();
+        assert_eq!(planes, [(0, 64, 48), (1, 64, 24)]);
+        assert_eq!(shape, (72, 64, 1));
+
+        assert!(ndarray_layout(PixelFormat::NV12, 63, 48).is_none());
+        assert!(ndarray_layout(PixelFormat::YUV422P, 64, 48).is_none());
+    }
+
+    #[test]
+    fn test_convert_frame() {
+        let frame = RawFrame::new(PixelFormat::RGB24, 64, 48);
+        let frame = convert_frame(&frame, PixelFormat::NV12).unwrap();
+        assert_eq!(frame.format(), PixelFormat::NV12);
+        assert_eq!((frame.width(), frame.height()), (64, 48));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_frame_to_ndarray() {
+        let mut frame = RawFrame::new(PixelFormat::YUV420P, 64, 48);
+        for (plane, value) in [(0, 16), (1, 128), (2, 240)] {
+            frame.data_mut(plane).fill(value);
+        }
+        let array = frame_to_ndarray(&frame).unwrap();
+        assert_eq!(array.dim(), (72, 64, 1));
+        assert!(array
+            .slice(ndarray::s![..48, .., 0])
+            .iter()
+            .all(|v| *v == 16));
+        assert!(array
+            .slice(ndarray::s![48..60, .., 0])
+            .iter()
+            .all(|v| *v == 128));
+        assert!(array
+            .slice(ndarray::s![60.., .., 0])
+            .iter()
+            .all(|v| *v == 240));
+
+        let frame = RawFrame::new(PixelFormat::YUV444P, 64, 48);
+        assert!(matches!(
+            frame_to_ndarray(&frame),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_resize_frame() {
+        let frame = Frame::from_elem((48, 64, 3), 200);
+        let frame_resized = resize_frame(&frame, 32, 24).unwrap();
+        assert_eq!(frame_resized.dim(), (24, 32, 3));
+        assert!(frame_resized.iter().all(|value| value.abs_diff(200) <= 1));
+
+        let frame = Frame::from_elem((48, 64, 4), 200);
+        assert_eq!(resize_frame(&frame, 16, 12).unwrap().dim(), (12, 16, 4));
+    }
+}
@@ -0,0 +1,280 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::pixel::Pixel as AvPixel;
+use ffmpeg::software::scaling::{context::Context as AvScaler, flag::Flags as AvScalerFlags};
+
+use crate::convert::set_scaled_color_space;
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::{copy_frame_props, crop_frame, set_scaler_color_spaces};
+use crate::{ColorSpace, Error, RawFrame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Represents a region of a frame to crop to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// Create a crop region.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the region.
+    /// * `y` - Vertical offset of the region.
+    /// * `width` - Width of the region.
+    /// * `height` - Height of the region.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Check that the region is not empty and fits in frames of the given dimensions.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Dimensions of the frames to crop: width and height.
+    pub fn validate(&self, size: (u32, u32)) -> Result<()> {
+        let fits = |offset: u32, len: u32, max: u32| {
+            len > 0 && offset.checked_add(len).is_some_and(|end| end <= max)
+        };
+        if fits(self.x, self.width, size.0) && fits(self.y, self.height, size.1) {
+            Ok(())
+        } else {
+            Err(Error::InvalidCropParameters)
+        }
+    }
+
+    /// Get the dimensions of the region: width and height.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Whether or not the offsets of the region line up with the chroma samples of a pixel format.
+    /// For example, with YUV420p the offsets must be even, because each chroma sample covers two
+    /// by two pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Pixel format of the frames to crop.
+    pub fn is_chroma_aligned(&self, format: AvPixel) -> bool {
+        match format.descriptor() {
+            Some(descriptor) => self
+                .is_aligned_to_subsampling(descriptor.log2_chroma_w(), descriptor.log2_chroma_h()),
+            None => false,
+        }
+    }
+
+    /// Whether or not the offsets of the region are multiples of the chroma subsampling factors.
+    ///
+    /// # Arguments
+    ///
+    /// * `log2_chroma_w` - Horizontal chroma subsampling as a power of two.
+    /// * `log2_chroma_h` - Vertical chroma subsampling as a power of two.
+    fn is_aligned_to_subsampling(&self, log2_chroma_w: u8, log2_chroma_h: u8) -> bool {
+        let is_multiple = |offset: u32, log2: u8| offset.trailing_zeros() >= log2 as u32;
+        is_multiple(self.x, log2_chroma_w) && is_multiple(self.y, log2_chroma_h)
+    }
+}
+
+/// Scaler that optionally crops frames before converting them to the output pixel format and
+/// size.
+///
+/// Cropping does not copy any data: it offsets the data pointers of the frame. If the crop offsets
+/// do not line up with the chroma samples of the input pixel format, the chroma planes cannot be
+/// offset exactly, so frames are first converted to a pixel format without chroma subsampling and
+/// cropped after that.
+pub(crate) struct CropScaler {
+    size: (u32, u32),
+    crop: Option<Crop>,
+    /// Converts frames at their full size before cropping, if the crop offsets do not line up with
+    /// the chroma samples of the input pixel format.
+    converter: Option<AvScaler>,
+    scaler: AvScaler,
+    /// Color space that frames are converted to, once it is set.
+    color_space_out: Option<ColorSpace>,
+}
+
+impl CropScaler {
+    /// Create a scaler.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Pixel format of input frames.
+    /// * `size` - Dimensions of input frames.
+    /// * `crop` - Optional region of input frames to crop to.
+    /// * `format_out` - Pixel format to convert to.
+    /// * `size_out` - Dimensions to resize to (after cropping).
+    /// * `flags` - Scaler flags.
+    pub fn new(
+        format: AvPixel,
+        size: (u32, u32),
+        crop: Option<Crop>,
+        format_out: AvPixel,
+        size_out: (u32, u32),
+        flags: AvScalerFlags,
+    ) -> Result<Self> {
+        let (converter, format_cropped, size_cropped) = match crop {
+            Some(crop) => {
+                crop.validate(size)?;
+                if crop.is_chroma_aligned(format) {
+                    (None, format, crop.size())
+                } else {
+                    let format_converted = if crop.is_chroma_aligned(format_out) {
+                        format_out
+                    } else {
+                        AvPixel::YUV444P
+                    };
+                    let converter = AvScaler::get(
+                        format,
+                        size.0,
+                        size.1,
+                        format_converted,
+                        size.0,
+                        size.1,
+                        flags,
+                    )?;
+                    (Some(converter), format_converted, crop.size())
+                }
+            }
+            None => (None, format, size),
+        };
+
+        let scaler = AvScaler::get(
+            format_cropped,
+            size_cropped.0,
+            size_cropped.1,
+            format_out,
+            size_out.0,
+            size_out.1,
+            flags,
+        )?;
+
+        Ok(Self {
+            size,
+            crop,
+            converter,
+            scaler,
+            color_space_out: None,
+        })
+    }
+
+    /// Set the color spaces (the matrices between RGB and YUV) to convert between. By default,
+    /// the scaler uses the BT.601 matrix for both.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Color space of input frames.
+    /// * `output` - Color space to convert to.
+    pub fn set_color_spaces(&mut self, input: ColorSpace, output: ColorSpace) -> Result<()> {
+        // Frames are converted to the output color space when they are scaled, after cropping.
+        if let Some(converter) = self.converter.as_mut() {
+            set_scaler_color_spaces(converter, input, input)?;
+        }
+        set_scaler_color_spaces(&mut self.scaler, input, output)?;
+        self.color_space_out = Some(output);
+
+        Ok(())
+    }
+
+    /// Copy the properties of a frame (such as the timestamp) to the frame that it was converted
+    /// to, along with the color space that it was converted to.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame that was converted.
+    /// * `frame_scaled` - Frame that it was converted to.
+    pub fn copy_props(&self, frame: &RawFrame, frame_scaled: &mut RawFrame) {
+        copy_frame_props(frame, frame_scaled);
+        if let Some(space) = self.color_space_out {
+            set_scaled_color_space(frame_scaled, space);
+        }
+    }
+
+    /// Get the pixel format of input frames.
+    pub fn format(&self) -> AvPixel {
+        match self.converter {
+            Some(ref converter) => converter.input().format,
+            None => self.scaler.input().format,
+        }
+    }
+
+    /// Get the dimensions of input frames.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Crop and convert a frame. Only the frame data is converted, frame properties such as the
+    /// timestamp are not copied over.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to convert.
+    pub fn run(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        let cropped;
+        let frame = match self.crop {
+            Some(crop) => {
+                let mut converted = RawFrame::empty();
+                let frame = match self.converter {
+                    Some(ref mut converter) => {
+                        converter
+                            .run(frame, &mut converted)
+                            .context(ErrorContext::new("scaler.run").with_pts(frame.pts()))?;
+                        &converted
+                    }
+                    None => frame,
+                };
+                cropped = crop_frame(frame, crop.x, crop.y, crop.width, crop.height)
+                    .context(ErrorContext::new("scaler.crop").with_pts(frame.pts()))?;
+                &cropped
+            }
+            None => frame,
+        };
+
+        let mut frame_scaled = RawFrame::empty();
+        self.scaler
+            .run(frame, &mut frame_scaled)
+            .context(ErrorContext::new("scaler.run").with_pts(frame.pts()))?;
+
+        Ok(frame_scaled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop_validate() {
+        assert!(Crop::new(0, 0, 64, 48).validate((64, 48)).is_ok());
+        assert!(Crop::new(8, 6, 48, 36).validate((64, 48)).is_ok());
+        assert!(Crop::new(8, 6, 57, 36).validate((64, 48)).is_err());
+        assert!(Crop::new(8, 13, 48, 36).validate((64, 48)).is_err());
+        assert!(Crop::new(0, 0, 0, 48).validate((64, 48)).is_err());
+        assert!(Crop::new(u32::MAX, 0, 2, 48).validate((64, 48)).is_err());
+    }
+
+    #[test]
+    fn test_crop_is_aligned_to_subsampling() {
+        // YUV444P and RGB24.
+        assert!(Crop::new(3, 5, 16, 16).is_aligned_to_subsampling(0, 0));
+        // YUV422P.
+        assert!(Crop::new(2, 5, 16, 16).is_aligned_to_subsampling(1, 0));
+        assert!(!Crop::new(3, 4, 16, 16).is_aligned_to_subsampling(1, 0));
+        // YUV420P.
+        assert!(Crop::new(2, 4, 16, 16).is_aligned_to_subsampling(1, 1));
+        assert!(!Crop::new(2, 5, 16, 16).is_aligned_to_subsampling(1, 1));
+        // YUV410P.
+        assert!(Crop::new(4, 4, 16, 16).is_aligned_to_subsampling(2, 2));
+        assert!(!Crop::new(2, 4, 16, 16).is_aligned_to_subsampling(2, 2));
+    }
+}
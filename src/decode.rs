@@ -1,24 +1,75 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use std::marker::PhantomData;
+use std::time::{Duration, SystemTime};
+
 use ffmpeg::codec::decoder::Video as AvDecoder;
 use ffmpeg::codec::Context as AvContext;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::ffi::{AVCodecContext, AVDiscard, AVFormatContext};
 use ffmpeg::format::pixel::Pixel as AvPixel;
-use ffmpeg::software::scaling::{context::Context as AvScaler, flag::Flags as AvScalerFlags};
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
 use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
-use crate::ffi::{copy_frame_props, set_decoder_context_time_base};
-use crate::frame::FRAME_PIXEL_FORMAT;
+use crate::analysis::{AnalysisConfig, AnalysisReport, Analyzer};
+use crate::attachment::read_attachments;
+use crate::chapter::read_chapters;
+use crate::convert::ScaleColorSpaces;
+use crate::crop::{Crop, CropScaler};
+use crate::device::{DeviceSource, ScreenCaptureConfig};
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::{
+    decoder_supports_hardware_device, frame_is_hardware, input_is_seekable, pixel_format_has_alpha,
+    set_decoder_context_time_base, set_decoder_discard, set_decoder_error_concealment,
+    set_decoder_export_motion_vectors, set_decoder_hardware_device, set_decoder_low_latency,
+    set_frame_packet_dts, transfer_hardware_frame,
+};
+use crate::filter::{
+    chain_filters, Deinterlace, DeinterlaceMode, FilterGraph, FilterInput, Transform,
+};
+use crate::follow::FollowPolicy;
+use crate::frame::{FRAME_PIXEL_FORMAT, FRAME_PIXEL_FORMAT_U16};
+use crate::health::{HealthMonitor, StreamHealth};
+use crate::hwaccel::HardwareDevice;
 use crate::io::Reader;
 use crate::options::Options;
 use crate::packet::Packet;
-use crate::{Error, Locator, RawFrame, Resize};
+use crate::rawvideo::RawVideoConfig;
+use crate::scene::SceneDetector;
+use crate::sequence::ImageSequence;
+use crate::sidedata::{stream_side_data, SideData};
+use crate::tag::{read_tags, TagTarget};
+use crate::{
+    Attachment, CancellationToken, Chapter, ColorPrimaries, ColorRange, ColorSpace,
+    ColorTransferCharacteristic, Error, InterruptHandle, Locator, PixelFormat, RawFrame, Resize,
+    Source, Time,
+};
+
+#[cfg(feature = "ndarray")]
+use std::collections::HashMap;
+
+#[cfg(feature = "ndarray")]
+use ndarray::{s, Array4, ArrayView2, ArrayViewMut4};
 
 #[cfg(feature = "ndarray")]
-use crate::{ffi::convert_frame_to_ndarray, Frame, Time};
+use crate::{
+    ffi::{convert_frame_to_ndarray, convert_frame_to_ndarray_u16},
+    frame::convert_rgb24_to_ndarray_f32,
+    Frame, Frame32, FrameU16, Normalization, RawFrameMetadata, YuvFrame,
+};
+
+#[cfg(feature = "image")]
+use crate::frame::pack_rows;
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// End of a time range that reaches past the end of any video, for decoding up to the end (see
+/// [`Decoder::decode_between`]).
+pub(crate) const END_OF_VIDEO_SECS: f64 = 1e9;
+
 /// Decode video files and streams.
 ///
 /// # Example
@@ -31,26 +82,50 @@ type Result<T> = std::result::Result<T, Error>;
 ///     .for_each(|frame| println!("Got frame!"),
 /// );
 /// ```
-pub struct Decoder {
+///
+/// The lifetime is that of the bytes that a decoder created with [`Decoder::from_slice`] borrows.
+/// Decoders that own their input are `Decoder<'static>`.
+pub struct Decoder<'a> {
     decoder: DecoderSplit,
     reader: Reader,
     reader_stream_index: usize,
+    reconnect_policy: Option<ReconnectPolicy>,
+    reconnect_callback: Option<ReconnectCallback>,
+    timestamp_continuity: TimestampContinuity,
+    raw_frame_size: Option<usize>,
+    health: HealthMonitor,
+    data: PhantomData<&'a [u8]>,
 }
 
-impl Decoder {
+impl<'a> Decoder<'a> {
     /// Create a new decoder for the specified file.
     ///
     /// # Arguments
     ///
     /// * `source` - Locator to file to decode.
     pub fn new(source: &Locator) -> Result<Self> {
-        let reader = Reader::new(source)?;
-        let reader_stream_index = reader.best_video_stream_index()?;
-        Ok(Self {
-            decoder: DecoderSplit::new(&reader, reader_stream_index, None)?,
-            reader,
-            reader_stream_index,
-        })
+        Self::from_reader(Reader::new(source)?, None)
+    }
+
+    /// Create a builder for a decoder for the specified file, to pick the stream, output format
+    /// and size, hardware device and input options in one place. See [`DecoderBuilder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::builder(&PathBuf::from("video.mkv").into())
+    ///     .stream(1)
+    ///     .output_format(PixelFormat::YUV420P)
+    ///     .output_size(1280, 720)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder(source: &Locator) -> DecoderBuilder {
+        DecoderBuilder::new(source)
     }
 
     /// Create a new decoder for the specified file with input options.
@@ -60,13 +135,42 @@ impl Decoder {
     /// * `source` - Locator to file to decode.
     /// * `options` - The input options.
     pub fn new_with_options(source: &Locator, options: &Options) -> Result<Self> {
-        let reader = Reader::new_with_options(source, options)?;
-        let reader_stream_index = reader.best_video_stream_index()?;
-        Ok(Self {
-            decoder: DecoderSplit::new(&reader, reader_stream_index, None)?,
-            reader,
-            reader_stream_index,
-        })
+        Self::from_reader(Reader::new_with_options(source, options)?, None)
+    }
+
+    /// Create a new decoder that uses the given demuxer, instead of probing the input for its
+    /// format. See [`Reader::new_with_input_format`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    /// * `format` - Name of the demuxer, like `mp4` or `mpegts`.
+    /// * `options` - The input options.
+    ///
+    /// # Return value
+    ///
+    /// The decoder, or [`Error::InputFormatNotFound`] with the names of similar demuxers if there
+    /// is no demuxer with the given name.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::new_with_input_format(
+    ///     &PathBuf::from("recording.bin").into(),
+    ///     "mp4",
+    ///     &Options::default(),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_with_input_format(
+        source: &Locator,
+        format: &str,
+        options: &Options,
+    ) -> Result<Self> {
+        Self::from_reader(
+            Reader::new_with_input_format(source, format, options)?,
+            None,
+        )
     }
 
     /// Create a new decoder for the specified file with input options and custom dimensions. Each
@@ -93,268 +197,3625 @@ impl Decoder {
         options: &Options,
         resize: Resize,
     ) -> Result<Self> {
-        let reader = Reader::new_with_options(source, options)?;
-        let reader_stream_index = reader.best_video_stream_index()?;
-        Ok(Self {
-            decoder: DecoderSplit::new(&reader, reader_stream_index, Some(resize))?,
-            reader,
-            reader_stream_index,
-        })
-    }
-
-    /// Get decoder time base.
-    #[inline]
-    pub fn time_base(&self) -> AvRational {
-        self.decoder.time_base()
+        Self::from_reader(Reader::new_with_options(source, options)?, Some(resize))
     }
 
-    /// Decode frames through iterator interface. This is similar to `decode` but it returns frames
-    /// through an infinite iterator.
+    /// Create a new decoder for the specified file with input options and a read timeout. If
+    /// reading a packet blocks for longer than `timeout`, decoding fails with
+    /// [`Error::ReadTimeout`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    /// * `options` - The input options.
+    /// * `timeout` - Maximum amount of time a single read may block.
     ///
     /// # Example
     ///
     /// ```ignore
-    /// decoder
-    ///     .decode_iter()
-    ///     .take_while(Result::is_ok)
-    ///     .map(Result::unwrap)
-    ///     .for_each(|(ts, frame)| {
-    ///         // Do something with frame...
-    ///     });
+    /// let decoder = Decoder::new_with_timeout(
+    ///     &Url::parse("rtsp://localhost/stream").unwrap().into(),
+    ///     &Options::new_with_rtsp_transport_tcp(),
+    ///     Duration::from_secs(5),
+    /// )
+    /// .unwrap();
     /// ```
-    #[cfg(feature = "ndarray")]
-    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<(Time, Frame)>> + '_ {
-        std::iter::from_fn(move || Some(self.decode()))
+    pub fn new_with_timeout(
+        source: &Locator,
+        options: &Options,
+        timeout: Duration,
+    ) -> Result<Self> {
+        Self::from_reader(Reader::new_with_timeout(source, options, timeout)?, None)
     }
 
-    /// Decode a single frame.
+    /// Create a new decoder for a network source, such as an RTSP camera. Failing to connect is
+    /// reported as [`Error::ConnectionFailed`], and a dropped connection as
+    /// [`Error::ConnectionLost`].
     ///
-    /// # Return value
+    /// # Arguments
     ///
-    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    /// * `source` - Source to decode.
     ///
     /// # Example
     ///
     /// ```ignore
-    /// loop {
-    ///     let (ts, frame) = decoder.decode()?;
-    ///     // Do something with frame...
-    /// }
+    /// let source = Source::rtsp(&Url::parse("rtsp://192.168.1.10/stream").unwrap())
+    ///     .with_transport(RtspTransport::Tcp)
+    ///     .with_credentials("admin", "secret")
+    ///     .with_timeout(Duration::from_secs(5));
+    /// let decoder = Decoder::from_source(&source).unwrap();
     /// ```
-    #[cfg(feature = "ndarray")]
-    pub fn decode(&mut self) -> Result<(Time, Frame)> {
-        Ok(loop {
-            let packet = self.reader.read(self.reader_stream_index)?;
-            if let Some(frame) = self.decoder.decode(packet)? {
-                break frame;
-            }
-        })
-    }
-
-    /// Decode frames through iterator interface. This is similar to `decode_raw` but it returns
-    /// frames through an infinite iterator.
-    pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawFrame>> + '_ {
-        std::iter::from_fn(move || Some(self.decode_raw()))
+    pub fn from_source(source: &Source) -> Result<Self> {
+        Self::from_reader(Reader::from_source(source)?, None)
     }
 
-    /// Decode a single frame and return the raw ffmpeg `AvFrame`.
+    /// Create a new decoder for a capture device, like a webcam. Frames are timestamped by the
+    /// device, which usually means that timestamps follow the wall clock rather than starting at
+    /// zero.
     ///
-    /// # Return value
+    /// # Arguments
     ///
-    /// The decoded raw frame as [`RawFrame`].
-    pub fn decode_raw(&mut self) -> Result<RawFrame> {
-        Ok(loop {
-            let packet = self.reader.read(self.reader_stream_index)?;
-            if let Some(frame) = self.decoder.decode_raw(packet)? {
-                break frame;
-            }
-        })
+    /// * `device` - Device to capture from.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let device = DeviceSource::new(DeviceKind::V4l2, "/dev/video0")
+    ///     .with_video_size(1280, 720)
+    ///     .with_framerate(30);
+    /// let mut decoder = Decoder::new_from_device(&device).unwrap();
+    /// let (timestamp, frame) = decoder.decode().unwrap();
+    /// ```
+    pub fn new_from_device(device: &DeviceSource) -> Result<Self> {
+        Self::from_reader(Reader::new_from_device(device)?, None)
     }
 
-    /// Split the decoder into a decoder (of type [`DecoderSplit`]) and a [`Reader`].
+    /// Create a new decoder for a sequence of numbered images, like `frame_%06d.png`. Frames are
+    /// timed on the grid of the frame rate of the sequence. See [`ImageSequence`].
     ///
-    /// This allows the caller to detach stream reading from decoding, which is useful for advanced
-    /// use cases.
+    /// # Arguments
     ///
-    /// # Return value
+    /// * `sequence` - Image sequence to decode.
     ///
-    /// Tuple of the [`DecoderSplit`], [`Reader`] and the reader stream index.
-    #[inline]
-    pub fn into_parts(self) -> (DecoderSplit, Reader, usize) {
-        (self.decoder, self.reader, self.reader_stream_index)
+    /// # Example
+    ///
+    /// ```ignore
+    /// let sequence = ImageSequence::new("renders/frame_%06d.png", 24.0);
+    /// let mut decoder = Decoder::new_image_sequence(&sequence).unwrap();
+    /// for frame in decoder.decode_iter() {
+    ///     let (timestamp, frame) = frame.unwrap();
+    /// }
+    /// ```
+    pub fn new_image_sequence(sequence: &ImageSequence) -> Result<Self> {
+        Self::from_reader(Reader::new_image_sequence(sequence)?, None)
     }
 
-    /// Get the decoders input size (resolution dimensions): width and height.
-    #[inline(always)]
-    pub fn size(&self) -> (u32, u32) {
-        self.decoder.size
-    }
+    /// Create a new decoder for headerless raw video, like frames piped in from another process.
+    /// See [`RawVideoConfig`].
+    ///
+    /// If the input ends with an incomplete frame (its size is not a multiple of the frame size),
+    /// decoding fails with [`Error::IncompleteRawVideoFrame`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to read from, like [`Locator::from_pipe_stdin`].
+    /// * `config` - Geometry, pixel format and frame rate of the frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let config = RawVideoConfig {
+    ///     width: 1920,
+    ///     height: 1080,
+    ///     pixel_format: PixelFormat::YUV420P,
+    ///     frame_rate: 30.0,
+    /// };
+    /// let mut decoder = Decoder::new_raw_video(&Locator::from_pipe_stdin(), config).unwrap();
+    /// ```
+    pub fn new_raw_video(source: &Locator, config: RawVideoConfig) -> Result<Self> {
+        let frame_size = config.frame_size()?;
+        let mut decoder = Self::from_reader(Reader::new_raw_video(source, &config)?, None)?;
+        decoder.raw_frame_size = Some(frame_size);
 
-    /// Get the decoders output size after resizing is applied (resolution dimensions): width and
-    /// height.
-    #[inline(always)]
-    pub fn size_out(&self) -> (u32, u32) {
-        self.decoder.size_out
+        Ok(decoder)
     }
 
-    /// Get the decoders input frame rate as floating-point value.
-    pub fn frame_rate(&self) -> f32 {
-        let frame_rate = self
-            .reader
-            .input
-            .stream(self.reader_stream_index)
-            .map(|stream| stream.rate());
+    /// Create a new decoder that captures the screen. Use [`Decoder::decode_raw_bgra`] to get
+    /// frames that can be passed to [`crate::Encoder::encode_raw`] directly.
+    ///
+    /// If a region is given, it is checked against the size of the display first.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Screen to capture.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let config = ScreenCaptureConfig {
+    ///     region: Some(Rect::new(0, 0, 1280, 720)),
+    ///     ..Default::default()
+    /// };
+    /// let mut decoder = Decoder::new_screen_capture(config).unwrap();
+    /// let frame = decoder.decode_raw_bgra().unwrap();
+    /// ```
+    pub fn new_screen_capture(config: ScreenCaptureConfig) -> Result<Self> {
+        let kind = ScreenCaptureConfig::platform_kind()
+            .ok_or_else(|| Error::DeviceNotSupported("screen capture".to_string()))?;
+        let decoder = Self::new_from_device(&config.device_source(&kind, None))?;
+        let Some(region) = config.region else {
+            return Ok(decoder);
+        };
 
-        if let Some(frame_rate) = frame_rate {
-            if frame_rate.denominator() > 0 {
-                (frame_rate.numerator() as f32) / (frame_rate.denominator() as f32)
-            } else {
-                0.0
-            }
+        // This captures the whole display, so its size is the size of the display.
+        let crop = region.to_crop();
+        crop.validate(decoder.size())?;
+        if ScreenCaptureConfig::supports_region(&kind) {
+            drop(decoder);
+            Self::new_from_device(&config.device_source(&kind, Some(region)))
         } else {
-            0.0
+            decoder.with_crop(crop.x, crop.y, crop.width, crop.height)
         }
     }
-}
 
-/// Decoder part of a split [`Decoder`] and [`Reader`].
-pub struct DecoderSplit {
-    decoder: AvDecoder,
-    decoder_time_base: AvRational,
-    scaler: AvScaler,
-    size: (u32, u32),
-    size_out: (u32, u32),
-}
+    /// Create a new decoder that reads from any type that implements [`std::io::Read`] and
+    /// [`std::io::Seek`].
+    ///
+    /// # Arguments
+    ///
+    /// * `io` - Stream to decode.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::from_io(std::fs::File::open("video.mp4").unwrap()).unwrap();
+    /// ```
+    pub fn from_io(io: impl std::io::Read + std::io::Seek + Send + 'static) -> Result<Self> {
+        Self::from_reader(Reader::from_io(io)?, None)
+    }
 
-impl DecoderSplit {
-    /// Get decoder time base.
-    #[inline]
-    pub fn time_base(&self) -> AvRational {
-        self.decoder_time_base
+    /// Create a new decoder that reads from any type that implements [`std::io::Read`] and
+    /// [`std::io::Seek`] with input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `io` - Stream to decode.
+    /// * `options` - The input options.
+    pub fn from_io_with_options(
+        io: impl std::io::Read + std::io::Seek + Send + 'static,
+        options: &Options,
+    ) -> Result<Self> {
+        Self::from_reader(Reader::from_io_with_options(io, options)?, None)
     }
 
-    /// Decode a [`Packet`].
+    /// Create a new decoder that decodes bytes in memory. The bytes can be held by any owner that
+    /// derefs to a byte slice, such as `Vec<u8>` or `Arc<[u8]>`. To decode bytes that are
+    /// borrowed without copying them, use [`Decoder::from_slice`].
     ///
-    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
-    /// should keep feeding packets until the decoder returns a frame.
+    /// # Arguments
     ///
-    /// # Return value
+    /// * `data` - Bytes to decode.
     ///
-    /// A tuple of the [`Frame`] and timestamp (relative to the stream) and the frame itself if the
-    /// decoder has a frame available, [`None`] if not.
-    #[cfg(feature = "ndarray")]
-    pub fn decode(&mut self, packet: Packet) -> Result<Option<(Time, Frame)>> {
-        match self.decode_raw(packet)? {
-            Some(mut frame) => {
-                // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the
-                // encoder will use when encoding for the `PTS` field.
-                let timestamp = Time::new(Some(frame.packet().dts), self.decoder_time_base);
-                let frame =
-                    convert_frame_to_ndarray(&mut frame).map_err(Error::BackendError)?;
+    /// # Example
+    ///
+    /// ```ignore
+    /// let data: Arc<[u8]> = receive_video().into();
+    /// let decoder = Decoder::from_bytes(data).unwrap();
+    /// ```
+    pub fn from_bytes(data: impl AsRef<[u8]> + Send + 'static) -> Result<Self> {
+        Self::from_reader(Reader::from_bytes(data)?, None)
+    }
 
-                Ok(Some((timestamp, frame)))
-            }
-            None => Ok(None),
-        }
+    /// Create a new decoder that decodes bytes in memory with input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Bytes to decode.
+    /// * `options` - The input options.
+    pub fn from_bytes_with_options(
+        data: impl AsRef<[u8]> + Send + 'static,
+        options: &Options,
+    ) -> Result<Self> {
+        Self::from_reader(Reader::from_bytes_with_options(data, options)?, None)
     }
 
-    /// Decode a [`Packet`].
+    /// Create a new decoder that decodes borrowed bytes in memory, without copying them. The
+    /// decoder cannot outlive the bytes.
     ///
-    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
-    /// should keep feeding packets until the decoder returns a frame.
+    /// # Arguments
     ///
-    /// # Return value
+    /// * `data` - Bytes to decode.
     ///
-    /// The decoded raw frame as [`RawFrame`] if the decoder has a frame available, [`None`] if not.
-    pub fn decode_raw(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
-        let (mut packet, packet_time_base) = packet.into_inner_parts();
-        packet.rescale_ts(packet_time_base, self.decoder_time_base);
+    /// # Example
+    ///
+    /// ```ignore
+    /// let data = std::fs::read("video.mp4").unwrap();
+    /// let mut decoder = Decoder::from_slice(&data).unwrap();
+    /// let (_, frame) = decoder.decode().unwrap();
+    /// ```
+    pub fn from_slice(data: &'a [u8]) -> Result<Self> {
+        Self::from_slice_with_options(data, &Options::default())
+    }
 
-        self.decoder
-            .send_packet(&packet)
-            .map_err(Error::BackendError)?;
+    /// Create a new decoder that decodes borrowed bytes in memory with input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Bytes to decode.
+    /// * `options` - The input options.
+    pub fn from_slice_with_options(data: &'a [u8], options: &Options) -> Result<Self> {
+        // SAFETY: The reader is only ever owned by the decoder, which is bound by the lifetime of
+        // the bytes. Only `'static` decoders can give it away (see `into_parts`).
+        let reader = unsafe { Reader::from_slice_with_options(data, options)? };
+        Self::from_reader(reader, None)
+    }
 
-        match self.decoder_receive_frame()? {
-            Some(frame) => {
-                let mut frame_scaled = RawFrame::empty();
-                self.scaler
-                    .run(&frame, &mut frame_scaled)
-                    .map_err(Error::BackendError)?;
+    /// Get decoder time base. With a filter, this is the time base of the filter output.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.decoder.time_base()
+    }
 
-                copy_frame_props(&frame, &mut frame_scaled);
+    /// Get a handle that can be used to cancel decoding from another thread. Cancelling aborts a
+    /// read that is in progress, after which decoding fails with [`Error::ReadInterrupted`].
+    #[inline]
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.reader.interrupt_handle()
+    }
 
-                Ok(Some(frame_scaled))
-            }
-            None => Ok(None),
-        }
+    /// Get the underlying ffmpeg decoder context, to read what the crate does not expose. See
+    /// [`DecoderSplit::codec_context`].
+    #[inline]
+    pub fn codec_context(&self) -> &AvContext {
+        self.decoder.codec_context()
     }
 
-    /// Get the decoders input size (resolution dimensions): width and height.
-    #[inline(always)]
-    pub fn size(&self) -> (u32, u32) {
-        self.size
+    /// Get the underlying ffmpeg decoder context, for anything that the crate does not wrap. See
+    /// [`DecoderSplit::as_raw_codec_context`].
+    ///
+    /// # Safety
+    ///
+    /// Like [`DecoderSplit::as_raw_codec_context`]. In addition, the pointer must not be used
+    /// after the decoder reconnected (see [`Decoder::with_reconnect_policy`]), since that opens a
+    /// new decoder context.
+    #[inline]
+    pub unsafe fn as_raw_codec_context(&mut self) -> *mut AVCodecContext {
+        self.decoder.as_raw_codec_context()
     }
 
-    /// Get the decoders output size after resizing is applied (resolution dimensions): width and
-    /// height.
-    #[inline(always)]
-    pub fn size_out(&self) -> (u32, u32) {
-        self.size_out
+    /// Get the underlying ffmpeg demuxer context of the reader. See
+    /// [`Reader::as_raw_format_context`].
+    ///
+    /// # Safety
+    ///
+    /// Like [`Reader::as_raw_format_context`]. In addition, the pointer must not be used after the
+    /// decoder reconnected (see [`Decoder::with_reconnect_policy`]), since that opens the source
+    /// again.
+    #[inline]
+    pub unsafe fn as_raw_format_context(&mut self) -> *mut AVFormatContext {
+        self.reader.as_raw_format_context()
     }
 
-    /// Create a new [`DecoderSplit`].
+    /// Reconnect according to the given policy when reading from the source fails, for example
+    /// because of a network blip. On reconnect, the input is reopened and probed again, after
+    /// which decoding continues. Frames that were still buffered in the decoder are discarded,
+    /// so frames are never delivered out of order.
+    ///
+    /// This only works for sources that can be reopened, and not for custom I/O.
     ///
     /// # Arguments
     ///
-    /// * `reader` - [`Reader`] to initialize decoder from.
-    /// * `resize` - Optional resize strategy to apply to frames.
-    pub fn new(
-        reader: &Reader,
-        reader_stream_index: usize,
+    /// * `reconnect_policy` - How to reconnect.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::from_source(&source)
+    ///     .unwrap()
+    ///     .with_reconnect_policy(ReconnectPolicy::default())
+    ///     .on_reconnect(|event| println!("{event:?}"));
+    /// ```
+    pub fn with_reconnect_policy(mut self, reconnect_policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(reconnect_policy);
+        self
+    }
+
+    /// Follow the source while another process is still writing it, like a recording in
+    /// progress: when decoding catches up with what was written so far, it waits and polls for
+    /// more data instead of ending. Decoding goes on until it is cancelled through
+    /// [`Decoder::interrupt_handle`], after which it fails with [`Error::ReadInterrupted`]. Use
+    /// [`Decoder::follow_with_policy`] to end at a period of inactivity or after a timeout instead.
+    ///
+    /// MPEG-TS, fragmented MP4 and most other formats can be followed. Plain MP4 files cannot,
+    /// since their index describes all frames up front: following those fails with
+    /// [`Error::NotFollowable`].
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_interval` - How long to wait before reading again after catching up.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("recording.ts").into())
+    ///     .unwrap()
+    ///     .follow(Duration::from_millis(100))
+    ///     .unwrap();
+    /// for frame in decoder.decode_raw_iter() {
+    ///     // Frames come in as they are written.
+    /// }
+    /// ```
+    pub fn follow(self, poll_interval: Duration) -> Result<Self> {
+        self.follow_with_policy(FollowPolicy {
+            poll_interval,
+            ..Default::default()
+        })
+    }
+
+    /// Follow the source while another process is still writing it (see [`Decoder::follow`]),
+    /// with a policy that can end decoding at a period of inactivity, like when the recording has
+    /// stopped, or after an overall timeout.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - How to wait for more data.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::new(&PathBuf::from("recording.ts").into())
+    ///     .unwrap()
+    ///     .follow_with_policy(FollowPolicy {
+    ///         poll_interval: Duration::from_millis(100),
+    ///         inactivity_timeout: Some(Duration::from_secs(10)),
+    ///         timeout: None,
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn follow_with_policy(mut self, policy: FollowPolicy) -> Result<Self> {
+        self.reader = self.reader.with_follow(policy)?;
+        Ok(self)
+    }
+
+    /// Crop frames to a region before they are converted (and resized, if the decoder resizes
+    /// frames). The crop is applied without copying the decoded frame.
+    ///
+    /// Note that if the offsets do not line up with the chroma samples of the decoded pixel format
+    /// (for example odd offsets with YUV420p), frames are converted at full size before cropping
+    /// so that the colors stay correct, which is slower.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the crop region.
+    /// * `y` - Vertical offset of the crop region.
+    /// * `width` - Width of the crop region.
+    /// * `height` - Height of the crop region.
+    ///
+    /// # Return value
+    ///
+    /// The decoder, or [`Error::InvalidCropParameters`] if the region does not fit in the frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Remove letterbox bars from a 1920x1080 video.
+    /// let decoder = Decoder::new(&PathBuf::from("video.mp4").into())
+    ///     .unwrap()
+    ///     .with_crop(0, 140, 1920, 800)
+    ///     .unwrap();
+    /// ```
+    pub fn with_crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Result<Self> {
+        self.decoder = self.decoder.with_crop(x, y, width, height)?;
+        Ok(self)
+    }
+
+    /// Apply a libavfilter filter graph to the decoded frames, described like the `-vf` option on
+    /// the command line. The filters run on the frames in the decoder pixel format, before they
+    /// are cropped, converted and resized, so [`Decoder::size`], [`Decoder::size_out`] and
+    /// [`Decoder::time_base`] follow filters that change the size or timing of the frames (like
+    /// `scale` and `fps`). Frame timestamps are the timestamps of the filter output.
+    ///
+    /// Filters and transforms (see [`Decoder::with_transform`]) apply in the order they are set.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filters to apply, separated by commas, like `yadif,scale=1280:720,fps=30`.
+    ///
+    /// # Return value
+    ///
+    /// The decoder, or [`Error::InvalidFilterGraph`] if the filters cannot be set up.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("interlaced.ts").into())
+    ///     .unwrap()
+    ///     .with_filter("yadif,scale=1280:720")
+    ///     .unwrap();
+    /// let (timestamp, frame) = decoder.decode().unwrap();
+    /// ```
+    pub fn with_filter(mut self, spec: &str) -> Result<Self> {
+        self.decoder = self.decoder.with_filter(spec)?;
+        Ok(self)
+    }
+
+    /// Flip or rotate the decoded frames, without writing a filter description (see
+    /// [`Decoder::with_filter`]). Quarter rotations swap the width and height, which
+    /// [`Decoder::size`] and [`Decoder::size_out`] reflect.
+    ///
+    /// The decoder does not rotate frames according to the rotation metadata of the stream (the
+    /// display matrix), so the transform applies to the frames as they are stored. Transforms
+    /// and filters apply in the order they are set, and the crop region (see
+    /// [`Decoder::with_crop`]) and resizing apply to the transformed frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - Transform to apply.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Portrait video that was recorded upside down.
+    /// let decoder = Decoder::new(&PathBuf::from("video.mp4").into())
+    ///     .unwrap()
+    ///     .with_transform(Transform::Rotate180)
+    ///     .unwrap();
+    /// ```
+    pub fn with_transform(mut self, transform: Transform) -> Result<Self> {
+        self.decoder = self.decoder.with_transform(transform)?;
+        Ok(self)
+    }
+
+    /// Deinterlace the decoded frames with the `yadif` filter, to remove the combing of interlaced
+    /// sources (like 1080i broadcasts). With [`Deinterlace::Auto`], only the frames that the
+    /// decoder flags as interlaced are deinterlaced, and the field order is taken from the flags
+    /// of each frame.
+    ///
+    /// With [`DeinterlaceMode::Field`], every field becomes a frame, so the frame rate (see
+    /// [`Decoder::frame_rate`]) doubles, and the timestamps are in a time base with twice the
+    /// resolution (see [`Decoder::time_base`]). With [`Deinterlace::Auto`], progressive frames
+    /// still produce a single frame, so the frame rate of mixed content varies.
+    ///
+    /// Deinterlacing is a filter (see [`Decoder::with_filter`]), and applies in the order it is
+    /// set. Deinterlace before applying any filters that resize the frames, which mixes the fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `deinterlace` - When to deinterlace.
+    /// * `mode` - Whether to produce a frame per frame or per field.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("capture.ts").into())
+    ///     .unwrap()
+    ///     .with_deinterlace(Deinterlace::Auto, DeinterlaceMode::Frame)
+    ///     .unwrap();
+    /// ```
+    pub fn with_deinterlace(
+        mut self,
+        deinterlace: Deinterlace,
+        mode: DeinterlaceMode,
+    ) -> Result<Self> {
+        self.decoder = self.decoder.with_deinterlace(deinterlace, mode)?;
+        Ok(self)
+    }
+
+    /// Export the motion vectors of the codec with each frame, for cheap motion analysis without
+    /// optical flow (like `-flags2 +export_mvs` on the command line). Get the motion vectors of
+    /// the frames from [`Decoder::decode_raw`] with [`crate::RawFrameSideData::motion_vectors`].
+    /// This is supported by decoders of codecs with motion compensation, like H.264 and MPEG-4.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("video.mp4").into())
+    ///     .unwrap()
+    ///     .with_motion_vectors();
+    /// let frame = decoder.decode_raw().unwrap();
+    /// let motion = frame.motion_vectors();
+    /// ```
+    pub fn with_motion_vectors(mut self) -> Self {
+        self.decoder = self.decoder.with_motion_vectors();
+        self
+    }
+
+    /// Set what to do with frames whose timestamps are missing or go backwards, which some broken
+    /// files (like those of some dashcams) have. Passing such timestamps on makes the muxer fail
+    /// when the frames are encoded, since it needs timestamps that increase. By default,
+    /// timestamps are passed on as they are (see [`TimestampPolicy`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - What to do with invalid timestamps.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("dashcam.ts").into())
+    ///     .unwrap()
+    ///     .with_timestamp_policy(TimestampPolicy::Repair);
+    /// while let Ok(frame) = decoder.decode_raw() {
+    ///     // Timestamps of frames always increase...
+    /// }
+    /// println!("Repaired {} timestamps", decoder.timestamps_repaired());
+    /// ```
+    pub fn with_timestamp_policy(mut self, policy: TimestampPolicy) -> Self {
+        self.decoder = self.decoder.with_timestamp_policy(policy);
+        self
+    }
+
+    /// Trade picture quality and frames for decoding speed, for example for a scrubbing preview
+    /// while fast-forwarding (see [`DecodeSpeed`]). By default, every frame is decoded in full.
+    ///
+    /// Frames that are skipped are simply not produced: the timestamps of the frames that come
+    /// out of [`Decoder::decode`] and [`Decoder::decode_iter`] are those of the frames that were
+    /// actually decoded, so skipped frames show up as gaps between timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `speed` - Which frames and decoding steps to skip.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("video.mp4").into())
+    ///     .unwrap()
+    ///     .with_decode_speed(DecodeSpeed::FastPreview);
+    /// for frame in decoder.decode_iter() {
+    ///     let (timestamp, frame) = frame.unwrap();
+    ///     // Show the frame at its own timestamp...
+    /// }
+    /// ```
+    pub fn with_decode_speed(mut self, speed: DecodeSpeed) -> Self {
+        self.decoder = self.decoder.with_decode_speed(speed);
+        self
+    }
+
+    /// Get the number of timestamps that were synthesized so far, with
+    /// [`TimestampPolicy::Repair`].
+    pub fn timestamps_repaired(&self) -> u64 {
+        self.decoder.timestamps_repaired()
+    }
+
+    /// Get the health of the video stream: its input bit rate, the jitter of the arrival of its
+    /// packets, the rate of decode errors and the number of discontinuities (timestamp jumps).
+    /// The figures are kept up to date as packets are read and decoded, which is cheap enough to
+    /// do for every packet. This is meant for supervising live inputs (like RTSP cameras), to
+    /// tell which ones are degrading.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let health = decoder.health();
+    /// if health.decode_errors_per_minute > 10 || health.bitrate < 100_000.0 {
+    ///     // Restart the camera stream...
+    /// }
+    /// ```
+    pub fn health(&self) -> StreamHealth {
+        self.health.health()
+    }
+
+    /// Start measuring the health of the video stream over, as if no packets were read yet (see
+    /// [`Decoder::health`]).
+    pub fn reset_health(&mut self) {
+        self.health.reset();
+    }
+
+    /// Set the smallest jump of the timestamps of the video stream (forward or back, from where
+    /// the previous packet ended) that counts as a discontinuity in [`Decoder::health`]. This is
+    /// one second by default. The timestamps are those of the input, before they are made
+    /// continuous across reconnects.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Smallest timestamp jump that counts as a discontinuity.
+    pub fn with_discontinuity_threshold(mut self, threshold: Duration) -> Self {
+        self.health.set_discontinuity_threshold(threshold);
+        self
+    }
+
+    /// Set a callback that is notified of each reconnect attempt and its outcome.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function to call with each [`ReconnectEvent`].
+    pub fn on_reconnect(
+        mut self,
+        callback: impl FnMut(&ReconnectEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.reconnect_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Decode frames through iterator interface. This is similar to `decode` but it returns frames
+    /// through an infinite iterator.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// decoder
+    ///     .decode_iter()
+    ///     .take_while(Result::is_ok)
+    ///     .map(Result::unwrap)
+    ///     .for_each(|(ts, frame)| {
+    ///         // Do something with frame...
+    ///     });
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<(Time, Frame)>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode()))
+    }
+
+    /// Decode frames through iterator interface, with their timestamps in seconds. This is
+    /// similar to `decode_iter`, but the timestamps are a lossy convenience (see
+    /// [`Time::as_secs_checked`]): they are [`None`] for frames without a timestamp, or with a
+    /// timestamp before the start of the stream.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for frame in decoder.decode_iter_secs() {
+    ///     let (secs, frame) = frame.unwrap();
+    ///     if let Some(secs) = secs {
+    ///         println!("frame at {secs:.3}s");
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_iter_secs(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(Option<f64>, Frame)>> + use<'_, 'a> {
+        self.decode_iter()
+            .map(|frame| frame.map(|(time, frame)| (time.as_secs_checked(), frame)))
+    }
+
+    /// Decode frames through iterator interface, and play the video again from the start when it
+    /// ends (see [`Decoder::seek_to_start`]), without reopening the source. Timestamps keep
+    /// increasing across the loops: every loop is offset by the duration of the video (up to the
+    /// end of its last frame), so the frames of the second loop follow the last frame of the
+    /// first loop as if the video were that much longer.
+    ///
+    /// Unlike [`Decoder::decode_iter`], the iterator ends after the last loop, and the frames that
+    /// the decoder still holds at the end of the video are decoded as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Number of times to play the video, or [`None`] to play it endlessly.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("clip.mp4").into()).unwrap();
+    /// for frame in decoder.decode_iter_looped(None) {
+    ///     let (timestamp, frame) = frame.unwrap();
+    ///     // Show the frame at `timestamp`...
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_iter_looped(
+        &mut self,
+        n: Option<usize>,
+    ) -> impl Iterator<Item = Result<(Time, Frame)>> + use<'_, 'a> {
+        let mut looped = LoopedPlayback::new(n);
+        std::iter::from_fn(move || self.decode_looped(&mut looped).transpose())
+    }
+
+    /// Decode a single frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself. Frames have 4
+    /// channels in BGRA order if the input has an alpha channel (see [`Decoder::has_alpha`]), and
+    /// 3 channels in RGB order otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// loop {
+    ///     let (ts, frame) = decoder.decode()?;
+    ///     // Do something with frame...
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode(&mut self) -> Result<(Time, Frame)> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.convert(frame)
+    }
+
+    /// Decode a single frame along with its metadata entries, like the `lavfi.*` entries of the
+    /// filters it passed through (see [`RawFrameMetadata`]). This is similar to `decode`.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream), the frame itself and its metadata.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_with_metadata(&mut self) -> Result<(Time, Frame, HashMap<String, String>)> {
+        let frame = self.decode_unscaled()?;
+        let metadata = RawFrameMetadata::metadata(&frame);
+        let (timestamp, frame) = self.decoder.convert(frame)?;
+
+        Ok((timestamp, frame, metadata))
+    }
+
+    /// Decode frames as separate YUV planes through iterator interface. This is similar to
+    /// `decode_yuv` but it returns frames through an infinite iterator.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_yuv_iter(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(Time, YuvFrame)>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode_yuv()))
+    }
+
+    /// Decode a single frame as separate Y, U and V planes with 4:2:0 chroma subsampling. If the
+    /// stream is already 4:2:0 (and frames are not cropped or resized), the planes are copied as
+    /// is, without any conversion.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (ts, frame) = decoder.decode_yuv()?;
+    /// let (width, height) = frame.size();
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_yuv(&mut self) -> Result<(Time, YuvFrame)> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.convert_yuv(frame)
+    }
+
+    /// Decode frames with 16 bits per component through iterator interface. This is similar to
+    /// `decode_u16` but it returns frames through an infinite iterator.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_u16_iter(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(Time, FrameU16)>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode_u16()))
+    }
+
+    /// Decode a single frame with 16 bits per component. Unlike `decode`, which converts to 8-bit
+    /// RGB, this preserves the full precision of high bit depth (10-bit, 12-bit) sources. Use
+    /// [`Decoder::color_primaries`] and [`Decoder::color_transfer_characteristic`] to find out how
+    /// to interpret the values (for example for tone mapping HDR content).
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself, as RGB with
+    /// type `u16`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// loop {
+    ///     let (ts, frame) = decoder.decode_u16()?;
+    ///     // Do something with frame...
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_u16(&mut self) -> Result<(Time, FrameU16)> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.convert_u16(frame)
+    }
+
+    /// Decode floating point frames through iterator interface. This is similar to `decode_f32`
+    /// but it returns frames through an infinite iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `normalization` - How to convert components to floating point values.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_f32_iter(
+        &mut self,
+        normalization: Normalization,
+    ) -> impl Iterator<Item = Result<(Time, Frame32)>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode_f32(&normalization)))
+    }
+
+    /// Decode a single frame as floating point values, for example as input for a machine
+    /// learning model. The components are converted and normalized while they are copied out of
+    /// the decoded frame, which saves converting (and allocating) the result of `decode` again.
+    ///
+    /// # Arguments
+    ///
+    /// * `normalization` - How to convert components to floating point values, and in which
+    ///   layout.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let normalization = Normalization::imagenet();
+    /// loop {
+    ///     let (ts, frame) = decoder.decode_f32(&normalization)?;
+    ///     // Feed the `(C, H, W)` frame to a model...
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_f32(&mut self, normalization: &Normalization) -> Result<(Time, Frame32)> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.convert_f32(frame, normalization)
+    }
+
+    /// Decode frames as images through iterator interface. This is similar to `decode_image` but
+    /// it returns frames through an infinite iterator.
+    #[cfg(feature = "image")]
+    pub fn decode_image_iter(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(Time, image::RgbImage)>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode_image()))
+    }
+
+    /// Decode a single frame as an RGB image of the `image` crate.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (ts, image) = decoder.decode_image()?;
+    /// image.save("first_frame.png")?;
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn decode_image(&mut self) -> Result<(Time, image::RgbImage)> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.convert_image(frame)
+    }
+
+    /// Decode frames through iterator interface. This is similar to `decode_raw` but it returns
+    /// frames through an infinite iterator.
+    pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawFrame>> + use<'_, 'a> {
+        std::iter::from_fn(move || Some(self.decode_raw()))
+    }
+
+    /// Decode a single frame and return the raw ffmpeg `AvFrame`.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`].
+    pub fn decode_raw(&mut self) -> Result<RawFrame> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.scale_raw(&frame)
+    }
+
+    /// Decode a single frame in BGRA format, which the encoder accepts as is.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] in BGRA format.
+    pub fn decode_raw_bgra(&mut self) -> Result<RawFrame> {
+        let frame = self.decode_unscaled()?;
+        self.decoder.scale_bgra(&frame)
+    }
+
+    /// Get the decoders input size (resolution dimensions): width and height.
+    #[inline(always)]
+    pub fn size(&self) -> (u32, u32) {
+        self.decoder.size
+    }
+
+    /// Get the decoders output size after cropping and resizing are applied (resolution
+    /// dimensions): width and height.
+    #[inline(always)]
+    pub fn size_out(&self) -> (u32, u32) {
+        self.decoder.size_out
+    }
+
+    /// Get the container format that the demuxer detected: its short name (like `matroska,webm`)
+    /// and its long name (like `Matroska / WebM`). See [`Reader::container_format`].
+    pub fn container_format(&self) -> (String, String) {
+        self.reader.container_format()
+    }
+
+    /// Get the name of the codec of the video stream, like `h264` or `hevc`.
+    #[inline]
+    pub fn codec_name(&self) -> String {
+        self.decoder.codec_name()
+    }
+
+    /// Get the bit rate of the video stream in bits per second. If the container does not store
+    /// the bit rate of the stream (as in Matroska), this is the overall bit rate of the input.
+    /// Returns `None` if neither is known.
+    pub fn bit_rate(&self) -> Option<u64> {
+        self.decoder.bit_rate().or_else(|| self.reader.bit_rate())
+    }
+
+    /// Get the number of bytes read from the input so far. See [`Reader::bytes_read`].
+    pub fn bytes_read(&self) -> u64 {
+        self.reader.bytes_read()
+    }
+
+    /// Get the size of the input in bytes, if it is known. See [`Reader::total_bytes`].
+    pub fn total_bytes(&self) -> Option<u64> {
+        self.reader.total_bytes()
+    }
+
+    /// Get the pixel format of the video stream, as it comes out of the decoder, before frames are
+    /// converted.
+    #[inline]
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.decoder.pixel_format()
+    }
+
+    /// Get the files embedded in the input, like fonts and cover art. This includes attachments (as
+    /// in Matroska) and attached pictures (as in MP4).
+    pub fn attachments(&self) -> Vec<Attachment> {
+        read_attachments(&self.reader.input)
+    }
+
+    /// Get the tags (metadata keys and values) of the input or one of its streams, like the tags
+    /// that [`crate::Encoder::set_tag`] writes. Keys may come back in another case than they were
+    /// written in, depending on the container format.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - What to get the tags of.
+    pub fn tags(&self, target: TagTarget) -> Result<Vec<(String, String)>> {
+        read_tags(&self.reader.input, target)
+    }
+
+    /// Get the keys of the input options that neither the protocol nor the demuxer recognized,
+    /// for example because of a typo in the name of an option.
+    pub fn ignored_options(&self) -> &[String] {
+        self.reader.ignored_options()
+    }
+
+    /// Get the wall-clock time at which a frame was captured, for correlating frames with other
+    /// sensors. See [`Reader::capture_time`] for the inputs that report it. Frames from
+    /// Video4Linux2 devices (see [`Decoder::new_from_device`]) carry the time at which the device
+    /// captured them.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Timestamp of the frame, as returned by the decoder.
+    ///
+    /// # Return value
+    ///
+    /// The capture time, or `None` if the input does not tell when the frame was captured.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (timestamp, frame) = decoder.decode().unwrap();
+    /// if let Some(capture_time) = decoder.capture_time(&timestamp) {
+    ///     println!("captured at {capture_time:?}");
+    /// }
+    /// ```
+    pub fn capture_time(&self, timestamp: &Time) -> Option<SystemTime> {
+        self.reader.capture_time(timestamp)
+    }
+
+    /// Get the chapters of the input, if the container has any.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        read_chapters(&self.reader.input)
+    }
+
+    /// Whether or not the input has an alpha channel, like VP9 in WebM with alpha or ProRes 4444
+    /// with alpha. If it does, [`Decoder::decode`] produces frames with 4 channels.
+    #[inline]
+    pub fn has_alpha(&self) -> bool {
+        self.decoder.has_alpha()
+    }
+
+    /// Get the color primaries of the input.
+    #[inline]
+    pub fn color_primaries(&self) -> ColorPrimaries {
+        self.decoder.color_primaries()
+    }
+
+    /// Get the color transfer characteristic of the input.
+    #[inline]
+    pub fn color_transfer_characteristic(&self) -> ColorTransferCharacteristic {
+        self.decoder.color_transfer_characteristic()
+    }
+
+    /// Get the color space of the input.
+    #[inline]
+    pub fn color_space(&self) -> ColorSpace {
+        self.decoder.color_space()
+    }
+
+    /// Get the color range of the input.
+    #[inline]
+    pub fn color_range(&self) -> ColorRange {
+        self.decoder.color_range()
+    }
+
+    /// Get the side data that the container stores with the video stream, like the HDR metadata of
+    /// MP4 and Matroska files (see [`crate::HdrMetadata::from_side_data`]). Decoded frames may
+    /// carry side data of their own (see [`crate::RawFrameSideData`]).
+    pub fn stream_side_data(&self) -> Vec<SideData> {
+        self.reader
+            .input
+            .stream(self.reader_stream_index)
+            .map(|stream| stream_side_data(&stream))
+            .unwrap_or_default()
+    }
+
+    /// Get the decoders input frame rate as floating-point value. With a filter that changes the
+    /// frame rate (like deinterlacing per field, or `fps`), this is the frame rate of the filter
+    /// output.
+    pub fn frame_rate(&self) -> f32 {
+        let frame_rate = self.decoder.filter_frame_rate().or_else(|| {
+            self.reader
+                .input
+                .stream(self.reader_stream_index)
+                .map(|stream| stream.rate())
+        });
+
+        if let Some(frame_rate) = frame_rate {
+            if frame_rate.denominator() > 0 {
+                (frame_rate.numerator() as f32) / (frame_rate.denominator() as f32)
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    /// Skip to the next keyframe and decode only that frame. Packets that are not keyframes are
+    /// discarded before they reach the decoder, so walking over the keyframes of a video (for
+    /// example to build an index of its GOPs) is much cheaper than decoding every frame. To
+    /// decode only keyframes throughout, use [`DecodeSpeed::KeyframesOnly`] instead.
+    ///
+    /// This can be used in between normal decoding and seeking. The frames that the decoder still
+    /// holds are discarded first, and decoding continues with the packets after the keyframe.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the keyframe timestamp (relative to the stream) and the frame itself (see
+    /// [`Decoder::decode`]), or [`None`] at the end of the video.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("video.mp4").into()).unwrap();
+    /// let mut keyframe_timestamps = Vec::new();
+    /// while let Some((timestamp, _)) = decoder.next_keyframe().unwrap() {
+    ///     keyframe_timestamps.push(timestamp);
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn next_keyframe(&mut self) -> Result<Option<(Time, Frame)>> {
+        self.next_keyframe_unscaled()?
+            .map(|frame| self.decoder.convert(frame))
+            .transpose()
+    }
+
+    /// Skip to the next keyframe and decode only that frame. This is the raw variant of
+    /// [`Decoder::next_keyframe`].
+    ///
+    /// # Return value
+    ///
+    /// The decoded keyframe as [`RawFrame`], or [`None`] at the end of the video.
+    pub fn next_keyframe_raw(&mut self) -> Result<Option<RawFrame>> {
+        self.next_keyframe_unscaled()?
+            .map(|frame| self.decoder.scale_raw(&frame))
+            .transpose()
+    }
+
+    /// Decode the frames of a time range: seek to the keyframe before `start`, discard the frames
+    /// before `start`, and stop at the first frame at or after `end`. Frames are compared by their
+    /// presentation timestamps, which are also the timestamps that the iterator returns. `start`
+    /// and `end` are on the same timeline as the frame timestamps of [`Decoder::decode`], so the
+    /// start time of the stream (if any) is taken into account.
+    ///
+    /// When the iterator stops, the frames that the decoder still holds are discarded, so that
+    /// the decoder can be used again, for example for the next range. A range that starts after
+    /// the end of the video has no frames.
+    ///
+    /// Sources that cannot seek (like pipes and live streams) fail with [`Error::NotSeekable`].
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start of the range (inclusive).
+    /// * `end` - End of the range (exclusive).
+    /// * `rebase` - Whether or not to rebase the timestamps to start at zero at `start`, like the
+    ///   encoder expects them for a clip.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let frames = decoder.decode_between(Time::from_secs(10.0), Time::from_secs(12.0), true)?;
+    /// for frame in frames {
+    ///     let (timestamp, frame) = frame?;
+    ///     encoder.encode(&frame, timestamp)?;
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_between(
+        &mut self,
+        start: Time,
+        end: Time,
+        rebase: bool,
+    ) -> Result<impl Iterator<Item = Result<(Time, Frame)>> + use<'_, 'a>> {
+        let mut range = self.seek_to_range(start, end, rebase)?;
+        Ok(std::iter::from_fn(move || {
+            let (pts, frame) = match self.decode_in_range(&mut range) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            let time_base = self.decoder.time_base();
+            Some(
+                self.decoder
+                    .convert(frame)
+                    .map(|(_, frame)| (Time::new(pts, time_base), frame)),
+            )
+        }))
+    }
+
+    /// Decode the frames of a time range. This is the raw variant of [`Decoder::decode_between`].
+    /// The timestamps are the presentation timestamps of the frames (see [`RawFrame::pts`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start of the range (inclusive).
+    /// * `end` - End of the range (exclusive).
+    /// * `rebase` - Whether or not to rebase the timestamps to start at zero at `start`.
+    pub fn decode_raw_between(
+        &mut self,
+        start: Time,
+        end: Time,
+        rebase: bool,
+    ) -> Result<impl Iterator<Item = Result<RawFrame>> + use<'_, 'a>> {
+        let mut range = self.seek_to_range(start, end, rebase)?;
+        Ok(std::iter::from_fn(move || {
+            let (pts, frame) = match self.decode_in_range(&mut range) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return None,
+                Err(err) => return Some(Err(err)),
+            };
+            Some(self.decoder.scale_raw(&frame).map(|mut frame| {
+                frame.set_pts(pts);
+                frame
+            }))
+        }))
+    }
+
+    /// Decode a clip of consecutive frames into a single tensor of shape `(N, H, W, C)`, like ML
+    /// data loaders take them. This seeks to the keyframe before `start` (see
+    /// [`Decoder::decode_between`]) and copies each frame at or after `start` straight into its
+    /// slot of the tensor, without an intermediate [`Frame`] per frame. Frames have 3 channels in
+    /// RGB order, or 4 channels in BGRA order if the input has an alpha channel (see
+    /// [`Decoder::has_alpha`]). Decode at a reduced size with [`DecoderBuilder::output_size`] to
+    /// make the tensor smaller.
+    ///
+    /// Sources that cannot seek (like pipes and live streams) fail with [`Error::NotSeekable`].
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Timestamp of the first frame of the clip (or a time before it).
+    /// * `frames` - Number of frames in the clip.
+    /// * `require_all` - Whether to fail with [`Error::ReadExhausted`] if the video ends before
+    ///   the clip is complete. If not, the clip is cut short instead.
+    ///
+    /// # Return value
+    ///
+    /// The timestamps of the frames (relative to the stream) and the tensor with the frames. The
+    /// first dimension of the tensor is the number of frames that were decoded, which is less
+    /// than `frames` for clips that are cut short by the end of the video.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::builder(&PathBuf::from("video.mp4").into())
+    ///     .output_size(224, 224)
+    ///     .build()
+    ///     .unwrap();
+    /// let (timestamps, clip) = decoder.decode_clip(Time::from_secs(4.0), 16, true).unwrap();
+    /// assert_eq!(clip.dim(), (16, 224, 224, 3));
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_clip(
+        &mut self,
+        start: Time,
+        frames: usize,
+        require_all: bool,
+    ) -> Result<(Vec<Time>, Array4<u8>)> {
+        let (width, height) = self.size_out();
+        let channels = if self.has_alpha() { 4 } else { 3 };
+        let mut clip = Array4::zeros((frames, height as usize, width as usize, channels));
+        let timestamps = self.decode_clip_into(start, clip.view_mut(), require_all)?;
+        if timestamps.len() < frames {
+            clip = clip.slice_move(s![..timestamps.len(), .., .., ..]);
+        }
+
+        Ok((timestamps, clip))
+    }
+
+    /// Decode a clip of consecutive frames into a tensor that the caller provides, for example a
+    /// slice of a larger batch. See [`Decoder::decode_clip`].
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Timestamp of the first frame of the clip (or a time before it).
+    /// * `clip` - Tensor to decode the frames into, of shape `(N, H, W, C)`. Its height, width and
+    ///   number of channels must match the frames of the decoder, and `N` is the number of frames
+    ///   in the clip.
+    /// * `require_all` - Whether to fail with [`Error::ReadExhausted`] if the video ends before
+    ///   the clip is complete. If not, the clip is cut short instead.
+    ///
+    /// # Return value
+    ///
+    /// The timestamps of the frames (relative to the stream). Only the first as many slots of the
+    /// tensor as there are timestamps are filled in, the others are left as they were. Fails with
+    /// [`Error::InvalidFrameFormat`] if the tensor does not have the shape of the frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut batch = Array4::<u8>::zeros((4 * 16, 224, 224, 3));
+    /// for (index, start) in [0.0, 10.0, 20.0, 30.0].into_iter().enumerate() {
+    ///     decoder.decode_clip_into(
+    ///         Time::from_secs(start),
+    ///         batch.slice_mut(s![index * 16..(index + 1) * 16, .., .., ..]),
+    ///         true,
+    ///     )?;
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_clip_into(
+        &mut self,
+        start: Time,
+        mut clip: ArrayViewMut4<u8>,
+        require_all: bool,
+    ) -> Result<Vec<Time>> {
+        let (frames, height, width, channels) = clip.dim();
+        let (width_out, height_out) = self.size_out();
+        let channels_out = if self.has_alpha() { 4 } else { 3 };
+        if (height, width, channels) != (height_out as usize, width_out as usize, channels_out) {
+            return Err(Error::invalid_frame_format(
+                format!("clip of {height_out}x{width_out}x{channels_out} frames"),
+                format!("clip of {height}x{width}x{channels} frames"),
+            ));
+        }
+
+        let mut range = self.seek_to_range(start, Time::from_secs_f64(END_OF_VIDEO_SECS), false)?;
+        let time_base = self.decoder.time_base();
+        let mut timestamps = Vec::with_capacity(frames);
+        for mut slot in clip.outer_iter_mut() {
+            let Some((pts, frame)) = self.decode_in_range(&mut range)? else {
+                break;
+            };
+            let frame = if self.decoder.has_alpha {
+                self.decoder.scale_bgra(&frame)?
+            } else {
+                self.decoder.scale(&frame)?
+            };
+            // Filters may change the size of frames midway.
+            if (frame.width() as usize, frame.height() as usize) != (width, height) {
+                return Err(Error::invalid_frame_format(
+                    format!("frame of {width}x{height}"),
+                    format!("frame of {}x{}", frame.width(), frame.height()),
+                ));
+            }
+            let (data, stride, row_len) = (frame.data(0), frame.stride(0), width * channels);
+            for (y, mut row) in slot.outer_iter_mut().enumerate() {
+                let pixels =
+                    ArrayView2::from_shape((width, channels), &data[y * stride..][..row_len])
+                        .map_err(|err| {
+                            Error::invalid_frame_format("packed RGB or BGRA frame", err)
+                        })?;
+                row.assign(&pixels);
+            }
+            timestamps.push(Time::new(pts, time_base));
+        }
+        // Leave the decoder in a clean state, without the frames after the clip.
+        self.decoder.flush()?;
+
+        if require_all && timestamps.len() < frames {
+            return Err(Error::ReadExhausted);
+        }
+
+        Ok(timestamps)
+    }
+
+    /// Count the frames of the video stream exactly, by reading all of its packets without
+    /// decoding them. Unlike estimates from the container (which are often missing, as in
+    /// Matroska) or from the duration and frame rate (which are off for variable frame rate
+    /// video), this counts every frame.
+    ///
+    /// This reads the whole file, so the time it takes grows with the size of the file. It is
+    /// much faster than decoding though, since packets are only demuxed.
+    ///
+    /// Decoding continues where it was afterwards: the packets are read from the source opened a
+    /// second time. Sources that cannot be opened again (custom I/O) are read by the decoder
+    /// itself, which is rewound to the start afterwards (see [`Decoder::seek_to_start`]). Sources
+    /// that cannot seek (like pipes and live streams) fail with [`Error::NotSeekable`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("video.mkv").into()).unwrap();
+    /// let num_frames = decoder.count_frames_exact().unwrap();
+    /// ```
+    pub fn count_frames_exact(&mut self) -> Result<u64> {
+        self.count_frames_exact_with_cancel(&CancellationToken::new())
+    }
+
+    /// Count the frames of the video stream exactly (see [`Decoder::count_frames_exact`]), and
+    /// stop with [`Error::Cancelled`] when the token is cancelled. Decoding continues where it was
+    /// afterwards, cancelled or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Token to cancel counting with.
+    pub fn count_frames_exact_with_cancel(&mut self, token: &CancellationToken) -> Result<u64> {
+        if !input_is_seekable(&self.reader.input) {
+            return Err(Error::NotSeekable);
+        }
+
+        let cancellation = self.reader.link_cancellation(token);
+        if self.reader.can_reopen() {
+            let mut reader = self.reader.reopen()?;
+            return count_packets(&mut reader, self.reader_stream_index, token);
+        }
+
+        self.reader.seek_to_start()?;
+        let count = count_packets(&mut self.reader, self.reader_stream_index, token);
+        drop(cancellation);
+        self.seek_to_start()?;
+        count
+    }
+
+    /// Find the scene changes (shot boundaries) in the rest of the video, from the current position
+    /// to the end. Each frame is scored by how much it differs from the frame before it, and the
+    /// frames that score above the threshold start a new scene. The first frame is never a scene
+    /// change, since it has nothing to compare with.
+    ///
+    /// Frames are compared by the mean absolute difference of their luma, scaled down to a small
+    /// fixed size, so that the score does not depend on the resolution. The score goes from `0.0`
+    /// (identical frames) to `1.0` (a cut from black to white). Hard cuts between unrelated shots
+    /// usually score above `0.1`, while motion within a shot stays below that. Use
+    /// [`Decoder::detect_scene_changes_with_scores`] to see the scores, to tune the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Score above which a frame starts a new scene.
+    ///
+    /// # Return value
+    ///
+    /// The timestamps of the first frames of the new scenes.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("movie.mp4").into()).unwrap();
+    /// for cut in decoder.detect_scene_changes(0.15).unwrap() {
+    ///     println!("cut at {:.3}s", cut.as_secs_f64());
+    /// }
+    /// ```
+    pub fn detect_scene_changes(&mut self, threshold: f32) -> Result<Vec<Time>> {
+        self.detect_scene_changes_with_scores(threshold, |_, _| {})
+    }
+
+    /// Find the scene changes in the rest of the video (see [`Decoder::detect_scene_changes`]),
+    /// and stop with [`Error::Cancelled`] when the token is cancelled. The decoder is where
+    /// detection stopped afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Score above which a frame starts a new scene.
+    /// * `token` - Token to cancel detection with.
+    pub fn detect_scene_changes_with_cancel(
+        &mut self,
+        threshold: f32,
+        token: &CancellationToken,
+    ) -> Result<Vec<Time>> {
+        self.find_scene_changes(threshold, |_, _| {}, token)
+    }
+
+    /// Find the scene changes in the rest of the video, and pass the score of every frame (but
+    /// the first) to a callback. See [`Decoder::detect_scene_changes`].
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Score above which a frame starts a new scene.
+    /// * `on_score` - Callback that receives the timestamp and score of each frame.
+    ///
+    /// # Return value
+    ///
+    /// The timestamps of the first frames of the new scenes.
+    pub fn detect_scene_changes_with_scores(
+        &mut self,
+        threshold: f32,
+        on_score: impl FnMut(&Time, f32),
+    ) -> Result<Vec<Time>> {
+        self.find_scene_changes(threshold, on_score, &CancellationToken::new())
+    }
+
+    /// Find the scene changes in the rest of the video, and pass the score of every frame (but
+    /// the first) to a callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Score above which a frame starts a new scene.
+    /// * `on_score` - Callback that receives the timestamp and score of each frame.
+    /// * `token` - Token to cancel detection with.
+    fn find_scene_changes(
+        &mut self,
+        threshold: f32,
+        mut on_score: impl FnMut(&Time, f32),
+        token: &CancellationToken,
+    ) -> Result<Vec<Time>> {
+        let _cancellation = self.reader.link_cancellation(token);
+        let mut detector = SceneDetector::new();
+        let mut scene_changes = Vec::new();
+        let mut draining = false;
+        loop {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let frame = if draining {
+                match self.decoder.drain_unscaled()? {
+                    Some(frame) => frame,
+                    None => break,
+                }
+            } else {
+                match self.decode_unscaled() {
+                    Ok(frame) => frame,
+                    Err(Error::ReadExhausted) => {
+                        draining = true;
+                        continue;
+                    }
+                    Err(_) if token.is_cancelled() => return Err(Error::Cancelled),
+                    Err(err) => return Err(err),
+                }
+            };
+            let Some(score) = detector.score(&frame)? else {
+                continue;
+            };
+            let timestamp = Time::new(frame.pts().or_else(|| frame.timestamp()), self.time_base());
+            on_score(&timestamp, score);
+            if score > threshold {
+                scene_changes.push(timestamp);
+            }
+        }
+
+        Ok(scene_changes)
+    }
+
+    /// Find the black and frozen segments in the rest of the video, in a single pass.
+    ///
+    /// Like scene changes (see [`Decoder::detect_scene_changes`]), frames are analyzed on their
+    /// luma, scaled down to a small size, so that the analysis is fast and does not depend on the
+    /// resolution. A frame is black when its mean luma is below a threshold, and frozen when it
+    /// differs less than a threshold from the frame before. Runs of black or frozen frames that
+    /// last at least the minimum duration are reported. The decoder is at the end of the video
+    /// afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - What to look for.
+    ///
+    /// # Return value
+    ///
+    /// The black and frozen segments, in order.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut decoder = Decoder::new(&PathBuf::from("broadcast.ts").into()).unwrap();
+    /// let report = decoder
+    ///     .analyze(AnalysisConfig {
+    ///         detect_black: Some(BlackParams::default()),
+    ///         detect_freeze: Some(FreezeParams::default()),
+    ///     })
+    ///     .unwrap();
+    /// for span in report.frozen {
+    ///     println!("frozen from {} to {}", span.start, span.end);
+    /// }
+    /// ```
+    pub fn analyze(&mut self, config: AnalysisConfig) -> Result<AnalysisReport> {
+        let mut analyzer = Analyzer::new(config)?;
+        let mut draining = false;
+        loop {
+            let frame = if draining {
+                match self.decoder.drain_unscaled()? {
+                    Some(frame) => frame,
+                    None => break,
+                }
+            } else {
+                match self.decode_unscaled() {
+                    Ok(frame) => frame,
+                    Err(Error::ReadExhausted) => {
+                        draining = true;
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+            let timestamp = Time::new(frame.pts().or_else(|| frame.timestamp()), self.time_base());
+            analyzer.push(&frame, timestamp)?;
+        }
+
+        Ok(analyzer.finish())
+    }
+
+    /// Rewind to the start of the video without reopening the source: seek to the start of the
+    /// stream, and discard the frames that the decoder (and the filter, if any) still hold. The
+    /// next frame is the first frame of the video.
+    ///
+    /// Sources that cannot seek (like pipes and live streams) fail with [`Error::NotSeekable`],
+    /// and are left as they were.
+    pub fn seek_to_start(&mut self) -> Result<()> {
+        if !input_is_seekable(&self.reader.input) {
+            return Err(Error::NotSeekable);
+        }
+        self.reader.seek_to_start()?;
+        self.decoder.flush()
+    }
+
+    /// Read up to the next keyframe and decode it, without converting or resizing it.
+    fn next_keyframe_unscaled(&mut self) -> Result<Option<RawFrame>> {
+        loop {
+            let packet = match self.read() {
+                Ok(packet) if packet.is_key() => packet,
+                Ok(_) => continue,
+                Err(Error::ReadExhausted) => return Ok(None),
+                Err(err) => return Err(err),
+            };
+
+            // Frames of earlier packets that the decoder still holds would come out first.
+            self.decoder.flush()?;
+            if let Some(frame) = self.decode_packet(packet)? {
+                return Ok(Some(frame));
+            }
+            // Decoders with a delay only give up the frame when they are drained.
+            let frame = self.decoder.drain_unscaled()?;
+            self.decoder.flush()?;
+            if let Some(frame) = frame {
+                return Ok(Some(frame));
+            }
+        }
+    }
+
+    /// Seek to the keyframe before the start of a time range, for [`Decoder::decode_between`].
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start of the range (inclusive).
+    /// * `end` - End of the range (exclusive).
+    /// * `rebase` - Whether or not to rebase the timestamps to start at zero at `start`.
+    fn seek_to_range(&mut self, start: Time, end: Time, rebase: bool) -> Result<TimeRange> {
+        let time_base = self.decoder.time_base();
+        let (Some(start_pts), Some(end_pts)) = (
+            start.aligned_with_rational(time_base).into_value(),
+            end.aligned_with_rational(time_base).into_value(),
+        ) else {
+            return Err(Error::InvalidOptions(
+                "time range without a start or end".to_string(),
+            ));
+        };
+        if end_pts < start_pts {
+            return Err(Error::InvalidOptions(format!(
+                "time range ends ({:.3}s) before it starts ({:.3}s)",
+                end.as_secs_f64(),
+                start.as_secs_f64()
+            )));
+        }
+        if !input_is_seekable(&self.reader.input) {
+            return Err(Error::NotSeekable);
+        }
+
+        let timestamp = start
+            .aligned_with_rational(TIME_BASE)
+            .into_value()
+            .unwrap_or_default();
+        self.reader.seek_to_keyframe_before(timestamp)?;
+        self.decoder.flush()?;
+
+        Ok(TimeRange::new(start_pts, end_pts, rebase))
+    }
+
+    /// Decode the next frame of a time range, for [`Decoder::decode_between`].
+    ///
+    /// # Arguments
+    ///
+    /// * `range` - Time range to decode.
+    ///
+    /// # Return value
+    ///
+    /// The timestamp (rebased if requested) and the frame, or [`None`] at the end of the range.
+    fn decode_in_range(
+        &mut self,
+        range: &mut TimeRange,
+    ) -> Result<Option<(Option<i64>, RawFrame)>> {
+        while !range.done {
+            let frame = if range.draining {
+                self.decoder.drain_unscaled()
+            } else {
+                match self.decode_unscaled() {
+                    Err(Error::ReadExhausted) => {
+                        range.draining = true;
+                        continue;
+                    }
+                    frame => frame.map(Some),
+                }
+            };
+            let frame = match frame {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    range.done = true;
+                    break;
+                }
+                Err(err) => {
+                    range.done = true;
+                    return Err(err);
+                }
+            };
+
+            let Some(pts) = frame.pts().or_else(|| frame.timestamp()) else {
+                return Ok(Some((None, frame)));
+            };
+            match range.compare(pts) {
+                std::cmp::Ordering::Less => continue,
+                std::cmp::Ordering::Equal => return Ok(Some((Some(range.timestamp(pts)), frame))),
+                std::cmp::Ordering::Greater => {
+                    range.done = true;
+                    // Leave the decoder in a clean state, without the frames after the range.
+                    self.decoder.flush()?;
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decode the next frame for [`Decoder::decode_iter_looped`], rewinding at the end of the
+    /// video.
+    ///
+    /// # Arguments
+    ///
+    /// * `looped` - State of the looped playback.
+    #[cfg(feature = "ndarray")]
+    fn decode_looped(&mut self, looped: &mut LoopedPlayback) -> Result<Option<(Time, Frame)>> {
+        loop {
+            if looped.remaining == Some(0) {
+                return Ok(None);
+            }
+
+            let frame = if looped.draining {
+                self.decoder.drain_unscaled()?
+            } else {
+                match self.decode_unscaled() {
+                    Ok(frame) => Some(frame),
+                    Err(Error::ReadExhausted) => {
+                        looped.draining = true;
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+
+            match frame {
+                Some(frame) => {
+                    let (timestamp, frame) = self.decoder.convert(frame)?;
+                    let frame_duration = self.frame_duration();
+                    return Ok(Some((looped.shift(timestamp, frame_duration), frame)));
+                }
+                None => {
+                    looped.remaining = looped.remaining.map(|remaining| remaining - 1);
+                    // Stop instead of rewinding forever if the video has no frames at all.
+                    if looped.remaining == Some(0) || !looped.next_loop() {
+                        return Ok(None);
+                    }
+                    self.seek_to_start()?;
+                }
+            }
+        }
+    }
+
+    /// Get the duration of a frame in units of the time base, based on the frame rate.
+    #[cfg(feature = "ndarray")]
+    fn frame_duration(&self) -> Option<i64> {
+        let frame_rate = self.frame_rate() as f64;
+        let time_base = self.decoder.time_base();
+        (frame_rate > 0.0).then(|| {
+            (time_base.denominator() as f64 / time_base.numerator() as f64 / frame_rate).round()
+                as i64
+        })
+    }
+
+    /// Create a decoder for the best video stream of a reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to decode from.
+    /// * `resize` - Optional resize strategy to apply to frames.
+    fn from_reader(reader: Reader, resize: Option<Resize>) -> Result<Self> {
+        let reader_stream_index = reader.best_video_stream_index()?;
+        Self::from_reader_with_config(
+            reader,
+            reader_stream_index,
+            DecoderConfig {
+                resize,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Create a decoder for a stream of a reader, set up with a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to decode from.
+    /// * `reader_stream_index` - Index of the video stream to decode.
+    /// * `config` - How to set up the decoder.
+    fn from_reader_with_config(
+        reader: Reader,
+        reader_stream_index: usize,
+        config: DecoderConfig,
+    ) -> Result<Self> {
+        Ok(Self {
+            decoder: DecoderSplit::new_with_config(&reader, reader_stream_index, config)?,
+            reader,
+            reader_stream_index,
+            reconnect_policy: None,
+            reconnect_callback: None,
+            timestamp_continuity: TimestampContinuity::new(),
+            raw_frame_size: None,
+            health: HealthMonitor::new(HealthMonitor::DEFAULT_DISCONTINUITY_THRESHOLD),
+            data: PhantomData,
+        })
+    }
+
+    /// Decode the next frame, without converting or resizing it. Frames that the filter (if any)
+    /// produced for earlier packets come out first.
+    fn decode_unscaled(&mut self) -> Result<RawFrame> {
+        loop {
+            if let Some(frame) = self.decoder.pull_filtered()? {
+                return Ok(frame);
+            }
+            let packet = self.read()?;
+            if let Some(frame) = self.decode_packet(packet)? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Decode a packet of the video stream, and note the packets that fail to decode (or are
+    /// skipped as corrupt) in the health figures.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to decode.
+    fn decode_packet(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        let num_corrupt = self.decoder.num_corrupt;
+        let result = self.decoder.decode_unscaled(packet);
+        if result.is_err() || self.decoder.num_corrupt > num_corrupt {
+            self.health.decode_error();
+        }
+        result
+    }
+
+    /// Read the next packet of the video stream, reconnecting according to the reconnect policy
+    /// if reading fails.
+    fn read(&mut self) -> Result<Packet> {
+        loop {
+            match self.reader.read(self.reader_stream_index) {
+                Ok(mut packet) => {
+                    self.health.packet_read(&packet);
+                    if self.decoder.speed == DecodeSpeed::KeyframesOnly && !packet.is_key() {
+                        continue;
+                    }
+                    // The raw video demuxer returns whatever is left at the end of the input as
+                    // a shorter packet.
+                    if let Some(expected) = self.raw_frame_size.filter(|s| *s != packet.size()) {
+                        return Err(Error::IncompleteRawVideoFrame {
+                            expected,
+                            received: packet.size(),
+                        });
+                    }
+                    if matches!(
+                        self.reconnect_policy,
+                        Some(ReconnectPolicy {
+                            resume_behavior: ResumeBehavior::Continue,
+                            ..
+                        })
+                    ) {
+                        if let Some(offset) = self
+                            .timestamp_continuity
+                            .offset_for(&packet.dts(), &packet.duration())
+                        {
+                            packet.set_pts(&packet.pts().aligned_with(&offset).add());
+                            packet.set_dts(&packet.dts().aligned_with(&offset).add());
+                        }
+                    }
+                    return Ok(packet);
+                }
+                Err(err) => self.reconnect(err)?,
+            }
+        }
+    }
+
+    /// Try to reconnect after reading failed with `err`. Returns `err` if reconnecting is not
+    /// possible, or the last error if all attempts failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - Error that reading failed with.
+    fn reconnect(&mut self, err: Error) -> Result<()> {
+        let Some(reconnect_policy) = self.reconnect_policy else {
+            return Err(err);
+        };
+
+        // End of stream is expected for files, and custom I/O cannot be reopened.
+        let reconnectable = match err {
+            Error::ConnectionLost | Error::ReadTimeout => true,
+            Error::ReadExhausted => matches!(self.reader.source, Some(Locator::Url(_))),
+            ref err => err.backend_error().is_some(),
+        };
+        if !reconnectable || !self.reader.can_reopen() {
+            return Err(err);
+        }
+
+        let mut err = err;
+        for attempt in 1..=reconnect_policy.max_retries {
+            self.notify(ReconnectEvent::Attempting {
+                attempt,
+                error: err.clone(),
+            });
+            std::thread::sleep(reconnect_policy.backoff);
+
+            match self.reopen() {
+                Ok(()) => {
+                    self.notify(ReconnectEvent::Reconnected { attempt });
+                    return Ok(());
+                }
+                // Cancelling also stops reconnecting.
+                Err(Error::ReadInterrupted) => return Err(Error::ReadInterrupted),
+                Err(reopen_err) => {
+                    self.notify(ReconnectEvent::Failed {
+                        attempt,
+                        error: reopen_err.clone(),
+                    });
+                    err = reopen_err;
+                }
+            }
+        }
+
+        Err(err)
+    }
+
+    /// Reopen the input and set up a new decoder for it.
+    fn reopen(&mut self) -> Result<()> {
+        let reader = self.reader.reopen()?;
+        let reader_stream_index = reader.best_video_stream_index()?;
+        let mut decoder = DecoderSplit::new(&reader, reader_stream_index, self.decoder.resize)?
+            .with_decode_speed(self.decoder.speed);
+        if let Some(filter) = &self.decoder.filter {
+            decoder.set_filter(filter.spec())?;
+        }
+        decoder.set_crop(self.decoder.crop)?;
+
+        self.decoder = decoder;
+        self.reader = reader;
+        self.reader_stream_index = reader_stream_index;
+        self.timestamp_continuity.reset();
+
+        Ok(())
+    }
+
+    /// Notify the reconnect callback, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event to notify of.
+    fn notify(&mut self, event: ReconnectEvent) {
+        if let Some(reconnect_callback) = self.reconnect_callback.as_mut() {
+            reconnect_callback(&event);
+        }
+    }
+}
+
+impl Decoder<'static> {
+    /// Split the decoder into a decoder (of type [`DecoderSplit`]) and a [`Reader`].
+    ///
+    /// This allows the caller to detach stream reading from decoding, which is useful for advanced
+    /// use cases. The reader does not carry a lifetime, so decoders that borrow their input (see
+    /// [`Decoder::from_slice`]) cannot be split.
+    ///
+    /// # Return value
+    ///
+    /// Tuple of the [`DecoderSplit`], [`Reader`] and the reader stream index.
+    #[inline]
+    pub fn into_parts(self) -> (DecoderSplit, Reader, usize) {
+        (self.decoder, self.reader, self.reader_stream_index)
+    }
+}
+
+impl std::fmt::Debug for Decoder<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (container_format, _) = self.container_format();
+        f.debug_struct("Decoder")
+            .field("container_format", &container_format)
+            .field("codec_name", &self.codec_name())
+            .field("pixel_format", &self.pixel_format())
+            .field("size", &self.size())
+            .field("size_out", &self.size_out())
+            .field("frame_rate", &self.frame_rate())
+            .field("time_base", &self.time_base())
+            .field("bit_rate", &self.bit_rate())
+            .field("stream_index", &self.reader_stream_index)
+            .finish()
+    }
+}
+
+/// Callback that is notified of reconnect attempts.
+type ReconnectCallback = Box<dyn FnMut(&ReconnectEvent) + Send + Sync>;
+
+/// Determines how a [`Decoder`] reconnects when reading from its source fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Maximum number of consecutive reconnect attempts before giving up.
+    pub max_retries: u32,
+    /// Delay before each reconnect attempt.
+    pub backoff: Duration,
+    /// What happens to timestamps after reconnecting.
+    pub resume_behavior: ResumeBehavior,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            backoff: Duration::from_secs(1),
+            resume_behavior: ResumeBehavior::Continue,
+        }
+    }
+}
+
+/// What happens to timestamps after a [`Decoder`] reconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeBehavior {
+    /// Offset the timestamps of the new input so that they continue monotonically where the
+    /// previous input left off.
+    Continue,
+    /// Use the timestamps of the new input as they are, which usually means that they restart.
+    Restart,
+}
+
+/// Reconnect attempt or outcome, which is passed to the callback set with
+/// [`Decoder::on_reconnect`].
+#[derive(Debug, Clone)]
+pub enum ReconnectEvent {
+    /// About to attempt to reconnect after `error`.
+    Attempting { attempt: u32, error: Error },
+    /// Reconnected successfully.
+    Reconnected { attempt: u32 },
+    /// Reconnect attempt failed with `error`.
+    Failed { attempt: u32, error: Error },
+}
+
+/// Keeps timestamps increasing across the loops of [`Decoder::decode_iter_looped`].
+#[cfg(feature = "ndarray")]
+struct LoopedPlayback {
+    /// Number of loops left, including the current one.
+    remaining: Option<usize>,
+    /// Whether the end of the video was reached, and the decoder is being drained.
+    draining: bool,
+    /// Offset to apply to the timestamps of the current loop.
+    offset: i64,
+    /// Timestamp of the first frame, and the end of the last frame of the current loop (as
+    /// reported by the decoder).
+    range: Option<(i64, i64)>,
+    /// Timestamp of the previous frame of the current loop.
+    last: Option<i64>,
+}
+
+#[cfg(feature = "ndarray")]
+impl LoopedPlayback {
+    fn new(n: Option<usize>) -> Self {
+        Self {
+            remaining: n,
+            draining: false,
+            offset: 0,
+            range: None,
+            last: None,
+        }
+    }
+
+    /// Offset the timestamp of a frame, and keep track of the duration of the loop.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Timestamp of the frame as reported by the decoder.
+    /// * `frame_duration` - Duration of a frame, if known. Otherwise the distance to the previous
+    ///   frame is used.
+    fn shift(&mut self, timestamp: Time, frame_duration: Option<i64>) -> Time {
+        let (value, time_base) = timestamp.into_parts();
+        let Some(value) = value else {
+            return Time::new(None, time_base);
+        };
+
+        let duration = frame_duration
+            .or_else(|| self.last.map(|last| value - last))
+            .filter(|duration| *duration > 0)
+            .unwrap_or(1);
+        let end = value + duration;
+        self.range = Some(match self.range {
+            Some((start, last_end)) => (start.min(value), last_end.max(end)),
+            None => (value, end),
+        });
+        self.last = Some(value);
+
+        Time::new(Some(value + self.offset), time_base)
+    }
+
+    /// Move on to the next loop, offsetting it by the duration of the current loop.
+    ///
+    /// # Return value
+    ///
+    /// Whether the current loop had any frames.
+    fn next_loop(&mut self) -> bool {
+        self.draining = false;
+        self.last = None;
+        match self.range.take() {
+            Some((start, end)) => {
+                self.offset += end - start;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Count the packets of a stream up to the end of the source. Packets without data (which some
+/// demuxers return for side data only) are not counted.
+///
+/// # Arguments
+///
+/// * `reader` - Reader to read from.
+/// * `stream_index` - Index of the stream to count the packets of.
+fn count_packets(
+    reader: &mut Reader,
+    stream_index: usize,
+    token: &CancellationToken,
+) -> Result<u64> {
+    let mut count = 0;
+    loop {
+        if token.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        match reader.read(stream_index) {
+            Ok(packet) if packet.size() > 0 => count += 1,
+            Ok(_) => {}
+            Err(Error::ReadExhausted) => return Ok(count),
+            Err(_) if token.is_cancelled() => return Err(Error::Cancelled),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Time range of [`Decoder::decode_between`], in units of the time base of the decoder.
+struct TimeRange {
+    /// Start of the range (inclusive).
+    start: i64,
+    /// End of the range (exclusive).
+    end: i64,
+    /// Whether or not to rebase the timestamps to start at zero at the start of the range.
+    rebase: bool,
+    /// Whether the end of the video was reached, and the decoder is being drained.
+    draining: bool,
+    /// Whether the end of the range was reached.
+    done: bool,
+}
+
+impl TimeRange {
+    fn new(start: i64, end: i64, rebase: bool) -> Self {
+        Self {
+            start,
+            end,
+            rebase,
+            draining: false,
+            done: false,
+        }
+    }
+
+    /// Compare a timestamp to the range: [`std::cmp::Ordering::Less`] before the start,
+    /// [`std::cmp::Ordering::Greater`] at or after the end, and [`std::cmp::Ordering::Equal`]
+    /// inside the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp to compare.
+    fn compare(&self, pts: i64) -> std::cmp::Ordering {
+        if pts < self.start {
+            std::cmp::Ordering::Less
+        } else if pts >= self.end {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Equal
+        }
+    }
+
+    /// Get the timestamp to return for a frame in the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp of the frame.
+    fn timestamp(&self, pts: i64) -> i64 {
+        match self.rebase {
+            true => pts - self.start,
+            false => pts,
+        }
+    }
+}
+
+/// Determines what a [`Decoder`] does with frames whose timestamps are missing or are not after
+/// the timestamp of the frame before them (see [`Decoder::with_timestamp_policy`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampPolicy {
+    /// Pass timestamps on as they are.
+    #[default]
+    Passthrough,
+    /// Fail with [`Error::InvalidTimestamp`] at the first invalid timestamp.
+    Reject,
+    /// Replace invalid timestamps by the timestamp of the frame before plus the duration of a
+    /// frame at the nominal frame rate, so that timestamps always increase. See
+    /// [`Decoder::timestamps_repaired`].
+    Repair,
+}
+
+/// Determines which frames a [`Decoder`] decodes, to trade quality for speed (see
+/// [`Decoder::with_decode_speed`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecodeSpeed {
+    /// Decode every frame in full.
+    #[default]
+    Full,
+    /// Skip frames that no other frame refers to (like most B-frames), and skip the loop
+    /// (deblocking) filter for the frames that are decoded. Frames look blockier, and the frame
+    /// rate depends on the structure of the stream: streams of only I- and P-frames lose no
+    /// frames.
+    FastPreview,
+    /// Decode only keyframes. Packets that are not keyframes are discarded before they reach the
+    /// decoder, like with [`Decoder::next_keyframe`].
+    KeyframesOnly,
+}
+
+/// Checks the timestamps of decoded frames according to a [`TimestampPolicy`].
+struct TimestampCheck {
+    policy: TimestampPolicy,
+    /// Number of frames checked so far.
+    frame_index: u64,
+    /// Timestamp of the last frame, after repairing it.
+    last_pts: Option<i64>,
+    /// Number of timestamps that were synthesized.
+    repaired: u64,
+}
+
+impl TimestampCheck {
+    fn new(policy: TimestampPolicy) -> Self {
+        Self {
+            policy,
+            frame_index: 0,
+            last_pts: None,
+            repaired: 0,
+        }
+    }
+
+    /// Signal that the decoder was flushed after seeking, after which timestamps may go back.
+    fn reset(&mut self) {
+        self.last_pts = None;
+    }
+
+    /// Check the timestamp of the next frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp of the frame.
+    /// * `frame_duration` - Duration of a frame at the nominal frame rate.
+    ///
+    /// # Return value
+    ///
+    /// The timestamp to give the frame.
+    fn check(&mut self, pts: Option<i64>, frame_duration: i64) -> Result<Option<i64>> {
+        let frame_index = self.frame_index;
+        self.frame_index += 1;
+        if self.policy == TimestampPolicy::Passthrough {
+            return Ok(pts);
+        }
+
+        let invalid = match (pts, self.last_pts) {
+            (None, _) => Some("frame has no timestamp".to_string()),
+            (Some(pts), Some(last_pts)) if pts <= last_pts => Some(format!(
+                "timestamp {pts} is not after timestamp {last_pts} of the frame before"
+            )),
+            _ => None,
+        };
+        let pts = match (invalid, self.policy) {
+            (None, _) => pts,
+            (Some(reason), TimestampPolicy::Reject) => {
+                return Err(Error::InvalidTimestamp {
+                    frame_index,
+                    reason,
+                })
+            }
+            (Some(_), _) => {
+                self.repaired += 1;
+                Some(
+                    self.last_pts
+                        .map_or(0, |last_pts| last_pts + frame_duration),
+                )
+            }
+        };
+        self.last_pts = pts;
+
+        Ok(pts)
+    }
+}
+
+/// Keeps timestamps monotonic across reconnects by offsetting the timestamps of the new input.
+struct TimestampContinuity {
+    /// End of the last packet (DTS plus duration).
+    last_end: Option<Time>,
+    /// Offset to apply to the timestamps of the current input.
+    offset: Option<Time>,
+    /// Whether or not the offset must be determined from the next packet.
+    pending: bool,
+}
+
+impl TimestampContinuity {
+    fn new() -> Self {
+        Self {
+            last_end: None,
+            offset: None,
+            pending: false,
+        }
+    }
+
+    /// Signal that the input was reopened. The offset is determined from the next packet.
+    fn reset(&mut self) {
+        self.pending = true;
+    }
+
+    /// Get the offset to apply to a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `dts` - Packet DTS as reported by the input.
+    /// * `duration` - Packet duration.
+    fn offset_for(&mut self, dts: &Time, duration: &Time) -> Option<Time> {
+        if !dts.has_value() {
+            return self.offset.clone();
+        }
+
+        if self.pending {
+            if let Some(last_end) = &self.last_end {
+                self.offset = Some(last_end.aligned_with(dts).subtract());
+            }
+            self.pending = false;
+        }
+
+        let dts = match &self.offset {
+            Some(offset) => dts.aligned_with(offset).add(),
+            None => dts.clone(),
+        };
+        // Packets without duration still advance by one time base unit.
+        let (_, time_base) = dts.clone().into_parts();
+        let duration = match duration.clone().into_value() {
+            Some(duration_value) if duration_value > 0 => duration.clone(),
+            _ => Time::new(Some(1), time_base),
+        };
+        self.last_end = Some(dts.aligned_with(&duration).add());
+
+        self.offset.clone()
+    }
+}
+
+/// Builds a [`Decoder`] with the stream to decode, the format and size of the frames, the hardware
+/// device to decode with and the input options, which would otherwise call for a separate
+/// constructor for every combination. Conflicting settings are caught when building.
+///
+/// # Example
+///
+/// Decode the second video stream of a file on a VAAPI device into NV12 frames.
+///
+/// ```ignore
+/// let mut decoder = Decoder::builder(&PathBuf::from("multicam.mkv").into())
+///     .stream(1)
+///     .hardware(HardwareDevice::new(HardwareDeviceType::Vaapi))
+///     .output_format(PixelFormat::NV12)
+///     .timeout(Duration::from_secs(5))
+///     .build()
+///     .unwrap();
+/// let frame = decoder.decode_raw().unwrap();
+/// ```
+pub struct DecoderBuilder {
+    source: Locator,
+    stream_index: Option<usize>,
+    output_format: Option<PixelFormat>,
+    output_size: Option<(u32, u32)>,
+    crop: Option<Crop>,
+    hardware: Option<HardwareDevice>,
+    options: Option<Options<'static>>,
+    timeout: Option<Duration>,
+    lenient: bool,
+    low_latency: bool,
+    decode_speed: DecodeSpeed,
+    color_spaces: ScaleColorSpaces,
+}
+
+impl DecoderBuilder {
+    /// Create a builder for a decoder for the specified file.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    pub fn new(source: &Locator) -> Self {
+        Self {
+            source: source.clone(),
+            stream_index: None,
+            output_format: None,
+            output_size: None,
+            crop: None,
+            hardware: None,
+            options: None,
+            timeout: None,
+            lenient: false,
+            low_latency: false,
+            decode_speed: DecodeSpeed::Full,
+            color_spaces: ScaleColorSpaces::default(),
+        }
+    }
+
+    /// Decode the stream with the given index, which must be a video stream. By default the best
+    /// video stream is decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the stream in the input.
+    pub fn stream(mut self, index: usize) -> Self {
+        self.stream_index = Some(index);
+        self
+    }
+
+    /// Set the pixel format of raw frames (like [`Decoder::decode_raw`]). By default raw frames
+    /// are RGB24. Frames that are converted to `ndarray` or images are RGB (or BGRA) regardless.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Pixel format of raw frames.
+    pub fn output_format(mut self, format: PixelFormat) -> Self {
+        self.output_format = Some(format);
+        self
+    }
+
+    /// Resize frames to the given size, like [`Resize::Exact`]. With [`DecoderBuilder::crop`],
+    /// the region that frames are cropped to is resized.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    pub fn output_size(mut self, width: u32, height: u32) -> Self {
+        self.output_size = Some((width, height));
+        self
+    }
+
+    /// Set the color spaces (the matrices between RGB and YUV) that frames are converted between
+    /// when they are converted to the output format and size. By default, decoded frames have the
+    /// color space that the stream declares, and YUV output frames have the color space that fits
+    /// their height (see [`ScaleColorSpaces`]), so that downsizing 1080p BT.709 video to an SD
+    /// size in YUV also converts it to BT.601. The color spaces set here take precedence, for
+    /// example for streams that declare the wrong one.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_spaces` - Color spaces to use instead of the declared or inferred ones.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = Decoder::builder(&PathBuf::from("untagged-hd.mp4").into())
+    ///     .output_format(PixelFormat::YUV420P)
+    ///     .output_size(640, 360)
+    ///     .scale_color_spaces(ScaleColorSpaces {
+    ///         input: Some(ColorSpace::BT470BG),
+    ///         output: Some(ColorSpace::BT709),
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn scale_color_spaces(mut self, color_spaces: ScaleColorSpaces) -> Self {
+        self.color_spaces = color_spaces;
+        self
+    }
+
+    /// Crop frames to a region. See [`Decoder::with_crop`].
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the region.
+    /// * `y` - Vertical offset of the region.
+    /// * `width` - Width of the region.
+    /// * `height` - Height of the region.
+    pub fn crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.crop = Some(Crop::new(x, y, width, height));
+        self
+    }
+
+    /// Decode on a hardware device. Frames are copied back to system memory after decoding, so
+    /// they can be used like any other frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Hardware device to decode with.
+    pub fn hardware(mut self, device: HardwareDevice) -> Self {
+        self.hardware = Some(device);
+        self
+    }
+
+    /// Set the input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The input options.
+    pub fn options(mut self, options: &Options) -> Self {
+        self.options = Some(options.to_owned_options());
+        self
+    }
+
+    /// Set a read timeout. If reading a packet blocks for longer than `timeout`, decoding fails
+    /// with [`Error::ReadTimeout`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum amount of time a single read may block.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Conceal errors in corrupt input (like a damaged recording) and skip the packets that
+    /// cannot be decoded at all, instead of failing on them. This is done by the software
+    /// decoder, so it cannot be combined with [`DecoderBuilder::hardware`].
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Output each frame as soon as possible, for live previews and the like. This applies the
+    /// whole set of low latency settings at once:
+    ///
+    /// * The demuxer does not buffer packets (`fflags=nobuffer`) or wait to reorder them
+    ///   (`max_delay=0`).
+    /// * The input is barely probed (`probesize=32` and `analyzeduration=0`), so decoding starts
+    ///   with the first packets instead of after some seconds of the stream.
+    /// * The decoder does not hold frames back (`flags=low_delay`) and uses slice threads instead
+    ///   of frame threads (`thread_type=slice`), which would keep a frame per thread in flight.
+    ///
+    /// Input options that are set with [`DecoderBuilder::options`] take precedence over these.
+    ///
+    /// This comes at a cost: decoding is slower with slice threads for most codecs, streams with
+    /// B-frames may stutter, and with so little probing, the parameters of some streams cannot be
+    /// detected. In that case, the input is opened again and probed as usual (which is logged as
+    /// a warning), and only the decoder settings keep the latency low.
+    pub fn low_latency(mut self) -> Self {
+        self.low_latency = true;
+        self
+    }
+
+    /// Skip frames or decoding steps to decode faster. See [`Decoder::with_decode_speed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `speed` - Which frames and decoding steps to skip.
+    pub fn decode_speed(mut self, speed: DecodeSpeed) -> Self {
+        self.decode_speed = speed;
+        self
+    }
+
+    /// Open the input and set up the decoder.
+    ///
+    /// # Return value
+    ///
+    /// The decoder, or an error if the settings conflict:
+    ///
+    /// * [`Error::IncompatibleDecoderOptions`] for hardware decoding that is lenient.
+    /// * [`Error::InvalidResizeParameters`] for an output size without area.
+    /// * [`Error::NotAVideoStream`] if the stream is not a video stream.
+    /// * [`Error::HardwareDecodingNotSupported`] if the decoder of the stream cannot decode on
+    ///   the hardware device.
+    /// * [`Error::InvalidCropParameters`] if the crop region is not within the frame.
+    pub fn build(mut self) -> Result<Decoder<'static>> {
+        if self.hardware.is_some() && self.lenient {
+            return Err(Error::IncompatibleDecoderOptions(
+                "hardware decoders do not conceal errors, so hardware decoding cannot be lenient"
+                    .to_string(),
+            ));
+        }
+        if matches!(self.output_size, Some((width, height)) if width == 0 || height == 0) {
+            return Err(Error::InvalidResizeParameters);
+        }
+
+        let options = self.options.take().unwrap_or_default();
+        if self.low_latency {
+            match self.open(&low_latency_input_options(&options)) {
+                Err(err)
+                    if matches!(err, Error::MissingCodecParameters)
+                        || err.backend_error() == Some(AvError::StreamNotFound) =>
+                {
+                    tracing::warn!(
+                        "stream detection with low latency probing failed ({err}), probing the \
+                         input again as usual"
+                    );
+                }
+                result => return result,
+            }
+        }
+
+        self.open(&options)
+    }
+
+    /// Open the input with some input options and set up the decoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The input options.
+    fn open(&self, options: &Options) -> Result<Decoder<'static>> {
+        let reader = match self.timeout {
+            Some(timeout) => Reader::new_with_timeout(&self.source, options, timeout)?,
+            None => Reader::new_with_options(&self.source, options)?,
+        };
+        let reader_stream_index = match self.stream_index {
+            Some(index) => {
+                let stream = reader.input.stream(index).ok_or(AvError::StreamNotFound)?;
+                if stream.parameters().medium() != AvMediaType::Video {
+                    return Err(Error::NotAVideoStream(index));
+                }
+                index
+            }
+            None => reader.best_video_stream_index()?,
+        };
+
+        let decoder = Decoder::from_reader_with_config(
+            reader,
+            reader_stream_index,
+            DecoderConfig {
+                resize: self
+                    .output_size
+                    .map(|(width, height)| Resize::Exact(width, height)),
+                output_format: self.output_format,
+                hardware: self.hardware.clone(),
+                lenient: self.lenient,
+                low_latency: self.low_latency,
+                color_spaces: self.color_spaces,
+            },
+        )?
+        .with_decode_speed(self.decode_speed);
+        match self.crop.as_ref() {
+            Some(crop) => decoder.with_crop(crop.x, crop.y, crop.width, crop.height),
+            None => Ok(decoder),
+        }
+    }
+}
+
+/// Input options that make the demuxer pass on packets as soon as possible (see
+/// [`DecoderBuilder::low_latency`]).
+const LOW_LATENCY_INPUT_OPTIONS: [(&str, &str); 4] = [
+    ("fflags", "nobuffer"),
+    ("max_delay", "0"),
+    ("probesize", "32"),
+    ("analyzeduration", "0"),
+];
+
+/// Get the low latency input options, along with the options that were set by the caller, which
+/// take precedence.
+///
+/// # Arguments
+///
+/// * `options` - Input options set by the caller.
+fn low_latency_input_options(options: &Options) -> Options<'static> {
+    let mut low_latency = Options::default();
+    for (key, value) in LOW_LATENCY_INPUT_OPTIONS {
+        low_latency.set(key, value);
+    }
+
+    low_latency.merge(options.to_owned_options())
+}
+
+/// Decoder part of a split [`Decoder`] and [`Reader`].
+pub struct DecoderSplit {
+    decoder: AvDecoder,
+    decoder_time_base: AvRational,
+    scaler: CropScaler,
+    scaler_u16: Option<CropScaler>,
+    scaler_yuv: Option<CropScaler>,
+    scaler_bgra: Option<CropScaler>,
+    /// Scaler to the pixel format of raw frames, if it is not the default format.
+    scaler_raw: Option<CropScaler>,
+    output_format: Option<AvPixel>,
+    size: (u32, u32),
+    size_out: (u32, u32),
+    resize: Option<Resize>,
+    crop: Option<Crop>,
+    filter: Option<FilterGraph>,
+    /// Frame rate of the stream, if it is known.
+    frame_rate: Option<AvRational>,
+    has_alpha: bool,
+    /// Pixel format of frames after they are copied out of the hardware device, once the first
+    /// frame has been decoded with a hardware device.
+    hardware_format: Option<AvPixel>,
+    lenient: bool,
+    /// Number of packets and frames that were skipped as corrupt, when lenient.
+    num_corrupt: u64,
+    timestamp_check: TimestampCheck,
+    speed: DecodeSpeed,
+    color_spaces: ScaleColorSpaces,
+}
+
+/// How a [`DecoderSplit`] is set up, besides the stream it decodes.
+#[derive(Default)]
+pub(crate) struct DecoderConfig {
+    /// How to resize frames.
+    pub(crate) resize: Option<Resize>,
+    /// Pixel format of raw frames, instead of RGB24.
+    pub(crate) output_format: Option<AvPixel>,
+    /// Hardware device to decode with.
+    pub(crate) hardware: Option<HardwareDevice>,
+    /// Whether to conceal errors in corrupt input instead of failing.
+    pub(crate) lenient: bool,
+    /// Whether to output frames as soon as possible.
+    pub(crate) low_latency: bool,
+    /// Color spaces to convert between when scaling frames.
+    pub(crate) color_spaces: ScaleColorSpaces,
+}
+
+impl DecoderSplit {
+    /// Get decoder time base. With a filter, this is the time base of the filter output.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        match self.filter {
+            Some(ref filter) => filter.output_time_base(),
+            None => self.decoder_time_base,
+        }
+    }
+
+    /// Get the underlying ffmpeg decoder context, to read what the crate does not expose, like
+    /// the profile or the number of reference frames of the stream.
+    #[inline]
+    pub fn codec_context(&self) -> &AvContext {
+        &self.decoder
+    }
+
+    /// Get the underlying ffmpeg decoder context, for anything that the crate does not wrap. The
+    /// context is valid for as long as the decoder exists.
+    ///
+    /// # Safety
+    ///
+    /// The caller must not free or close the context, and must not use the pointer after the
+    /// decoder is dropped. Changing the size or the pixel format of the context, or sending and
+    /// receiving through it directly, leaves the scalers and filters of the decoder out of step
+    /// with the frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (mut decoder, reader, stream_index) = Decoder::new(&source).unwrap().into_parts();
+    /// let profile = unsafe { (*decoder.as_raw_codec_context()).profile };
+    /// ```
+    #[inline]
+    pub unsafe fn as_raw_codec_context(&mut self) -> *mut AVCodecContext {
+        self.decoder.as_mut_ptr()
+    }
+
+    /// Crop frames to a region before they are converted and resized. See
+    /// [`Decoder::with_crop`].
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the crop region.
+    /// * `y` - Vertical offset of the crop region.
+    /// * `width` - Width of the crop region.
+    /// * `height` - Height of the crop region.
+    pub fn with_crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Result<Self> {
+        self.set_crop(Some(Crop::new(x, y, width, height)))?;
+        Ok(self)
+    }
+
+    /// Apply a filter graph to the decoded frames, before they are converted and resized. See
+    /// [`Decoder::with_filter`].
+    ///
+    /// The decoder produces at most one frame per packet. If the filter produces more frames than
+    /// it consumes (like `fps` with a higher frame rate), the extra frames come out when draining.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filters to apply, like `yadif,scale=1280:720`.
+    pub fn with_filter(mut self, spec: &str) -> Result<Self> {
+        self.set_filter(spec)?;
+        Ok(self)
+    }
+
+    /// Apply a geometric transform to the decoded frames. See [`Decoder::with_transform`].
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - Transform to apply.
+    pub fn with_transform(mut self, transform: Transform) -> Result<Self> {
+        self.set_filter(transform.filter_spec())?;
+        Ok(self)
+    }
+
+    /// Deinterlace the decoded frames. See [`Decoder::with_deinterlace`].
+    ///
+    /// # Arguments
+    ///
+    /// * `deinterlace` - When to deinterlace.
+    /// * `mode` - Whether to produce a frame per frame or per field.
+    pub fn with_deinterlace(
+        mut self,
+        deinterlace: Deinterlace,
+        mode: DeinterlaceMode,
+    ) -> Result<Self> {
+        if let Some(spec) = deinterlace.filter_spec(mode) {
+            self.set_filter(&spec)?;
+        }
+        Ok(self)
+    }
+
+    /// Discard the frames that the decoder and the filter (if any) still hold, after seeking. This
+    /// also makes the decoder accept packets again after it was drained.
+    pub(crate) fn flush(&mut self) -> Result<()> {
+        self.decoder.flush();
+        self.timestamp_check.reset();
+        if let Some(filter) = self.filter.as_ref() {
+            self.filter = Some(FilterGraph::parse(filter.spec(), *filter.input())?);
+        }
+        Ok(())
+    }
+
+    /// Get the frame rate of the frames that come out of the filter, if there is a filter and it
+    /// knows the frame rate.
+    pub(crate) fn filter_frame_rate(&self) -> Option<AvRational> {
+        self.filter
+            .as_ref()
+            .and_then(FilterGraph::output_frame_rate)
+    }
+
+    /// Export the motion vectors of the codec with each frame. See
+    /// [`Decoder::with_motion_vectors`].
+    pub fn with_motion_vectors(mut self) -> Self {
+        set_decoder_export_motion_vectors(&mut self.decoder);
+        self
+    }
+
+    /// Set what to do with frames whose timestamps are missing or go backwards. See
+    /// [`Decoder::with_timestamp_policy`].
+    pub fn with_timestamp_policy(mut self, policy: TimestampPolicy) -> Self {
+        self.timestamp_check = TimestampCheck::new(policy);
+        self
+    }
+
+    /// Skip frames or decoding steps to decode faster. See [`Decoder::with_decode_speed`]. Only
+    /// the codec settings are applied here: with [`DecodeSpeed::KeyframesOnly`], packets that are
+    /// not keyframes can be left out before they are passed to [`DecoderSplit::decode_raw`] to save
+    /// more time.
+    pub fn with_decode_speed(mut self, speed: DecodeSpeed) -> Self {
+        let (skip_frame, skip_loop_filter) = match speed {
+            DecodeSpeed::Full => (AVDiscard::AVDISCARD_DEFAULT, AVDiscard::AVDISCARD_DEFAULT),
+            DecodeSpeed::FastPreview => (AVDiscard::AVDISCARD_NONREF, AVDiscard::AVDISCARD_ALL),
+            DecodeSpeed::KeyframesOnly => {
+                (AVDiscard::AVDISCARD_NONKEY, AVDiscard::AVDISCARD_DEFAULT)
+            }
+        };
+        set_decoder_discard(
+            &mut self.decoder,
+            skip_frame,
+            skip_loop_filter,
+            AVDiscard::AVDISCARD_DEFAULT,
+        );
+        self.speed = speed;
+        self
+    }
+
+    /// Get the number of timestamps that were synthesized. See [`Decoder::timestamps_repaired`].
+    pub fn timestamps_repaired(&self) -> u64 {
+        self.timestamp_check.repaired
+    }
+
+    /// Get the duration of a frame at the nominal frame rate, in units of the decoder time base.
+    /// This is one unit if the frame rate is not known.
+    fn nominal_frame_duration(&self) -> i64 {
+        self.frame_rate
+            .map(|frame_rate| {
+                let time_base = self.decoder_time_base;
+                (time_base.denominator() as f64 * frame_rate.denominator() as f64
+                    / (time_base.numerator() as f64 * frame_rate.numerator() as f64))
+                    .round() as i64
+            })
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// Decode a [`Packet`].
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the [`Frame`] and timestamp (relative to the stream) and the frame itself if the
+    /// decoder has a frame available, [`None`] if not. Frames have 4 channels in BGRA order if the
+    /// input has an alpha channel (see [`DecoderSplit::has_alpha`]), and 3 channels in RGB order
+    /// otherwise.
+    #[cfg(feature = "ndarray")]
+    pub fn decode(&mut self, packet: Packet) -> Result<Option<(Time, Frame)>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.convert(frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into a floating point frame.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to decode.
+    /// * `normalization` - How to convert components to floating point values.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the frame itself if the decoder has a
+    /// frame available, [`None`] if not.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_f32(
+        &mut self,
+        packet: Packet,
+        normalization: &Normalization,
+    ) -> Result<Option<(Time, Frame32)>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.convert_f32(frame, normalization))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into an RGB image of the `image` crate.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the image itself if the decoder has a
+    /// frame available, [`None`] if not.
+    #[cfg(feature = "image")]
+    pub fn decode_image(&mut self, packet: Packet) -> Result<Option<(Time, image::RgbImage)>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.convert_image(frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`].
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] if the decoder has a frame available, [`None`] if not.
+    pub fn decode_raw(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        match self.decode_unscaled(packet)? {
+            Some(frame) => Ok(Some(self.scale_raw(&frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Signal the end of the stream to the decoder, and pull one of the frames that it still holds.
+    /// The caller should keep calling this until it returns [`None`].
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] if the decoder had a frame left, [`None`] if not.
+    pub(crate) fn drain_raw(&mut self) -> Result<Option<RawFrame>> {
+        match self.drain_unscaled()? {
+            Some(frame) => Ok(Some(self.scale_raw(&frame)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Signal the end of the stream to the decoder, and pull one of the frames (in the decoder
+    /// pixel format) that it still holds. With a filter, the frames that the filter still holds
+    /// are pulled as well.
+    pub(crate) fn drain_unscaled(&mut self) -> Result<Option<RawFrame>> {
+        loop {
+            if let Some(frame) = self.pull_filtered()? {
+                return Ok(Some(frame));
+            }
+
+            // This fails if the end of the stream was signalled before, which is fine.
+            let _ = self.decoder.send_eof();
+            let frame = match self.decoder_receive_frame() {
+                Ok(frame) => frame,
+                Err(err) if err.backend_error() == Some(AvError::Eof) => None,
+                Err(err) => return Err(err),
+            };
+            match (self.filter.as_mut(), frame) {
+                (Some(filter), Some(frame)) => filter.push(frame)?,
+                (Some(filter), None) if !filter.is_flushed() => filter.flush()?,
+                (_, frame) => return Ok(frame),
+            }
+        }
+    }
+
+    /// Decode a [`Packet`] into a frame with 16 bits per component.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the frame itself as RGB with type
+    /// `u16` if the decoder has a frame available, [`None`] if not.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_u16(&mut self, packet: Packet) -> Result<Option<(Time, FrameU16)>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.convert_u16(frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into separate Y, U and V planes with 4:2:0 chroma subsampling.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the timestamp (relative to the stream) and the frame itself if the decoder has a
+    /// frame available, [`None`] if not.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_yuv(&mut self, packet: Packet) -> Result<Option<(Time, YuvFrame)>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.convert_yuv(frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into a raw frame with 4:2:0 chroma subsampling.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] in YUV420p format (or YUVJ420p, if the stream is
+    /// full range and frames are not converted) if the decoder has a frame available, [`None`] if
+    /// not.
+    pub fn decode_raw_yuv(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.scale_yuv(frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into a raw frame with 16 bits per component.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] in RGB48 format (native endianness) if the decoder
+    /// has a frame available, [`None`] if not.
+    pub fn decode_raw_u16(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.scale_u16(&frame))
+            .transpose()
+    }
+
+    /// Decode a [`Packet`] into a frame in BGRA format.
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawFrame`] in BGRA format if the decoder has a frame available,
+    /// [`None`] if not.
+    pub fn decode_raw_bgra(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        self.decode_unscaled(packet)?
+            .map(|frame| self.scale_bgra(&frame))
+            .transpose()
+    }
+
+    /// Get the name of the codec of the input, like `h264` or `hevc`.
+    #[inline]
+    pub fn codec_name(&self) -> String {
+        self.decoder.id().name().to_string()
+    }
+
+    /// Get the bit rate of the input stream in bits per second, if the container stores it.
+    #[inline]
+    pub fn bit_rate(&self) -> Option<u64> {
+        Some(self.decoder.bit_rate() as u64).filter(|bit_rate| *bit_rate > 0)
+    }
+
+    /// Get the pixel format of the input, as it comes out of the decoder, before frames are
+    /// converted.
+    #[inline]
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.decoder.format()
+    }
+
+    /// Whether or not the input has an alpha channel, like VP9 in WebM with alpha or ProRes 4444
+    /// with alpha.
+    #[inline]
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    /// Get the color primaries of the input.
+    #[inline]
+    pub fn color_primaries(&self) -> ColorPrimaries {
+        self.decoder.color_primaries()
+    }
+
+    /// Get the color transfer characteristic of the input.
+    #[inline]
+    pub fn color_transfer_characteristic(&self) -> ColorTransferCharacteristic {
+        self.decoder.color_transfer_characteristic()
+    }
+
+    /// Get the color space of the input.
+    #[inline]
+    pub fn color_space(&self) -> ColorSpace {
+        self.decoder.color_space()
+    }
+
+    /// Get the color range of the input.
+    #[inline]
+    pub fn color_range(&self) -> ColorRange {
+        self.decoder.color_range()
+    }
+
+    /// Get the decoders input size (resolution dimensions): width and height.
+    #[inline(always)]
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Get the decoders output size after cropping and resizing are applied (resolution
+    /// dimensions): width and height.
+    #[inline(always)]
+    pub fn size_out(&self) -> (u32, u32) {
+        self.size_out
+    }
+
+    /// Create a new [`DecoderSplit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    /// * `resize` - Optional resize strategy to apply to frames.
+    pub fn new(
+        reader: &Reader,
+        reader_stream_index: usize,
         resize: Option<Resize>,
     ) -> Result<Self> {
+        Self::new_with_config(
+            reader,
+            reader_stream_index,
+            DecoderConfig {
+                resize,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Create a new [`DecoderSplit`] with a configuration (see [`DecoderBuilder`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    /// * `reader_stream_index` - Index of the video stream to decode.
+    /// * `config` - How to set up the decoder.
+    pub(crate) fn new_with_config(
+        reader: &Reader,
+        reader_stream_index: usize,
+        config: DecoderConfig,
+    ) -> Result<Self> {
+        let DecoderConfig {
+            resize,
+            output_format,
+            hardware,
+            lenient,
+            low_latency,
+            color_spaces,
+        } = config;
         let reader_stream = reader
             .input
             .stream(reader_stream_index)
             .ok_or(AvError::StreamNotFound)?;
 
-        let mut decoder = AvContext::new();
-        set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
-        decoder.set_parameters(reader_stream.parameters())?;
-        let decoder = decoder.decoder().video()?;
-        let decoder_time_base = decoder.time_base();
+        // The native VP9 decoder ignores the alpha channel that WebM stores alongside the video,
+        // but the libvpx decoder does not.
+        let alpha_codec = if reader_stream.parameters().id() == AvCodecId::VP9
+            && reader_stream.metadata().get("alpha_mode") == Some("1")
+        {
+            ffmpeg::decoder::find_by_name("libvpx-vp9")
+        } else {
+            None
+        };
+
+        let mut decoder = AvContext::new();
+        set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
+        decoder.set_parameters(reader_stream.parameters())?;
+        if lenient {
+            set_decoder_error_concealment(&mut decoder);
+        }
+        if low_latency {
+            set_decoder_low_latency(&mut decoder);
+        }
+        if let Some(hardware) = hardware.as_ref() {
+            let codec_id = reader_stream.parameters().id();
+            let device_type = hardware.device_type();
+            let codec = alpha_codec
+                .or_else(|| ffmpeg::decoder::find(codec_id))
+                .ok_or(Error::UnsupportedCodec {
+                    name: codec_id.name().to_string(),
+                })?;
+            if !decoder_supports_hardware_device(&codec, device_type.as_av()) {
+                return Err(Error::HardwareDecodingNotSupported {
+                    codec: codec.name().to_string(),
+                    device: device_type.name().to_string(),
+                });
+            }
+            set_decoder_hardware_device(&mut decoder, device_type.as_av(), hardware.device())
+                .context(ErrorContext::new("hwdevice.create"))?;
+        }
+        let decoder = match alpha_codec {
+            Some(codec) => decoder
+                .decoder()
+                .open_as(codec)
+                .and_then(|decoder| decoder.video()),
+            None => decoder.decoder().video(),
+        }
+        .map_err(|err| {
+            ErrorContext::new("decoder.open")
+                .with_stream_index(reader_stream_index)
+                .wrap(Error::from_codec_error(
+                    err,
+                    reader_stream.parameters().id(),
+                ))
+        })?;
+        let decoder_time_base = decoder.time_base();
+
+        let (resize_width, resize_height) = match resize {
+            Some(resize) => resize
+                .compute_for((decoder.width(), decoder.height()))
+                .ok_or(Error::InvalidResizeParameters)?,
+            None => (decoder.width(), decoder.height()),
+        };
+
+        if decoder.format() == AvPixel::None || decoder.width() == 0 || decoder.height() == 0 {
+            return Err(Error::MissingCodecParameters);
+        }
+
+        let size = (decoder.width(), decoder.height());
+        let size_out = (resize_width, resize_height);
+        let has_alpha = alpha_codec.is_some() || pixel_format_has_alpha(decoder.format());
+        let scaler_color_spaces = color_spaces.resolve(
+            (decoder.color_space(), size.1),
+            (ColorSpace::Unspecified, size_out.1),
+        );
+
+        let scaler = new_scaler(
+            decoder.format(),
+            size,
+            None,
+            FRAME_PIXEL_FORMAT,
+            size_out,
+            AvScalerFlags::AREA,
+            scaler_color_spaces,
+        )?;
+        // The scaler to the output format is set up right away, so that output formats that
+        // cannot be converted to fail here rather than at the first frame.
+        let output_format = output_format.filter(|format| *format != FRAME_PIXEL_FORMAT);
+        let scaler_raw = output_format
+            .map(|output_format| {
+                new_scaler(
+                    decoder.format(),
+                    size,
+                    None,
+                    output_format,
+                    size_out,
+                    AvScalerFlags::AREA,
+                    scaler_color_spaces,
+                )
+            })
+            .transpose()?;
+
+        Ok(Self {
+            decoder,
+            decoder_time_base,
+            scaler,
+            scaler_u16: None,
+            scaler_yuv: None,
+            scaler_bgra: None,
+            scaler_raw,
+            output_format,
+            size,
+            size_out,
+            resize,
+            crop: None,
+            filter: None,
+            frame_rate: Some(reader_stream.rate()).filter(|rate| rate.numerator() > 0),
+            has_alpha,
+            hardware_format: None,
+            lenient,
+            num_corrupt: 0,
+            timestamp_check: TimestampCheck::new(TimestampPolicy::default()),
+            speed: DecodeSpeed::Full,
+            color_spaces,
+        })
+    }
+
+    /// Set the region to crop frames to, and set up the scaler for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `crop` - Region to crop to, or `None` to not crop frames.
+    fn set_crop(&mut self, crop: Option<Crop>) -> Result<()> {
+        let size_cropped = match crop {
+            Some(crop) => {
+                crop.validate(self.size)?;
+                crop.size()
+            }
+            None => self.size,
+        };
+        let size_out = match self.resize {
+            Some(resize) => resize
+                .compute_for(size_cropped)
+                .ok_or(Error::InvalidResizeParameters)?,
+            None => size_cropped,
+        };
+
+        self.scaler = new_scaler(
+            self.input_format(),
+            self.size,
+            crop,
+            FRAME_PIXEL_FORMAT,
+            size_out,
+            AvScalerFlags::AREA,
+            self.scale_color_spaces_for(size_out),
+        )?;
+        // The high bit depth, YUV, BGRA and raw scalers are initialized again when they are
+        // needed.
+        self.scaler_u16 = None;
+        self.scaler_yuv = None;
+        self.scaler_bgra = None;
+        self.scaler_raw = None;
+        self.size_out = size_out;
+        self.crop = crop;
+
+        Ok(())
+    }
+
+    /// Set up a filter graph for the decoded frames, after the filters set up before (if any). The
+    /// frames that come out of the filter are cropped, converted and resized like decoded frames
+    /// would be, so the input size becomes the size of the filter output.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filters to apply.
+    fn set_filter(&mut self, spec: &str) -> Result<()> {
+        let filter = FilterGraph::parse(
+            &chain_filters(self.filter.as_ref().map(FilterGraph::spec), spec),
+            FilterInput {
+                width: self.decoder.width(),
+                height: self.decoder.height(),
+                pixel_format: self.decoded_format(),
+                time_base: self.decoder_time_base,
+                frame_rate: self.frame_rate.or_else(|| self.decoder.frame_rate()),
+            },
+        )?;
+
+        self.size = filter.output_size();
+        self.has_alpha = pixel_format_has_alpha(filter.output_format());
+        self.filter = Some(filter);
+        self.set_crop(self.crop)
+    }
+
+    /// Get the pixel format of the frames that go into the scalers: the decoder pixel format, or
+    /// the output format of the filter.
+    fn input_format(&self) -> AvPixel {
+        match self.filter {
+            Some(ref filter) => filter.output_format(),
+            None => self.decoded_format(),
+        }
+    }
+
+    /// Get the pixel format of decoded frames: the decoder pixel format, or the format that frames
+    /// have after they are copied out of the hardware device.
+    fn decoded_format(&self) -> AvPixel {
+        self.hardware_format.unwrap_or(self.decoder.format())
+    }
+
+    /// Pull a frame out of the filter, if there is a filter and it has a frame available. The
+    /// packet DTS of the frame is set to its timestamp, since filters like `fps` produce frames
+    /// that were not decoded from a packet.
+    pub(crate) fn pull_filtered(&mut self) -> Result<Option<RawFrame>> {
+        let Some(filter) = self.filter.as_mut() else {
+            return Ok(None);
+        };
+        let frame = filter.pull()?.map(|mut frame| {
+            let pts = frame.pts();
+            set_frame_packet_dts(&mut frame, pts);
+            frame
+        });
+        Ok(frame)
+    }
 
-        let (resize_width, resize_height) = match resize {
-            Some(resize) => resize
-                .compute_for((decoder.width(), decoder.height()))
-                .ok_or(Error::InvalidResizeParameters)?,
-            None => (decoder.width(), decoder.height()),
+    /// Get the color spaces that the scalers convert between, see [`ScaleColorSpaces`].
+    fn scale_color_spaces(&self) -> (ColorSpace, ColorSpace) {
+        self.scale_color_spaces_for(self.size_out)
+    }
+
+    /// Get the color spaces that a scaler to the given output size converts between.
+    ///
+    /// # Arguments
+    ///
+    /// * `size_out` - Dimensions to resize to.
+    fn scale_color_spaces_for(&self, size_out: (u32, u32)) -> (ColorSpace, ColorSpace) {
+        self.color_spaces.resolve(
+            (self.color_space(), self.size.1),
+            (ColorSpace::Unspecified, size_out.1),
+        )
+    }
+
+    /// Convert a frame in the decoder pixel format and size to the output pixel format and size.
+    fn scale(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        let mut frame_scaled = self.scaler.run(frame)?;
+        self.scaler.copy_props(frame, &mut frame_scaled);
+
+        Ok(frame_scaled)
+    }
+
+    /// Convert a frame in the decoder pixel format and size to the pixel format of raw frames (see
+    /// [`DecoderBuilder::output_format`]) and the output size.
+    fn scale_raw(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        let Some(output_format) = self.output_format else {
+            return self.scale(frame);
         };
 
-        if decoder.format() == AvPixel::None || decoder.width() == 0 || decoder.height() == 0 {
-            return Err(Error::MissingCodecParameters);
+        let scaler = match self.scaler_raw {
+            Some(ref mut scaler) => scaler,
+            None => self.scaler_raw.insert(new_scaler(
+                self.input_format(),
+                self.size,
+                self.crop,
+                output_format,
+                self.size_out,
+                AvScalerFlags::AREA,
+                self.scale_color_spaces(),
+            )?),
+        };
+
+        let mut frame_scaled = scaler.run(frame)?;
+        scaler.copy_props(frame, &mut frame_scaled);
+
+        Ok(frame_scaled)
+    }
+
+    /// Convert a frame in the decoder pixel format to a [`Frame`]. See [`DecoderSplit::decode`].
+    #[cfg(feature = "ndarray")]
+    fn convert(&mut self, frame: RawFrame) -> Result<(Time, Frame)> {
+        let mut frame = if self.has_alpha {
+            self.scale_bgra(&frame)?
+        } else {
+            self.scale(&frame)?
+        };
+        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+        // will use when encoding for the `PTS` field.
+        let timestamp = Time::new(Some(frame.packet().dts), self.time_base());
+        let frame = convert_frame_to_ndarray(&mut frame)?;
+
+        Ok((timestamp, frame))
+    }
+
+    /// Convert a frame in the decoder pixel format to a [`Frame32`]. See
+    /// [`DecoderSplit::decode_f32`].
+    #[cfg(feature = "ndarray")]
+    fn convert_f32(
+        &mut self,
+        frame: RawFrame,
+        normalization: &Normalization,
+    ) -> Result<(Time, Frame32)> {
+        let frame = self.scale(&frame)?;
+        if frame.format() != AvPixel::RGB24 {
+            return Err(Error::invalid_frame_format(
+                "RGB24",
+                format!("{:?}", frame.format()),
+            ));
         }
 
-        let scaler = AvScaler::get(
-            decoder.format(),
-            decoder.width(),
-            decoder.height(),
-            FRAME_PIXEL_FORMAT,
-            resize_width,
-            resize_height,
-            AvScalerFlags::AREA,
-        )?;
+        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+        // will use when encoding for the `PTS` field.
+        let timestamp = Time::new(Some(frame.packet().dts), self.time_base());
+        let frame = convert_rgb24_to_ndarray_f32(
+            frame.data(0),
+            frame.stride(0),
+            frame.width() as usize,
+            frame.height() as usize,
+            normalization,
+        );
 
-        let size = (decoder.width(), decoder.height());
-        let size_out = (resize_width, resize_height);
+        Ok((timestamp, frame))
+    }
 
-        Ok(Self {
-            decoder,
-            decoder_time_base,
-            scaler,
-            size,
-            size_out,
-        })
+    /// Convert a frame in the decoder pixel format to an RGB image. See
+    /// [`DecoderSplit::decode_image`].
+    #[cfg(feature = "image")]
+    fn convert_image(&mut self, frame: RawFrame) -> Result<(Time, image::RgbImage)> {
+        let frame = self.scale(&frame)?;
+        if frame.format() != AvPixel::RGB24 {
+            return Err(Error::invalid_frame_format(
+                "RGB24",
+                format!("{:?}", frame.format()),
+            ));
+        }
+
+        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+        // will use when encoding for the `PTS` field.
+        let timestamp = Time::new(Some(frame.packet().dts), self.time_base());
+        let packed = pack_rows(
+            frame.data(0),
+            frame.stride(0),
+            frame.width() as usize * 3,
+            frame.height() as usize,
+        );
+        let (width, height) = (frame.width(), frame.height());
+        let image = image::RgbImage::from_raw(width, height, packed).ok_or_else(|| {
+            Error::invalid_frame_format(
+                format!("RGB24 frame of {width}x{height}"),
+                "frame with too little data",
+            )
+        })?;
+
+        Ok((timestamp, image))
+    }
+
+    /// Convert a frame in the decoder pixel format to a [`FrameU16`]. See
+    /// [`DecoderSplit::decode_u16`].
+    #[cfg(feature = "ndarray")]
+    fn convert_u16(&mut self, frame: RawFrame) -> Result<(Time, FrameU16)> {
+        let mut frame = self.scale_u16(&frame)?;
+        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+        // will use when encoding for the `PTS` field.
+        let timestamp = Time::new(Some(frame.packet().dts), self.time_base());
+        let frame = convert_frame_to_ndarray_u16(&mut frame)?;
+
+        Ok((timestamp, frame))
+    }
+
+    /// Convert a frame in the decoder pixel format to a [`YuvFrame`]. See
+    /// [`DecoderSplit::decode_yuv`].
+    #[cfg(feature = "ndarray")]
+    fn convert_yuv(&mut self, frame: RawFrame) -> Result<(Time, YuvFrame)> {
+        let frame = self.scale_yuv(frame)?;
+        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+        // will use when encoding for the `PTS` field.
+        let timestamp = Time::new(Some(frame.packet().dts), self.time_base());
+        let frame = YuvFrame::from_raw_frame(&frame)?;
+
+        Ok((timestamp, frame))
+    }
+
+    /// Convert a frame in the decoder pixel format to YUV420p, at the output size.
+    fn scale_yuv(&mut self, frame: RawFrame) -> Result<RawFrame> {
+        // Frames that are already 4:2:0 do not need to go through the scaler at all.
+        if matches!(frame.format(), AvPixel::YUV420P | AvPixel::YUVJ420P)
+            && self.crop.is_none()
+            && self.size_out == self.size
+        {
+            return Ok(frame);
+        }
+
+        // The YUV scaler is only initialized when it is first needed.
+        let scaler = match self.scaler_yuv {
+            Some(ref mut scaler) => scaler,
+            None => self.scaler_yuv.insert(new_scaler(
+                self.input_format(),
+                self.size,
+                self.crop,
+                AvPixel::YUV420P,
+                self.size_out,
+                AvScalerFlags::AREA,
+                self.scale_color_spaces(),
+            )?),
+        };
+
+        let mut frame_scaled = scaler.run(&frame)?;
+        scaler.copy_props(&frame, &mut frame_scaled);
+
+        Ok(frame_scaled)
+    }
+
+    /// Convert a frame in the decoder pixel format to RGB48, at the output size.
+    fn scale_u16(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        // The high bit depth scaler is only initialized when it is first needed.
+        let scaler = match self.scaler_u16 {
+            Some(ref mut scaler) => scaler,
+            None => self.scaler_u16.insert(new_scaler(
+                self.input_format(),
+                self.size,
+                self.crop,
+                FRAME_PIXEL_FORMAT_U16,
+                self.size_out,
+                AvScalerFlags::AREA | AvScalerFlags::ACCURATE_RND,
+                self.scale_color_spaces(),
+            )?),
+        };
+
+        let mut frame_scaled = scaler.run(frame)?;
+        scaler.copy_props(frame, &mut frame_scaled);
+
+        Ok(frame_scaled)
+    }
+
+    /// Convert a frame in the decoder pixel format to BGRA, at the output size.
+    fn scale_bgra(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        // The BGRA scaler is only initialized when it is first needed. Decoders that only find out
+        // about the alpha channel while decoding (like libvpx) produce frames in a different pixel
+        // format than they reported at first.
+        let scaler = match self.scaler_bgra {
+            Some(ref mut scaler) if scaler.format() == frame.format() => scaler,
+            _ => self.scaler_bgra.insert(new_scaler(
+                frame.format(),
+                self.size,
+                self.crop,
+                AvPixel::BGRA,
+                self.size_out,
+                AvScalerFlags::AREA,
+                self.scale_color_spaces(),
+            )?),
+        };
+
+        let mut frame_scaled = scaler.run(frame)?;
+        scaler.copy_props(frame, &mut frame_scaled);
+
+        Ok(frame_scaled)
+    }
+
+    /// Feed a [`Packet`] to the decoder and pull a decoded frame (in the decoder pixel format, or
+    /// the output format of the filter) if there is one available.
+    pub(crate) fn decode_unscaled(&mut self, packet: Packet) -> Result<Option<RawFrame>> {
+        let (mut packet, packet_time_base) = packet.into_inner_parts();
+        packet.rescale_ts(packet_time_base, self.decoder_time_base);
+
+        match self.decoder.send_packet(&packet) {
+            Err(AvError::InvalidData) if self.lenient => {
+                tracing::warn!(pts = ?packet.pts(), "skipping corrupt packet");
+                self.num_corrupt += 1;
+            }
+            result => result.context(
+                ErrorContext::new("decoder.send_packet")
+                    .with_stream_index(packet.stream())
+                    .with_pts(packet.pts()),
+            )?,
+        }
+
+        let frame = self.decoder_receive_frame()?;
+        match self.filter.as_mut() {
+            Some(filter) => {
+                if let Some(frame) = frame {
+                    filter.push(frame)?;
+                }
+                self.pull_filtered()
+            }
+            None => Ok(frame),
+        }
     }
 
     /// Pull a decoded frame from the decoder. This function also implements retry mechanism in case
@@ -362,11 +3823,32 @@ impl DecoderSplit {
     fn decoder_receive_frame(&mut self) -> Result<Option<RawFrame>> {
         let mut frame = RawFrame::empty();
         let decode_result = self.decoder.receive_frame(&mut frame);
-        match decode_result {
-            Ok(()) => Ok(Some(frame)),
-            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
-            Err(err) => Err(err.into()),
-        }
+        let mut frame = match decode_result {
+            Ok(()) if frame_is_hardware(&frame) => {
+                let frame = transfer_hardware_frame(&frame)
+                    .context(ErrorContext::new("hwframe.transfer_data"))?;
+                // The format that frames are copied out in is only known after the first frame,
+                // so the scalers are set up for it again.
+                if self.hardware_format != Some(frame.format()) {
+                    self.hardware_format = Some(frame.format());
+                    self.set_crop(self.crop)?;
+                }
+                frame
+            }
+            Ok(()) => frame,
+            Err(AvError::Other { errno }) if errno == EAGAIN => return Ok(None),
+            Err(AvError::InvalidData) if self.lenient => {
+                tracing::warn!("skipping corrupt frame");
+                self.num_corrupt += 1;
+                return Ok(None);
+            }
+            Err(err) => return Err(ErrorContext::new("decoder.receive_frame").wrap(err)),
+        };
+
+        let frame_duration = self.nominal_frame_duration();
+        let pts = self.timestamp_check.check(frame.pts(), frame_duration)?;
+        frame.set_pts(pts);
+        Ok(Some(frame))
     }
 }
 
@@ -389,3 +3871,976 @@ impl Drop for DecoderSplit {
 
 unsafe impl Send for DecoderSplit {}
 unsafe impl Sync for DecoderSplit {}
+
+/// Create a scaler that converts frames from the decoder pixel format and size to the given output
+/// pixel format and size.
+///
+/// # Arguments
+///
+/// * `format` - Pixel format of decoded frames.
+/// * `size` - Dimensions of decoded frames.
+/// * `crop` - Optional region of decoded frames to crop to.
+/// * `format_out` - Pixel format to convert to.
+/// * `size_out` - Dimensions to resize to.
+/// * `flags` - Scaler flags.
+/// * `color_spaces` - Color spaces of decoded frames and of the frames to convert to.
+fn new_scaler(
+    format: AvPixel,
+    size: (u32, u32),
+    crop: Option<Crop>,
+    format_out: AvPixel,
+    size_out: (u32, u32),
+    flags: AvScalerFlags,
+    color_spaces: (ColorSpace, ColorSpace),
+) -> Result<CropScaler> {
+    let mut scaler = CropScaler::new(format, size, crop, format_out, size_out, flags)?;
+    scaler.set_color_spaces(color_spaces.0, color_spaces.1)?;
+    Ok(scaler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    #[test]
+    fn test_timestamp_continuity_continues_after_reset() {
+        let time_base = AvRational::new(1, 90000);
+        let time = |value| Time::new(Some(value), time_base);
+
+        let mut timestamp_continuity = TimestampContinuity::new();
+        assert_eq!(
+            timestamp_continuity.offset_for(&time(1000), &time(3000)),
+            None
+        );
+        assert_eq!(
+            timestamp_continuity.offset_for(&time(4000), &time(3000)),
+            None
+        );
+
+        // The new input restarts at zero, so it must be offset to start at the end of the last
+        // packet of the previous input.
+        timestamp_continuity.reset();
+        let offset = timestamp_continuity
+            .offset_for(&time(0), &time(3000))
+            .unwrap();
+        assert_eq!(offset, time(7000));
+        assert_eq!(
+            timestamp_continuity.offset_for(&time(3000), &time(3000)),
+            Some(time(7000))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_continuity_without_duration() {
+        let time_base = AvRational::new(1, 1000);
+        let time = |value| Time::new(Some(value), time_base);
+
+        let mut timestamp_continuity = TimestampContinuity::new();
+        timestamp_continuity.offset_for(&time(500), &time(0));
+        timestamp_continuity.reset();
+        assert_eq!(
+            timestamp_continuity.offset_for(&time(100), &time(0)),
+            Some(time(401))
+        );
+    }
+
+    #[test]
+    fn test_timestamp_check() {
+        let check_all = |policy, timestamps: &[Option<i64>]| {
+            let mut timestamp_check = TimestampCheck::new(policy);
+            let checked = timestamps
+                .iter()
+                .map(|pts| timestamp_check.check(*pts, 10))
+                .collect::<Result<Vec<_>>>();
+            (checked, timestamp_check.repaired)
+        };
+        let broken = [
+            Some(0),
+            Some(10),
+            None,
+            Some(30),
+            Some(5),
+            Some(15),
+            Some(60),
+        ];
+
+        let (checked, repaired) = check_all(TimestampPolicy::Passthrough, &broken);
+        assert_eq!(checked.unwrap(), broken);
+        assert_eq!(repaired, 0);
+
+        let (checked, repaired) = check_all(TimestampPolicy::Repair, &broken);
+        assert_eq!(
+            checked.unwrap(),
+            [
+                Some(0),
+                Some(10),
+                Some(20),
+                Some(30),
+                Some(40),
+                Some(50),
+                Some(60)
+            ]
+        );
+        assert_eq!(repaired, 3);
+        let (checked, _) = check_all(TimestampPolicy::Repair, &[None, Some(0)]);
+        assert_eq!(checked.unwrap(), [Some(0), Some(10)]);
+
+        let (checked, _) = check_all(TimestampPolicy::Reject, &broken);
+        assert!(matches!(
+            checked,
+            Err(Error::InvalidTimestamp { frame_index: 2, .. })
+        ));
+        let (checked, _) = check_all(TimestampPolicy::Reject, &[Some(0), Some(5), Some(5)]);
+        assert!(matches!(
+            checked,
+            Err(Error::InvalidTimestamp { frame_index: 2, .. })
+        ));
+
+        // Timestamps may go back after seeking.
+        let mut timestamp_check = TimestampCheck::new(TimestampPolicy::Reject);
+        timestamp_check.check(Some(100), 10).unwrap();
+        timestamp_check.reset();
+        assert_eq!(timestamp_check.check(Some(0), 10).unwrap(), Some(0));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_decode_iter_looped() {
+        const NUM_FRAMES: usize = 10;
+
+        let path = TempPath::new("looped.mp4");
+        testing::write_test_video(
+            &path.locator(),
+            (0..NUM_FRAMES).map(|i| testing::filled_frame(i as u8 * 20)),
+        );
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let (first, _) = decoder.decode().unwrap();
+        decoder.decode().unwrap();
+        decoder.seek_to_start().unwrap();
+        assert_eq!(decoder.decode().unwrap().0, first);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let timestamps = decoder
+            .decode_iter_looped(Some(3))
+            .map(|frame| frame.unwrap().0.as_secs_f64())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 3 * NUM_FRAMES);
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            assert!((timestamp - timestamps[0] - i as f64 / 30.0).abs() < 0.002);
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_next_keyframe() {
+        use crate::{Encoder, EncoderSettings};
+
+        let path = TempPath::new("keyframes.mp4");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false).with_key_frame_interval(10),
+        )
+        .unwrap();
+        testing::encode_frames(
+            &mut encoder,
+            0,
+            (0..30).map(|i| testing::filled_frame(i as u8 * 8)),
+        );
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let mut timestamps = Vec::new();
+        while let Some((timestamp, frame)) = decoder.next_keyframe().unwrap() {
+            assert_eq!(frame.dim(), (48, 64, 3));
+            timestamps.push(timestamp.as_secs_f64());
+        }
+        assert_eq!(timestamps.len(), 3);
+        for (timestamp, expected) in timestamps.iter().zip([0.0, 10.0 / 30.0, 20.0 / 30.0]) {
+            assert!((timestamp - timestamps[0] - expected).abs() < 0.002);
+        }
+
+        // Interleaved with normal decoding.
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        decoder.decode().unwrap();
+        let (keyframe, _) = decoder.next_keyframe().unwrap().unwrap();
+        assert!((keyframe.as_secs_f64() - timestamps[1]).abs() < 0.002);
+        decoder.seek_to_start().unwrap();
+        let (keyframe, _) = decoder.next_keyframe().unwrap().unwrap();
+        assert!((keyframe.as_secs_f64() - timestamps[0]).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_decode_speed() {
+        use crate::{Encoder, EncoderSettings};
+
+        let path = TempPath::new("speed.mkv");
+        // The encoder uses B-frames when it is not real time.
+        let settings = EncoderSettings::for_h264_yuv420p(64, 48, false).with_key_frame_interval(10);
+        let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+        testing::encode_frames(
+            &mut encoder,
+            0,
+            (0..30).map(|i| testing::filled_frame(i as u8 * 8)),
+        );
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let decode_all = |speed: Option<DecodeSpeed>| {
+            let mut decoder = Decoder::new(&path.locator()).unwrap();
+            if let Some(speed) = speed {
+                decoder = decoder.with_decode_speed(speed);
+            }
+            let mut frames = Vec::new();
+            while let Ok(frame) = decoder.decode_raw() {
+                frames.push((frame.pts().unwrap(), frame.data(0).to_vec()));
+            }
+            frames
+        };
+
+        // Decoding in full is the same as decoding by default.
+        let full = decode_all(None);
+        assert_eq!(full.len(), 30);
+        assert_eq!(decode_all(Some(DecodeSpeed::Full)), full);
+
+        // Fewer frames come out, at the timestamps of the frames they are.
+        for speed in [DecodeSpeed::FastPreview, DecodeSpeed::KeyframesOnly] {
+            let frames = decode_all(Some(speed));
+            assert!(!frames.is_empty() && frames.len() < full.len(), "{speed:?}");
+            assert!(frames.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert!(frames
+                .iter()
+                .all(|(pts, _)| full.iter().any(|(full_pts, _)| full_pts == pts)));
+        }
+
+        let keyframes = Decoder::builder(&path.locator())
+            .decode_speed(DecodeSpeed::KeyframesOnly)
+            .build()
+            .unwrap()
+            .decode_raw_iter()
+            .map(|frame| frame.unwrap().pts().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(keyframes, [full[0].0, full[10].0, full[20].0]);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_decode_between() {
+        use crate::{Encoder, EncoderSettings};
+
+        let path = TempPath::new("between.mp4");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false).with_key_frame_interval(10),
+        )
+        .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(60));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        // The range starts in the middle of a GOP.
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let timestamps = decoder
+            .decode_between(Time::from_secs_f64(0.5), Time::from_secs_f64(1.0), true)
+            .unwrap()
+            .map(|frame| frame.unwrap().0.as_secs_f64())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 15);
+        assert!(timestamps[0] >= 0.0 && timestamps[0] < 1.0 / 30.0);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // The decoder can be used for another range afterwards.
+        let timestamps = decoder
+            .decode_between(Time::from_secs_f64(1.0), Time::from_secs_f64(1.5), false)
+            .unwrap()
+            .map(|frame| frame.unwrap().0.as_secs_f64())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 15);
+        assert!(timestamps.iter().all(|ts| (1.0..1.5).contains(ts)));
+
+        assert_eq!(
+            decoder
+                .decode_between(Time::from_secs_f64(10.0), Time::from_secs_f64(11.0), false)
+                .unwrap()
+                .count(),
+            0
+        );
+        assert!(decoder
+            .decode_between(Time::from_secs_f64(1.0), Time::from_secs_f64(0.5), false)
+            .is_err());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_decode_clip() {
+        use crate::{Encoder, EncoderSettings};
+
+        let path = TempPath::new("clip.mp4");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false).with_key_frame_interval(10),
+        )
+        .unwrap();
+        testing::encode_frames(
+            &mut encoder,
+            0,
+            (0..30).map(|i| testing::filled_frame(i as u8 * 8)),
+        );
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        // The clip starts in the middle of a GOP, and has the same frames as the range.
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let expected = decoder
+            .decode_between(Time::from_secs_f64(0.5), Time::from_secs_f64(0.75), false)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(expected.len(), 8);
+        let (timestamps, clip) = decoder
+            .decode_clip(Time::from_secs_f64(0.5), 8, true)
+            .unwrap();
+        assert_eq!(clip.dim(), (8, 48, 64, 3));
+        assert!(clip.is_standard_layout());
+        for (index, (timestamp, frame)) in expected.iter().enumerate() {
+            assert_eq!(timestamps[index], *timestamp);
+            assert_eq!(clip.index_axis(ndarray::Axis(0), index), frame);
+        }
+
+        // Near the end, the clip is cut short, unless all frames are required.
+        let (timestamps, clip) = decoder
+            .decode_clip(Time::from_secs_f64(25.0 / 30.0), 10, false)
+            .unwrap();
+        assert_eq!(timestamps.len(), 5);
+        assert_eq!(clip.dim(), (5, 48, 64, 3));
+        assert!(matches!(
+            decoder.decode_clip(Time::from_secs_f64(25.0 / 30.0), 10, true),
+            Err(Error::ReadExhausted)
+        ));
+
+        // Into part of a larger tensor, which must have the shape of the frames.
+        let mut batch = Array4::<u8>::zeros((12, 48, 64, 3));
+        let timestamps = decoder
+            .decode_clip_into(
+                Time::from_secs_f64(0.5),
+                batch.slice_mut(s![4..12, .., .., ..]),
+                true,
+            )
+            .unwrap();
+        assert_eq!(timestamps.len(), 8);
+        assert!(batch
+            .slice(s![..4, .., .., ..])
+            .iter()
+            .all(|value| *value == 0));
+        assert_eq!(batch.index_axis(ndarray::Axis(0), 4), expected[0].1);
+        assert!(matches!(
+            decoder.decode_clip_into(
+                Time::from_secs_f64(0.5),
+                Array4::<u8>::zeros((2, 48, 64, 4)).view_mut(),
+                true,
+            ),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_frame_metadata_from_filter() {
+        use crate::{Encoder, EncoderSettings, RawFrameMetadata};
+
+        let path = TempPath::new("frame-metadata.mkv");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        for i in 0..5 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            // Entries set before encoding are not stored in the encoded stream.
+            encoder
+                .encode_raw_with_metadata(frame, &[("camera", "front")])
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator())
+            .unwrap()
+            .with_filter("signalstats")
+            .unwrap();
+        let metadata = decoder.decode_raw().unwrap().metadata();
+        assert!(metadata.contains_key("lavfi.signalstats.YAVG"));
+        assert!(!metadata.contains_key("camera"));
+    }
+
+    #[test]
+    fn test_detect_scene_changes() {
+        use crate::{Encoder, EncoderSettings};
+
+        let path = TempPath::new("scenes.mkv");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(320, 240, false),
+        )
+        .unwrap();
+        // Three shots of half a second at 30 fps, with a little change from frame to frame.
+        for i in 0..45 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 320, 240);
+            let level = [40, 200, 120][i as usize / 15] + (i % 3) as u8;
+            frame.data_mut(0).fill(level);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let mut num_scores = 0;
+        let scene_changes = decoder
+            .detect_scene_changes_with_scores(0.1, |_, score| {
+                assert!((0.0..=1.0).contains(&score));
+                num_scores += 1;
+            })
+            .unwrap();
+        assert_eq!(num_scores, 44);
+        assert_eq!(scene_changes.len(), 2);
+        for (scene_change, expected) in scene_changes.iter().zip([0.5, 1.0]) {
+            assert!((scene_change.as_secs_f64() - expected).abs() <= 1.0 / 30.0);
+        }
+    }
+
+    #[test]
+    fn test_decoder_analyze() {
+        use crate::{BlackParams, Encoder, EncoderSettings, FreezeParams};
+
+        let path = TempPath::new("analyze.mkv");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(320, 240, false),
+        )
+        .unwrap();
+        // A second of black, a second that gets brighter, and a second that stands still, at 30
+        // fps.
+        for i in 0..90 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 320, 240);
+            let level = match i {
+                0..=29 => 0,
+                30..=59 => 60 + (i as u8 - 30) * 4,
+                _ => 150,
+            };
+            frame.data_mut(0).fill(level);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let report = decoder
+            .analyze(AnalysisConfig {
+                detect_black: Some(BlackParams {
+                    luma_threshold: 0.1,
+                    min_duration: Time::from_millis(500),
+                }),
+                detect_freeze: Some(FreezeParams {
+                    difference_threshold: 0.005,
+                    min_duration: Time::from_millis(500),
+                }),
+            })
+            .unwrap();
+        let is_near = |time: &Time, expected: f64| (time.as_secs_f64() - expected).abs() <= 0.05;
+        assert_eq!(report.black.len(), 1);
+        assert!(is_near(&report.black[0].start, 0.0));
+        assert!(is_near(&report.black[0].end, 1.0));
+        // The black second stands still as well.
+        assert_eq!(report.frozen.len(), 2);
+        assert!(is_near(&report.frozen[0].start, 0.0));
+        assert!(is_near(&report.frozen[0].end, 1.0));
+        assert!(is_near(&report.frozen[1].start, 2.0));
+        assert!(is_near(&report.frozen[1].end, 3.0));
+    }
+
+    #[test]
+    fn test_builder_conflicts() {
+        use crate::HardwareDeviceType;
+
+        let source: Locator = std::path::PathBuf::from("does-not-exist.mp4").into();
+        assert!(matches!(
+            Decoder::builder(&source)
+                .hardware(HardwareDevice::new(HardwareDeviceType::Cuda))
+                .lenient()
+                .build(),
+            Err(Error::IncompatibleDecoderOptions(_))
+        ));
+        assert!(matches!(
+            Decoder::builder(&source).output_size(0, 480).build(),
+            Err(Error::InvalidResizeParameters)
+        ));
+    }
+
+    #[test]
+    fn test_low_latency_input_options() {
+        let options = low_latency_input_options(&Options::default());
+        assert_eq!(options.get("fflags"), Some("nobuffer"));
+        assert_eq!(options.get("probesize"), Some("32"));
+
+        let mut custom = Options::default();
+        custom.set("probesize", "4096");
+        custom.set("rtsp_transport", "tcp");
+        let options = low_latency_input_options(&custom);
+        assert_eq!(options.get("probesize"), Some("4096"));
+        assert_eq!(options.get("rtsp_transport"), Some("tcp"));
+        assert_eq!(options.get("analyzeduration"), Some("0"));
+    }
+
+    #[test]
+    fn test_decoder_builder() {
+        let path = TempPath::new("builder.mkv");
+        testing::write_test_video(&path.locator(), testing::frames(10));
+
+        let source: Locator = path.locator();
+        let mut decoder = Decoder::builder(&source)
+            .stream(0)
+            .output_format(AvPixel::YUV420P)
+            .crop(16, 8, 32, 32)
+            .output_size(16, 16)
+            .lenient()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(decoder.size(), (64, 48));
+        assert_eq!(decoder.size_out(), (16, 16));
+        let frame = decoder.decode_raw().unwrap();
+        assert_eq!(frame.format(), AvPixel::YUV420P);
+        assert_eq!((frame.width(), frame.height()), (16, 16));
+
+        assert!(Decoder::builder(&source).stream(1).build().is_err());
+        assert!(matches!(
+            Decoder::builder(&source).crop(48, 0, 32, 32).build(),
+            Err(Error::InvalidCropParameters)
+        ));
+    }
+
+    #[test]
+    fn test_detected_formats() {
+        let path = TempPath::new("detected.mkv");
+        testing::write_test_video(&path.locator(), testing::frames(10));
+
+        let decoder = Decoder::new(&path.locator()).unwrap();
+        let (name, long_name) = decoder.container_format();
+        assert!(name.contains("matroska"));
+        assert!(long_name.contains("Matroska"));
+        assert_eq!(decoder.codec_name(), "h264");
+        assert_eq!(decoder.pixel_format(), AvPixel::YUV420P);
+        assert!(decoder.bit_rate().is_some());
+        let debug = format!("{decoder:?}");
+        assert!(debug.contains("h264") && debug.contains("YUV420P"));
+    }
+
+    #[test]
+    fn test_new_with_input_format() {
+        use crate::{Encoder, EncoderSettings};
+
+        // An MP4 file with an extension that says nothing about its format.
+        let path = TempPath::new("input-format.dat");
+        let mut encoder = Encoder::new_with_format(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false),
+            "mp4",
+        )
+        .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder =
+            Decoder::new_with_input_format(&path.locator(), "mp4", &Options::default()).unwrap();
+        assert!(decoder.container_format().0.contains("mp4"));
+        assert_eq!(
+            decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+            10
+        );
+
+        match Decoder::new_with_input_format(&path.locator(), "mp5", &Options::default()) {
+            Err(Error::InputFormatNotFound { name, similar }) => {
+                assert_eq!(name, "mp5");
+                assert!(similar.iter().any(|format| format == "mp4"));
+            }
+            result => panic!("unexpected result: {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_health_discontinuity() {
+        use crate::{Encoder, EncoderSettings};
+
+        // 30 frames at 30 fps, with a gap of five seconds after the first 15.
+        let path = TempPath::new("health.mkv");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        for i in 0..30 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            let offset = if i < 15 { 0 } else { 150 };
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * (i + offset)),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        assert_eq!(
+            decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+            30
+        );
+        let health = decoder.health();
+        assert_eq!(health.discontinuities, 1);
+        assert_eq!(health.decode_errors, 0);
+        assert_eq!(health.packets, 30);
+        assert!(health.average_bitrate > 0.0);
+
+        // Gaps shorter than the threshold do not count.
+        let mut decoder = Decoder::new(&path.locator())
+            .unwrap()
+            .with_discontinuity_threshold(Duration::from_secs(10));
+        decoder.decode_raw_iter().take_while(Result::is_ok).count();
+        assert_eq!(decoder.health().discontinuities, 0);
+        decoder.reset_health();
+        assert_eq!(decoder.health().packets, 0);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_count_frames_exact() {
+        const NUM_FRAMES: u64 = 37;
+
+        let path = TempPath::new("count.mkv");
+        testing::write_test_video(&path.locator(), testing::frames(NUM_FRAMES as usize));
+
+        // Decoding continues where it was.
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let timestamps = (0..5)
+            .map(|_| decoder.decode().unwrap().0.as_secs_f64())
+            .collect::<Vec<_>>();
+        assert_eq!(decoder.count_frames_exact().unwrap(), NUM_FRAMES);
+        let (timestamp, _) = decoder.decode().unwrap();
+        assert!(timestamp.as_secs_f64() > timestamps[4]);
+
+        // Decoders without a source to reopen are rewound.
+        let mut decoder = Decoder::from_bytes(std::fs::read(&path).unwrap()).unwrap();
+        decoder.decode().unwrap();
+        assert_eq!(decoder.count_frames_exact().unwrap(), NUM_FRAMES);
+        let (timestamp, _) = decoder.decode().unwrap();
+        assert!((timestamp.as_secs_f64() - timestamps[0]).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_byte_progress() {
+        use crate::RawFrameMetadata;
+
+        let path = TempPath::new("bytes.mkv");
+        testing::write_test_video(&path.locator(), testing::frames(30));
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        let file = Decoder::new(&path.locator()).unwrap();
+        let memory = Decoder::from_bytes(std::fs::read(&path).unwrap()).unwrap();
+        for mut decoder in [file, memory] {
+            assert_eq!(decoder.total_bytes(), Some(size));
+            let mut bytes_read = decoder.bytes_read();
+            let mut offsets = Vec::new();
+            while let Ok(frame) = decoder.decode_raw() {
+                assert!(decoder.bytes_read() >= bytes_read);
+                bytes_read = decoder.bytes_read();
+                offsets.push(frame.byte_offset().unwrap());
+            }
+            assert_eq!(offsets.len(), 30);
+            assert!(offsets.iter().all(|offset| *offset < size));
+            assert_eq!(decoder.bytes_read(), size);
+        }
+    }
+
+    #[test]
+    fn test_follow_growing_file() {
+        use std::io::Write;
+
+        use crate::{Encoder, EncoderSettings};
+
+        const NUM_FRAMES: usize = 60;
+
+        let dir = TempPath::new("follow");
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("recording.ts");
+        let growing = dir.join("growing.ts");
+
+        testing::write_test_video(&recording.clone().into(), testing::frames(NUM_FRAMES));
+
+        // The first half of the recording is there when decoding starts, on a TS packet boundary.
+        let data = std::fs::read(&recording).unwrap();
+        let half = data.len() / 2 / 188 * 188;
+        std::fs::write(&growing, &data[..half]).unwrap();
+        let mut decoder = Decoder::new(&growing.clone().into())
+            .unwrap()
+            .follow_with_policy(FollowPolicy {
+                poll_interval: Duration::from_millis(10),
+                inactivity_timeout: Some(Duration::from_secs(2)),
+                timeout: Some(Duration::from_secs(30)),
+            })
+            .unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&growing)
+                .unwrap();
+            file.write_all(&data[half..]).unwrap();
+        });
+        let num_frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+        writer.join().unwrap();
+        assert_eq!(num_frames, NUM_FRAMES);
+
+        // Plain MP4 files cannot be followed.
+        let plain = dir.join("plain.mp4");
+        let mut encoder = Encoder::new(
+            &plain.clone().into(),
+            EncoderSettings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        encoder
+            .encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48))
+            .unwrap();
+        encoder.finish().unwrap();
+        drop(encoder);
+        assert!(matches!(
+            Decoder::new(&plain.into())
+                .unwrap()
+                .follow(Duration::from_millis(10)),
+            Err(Error::NotFollowable(_))
+        ));
+    }
+
+    #[test]
+    fn test_time_range() {
+        let range = TimeRange::new(100, 200, true);
+        assert_eq!(range.compare(99), std::cmp::Ordering::Less);
+        assert_eq!(range.compare(100), std::cmp::Ordering::Equal);
+        assert_eq!(range.compare(199), std::cmp::Ordering::Equal);
+        assert_eq!(range.compare(200), std::cmp::Ordering::Greater);
+        assert_eq!(range.timestamp(150), 50);
+        assert_eq!(TimeRange::new(100, 200, false).timestamp(150), 150);
+    }
+
+    #[test]
+    fn test_deinterlace_removes_combing() {
+        const WIDTH: u32 = 64;
+        const HEIGHT: u32 = 32;
+        const FRAMES: usize = 8;
+
+        // A moving bar, with the odd field a bit further along than the even field, like
+        // interlaced footage of a moving object.
+        let path = TempPath::new("interlaced.gray");
+        let mut data = Vec::new();
+        for i in 0..FRAMES {
+            for y in 0..HEIGHT as usize {
+                let bar = 4 * i + (y % 2) * 8;
+                data.extend((0..WIDTH as usize).map(|x| {
+                    if (bar..bar + 16).contains(&x) {
+                        255
+                    } else {
+                        0
+                    }
+                }));
+            }
+        }
+        std::fs::write(&path, data).unwrap();
+        let config = RawVideoConfig {
+            width: WIDTH,
+            height: HEIGHT,
+            pixel_format: AvPixel::GRAY8,
+            frame_rate: 25.0,
+        };
+
+        let decode = |deinterlace, mode| {
+            let decoder = Decoder::new_raw_video(&path.locator(), config)
+                .unwrap()
+                .with_deinterlace(deinterlace, mode)
+                .unwrap();
+            let frame_rate = decoder.frame_rate();
+            let (mut decoder, mut reader, stream_index) = decoder.into_parts();
+            let mut frames = Vec::new();
+            while let Ok(packet) = reader.read(stream_index) {
+                frames.extend(decoder.decode_raw(packet).unwrap());
+            }
+            while let Some(frame) = decoder.drain_raw().unwrap() {
+                frames.push(frame);
+            }
+            (frame_rate, frames)
+        };
+        // Difference of every row with the average of its neighbours, which is large where the
+        // fields do not line up.
+        let combing = |frames: &[RawFrame]| {
+            frames
+                .iter()
+                .map(|frame| {
+                    let stride = frame.stride(0);
+                    let data = frame.data(0);
+                    let pixel = |x: usize, y: usize| data[y * stride + x * 3] as i64;
+                    (1..HEIGHT as usize - 1)
+                        .flat_map(|y| (0..WIDTH as usize).map(move |x| (x, y)))
+                        .map(|(x, y)| (2 * pixel(x, y) - pixel(x, y - 1) - pixel(x, y + 1)).abs())
+                        .sum::<i64>()
+                })
+                .sum::<i64>()
+                / frames.len() as i64
+        };
+
+        let (frame_rate, never) = decode(Deinterlace::Never, DeinterlaceMode::Frame);
+        assert_eq!(frame_rate, 25.0);
+        assert_eq!(never.len(), FRAMES);
+        // Raw video has no interlacing flags, so nothing is deinterlaced automatically.
+        let (_, auto) = decode(Deinterlace::Auto, DeinterlaceMode::Frame);
+        assert_eq!(combing(&auto), combing(&never));
+        let (frame_rate, always) = decode(Deinterlace::Always, DeinterlaceMode::Frame);
+        assert_eq!(frame_rate, 25.0);
+        assert_eq!(always.len(), FRAMES);
+        assert!(combing(&always) < combing(&never) / 2);
+
+        let (frame_rate, bob) = decode(Deinterlace::Always, DeinterlaceMode::Field);
+        assert_eq!(frame_rate, 50.0);
+        assert!(bob.len() >= 2 * (FRAMES - 1));
+        assert!(combing(&bob) < combing(&never) / 2);
+        let timestamps = bob
+            .iter()
+            .map(|frame| frame.pts().unwrap())
+            .collect::<Vec<_>>();
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_u16_preserves_10_bit_gradient() {
+        const WIDTH: u32 = 1024;
+        const HEIGHT: u32 = 2;
+
+        // Horizontal 10-bit luma gradient over the full limited range (64 to 940), no chroma.
+        let mut frame = RawFrame::new(AvPixel::YUV420P10LE, WIDTH, HEIGHT);
+        for plane in 0..3 {
+            let stride = frame.stride(plane);
+            let plane_width = frame.plane_width(plane) as usize;
+            let data = frame.data_mut(plane);
+            for row in data.chunks_mut(stride) {
+                for (x, value) in row.chunks_exact_mut(2).take(plane_width).enumerate() {
+                    let level = if plane == 0 {
+                        64 + (x as u16 * 876) / (WIDTH as u16 - 1)
+                    } else {
+                        512
+                    };
+                    value.copy_from_slice(&level.to_le_bytes());
+                }
+            }
+        }
+
+        let mut scaler = new_scaler(
+            AvPixel::YUV420P10LE,
+            (WIDTH, HEIGHT),
+            None,
+            FRAME_PIXEL_FORMAT_U16,
+            (WIDTH, HEIGHT),
+            AvScalerFlags::AREA | AvScalerFlags::ACCURATE_RND,
+            (ColorSpace::BT470BG, ColorSpace::BT470BG),
+        )
+        .unwrap();
+        let mut frame_scaled = scaler.run(&frame).unwrap();
+
+        let frame = convert_frame_to_ndarray_u16(&mut frame_scaled).unwrap();
+        assert_eq!(frame.dim(), (HEIGHT as usize, WIDTH as usize, 3));
+        let levels = frame
+            .slice(ndarray::s![0, .., 0])
+            .iter()
+            .copied()
+            .collect::<std::collections::HashSet<u16>>();
+        assert!(levels.len() > 256);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_decode_u16_from_10_bit_stream() {
+        use crate::{Encoder, EncoderSettings};
+
+        let (width, height) = (64, 48);
+        let settings = EncoderSettings::for_hevc_custom(
+            width,
+            height,
+            PixelFormat::YUV420P10LE,
+            Options::new_hevc(),
+        );
+        let (mut encoder, sink) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        // Gray with a horizontal gradient in steps of a quarter of an 8-bit level.
+        let frame =
+            FrameU16::from_shape_fn((height, width, 3), |(_, x, _)| 16384 + (x as u16) * 64);
+        for i in 0..5 {
+            let timestamp = Time::new(Some(i), AvRational::new(1, 30));
+            encoder.encode_u16(&frame, &timestamp).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::from_bytes(sink.into_bytes()).unwrap();
+        assert_eq!(decoder.pixel_format(), PixelFormat::YUV420P10LE);
+        let (_, decoded) = decoder.decode_u16().unwrap();
+        assert_eq!(decoded.dim(), (height, width, 3));
+        // The lower 8 bits carry detail, which they would not if the frame went through 8 bits.
+        assert!(decoded.iter().any(|value| value & 0xff != 0));
+        let row = decoded.slice(s![height / 2, .., 1]);
+        assert!(row
+            .iter()
+            .zip(frame.slice(s![height / 2, .., 1]))
+            .all(|(decoded, original)| decoded.abs_diff(*original) < 1024));
+    }
+
+    #[test]
+    fn test_decode_from_slice() {
+        use crate::{Encoder, EncoderSettings};
+
+        let settings = EncoderSettings::for_h264_yuv420p(64, 48, false);
+        let (mut encoder, sink) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        encoder.finish().unwrap();
+        let bytes = sink.into_bytes();
+
+        let mut decoder = Decoder::from_slice(&bytes).unwrap();
+        let timestamps = std::iter::from_fn(|| decoder.decode_raw().ok())
+            .map(|frame| frame.pts())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 10);
+
+        let mut decoder = Decoder::from_bytes(bytes.to_vec()).unwrap();
+        let timestamps_owned = std::iter::from_fn(|| decoder.decode_raw().ok())
+            .map(|frame| frame.pts())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, timestamps_owned);
+    }
+}
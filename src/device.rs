@@ -0,0 +1,419 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::path::PathBuf;
+
+use crate::crop::Crop;
+use crate::error::Error;
+use crate::ffi;
+use crate::init;
+use crate::io::Locator;
+use crate::options::Options;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of capture device, which decides the ffmpeg device demuxer that is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceKind {
+    /// Video4Linux2 on Linux. Devices are identified by their path, like `/dev/video0`.
+    V4l2,
+    /// AVFoundation on macOS. Devices are identified by their index or name, like `0` or
+    /// `FaceTime HD Camera`.
+    AvFoundation,
+    /// DirectShow on Windows. Devices are identified by their name, like `Integrated Camera`.
+    DirectShow,
+    /// Any other device demuxer, by name.
+    Other(String),
+}
+
+impl DeviceKind {
+    /// Get the kind of capture device of the current platform, if there is one.
+    pub fn platform_default() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Some(DeviceKind::V4l2)
+        } else if cfg!(target_os = "macos") {
+            Some(DeviceKind::AvFoundation)
+        } else if cfg!(target_os = "windows") {
+            Some(DeviceKind::DirectShow)
+        } else {
+            None
+        }
+    }
+
+    /// Get the name of the device demuxer.
+    pub(crate) fn format_name(&self) -> &str {
+        match self {
+            DeviceKind::V4l2 => "video4linux2",
+            DeviceKind::AvFoundation => "avfoundation",
+            DeviceKind::DirectShow => "dshow",
+            DeviceKind::Other(name) => name,
+        }
+    }
+}
+
+/// Describes a capture device to read from, along with device-specific options like the capture
+/// size.
+///
+/// # Example
+///
+/// ```ignore
+/// let device = DeviceSource::new(DeviceKind::V4l2, "/dev/video0")
+///     .with_video_size(1280, 720)
+///     .with_framerate(30)
+///     .with_pixel_format("yuyv422");
+/// let mut decoder = Decoder::new_from_device(&device).unwrap();
+/// ```
+pub struct DeviceSource {
+    /// Kind of device.
+    pub kind: DeviceKind,
+    /// Identifier of the device, in the format of the device kind (see [`DeviceKind`]).
+    pub identifier: String,
+    /// Options to pass to the device demuxer.
+    pub options: Options<'static>,
+}
+
+impl DeviceSource {
+    /// Create a device source.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of device.
+    /// * `identifier` - Identifier of the device, like `/dev/video0`. See [`DeviceKind`].
+    pub fn new(kind: DeviceKind, identifier: &str) -> Self {
+        Self {
+            kind,
+            identifier: identifier.to_string(),
+            options: Options::default(),
+        }
+    }
+
+    /// Set the capture size (`video_size` option).
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width to capture.
+    /// * `height` - Height to capture.
+    pub fn with_video_size(self, width: u32, height: u32) -> Self {
+        self.with_option("video_size", &format!("{width}x{height}"))
+    }
+
+    /// Set the capture frame rate (`framerate` option).
+    ///
+    /// # Arguments
+    ///
+    /// * `framerate` - Frames per second to capture.
+    pub fn with_framerate(self, framerate: u32) -> Self {
+        self.with_option("framerate", &framerate.to_string())
+    }
+
+    /// Set the pixel format to capture in (`pixel_format` option), like `yuyv422`. Use
+    /// [`DeviceSource::with_option`] with `input_format` to capture compressed video (like `mjpeg`)
+    /// from Video4Linux2 devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel_format` - Name of the pixel format.
+    pub fn with_pixel_format(self, pixel_format: &str) -> Self {
+        self.with_option("pixel_format", pixel_format)
+    }
+
+    /// Set any other option of the device demuxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    /// * `value` - Value of the option.
+    pub fn with_option(mut self, key: &str, value: &str) -> Self {
+        self.options.set(key, value);
+        self
+    }
+
+    /// Get the options to pass to the device demuxer. Video4Linux2 devices report the time at which
+    /// the device captured each frame, which is turned into wall-clock time unless the
+    /// `timestamps` option is set otherwise.
+    pub(crate) fn demuxer_options(&self) -> Options<'static> {
+        let mut options = self.options.to_owned_options();
+        if self.kind == DeviceKind::V4l2 && options.get("timestamps").is_none() {
+            options.set("timestamps", "abs");
+        }
+
+        options
+    }
+
+    /// Whether or not the timestamps of the frames of the device are wall-clock times (relative to
+    /// the Unix epoch), given the options that are passed to the device demuxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options passed to the device demuxer.
+    pub(crate) fn has_wallclock_timestamps(&self, options: &Options) -> bool {
+        self.kind == DeviceKind::V4l2
+            && matches!(options.get("timestamps"), Some("abs") | Some("mono2abs"))
+    }
+
+    /// Get the locator to pass to the device demuxer. DirectShow expects the device name to be
+    /// prefixed with the media type.
+    pub(crate) fn locator(&self) -> Locator {
+        match self.kind {
+            DeviceKind::DirectShow if !self.identifier.contains('=') => {
+                Locator::Path(PathBuf::from(format!("video={}", self.identifier)))
+            }
+            _ => Locator::Path(PathBuf::from(&self.identifier)),
+        }
+    }
+}
+
+/// Describes a capture device that is available.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureDevice {
+    /// Kind of device.
+    pub kind: DeviceKind,
+    /// Identifier of the device, to use with [`DeviceSource::new`].
+    pub identifier: String,
+    /// Human-readable name of the device.
+    pub description: String,
+}
+
+/// List the capture devices of the current platform. See [`list_capture_devices_for`].
+pub fn list_capture_devices() -> Result<Vec<CaptureDevice>> {
+    match DeviceKind::platform_default() {
+        Some(kind) => list_capture_devices_for(&kind),
+        None => Err(Error::DeviceNotSupported(
+            "no capture devices on this platform".to_string(),
+        )),
+    }
+}
+
+/// List the capture devices of a kind. Not all device demuxers support listing their devices
+/// (AVFoundation does not, for example), in which case this fails with
+/// [`Error::DeviceNotSupported`].
+///
+/// # Arguments
+///
+/// * `kind` - Kind of device to list.
+pub fn list_capture_devices_for(kind: &DeviceKind) -> Result<Vec<CaptureDevice>> {
+    init::initialize()?;
+
+    let format = kind.format_name();
+    let sources = ffi::list_input_sources(format).map_err(|err| match err {
+        ffmpeg::Error::DemuxerNotFound => Error::DeviceNotSupported(format.to_string()),
+        ffmpeg::Error::Other {
+            errno: ffmpeg::util::error::ENOSYS,
+        } => Error::DeviceNotSupported(format!("listing {format} devices")),
+        err => err.into(),
+    })?;
+
+    Ok(sources
+        .into_iter()
+        .map(|(identifier, description)| CaptureDevice {
+            kind: kind.clone(),
+            identifier,
+            description,
+        })
+        .collect())
+}
+
+/// Rectangular region of a display, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// Horizontal offset of the region.
+    pub x: u32,
+    /// Vertical offset of the region.
+    pub y: u32,
+    /// Width of the region.
+    pub width: u32,
+    /// Height of the region.
+    pub height: u32,
+}
+
+impl Rect {
+    /// Create a region.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the region.
+    /// * `y` - Vertical offset of the region.
+    /// * `width` - Width of the region.
+    /// * `height` - Height of the region.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Get the region as a crop region.
+    pub(crate) fn to_crop(self) -> Crop {
+        Crop::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Describes the screen to capture with [`crate::Decoder::new_screen_capture`].
+///
+/// Screens are captured with `x11grab` on Linux, `gdigrab` on Windows and `avfoundation` on
+/// macOS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenCaptureConfig {
+    /// Display to capture, in the format of the platform: like `:0.0` for `x11grab`, `desktop`
+    /// for `gdigrab` or `Capture screen 0` for `avfoundation`.
+    pub display: String,
+    /// Region of the display to capture, or `None` to capture the whole display.
+    pub region: Option<Rect>,
+    /// Frames per second to capture.
+    pub frame_rate: u32,
+    /// Whether or not to draw the mouse cursor.
+    pub capture_cursor: bool,
+}
+
+impl Default for ScreenCaptureConfig {
+    fn default() -> Self {
+        let display = if cfg!(target_os = "windows") {
+            "desktop"
+        } else if cfg!(target_os = "macos") {
+            "Capture screen 0"
+        } else {
+            ":0.0"
+        };
+
+        Self {
+            display: display.to_string(),
+            region: None,
+            frame_rate: 30,
+            capture_cursor: true,
+        }
+    }
+}
+
+impl ScreenCaptureConfig {
+    /// Get the kind of screen capture device of the current platform, if there is one.
+    pub(crate) fn platform_kind() -> Option<DeviceKind> {
+        if cfg!(target_os = "linux") {
+            Some(DeviceKind::Other("x11grab".to_string()))
+        } else if cfg!(target_os = "macos") {
+            Some(DeviceKind::AvFoundation)
+        } else if cfg!(target_os = "windows") {
+            Some(DeviceKind::Other("gdigrab".to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Whether or not a screen capture device can capture a region itself. If it cannot, frames
+    /// need to be cropped to the region after decoding instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of screen capture device.
+    pub(crate) fn supports_region(kind: &DeviceKind) -> bool {
+        matches!(kind.format_name(), "x11grab" | "gdigrab")
+    }
+
+    /// Map the configuration to a device source with the options of the screen capture device.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of screen capture device.
+    /// * `region` - Region to capture, or `None` to capture the whole display. Must be `None` if
+    ///   the device does not support capturing a region.
+    pub(crate) fn device_source(&self, kind: &DeviceKind, region: Option<Rect>) -> DeviceSource {
+        let draw_mouse = if self.capture_cursor { "1" } else { "0" };
+        let mut device =
+            DeviceSource::new(kind.clone(), &self.display).with_framerate(self.frame_rate);
+        device = match kind.format_name() {
+            "avfoundation" => device.with_option("capture_cursor", draw_mouse),
+            _ => device.with_option("draw_mouse", draw_mouse),
+        };
+
+        if let Some(region) = region {
+            let (offset_x, offset_y) = match kind.format_name() {
+                "x11grab" => ("grab_x", "grab_y"),
+                _ => ("offset_x", "offset_y"),
+            };
+            device = device
+                .with_video_size(region.width, region.height)
+                .with_option(offset_x, &region.x.to_string())
+                .with_option(offset_y, &region.y.to_string());
+        }
+
+        device
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_source_options() {
+        let device = DeviceSource::new(DeviceKind::V4l2, "/dev/video0")
+            .with_video_size(1280, 720)
+            .with_framerate(30)
+            .with_pixel_format("yuyv422");
+        assert_eq!(device.options.get("video_size"), Some("1280x720"));
+        assert_eq!(device.options.get("framerate"), Some("30"));
+        assert_eq!(device.options.get("pixel_format"), Some("yuyv422"));
+        assert_eq!(device.locator().to_string(), "/dev/video0");
+    }
+
+    #[test]
+    fn test_device_wallclock_timestamps() {
+        let device = DeviceSource::new(DeviceKind::V4l2, "/dev/video0");
+        let options = device.demuxer_options();
+        assert_eq!(options.get("timestamps"), Some("abs"));
+        assert!(device.has_wallclock_timestamps(&options));
+
+        let device = device.with_option("timestamps", "default");
+        let options = device.demuxer_options();
+        assert_eq!(options.get("timestamps"), Some("default"));
+        assert!(!device.has_wallclock_timestamps(&options));
+
+        let device = DeviceSource::new(DeviceKind::AvFoundation, "0");
+        let options = device.demuxer_options();
+        assert_eq!(options.get("timestamps"), None);
+        assert!(!device.has_wallclock_timestamps(&options));
+    }
+
+    #[test]
+    fn test_direct_show_locator() {
+        let device = DeviceSource::new(DeviceKind::DirectShow, "Integrated Camera");
+        assert_eq!(device.locator().to_string(), "video=Integrated Camera");
+        let device = DeviceSource::new(DeviceKind::DirectShow, "video=Camera:audio=Microphone");
+        assert_eq!(
+            device.locator().to_string(),
+            "video=Camera:audio=Microphone"
+        );
+    }
+
+    #[test]
+    fn test_screen_capture_device_source() {
+        let config = ScreenCaptureConfig {
+            display: ":0.0".to_string(),
+            region: None,
+            frame_rate: 25,
+            capture_cursor: false,
+        };
+        let region = Some(Rect::new(100, 50, 640, 480));
+
+        let kind = DeviceKind::Other("x11grab".to_string());
+        let device = config.device_source(&kind, region);
+        assert_eq!(device.locator().to_string(), ":0.0");
+        assert_eq!(device.options.get("framerate"), Some("25"));
+        assert_eq!(device.options.get("draw_mouse"), Some("0"));
+        assert_eq!(device.options.get("video_size"), Some("640x480"));
+        assert_eq!(device.options.get("grab_x"), Some("100"));
+        assert_eq!(device.options.get("grab_y"), Some("50"));
+
+        let kind = DeviceKind::Other("gdigrab".to_string());
+        let device = config.device_source(&kind, region);
+        assert_eq!(device.options.get("offset_x"), Some("100"));
+        assert_eq!(device.options.get("offset_y"), Some("50"));
+
+        let device = config.device_source(&DeviceKind::AvFoundation, None);
+        assert_eq!(device.options.get("capture_cursor"), Some("0"));
+        assert_eq!(device.options.get("video_size"), None);
+        assert!(!ScreenCaptureConfig::supports_region(
+            &DeviceKind::AvFoundation
+        ));
+    }
+}
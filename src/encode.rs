@@ -1,14 +1,20 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
 use ffmpeg::codec::codec::Codec as AvCodec;
 use ffmpeg::codec::encoder::video::Encoder as AvEncoder;
 use ffmpeg::codec::encoder::video::Video as AvVideo;
 use ffmpeg::codec::flag::Flags as AvCodecFlags;
-use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::packet::side_data::Type as AvPacketSideDataType;
+use ffmpeg::codec::packet::{Flags as AvPacketFlags, Packet as AvPacket};
+use ffmpeg::codec::threading::{Config as AvThreadingConfig, Type as AvThreadingType};
+use ffmpeg::codec::Parameters as AvCodecParameters;
 use ffmpeg::codec::{Context as AvContext, Id as AvCodecId};
-use ffmpeg::ffi::AVPixelFormat::*;
-use ffmpeg::format::flag::Flags as AvFormatFlags;
-use ffmpeg::software::scaling::context::Context as AvScaler;
+use ffmpeg::ffi::{AVCodecContext, AVFormatContext};
+use ffmpeg::format::context::Output as AvOutput;
+use ffmpeg::media::Type as AvMediaType;
 use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
 use ffmpeg::util::error::EAGAIN;
 use ffmpeg::util::format::Pixel as AvPixel;
@@ -16,17 +22,49 @@ use ffmpeg::util::mathematics::rescale::TIME_BASE;
 use ffmpeg::util::picture::Type as AvFrameType;
 use ffmpeg::Error as AvError;
 use ffmpeg::Rational as AvRational;
+use ffmpeg::{Dictionary as AvDictionary, DictionaryRef as AvDictionaryRef};
 
 use crate::{
-    ffi::{codec_context_as, get_encoder_time_base},
-    frame::FRAME_PIXEL_FORMAT,
-    io::{private::Write, Writer},
-    options::Options,
-    Error, Locator, PixelFormat, RawFrame,
+    attachment::{add_attachment, add_cover_art, ImageFormat},
+    capabilities::{encoders_for, pick_encoder, validate_encoder_options, CodecId, EncoderInfo},
+    chapter::write_chapters,
+    convert::ScaleColorSpaces,
+    crop::{Crop, CropScaler},
+    error::{ErrorContext, WithContext},
+    faststart::FaststartPath,
+    ffi::{
+        clear_codec_tag, codec_context_as, codec_context_extradata, copy_frame_side_data,
+        encoder_delay, encoder_open_with, encoder_rate_control, encoder_reorders_frames,
+        encoder_set_color_properties, encoder_set_rate_control, find_best_pixel_format,
+        get_encoder_time_base, output_format_supports_codec, output_set_bitexact,
+        parameters_extradata, pixel_format_is_high_bit_depth, pixel_format_layout,
+        set_stream_disposition, BitstreamFilter as AvBitstreamFilter,
+    },
+    filter::{chain_filters, FilterGraph, FilterInput, Transform},
+    io::{private::Write, MemorySink, Writer, WriterBuilder},
+    options::{ignored_option_keys, MovFlag, Options},
+    ratecontrol::RateControl,
+    segment::{CompletedSegment, SegmentLimit, SegmentPolicy, Segmenter},
+    sidedata::{
+        add_stream_hdr_metadata, HdrMetadata, RawFrameMetadata, RawFrameSideData, SideData,
+    },
+    stamp::{DebugStamp, StampConfig},
+    tag::{set_tag, TagTarget},
+    Chapter, CodecParams, ColorSpace, ColorSpec, DispositionFlag, EncodedPacket, Error, Locator,
+    Overlay, Packet, PixelFormat, RawFrame, StreamInfo, Time, Url,
 };
 
 #[cfg(feature = "ndarray")]
-use crate::{ffi::convert_ndarray_to_frame, Frame, Time};
+use ffmpeg::ffi::{AVPixelFormat::*, AV_PIX_FMT_GRAY16, AV_PIX_FMT_RGB48};
+
+#[cfg(feature = "ndarray")]
+use crate::{
+    ffi::convert_ndarray_to_frame, frame::convert_ndarray_f32_to_rgb24, Frame, Frame32, FrameU16,
+    Normalization, YuvFrame,
+};
+
+#[cfg(feature = "image")]
+use crate::frame::unpack_rows;
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -51,15 +89,51 @@ type Result<T> = std::result::Result<T, Error>;
 ///     );
 /// ```
 pub struct Encoder {
-    writer: Writer,
+    /// Primary output, or `None` if encoded packets go to the packet sink instead.
+    writer: Option<Writer>,
     writer_stream_index: usize,
+    packet_sink: Option<PacketSink>,
+    segmenter: Option<Segmenter>,
+    additional_outputs: Vec<AdditionalOutput>,
     encoder: AvEncoder,
+    /// Settings the encoder was opened with, to open it again at another size (see
+    /// [`Encoder::reconfigure`]).
+    settings: Settings<'static>,
+    /// Whether or not the outputs need the codec headers out of band.
+    global_header: bool,
     encoder_time_base: AvRational,
-    interleaved: bool,
-    scaler: AvScaler,
+    interleaving: Interleaving,
+    /// Whether or not the encoder reorders frames (with B-frames).
+    reorders_frames: bool,
+    /// Scalers for the pixel formats of the input frames seen so far, so that alternating between
+    /// formats does not recreate the scaler for every frame.
+    scalers: Vec<CropScaler>,
     scaler_width: u32,
     scaler_height: u32,
-    frame_count: u64,
+    /// Whether the transforms swap the width and height of the input frames (see
+    /// [`Encoder::with_transform`]).
+    swaps_dimensions: bool,
+    crop: Option<Crop>,
+    overlay: Option<OverlaySource>,
+    debug_stamp: Option<DebugStamp>,
+    /// Description of the filter graph, and the graph itself once the first frame came in.
+    filter_spec: Option<String>,
+    filter: Option<FilterGraph>,
+    key_frame_interval: Option<u64>,
+    force_key_frame: bool,
+    key_frame_schedule: Option<KeyFrameSchedule>,
+    pacer: Option<Pacer>,
+    flush_schedule: Option<FlushSchedule>,
+    frames_encoded: u64,
+    packets_written: u64,
+    packet_stats: PacketStats,
+    packet_durations: PacketDurations,
+    reconnect: Option<Reconnect>,
+    drop_timeout: Option<Duration>,
+    attached_pictures: Vec<AvPacket>,
+    /// Indices of the output streams of packets that were encoded elsewhere.
+    preencoded_streams: Vec<usize>,
+    ignored_options: Vec<String>,
     have_written_header: bool,
     have_written_trailer: bool,
 }
@@ -67,6 +141,13 @@ pub struct Encoder {
 impl Encoder {
     const KEY_FRAME_INTERVAL: u64 = 12;
 
+    /// Default maximum amount of time that finishing the output may take when the encoder is
+    /// dropped.
+    const DEFAULT_DROP_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Maximum key frame interval (in seconds) that RTMP ingest servers accept.
+    const RTMP_MAX_KEY_FRAME_INTERVAL_SECS: u64 = 2;
+
     /// Create a new encoder that writes to the specified file.
     ///
     /// # Arguments
@@ -120,344 +201,6244 @@ impl Encoder {
         )
     }
 
-    /// Turn the encoder into an interleaved version, that automatically reorders packets when
-    /// necessary.
-    pub fn interleaved(mut self) -> Self {
-        self.interleaved = true;
-        self
+    /// Create a new encoder with a writer configured through a [`WriterBuilder`], for typed
+    /// container settings like `faststart`.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - Builder of the writer to encode to.
+    /// * `settings` - Encoder settings to use.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let encoder = Encoder::new_with_builder(
+    ///     WriterBuilder::new(&PathBuf::from("my_file.mp4").into()).faststart(),
+    ///     Settings::for_h264_yuv420p(1280, 720, false),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_with_builder(builder: WriterBuilder, settings: Settings) -> Result<Self> {
+        Self::from_writer(builder.build()?, settings)
     }
 
-    /// Get encoder time base.
-    #[inline]
-    pub fn time_base(&self) -> AvRational {
-        self.encoder_time_base
+    /// Create a new encoder that writes to any type that implements [`std::io::Write`] and
+    /// [`std::io::Seek`].
+    ///
+    /// # Arguments
+    ///
+    /// * `io` - Stream to encode to.
+    /// * `settings` - Encoder settings to use.
+    /// * `format` - Container format to use.
+    pub fn from_io(
+        io: impl std::io::Write + std::io::Seek + Send + 'static,
+        settings: Settings,
+        format: &str,
+    ) -> Result<Self> {
+        Self::from_writer(Writer::from_io(io, format)?, settings)
     }
 
-    /// Encode a single `ndarray` frame.
+    /// Create a new encoder that writes to any type that implements [`std::io::Write`] and
+    /// [`std::io::Seek`] with the given muxer options.
     ///
     /// # Arguments
     ///
-    /// * `frame` - Frame to encode in `HWC` format and standard layout.
-    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
-    ///   the output will be timed correctly.
-    #[cfg(feature = "ndarray")]
-    pub fn encode(&mut self, frame: &Frame, source_timestamp: &Time) -> Result<()> {
-        let (height, width, channels) = frame.dim();
-        let pixel_format = match channels {
-            3 => AV_PIX_FMT_RGB24,
-            4 => AV_PIX_FMT_BGRA,
-            _ => return Err(Error::InvalidFrameFormat),
-        };
-
-        if height != self.scaler_height as usize || width != self.scaler_width as usize {
-            return Err(Error::InvalidFrameFormat);
-        }
-
-        let mut frame = convert_ndarray_to_frame(frame, pixel_format).map_err(Error::BackendError)?;
+    /// * `io` - Stream to encode to.
+    /// * `settings` - Encoder settings to use.
+    /// * `format` - Container format to use.
+    /// * `options` - The muxer options.
+    pub fn from_io_with_options(
+        io: impl std::io::Write + std::io::Seek + Send + 'static,
+        settings: Settings,
+        format: &str,
+        options: &Options,
+    ) -> Result<Self> {
+        Self::from_writer(Writer::from_io_with_options(io, format, options)?, settings)
+    }
 
-        frame.set_pts(
-            source_timestamp
-                .aligned_with_rational(self.encoder_time_base)
-                .into_value(),
-        );
+    /// Create a new encoder that writes to memory. The encoded container bytes can be retrieved
+    /// through the returned [`MemorySink`] after calling [`Encoder::finish`].
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Encoder settings to use.
+    /// * `format` - Container format to use.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (mut encoder, sink) =
+    ///     Encoder::new_in_memory(Settings::for_h264_yuv420p(800, 600, false), "mp4").unwrap();
+    /// // Encode some frames...
+    /// encoder.finish().unwrap();
+    /// let bytes = sink.into_bytes();
+    /// ```
+    pub fn new_in_memory(settings: Settings, format: &str) -> Result<(Self, MemorySink)> {
+        let (writer, sink) = Writer::new_in_memory(format)?;
+        Ok((Self::from_writer(writer, settings)?, sink))
+    }
 
-        self.encode_raw(frame)
+    /// Create a new encoder that writes to memory with the given muxer options.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Encoder settings to use.
+    /// * `format` - Container format to use.
+    /// * `options` - The muxer options.
+    pub fn new_in_memory_with_options(
+        settings: Settings,
+        format: &str,
+        options: &Options,
+    ) -> Result<(Self, MemorySink)> {
+        let (writer, sink) = Writer::new_in_memory_with_options(format, options)?;
+        Ok((Self::from_writer(writer, settings)?, sink))
     }
 
-    /// Encode a single raw frame.
+    /// Create a new encoder that streams to an RTMP server, such as the ingest servers of Twitch
+    /// or YouTube.
+    ///
+    /// The stream is muxed into FLV and written interleaved, and B-frames are disabled. Ingest
+    /// servers require regular key frames, so the settings must have a key frame interval of at
+    /// most two seconds (see [`Settings::with_key_frame_interval`] and
+    /// [`Settings::force_keyframe_every`]). Connection failures are
+    /// reported as [`Error::ConnectionFailed`]. If the connection drops, the encoder does not
+    /// reconnect (see [`Encoder::new_rtmp_with_retry_policy`]).
+    ///
+    /// Note that only video is streamed. Some ingest servers require an audio stream as well.
     ///
     /// # Arguments
     ///
-    /// * `frame` - Frame to encode.
-    pub fn encode_raw(&mut self, frame: RawFrame) -> Result<()> {
-        if frame.width() != self.scaler_width
-            || frame.height() != self.scaler_height
-            || (frame.format() != AvPixel::RGB24 && frame.format() != AvPixel::BGRA)
-        {
-            return Err(Error::InvalidFrameFormat);
-        }
+    /// * `url` - RTMP URL to stream to, including the stream key.
+    /// * `settings` - Encoder settings to use.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, true).with_key_frame_interval(60);
+    /// let mut encoder = Encoder::new_rtmp(
+    ///     &Url::parse("rtmp://live.twitch.tv/app/my_stream_key").unwrap(),
+    ///     settings,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_rtmp(url: &Url, settings: Settings) -> Result<Self> {
+        Self::new_rtmp_with_retry_policy(url, settings, RetryPolicy::never())
+    }
 
-        // Write file header if we hadn't done that yet.
-        if !self.have_written_header {
-            self.writer.write_header()?;
-            self.have_written_header = true;
-        }
+    /// Create a new encoder that streams to an RTMP server, and reconnects according to the
+    /// given retry policy when the connection drops. See [`Encoder::new_rtmp`].
+    ///
+    /// After reconnecting, packets are dropped until the next key frame, which the encoder forces
+    /// right away. The stream on the new connection starts at that key frame, with timestamps that
+    /// start at zero again.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - RTMP URL to stream to, including the stream key.
+    /// * `settings` - Encoder settings to use.
+    /// * `retry_policy` - How to reconnect when the connection drops.
+    pub fn new_rtmp_with_retry_policy(
+        url: &Url,
+        settings: Settings,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        Self::new_rtmp_with_options(url, settings, &Options::default(), retry_policy)
+    }
 
-        // Reformat frame to target pixel format.
-        let mut frame = self.scale(frame)?;
-        // Producer key frame every once in a while
-        if self.frame_count % Self::KEY_FRAME_INTERVAL == 0 {
-            frame.set_kind(AvFrameType::I);
+    /// Create a new encoder that streams to an RTMP server with custom options for the ffmpeg
+    /// backend (like `rtmp_live` or `rtmp_buffer`), and reconnects according to the given retry
+    /// policy when the connection drops. The options are passed on every connection. See
+    /// [`Encoder::new_rtmp_with_retry_policy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - RTMP URL to stream to, including the stream key.
+    /// * `settings` - Encoder settings to use.
+    /// * `options` - Options to pass on.
+    /// * `retry_policy` - How to reconnect when the connection drops.
+    pub fn new_rtmp_with_options(
+        url: &Url,
+        mut settings: Settings,
+        options: &Options,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        // Key frame intervals are counted in frames, so the limit depends on the frame rate.
+        let frame_rate = settings
+            .frame_rate
+            .unwrap_or(AvRational::new(Settings::FRAME_RATE, 1));
+        let max_key_frame_interval =
+            (Self::RTMP_MAX_KEY_FRAME_INTERVAL_SECS as f64 * f64::from(frame_rate)).floor() as u64;
+        let max_key_frame_every = Duration::from_secs(Self::RTMP_MAX_KEY_FRAME_INTERVAL_SECS);
+        let key_frames_valid = match settings.key_frame_every {
+            Some(interval) => !interval.is_zero() && interval <= max_key_frame_every,
+            None => matches!(
+                settings.key_frame_interval,
+                Some(interval) if interval > 0 && interval <= max_key_frame_interval
+            ),
+        };
+        if !key_frames_valid {
+            return Err(Error::InvalidKeyFrameInterval);
         }
+        settings.max_b_frames = Some(0);
 
-        self.encoder
-            .send_frame(&frame)
-            .map_err(Error::BackendError)?;
+        let reconnect = Reconnect::new(Locator::Url(url.clone()), options, retry_policy);
+        let writer = reconnect.connect()?;
+        let mut encoder = Self::from_writer(writer, settings)?.interleaved();
+        encoder.reconnect = Some(reconnect);
 
-        if let Some(packet) = self.encoder_receive_packet()? {
-            self.write(packet)?;
-        }
-
-        Ok(())
+        Ok(encoder)
     }
 
-    /// Signal to the encoder that writing has finished. This will cause any packets in the encoder
-    /// to be flushed and a trailer to be written if the container format has one.
+    /// Create a new encoder that produces an HLS stream. `dest` is the path of the playlist, and
+    /// segments are written according to [`HlsOutput::path_pattern`].
     ///
-    /// Note: If you don't call this function before dropping the encoder, it will be called
-    /// automatically. This will block the caller thread. Any errors cannot be propagated in this
-    /// case.
-    pub fn finish(&mut self) -> Result<()> {
-        if self.have_written_header && !self.have_written_trailer {
-            self.have_written_trailer = true;
-            self.flush()?;
-            self.writer.write_trailer()?;
-        }
+    /// Key frames are forced on segment boundaries, so that each segment can be decoded
+    /// independently. The playlist is only finalized when [`Encoder::finish`] is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - Locator of the playlist to write.
+    /// * `settings` - Encoder settings to use.
+    /// * `hls_output` - HLS segment and playlist configuration.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let hls_output = HlsOutput {
+    ///     segment_duration: Duration::from_secs(2),
+    ///     playlist_size: 5,
+    ///     delete_old: true,
+    ///     path_pattern: "/var/www/live/segment_%05d.ts".to_string(),
+    /// };
+    /// let mut encoder = Encoder::new_hls(
+    ///     &PathBuf::from("/var/www/live/playlist.m3u8").into(),
+    ///     Settings::for_h264_yuv420p(1280, 720, true),
+    ///     &hls_output,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_hls(dest: &Locator, settings: Settings, hls_output: &HlsOutput) -> Result<Self> {
+        let writer = Writer::new_with_format_and_options(dest, "hls", &hls_output.options())?;
+        let settings = if settings.key_frame_every.is_none() {
+            settings.force_keyframe_every(hls_output.segment_duration)
+        } else {
+            settings
+        };
 
-        Ok(())
+        Self::from_writer(writer, settings)
     }
 
-    /// Create an encoder from a `FileWriter` instance.
+    /// Create a new encoder that does not write to a container, but hands each encoded packet to a
+    /// callback instead, for example to send it over a custom transport (like WebRTC). Packets are
+    /// produced while encoding frames, and [`Encoder::finish`] hands out the remaining packets the
+    /// same way.
+    ///
+    /// Without a container, there is no global header: parameter sets (like the SPS and PPS of an
+    /// H.264 stream) are in band, in the key frame packets. Functions that need a container (like
+    /// [`Encoder::set_chapters`]) fail with [`Error::NoContainer`].
     ///
     /// # Arguments
     ///
-    /// * `writer` - `FileWriter` to create encoder from.
     /// * `settings` - Encoder settings to use.
-    fn from_writer(mut writer: Writer, settings: Settings) -> Result<Self> {
-        let global_header = writer
-            .output
-            .format()
-            .flags()
-            .contains(AvFormatFlags::GLOBAL_HEADER);
+    /// * `time_base` - Time base of the timestamps of the encoded packets.
+    /// * `on_packet` - Function that is called with each encoded packet, in decoding order.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new_packetized(
+    ///     Settings::for_h264_yuv420p(1280, 720, true),
+    ///     AvRational::new(1, 90_000),
+    ///     move |packet| transport.send(packet.pts, &packet.data),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_packetized(
+        settings: Settings,
+        time_base: AvRational,
+        on_packet: impl FnMut(EncodedPacket) + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let mut encoder = Self::from_writers(None, Vec::new(), settings)?;
+        encoder.packet_sink = Some(PacketSink {
+            time_base,
+            on_packet: Box::new(on_packet),
+        });
 
-        let mut writer_stream = writer.output.add_stream(settings.codec())?;
-        let writer_stream_index = writer_stream.index();
+        Ok(encoder)
+    }
 
-        let mut encoder_context = match settings.codec() {
-            Some(codec) => codec_context_as(&codec)?,
-            None => AvContext::new(),
+    /// Create a new encoder that splits its output into segments, like a dashcam that starts a new
+    /// file every five minutes. Segments are numbered from one, and the container format is
+    /// derived from the extension of the pattern. Segments always start at a key frame, so that
+    /// each segment can be played independently, and no frames are lost at the boundaries. Use
+    /// [`Encoder::on_segment_complete`] to be notified when a segment has been written completely.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Path of the segments, with the segment number as `%d` or zero-padded as
+    ///   `%0Nd` (use `%%` for a literal `%`), like `rec_%04d.mp4`.
+    /// * `settings` - Encoder settings to use.
+    /// * `policy` - When to start a new segment.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let policy = SegmentPolicy {
+    ///     limit: SegmentLimit::Duration(Duration::from_secs(300)),
+    ///     reset_timestamps: true,
+    /// };
+    /// let mut encoder = Encoder::new_segmented(
+    ///     Path::new("recordings/rec_%04d.mp4"),
+    ///     Settings::for_h264_yuv420p(1280, 720, true),
+    ///     policy,
+    /// )
+    /// .unwrap()
+    /// .on_segment_complete(|segment| uploader.upload(&segment.path));
+    /// ```
+    pub fn new_segmented(
+        pattern: &std::path::Path,
+        settings: Settings,
+        policy: SegmentPolicy,
+    ) -> Result<Self> {
+        let segmenter = Segmenter::new(pattern, policy)?;
+        let settings = match policy.limit {
+            SegmentLimit::Duration(duration) if settings.key_frame_every.is_none() => {
+                settings.force_keyframe_every(duration)
+            }
+            _ => settings,
         };
 
-        // Some formats require this flag to be set or the output will
-        // not be playable by dumb players.
-        if global_header {
-            encoder_context.set_flags(AvCodecFlags::GLOBAL_HEADER);
-        }
-
-        let mut encoder = encoder_context.encoder().video()?;
-        settings.apply_to(&mut encoder);
+        let writer = Writer::new(&Locator::Path(segmenter.path()))?;
+        let mut encoder = Self::from_writer(writer, settings)?;
+        encoder.segmenter = Some(segmenter.with_time_base(encoder.encoder_time_base));
 
-        // Just use the ffmpeg global time base which is precise enough
-        // that we should never get in trouble.
-        encoder.set_time_base(TIME_BASE);
-
-        let encoder = encoder.open_with(settings.options().to_dict())?;
-        let encoder_time_base = get_encoder_time_base(&encoder);
+        Ok(encoder)
+    }
 
-        writer_stream.set_parameters(&encoder);
+    /// Set a function that is called each time a segment of an encoder created with
+    /// [`Encoder::new_segmented`] has been written completely, for example to upload it. The
+    /// function is called on the encoding thread, so it should not block for long. The last
+    /// segment is completed by [`Encoder::finish`].
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function to call with the completed segment.
+    pub fn on_segment_complete(
+        mut self,
+        callback: impl FnMut(&CompletedSegment) + Send + Sync + 'static,
+    ) -> Self {
+        if let Some(segmenter) = self.segmenter.as_mut() {
+            segmenter.set_on_complete(Box::new(callback));
+        }
+        self
+    }
 
-        let scaler_width = encoder.width();
-        let scaler_height = encoder.height();
-        let scaler = AvScaler::get(
-            AvPixel::BGRA,
-            scaler_width,
-            scaler_height,
-            encoder.format(),
-            scaler_width,
-            scaler_height,
-            AvScalerFlags::empty(),
-        )?;
+    /// Create a new encoder that writes the same encoded stream to multiple destinations, for
+    /// example to record to a local file while streaming to a server. Frames are only encoded
+    /// once, and the encoded packets are written to each output.
+    ///
+    /// The first output is the primary output, and failing to write to it always fails encoding.
+    /// What happens when writing to any of the other outputs fails is determined by their
+    /// [`FailurePolicy`]. Use [`Encoder::output_error`] to find out whether an output failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `outputs` - Outputs to write to. There must be at least one.
+    /// * `settings` - Encoder settings to use.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new_multi(
+    ///     &[
+    ///         OutputSpec::new(PathBuf::from("recording.mp4").into(), "mp4"),
+    ///         OutputSpec::new(Url::parse("rtmp://live.example.com/app/key").unwrap().into(), "flv")
+    ///             .with_failure_policy(FailurePolicy::Ignore),
+    ///     ],
+    ///     Settings::for_h264_yuv420p(1280, 720, true),
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn new_multi(outputs: &[OutputSpec], settings: Settings) -> Result<Self> {
+        let (primary, additional) = outputs.split_first().ok_or(Error::NoOutputs)?;
+        let writer = primary.open()?;
+        let additional_writers = additional
+            .iter()
+            .map(|output| Ok((output.open()?, output.failure_policy)))
+            .collect::<Result<Vec<_>>>()?;
 
-        Ok(Self {
-            writer,
-            writer_stream_index,
-            encoder,
-            encoder_time_base,
-            interleaved: false,
-            scaler,
-            scaler_width,
-            scaler_height,
-            frame_count: 0,
-            have_written_header: false,
-            have_written_trailer: false,
-        })
+        Self::from_writers(Some(writer), additional_writers, settings)
     }
 
-    /// Apply scaling (or pixel reformatting in this case) on the frame with the scaler we
-    /// initialized earlier.
+    /// Get the error that made writing to an output fail, if it failed. Outputs are indexed in
+    /// the order they were passed to [`Encoder::new_multi`]. Since failing to write to the
+    /// primary output fails encoding, this only ever returns an error for additional outputs.
     ///
     /// # Arguments
     ///
-    /// * `frame` - Frame to rescale.
-    fn scale(&mut self, frame: RawFrame) -> Result<RawFrame> {
-        let mut frame_scaled = RawFrame::empty();
-        self.scaler
-            .run(&frame, &mut frame_scaled)
-            .map_err(Error::BackendError)?;
-        // Copy over PTS from old frame.
-        frame_scaled.set_pts(frame.pts());
-
-        Ok(frame_scaled)
+    /// * `index` - Index of the output.
+    pub fn output_error(&self, index: usize) -> Option<&Error> {
+        index
+            .checked_sub(1)
+            .and_then(|index| self.additional_outputs.get(index))
+            .and_then(|output| output.error.as_ref())
     }
 
-    /// Pull an encoded packet from the decoder. This function also handles the possible `EAGAIN`
-    /// result, in which case we just need to go again.
-    fn encoder_receive_packet(&mut self) -> Result<Option<AvPacket>> {
-        let mut packet = AvPacket::empty();
-        let encode_result = self.encoder.receive_packet(&mut packet);
-        match encode_result {
-            Ok(()) => Ok(Some(packet)),
-            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
-            Err(err) => Err(err.into()),
-        }
+    /// Turn the encoder into an interleaved version, that automatically reorders packets when
+    /// necessary.
+    ///
+    /// By default, packets are written interleaved when the output has more than one stream, or
+    /// when the encoder reorders frames (with B-frames). This forces interleaving for all outputs.
+    pub fn interleaved(mut self) -> Self {
+        self.interleaving = Interleaving::Always;
+        self
     }
 
-    /// Acquire the time base of the output stream.
-    fn stream_time_base(&mut self) -> AvRational {
-        self.writer
-            .output
-            .stream(self.writer_stream_index)
-            .unwrap()
-            .time_base()
+    /// Turn the encoder into a non-interleaved version, that writes packets in the order in which
+    /// they come in. Some players refuse outputs with multiple streams that are not interleaved,
+    /// so this is only useful when packets are known to come in the right order already.
+    pub fn non_interleaved(mut self) -> Self {
+        self.interleaving = Interleaving::Never;
+        self
     }
 
-    /// Write encoded packet to output stream.
+    /// Enable or disable real-time pacing. When enabled, packets are written at wall-clock rate
+    /// according to their decoding timestamps, instead of as fast as the encoder produces them.
+    /// This is necessary when streaming to receivers with limited buffers, such as MPEG-TS over
+    /// UDP.
     ///
     /// # Arguments
     ///
-    /// * `packet` - Encoded packet.
-    fn write(&mut self, mut packet: AvPacket) -> Result<()> {
-        packet.set_stream(self.writer_stream_index);
-        packet.set_position(-1);
-        packet.rescale_ts(self.encoder_time_base, self.stream_time_base());
-        if self.interleaved {
-            self.writer.write_interleaved(&mut packet)?;
-        } else {
-            self.writer.write(&mut packet)?;
-        };
-
-        self.frame_count += 1;
-        Ok(())
+    /// * `enabled` - Whether or not to pace writing.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new_with_format_and_options(
+    ///     &Url::parse("udp://239.1.1.1:5000?pkt_size=1316").unwrap().into(),
+    ///     Settings::for_h264_yuv420p(1280, 720, true),
+    ///     "mpegts",
+    ///     &Options::new_with_mpegts_service(1, 0x1000, 0x100),
+    /// )
+    /// .unwrap()
+    /// .realtime_pacing(true);
+    /// ```
+    pub fn realtime_pacing(mut self, enabled: bool) -> Self {
+        self.pacer = enabled.then(Pacer::new);
+        self
     }
 
-    /// Flush the encoder, drain any packets that still need processing.
-    fn flush(&mut self) -> Result<()> {
-        // Maximum number of invocations to `encoder_receive_packet`
-        // to drain the items still on the queue before giving up.
-        const MAX_DRAIN_ITERATIONS: u32 = 100;
+    /// Set the maximum amount of time that finishing the output may take when the encoder is
+    /// dropped without calling [`Encoder::finish`]. The default is 30 seconds. See
+    /// [`Encoder::finish_with_timeout`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum amount of time, or `None` to wait for as long as it takes.
+    pub fn drop_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.drop_timeout = timeout;
+        self
+    }
 
-        // Notify the encoder that the last frame has been sent.
-        self.encoder.send_eof()?;
+    /// Flush output to the destination automatically at the given interval, so that downstream
+    /// consumers (like network receivers or processes tailing the output file) do not have to
+    /// wait for the output buffer to fill up. See [`Encoder::flush_output`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How often to flush.
+    pub fn flush_every(mut self, interval: FlushInterval) -> Self {
+        self.flush_schedule = Some(FlushSchedule::new(interval, Instant::now()));
+        self
+    }
 
-        // We need to drain the items still in the encoders queue.
-        for _ in 0..MAX_DRAIN_ITERATIONS {
-            match self.encoder_receive_packet() {
-                Ok(Some(packet)) => self.write(packet)?,
-                Ok(None) => continue,
-                Err(_) => break,
-            }
-        }
+    /// Composite an overlay onto each frame before it is encoded, like a logo or a watermark.
+    /// This replaces any overlay set before.
+    ///
+    /// The overlay is alpha-composited onto frames in their input format (RGB or BGRA), before
+    /// they are converted to the pixel format of the encoder. Parts of the overlay that fall
+    /// outside of the frame are clipped, and fully transparent pixels leave the frame as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Overlay image in `HWC` format with four channels: red, green, blue and alpha.
+    /// * `position` - Position of the top left corner of the overlay on the encoded frame.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let logo = Frame::from_shape_vec((32, 32, 4), logo_rgba).unwrap();
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)
+    ///     .unwrap()
+    ///     .with_overlay(logo, (16, 16))
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn with_overlay(mut self, image: Frame, position: (u32, u32)) -> Result<Self> {
+        self.overlay = Some(OverlaySource::Static(Overlay::new(&image, position)?));
+        Ok(self)
+    }
 
-        Ok(())
+    /// Composite an overlay that is supplied per frame onto each frame before it is encoded, like
+    /// a timecode burn-in. This replaces any overlay set before. See [`Encoder::with_overlay`].
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function that is called with the timestamp of each frame, and returns the
+    ///   overlay to composite onto it (or `None` to leave the frame as is).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)
+    ///     .unwrap()
+    ///     .with_overlay_fn(|timestamp| Some(render_timecode(timestamp)));
+    /// ```
+    pub fn with_overlay_fn(
+        mut self,
+        callback: impl FnMut(&Time) -> Option<Overlay> + Send + Sync + 'static,
+    ) -> Self {
+        self.overlay = Some(OverlaySource::Dynamic(Box::new(callback)));
+        self
     }
-}
 
-impl Drop for Encoder {
-    fn drop(&mut self) {
-        let _ = self.finish();
+    /// Draw the number and timestamp (as `HH:MM:SS.mmm`) of each frame into a corner of it, for
+    /// debugging synchronization issues. The stamp is drawn with a small bitmap font that is
+    /// built in, after frames are converted to the pixel format and size of the encoder (and after
+    /// the filter graph, if any), so it costs no more than writing a few hundred pixels per frame.
+    /// Frame numbers count the frames sent to the encoder, from zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - How to draw the stamp.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)
+    ///     .unwrap()
+    ///     .with_debug_stamp(StampConfig {
+    ///         position: StampPosition::BottomRight,
+    ///         scale: 3,
+    ///         color: [255, 255, 0],
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn with_debug_stamp(mut self, config: StampConfig) -> Result<Self> {
+        self.debug_stamp = Some(DebugStamp::new(config, self.encoder.format())?);
+        Ok(self)
     }
-}
 
-/// Holds a logical combination of encoder settings.
-pub struct Settings<'o> {
-    width: u32,
-    height: u32,
-    pixel_format: AvPixel,
-    options: Options<'o>,
-}
+    /// Apply a libavfilter filter graph to the frames before they are encoded, described like the
+    /// `-vf` option on the command line. The filters run on the input frames (after the overlay,
+    /// if any), and the frames that come out of the filters are converted and resized to the
+    /// encoder pixel format and size. Filters that change the timing of the frames (like `fps`)
+    /// decide which frames are encoded, and frames that filters hold back are encoded when the
+    /// encoder finishes. If the input is cropped (see [`Settings::with_crop`]), the crop region
+    /// applies to the frames that come out of the filters.
+    ///
+    /// The filter graph is set up for the pixel format of the first frame. Filters and transforms
+    /// (see [`Encoder::with_transform`]) apply in the order they are set.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filters to apply, separated by commas, like `hqdn3d,fps=30`.
+    ///
+    /// # Return value
+    ///
+    /// The encoder, or [`Error::InvalidFilterGraph`] if the filters cannot be set up.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)
+    ///     .unwrap()
+    ///     .with_filter("hflip,fps=30")
+    ///     .unwrap();
+    /// ```
+    pub fn with_filter(mut self, spec: &str) -> Result<Self> {
+        // Set up the graph for frames in the default format, so that a description that cannot be
+        // parsed fails here instead of when the first frame comes in.
+        let spec = chain_filters(self.filter_spec.as_deref(), spec);
+        FilterGraph::parse(&spec, self.filter_input(AvPixel::RGB24, self.input_size()))?;
+        self.filter_spec = Some(spec);
+        self.filter = None;
+        Ok(self)
+    }
 
-impl<'o> Settings<'o> {
-    /// This is the assumed FPS for the encoder to use. Note that this does not need to be correct
-    /// exactly.
-    const FRAME_RATE: i32 = 30;
+    /// Flip or rotate the frames before they are encoded, without writing a filter description
+    /// (see [`Encoder::with_filter`]). The encoder settings describe the encoded frames, so with a
+    /// quarter rotation, input frames are expected to have the width and height of the settings
+    /// swapped: a 1080x1920 portrait frame rotated by 90 degrees is encoded as 1920x1080.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - Transform to apply.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1920, 1080, false);
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)
+    ///     .unwrap()
+    ///     .with_transform(Transform::Rotate90)
+    ///     .unwrap();
+    /// encoder.encode_raw(RawFrame::new(PixelFormat::RGB24, 1080, 1920)).unwrap();
+    /// ```
+    pub fn with_transform(mut self, transform: Transform) -> Result<Self> {
+        if transform.swaps_dimensions() {
+            std::mem::swap(&mut self.scaler_width, &mut self.scaler_height);
+            self.swaps_dimensions = !self.swaps_dimensions;
+        }
+        self.with_filter(transform.filter_spec())
+    }
 
-    /// Create encoder settings for an H264 stream with YUV420p pixel format. This will encode to
-    /// arguably the most widely compatible video file since H264 is a common codec and YUV420p is
-    /// the most commonly used pixel format.
-    pub fn for_h264_yuv420p(width: usize, height: usize, realtime: bool) -> Settings<'o> {
-        let options = if realtime {
-            Options::new_h264_realtime()
-        } else {
-            Options::new_h264()
+    /// Get the keys of options that were not recognized: first the codec options that the encoder
+    /// did not recognize, then the options that the output protocol and muxer did not recognize.
+    /// The muxer options are only known once the header has been written, which happens when the
+    /// first frame is encoded.
+    pub fn ignored_options(&self) -> Vec<String> {
+        self.ignored_options
+            .iter()
+            .chain(self.writer.iter().flat_map(Writer::ignored_options))
+            .cloned()
+            .collect()
+    }
+
+    /// Get the pixel format that was asked for in the settings and the pixel format that the
+    /// encoder uses instead, if the encoder does not support the first one (see
+    /// [`Settings::with_auto_convert`]). Frames are converted to the second one.
+    pub fn pixel_format_substitution(&self) -> Option<(PixelFormat, PixelFormat)> {
+        let requested = self.settings.pixel_format;
+        let used = self.encoder.format();
+        (requested != used).then_some((requested, used))
+    }
+
+    /// Find the best available encoder for a codec, picked from the encoders the linked ffmpeg
+    /// build actually provides. See [`crate::capabilities`] for a list of all encoders.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to find an encoder for, like [`CodecId::H264`].
+    /// * `prefer_hardware` - Whether to prefer a hardware encoder (like `h264_nvenc`) over a
+    ///   software encoder (like `libx264`). The other kind is picked if there is no encoder of the
+    ///   preferred kind.
+    ///
+    /// # Return value
+    ///
+    /// The encoder, or `None` if there is no encoder for the codec.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// match Encoder::find_best(CodecId::HEVC, true) {
+    ///     Some(encoder) => println!("encoding with {}", encoder.name),
+    ///     None => println!("no HEVC encoder available"),
+    /// }
+    /// ```
+    pub fn find_best(codec_id: CodecId, prefer_hardware: bool) -> Option<EncoderInfo> {
+        pick_encoder(encoders_for(codec_id), prefer_hardware)
+    }
+
+    /// Get the number of frames that have been sent to the encoder.
+    pub fn frames_encoded(&self) -> u64 {
+        self.frames_encoded
+    }
+
+    /// Get the number of packets that have been written to the primary output. This lags behind
+    /// [`Encoder::frames_encoded`] for encoders with delay, until the encoder is finished.
+    pub fn packets_written(&self) -> u64 {
+        self.packets_written
+    }
+
+    /// Get statistics of the encoded output, for logging a summary after [`Encoder::finish`]
+    /// without reopening the output. Before the encoder is finished, the statistics only cover
+    /// the packets that have been written so far.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// encoder.finish().unwrap();
+    /// let stats = encoder.stats();
+    /// println!(
+    ///     "{} frames, {} bytes, {:.1}s at {} bps",
+    ///     stats.frames,
+    ///     stats.bytes,
+    ///     stats.duration.as_secs_f64(),
+    ///     stats.average_bitrate,
+    /// );
+    /// ```
+    pub fn stats(&self) -> EncodeStats {
+        EncodeStats {
+            faststart: self.writer.as_ref().and_then(Writer::faststart),
+            ..self
+                .packet_stats
+                .summary(self.frames_encoded, self.encoder_time_base)
+        }
+    }
+
+    /// Get encoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.encoder_time_base
+    }
+
+    /// Get the primary writer of the encoder, or `None` if encoded packets go to a packet sink
+    /// instead (see [`Encoder::new_packetized`]).
+    pub fn writer(&self) -> Option<&Writer> {
+        self.writer.as_ref()
+    }
+
+    /// Get the underlying ffmpeg encoder context, to read what the crate does not expose, like
+    /// the extradata or the options that the encoder settled on.
+    pub fn codec_context(&self) -> &AvContext {
+        &self.encoder
+    }
+
+    /// Get the underlying ffmpeg encoder context, for anything that the crate does not wrap. The
+    /// context is valid from when the encoder is created until it is dropped. After
+    /// [`Encoder::finish`] the encoder has been flushed, so it does not accept frames anymore.
+    ///
+    /// # Safety
+    ///
+    /// The caller must not free or close the context, and must not use the pointer after the
+    /// encoder is dropped, or after [`Encoder::reconfigure`], which opens a new encoder context.
+    /// Settings that are fixed when the encoder is opened (like the size, pixel format and time
+    /// base) must not be changed. Sending frames to or receiving packets from the context
+    /// directly bypasses the writer.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings).unwrap();
+    /// let max_b_frames = unsafe { (*encoder.as_raw_codec_context()).max_b_frames };
+    /// ```
+    pub unsafe fn as_raw_codec_context(&mut self) -> *mut AVCodecContext {
+        self.encoder.as_mut_ptr()
+    }
+
+    /// Get the underlying ffmpeg muxer context of the primary writer, or `None` if encoded packets
+    /// go to a packet sink instead. See [`Writer::as_raw_format_context`].
+    ///
+    /// # Safety
+    ///
+    /// Like [`Writer::as_raw_format_context`]. In addition, the pointer must not be used after the
+    /// encoder replaced its writer: when it reconnects (see
+    /// [`Encoder::new_rtmp_with_retry_policy`]), or when a segment is complete (see
+    /// [`Encoder::new_segmented`]). The header is written with the first frame.
+    pub unsafe fn as_raw_format_context(&mut self) -> Option<*mut AVFormatContext> {
+        self.writer
+            .as_mut()
+            .map(|writer| writer.as_raw_format_context())
+    }
+
+    /// Get the number of frames that the encoder declares to hold back before the first packet
+    /// comes out, so that applications can prime their pipelines. Some encoders hold back more
+    /// frames than they declare (like `libx264` with lookahead, see [`Settings::with_lookahead`]
+    /// and [`Settings::with_low_latency`]).
+    pub fn delay(&self) -> usize {
+        encoder_delay(&self.encoder)
+    }
+
+    /// Get the codec extradata of the encoder, like the SPS and PPS of an H.264 stream, for
+    /// consumers that need the parameter sets out of band (like WebRTC stacks).
+    ///
+    /// Encoders only produce extradata when the container requires a global header (like MP4 and
+    /// Matroska). Otherwise, the parameter sets are repeated in band in the key frame packets, and
+    /// there is no extradata. The format of the extradata depends on the encoder: `libx264` uses
+    /// Annex B, but other encoders use AVCC. Use [`Encoder::extradata_annexb`] to always get
+    /// Annex B, or [`crate::extract_parameter_sets_h264`] to get the parameter sets.
+    ///
+    /// # Return value
+    ///
+    /// The extradata, or `None` if the encoder has none.
+    pub fn extradata(&self) -> Option<Vec<u8>> {
+        let extradata = codec_context_extradata(&self.encoder);
+        (!extradata.is_empty()).then_some(extradata)
+    }
+
+    /// Get the codec extradata of the encoder in Annex B format, where every parameter set is
+    /// prefixed with a start code. Extradata in AVCC (or HVCC) format is converted with the
+    /// `h264_mp4toannexb` (or `hevc_mp4toannexb`) bitstream filter. See [`Encoder::extradata`].
+    ///
+    /// # Return value
+    ///
+    /// The extradata in Annex B format, or `None` if the encoder has none.
+    pub fn extradata_annexb(&self) -> Result<Option<Vec<u8>>> {
+        let Some(extradata) = self.extradata() else {
+            return Ok(None);
         };
+        // AVCC and HVCC start with a version byte of 1, and Annex B starts with a start code.
+        if extradata[0] != 1 {
+            return Ok(Some(extradata));
+        }
 
-        Self {
-            width: width as u32,
-            height: height as u32,
-            pixel_format: AvPixel::YUV420P,
-            options,
+        let filter_name = match self.encoder.id() {
+            AvCodecId::H264 => "h264_mp4toannexb",
+            AvCodecId::HEVC => "hevc_mp4toannexb",
+            _ => return Err(Error::InvalidExtraData),
+        };
+        let filter = AvBitstreamFilter::new(
+            filter_name,
+            &AvCodecParameters::from(&self.encoder),
+            self.encoder_time_base,
+        )?;
+
+        Ok(Some(parameters_extradata(&filter.parameters_out())))
+    }
+
+    /// Encode a single `ndarray` frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode in `HWC` format and standard layout.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub fn encode(&mut self, frame: &Frame, source_timestamp: &Time) -> Result<()> {
+        let frame = ndarray_to_raw_frame(
+            frame,
+            source_timestamp,
+            self.encoder_time_base,
+            (self.scaler_width, self.scaler_height),
+        )?;
+
+        self.encode_raw(frame)
+    }
+
+    /// Encode a single `ndarray` frame with 16 bits per component, like the output of a
+    /// high bit depth camera. Use a high bit depth encoder pixel format (like
+    /// [`PixelFormat::YUV420P10LE`], see [`Settings::for_hevc_custom`]) to keep more than 8 bits
+    /// of precision.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode in `HWC` format and standard layout, with 3 channels (RGB) or
+    ///   a single channel (grayscale). All 16 bits of each component are used.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub fn encode_u16(&mut self, frame: &FrameU16, source_timestamp: &Time) -> Result<()> {
+        let (height, width, channels) = frame.dim();
+        let pixel_format = match channels {
+            3 => AV_PIX_FMT_RGB48,
+            1 => AV_PIX_FMT_GRAY16,
+            _ => {
+                return Err(Error::invalid_frame_format(
+                    "1 or 3 channels",
+                    format!("{channels} channels"),
+                ))
+            }
+        };
+        if (width, height) != (self.scaler_width as usize, self.scaler_height as usize) {
+            return Err(self.invalid_frame_size(width, height));
+        }
+
+        let mut frame = convert_ndarray_to_frame(frame, pixel_format).map_err(Error::from)?;
+        frame.set_pts(
+            source_timestamp
+                .aligned_with_rational(self.encoder_time_base)
+                .into_value(),
+        );
+
+        self.encode_frame(frame)
+    }
+
+    /// Encode a single floating point frame, like the output of a machine learning model. Values
+    /// are converted back to components with the given normalization, and clamped to the valid
+    /// range.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode, in the layout of `normalization`.
+    /// * `normalization` - How the components were converted to floating point values.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub fn encode_f32(
+        &mut self,
+        frame: &Frame32,
+        normalization: &Normalization,
+        source_timestamp: &Time,
+    ) -> Result<()> {
+        let (width, height) = normalization.frame_size(frame).ok_or_else(|| {
+            Error::invalid_frame_format(
+                "frame with 3 channels in the layout of the normalization",
+                format!("frame of shape {:?}", frame.dim()),
+            )
+        })?;
+        if height != self.scaler_height as usize || width != self.scaler_width as usize {
+            return Err(self.invalid_frame_size(width, height));
         }
+
+        let mut raw_frame = RawFrame::new(AvPixel::RGB24, width as u32, height as u32);
+        let stride = raw_frame.stride(0);
+        convert_ndarray_f32_to_rgb24(frame, normalization, raw_frame.data_mut(0), stride);
+
+        raw_frame.set_pts(
+            source_timestamp
+                .aligned_with_rational(self.encoder_time_base)
+                .into_value(),
+        );
+
+        self.encode_raw(raw_frame)
     }
 
-    /// Create encoder settings for an H264 stream with a custom pixel format and options.
-    /// This allows for greater flexibility in encoding settings, enabling specific requirements
-    /// or optimizations to be set depending on the use case.
+    /// Encode a single image. RGB, RGBA and grayscale images (with 8 bits per component) are
+    /// encoded as is. Images of other types are converted to RGB first, which logs a warning.
     ///
     /// # Arguments
     ///
-    /// * `width` - The width of the video stream.
-    /// * `height` - The height of the video stream.
-    /// * `pixel_format` - The desired pixel format for the video stream.
-    /// * `options` - Custom H264 encoding options.
+    /// * `image` - Image to encode. It must have the same size as the encoder.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "image")]
+    pub fn encode_image(
+        &mut self,
+        image: &image::DynamicImage,
+        source_timestamp: &Time,
+    ) -> Result<()> {
+        use image::DynamicImage;
+
+        if image.width() != self.scaler_width || image.height() != self.scaler_height {
+            return Err(self.invalid_frame_size(image.width() as usize, image.height() as usize));
+        }
+
+        let converted;
+        let (pixel_format, bytes_per_pixel, packed) = match image {
+            DynamicImage::ImageRgb8(image) => (AvPixel::RGB24, 3, image.as_raw()),
+            DynamicImage::ImageRgba8(image) => (AvPixel::RGBA, 4, image.as_raw()),
+            DynamicImage::ImageLuma8(image) => (AvPixel::GRAY8, 1, image.as_raw()),
+            image => {
+                tracing::warn!(
+                    target: "video",
+                    "converting image of type {:?} to RGB before encoding",
+                    image.color(),
+                );
+                converted = image.to_rgb8();
+                (AvPixel::RGB24, 3, converted.as_raw())
+            }
+        };
+
+        let mut frame = RawFrame::new(pixel_format, image.width(), image.height());
+        let stride = frame.stride(0);
+        unpack_rows(
+            packed,
+            image.width() as usize * bytes_per_pixel,
+            frame.data_mut(0),
+            stride,
+        );
+
+        frame.set_pts(
+            source_timestamp
+                .aligned_with_rational(self.encoder_time_base)
+                .into_value(),
+        );
+
+        self.encode_raw(frame)
+    }
+
+    /// Encode a single frame given as separate Y, U and V planes with 4:2:0 chroma subsampling.
+    /// If the encoder pixel format is YUV420p (and frames are not cropped), the planes are copied
+    /// into the encoder frame as is, without any conversion.
     ///
-    /// # Return value
+    /// Note that overlays (see [`Encoder::with_overlay`]) can only be composited onto RGB frames.
+    /// If an overlay is set, encoding YUV frames fails with [`Error::InvalidFrameFormat`].
     ///
-    /// A `Settings` instance with the specified configuration.+
-    pub fn for_h264_custom(
-        width: usize,
-        height: usize,
-        pixel_format: PixelFormat,
-        options: Options<'o>,
-    ) -> Settings<'o> {
-        Self {
-            width: width as u32,
-            height: height as u32,
-            pixel_format,
-            options,
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode. It must have the same size as the encoder.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub fn encode_yuv(&mut self, frame: &YuvFrame, source_timestamp: &Time) -> Result<()> {
+        if frame.size() != (self.scaler_width, self.scaler_height) {
+            let (width, height) = frame.size();
+            return Err(self.invalid_frame_size(width as usize, height as usize));
+        }
+
+        let mut frame = frame.to_raw_frame()?;
+        frame.set_pts(
+            source_timestamp
+                .aligned_with_rational(self.encoder_time_base)
+                .into_value(),
+        );
+
+        self.encode_frame(frame)
+    }
+
+    /// Encode a single raw frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    pub fn encode_raw(&mut self, frame: RawFrame) -> Result<()> {
+        if frame.width() != self.scaler_width || frame.height() != self.scaler_height {
+            return Err(self.invalid_frame_size(frame.width() as usize, frame.height() as usize));
+        }
+        if !matches!(
+            frame.format(),
+            AvPixel::RGB24
+                | AvPixel::BGRA
+                | AvPixel::RGBA
+                | AvPixel::GRAY8
+                | AvPixel::RGB48
+                | AvPixel::GRAY16
+        ) {
+            return Err(Error::invalid_frame_format(
+                "RGB24, BGRA, RGBA, GRAY8, RGB48 or GRAY16",
+                format!("{:?}", frame.format()),
+            ));
         }
+
+        self.encode_frame(frame)
     }
 
-    /// Apply the settings to an encoder.
+    /// Encode a single raw frame with side data, like closed captions, so that the side data ends
+    /// up in the encoded stream. See [`Encoder::encode_raw`]. Side data that the frame already has
+    /// (like the captions of a decoded frame) is passed on as well, so this is only needed to add
+    /// side data. Whether or not side data is written depends on the encoder: `libx264` writes
+    /// captions as SEI messages, and `libx265` writes HDR metadata.
     ///
     /// # Arguments
     ///
-    /// * `encoder` - Encoder to apply settings to.
+    /// * `frame` - Frame to encode.
+    /// * `side_data` - Side data to set on the frame, replacing any side data of the same kind.
+    pub fn encode_with_side_data(
+        &mut self,
+        mut frame: RawFrame,
+        side_data: &[SideData],
+    ) -> Result<()> {
+        for side_data in side_data {
+            frame.set_side_data(side_data)?;
+        }
+
+        self.encode_raw(frame)
+    }
+
+    /// Encode a single `ndarray` frame with metadata entries. The entries are set on the frame
+    /// before it goes through the filter graph (if any) to the encoder, for filters and encoders
+    /// that read frame metadata. See [`RawFrameMetadata`] for what happens to them.
     ///
-    /// # Return value
+    /// # Arguments
     ///
-    /// New encoder with settings applied.
-    fn apply_to(&self, encoder: &mut AvVideo) {
-        encoder.set_width(self.width);
-        encoder.set_height(self.height);
-        encoder.set_format(self.pixel_format);
-        encoder.set_frame_rate(Some((Self::FRAME_RATE, 1)));
+    /// * `frame` - Frame to encode in `HWC` format and standard layout.
+    /// * `source_timestamp` - Frame timestamp of original source.
+    /// * `metadata` - Metadata entries to set, as keys and values.
+    #[cfg(feature = "ndarray")]
+    pub fn encode_with_metadata(
+        &mut self,
+        frame: &Frame,
+        source_timestamp: &Time,
+        metadata: &[(&str, &str)],
+    ) -> Result<()> {
+        let frame = ndarray_to_raw_frame(
+            frame,
+            source_timestamp,
+            self.encoder_time_base,
+            (self.scaler_width, self.scaler_height),
+        )?;
+
+        self.encode_raw_with_metadata(frame, metadata)
     }
 
-    /// Get codec.
-    fn codec(&self) -> Option<AvCodec> {
-        // Try to use the libx264 decoder. If it is not available, then use use whatever default
-        // h264 decoder we have.
-        Some(
-            ffmpeg::encoder::find_by_name("libx264")
-                .unwrap_or(ffmpeg::encoder::find(AvCodecId::H264)?),
-        )
+    /// Encode a single raw frame with metadata entries. This is the raw variant of
+    /// [`Encoder::encode_with_metadata`]. Entries that the frame already has are passed on as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    /// * `metadata` - Metadata entries to set, as keys and values.
+    pub fn encode_raw_with_metadata(
+        &mut self,
+        mut frame: RawFrame,
+        metadata: &[(&str, &str)],
+    ) -> Result<()> {
+        for (key, value) in metadata {
+            frame.set_metadata_entry(key, value);
+        }
+
+        self.encode_raw(frame)
     }
 
-    /// Get encoder options.
-    fn options(&self) -> &Options<'o> {
-        &self.options
+    /// Encode a batch of `ndarray` frames. See [`Encoder::encode`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Frames to encode in `HWC` format and standard layout, each with the frame
+    ///   timestamp of the original source.
+    ///
+    /// # Return value
+    ///
+    /// The number of frames that were encoded. Like [`std::io::Write::write`], this is less than
+    /// the number of frames if a frame was rejected after at least one other frame was encoded:
+    /// passing the rejected frame again returns the error. An error that occurs after the encoder
+    /// accepted a frame is always returned.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut frames = &frames[..];
+    /// while !frames.is_empty() {
+    ///     let encoded = encoder.encode_batch(frames)?;
+    ///     frames = &frames[encoded..];
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn encode_batch(&mut self, frames: &[(Frame, Time)]) -> Result<usize> {
+        self.encode_each(frames, |encoder, (frame, source_timestamp)| {
+            encoder.encode(frame, source_timestamp)
+        })
     }
-}
 
-unsafe impl Send for Encoder {}
-unsafe impl Sync for Encoder {}
+    /// Encode a batch of raw frames. See [`Encoder::encode_raw`] and [`Encoder::encode_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Frames to encode.
+    ///
+    /// # Return value
+    ///
+    /// The number of frames that were encoded. This is less than the number of frames if a frame
+    /// was rejected after at least one other frame was encoded.
+    pub fn encode_raw_batch(
+        &mut self,
+        frames: impl IntoIterator<Item = RawFrame>,
+    ) -> Result<usize> {
+        self.encode_each(frames, Self::encode_raw)
+    }
+
+    /// Encode frames one by one, and stop at the first error.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Frames to encode.
+    /// * `encode` - Function that encodes a single frame.
+    ///
+    /// # Return value
+    ///
+    /// The number of frames that were encoded. See [`Encoder::encode_batch`].
+    fn encode_each<T>(
+        &mut self,
+        frames: impl IntoIterator<Item = T>,
+        mut encode: impl FnMut(&mut Self, T) -> Result<()>,
+    ) -> Result<usize> {
+        let mut encoded = 0;
+        for frame in frames {
+            let frames_encoded = self.frames_encoded;
+            match encode(self, frame) {
+                Ok(()) => encoded += 1,
+                // The frame was rejected before reaching the encoder, so report the frames that
+                // were encoded and leave the error to the next call.
+                Err(_) if encoded > 0 && self.frames_encoded == frames_encoded => {
+                    return Ok(encoded)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(encoded)
+    }
+
+    /// Encode a single raw frame that has been checked to have the input size of the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    fn encode_frame(&mut self, frame: RawFrame) -> Result<()> {
+        self.write_header()?;
+
+        let mut frame = frame;
+        self.apply_overlay(&mut frame)?;
+
+        if let Some(spec) = &self.filter_spec {
+            let filter = match self.filter {
+                Some(ref mut filter) => filter,
+                None => {
+                    let input = self.filter_input(frame.format(), (frame.width(), frame.height()));
+                    self.filter.insert(FilterGraph::parse(spec, input)?)
+                }
+            };
+            filter.push(frame)?;
+            return self.encode_filtered();
+        }
+
+        self.send_frame(frame)
+    }
+
+    /// Encode the frames that the filter has ready.
+    fn encode_filtered(&mut self) -> Result<()> {
+        loop {
+            let Some(filter) = self.filter.as_mut() else {
+                return Ok(());
+            };
+            let filter_time_base = filter.output_time_base();
+            let Some(mut frame) = filter.pull()? else {
+                return Ok(());
+            };
+            // Filters like `fps` time their output in a time base of their own.
+            let pts = Time::new(frame.pts(), filter_time_base)
+                .aligned_with_rational(self.encoder_time_base)
+                .into_value();
+            frame.set_pts(pts);
+            self.send_frame(frame)?;
+        }
+    }
+
+    /// Convert a frame to the encoder pixel format and size, and send it to the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    fn send_frame(&mut self, frame: RawFrame) -> Result<()> {
+        // Reformat frame to target pixel format, unless it already has that format.
+        let mut frame = if frame.format() == self.encoder.format()
+            && (frame.width(), frame.height()) == (self.encoder.width(), self.encoder.height())
+            && self.crop.is_none()
+        {
+            frame
+        } else {
+            self.scale(frame)?
+        };
+        if let Some(debug_stamp) = self.debug_stamp.as_ref() {
+            let timestamp = Time::new(frame.pts(), self.encoder_time_base);
+            debug_stamp.apply(&mut frame, self.frames_encoded, &timestamp)?;
+        }
+        // Producer key frame every once in a while
+        let key_frame_forced = std::mem::take(&mut self.force_key_frame);
+        let key_frame_scheduled = match (&mut self.key_frame_schedule, frame.pts()) {
+            (Some(key_frame_schedule), Some(pts)) => key_frame_schedule.is_due(pts),
+            _ => false,
+        };
+        let key_frame_interval_reached = self
+            .key_frame_interval
+            .is_some_and(|interval| self.frames_encoded % interval == 0);
+        if key_frame_interval_reached || key_frame_forced || key_frame_scheduled {
+            frame.set_kind(AvFrameType::I);
+        }
+
+        let context = ErrorContext::new("encoder.send_frame")
+            .with_stream_index(self.writer_stream_index)
+            .with_pts(frame.pts());
+        match self.encoder.send_frame(&frame) {
+            // The output queue of the encoder is full, so it only accepts the frame after it has
+            // been drained.
+            Err(AvError::Other { errno }) if errno == EAGAIN => {
+                self.drain_packets()?;
+                self.encoder.send_frame(&frame).context(context)?;
+            }
+            result => result.context(context)?,
+        }
+        self.packet_durations.frame_sent(frame.pts());
+        self.frames_encoded += 1;
+
+        // Encoders with delay (like B-frames) do not produce a packet for every frame, and may
+        // produce more than one packet at once.
+        self.drain_packets()
+    }
+
+    /// Write the container header now, instead of with the first frame. Settings that go into the
+    /// header (like [`Encoder::set_chapters`], [`Encoder::set_tag`] and
+    /// [`Encoder::set_stream_disposition`]) must be made before this, and fail with
+    /// [`Error::HeaderAlreadyWritten`] afterwards.
+    ///
+    /// By default, the header is written when the first frame (or packet) is encoded, so that
+    /// errors of the muxer (like a destination that rejects the header, or settings that the
+    /// container format does not support) only come out of [`Encoder::encode`], and an encoder
+    /// that never receives a frame leaves an empty file behind. Writing the header up front
+    /// surfaces such errors right away, and makes [`Encoder::finish`] produce a valid container
+    /// without frames if none are encoded. Calling this again after the header was written does
+    /// nothing.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings)?;
+    /// encoder.set_tag(TagTarget::Global, "title", "Live")?;
+    /// encoder.write_header()?;
+    /// ```
+    pub fn write_header(&mut self) -> Result<()> {
+        if !self.have_written_header {
+            self.write_headers()?;
+            self.have_written_header = true;
+        }
+
+        Ok(())
+    }
+
+    /// Signal to the encoder that writing has finished. This will cause any packets in the encoder
+    /// to be flushed and a trailer to be written if the container format has one (see
+    /// [`crate::FormatCaps::has_trailer`]). Packets that are still queued for interleaving (like
+    /// copied audio packets) are written as well.
+    ///
+    /// Note: If you don't call this function before dropping the encoder, it will be called
+    /// automatically. This will block the caller thread for at most the drop timeout (see
+    /// [`Encoder::drop_timeout`]). Any errors cannot be propagated in this case.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.have_written_header && !self.have_written_trailer {
+            self.have_written_trailer = true;
+            if let Some(filter) = self.filter.as_mut() {
+                filter.flush()?;
+                self.encode_filtered()?;
+            }
+            self.flush()?;
+            let (interleaving, reorders_frames) = (self.interleaving, self.reorders_frames);
+            if let Some(writer) = self.writer.as_mut() {
+                if interleaving.is_enabled(writer.output.nb_streams(), reorders_frames) {
+                    writer.flush_interleaved()?;
+                }
+                writer.write_trailer()?;
+            }
+            if let Some(segmenter) = self.segmenter.as_mut() {
+                segmenter.complete();
+            }
+            self.for_each_additional_output(|output| {
+                if interleaving.is_enabled(output.writer.output.nb_streams(), reorders_frames) {
+                    output.writer.flush_interleaved()?;
+                }
+                output.writer.write_trailer()?;
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Finish the encoder like [`Encoder::finish`], but abort writing once the timeout has passed,
+    /// for example because a network destination stalled. Only blocking I/O is aborted: flushing
+    /// the encoder itself is not. Outputs to custom I/O cannot be aborted. The encoder does not
+    /// reconnect while finishing with a timeout.
+    ///
+    /// After a timeout, the output is closed without a complete trailer:
+    ///
+    /// * MP4 and QuickTime files are not playable, since the index (`moov` atom) is written with
+    ///   the trailer. Fragmented MP4 files are playable up to the last complete fragment.
+    /// * MPEG-TS files do not have a trailer, so they are playable up to the last packet that was
+    ///   written.
+    /// * Matroska (and WebM) files are playable up to the last packet that was written, but the
+    ///   duration and the seek index (cues) are missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum amount of time that writing may take.
+    ///
+    /// # Return value
+    ///
+    /// [`Error::FinishTimeout`] if writing was aborted.
+    pub fn finish_with_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let retry_policy = self
+            .reconnect
+            .as_mut()
+            .map(|reconnect| std::mem::replace(&mut reconnect.retry_policy, RetryPolicy::never()));
+        if let Some(writer) = &self.writer {
+            writer.set_deadline(Some(timeout));
+        }
+        for output in &self.additional_outputs {
+            output.writer.set_deadline(Some(timeout));
+        }
+
+        let result = self.finish();
+        if let (Some(reconnect), Some(retry_policy)) = (self.reconnect.as_mut(), retry_policy) {
+            reconnect.retry_policy = retry_policy;
+        }
+
+        let timed_out = self.writer.as_ref().is_some_and(Writer::is_timed_out)
+            || self.additional_outputs.iter().any(|output| {
+                output.failure_policy == FailurePolicy::Abort && output.writer.is_timed_out()
+            });
+        if timed_out {
+            // Keep the deadline in place, so that closing the output does not block either.
+            return Err(Error::FinishTimeout);
+        }
+
+        if let Some(writer) = &self.writer {
+            writer.set_deadline(None);
+        }
+        for output in &self.additional_outputs {
+            output.writer.set_deadline(None);
+        }
+
+        result
+    }
+
+    /// Flush buffered output to the destination.
+    ///
+    /// Note that this is distinct from [`Encoder::finish`], which flushes the encoder itself.
+    /// Packets that are still being processed by the encoder are not written.
+    pub fn flush_output(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        self.for_each_additional_output(|output| output.writer.flush())
+    }
+
+    /// Change the size of the encoded video mid-stream, for example to switch to another rung of
+    /// an adaptive streaming ladder. The encoder is flushed and opened again at the new size with
+    /// the same settings, and encoding continues in the same output stream. The first frame after
+    /// the switch is a key frame, and timestamps continue as they were.
+    ///
+    /// Input frames are expected to have the new size from then on, unless the encoder crops them
+    /// (see [`Settings::with_crop`]): frames are then still cropped from frames of the input size,
+    /// and scaled to the new size.
+    ///
+    /// Only container formats that carry the codec headers in the stream can change the size of
+    /// a stream: MPEG-TS (and HLS with MPEG-TS segments). Encoders without a container (see
+    /// [`Encoder::new_packetized`]) can change size as well. Other formats, like MP4 (also when
+    /// fragmented, as the init segment holds the codec headers) and Matroska, fail with
+    /// [`Error::ContainerDoesNotSupportReconfiguration`].
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - New width of the encoded video.
+    /// * `height` - New height of the encoded video.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, false);
+    /// let mut encoder = Encoder::new(&PathBuf::from("ladder.ts").into(), settings)?;
+    /// encoder.encode_raw(RawFrame::new(PixelFormat::RGB24, 1280, 720))?;
+    /// encoder.reconfigure(854, 480)?;
+    /// encoder.encode_raw(RawFrame::new(PixelFormat::RGB24, 854, 480))?;
+    /// ```
+    pub fn reconfigure(&mut self, width: u32, height: u32) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidOptions(format!(
+                "invalid encoder size: {width}x{height}"
+            )));
+        }
+        for writer in self
+            .writer
+            .iter()
+            .chain(self.additional_outputs.iter().map(|output| &output.writer))
+        {
+            let format = writer.output.format().name().to_string();
+            if !format
+                .split(',')
+                .any(|name| RECONFIGURABLE_FORMATS.contains(&name))
+            {
+                return Err(Error::ContainerDoesNotSupportReconfiguration(format));
+            }
+        }
+
+        // Everything that was encoded at the old size is written first.
+        if self.have_written_header {
+            if let Some(filter) = self.filter.as_mut() {
+                filter.flush()?;
+                self.encode_filtered()?;
+            }
+            self.flush()?;
+        }
+        // The filter graph is set up again with the next frame.
+        self.filter = None;
+
+        self.settings.width = width;
+        self.settings.height = height;
+        let (encoder, _) = open_encoder(&self.settings, self.global_header)?;
+        self.encoder = encoder;
+        self.encoder_time_base = get_encoder_time_base(&self.encoder);
+        self.reorders_frames = encoder_reorders_frames(&self.encoder);
+
+        // The stream keeps its index, but takes on the parameters of the new encoder.
+        if let Some(writer) = self.writer.as_mut() {
+            if let Some(mut stream) = writer.output.stream_mut(self.writer_stream_index) {
+                stream.set_parameters(&self.encoder);
+            }
+        }
+        for output in &mut self.additional_outputs {
+            if let Some(mut stream) = output.writer.output.stream_mut(output.stream_index) {
+                stream.set_parameters(&self.encoder);
+            }
+        }
+
+        // Frames that the encoder crops keep the input size. Otherwise they have the new size,
+        // with the width and height swapped if a transform swaps them.
+        if self.settings.input_size.is_none() {
+            (self.scaler_width, self.scaler_height) = if self.swaps_dimensions {
+                (height, width)
+            } else {
+                (width, height)
+            };
+        }
+        self.scalers.clear();
+        self.force_key_frame = true;
+
+        Ok(())
+    }
+
+    /// Change the bitrate while encoding, for example to adapt to the bandwidth of the network
+    /// when streaming. Unlike [`Encoder::reconfigure`], the encoder is not opened again: it picks
+    /// up the new bitrate with the next frame, which is encoded as a key frame.
+    ///
+    /// The encoder must use a rate control mode with a bitrate (see
+    /// [`Settings::with_rate_control`]). With [`RateControl::TargetBitrate`], the target changes
+    /// (and the maximum bitrate and buffer size, if set, change along with it). With
+    /// [`RateControl::ConstrainedQuality`], the maximum bitrate changes.
+    ///
+    /// Only `libx264` (and `libx264rgb`) and the NVENC encoders (like `h264_nvenc`) can change
+    /// their bitrate while encoding. `libx264` needs a maximum bitrate and buffer size for that
+    /// (the `maxrate` and `bufsize` options), which constrained quality sets. Other encoders, and
+    /// encoders in constant quality mode, fail with [`Error::ReconfigurationNotSupported`].
+    ///
+    /// # Arguments
+    ///
+    /// * `bitrate` - New bitrate in bits per second.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, true).with_rate_control(
+    ///     RateControl::ConstrainedQuality {
+    ///         quality: 23,
+    ///         max_bitrate: 4_000_000,
+    ///     },
+    /// );
+    /// let mut encoder = Encoder::new(&Url::parse("rtmp://example.com/live/stream")?.into(), settings)?;
+    /// // ...
+    /// // The network degrades.
+    /// encoder.set_bitrate(1_000_000)?;
+    /// ```
+    pub fn set_bitrate(&mut self, bitrate: u64) -> Result<()> {
+        if bitrate == 0 {
+            return Err(Error::InvalidOptions(
+                "bitrate must not be zero".to_string(),
+            ));
+        }
+        let encoder_name = self
+            .settings
+            .codec()
+            .map_or_else(|| "encoder".to_string(), |codec| codec.name().to_string());
+        let not_supported = |reason: &str| Error::ReconfigurationNotSupported {
+            encoder: encoder_name.clone(),
+            reason: reason.to_string(),
+        };
+        let is_x264 = matches!(encoder_name.as_str(), "libx264" | "libx264rgb");
+        if !is_x264 && !encoder_name.ends_with("_nvenc") {
+            return Err(not_supported("the encoder cannot be reconfigured"));
+        }
+
+        let (bit_rate, max_rate, buffer_size) = encoder_rate_control(&self.encoder);
+        let (rate_control, (bit_rate, max_rate, buffer_size)) = match self.settings.rate_control {
+            Some(RateControl::TargetBitrate(_)) => {
+                if is_x264 && (max_rate == 0 || buffer_size == 0) {
+                    return Err(not_supported(
+                        "libx264 needs a maximum bitrate and buffer size (the maxrate and \
+                         bufsize options) to change its bitrate",
+                    ));
+                }
+                let scale = |value: u64| {
+                    (value as f64 * bitrate as f64 / bit_rate.max(1) as f64).round() as u64
+                };
+                (
+                    RateControl::TargetBitrate(bitrate),
+                    (bitrate, scale(max_rate), scale(buffer_size)),
+                )
+            }
+            Some(RateControl::ConstrainedQuality { quality, .. }) => (
+                RateControl::ConstrainedQuality {
+                    quality,
+                    max_bitrate: bitrate,
+                },
+                (bit_rate, bitrate, bitrate * 2),
+            ),
+            _ => {
+                return Err(not_supported(
+                    "the rate control mode has no bitrate (see Settings::with_rate_control)",
+                ))
+            }
+        };
+        encoder_set_rate_control(&mut self.encoder, bit_rate, max_rate, buffer_size);
+        // The settings keep the new bitrate for when the encoder is opened again.
+        self.settings.rate_control = Some(rate_control);
+        self.force_key_frame = true;
+
+        Ok(())
+    }
+
+    /// Set the chapters of the output, replacing any chapters set before. This must be called
+    /// before encoding the first frame, since chapters are written with the header. Only the
+    /// primary output receives chapters.
+    ///
+    /// Chapters are supported for Matroska (and WebM), MP4 and QuickTime outputs. Other container
+    /// formats fail with [`Error::ChaptersNotSupported`].
+    ///
+    /// # Arguments
+    ///
+    /// * `chapters` - Chapters to write.
+    pub fn set_chapters(&mut self, chapters: &[Chapter]) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        write_chapters(&mut self.writer_mut()?.output, chapters)
+    }
+
+    /// Set a tag (a metadata key and value) of the output, like the artist or the name of a track.
+    /// This must be called before encoding the first frame, since tags are written with the
+    /// header. Only the primary output receives tags.
+    ///
+    /// Matroska (and WebM) stores tags in its `Tags` element. Other container formats keep the
+    /// tags they can store.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - What the tag applies to. The encoded video stream is stream 0.
+    /// * `key` - Key of the tag, like `ARTIST`.
+    /// * `value` - Value of the tag.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// encoder.set_tag(TagTarget::Global, "ARTIST", "Someone")?;
+    /// encoder.set_tag(TagTarget::Stream(0), "COMMENT", "Main camera")?;
+    /// ```
+    pub fn set_tag(&mut self, target: TagTarget, key: &str, value: &str) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        set_tag(&mut self.writer_mut()?.output, target, key, value)
+    }
+
+    /// Attach a file to the output, like a font for soft subtitles. This must be called before
+    /// encoding the first frame, since the header is written at that point. Only the primary
+    /// output receives attachments.
+    ///
+    /// Attachments are only supported for Matroska outputs. Other container formats fail with
+    /// [`Error::AttachmentsNotSupported`].
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - Name of the file.
+    /// * `mime_type` - MIME type of the file, like `font/ttf`.
+    /// * `data` - Contents of the file.
+    pub fn add_attachment(&mut self, filename: &str, mime_type: &str, data: &[u8]) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        add_attachment(&mut self.writer_mut()?.output, filename, mime_type, data)
+    }
+
+    /// Attach cover art to the output. This must be called before encoding the first frame, since
+    /// the header is written at that point. Only the primary output receives cover art.
+    ///
+    /// Cover art is supported for Matroska, MP4 and QuickTime outputs. Other container formats fail
+    /// with [`Error::CoverArtNotSupported`].
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Encoded picture.
+    /// * `mime_type` - MIME type of the picture, either `image/jpeg` or `image/png`.
+    pub fn attach_cover_art(&mut self, data: &[u8], mime_type: &str) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        if let Some(packet) = add_cover_art(&mut self.writer_mut()?.output, data, mime_type)? {
+            self.attached_pictures.push(packet);
+        }
+        Ok(())
+    }
+
+    /// Add an output stream that packets are copied into without encoding, based on an input stream
+    /// from a reader. Stream metadata (like the language) and dispositions are copied as well. This
+    /// must be called before encoding the first frame, since the header is written at that point.
+    /// After that, this fails with [`Error::HeaderAlreadyWritten`].
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_info` - Stream information of the input stream to copy.
+    ///
+    /// # Return value
+    ///
+    /// Index of the output stream, to pass to [`Encoder::write_copy`].
+    pub(crate) fn add_copy_stream(&mut self, stream_info: StreamInfo) -> Result<usize> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        let metadata = stream_info.metadata().clone();
+        let disposition = stream_info.disposition();
+        let (_, codec_parameters, _) = stream_info.into_parts();
+        let mut codec_parameters = codec_parameters.clone();
+        clear_codec_tag(&mut codec_parameters);
+
+        let writer = self.writer_mut()?;
+        if codec_parameters.medium() == AvMediaType::Data
+            && output_format_supports_codec(&writer.output, codec_parameters.id()) == Some(false)
+        {
+            return Err(Error::DataStreamsNotSupported(
+                writer.output.format().name().to_string(),
+            ));
+        }
+        let mut writer_stream = writer
+            .output
+            .add_stream(ffmpeg::encoder::find(codec_parameters.id()))?;
+        writer_stream.set_parameters(codec_parameters);
+        writer_stream.set_metadata(metadata);
+        let writer_stream_index = writer_stream.index();
+        set_stream_disposition(&mut writer.output, writer_stream_index, disposition);
+
+        Ok(writer_stream_index)
+    }
+
+    /// Add an output stream for packets that were encoded elsewhere, like by a hardware encoder
+    /// outside of ffmpeg. Its packets are written with [`Encoder::write_preencoded`], muxed along
+    /// with the frames that this encoder encodes. This must be called before encoding the first
+    /// frame (or writing the first pre-encoded packet), since the header is written at that point.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Codec parameters of the packets.
+    ///
+    /// # Return value
+    ///
+    /// Index of the output stream, to pass to [`Encoder::write_preencoded`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let stream = encoder.add_preencoded_stream(&CodecParams::new_video(CodecId::H264, 1920, 1080))?;
+    /// for access_unit in hardware_encoder {
+    ///     encoder.write_preencoded(
+    ///         stream,
+    ///         &access_unit.data,
+    ///         access_unit.pts,
+    ///         access_unit.dts,
+    ///         access_unit.is_key,
+    ///     )?;
+    /// }
+    /// ```
+    pub fn add_preencoded_stream(&mut self, params: &CodecParams) -> Result<usize> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        let codec_parameters = params.to_parameters()?;
+        let writer = self.writer_mut()?;
+        let mut writer_stream = writer
+            .output
+            .add_stream(ffmpeg::encoder::find(params.codec_id()))?;
+        writer_stream.set_parameters(codec_parameters);
+        let writer_stream_index = writer_stream.index();
+        self.preencoded_streams.push(writer_stream_index);
+
+        Ok(writer_stream_index)
+    }
+
+    /// Write a packet that was encoded elsewhere to a stream added with
+    /// [`Encoder::add_preencoded_stream`]. Timestamps are rescaled to the time base of the output
+    /// stream, and the packet is interleaved with the other streams like encoded packets are.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the output stream.
+    /// * `data` - Encoded data, like an H.264 access unit.
+    /// * `pts` - Presentation timestamp.
+    /// * `dts` - Decoding timestamp. With frame reordering (B-frames), this differs from the
+    ///   presentation timestamp.
+    /// * `keyframe` - Whether or not the packet is a key frame.
+    pub fn write_preencoded(
+        &mut self,
+        stream_index: usize,
+        data: &[u8],
+        pts: Time,
+        dts: Time,
+        keyframe: bool,
+    ) -> Result<()> {
+        if !self.preencoded_streams.contains(&stream_index) {
+            return Err(AvError::StreamNotFound.into());
+        }
+
+        let (pts, time_base) = pts.into_parts();
+        let mut packet = AvPacket::copy(data);
+        packet.set_pts(pts);
+        packet.set_dts(dts.aligned_with_rational(time_base).into_value());
+        if keyframe {
+            packet.set_flags(AvPacketFlags::KEY);
+        }
+
+        self.write_copy(Packet::new(packet, time_base), stream_index)
+    }
+
+    /// Set the disposition flags of an output stream of the primary output, replacing the flags
+    /// it has. Players use these to pick the streams to play, like the default audio track. This
+    /// must be called before encoding the first frame, since the header is written at that point.
+    ///
+    /// The encoded video stream is the first output stream. Streams that are added afterwards
+    /// (like cover art) follow in the order in which they were added.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the output stream.
+    /// * `flags` - Disposition flags to set.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// encoder.set_stream_disposition(0, &[DispositionFlag::Default])?;
+    /// ```
+    pub fn set_stream_disposition(
+        &mut self,
+        stream_index: usize,
+        flags: &[DispositionFlag],
+    ) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        let writer = self.writer_mut()?;
+        if stream_index >= writer.output.nb_streams() as usize {
+            return Err(AvError::StreamNotFound.into());
+        }
+        set_stream_disposition(
+            &mut writer.output,
+            stream_index,
+            DispositionFlag::to_bits(flags),
+        );
+        Ok(())
+    }
+
+    /// Set the raw disposition flags (like the default and forced flags) of the encoded video
+    /// stream. This must be called before encoding the first frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `disposition` - Raw disposition flags.
+    pub(crate) fn set_video_stream_disposition(&mut self, disposition: i32) {
+        if let Some(writer) = self.writer.as_mut() {
+            set_stream_disposition(&mut writer.output, self.writer_stream_index, disposition);
+        }
+    }
+
+    /// Pick the codec to re-encode an audio stream with: the codec of the source if the container
+    /// of the primary output supports it, or else the default audio codec of the container.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_codec_id` - Codec of the source stream.
+    ///
+    /// # Return value
+    ///
+    /// The codec, and whether or not the container needs global headers (see
+    /// [`AvCodecFlags::GLOBAL_HEADER`]).
+    pub(crate) fn audio_codec_for(&self, source_codec_id: AvCodecId) -> Result<(AvCodecId, bool)> {
+        let output = &self.writer.as_ref().ok_or(Error::NoContainer)?.output;
+        let codec_id = if ffmpeg::encoder::find(source_codec_id).is_some()
+            && output_format_supports_codec(output, source_codec_id) != Some(false)
+        {
+            source_codec_id
+        } else {
+            output.format().codec(&"", AvMediaType::Audio)
+        };
+        let global_header = self
+            .writer
+            .as_ref()
+            .is_some_and(|writer| writer.format_capabilities().needs_global_header);
+
+        Ok((codec_id, global_header))
+    }
+
+    /// Write a packet to an output stream added with [`Encoder::add_copy_stream`], without
+    /// encoding it.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write.
+    /// * `stream_index` - Index of the output stream.
+    pub(crate) fn write_copy(&mut self, packet: Packet, stream_index: usize) -> Result<()> {
+        self.write_header()?;
+
+        let (interleaving, reorders_frames) = (self.interleaving, self.reorders_frames);
+        let writer = self.writer_mut()?;
+        let interleaved = interleaving.is_enabled(writer.output.nb_streams(), reorders_frames);
+        let (mut packet, packet_time_base) = packet.into_inner_parts();
+        let stream_time_base = writer
+            .output
+            .stream(stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .time_base();
+        packet.set_stream(stream_index);
+        packet.set_position(-1);
+        packet.rescale_ts(packet_time_base, stream_time_base);
+
+        if interleaved {
+            writer.write_interleaved(&mut packet)
+        } else {
+            writer.write(&mut packet)
+        }
+    }
+
+    /// Create an encoder from a `FileWriter` instance.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - `FileWriter` to create encoder from.
+    /// * `settings` - Encoder settings to use.
+    fn from_writer(writer: Writer, settings: Settings) -> Result<Self> {
+        Self::from_writers(Some(writer), Vec::new(), settings)
+    }
+
+    /// Create an encoder from a primary `Writer` and additional writers that receive the same
+    /// encoded stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Primary writer to create encoder from, or `None` for an encoder without a
+    ///   container.
+    /// * `additional_writers` - Additional writers and what to do when writing to them fails.
+    /// * `settings` - Encoder settings to use.
+    fn from_writers(
+        mut writer: Option<Writer>,
+        additional_writers: Vec<(Writer, FailurePolicy)>,
+        settings: Settings,
+    ) -> Result<Self> {
+        let global_header = writer
+            .iter()
+            .chain(additional_writers.iter().map(|(writer, _)| writer))
+            .any(|writer| writer.format_capabilities().needs_global_header);
+
+        let (encoder, ignored_options) = open_encoder(&settings, global_header)?;
+        let encoder_time_base = get_encoder_time_base(&encoder);
+        let reorders_frames = encoder_reorders_frames(&encoder);
+
+        let writer_stream_index = match writer.as_mut() {
+            Some(writer) => {
+                add_stream_for_encoder(writer, &encoder, settings.hdr_metadata.as_ref())?
+            }
+            None => 0,
+        };
+
+        let additional_outputs = additional_writers
+            .into_iter()
+            .map(|(mut writer, failure_policy)| {
+                let stream_index =
+                    add_stream_for_encoder(&mut writer, &encoder, settings.hdr_metadata.as_ref())?;
+                Ok(AdditionalOutput {
+                    writer,
+                    stream_index,
+                    failure_policy,
+                    error: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Key frames are either placed on a grid of presentation time, or every so many frames.
+        let key_frame_schedule = settings.key_frame_every.map(|interval| {
+            KeyFrameSchedule::new(
+                Time::from(interval)
+                    .with_time_base(encoder_time_base)
+                    .into_value()
+                    .unwrap_or_default(),
+            )
+        });
+        let key_frame_interval = match key_frame_schedule {
+            Some(_) => None,
+            None => Some(
+                settings
+                    .key_frame_interval
+                    .unwrap_or(Self::KEY_FRAME_INTERVAL),
+            ),
+        };
+
+        // Input frames have the encoder size, unless they are cropped to it.
+        let (scaler_width, scaler_height) = settings.input_size();
+        let mut scaler = CropScaler::new(
+            AvPixel::BGRA,
+            (scaler_width, scaler_height),
+            settings.crop,
+            encoder.format(),
+            (encoder.width(), encoder.height()),
+            AvScalerFlags::empty(),
+        )?;
+        let (color_space, color_space_out) =
+            settings.scale_color_spaces((ColorSpace::Unspecified, scaler_height));
+        scaler.set_color_spaces(color_space, color_space_out)?;
+
+        Ok(Self {
+            writer,
+            writer_stream_index,
+            packet_sink: None,
+            segmenter: None,
+            additional_outputs,
+            encoder,
+            settings: settings.to_owned_settings(),
+            global_header,
+            encoder_time_base,
+            interleaving: Interleaving::Auto,
+            reorders_frames,
+            scalers: vec![scaler],
+            scaler_width,
+            scaler_height,
+            swaps_dimensions: false,
+            crop: settings.crop,
+            overlay: None,
+            debug_stamp: None,
+            filter_spec: None,
+            filter: None,
+            key_frame_interval,
+            force_key_frame: false,
+            key_frame_schedule,
+            pacer: None,
+            flush_schedule: None,
+            frames_encoded: 0,
+            packets_written: 0,
+            packet_stats: PacketStats::default(),
+            packet_durations: PacketDurations::new(&settings, encoder_time_base),
+            reconnect: None,
+            drop_timeout: Some(Self::DEFAULT_DROP_TIMEOUT),
+            have_written_header: false,
+            attached_pictures: Vec::new(),
+            preencoded_streams: Vec::new(),
+            ignored_options,
+            have_written_trailer: false,
+        })
+    }
+
+    /// Apply cropping (if configured) and scaling (or pixel reformatting in this case) on the frame
+    /// with the scaler for the pixel format of the frame. The scaler is created the first time a
+    /// frame with that pixel format comes in.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to rescale.
+    fn scale(&mut self, frame: RawFrame) -> Result<RawFrame> {
+        // Frames have the input size, unless a filter changes their size.
+        let size = (frame.width(), frame.height());
+        let index = match self
+            .scalers
+            .iter()
+            .position(|scaler| scaler.format() == frame.format() && scaler.size() == size)
+        {
+            Some(index) => index,
+            None => {
+                let mut scaler = CropScaler::new(
+                    frame.format(),
+                    size,
+                    self.crop,
+                    self.encoder.format(),
+                    (self.encoder.width(), self.encoder.height()),
+                    // Frames with more than 8 bits per component are scaled with accurate
+                    // rounding, so that their precision is kept.
+                    if pixel_format_is_high_bit_depth(frame.format()) {
+                        AvScalerFlags::ACCURATE_RND | AvScalerFlags::FULL_CHR_H_INT
+                    } else {
+                        AvScalerFlags::empty()
+                    },
+                )?;
+                let (color_space, color_space_out) = self
+                    .settings
+                    .scale_color_spaces((frame.color_space(), frame.height()));
+                scaler.set_color_spaces(color_space, color_space_out)?;
+                self.scalers.push(scaler);
+                self.scalers.len() - 1
+            }
+        };
+
+        let mut frame_scaled = self.scalers[index].run(&frame)?;
+        // Copy over PTS, side data (like captions) and metadata from old frame.
+        frame_scaled.set_pts(frame.pts());
+        copy_frame_side_data(&frame, &mut frame_scaled);
+        frame_scaled.set_metadata(ffmpeg::util::frame::Frame::metadata(&frame).to_owned());
+
+        Ok(frame_scaled)
+    }
+
+    /// Composite the overlay (if any) onto the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to composite onto.
+    fn apply_overlay(&mut self, frame: &mut RawFrame) -> Result<()> {
+        // The overlay is positioned on the encoded frame, which may be cropped from the input.
+        let offset = self.crop.map(|crop| (crop.x, crop.y)).unwrap_or((0, 0));
+        match self.overlay {
+            #[cfg(feature = "ndarray")]
+            Some(OverlaySource::Static(ref overlay)) => overlay.composite(frame, offset),
+            Some(OverlaySource::Dynamic(ref mut callback)) => {
+                match callback(&Time::new(frame.pts(), self.encoder_time_base)) {
+                    Some(overlay) => overlay.composite(frame, offset),
+                    None => Ok(()),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Pull an encoded packet from the encoder.
+    ///
+    /// # Return value
+    ///
+    /// The packet, or `None` if the encoder needs more frames (`EAGAIN`) or has been flushed
+    /// completely.
+    fn encoder_receive_packet(&mut self) -> Result<Option<AvPacket>> {
+        let mut packet = AvPacket::empty();
+        let encode_result = self.encoder.receive_packet(&mut packet);
+        match encode_result {
+            Ok(()) => Ok(Some(packet)),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(AvError::Eof) => Ok(None),
+            Err(err) => Err(ErrorContext::new("encoder.receive_packet")
+                .with_stream_index(self.writer_stream_index)
+                .wrap(err)),
+        }
+    }
+
+    /// Create an error for an input frame that does not have the size the encoder expects.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the frame.
+    /// * `height` - Height of the frame.
+    fn invalid_frame_size(&self, width: usize, height: usize) -> Error {
+        frame_size_error(self.input_size(), width, height)
+    }
+
+    /// Describe the frames that go into the filter graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel_format` - Pixel format of the frames.
+    /// * `(width, height)` - Size of the frames.
+    fn filter_input(&self, pixel_format: AvPixel, (width, height): (u32, u32)) -> FilterInput {
+        FilterInput {
+            width,
+            height,
+            pixel_format,
+            time_base: self.encoder_time_base,
+            frame_rate: None,
+        }
+    }
+
+    /// Get the size of input frames as width and height.
+    pub(crate) fn input_size(&self) -> (u32, u32) {
+        (self.scaler_width, self.scaler_height)
+    }
+
+    /// Get the index of the video stream in the primary output.
+    pub(crate) fn video_stream_index(&self) -> usize {
+        self.writer_stream_index
+    }
+
+    /// Add a flag to the `movflags` of the primary output (see [`Writer::add_movflag`]). This must
+    /// be done before the header is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `flag` - Flag to add.
+    pub(crate) fn add_movflag(&mut self, flag: MovFlag) -> Result<()> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        self.writer_mut()?.add_movflag(flag);
+        Ok(())
+    }
+
+    /// Get the primary writer. Encoders without a container have none.
+    fn writer_mut(&mut self) -> Result<&mut Writer> {
+        self.writer.as_mut().ok_or(Error::NoContainer)
+    }
+
+    /// Acquire the time base of the output stream.
+    fn stream_time_base(&self) -> Result<AvRational> {
+        let writer = self.writer.as_ref().ok_or(Error::NoContainer)?;
+        Ok(writer
+            .output
+            .stream(self.writer_stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .time_base())
+    }
+
+    /// Write the container headers of the outputs. For RTMP streams, failures are reported as
+    /// [`Error::ConnectionFailed`].
+    fn write_headers(&mut self) -> Result<()> {
+        let deterministic = self.settings.deterministic;
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        if deterministic {
+            make_output_deterministic(&mut writer.output);
+        }
+        let result = writer.write_header();
+        if self.reconnect.is_some() {
+            result.map_err(Error::into_connection_error)?;
+        } else {
+            result?;
+        }
+
+        for mut packet in std::mem::take(&mut self.attached_pictures) {
+            writer.write(&mut packet)?;
+        }
+
+        self.for_each_additional_output(|output| {
+            if deterministic {
+                make_output_deterministic(&mut output.writer.output);
+            }
+            output.writer.write_header()?;
+            Ok(())
+        })
+    }
+
+    /// Apply `f` to each additional output that has not failed yet. If `f` fails, the output
+    /// either fails encoding or is marked as failed, depending on its failure policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Function to apply.
+    fn for_each_additional_output(
+        &mut self,
+        mut f: impl FnMut(&mut AdditionalOutput) -> Result<()>,
+    ) -> Result<()> {
+        for output in self
+            .additional_outputs
+            .iter_mut()
+            .filter(|output| output.error.is_none())
+        {
+            if let Err(err) = f(output) {
+                match output.failure_policy {
+                    FailurePolicy::Abort => return Err(err),
+                    FailurePolicy::Ignore => {
+                        tracing::warn!(target: "video", "writing to output failed: {err}");
+                        output.error = Some(err);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write encoded packet to output stream. If the encoder was configured to reconnect, writing
+    /// is retried on a new connection if it fails. Encoders without a container hand the packet to
+    /// the packet sink instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Encoded packet.
+    fn write(&mut self, mut packet: AvPacket) -> Result<()> {
+        if self.settings.measures_psnr() {
+            let size = (self.encoder.width(), self.encoder.height());
+            let psnr = packet
+                .side_data()
+                .find(|side_data| side_data.kind() == AvPacketSideDataType::QualityStats)
+                .and_then(|side_data| frame_psnr(side_data.data(), self.encoder.format(), size));
+            if let Some(psnr) = psnr {
+                self.packet_stats.record_psnr(psnr);
+            }
+        }
+        self.packet_stats.record(
+            packet.pts(),
+            packet.duration(),
+            packet.size(),
+            packet.is_key(),
+        );
+
+        if let Some(packet_sink) = self.packet_sink.as_mut() {
+            packet_sink.send(packet, self.encoder_time_base);
+            self.packets_written += 1;
+            return Ok(());
+        }
+
+        // Writing consumes the packet, so keep a copy for any additional outputs.
+        let packet_copy = (!self.additional_outputs.is_empty()).then(|| packet.clone());
+
+        let segment_offset = match (self.segmenter.as_mut(), packet.pts()) {
+            (Some(segmenter), Some(pts)) => {
+                if segmenter.starts_segment(pts, packet.is_key()) {
+                    self.next_segment(pts)?;
+                }
+                self.segmenter.as_ref().map(Segmenter::timestamp_offset)
+            }
+            _ => None,
+        };
+        let segment_packet = (
+            packet.pts().unwrap_or_default(),
+            packet.duration(),
+            packet.size(),
+        );
+        if let Some(offset) = segment_offset.filter(|offset| *offset != 0) {
+            packet.set_pts(packet.pts().map(|pts| pts - offset));
+            packet.set_dts(packet.dts().map(|dts| dts - offset));
+        }
+
+        if let (Some(pacer), Some(dts)) = (&mut self.pacer, packet.dts()) {
+            let timestamp = Time::new(Some(dts), self.encoder_time_base);
+            std::thread::sleep(pacer.delay(timestamp.as_secs_f64(), Instant::now()));
+        }
+
+        let mut attempt = 0;
+        loop {
+            // Writing consumes the packet, so every attempt writes a copy of it.
+            let mut attempt_packet = packet.clone();
+            if let Some(reconnect) = self.reconnect.as_mut() {
+                if !reconnect.rebase(&mut attempt_packet) {
+                    break;
+                }
+            }
+            let stream_time_base = self.stream_time_base()?;
+            attempt_packet.set_stream(self.writer_stream_index);
+            attempt_packet.set_position(-1);
+            attempt_packet.rescale_ts(self.encoder_time_base, stream_time_base);
+
+            let (interleaving, reorders_frames) = (self.interleaving, self.reorders_frames);
+            let writer = self.writer_mut()?;
+            let result = if interleaving.is_enabled(writer.output.nb_streams(), reorders_frames) {
+                writer.write_interleaved(&mut attempt_packet)
+            } else {
+                writer.write(&mut attempt_packet)
+            };
+
+            match (result, &self.reconnect) {
+                (Ok(()), _) => break,
+                (Err(err), Some(reconnect))
+                    if err.backend_error().is_some()
+                        && attempt < reconnect.retry_policy.max_attempts =>
+                {
+                    tracing::warn!(target: "video", "connection lost ({err}), reconnecting");
+                    std::thread::sleep(reconnect.retry_policy.backoff(attempt));
+                    attempt += 1;
+                    if let Err(err) = self.reconnect() {
+                        tracing::warn!(target: "video", "failed to reconnect: {err}");
+                    }
+                }
+                (Err(err), Some(_)) => return Err(err.into_connection_error()),
+                (Err(err), _) => return Err(err),
+            }
+        }
+
+        if let Some(packet_copy) = packet_copy {
+            let encoder_time_base = self.encoder_time_base;
+            let (interleaving, reorders_frames) = (self.interleaving, self.reorders_frames);
+            self.for_each_additional_output(|output| {
+                let mut packet = packet_copy.clone();
+                packet.set_stream(output.stream_index);
+                packet.set_position(-1);
+                packet.rescale_ts(encoder_time_base, output.stream_time_base()?);
+                if interleaving.is_enabled(output.writer.output.nb_streams(), reorders_frames) {
+                    output.writer.write_interleaved(&mut packet)
+                } else {
+                    output.writer.write(&mut packet)
+                }
+            })?;
+        }
+
+        if let Some(flush_schedule) = self.flush_schedule.as_mut() {
+            if flush_schedule.is_due(Instant::now()) {
+                self.writer_mut()?.flush()?;
+            }
+        }
+
+        if let Some(segmenter) = self.segmenter.as_mut() {
+            let (pts, duration, size) = segment_packet;
+            // Force a key frame to start the next segment at once when it has become too large.
+            if segmenter.record(pts, duration, size) {
+                self.force_key_frame = true;
+            }
+        }
+
+        self.packets_written += 1;
+        Ok(())
+    }
+
+    /// Replace the writer with a new connection to the same destination, with the same options,
+    /// and write the header. The next frame is encoded as a key frame so that the server can resume
+    /// decoding immediately.
+    fn reconnect(&mut self) -> Result<()> {
+        let Some(reconnect) = self.reconnect.as_mut() else {
+            return Ok(());
+        };
+
+        let mut writer = reconnect.connect()?;
+        reconnect.awaiting_key_frame = true;
+        let writer_stream_index = add_stream_for_encoder(
+            &mut writer,
+            &self.encoder,
+            self.settings.hdr_metadata.as_ref(),
+        )?;
+        if self.settings.deterministic {
+            make_output_deterministic(&mut writer.output);
+        }
+        writer
+            .write_header()
+            .map_err(Error::into_connection_error)?;
+
+        self.writer = Some(writer);
+        self.writer_stream_index = writer_stream_index;
+        self.force_key_frame = true;
+
+        Ok(())
+    }
+
+    /// Finish the current segment of segmented output, and open the next one.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Presentation timestamp of the first packet of the next segment.
+    fn next_segment(&mut self, pts: i64) -> Result<()> {
+        self.writer_mut()?.write_trailer()?;
+        let Some(segmenter) = self.segmenter.as_mut() else {
+            return Ok(());
+        };
+        let path = segmenter.next(pts);
+
+        let mut writer = Writer::new(&Locator::Path(path))?;
+        let writer_stream_index = add_stream_for_encoder(
+            &mut writer,
+            &self.encoder,
+            self.settings.hdr_metadata.as_ref(),
+        )?;
+        if self.settings.deterministic {
+            make_output_deterministic(&mut writer.output);
+        }
+        writer.write_header()?;
+
+        self.writer = Some(writer);
+        self.writer_stream_index = writer_stream_index;
+
+        Ok(())
+    }
+
+    /// Flush the encoder, drain any packets that still need processing.
+    fn flush(&mut self) -> Result<()> {
+        // Notify the encoder that the last frame has been sent.
+        self.encoder.send_eof().operation("encoder.send_eof")?;
+
+        // We need to drain the items still in the encoders queue.
+        self.drain_packets()?;
+        if let Some(packet) = self.packet_durations.finish() {
+            self.write(packet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write all packets that the encoder has ready, until it needs more frames or has been
+    /// flushed completely.
+    fn drain_packets(&mut self) -> Result<()> {
+        while let Some(packet) = self.encoder_receive_packet()? {
+            if let Some(packet) = self.packet_durations.push(packet) {
+                self.write(packet)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Statistics of the output of an encoder. See [`Encoder::stats`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeStats {
+    /// Number of frames sent to the encoder.
+    pub frames: u64,
+    /// Number of encoded packets.
+    pub packets: u64,
+    /// Total size of the encoded packets in bytes. This excludes the overhead of the container
+    /// (headers, indexes and framing), so the output file is somewhat larger.
+    pub bytes: u64,
+    /// Duration from the start of the first packet to the end of the last packet, in the time
+    /// base of the encoder.
+    pub duration: Time,
+    /// Average bitrate of the encoded packets in bits per second, or zero if the duration is
+    /// zero.
+    pub average_bitrate: u64,
+    /// Number of key frame packets.
+    pub keyframes: u64,
+    /// Average PSNR of the encoded frames in decibels, over all planes, if it was measured (see
+    /// [`Settings::with_quality_metrics`]). Frames that are encoded without loss count as 100 dB.
+    pub psnr_avg: Option<f64>,
+    /// How the index of an MP4 file was placed at the start of the file, once the encoder has
+    /// finished, if space was reserved for it (see
+    /// [`WriterBuilder::faststart_with_reserved_moov`]).
+    pub faststart: Option<FaststartPath>,
+}
+
+/// Quality metrics to measure while encoding. See [`Settings::with_quality_metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityMetrics {
+    /// Peak signal-to-noise ratio.
+    pub psnr: bool,
+    /// Structural similarity.
+    pub ssim: bool,
+}
+
+/// Encoders that report the PSNR of encoded frames, in the quality statistics of the packets.
+const PSNR_ENCODERS: &[&str] = &[
+    "libx264",
+    "libx264rgb",
+    "libvpx",
+    "libvpx-vp9",
+    "libaom-av1",
+    "mpeg1video",
+    "mpeg2video",
+    "mpeg4",
+    "mjpeg",
+];
+
+/// PSNR of frames without any difference, as `libx264` reports it.
+const PSNR_LOSSLESS: f64 = 100.0;
+
+/// Compute the PSNR of a frame from the quality statistics of its packet
+/// (`AV_PKT_DATA_QUALITY_STATS`): the quality (4 bytes), the picture type, the number of error
+/// values, two reserved bytes and then the sum of squared errors of each plane (8 bytes each), all
+/// little endian.
+///
+/// # Arguments
+///
+/// * `quality_stats` - Quality statistics of the packet.
+/// * `pixel_format` - Pixel format of the encoder.
+/// * `size` - Width and height of the frames.
+///
+/// # Return value
+///
+/// The PSNR in decibels, or `None` if the statistics hold no errors.
+fn frame_psnr(
+    quality_stats: &[u8],
+    pixel_format: AvPixel,
+    (width, height): (u32, u32),
+) -> Option<f64> {
+    let layout = pixel_format_layout(pixel_format)?;
+    let num_errors = *quality_stats.get(5)? as usize;
+    let errors = quality_stats
+        .get(8..8 + num_errors * 8)?
+        .chunks_exact(8)
+        // Chunks are exactly 8 bytes long, so they always convert.
+        .map(|error| u64::from_le_bytes(error.try_into().unwrap()));
+    let num_planes = layout
+        .components
+        .iter()
+        .map(|component| component.plane + 1)
+        .max()?;
+    let (chroma_w, chroma_h) = layout.log2_chroma;
+
+    let mut sum_of_squares = 0.0;
+    let mut samples = 0.0;
+    for (plane, error) in errors.take(num_planes).enumerate() {
+        let (plane_width, plane_height) = if plane == 0 || layout.rgb {
+            (width, height)
+        } else {
+            (
+                width.div_ceil(1 << chroma_w),
+                height.div_ceil(1 << chroma_h),
+            )
+        };
+        sum_of_squares += error as f64;
+        samples += plane_width as f64 * plane_height as f64;
+    }
+    if samples == 0.0 {
+        return None;
+    }
+
+    let max = ((1_u64 << layout.components[0].depth) - 1) as f64;
+    let mse = sum_of_squares / samples;
+    Some(if mse > 0.0 {
+        (10.0 * (max * max / mse).log10()).min(PSNR_LOSSLESS)
+    } else {
+        PSNR_LOSSLESS
+    })
+}
+
+/// Accumulates the statistics of the packets that an encoder writes.
+#[derive(Debug, Default)]
+struct PacketStats {
+    packets: u64,
+    bytes: u64,
+    keyframes: u64,
+    /// Earliest start and latest end of the packets, in the time base of the encoder.
+    span: Option<(i64, i64)>,
+    /// Sum of the PSNR of the frames it was measured for, and the number of those frames.
+    psnr: Option<(f64, u64)>,
+}
+
+impl PacketStats {
+    /// Account for an encoded packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Presentation timestamp of the packet.
+    /// * `duration` - Duration of the packet.
+    /// * `size` - Size of the packet in bytes.
+    /// * `is_key` - Whether or not the packet is a key frame.
+    fn record(&mut self, pts: Option<i64>, duration: i64, size: usize, is_key: bool) {
+        self.packets += 1;
+        self.bytes += size as u64;
+        if is_key {
+            self.keyframes += 1;
+        }
+        if let Some(pts) = pts {
+            let end = pts + duration.max(0);
+            self.span = Some(match self.span {
+                Some((start, last_end)) => (start.min(pts), last_end.max(end)),
+                None => (pts, end),
+            });
+        }
+    }
+
+    /// Account for the PSNR of an encoded frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `psnr` - PSNR of the frame in decibels.
+    fn record_psnr(&mut self, psnr: f64) {
+        let (sum, frames) = self.psnr.unwrap_or_default();
+        self.psnr = Some((sum + psnr, frames + 1));
+    }
+
+    /// Summarize the statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames sent to the encoder.
+    /// * `time_base` - Time base of the encoder.
+    fn summary(&self, frames: u64, time_base: AvRational) -> EncodeStats {
+        let duration = Time::new(
+            Some(self.span.map(|(start, end)| end - start).unwrap_or(0)),
+            time_base,
+        );
+        let secs = duration.as_secs_f64();
+        let average_bitrate = if secs > 0.0 {
+            (self.bytes as f64 * 8.0 / secs).round() as u64
+        } else {
+            0
+        };
+
+        EncodeStats {
+            frames,
+            packets: self.packets,
+            bytes: self.bytes,
+            duration,
+            average_bitrate,
+            keyframes: self.keyframes,
+            psnr_avg: self.psnr.map(|(sum, frames)| sum / frames as f64),
+            faststart: None,
+        }
+    }
+}
+
+/// Receives the encoded packets of an encoder without a container.
+struct PacketSink {
+    time_base: AvRational,
+    on_packet: PacketFn,
+}
+
+impl PacketSink {
+    /// Hand an encoded packet to the callback, with timestamps in the time base of the sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Encoded packet.
+    /// * `encoder_time_base` - Time base of the packet timestamps.
+    fn send(&mut self, mut packet: AvPacket, encoder_time_base: AvRational) {
+        packet.rescale_ts(encoder_time_base, self.time_base);
+        (self.on_packet)(EncodedPacket::new(&packet, self.time_base));
+    }
+}
+
+type PacketFn = Box<dyn FnMut(EncodedPacket) + Send + Sync>;
+
+/// Destination, format and options of an output of [`Encoder::new_multi`].
+pub struct OutputSpec<'o> {
+    dest: Locator,
+    format: String,
+    options: Options<'o>,
+    failure_policy: FailurePolicy,
+}
+
+impl<'o> OutputSpec<'o> {
+    /// Create a new output specification. Failing to write to the output fails encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - Where to write to.
+    /// * `format` - Container format to use.
+    pub fn new(dest: Locator, format: &str) -> Self {
+        Self {
+            dest,
+            format: format.to_string(),
+            options: Options::default(),
+            failure_policy: FailurePolicy::Abort,
+        }
+    }
+
+    /// Set the output options.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The output options.
+    pub fn with_options(mut self, options: Options<'o>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Set what happens when writing to the output fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `failure_policy` - Failure policy.
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+
+    /// Open a writer for the output.
+    fn open(&self) -> Result<Writer> {
+        Writer::new_with_format_and_options(&self.dest, &self.format, &self.options)
+    }
+}
+
+/// What happens when writing to an output of [`Encoder::new_multi`] fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Fail encoding.
+    Abort,
+    /// Stop writing to the failed output, and keep writing to the other outputs.
+    Ignore,
+}
+
+/// Container formats in which the size of a stream can change mid-stream (see
+/// [`Encoder::reconfigure`]).
+const RECONFIGURABLE_FORMATS: &[&str] = &["mpegts", "hls"];
+
+/// When an encoder writes packets interleaved (see [`Encoder::interleaved`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interleaving {
+    /// Interleave when the output has more than one stream, or when the encoder reorders frames.
+    Auto,
+    Always,
+    Never,
+}
+
+impl Interleaving {
+    /// Whether or not to write packets to an output interleaved.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_streams` - Number of streams of the output.
+    /// * `reorders_frames` - Whether or not the encoder reorders frames (with B-frames).
+    fn is_enabled(&self, num_streams: u32, reorders_frames: bool) -> bool {
+        match self {
+            Interleaving::Auto => num_streams > 1 || reorders_frames,
+            Interleaving::Always => true,
+            Interleaving::Never => false,
+        }
+    }
+}
+
+/// Additional output of an encoder that receives the same encoded stream as the primary output.
+struct AdditionalOutput {
+    writer: Writer,
+    stream_index: usize,
+    failure_policy: FailurePolicy,
+    error: Option<Error>,
+}
+
+impl AdditionalOutput {
+    /// Acquire the time base of the output stream.
+    fn stream_time_base(&self) -> Result<AvRational> {
+        Ok(self
+            .writer
+            .output
+            .stream(self.stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .time_base())
+    }
+}
+
+/// Open an encoder with the given settings.
+///
+/// # Arguments
+///
+/// * `settings` - Encoder settings to use.
+/// * `global_header` - Whether or not the output needs the codec headers out of band (in the
+///   extradata) instead of in the stream.
+///
+/// # Return value
+///
+/// The opened encoder, along with the keys of the options that it did not recognize.
+fn open_encoder(settings: &Settings, global_header: bool) -> Result<(AvEncoder, Vec<String>)> {
+    settings.validate_hdr_metadata()?;
+    let codec = settings.codec();
+    let encoder_name = codec.as_ref().map_or("encoder", |codec| codec.name());
+    settings.validate_quality_metrics(encoder_name)?;
+    let options = settings.options(encoder_name)?;
+    validate_encoder_options(encoder_name, &options)?;
+
+    let mut encoder_context = match settings.codec() {
+        Some(codec) => codec_context_as(&codec)?,
+        None => AvContext::new(),
+    };
+
+    // Some formats require this flag to be set or the output will
+    // not be playable by dumb players.
+    let mut flags = AvCodecFlags::empty();
+    if global_header {
+        flags |= AvCodecFlags::GLOBAL_HEADER;
+    }
+    if settings.measures_psnr() {
+        flags |= AvCodecFlags::PSNR;
+    }
+    if settings.deterministic {
+        flags |= AvCodecFlags::BITEXACT;
+    }
+    encoder_context.set_flags(flags);
+
+    let mut encoder = encoder_context.encoder().video()?;
+    settings.apply_to(&mut encoder);
+    if let Some(codec) = settings.codec() {
+        let supported = codec
+            .video()
+            .ok()
+            .and_then(|video| video.formats())
+            .map(|formats| formats.collect::<Vec<_>>())
+            .unwrap_or_default();
+        encoder.set_format(pick_pixel_format(
+            settings.pixel_format,
+            &supported,
+            settings.auto_convert,
+        )?);
+    }
+
+    // Unless set otherwise, just use the ffmpeg global time base which is precise enough
+    // that we should never get in trouble.
+    encoder.set_time_base(settings.time_base.unwrap_or(TIME_BASE));
+
+    let (encoder, unconsumed_options) =
+        encoder_open_with(encoder, options.to_dict()).operation("encoder.open")?;
+    let codec_name = encoder
+        .codec()
+        .map_or_else(|| "encoder".to_string(), |codec| codec.name().to_string());
+    let ignored_options =
+        ignored_option_keys(&codec_name, unconsumed_options.iter().map(|(key, _)| key));
+
+    Ok((encoder, ignored_options))
+}
+
+/// Pick the pixel format to encode with.
+///
+/// # Arguments
+///
+/// * `requested` - Pixel format of the settings.
+/// * `supported` - Pixel formats that the encoder supports, or none if it does not say.
+/// * `auto_convert` - Whether to pick the closest supported pixel format if the requested one is
+///   not supported (see [`Settings::with_auto_convert`]).
+fn pick_pixel_format(
+    requested: AvPixel,
+    supported: &[AvPixel],
+    auto_convert: bool,
+) -> Result<AvPixel> {
+    if supported.is_empty() || supported.contains(&requested) {
+        return Ok(requested);
+    }
+
+    let not_supported = || Error::PixelFormatNotSupported {
+        requested,
+        supported: supported.to_vec(),
+    };
+    if auto_convert {
+        find_best_pixel_format(supported, requested).ok_or_else(not_supported)
+    } else {
+        Err(not_supported())
+    }
+}
+
+/// Prepare an output for deterministic encoding (see [`Settings::deterministic`]): set the muxer
+/// to `bitexact`, and remove the metadata entries that differ between runs. This must be done
+/// before the header is written.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+fn make_output_deterministic(output: &mut AvOutput) {
+    const NONDETERMINISTIC_KEYS: [&str; 2] = ["creation_time", "encoder"];
+    let without_nondeterministic_keys = |metadata: AvDictionaryRef| {
+        let mut filtered = AvDictionary::new();
+        for (key, value) in metadata
+            .iter()
+            .filter(|(key, _)| !NONDETERMINISTIC_KEYS.contains(key))
+        {
+            filtered.set(key, value);
+        }
+        filtered
+    };
+
+    output_set_bitexact(output);
+    let metadata = without_nondeterministic_keys(output.metadata());
+    output.set_metadata(metadata);
+    for stream_index in 0..output.nb_streams() as usize {
+        if let Some(mut stream) = output.stream_mut(stream_index) {
+            let metadata = without_nondeterministic_keys(stream.metadata());
+            stream.set_metadata(metadata);
+        }
+    }
+}
+
+/// Add a stream to the writer with the parameters of an opened encoder.
+///
+/// # Arguments
+///
+/// * `writer` - Writer to add stream to.
+/// * `encoder` - Encoder to copy parameters from.
+/// * `hdr_metadata` - HDR metadata to store with the stream, if any.
+///
+/// # Return value
+///
+/// Index of the new stream.
+fn add_stream_for_encoder(
+    writer: &mut Writer,
+    encoder: &AvEncoder,
+    hdr_metadata: Option<&HdrMetadata>,
+) -> Result<usize> {
+    let mut writer_stream = writer.output.add_stream(encoder.codec())?;
+    writer_stream.set_parameters(encoder);
+    // A time base that was set explicitly (see `Settings::with_time_base`) is passed on to the
+    // muxer, which keeps it if the container supports it.
+    let encoder_time_base = get_encoder_time_base(encoder);
+    if encoder_time_base != TIME_BASE {
+        writer_stream.set_time_base(encoder_time_base);
+    }
+    let index = writer_stream.index();
+    if let Some(hdr_metadata) = hdr_metadata {
+        add_stream_hdr_metadata(&mut writer.output, index, hdr_metadata)?;
+    }
+    Ok(index)
+}
+
+/// Forces key frames at a fixed interval of presentation time, for example on segment boundaries.
+pub(crate) struct KeyFrameSchedule {
+    interval: i64,
+    next: Option<i64>,
+}
+
+impl KeyFrameSchedule {
+    /// Create a new key frame schedule.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Interval between key frames in encoder time base units.
+    pub(crate) fn new(interval: i64) -> Self {
+        Self {
+            interval: interval.max(1),
+            next: None,
+        }
+    }
+
+    /// Whether or not the frame with the given timestamp must be a key frame. The first frame is
+    /// always a key frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp of the frame in encoder time base units.
+    pub(crate) fn is_due(&mut self, pts: i64) -> bool {
+        let next = self.next.get_or_insert(pts);
+        if pts < *next {
+            return false;
+        }
+
+        while *next <= pts {
+            *next += self.interval;
+        }
+
+        true
+    }
+}
+
+/// Sets the duration of encoded packets, which containers like WebM and fragmented MP4 need to
+/// get the duration of the last frame right. See [`Settings::with_frame_rate`].
+struct PacketDurations {
+    /// Duration of a frame in the encoder time base, if the input has a constant frame rate.
+    frame_duration: Option<i64>,
+    /// Whether or not to hold back a packet until the frame after it has been encoded.
+    holds_packet: bool,
+    /// Timestamps of the latest frames that went into the encoder.
+    frame_pts: BTreeSet<i64>,
+    pending: Option<AvPacket>,
+}
+
+impl PacketDurations {
+    /// Number of frame timestamps to keep, which is more than any encoder reorders.
+    const MAX_FRAMES: usize = 64;
+
+    /// Create packet durations for an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Settings of the encoder.
+    /// * `time_base` - Time base of the encoder.
+    fn new(settings: &Settings, time_base: AvRational) -> Self {
+        let frame_duration = settings.frame_rate.map(|frame_rate| {
+            ((time_base.denominator() as f64 * frame_rate.denominator() as f64)
+                / (time_base.numerator() as f64 * frame_rate.numerator() as f64))
+                .round()
+                .max(1.0) as i64
+        });
+        Self {
+            frame_duration,
+            holds_packet: frame_duration.is_none() && !settings.low_latency,
+            frame_pts: BTreeSet::new(),
+            pending: None,
+        }
+    }
+
+    /// Register that a frame went into the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp of the frame in the encoder time base.
+    fn frame_sent(&mut self, pts: Option<i64>) {
+        if let Some(pts) = pts {
+            self.frame_pts.insert(pts);
+            if self.frame_pts.len() > Self::MAX_FRAMES {
+                self.frame_pts.pop_first();
+            }
+        }
+    }
+
+    /// Set the duration of a packet that came out of the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Encoded packet.
+    ///
+    /// # Return value
+    ///
+    /// The packet to write next, if any. This is the packet before it if packets are held back.
+    fn push(&mut self, mut packet: AvPacket) -> Option<AvPacket> {
+        if !self.holds_packet {
+            self.set_duration(&mut packet);
+            return Some(packet);
+        }
+        let previous = self.finish();
+        self.pending = Some(packet);
+        previous
+    }
+
+    /// Release the packet that is held back, at the end of the stream.
+    fn finish(&mut self) -> Option<AvPacket> {
+        let mut packet = self.pending.take()?;
+        self.set_duration(&mut packet);
+        Some(packet)
+    }
+
+    /// Set the duration of a packet to the time until the next frame in presentation order, or to
+    /// the time since the frame before it if the next frame is not known.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to set the duration of.
+    fn set_duration(&mut self, packet: &mut AvPacket) {
+        if let Some(frame_duration) = self.frame_duration {
+            packet.set_duration(frame_duration);
+            return;
+        }
+        let Some(pts) = packet.pts() else {
+            return;
+        };
+        let next_frame_duration = self
+            .frame_pts
+            .range(pts + 1..)
+            .next()
+            .map(|next_pts| next_pts - pts);
+        let previous_frame_duration = || {
+            self.frame_pts
+                .range(..pts)
+                .next_back()
+                .map(|previous_pts| pts - previous_pts)
+        };
+        if let Some(duration) = next_frame_duration.or_else(previous_frame_duration) {
+            packet.set_duration(duration);
+        }
+    }
+}
+
+/// Paces writing so that packets are written at wall-clock rate.
+struct Pacer {
+    /// Wall-clock time and timestamp (in seconds) of the first packet.
+    origin: Option<(Instant, f64)>,
+}
+
+impl Pacer {
+    /// Create a new pacer. The first packet is written immediately.
+    fn new() -> Self {
+        Self { origin: None }
+    }
+
+    /// Get how long to wait before writing a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - Timestamp of the packet in seconds.
+    /// * `now` - Current wall-clock time.
+    fn delay(&mut self, timestamp: f64, now: Instant) -> Duration {
+        let (origin, origin_timestamp) = *self.origin.get_or_insert((now, timestamp));
+        let target = origin + Duration::from_secs_f64((timestamp - origin_timestamp).max(0.0));
+        target.saturating_duration_since(now)
+    }
+}
+
+/// How often an encoder flushes output. See [`Encoder::flush_every`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushInterval {
+    /// Flush after every `n` packets.
+    Packets(u64),
+    /// Flush when at least the given amount of time has passed since the last flush.
+    Duration(Duration),
+}
+
+/// Keeps track of when output must be flushed.
+struct FlushSchedule {
+    interval: FlushInterval,
+    packets: u64,
+    last_flush: Instant,
+}
+
+impl FlushSchedule {
+    /// Create a new flush schedule.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - How often to flush.
+    /// * `now` - Current wall-clock time.
+    fn new(interval: FlushInterval, now: Instant) -> Self {
+        Self {
+            interval,
+            packets: 0,
+            last_flush: now,
+        }
+    }
+
+    /// Register that a packet was written, and get whether or not output must be flushed.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current wall-clock time.
+    fn is_due(&mut self, now: Instant) -> bool {
+        self.packets += 1;
+        let due = match self.interval {
+            FlushInterval::Packets(packets) => self.packets >= packets,
+            FlushInterval::Duration(duration) => now.duration_since(self.last_flush) >= duration,
+        };
+        if due {
+            self.packets = 0;
+            self.last_flush = now;
+        }
+
+        due
+    }
+}
+
+/// HLS segment and playlist configuration for [`Encoder::new_hls`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HlsOutput {
+    /// Target duration of each segment (`hls_time`).
+    pub segment_duration: Duration,
+    /// Maximum number of segments in the playlist, or zero to keep all of them (`hls_list_size`).
+    pub playlist_size: usize,
+    /// Whether or not to delete segments that are no longer in the playlist.
+    pub delete_old: bool,
+    /// Segment filename pattern, such as `segment_%05d.ts` (`hls_segment_filename`).
+    pub path_pattern: String,
+}
+
+impl HlsOutput {
+    /// Get the muxer options for this configuration.
+    fn options(&self) -> Options<'static> {
+        Options::new_from_hashmap(&self.options_map())
+    }
+
+    /// Get the muxer options as key-value pairs.
+    fn options_map(&self) -> HashMap<String, String> {
+        let mut hls_flags = "independent_segments".to_string();
+        if self.delete_old {
+            hls_flags.push_str("+delete_segments");
+        }
+
+        HashMap::from([
+            (
+                "hls_time".to_string(),
+                self.segment_duration.as_secs_f64().to_string(),
+            ),
+            ("hls_list_size".to_string(), self.playlist_size.to_string()),
+            ("hls_flags".to_string(), hls_flags),
+            (
+                "hls_segment_filename".to_string(),
+                self.path_pattern.clone(),
+            ),
+        ])
+    }
+}
+
+impl Default for HlsOutput {
+    fn default() -> Self {
+        Self {
+            segment_duration: Duration::from_secs(2),
+            playlist_size: 5,
+            delete_old: false,
+            path_pattern: "segment_%05d.ts".to_string(),
+        }
+    }
+}
+
+/// Destination and retry policy of an encoder that reconnects when the connection drops.
+struct Reconnect {
+    dest: Locator,
+    /// Options to open every connection with.
+    options: Options<'static>,
+    retry_policy: RetryPolicy,
+    /// Whether packets are dropped until the next key frame, since the server cannot decode the
+    /// stream on a new connection before it.
+    awaiting_key_frame: bool,
+    /// Offset in the encoder time base that is subtracted from the timestamps of packets, so that
+    /// the stream on the current connection starts at zero.
+    timestamp_offset: i64,
+}
+
+impl Reconnect {
+    /// Create the reconnect state of an encoder that is about to make its first connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - Destination to connect to.
+    /// * `options` - Options to open every connection with.
+    /// * `retry_policy` - How to reconnect when the connection drops.
+    fn new(dest: Locator, options: &Options, retry_policy: RetryPolicy) -> Self {
+        Self {
+            dest,
+            options: options.to_owned_options(),
+            retry_policy,
+            awaiting_key_frame: false,
+            timestamp_offset: 0,
+        }
+    }
+
+    /// Open a new connection to the destination.
+    fn connect(&self) -> Result<Writer> {
+        Writer::new_with_format_and_options(&self.dest, "flv", &self.options)
+            .map_err(Error::into_connection_error)
+    }
+
+    /// Move the timestamps of a packet in the encoder time base to the stream on the current
+    /// connection. After a reconnect, the stream starts at the first key frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to move.
+    ///
+    /// # Return value
+    ///
+    /// Whether to write the packet. Packets before the first key frame on a new connection are
+    /// dropped.
+    fn rebase(&mut self, packet: &mut AvPacket) -> bool {
+        if self.awaiting_key_frame {
+            if !packet.is_key() {
+                return false;
+            }
+            self.awaiting_key_frame = false;
+            self.timestamp_offset = packet.dts().or(packet.pts()).unwrap_or_default();
+        }
+        if self.timestamp_offset != 0 {
+            packet.set_pts(packet.pts().map(|pts| pts - self.timestamp_offset));
+            packet.set_dts(packet.dts().map(|dts| dts - self.timestamp_offset));
+        }
+        true
+    }
+}
+
+/// Determines how often and how fast an encoder tries to reconnect after the connection dropped.
+///
+/// The delay before each attempt starts at `initial_backoff` and doubles with every failed
+/// attempt, up to `max_backoff`.
+///
+/// # Example
+///
+/// ```ignore
+/// let retry_policy = RetryPolicy::new(5, Duration::from_millis(500), Duration::from_secs(8));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create a new retry policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - Maximum number of reconnection attempts before giving up.
+    /// * `initial_backoff` - Delay before the first attempt.
+    /// * `max_backoff` - Maximum delay between attempts.
+    pub fn new(max_attempts: u32, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    /// Retry policy that never reconnects.
+    pub fn never() -> Self {
+        Self::new(0, Duration::ZERO, Duration::ZERO)
+    }
+
+    /// Get the delay before the given attempt.
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - Zero-based index of the attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1 << attempt.min(31))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(5, Duration::from_millis(500), Duration::from_secs(8))
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        // Do not block the dropping thread on pacing the remaining packets.
+        self.pacer = None;
+        let _ = match self.drop_timeout {
+            Some(timeout) => self.finish_with_timeout(timeout),
+            None => self.finish(),
+        };
+    }
+}
+
+/// Supplies the overlay that is composited onto frames.
+enum OverlaySource {
+    /// The same overlay for every frame.
+    #[cfg(feature = "ndarray")]
+    Static(Overlay),
+    /// Callback that supplies the overlay for each frame based on its timestamp.
+    Dynamic(OverlayFn),
+}
+
+type OverlayFn = Box<dyn FnMut(&Time) -> Option<Overlay> + Send + Sync>;
+
+/// Holds a logical combination of encoder settings.
+pub struct Settings<'o> {
+    width: u32,
+    height: u32,
+    pixel_format: AvPixel,
+    key_frame_interval: Option<u64>,
+    key_frame_every: Option<Duration>,
+    max_b_frames: Option<usize>,
+    lookahead: Option<u32>,
+    low_latency: bool,
+    auto_convert: bool,
+    color_spec: Option<ColorSpec>,
+    hdr_metadata: Option<HdrMetadata>,
+    quality_metrics: Option<QualityMetrics>,
+    deterministic: bool,
+    crop: Option<Crop>,
+    input_size: Option<(u32, u32)>,
+    rate_control: Option<RateControl>,
+    time_base: Option<AvRational>,
+    frame_rate: Option<AvRational>,
+    encoder: Option<&'static str>,
+    options: Options<'o>,
+}
+
+impl<'o> Settings<'o> {
+    /// This is the assumed FPS for the encoder to use. Note that this does not need to be correct
+    /// exactly.
+    const FRAME_RATE: i32 = 30;
+
+    /// GOP size used when key frames are placed by timestamp, so that the encoder never places
+    /// periodic key frames of its own.
+    const GOP_UNLIMITED: u32 = i32::MAX as u32;
+
+    /// Create encoder settings for an H264 stream with YUV420p pixel format. This will encode to
+    /// arguably the most widely compatible video file since H264 is a common codec and YUV420p is
+    /// the most commonly used pixel format.
+    pub fn for_h264_yuv420p(width: usize, height: usize, realtime: bool) -> Settings<'o> {
+        let options = if realtime {
+            Options::new_h264_realtime()
+        } else {
+            Options::new_h264()
+        };
+
+        Self {
+            width: width as u32,
+            height: height as u32,
+            pixel_format: AvPixel::YUV420P,
+            key_frame_interval: None,
+            key_frame_every: None,
+            max_b_frames: None,
+            lookahead: None,
+            low_latency: false,
+            auto_convert: false,
+            color_spec: None,
+            hdr_metadata: None,
+            quality_metrics: None,
+            deterministic: false,
+            crop: None,
+            input_size: None,
+            rate_control: None,
+            time_base: None,
+            frame_rate: None,
+            encoder: None,
+            options,
+        }
+    }
+
+    /// Create encoder settings for an H264 stream with a custom pixel format and options.
+    /// This allows for greater flexibility in encoding settings, enabling specific requirements
+    /// or optimizations to be set depending on the use case.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video stream.
+    /// * `height` - The height of the video stream.
+    /// * `pixel_format` - The desired pixel format for the video stream.
+    /// * `options` - Custom H264 encoding options.
+    ///
+    /// # Return value
+    ///
+    /// A `Settings` instance with the specified configuration.+
+    pub fn for_h264_custom(
+        width: usize,
+        height: usize,
+        pixel_format: PixelFormat,
+        options: Options<'o>,
+    ) -> Settings<'o> {
+        Self {
+            width: width as u32,
+            height: height as u32,
+            pixel_format,
+            key_frame_interval: None,
+            key_frame_every: None,
+            max_b_frames: None,
+            lookahead: None,
+            low_latency: false,
+            auto_convert: false,
+            color_spec: None,
+            hdr_metadata: None,
+            quality_metrics: None,
+            deterministic: false,
+            crop: None,
+            input_size: None,
+            rate_control: None,
+            time_base: None,
+            frame_rate: None,
+            encoder: None,
+            options,
+        }
+    }
+
+    /// Create encoder settings for an HEVC stream (`libx265`) with a custom pixel format and
+    /// options. This supports high bit depth pixel formats like [`PixelFormat::YUV420P10LE`] and
+    /// [`PixelFormat::YUV422P10LE`].
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video stream.
+    /// * `height` - The height of the video stream.
+    /// * `pixel_format` - The desired pixel format for the video stream.
+    /// * `options` - Custom HEVC encoding options, like [`Options::new_hevc`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_hevc_custom(
+    ///     1920,
+    ///     1080,
+    ///     PixelFormat::YUV420P10LE,
+    ///     Options::new_hevc(),
+    /// );
+    /// ```
+    pub fn for_hevc_custom(
+        width: usize,
+        height: usize,
+        pixel_format: PixelFormat,
+        options: Options<'o>,
+    ) -> Settings<'o> {
+        Self {
+            encoder: Some("libx265"),
+            ..Self::for_h264_custom(width, height, pixel_format, options)
+        }
+    }
+
+    /// Create encoder settings for a ProRes stream (`prores_ks`). The ProRes profile follows from
+    /// the pixel format: [`PixelFormat::YUV422P10LE`] for 4:2:2 profiles and
+    /// [`PixelFormat::YUV444P10LE`] for 4:4:4 profiles. ProRes is usually stored in MOV files.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video stream.
+    /// * `height` - The height of the video stream.
+    /// * `pixel_format` - The desired pixel format for the video stream.
+    pub fn for_prores(width: usize, height: usize, pixel_format: PixelFormat) -> Settings<'o> {
+        Self {
+            encoder: Some("prores_ks"),
+            ..Self::for_h264_custom(width, height, pixel_format, Options::default())
+        }
+    }
+
+    /// Create encoder settings for a VP9 stream with an alpha channel (`libvpx-vp9` with
+    /// YUVA420p), for videos with transparency. The alpha channel can only be stored in WebM (or
+    /// Matroska) files. Encode frames with 4 channels (BGRA) to set their transparency.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video stream.
+    /// * `height` - The height of the video stream.
+    pub fn for_vp9_alpha(width: usize, height: usize) -> Settings<'o> {
+        let mut options = Options::default();
+        // Constant quality, since libvpx defaults to a low bitrate otherwise.
+        options.set("crf", "31");
+        options.set("b", "0");
+        // Alternate reference frames do not support alpha.
+        options.set("auto-alt-ref", "0");
+
+        Self {
+            encoder: Some("libvpx-vp9"),
+            ..Self::for_h264_custom(width, height, AvPixel::YUVA420P, options)
+        }
+    }
+
+    /// Create encoder settings for a ProRes 4444 stream with an alpha channel (`prores_ks` with
+    /// YUVA444p10le), for videos with transparency. ProRes is usually stored in MOV files. Encode
+    /// frames with 4 channels (BGRA) to set their transparency.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the video stream.
+    /// * `height` - The height of the video stream.
+    pub fn for_prores_4444_alpha(width: usize, height: usize) -> Settings<'o> {
+        let mut options = Options::default();
+        options.set("profile", "4444");
+        options.set("alpha_bits", "16");
+
+        Self {
+            encoder: Some("prores_ks"),
+            ..Self::for_h264_custom(width, height, AvPixel::YUVA444P10LE, options)
+        }
+    }
+
+    /// Create encoder settings for a single still image (`mjpeg` or `png`), to be written with the
+    /// `image2` muxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the image.
+    /// * `height` - The height of the image.
+    /// * `format` - Format of the image.
+    pub(crate) fn for_still_image(
+        width: usize,
+        height: usize,
+        format: ImageFormat,
+    ) -> Settings<'o> {
+        let mut options = Options::default();
+        let (encoder, pixel_format) = match format {
+            ImageFormat::Jpeg => {
+                // Keep the quantizer low, since the default bitrate is meant for video.
+                options.set("qmin", "2");
+                options.set("qmax", "4");
+                ("mjpeg", AvPixel::YUVJ420P)
+            }
+            ImageFormat::Png => ("png", AvPixel::RGB24),
+        };
+
+        Self {
+            encoder: Some(encoder),
+            ..Self::for_h264_custom(width, height, pixel_format, options)
+        }
+    }
+
+    /// Set the key frame interval. The encoder produces a key frame every `interval` frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Number of frames between key frames.
+    pub fn with_key_frame_interval(mut self, interval: u64) -> Settings<'o> {
+        self.key_frame_interval = Some(interval);
+        self
+    }
+
+    /// Force key frames on a grid of presentation time instead of every so many frames, for example
+    /// to have a key frame on every segment boundary when segmenting for HLS or DASH. The grid
+    /// starts at the timestamp of the first frame, and the first frame at or after each point on
+    /// the grid becomes a key frame, so this also works for variable frame rate input.
+    ///
+    /// Forced key frames are IDR frames, and the encoder does not place key frames of its own (on
+    /// scene cuts, for example), for the encoders that support it (`libx264`, `libx265` and NVENC).
+    /// This takes precedence over [`Settings::with_key_frame_interval`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Presentation time between key frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, false)
+    ///     .force_keyframe_every(Duration::from_secs(2));
+    /// ```
+    pub fn force_keyframe_every(mut self, interval: Duration) -> Settings<'o> {
+        self.key_frame_every = Some(interval);
+        self
+    }
+
+    /// Set how many frames the encoder looks ahead for rate control and frame type decisions
+    /// (`rc-lookahead` for `libx264`, `libx265` and NVENC, `lag-in-frames` for `libvpx` and
+    /// `libaom-av1`). The encoder holds back this many frames before the first packet comes out,
+    /// so fewer frames means less latency at the cost of some compression. Other encoders ignore
+    /// this.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames to look ahead.
+    pub fn with_lookahead(mut self, frames: u32) -> Settings<'o> {
+        self.lookahead = Some(frames);
+        self
+    }
+
+    /// Make the encoder produce a packet for every frame as soon as possible, for interactive
+    /// previews and the like. Unless set otherwise, this turns off lookahead (see
+    /// [`Settings::with_lookahead`]) and B-frames, and the encoder runs on slice threads instead
+    /// of frame threads, which would hold back a frame per thread. With `libx264`, the first
+    /// packet then comes out for the first frame.
+    pub fn with_low_latency(mut self) -> Settings<'o> {
+        self.low_latency = true;
+        self
+    }
+
+    /// Encode with the closest pixel format that the encoder supports if it does not support the
+    /// pixel format of the settings, instead of failing with [`Error::PixelFormatNotSupported`]
+    /// when the encoder is created. Hardware encoders in particular often take only a single pixel
+    /// format (like NV12). Frames are converted to the pixel format that is picked, and
+    /// [`Encoder::pixel_format_substitution`] tells which one that is.
+    pub fn with_auto_convert(mut self) -> Settings<'o> {
+        self.auto_convert = true;
+        self
+    }
+
+    /// Tag the output with color properties, like the primaries and transfer characteristic of
+    /// HDR content (see [`ColorSpec`]). Frames are not converted: they must already have these
+    /// properties.
+    ///
+    /// # Arguments
+    ///
+    /// * `color_spec` - Color properties of the frames.
+    pub fn with_color_spec(mut self, color_spec: ColorSpec) -> Settings<'o> {
+        self.color_spec = Some(color_spec);
+        self
+    }
+
+    /// Write HDR metadata (the color volume of the mastering display and the content light levels)
+    /// to the output, so that displays tone map the content correctly. The metadata is stored
+    /// with the stream in containers that support it (like MP4 and Matroska), and `libx265`
+    /// writes it to the stream as SEI messages as well.
+    ///
+    /// HDR metadata needs a pixel format with more than 8 bits per component and a PQ or HLG
+    /// transfer characteristic (see [`Settings::with_color_spec`]), otherwise creating the encoder
+    /// fails with [`Error::InvalidOptions`].
+    ///
+    /// # Arguments
+    ///
+    /// * `hdr_metadata` - HDR metadata to write.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_hevc_custom(
+    ///     3840,
+    ///     2160,
+    ///     PixelFormat::YUV420P10LE,
+    ///     Options::new_hevc(),
+    /// )
+    /// .with_color_spec(ColorSpec::bt2020_pq())
+    /// .with_hdr_metadata(HdrMetadata {
+    ///     primaries: [(0.708, 0.292), (0.17, 0.797), (0.131, 0.046)],
+    ///     white_point: (0.3127, 0.329),
+    ///     max_luminance: 1000.0,
+    ///     min_luminance: 0.0001,
+    ///     max_cll: 1000,
+    ///     max_fall: 400,
+    /// });
+    /// ```
+    pub fn with_hdr_metadata(mut self, hdr_metadata: HdrMetadata) -> Settings<'o> {
+        self.hdr_metadata = Some(hdr_metadata);
+        self
+    }
+
+    /// Measure the quality of the encoded frames against the input frames while encoding, for
+    /// comparing codecs and settings without decoding the output again. The encoder computes the
+    /// metrics itself, and the average over all frames ends up in [`Encoder::stats`].
+    ///
+    /// PSNR is reported by `libx264`, `libvpx`, `libvpx-vp9`, `libaom-av1` and the MPEG-1, MPEG-2,
+    /// MPEG-4 and MJPEG encoders of ffmpeg. No encoder reports SSIM through ffmpeg. Creating an
+    /// encoder that cannot report a metric that was asked for fails with
+    /// [`Error::QualityMetricNotSupported`].
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - Metrics to measure.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, false).with_quality_metrics(
+    ///     QualityMetrics {
+    ///         psnr: true,
+    ///         ssim: false,
+    ///     },
+    /// );
+    /// // Encode frames...
+    /// encoder.finish().unwrap();
+    /// println!("PSNR: {:.2} dB", encoder.stats().psnr_avg.unwrap());
+    /// ```
+    pub fn with_quality_metrics(mut self, metrics: QualityMetrics) -> Settings<'o> {
+        self.quality_metrics = Some(metrics);
+        self
+    }
+
+    /// Encode deterministically, so that encoding the same frames with the same settings gives
+    /// the same bytes every time, for example to compare the output with a golden file in tests.
+    ///
+    /// The encoder runs on a single thread, and both the encoder and the muxer are set to
+    /// `bitexact`, so that they leave out the version of the library that wrote the output and
+    /// random identifiers (like the UIDs of Matroska). The `creation_time` and `encoder` metadata
+    /// entries are left out as well.
+    ///
+    /// Whether the encoded stream itself is the same across runs depends on the encoder: the
+    /// software encoders (like `libx264`, `libx265`, `libvpx` and the encoders of ffmpeg itself)
+    /// are deterministic on a single thread, but hardware encoders (like NVENC and VAAPI) may not
+    /// be. The output may still differ between versions of ffmpeg and of the encoder libraries.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(640, 480, false).deterministic();
+    /// ```
+    pub fn deterministic(mut self) -> Settings<'o> {
+        self.deterministic = true;
+        self
+    }
+
+    /// Set the rate control mode. The mode is translated to the options of the encoder that ends
+    /// up being used, and takes precedence over any rate control options that were passed
+    /// explicitly. See [`RateControl`].
+    ///
+    /// # Arguments
+    ///
+    /// * `rate_control` - Rate control mode.
+    pub fn with_rate_control(mut self, rate_control: RateControl) -> Settings<'o> {
+        self.rate_control = Some(rate_control);
+        self
+    }
+
+    /// Set the time base of the encoder, instead of the default of one microsecond. Timestamps of
+    /// frames are aligned to it, and the encoded packets are in it (see [`Encoder::time_base`]).
+    /// For example, use `1/90000` for MPEG-TS, or `1/fps` to have the frame index as timestamp.
+    ///
+    /// The time base is passed on to the container, unless the container requires its own time
+    /// base (like MPEG-TS, Matroska and MP4), in which case packets are rescaled when they are
+    /// written.
+    ///
+    /// # Arguments
+    ///
+    /// * `num` - Numerator of the time base.
+    /// * `den` - Denominator of the time base.
+    ///
+    /// # Return value
+    ///
+    /// The settings, or [`Error::InvalidOptions`] if the numerator or denominator is not
+    /// positive.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, false)
+    ///     .with_time_base(1, 25)
+    ///     .unwrap();
+    /// ```
+    pub fn with_time_base(mut self, num: i32, den: i32) -> Result<Settings<'o>> {
+        if num <= 0 || den <= 0 {
+            return Err(Error::InvalidOptions(format!(
+                "invalid time base: {num}/{den}"
+            )));
+        }
+        self.time_base = Some(AvRational::new(num, den));
+        Ok(self)
+    }
+
+    /// Declare that the input has a constant frame rate. Every encoded packet then lasts exactly
+    /// one frame, which the container stores as the duration of the packet.
+    ///
+    /// Without a constant frame rate, the duration of a packet is taken from the timestamp of the
+    /// frame that comes after it. This holds back one packet until the next frame has been
+    /// encoded, unless the encoder is set to low latency (see [`Settings::with_low_latency`]), in
+    /// which case the time since the frame before it is used instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `num` - Numerator of the frame rate.
+    /// * `den` - Denominator of the frame rate.
+    ///
+    /// # Return value
+    ///
+    /// The settings, or [`Error::InvalidOptions`] if the numerator or denominator is not
+    /// positive.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::for_h264_yuv420p(1280, 720, false)
+    ///     .with_frame_rate(30000, 1001)
+    ///     .unwrap();
+    /// ```
+    pub fn with_frame_rate(mut self, num: i32, den: i32) -> Result<Settings<'o>> {
+        if num <= 0 || den <= 0 {
+            return Err(Error::InvalidOptions(format!(
+                "invalid frame rate: {num}/{den}"
+            )));
+        }
+        self.frame_rate = Some(AvRational::new(num, den));
+        Ok(self)
+    }
+
+    /// Crop input frames to the encoder size before encoding them. This is for input frames that
+    /// are larger than the encoder size: frames passed to the encoder must have the given input
+    /// size, and the region at the given offsets is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the region to encode.
+    /// * `y` - Vertical offset of the region to encode.
+    /// * `input_width` - Width of input frames.
+    /// * `input_height` - Height of input frames.
+    ///
+    /// # Return value
+    ///
+    /// The settings, or [`Error::InvalidCropParameters`] if the region does not fit in the input
+    /// frames.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // Encode the center 640x480 of 1280x720 frames.
+    /// let settings = Settings::for_h264_yuv420p(640, 480, false)
+    ///     .with_crop(320, 120, 1280, 720)
+    ///     .unwrap();
+    /// ```
+    pub fn with_crop(
+        mut self,
+        x: u32,
+        y: u32,
+        input_width: u32,
+        input_height: u32,
+    ) -> Result<Settings<'o>> {
+        let crop = Crop::new(x, y, self.width, self.height);
+        crop.validate((input_width, input_height))?;
+        self.crop = Some(crop);
+        self.input_size = Some((input_width, input_height));
+        Ok(self)
+    }
+
+    /// Whether or not the linked ffmpeg build has an encoder for these settings, and whether that
+    /// encoder accepts the pixel format.
+    pub fn is_supported(&self) -> bool {
+        self.codec().is_some_and(|codec| {
+            codec
+                .video()
+                .ok()
+                .and_then(|video| video.formats())
+                .is_none_or(|mut formats| {
+                    formats.any(|pixel_format| pixel_format == self.pixel_format)
+                })
+        })
+    }
+
+    /// Apply the settings to an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder to apply settings to.
+    ///
+    /// # Return value
+    ///
+    /// New encoder with settings applied.
+    fn apply_to(&self, encoder: &mut AvVideo) {
+        encoder.set_width(self.width);
+        encoder.set_height(self.height);
+        encoder.set_format(self.pixel_format);
+        encoder.set_frame_rate(Some(
+            self.frame_rate
+                .unwrap_or(AvRational::new(Self::FRAME_RATE, 1)),
+        ));
+        if self.key_frame_every.is_some() {
+            encoder.set_gop(Self::GOP_UNLIMITED);
+        } else if let Some(key_frame_interval) = self.key_frame_interval {
+            encoder.set_gop(key_frame_interval as u32);
+        }
+        if let Some(max_b_frames) = self.max_b_frames {
+            encoder.set_max_b_frames(max_b_frames);
+        } else if self.low_latency {
+            encoder.set_max_b_frames(0);
+        }
+        if self.low_latency {
+            encoder.set_threading(AvThreadingConfig::kind(AvThreadingType::Slice));
+        }
+        if let Some(color_spec) = self.color_spec {
+            encoder_set_color_properties(
+                encoder,
+                color_spec.primaries,
+                color_spec.transfer_characteristic,
+                color_spec.space,
+            );
+        }
+    }
+
+    /// Check that HDR metadata (if any) goes with the pixel format and the transfer
+    /// characteristic.
+    fn validate_hdr_metadata(&self) -> Result<()> {
+        if self.hdr_metadata.is_none() {
+            return Ok(());
+        }
+        if !pixel_format_is_high_bit_depth(self.pixel_format) {
+            return Err(Error::InvalidOptions(format!(
+                "HDR metadata needs a pixel format with at least 10 bits per component, not {:?}",
+                self.pixel_format
+            )));
+        }
+        if !self
+            .color_spec
+            .is_some_and(|color_spec| color_spec.is_hdr())
+        {
+            return Err(Error::InvalidOptions(
+                "HDR metadata needs a PQ or HLG transfer characteristic".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that an encoder can report the quality metrics (if any) that were asked for.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Name of the encoder.
+    fn validate_quality_metrics(&self, encoder: &str) -> Result<()> {
+        let Some(metrics) = self.quality_metrics else {
+            return Ok(());
+        };
+        let not_supported = |metric: &str| Error::QualityMetricNotSupported {
+            encoder: encoder.to_string(),
+            metric: metric.to_string(),
+        };
+        if metrics.psnr && !PSNR_ENCODERS.contains(&encoder) {
+            return Err(not_supported("PSNR"));
+        }
+        if metrics.ssim {
+            return Err(not_supported("SSIM"));
+        }
+
+        Ok(())
+    }
+
+    /// Whether or not the encoder computes the PSNR of encoded frames.
+    fn measures_psnr(&self) -> bool {
+        self.quality_metrics.is_some_and(|metrics| metrics.psnr)
+    }
+
+    /// Get codec.
+    fn codec(&self) -> Option<AvCodec> {
+        if let Some(encoder) = self.encoder {
+            return ffmpeg::encoder::find_by_name(encoder);
+        }
+
+        // Try to use the libx264 decoder. If it is not available, then use use whatever default
+        // h264 decoder we have.
+        Some(
+            ffmpeg::encoder::find_by_name("libx264")
+                .unwrap_or(ffmpeg::encoder::find(AvCodecId::H264)?),
+        )
+    }
+
+    /// Get the identifier of the codec to encode with.
+    pub(crate) fn codec_id(&self) -> Option<AvCodecId> {
+        self.codec().map(|codec| codec.id())
+    }
+
+    /// Encode without B-frames, so that packets are written in presentation order.
+    pub(crate) fn without_b_frames(mut self) -> Settings<'o> {
+        self.max_b_frames = Some(0);
+        self
+    }
+
+    /// Get encoder options, including the options for the rate control mode and for placing key
+    /// frames by timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Name of the encoder the options are for.
+    fn options(&self, encoder: &str) -> Result<Options<'static>> {
+        let mut options = self.options.to_owned_options();
+        if let Some(rate_control) = self.rate_control {
+            for (key, value) in rate_control.options_for(encoder)? {
+                options.set(key, &value);
+            }
+        }
+        if self.key_frame_every.is_some() {
+            set_forced_idr_options(&mut options, encoder);
+        }
+        if let Some(frames) = self.lookahead.or(self.low_latency.then_some(0)) {
+            set_lookahead_options(&mut options, encoder, frames);
+        }
+        if self.low_latency {
+            set_low_latency_options(&mut options, encoder);
+        }
+        if self.deterministic {
+            options.set("threads", "1");
+        }
+        if let Some(hdr_metadata) = self.hdr_metadata.filter(|_| encoder == "libx265") {
+            let params = match options.get("x265-params") {
+                Some(params) if !params.is_empty() => {
+                    format!("{params}:{}", hdr_metadata.x265_params())
+                }
+                _ => hdr_metadata.x265_params(),
+            };
+            options.set("x265-params", &params);
+        }
+
+        Ok(options)
+    }
+
+    /// Get the frame size as width and height.
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Get the size of input frames as width and height. This differs from the frame size if
+    /// input frames are cropped.
+    pub(crate) fn input_size(&self) -> (u32, u32) {
+        self.input_size.unwrap_or((self.width, self.height))
+    }
+
+    /// Whether or not the options ask for two-pass encoding, either directly (`pass`) or through
+    /// the parameters of `libx264` or `libx265`.
+    pub(crate) fn is_two_pass(&self) -> bool {
+        let in_params = |key: &str| {
+            self.options.get(key).is_some_and(|params| {
+                params
+                    .split(':')
+                    .any(|param| param.starts_with("pass=") || param.starts_with("stats="))
+            })
+        };
+
+        self.options.get("pass").is_some()
+            || self.options.get("passlogfile").is_some()
+            || in_params("x264-params")
+            || in_params("x265-params")
+    }
+
+    /// Get the color spaces that input frames are converted between when they are scaled to the
+    /// pixel format and size of the encoder. The encoded frames have the color space that the
+    /// output is tagged with (see [`Settings::with_color_spec`]), or the one that fits their
+    /// height if it is not tagged (see [`crate::ScaleColorSpaces`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Color space that input frames declare, and their height.
+    fn scale_color_spaces(&self, input: (ColorSpace, u32)) -> (ColorSpace, ColorSpace) {
+        let space = self
+            .color_spec
+            .map_or(ColorSpace::Unspecified, |color_spec| color_spec.space);
+        ScaleColorSpaces::default().resolve(input, (space, self.height))
+    }
+
+    /// Copy the settings, along with their options.
+    pub(crate) fn to_owned_settings(&self) -> Settings<'static> {
+        Settings {
+            width: self.width,
+            height: self.height,
+            pixel_format: self.pixel_format,
+            key_frame_interval: self.key_frame_interval,
+            key_frame_every: self.key_frame_every,
+            max_b_frames: self.max_b_frames,
+            lookahead: self.lookahead,
+            low_latency: self.low_latency,
+            auto_convert: self.auto_convert,
+            color_spec: self.color_spec,
+            hdr_metadata: self.hdr_metadata,
+            quality_metrics: self.quality_metrics,
+            deterministic: self.deterministic,
+            crop: self.crop,
+            input_size: self.input_size,
+            rate_control: self.rate_control,
+            time_base: self.time_base,
+            frame_rate: self.frame_rate,
+            encoder: self.encoder,
+            options: self.options.to_owned_options(),
+        }
+    }
+}
+
+/// Encoders that settings can name, besides the default H264 encoder.
+#[cfg(feature = "serde")]
+const SETTINGS_ENCODERS: &[&str] = &["libx265", "prores_ks", "libvpx-vp9"];
+
+/// Serialized form of [`Settings`], which owns its options and names its pixel format and time
+/// base in a way that does not depend on the version of ffmpeg.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SettingsRepr<'o> {
+    width: u32,
+    height: u32,
+    #[serde(with = "crate::frame::pixel_format_name")]
+    pixel_format: AvPixel,
+    key_frame_interval: Option<u64>,
+    key_frame_every: Option<Duration>,
+    max_b_frames: Option<usize>,
+    #[serde(default)]
+    lookahead: Option<u32>,
+    #[serde(default)]
+    low_latency: bool,
+    #[serde(default)]
+    auto_convert: bool,
+    #[serde(default)]
+    color_spec: Option<ColorSpec>,
+    #[serde(default)]
+    hdr_metadata: Option<HdrMetadata>,
+    #[serde(default)]
+    quality_metrics: Option<QualityMetrics>,
+    #[serde(default)]
+    deterministic: bool,
+    crop: Option<Crop>,
+    input_size: Option<(u32, u32)>,
+    rate_control: Option<RateControl>,
+    time_base: Option<(i32, i32)>,
+    #[serde(default)]
+    frame_rate: Option<(i32, i32)>,
+    encoder: Option<String>,
+    options: Options<'o>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Settings<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        SettingsRepr {
+            width: self.width,
+            height: self.height,
+            pixel_format: self.pixel_format,
+            key_frame_interval: self.key_frame_interval,
+            key_frame_every: self.key_frame_every,
+            max_b_frames: self.max_b_frames,
+            lookahead: self.lookahead,
+            low_latency: self.low_latency,
+            auto_convert: self.auto_convert,
+            color_spec: self.color_spec,
+            hdr_metadata: self.hdr_metadata,
+            quality_metrics: self.quality_metrics,
+            deterministic: self.deterministic,
+            crop: self.crop,
+            input_size: self.input_size,
+            rate_control: self.rate_control,
+            time_base: self
+                .time_base
+                .map(|time_base| (time_base.numerator(), time_base.denominator())),
+            frame_rate: self
+                .frame_rate
+                .map(|frame_rate| (frame_rate.numerator(), frame_rate.denominator())),
+            encoder: self.encoder.map(str::to_string),
+            options: self.options.to_owned_options(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, 'o> serde::Deserialize<'de> for Settings<'o> {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let repr = SettingsRepr::deserialize(deserializer)?;
+        let encoder = repr
+            .encoder
+            .map(|name| {
+                SETTINGS_ENCODERS
+                    .iter()
+                    .copied()
+                    .find(|encoder| *encoder == name)
+                    .ok_or_else(|| D::Error::custom(format!("unknown encoder: {name}")))
+            })
+            .transpose()?;
+        let time_base = repr
+            .time_base
+            .map(|(num, den)| {
+                if num > 0 && den > 0 {
+                    Ok(AvRational::new(num, den))
+                } else {
+                    Err(D::Error::custom(format!("invalid time base: {num}/{den}")))
+                }
+            })
+            .transpose()?;
+        let frame_rate = repr
+            .frame_rate
+            .map(|(num, den)| {
+                if num > 0 && den > 0 {
+                    Ok(AvRational::new(num, den))
+                } else {
+                    Err(D::Error::custom(format!("invalid frame rate: {num}/{den}")))
+                }
+            })
+            .transpose()?;
+        if let Some(crop) = repr.crop {
+            crop.validate(repr.input_size.unwrap_or((repr.width, repr.height)))
+                .map_err(D::Error::custom)?;
+        }
+
+        Ok(Settings {
+            width: repr.width,
+            height: repr.height,
+            pixel_format: repr.pixel_format,
+            key_frame_interval: repr.key_frame_interval,
+            key_frame_every: repr.key_frame_every,
+            max_b_frames: repr.max_b_frames,
+            lookahead: repr.lookahead,
+            low_latency: repr.low_latency,
+            auto_convert: repr.auto_convert,
+            color_spec: repr.color_spec,
+            hdr_metadata: repr.hdr_metadata,
+            quality_metrics: repr.quality_metrics,
+            deterministic: repr.deterministic,
+            crop: repr.crop,
+            input_size: repr.input_size,
+            rate_control: repr.rate_control,
+            time_base,
+            frame_rate,
+            encoder,
+            options: repr.options,
+        })
+    }
+}
+
+/// Convert an `ndarray` frame to a raw frame for the encoder. This is the part of encoding a
+/// frame that does not need the encoder itself.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to convert in `HWC` format and standard layout.
+/// * `source_timestamp` - Frame timestamp of original source.
+/// * `time_base` - Time base of the encoder.
+/// * `input_size` - Size of the input frames the encoder expects: width and height.
+#[cfg(feature = "ndarray")]
+pub(crate) fn ndarray_to_raw_frame(
+    frame: &Frame,
+    source_timestamp: &Time,
+    time_base: AvRational,
+    input_size: (u32, u32),
+) -> Result<RawFrame> {
+    let (height, width, channels) = frame.dim();
+    let pixel_format = match channels {
+        3 => AV_PIX_FMT_RGB24,
+        4 => AV_PIX_FMT_BGRA,
+        _ => {
+            return Err(Error::invalid_frame_format(
+                "3 or 4 channels",
+                format!("{channels} channels"),
+            ))
+        }
+    };
+
+    if (width, height) != (input_size.0 as usize, input_size.1 as usize) {
+        return Err(frame_size_error(input_size, width, height));
+    }
+
+    let mut frame = convert_ndarray_to_frame(frame, pixel_format).map_err(Error::from)?;
+    frame.set_pts(
+        source_timestamp
+            .aligned_with_rational(time_base)
+            .into_value(),
+    );
+
+    Ok(frame)
+}
+
+/// Create an error for an input frame that does not have the size the encoder expects.
+///
+/// # Arguments
+///
+/// * `input_size` - Size the encoder expects: width and height.
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+fn frame_size_error(input_size: (u32, u32), width: usize, height: usize) -> Error {
+    Error::invalid_frame_format(
+        format!("frame of {}x{}", input_size.0, input_size.1),
+        format!("frame of {width}x{height}"),
+    )
+}
+
+/// Set the options that make forced key frames IDR frames, and that stop the encoder from placing
+/// key frames of its own on scene cuts.
+///
+/// # Arguments
+///
+/// * `options` - Encoder options to extend.
+/// * `encoder` - Name of the encoder.
+fn set_forced_idr_options(options: &mut Options, encoder: &str) {
+    match encoder {
+        "libx264" | "libx264rgb" => {
+            options.set("forced-idr", "1");
+            options.set("sc_threshold", "0");
+        }
+        "libx265" => {
+            options.set("forced-idr", "1");
+            append_codec_param(options, "x265-params", "scenecut=0");
+        }
+        _ if encoder.ends_with("_nvenc") => {
+            options.set("forced-idr", "1");
+        }
+        _ => {}
+    }
+}
+
+/// Set the options for the number of frames the encoder looks ahead (see
+/// [`Settings::with_lookahead`]).
+///
+/// # Arguments
+///
+/// * `options` - Options to add to.
+/// * `encoder` - Name of the encoder.
+/// * `frames` - Number of frames to look ahead.
+fn set_lookahead_options(options: &mut Options, encoder: &str, frames: u32) {
+    match encoder {
+        "libx264" | "libx264rgb" => options.set("rc-lookahead", &frames.to_string()),
+        "libx265" => append_codec_param(options, "x265-params", &format!("rc-lookahead={frames}")),
+        "libvpx" | "libvpx-vp9" | "libaom-av1" => options.set("lag-in-frames", &frames.to_string()),
+        _ if encoder.ends_with("_nvenc") => options.set("rc-lookahead", &frames.to_string()),
+        _ => {}
+    }
+}
+
+/// Set the options that keep encoders from holding back frames, besides lookahead and B-frames
+/// (see [`Settings::with_low_latency`]).
+///
+/// # Arguments
+///
+/// * `options` - Options to add to.
+/// * `encoder` - Name of the encoder.
+fn set_low_latency_options(options: &mut Options, encoder: &str) {
+    match encoder {
+        "libx264" | "libx264rgb" => append_codec_param(options, "x264-params", "sync-lookahead=0"),
+        // `libx265` does not do slice threads, so it needs to be kept from frame threading.
+        "libx265" => append_codec_param(options, "x265-params", "frame-threads=1"),
+        _ => {}
+    }
+}
+
+/// Append a parameter to an option that holds a list of parameters, like `x265-params`.
+///
+/// # Arguments
+///
+/// * `options` - Options to add to.
+/// * `key` - Key of the option.
+/// * `param` - Parameter to append, like `scenecut=0`.
+fn append_codec_param(options: &mut Options, key: &str, param: &str) {
+    let params = match options.get(key) {
+        Some(params) if !params.is_empty() => format!("{params}:{param}"),
+        _ => param.to_string(),
+    };
+    options.set(key, &params);
+}
+
+unsafe impl Send for Encoder {}
+unsafe impl Sync for Encoder {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempPath};
+    use crate::{ColorTransferCharacteristic, Decoder};
+
+    #[test]
+    fn test_forced_idr_options() {
+        let settings =
+            Settings::for_h264_yuv420p(64, 48, false).force_keyframe_every(Duration::from_secs(2));
+        let options = settings.options("libx264").unwrap();
+        assert_eq!(options.get("forced-idr"), Some("1"));
+        assert_eq!(options.get("sc_threshold"), Some("0"));
+
+        let mut x265_options = Options::default();
+        x265_options.set("x265-params", "log-level=error");
+        let options = Settings::for_hevc_custom(64, 48, AvPixel::YUV420P, x265_options)
+            .force_keyframe_every(Duration::from_secs(2))
+            .options("libx265")
+            .unwrap();
+        assert_eq!(
+            options.get("x265-params"),
+            Some("log-level=error:scenecut=0")
+        );
+
+        let options = Settings::for_h264_yuv420p(64, 48, false)
+            .options("libx264")
+            .unwrap();
+        assert_eq!(options.get("forced-idr"), None);
+    }
+
+    #[test]
+    fn test_pick_pixel_format() {
+        let supported = [AvPixel::NV12, AvPixel::YUV420P];
+        assert_eq!(
+            pick_pixel_format(AvPixel::YUV420P, &supported, false).unwrap(),
+            AvPixel::YUV420P
+        );
+        // Encoders that do not list their pixel formats get what is asked for.
+        assert_eq!(
+            pick_pixel_format(AvPixel::YUV444P, &[], false).unwrap(),
+            AvPixel::YUV444P
+        );
+        match pick_pixel_format(AvPixel::YUV444P, &supported, false) {
+            Err(Error::PixelFormatNotSupported {
+                requested,
+                supported,
+            }) => {
+                assert_eq!(requested, AvPixel::YUV444P);
+                assert_eq!(supported, vec![AvPixel::NV12, AvPixel::YUV420P]);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_lookahead_options() {
+        let settings = Settings::for_h264_yuv420p(64, 48, false).with_lookahead(10);
+        let options = settings.options("libx264").unwrap();
+        assert_eq!(options.get("rc-lookahead"), Some("10"));
+        assert_eq!(options.get("x264-params"), None);
+        let options = settings.options("libvpx-vp9").unwrap();
+        assert_eq!(options.get("lag-in-frames"), Some("10"));
+
+        let options = Settings::for_h264_yuv420p(64, 48, false)
+            .with_low_latency()
+            .options("libx264")
+            .unwrap();
+        assert_eq!(options.get("rc-lookahead"), Some("0"));
+        assert_eq!(options.get("x264-params"), Some("sync-lookahead=0"));
+
+        let mut x265_options = Options::default();
+        x265_options.set("x265-params", "log-level=error");
+        let options = Settings::for_hevc_custom(64, 48, AvPixel::YUV420P, x265_options)
+            .with_lookahead(4)
+            .with_low_latency()
+            .options("libx265")
+            .unwrap();
+        assert_eq!(
+            options.get("x265-params"),
+            Some("log-level=error:rc-lookahead=4:frame-threads=1")
+        );
+    }
+
+    #[test]
+    fn test_encoder_low_latency_first_packet() {
+        let path = TempPath::new("low-latency.mkv");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            Settings::for_h264_yuv420p(64, 48, false).with_low_latency(),
+        )
+        .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(2));
+        assert!(encoder.delay() <= 1);
+        assert!(encoder.stats().packets >= 1);
+        encoder.finish().unwrap();
+        drop(encoder);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_settings_serde_round_trip() {
+        let mut settings = Settings::for_hevc_custom(
+            640,
+            360,
+            AvPixel::YUV420P10LE,
+            "preset=slow:x265-params=log-level=error".parse().unwrap(),
+        )
+        .with_key_frame_interval(60)
+        .force_keyframe_every(Duration::from_millis(2500))
+        .with_rate_control(RateControl::ConstrainedQuality {
+            quality: 23,
+            max_bitrate: 4_000_000,
+        })
+        .with_time_base(1, 90_000)
+        .unwrap()
+        .with_crop(320, 180, 1280, 720)
+        .unwrap();
+        settings.max_b_frames = Some(2);
+
+        let json = serde_json::to_string(&settings).unwrap();
+        assert!(json.contains("\"pixel_format\":\"yuv420p10le\""));
+        assert!(json.contains("\"encoder\":\"libx265\""));
+        let restored: Settings = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        assert_eq!(restored.size(), (640, 360));
+        assert_eq!(restored.input_size(), (1280, 720));
+        assert_eq!(restored.encoder, Some("libx265"));
+        assert_eq!(
+            restored.options("libx265").unwrap().get("x265-params"),
+            Some("log-level=error:scenecut=0")
+        );
+
+        let unknown_encoder = json.replace("libx265", "libunknown");
+        assert!(serde_json::from_str::<Settings>(&unknown_encoder).is_err());
+        let unknown_pixel_format = json.replace("yuv420p10le", "yuv999p");
+        assert!(serde_json::from_str::<Settings>(&unknown_pixel_format).is_err());
+    }
+
+    #[test]
+    fn test_key_frame_schedule() {
+        let mut key_frame_schedule = KeyFrameSchedule::new(100);
+        assert!(key_frame_schedule.is_due(10));
+        assert!(!key_frame_schedule.is_due(50));
+        assert!(!key_frame_schedule.is_due(109));
+        assert!(key_frame_schedule.is_due(110));
+        assert!(key_frame_schedule.is_due(450));
+        assert!(!key_frame_schedule.is_due(500));
+        assert!(key_frame_schedule.is_due(510));
+    }
+
+    #[test]
+    fn test_interleaving() {
+        assert!(!Interleaving::Auto.is_enabled(1, false));
+        assert!(Interleaving::Auto.is_enabled(2, false));
+        assert!(Interleaving::Auto.is_enabled(1, true));
+        assert!(Interleaving::Always.is_enabled(1, false));
+        assert!(!Interleaving::Never.is_enabled(2, true));
+    }
+
+    #[test]
+    fn test_packet_stats() {
+        let time_base = AvRational::new(1, 30);
+        let mut stats = PacketStats::default();
+        assert_eq!(stats.summary(0, time_base).average_bitrate, 0);
+
+        // Packets arrive in decoding order, so the timestamps are not monotonic.
+        stats.record(Some(0), 1, 5000, true);
+        stats.record(Some(2), 1, 1000, false);
+        stats.record(Some(1), 1, 1500, false);
+        stats.record(Some(29), 1, 1000, false);
+        stats.record(None, 0, 500, false);
+
+        let summary = stats.summary(30, time_base);
+        assert_eq!(summary.frames, 30);
+        assert_eq!(summary.packets, 5);
+        assert_eq!(summary.bytes, 9000);
+        assert_eq!(summary.keyframes, 1);
+        assert_eq!(summary.duration.as_secs_f64(), 1.0);
+        assert_eq!(summary.average_bitrate, 72000);
+        assert_eq!(summary.psnr_avg, None);
+
+        stats.record_psnr(40.0);
+        stats.record_psnr(44.0);
+        assert_eq!(stats.summary(30, time_base).psnr_avg, Some(42.0));
+    }
+
+    #[test]
+    fn test_frame_psnr() {
+        let quality_stats = |errors: &[u64]| {
+            let mut data = vec![0, 0, 0, 0, b'I', errors.len() as u8, 0, 0];
+            for error in errors {
+                data.extend_from_slice(&error.to_le_bytes());
+            }
+            data
+        };
+        // An error of 1 per sample in each plane of an 8 by 8 frame with 4 by 4 chroma planes.
+        let psnr = frame_psnr(&quality_stats(&[64, 16, 16]), AvPixel::YUV420P, (8, 8)).unwrap();
+        assert!((psnr - 10.0 * (255.0_f64 * 255.0).log10()).abs() < 1e-9);
+        assert_eq!(
+            frame_psnr(&quality_stats(&[0, 0, 0]), AvPixel::YUV420P, (8, 8)),
+            Some(PSNR_LOSSLESS)
+        );
+        // Ten bits per sample.
+        let psnr = frame_psnr(&quality_stats(&[96, 24, 24]), AvPixel::YUV420P10LE, (8, 8)).unwrap();
+        assert!((psnr - 10.0 * (1023.0_f64 * 1023.0 / 1.5).log10()).abs() < 1e-9);
+        // Encoders that do not measure the error leave out the error values.
+        assert_eq!(
+            frame_psnr(&quality_stats(&[]), AvPixel::YUV420P, (8, 8)),
+            None
+        );
+        assert_eq!(frame_psnr(&[0; 4], AvPixel::YUV420P, (8, 8)), None);
+    }
+
+    #[test]
+    fn test_validate_quality_metrics() {
+        let settings = |psnr, ssim| {
+            Settings::for_h264_yuv420p(64, 48, false)
+                .with_quality_metrics(QualityMetrics { psnr, ssim })
+        };
+        assert!(settings(true, false)
+            .validate_quality_metrics("libx264")
+            .is_ok());
+        assert!(matches!(
+            settings(true, false).validate_quality_metrics("h264_nvenc"),
+            Err(Error::QualityMetricNotSupported { metric, .. }) if metric == "PSNR"
+        ));
+        assert!(matches!(
+            settings(false, true).validate_quality_metrics("libx264"),
+            Err(Error::QualityMetricNotSupported { metric, .. }) if metric == "SSIM"
+        ));
+        assert!(Settings::for_h264_yuv420p(64, 48, false)
+            .validate_quality_metrics("h264_nvenc")
+            .is_ok());
+    }
+
+    #[test]
+    fn test_pacer_delay() {
+        let mut pacer = Pacer::new();
+        let start = Instant::now();
+        assert_eq!(pacer.delay(10.0, start), Duration::ZERO);
+        assert_eq!(pacer.delay(10.5, start), Duration::from_millis(500));
+        assert_eq!(
+            pacer.delay(11.0, start + Duration::from_millis(400)),
+            Duration::from_millis(600)
+        );
+        // Packets that are late are written immediately.
+        assert_eq!(
+            pacer.delay(11.0, start + Duration::from_secs(2)),
+            Duration::ZERO
+        );
+        assert_eq!(pacer.delay(9.0, start), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_new_multi_requires_outputs() {
+        assert!(matches!(
+            Encoder::new_multi(&[], Settings::for_h264_yuv420p(64, 48, false)),
+            Err(Error::NoOutputs)
+        ));
+    }
+
+    #[test]
+    fn test_settings_with_crop() {
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .with_crop(16, 8, 96, 64)
+            .unwrap();
+        assert_eq!(settings.size(), (64, 48));
+        assert_eq!(settings.input_size(), (96, 64));
+        assert!(matches!(
+            Settings::for_h264_yuv420p(64, 48, false).with_crop(33, 8, 96, 64),
+            Err(Error::InvalidCropParameters)
+        ));
+        assert!(matches!(
+            Settings::for_h264_yuv420p(64, 48, false).with_crop(0, 0, 32, 32),
+            Err(Error::InvalidCropParameters)
+        ));
+    }
+
+    #[test]
+    fn test_settings_with_time_base() {
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .with_time_base(1, 90_000)
+            .unwrap();
+        assert_eq!(settings.time_base, Some(AvRational::new(1, 90_000)));
+        for (num, den) in [(1, 0), (1, -25), (0, 25)] {
+            assert!(matches!(
+                Settings::for_h264_yuv420p(64, 48, false).with_time_base(num, den),
+                Err(Error::InvalidOptions(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_flush_schedule() {
+        let start = Instant::now();
+        let mut flush_schedule = FlushSchedule::new(FlushInterval::Packets(3), start);
+        let due = (0..6)
+            .map(|_| flush_schedule.is_due(start))
+            .collect::<Vec<_>>();
+        assert_eq!(due, [false, false, true, false, false, true]);
+
+        let mut flush_schedule =
+            FlushSchedule::new(FlushInterval::Duration(Duration::from_secs(1)), start);
+        assert!(!flush_schedule.is_due(start + Duration::from_millis(500)));
+        assert!(flush_schedule.is_due(start + Duration::from_millis(1000)));
+        assert!(!flush_schedule.is_due(start + Duration::from_millis(1500)));
+        assert!(flush_schedule.is_due(start + Duration::from_millis(2000)));
+    }
+
+    #[test]
+    fn test_encode_raw_batch() {
+        let path = TempPath::new("batch.mp4");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        let time_base = encoder.time_base();
+        let frames = |range: std::ops::Range<i64>, width: u32| {
+            range
+                .map(move |i| {
+                    let mut frame = RawFrame::new(AvPixel::RGB24, width, 48);
+                    frame.set_pts(
+                        Time::from_nth_of_a_second(30)
+                            .aligned_with_rational(time_base)
+                            .into_value()
+                            .map(|frame_duration| frame_duration * i),
+                    );
+                    frame
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut batch = frames(0..3, 64);
+        batch.extend(frames(3..4, 32));
+        assert_eq!(encoder.encode_raw_batch(batch).unwrap(), 3);
+        assert!(matches!(
+            encoder.encode_raw_batch(frames(3..4, 32)),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        assert_eq!(encoder.encode_raw_batch(frames(3..10, 64)).unwrap(), 7);
+        assert_eq!(encoder.frames_encoded(), 10);
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_scale_rgb24_and_bgra() {
+        let path = TempPath::new("scale.mp4");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+
+        // Fill both frames with the same red, green and blue stripes.
+        let color = |x: usize| match x * 3 / 64 {
+            0 => [255, 0, 0],
+            1 => [0, 255, 0],
+            _ => [0, 0, 255],
+        };
+        let fill = |pixel_format: AvPixel, bytes_per_pixel: usize| {
+            let mut frame = RawFrame::new(pixel_format, 64, 48);
+            let stride = frame.stride(0);
+            for row in frame.data_mut(0).chunks_mut(stride).take(48) {
+                for x in 0..64 {
+                    let [r, g, b] = color(x);
+                    let pixel = &mut row[x * bytes_per_pixel..(x + 1) * bytes_per_pixel];
+                    match pixel_format {
+                        AvPixel::RGB24 => pixel.copy_from_slice(&[r, g, b]),
+                        _ => pixel.copy_from_slice(&[b, g, r, 255]),
+                    }
+                }
+            }
+            frame
+        };
+
+        // Compare the visible part of the planes, not the padding at the end of each row.
+        let planes = |frame: &RawFrame| {
+            [(0, 64, 48), (1, 32, 24), (2, 32, 24)]
+                .into_iter()
+                .flat_map(|(plane, width, height)| {
+                    frame
+                        .data(plane)
+                        .chunks(frame.stride(plane))
+                        .take(height)
+                        .flat_map(|row| row[..width].to_vec())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let scaled_rgb24 = encoder.scale(fill(AvPixel::RGB24, 3)).unwrap();
+        let scaled_bgra = encoder.scale(fill(AvPixel::BGRA, 4)).unwrap();
+        assert_eq!(planes(&scaled_rgb24), planes(&scaled_bgra));
+
+        // Red has a high V (Cr) and a low U (Cb).
+        assert!(scaled_rgb24.data(2)[0] > 200);
+        assert!(scaled_rgb24.data(1)[0] < 128);
+
+        // Alternating between the formats reuses the scalers.
+        encoder.scale(fill(AvPixel::RGB24, 3)).unwrap();
+        encoder.scale(fill(AvPixel::BGRA, 4)).unwrap();
+        assert_eq!(encoder.scalers.len(), 2);
+    }
+
+    #[test]
+    fn test_flush_output_writes_to_file() {
+        let path = TempPath::new("flush.ts");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, true)).unwrap();
+        for i in 0..30 {
+            let mut frame = RawFrame::new(AvPixel::BGRA, 64, 48);
+            frame.data_mut(0).fill(i as u8);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i as i64),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+
+        let size_before = std::fs::metadata(&path).unwrap().len();
+        encoder.flush_output().unwrap();
+        let size_after = std::fs::metadata(&path).unwrap().len();
+        assert!(size_after > size_before);
+
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_key_frames_with_b_frames() {
+        let path = TempPath::new("b-frames.mkv");
+        let mut settings = Settings::for_h264_yuv420p(64, 48, false);
+        settings.max_b_frames = Some(3);
+        let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+        for (i, mut frame) in testing::frames(40).enumerate() {
+            frame.set_pts(testing::pts(&encoder, i));
+            encoder.encode_raw(frame).unwrap();
+            assert_eq!(encoder.frames_encoded(), i as u64 + 1);
+            assert!(encoder.packets_written() <= encoder.frames_encoded());
+        }
+        encoder.finish().unwrap();
+        assert_eq!(encoder.frames_encoded(), 40);
+        assert_eq!(encoder.packets_written(), 40);
+        drop(encoder);
+
+        let mut reader = crate::Reader::new(&path.locator()).unwrap();
+        let mut key_frames = Vec::new();
+        while let Ok(packet) = reader.read(0) {
+            if packet.is_key() {
+                key_frames.push((packet.pts().as_secs_f64() * 30.0).round() as i64);
+            }
+        }
+        key_frames.sort();
+        let first = key_frames[0];
+        let key_frames = key_frames
+            .into_iter()
+            .map(|key_frame| key_frame - first)
+            .collect::<Vec<_>>();
+        assert_eq!(key_frames, vec![0, 12, 24, 36]);
+    }
+
+    #[test]
+    fn test_force_keyframe_every_with_variable_frame_rate() {
+        let path = TempPath::new("key-grid.mkv");
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .force_keyframe_every(Duration::from_millis(500));
+        let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+        // Frame durations alternate between 20 and 70 milliseconds.
+        let mut timestamp_ms = 0;
+        for i in 0..60 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.set_pts(
+                Time::new(Some(timestamp_ms), AvRational::new(1, 1000))
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value(),
+            );
+            encoder.encode_raw(frame).unwrap();
+            timestamp_ms += if i % 2 == 0 { 20 } else { 70 };
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut reader = crate::Reader::new(&path.locator()).unwrap();
+        let mut key_frames = Vec::new();
+        while let Ok(packet) = reader.read(0) {
+            if packet.is_key() {
+                key_frames.push((packet.pts().as_secs_f64() * 1000.0).round() as i64);
+            }
+        }
+        key_frames.sort();
+        let first = key_frames[0];
+        // One key frame per 500 millisecond grid point, which are at most a frame late.
+        assert_eq!(
+            key_frames.len().saturating_sub(1),
+            (timestamp_ms / 500) as usize
+        );
+        for (n, key_frame) in key_frames.into_iter().enumerate() {
+            let late = key_frame - first - n as i64 * 500;
+            assert!((0..70).contains(&late), "key frame {n} is {late} ms late");
+        }
+    }
+
+    #[test]
+    fn test_extradata() {
+        let settings = Settings::for_h264_yuv420p(64, 48, false);
+        let (encoder, _) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        let extradata = encoder.extradata().unwrap();
+        assert!(crate::extract_parameter_sets_h264(&extradata).is_ok());
+        let extradata = encoder.extradata_annexb().unwrap().unwrap();
+        assert!(extradata.starts_with(&[0, 0, 1]) || extradata.starts_with(&[0, 0, 0, 1]));
+
+        // MPEG-TS has no global header, so the parameter sets are only in band.
+        let settings = Settings::for_h264_yuv420p(64, 48, false);
+        let (encoder, _) = Encoder::new_in_memory(settings, "mpegts").unwrap();
+        assert_eq!(encoder.extradata(), None);
+    }
+
+    #[test]
+    fn test_encode_packetized() {
+        let packets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut encoder = Encoder::new_packetized(
+            Settings::for_h264_yuv420p(64, 48, false),
+            AvRational::new(1, 90_000),
+            {
+                let packets = packets.clone();
+                move |packet| packets.lock().unwrap().push(packet)
+            },
+        )
+        .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        assert!(matches!(encoder.set_chapters(&[]), Err(Error::NoContainer)));
+        encoder.finish().unwrap();
+
+        let packets = packets.lock().unwrap();
+        assert_eq!(packets.len(), 10);
+        assert!(packets[0].is_key);
+        assert!(packets[0].data.starts_with(&[0, 0, 0, 1]));
+        let mut pts = packets
+            .iter()
+            .map(|packet| packet.pts.clone().into_value().unwrap())
+            .collect::<Vec<_>>();
+        pts.sort();
+        assert_eq!(pts[1] - pts[0], 3000);
+    }
+
+    #[test]
+    fn test_write_preencoded() {
+        let frame_at = |i: i64, time_base: AvRational| {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(time_base)
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            frame
+        };
+
+        // Annex B access units, as an encoder outside of the output would produce them.
+        let packets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut encoder = Encoder::new_packetized(
+            Settings::for_h264_yuv420p(64, 48, false),
+            AvRational::new(1, 90_000),
+            {
+                let packets = packets.clone();
+                move |packet| packets.lock().unwrap().push(packet)
+            },
+        )
+        .unwrap();
+        for i in 0..10 {
+            encoder
+                .encode_raw(frame_at(i, encoder.time_base()))
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        let packets = std::mem::take(&mut *packets.lock().unwrap());
+
+        let path = TempPath::new("preencoded.ts");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        let stream_index = encoder
+            .add_preencoded_stream(&CodecParams::new_video(CodecId::H264, 64, 48))
+            .unwrap();
+        assert_eq!(stream_index, 1);
+        assert!(encoder
+            .write_preencoded(0, &[], Time::zero(), Time::zero(), false)
+            .is_err());
+        for (i, packet) in packets.into_iter().enumerate() {
+            encoder
+                .encode_raw(frame_at(i as i64, encoder.time_base()))
+                .unwrap();
+            encoder
+                .write_preencoded(
+                    stream_index,
+                    &packet.data,
+                    packet.pts,
+                    packet.dts,
+                    packet.is_key,
+                )
+                .unwrap();
+        }
+        assert!(matches!(
+            encoder.add_preencoded_stream(&CodecParams::new_video(CodecId::H264, 64, 48)),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        // The pre-encoded stream decodes back.
+        let mut reader = crate::Reader::new(&path.locator()).unwrap();
+        let mut decoder = crate::DecoderSplit::new(&reader, stream_index, None).unwrap();
+        let mut num_frames = 0;
+        while let Ok(packet) = reader.read(stream_index) {
+            if let Some(frame) = decoder.decode_raw(packet).unwrap() {
+                assert_eq!((frame.width(), frame.height()), (64, 48));
+                num_frames += 1;
+            }
+        }
+        while decoder.drain_raw().unwrap().is_some() {
+            num_frames += 1;
+        }
+        assert_eq!(num_frames, 10);
+    }
+
+    #[test]
+    fn test_encode_with_frame_rate_time_base() {
+        const NUM_FRAMES: i64 = 10;
+
+        let path = TempPath::new("time-base.nut");
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .with_time_base(1, 30)
+            .unwrap();
+        let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+        assert_eq!(encoder.time_base(), AvRational::new(1, 30));
+        testing::encode_frames(&mut encoder, 0, testing::frames(NUM_FRAMES as usize));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut reader = crate::Reader::new(&path.locator()).unwrap();
+        let stream_index = reader.best_video_stream_index().unwrap();
+        assert_eq!(
+            reader.input.stream(stream_index).unwrap().time_base(),
+            AvRational::new(1, 30)
+        );
+        let mut pts = std::iter::from_fn(|| reader.read(stream_index).ok())
+            .map(|packet| packet.pts().into_value().unwrap())
+            .collect::<Vec<_>>();
+        pts.sort();
+        assert_eq!(pts, (0..NUM_FRAMES).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_set_stream_disposition() {
+        let path = TempPath::new("disposition.mkv");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        assert!(encoder
+            .set_stream_disposition(1, &[DispositionFlag::Default])
+            .is_err());
+        encoder
+            .set_stream_disposition(0, &[DispositionFlag::Default, DispositionFlag::Original])
+            .unwrap();
+        encoder
+            .encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48))
+            .unwrap();
+        assert!(matches!(
+            encoder.set_stream_disposition(0, &[]),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let reader = crate::Reader::new(&path.locator()).unwrap();
+        let stream_index = reader.best_video_stream_index().unwrap();
+        assert_eq!(
+            reader
+                .stream_info(stream_index)
+                .unwrap()
+                .disposition_flags(),
+            vec![DispositionFlag::Default, DispositionFlag::Original]
+        );
+    }
+
+    #[test]
+    fn test_reconfigure() {
+        let encode = |encoder: &mut Encoder, range: std::ops::Range<i64>, (width, height)| {
+            for i in range {
+                let mut frame = RawFrame::new(AvPixel::RGB24, width, height);
+                frame.set_pts(
+                    Time::from_nth_of_a_second(30)
+                        .aligned_with_rational(encoder.time_base())
+                        .into_value()
+                        .map(|frame_duration| frame_duration * i),
+                );
+                encoder.encode_raw(frame).unwrap();
+            }
+        };
+
+        let path = TempPath::new("reconfigure.ts");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            Settings::for_h264_yuv420p(1280, 720, false),
+        )
+        .unwrap();
+        encode(&mut encoder, 0..15, (1280, 720));
+        assert!(encoder.reconfigure(0, 480).is_err());
+        encoder.reconfigure(854, 480).unwrap();
+        encode(&mut encoder, 15..30, (854, 480));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = crate::Decoder::new(&path.locator()).unwrap();
+        let sizes = decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .map(|frame| {
+                let frame = frame.unwrap();
+                (frame.width(), frame.height())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes.len(), 30);
+        assert!(sizes[..15].iter().all(|size| *size == (1280, 720)));
+        assert!(sizes[15..].iter().all(|size| *size == (854, 480)));
+
+        let path = TempPath::new("reconfigure.mp4");
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            Settings::for_h264_yuv420p(1280, 720, false),
+        )
+        .unwrap();
+        assert!(matches!(
+            encoder.reconfigure(854, 480),
+            Err(Error::ContainerDoesNotSupportReconfiguration(_))
+        ));
+    }
+
+    #[test]
+    fn test_reconfigure_square_with_rotation() {
+        let square_frames = || (0..5).map(|_| RawFrame::new(AvPixel::RGB24, 64, 64));
+        let portrait_frames = || (0..5).map(|_| RawFrame::new(AvPixel::RGB24, 48, 64));
+
+        // Square frames have the same size whether or not the rotation swaps width and height.
+        let path = TempPath::new("reconfigure-rotated.ts");
+        let mut encoder = Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 64, false))
+            .unwrap()
+            .with_transform(Transform::Rotate90)
+            .unwrap();
+        testing::encode_frames(&mut encoder, 0, square_frames());
+        encoder.reconfigure(64, 48).unwrap();
+        assert_eq!(encoder.input_size(), (48, 64));
+        assert!(matches!(
+            encoder.encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48)),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        testing::encode_frames(&mut encoder, 5, portrait_frames());
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = crate::Decoder::new(&path.locator()).unwrap();
+        let sizes = decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .map(|frame| {
+                let frame = frame.unwrap();
+                (frame.width(), frame.height())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes.len(), 10);
+        assert!(sizes[..5].iter().all(|size| *size == (64, 64)));
+        assert!(sizes[5..].iter().all(|size| *size == (64, 48)));
+    }
+
+    #[test]
+    fn test_encode_segmented() {
+        let dir = TempPath::new("segments");
+        std::fs::create_dir_all(&dir).unwrap();
+        let policy = SegmentPolicy {
+            limit: SegmentLimit::Duration(Duration::from_secs(1)),
+            reset_timestamps: true,
+        };
+        let completed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut encoder = Encoder::new_segmented(
+            &dir.join("rec_%04d.mkv"),
+            Settings::for_h264_yuv420p(64, 48, false),
+            policy,
+        )
+        .unwrap()
+        .on_segment_complete({
+            let completed = completed.clone();
+            move |segment| completed.lock().unwrap().push(segment.clone())
+        });
+        testing::encode_frames(&mut encoder, 0, testing::frames(105));
+        encoder.finish().unwrap();
+
+        let completed = completed.lock().unwrap();
+        assert_eq!(completed.len(), 4);
+        for (n, segment) in completed.iter().enumerate() {
+            assert_eq!(segment.path, dir.join(format!("rec_{:04}.mkv", n + 1)));
+            assert!((segment.start.as_secs_f64() - n as f64).abs() < 0.01);
+
+            let mut reader = crate::Reader::new(&segment.path.clone().into()).unwrap();
+            let packet = reader.read(0).unwrap();
+            assert!(packet.is_key());
+            assert!(packet.pts().as_secs_f64().abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_encode_with_side_data_keeps_captions() {
+        let path = TempPath::new("captions.mkv");
+        // A single CEA-608 byte triplet: field 1, "HI".
+        let captions = vec![0xfc, 0x48, 0x49];
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        for i in 0..5 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder
+                .encode_with_side_data(frame, &[SideData::A53Captions(captions.clone())])
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        let frame = decoder.decode_raw().unwrap();
+        assert!(frame.side_data().contains(&SideData::A53Captions(captions)));
+    }
+
+    #[test]
+    fn test_decode_motion_vectors() {
+        let path = TempPath::new("motion.mkv");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        for i in 0..10 {
+            // A white square that moves to the right on a black background.
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            let stride = frame.stride(0);
+            let data = frame.data_mut(0);
+            data.fill(0);
+            for y in 16..32 {
+                for x in (4 + i * 2)..(20 + i * 2) {
+                    data[y * stride + x * 3..y * stride + x * 3 + 3].fill(255);
+                }
+            }
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i as i64),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let mut decoder = Decoder::new(&path.locator()).unwrap().with_motion_vectors();
+        let first = decoder.decode_raw().unwrap();
+        assert!(first.motion_vectors().is_empty());
+        let mut motion_vectors = Vec::new();
+        while let Ok(frame) = decoder.decode_raw() {
+            motion_vectors.extend(frame.motion_vectors());
+        }
+        assert!(motion_vectors
+            .iter()
+            .any(|vector| vector.src_x != vector.dst_x));
+    }
+
+    #[test]
+    fn test_ignored_options() {
+        let path = TempPath::new("ignored.mkv");
+        let codec_options: Options = "preset=fast:nonsense_codec_option=1".parse().unwrap();
+        let output_options: Options = "truncate=1:max_interleave_delta=0:nonsense_muxer_option=1"
+            .parse()
+            .unwrap();
+        let mut encoder = Encoder::new_with_options(
+            &path.locator(),
+            Settings::for_h264_custom(64, 48, AvPixel::YUV420P, codec_options),
+            &output_options,
+        )
+        .unwrap();
+        assert_eq!(encoder.ignored_options(), vec!["nonsense_codec_option"]);
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        encoder.finish().unwrap();
+        assert_eq!(
+            encoder.ignored_options(),
+            vec!["nonsense_codec_option", "nonsense_muxer_option"]
+        );
+        drop(encoder);
+
+        let input_options: Options = "probesize=1000000:nonsense_demuxer_option=1"
+            .parse()
+            .unwrap();
+        let decoder = Decoder::new_with_options(&path.locator(), &input_options).unwrap();
+        assert_eq!(decoder.ignored_options(), ["nonsense_demuxer_option"]);
+    }
+
+    #[test]
+    fn test_hls_output_options() {
+        let hls_output = HlsOutput {
+            segment_duration: Duration::from_millis(1500),
+            playlist_size: 3,
+            delete_old: true,
+            path_pattern: "seg_%03d.ts".to_string(),
+        };
+        let options = hls_output.options_map();
+        assert_eq!(options["hls_time"], "1.5");
+        assert_eq!(options["hls_list_size"], "3");
+        assert_eq!(options["hls_flags"], "independent_segments+delete_segments");
+        assert_eq!(options["hls_segment_filename"], "seg_%03d.ts");
+    }
+
+    #[test]
+    fn test_encode_hls_segments_and_playlist() {
+        const NUM_FRAMES: usize = 300;
+
+        let dir = TempPath::new("hls");
+        std::fs::create_dir_all(&dir).unwrap();
+        let playlist = dir.join("playlist.m3u8");
+        let hls_output = HlsOutput {
+            segment_duration: Duration::from_secs(2),
+            playlist_size: 0,
+            delete_old: false,
+            path_pattern: dir.join("segment_%03d.ts").to_string_lossy().into_owned(),
+        };
+
+        let mut encoder = Encoder::new_hls(
+            &playlist.clone().into(),
+            Settings::for_h264_yuv420p(64, 48, false),
+            &hls_output,
+        )
+        .unwrap();
+        for i in 0..NUM_FRAMES {
+            let mut frame = RawFrame::new(AvPixel::BGRA, 64, 48);
+            frame.data_mut(0).fill(i as u8);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i as i64),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let playlist = std::fs::read_to_string(&playlist).unwrap();
+        assert!(playlist.starts_with("#EXTM3U"));
+        assert!(playlist.contains("#EXT-X-ENDLIST"));
+        let num_segments = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| {
+                entry.as_ref().unwrap().path().extension() == Some(std::ffi::OsStr::new("ts"))
+            })
+            .count();
+        assert!(num_segments >= 3);
+    }
+
+    #[test]
+    fn test_retry_policy_backoff() {
+        let retry_policy = RetryPolicy::new(10, Duration::from_millis(500), Duration::from_secs(4));
+        assert_eq!(retry_policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(retry_policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(retry_policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(retry_policy.backoff(40), Duration::from_secs(4));
+        assert_eq!(RetryPolicy::never().max_attempts, 0);
+    }
+
+    #[test]
+    fn test_new_rtmp_key_frame_interval_at_frame_rate() {
+        let url = Url::parse("rtmp://localhost/live/stream").unwrap();
+        // 60 frames are two seconds at 30 fps, but four seconds at 15 fps.
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .with_frame_rate(15, 1)
+            .unwrap()
+            .with_key_frame_interval(60);
+        assert!(matches!(
+            Encoder::new_rtmp(&url, settings),
+            Err(Error::InvalidKeyFrameInterval)
+        ));
+    }
+
+    #[test]
+    fn test_reconnect_resumes_at_key_frame() {
+        let mut reconnect = Reconnect::new(
+            Url::parse("rtmp://localhost/live/stream").unwrap().into(),
+            &Options::default(),
+            RetryPolicy::default(),
+        );
+        let rebase = |reconnect: &mut Reconnect, pts: i64, key: bool| {
+            let mut packet = AvPacket::empty();
+            packet.set_pts(Some(pts));
+            packet.set_dts(Some(pts));
+            if key {
+                packet.set_flags(AvPacketFlags::KEY);
+            }
+            reconnect.rebase(&mut packet).then(|| packet.pts().unwrap())
+        };
+
+        // Timestamps are untouched on the first connection.
+        assert_eq!(rebase(&mut reconnect, 3000, false), Some(3000));
+        assert_eq!(rebase(&mut reconnect, 6000, false), Some(6000));
+
+        reconnect.awaiting_key_frame = true;
+        assert_eq!(rebase(&mut reconnect, 9000, false), None);
+        assert_eq!(rebase(&mut reconnect, 12000, true), Some(0));
+        assert_eq!(rebase(&mut reconnect, 15000, false), Some(3000));
+    }
+
+    #[test]
+    fn test_encode_in_memory_and_decode_back() {
+        const NUM_FRAMES: usize = 30;
+
+        let (mut encoder, sink) =
+            Encoder::new_in_memory(Settings::for_h264_yuv420p(64, 48, false), "mp4").unwrap();
+        for i in 0..NUM_FRAMES {
+            let mut frame = RawFrame::new(AvPixel::BGRA, 64, 48);
+            frame.data_mut(0).fill((i * 8) as u8);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i as i64),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let bytes = sink.into_bytes();
+        assert!(!bytes.is_empty());
+
+        let stats = encoder.stats();
+        assert_eq!(stats.frames, NUM_FRAMES as u64);
+        assert_eq!(stats.packets, NUM_FRAMES as u64);
+        assert!(stats.bytes > 0 && stats.bytes < bytes.len() as u64);
+        assert!(stats.keyframes >= 1);
+        // The last packet may not have a duration.
+        assert!(stats.duration.as_secs_f64() >= 29.0 / 30.0);
+        assert!(stats.average_bitrate > 0);
+
+        let mut decoder = Decoder::from_bytes(bytes).unwrap();
+        let num_frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+        assert_eq!(num_frames, NUM_FRAMES);
+    }
+
+    #[test]
+    fn test_encode_measures_psnr() {
+        let settings = || {
+            Settings::for_h264_yuv420p(64, 48, false).with_quality_metrics(QualityMetrics {
+                psnr: true,
+                ssim: false,
+            })
+        };
+        let (mut encoder, _sink) = Encoder::new_in_memory(settings(), "mp4").unwrap();
+        for i in 0..10 {
+            let mut frame = RawFrame::new(AvPixel::BGRA, 64, 48);
+            frame.data_mut(0).fill((i * 8) as u8);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        let psnr = encoder.stats().psnr_avg.unwrap();
+        assert!(psnr > 20.0 && psnr <= PSNR_LOSSLESS);
+
+        let ssim = settings().with_quality_metrics(QualityMetrics {
+            psnr: false,
+            ssim: true,
+        });
+        assert!(matches!(
+            Encoder::new_in_memory(ssim, "mp4"),
+            Err(Error::QualityMetricNotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_packet_durations() {
+        let packet = |pts| {
+            let mut packet = AvPacket::empty();
+            packet.set_pts(Some(pts));
+            packet
+        };
+        let settings = Settings::for_h264_yuv420p(64, 48, false);
+
+        // Constant frame rate: every packet lasts one frame, and none are held back.
+        let mut durations = PacketDurations::new(
+            &Settings::for_h264_yuv420p(64, 48, false)
+                .with_frame_rate(25, 1)
+                .unwrap(),
+            AvRational::new(1, 1000),
+        );
+        assert_eq!(durations.push(packet(0)).unwrap().duration(), 40);
+
+        // Variable frame rate with B-frames: packets come out in decoding order.
+        let mut durations = PacketDurations::new(&settings, AvRational::new(1, 30));
+        for pts in [0, 1, 2, 4] {
+            durations.frame_sent(Some(pts));
+        }
+        let mut written = Vec::new();
+        for pts in [0, 4, 1, 2] {
+            written.extend(durations.push(packet(pts)));
+        }
+        written.extend(durations.finish());
+        let written = written
+            .iter()
+            .map(|packet| (packet.pts().unwrap(), packet.duration()))
+            .collect::<Vec<_>>();
+        // The last frame, of which the next frame is not known, lasts as long as the time since
+        // the frame before it.
+        assert_eq!(written, vec![(0, 1), (4, 2), (1, 1), (2, 2)]);
+
+        // Low latency: nothing is held back.
+        let mut durations =
+            PacketDurations::new(&settings.with_low_latency(), AvRational::new(1, 30));
+        durations.frame_sent(Some(0));
+        assert_eq!(durations.push(packet(0)).unwrap().duration(), 0);
+        durations.frame_sent(Some(2));
+        assert_eq!(durations.push(packet(2)).unwrap().duration(), 2);
+        assert!(durations.finish().is_none());
+    }
+
+    #[test]
+    fn test_encode_after_failed_header_write() {
+        // IVF only holds VP8, VP9 and AV1, so writing the header of an H.264 stream fails.
+        let path = TempPath::new("bad-header.ivf");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        for i in 0..2 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.set_pts(Some(i));
+            assert!(encoder.encode_raw(frame).is_err());
+        }
+        let _ = encoder.finish();
+    }
+
+    #[test]
+    fn test_set_bitrate() {
+        const NUM_FRAMES: i64 = 60;
+
+        // A static scene of noise, so that the encoder has use for every bit it may spend.
+        let mut seed = 1u32;
+        let noise = (0..160 * 120 * 3)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<_>>();
+        let mut options = Options::new_h264_realtime();
+        options.set("maxrate", "2000000");
+        options.set("bufsize", "2000000");
+        let settings = Settings::for_h264_custom(160, 120, AvPixel::YUV420P, options)
+            .with_rate_control(RateControl::TargetBitrate(2_000_000));
+
+        let packets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut encoder = Encoder::new_packetized(settings, AvRational::new(1, 90_000), {
+            let packets = packets.clone();
+            move |packet| packets.lock().unwrap().push(packet)
+        })
+        .unwrap();
+        for i in 0..NUM_FRAMES {
+            if i == NUM_FRAMES / 2 {
+                encoder.set_bitrate(50_000).unwrap();
+            }
+            let mut frame = RawFrame::new(AvPixel::RGB24, 160, 120);
+            let stride = frame.stride(0);
+            for (row, line) in noise.chunks(160 * 3).enumerate() {
+                frame.data_mut(0)[row * stride..row * stride + line.len()].copy_from_slice(line);
+            }
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let packets = std::mem::take(&mut *packets.lock().unwrap());
+        assert_eq!(packets.len(), NUM_FRAMES as usize);
+        let (before, after) = packets.split_at(packets.len() / 2);
+        // The change starts with a key frame.
+        assert!(after[0].is_key);
+        let size = |packets: &[EncodedPacket]| -> usize {
+            packets.iter().map(|packet| packet.data.len()).sum()
+        };
+        assert!(size(after) * 4 < size(before));
+
+        // Constant quality has no bitrate to change.
+        let (mut encoder, _sink) =
+            Encoder::new_in_memory(Settings::for_h264_yuv420p(64, 48, false), "mpegts").unwrap();
+        assert!(matches!(
+            encoder.set_bitrate(50_000),
+            Err(Error::ReconfigurationNotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deterministic_encode() {
+        let encode = |name: &str, settings: Settings| {
+            let path = TempPath::new(&format!("deterministic-{name}.mkv"));
+            testing::write_test_video_with_settings(
+                &path.locator(),
+                settings,
+                (0..30).map(|i| testing::filled_frame((i * 8) as u8)),
+            );
+            std::fs::read(&path).unwrap()
+        };
+        let settings = || Settings::for_h264_yuv420p(64, 48, false);
+
+        assert_eq!(
+            encode("first", settings().deterministic()),
+            encode("second", settings().deterministic())
+        );
+        // Matroska has random identifiers otherwise.
+        assert_ne!(encode("first", settings()), encode("second", settings()));
+    }
+
+    #[test]
+    fn test_encode_and_hash_back() {
+        const NUM_FRAMES: usize = 10;
+
+        let path = TempPath::new("hash.mkv");
+        testing::write_test_video(
+            &path.locator(),
+            (0..NUM_FRAMES).map(|i| testing::filled_frame((i * 16) as u8)),
+        );
+
+        let source: Locator = path.locator();
+        let hashes = crate::hash_video(&source).unwrap();
+        assert_eq!(hashes.len(), NUM_FRAMES);
+        assert_eq!(hashes, crate::hash_video(&source).unwrap());
+
+        let hashes_rgb24 =
+            crate::hash_video_with_format(&source, crate::HashFormat::Rgb24).unwrap();
+        assert_eq!(hashes_rgb24.len(), NUM_FRAMES);
+        assert_ne!(hashes_rgb24[0].1, hashes[0].1);
+        #[cfg(feature = "ndarray")]
+        {
+            let mut decoder = Decoder::new(&source).unwrap();
+            let decoded = decoder
+                .decode_iter()
+                .take_while(Result::is_ok)
+                .map(|frame| crate::hash_frame(&frame.unwrap().1))
+                .collect::<Vec<_>>();
+            let hashes_rgb24 = hashes_rgb24
+                .into_iter()
+                .map(|(_, hash)| hash)
+                .collect::<Vec<_>>();
+            assert_eq!(decoded, hashes_rgb24);
+        }
+    }
+
+    #[test]
+    fn test_encode_faststart_moves_index_to_front() {
+        let dir = TempPath::new("faststart");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest: Locator = dir.join("faststart.mp4").into();
+
+        let mut encoder = Encoder::new_with_builder(
+            WriterBuilder::new(&dest).faststart(),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(30));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let bytes = std::fs::read(dir.join("faststart.mp4")).unwrap();
+        let position = |atom: &[u8]| bytes.windows(4).position(|window| window == atom).unwrap();
+        assert!(position(b"moov") < position(b"mdat"));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_encode_f32_and_decode_f32_back() {
+        let normalization = Normalization::imagenet();
+        let (mut encoder, sink) =
+            Encoder::new_in_memory(Settings::for_h264_yuv420p(64, 48, false), "mp4").unwrap();
+        // Mid gray, expressed in the normalized range of each channel.
+        let gray = crate::frame::convert_rgb24_to_ndarray_f32(&[128; 3], 3, 1, 1, &normalization);
+        let mut frame = Frame32::zeros((3, 48, 64));
+        for channel in 0..3 {
+            frame
+                .index_axis_mut(ndarray::Axis(0), channel)
+                .fill(gray[[channel, 0, 0]]);
+        }
+        for i in 0..10 {
+            let timestamp = Time::new(Some(i), AvRational::new(1, 30));
+            encoder
+                .encode_f32(&frame, &normalization, &timestamp)
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::from_bytes(sink.into_bytes()).unwrap();
+        let (_, decoded) = decoder.decode_f32(&normalization).unwrap();
+        assert_eq!(decoded.dim(), (3, 48, 64));
+        for (decoded, expected) in decoded.iter().zip(frame.iter()) {
+            // Allow for a few steps of compression and color conversion error.
+            assert!((decoded - expected).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_encode_cropped_and_decode_cropped_back() {
+        const RED: [u8; 3] = [220, 20, 20];
+        const GREEN: [u8; 3] = [20, 220, 20];
+
+        // Input frames are red, except for the region that is encoded, which is green.
+        let settings = Settings::for_h264_yuv420p(64, 48, false)
+            .with_crop(16, 8, 96, 64)
+            .unwrap();
+        let (mut encoder, sink) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        for i in 0..10 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 96, 64);
+            let stride = frame.stride(0);
+            for (y, row) in frame.data_mut(0).chunks_mut(stride).take(64).enumerate() {
+                for (x, pixel) in row.chunks_exact_mut(3).take(96).enumerate() {
+                    let inside = (16..80).contains(&x) && (8..56).contains(&y);
+                    pixel.copy_from_slice(if inside { &GREEN } else { &RED });
+                }
+            }
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        assert!(matches!(
+            encoder.encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48)),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        encoder.finish().unwrap();
+        let bytes = sink.into_bytes();
+
+        let is_green = |pixel: &[u8]| {
+            pixel
+                .iter()
+                .zip(GREEN)
+                .all(|(component, expected)| (*component as i32 - expected as i32).abs() <= 16)
+        };
+
+        let mut decoder = Decoder::from_bytes(bytes.clone()).unwrap();
+        let frame = decoder.decode_raw().unwrap();
+        assert_eq!((frame.width(), frame.height()), (64, 48));
+        // Stay away from the edges, where compression blends in neighbouring colors.
+        for (x, y) in [(4, 4), (59, 4), (4, 43), (59, 43), (32, 24)] {
+            let offset = y * frame.stride(0) + x * 3;
+            assert!(is_green(&frame.data(0)[offset..offset + 3]));
+        }
+
+        // Odd offsets do not line up with the chroma samples of the YUV420p stream.
+        let mut decoder = Decoder::from_bytes(bytes.clone())
+            .unwrap()
+            .with_crop(5, 3, 33, 25)
+            .unwrap();
+        assert_eq!(decoder.size(), (64, 48));
+        assert_eq!(decoder.size_out(), (33, 25));
+        let frame = decoder.decode_raw().unwrap();
+        assert_eq!((frame.width(), frame.height()), (33, 25));
+        let offset = 12 * frame.stride(0) + 16 * 3;
+        assert!(is_green(&frame.data(0)[offset..offset + 3]));
+
+        assert!(matches!(
+            Decoder::from_bytes(bytes).unwrap().with_crop(32, 0, 33, 48),
+            Err(Error::InvalidCropParameters)
+        ));
+    }
+
+    #[test]
+    fn test_encode_and_decode_with_filter() {
+        let settings = || Settings::for_h264_yuv420p(64, 48, false);
+        let (encoder, _) = Encoder::new_in_memory(settings(), "mp4").unwrap();
+        assert!(matches!(
+            encoder.with_filter("nosuchfilter"),
+            Err(Error::InvalidFilterGraph(reason)) if reason.contains("nosuchfilter")
+        ));
+
+        let (encoder, sink) = Encoder::new_in_memory(settings(), "mp4").unwrap();
+        // One second of frames at 30 frames per second comes out at 10 frames per second.
+        let mut encoder = encoder.with_filter("fps=10").unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(30));
+        encoder.finish().unwrap();
+        assert!((9..=11).contains(&encoder.stats().frames));
+        let bytes = sink.into_bytes();
+
+        let mut decoder = Decoder::from_bytes(bytes)
+            .unwrap()
+            .with_filter("scale=32:24,fps=20")
+            .unwrap();
+        assert_eq!(decoder.size(), (32, 24));
+        assert_eq!(decoder.size_out(), (32, 24));
+        assert_eq!(decoder.time_base(), AvRational::new(1, 20));
+        let frame = decoder.decode_raw().unwrap();
+        assert_eq!((frame.width(), frame.height()), (32, 24));
+        assert_eq!(frame.format(), AvPixel::RGB24);
+        assert_eq!(frame.packet().dts, frame.pts().unwrap());
+    }
+
+    #[test]
+    fn test_encode_and_decode_with_transform() {
+        const RED: [u8; 3] = [220, 20, 20];
+        const GREEN: [u8; 3] = [20, 220, 20];
+
+        // Portrait input frames, red at the top and green at the bottom. Rotated clockwise, the top
+        // ends up on the right.
+        let settings = Settings::for_h264_yuv420p(64, 48, false);
+        let (encoder, sink) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        let mut encoder = encoder.with_transform(Transform::Rotate90).unwrap();
+        assert!(matches!(
+            encoder.encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48)),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        for i in 0..10 {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 48, 64);
+            let stride = frame.stride(0);
+            for (y, row) in frame.data_mut(0).chunks_mut(stride).take(64).enumerate() {
+                for pixel in row.chunks_exact_mut(3).take(48) {
+                    pixel.copy_from_slice(if y < 32 { &RED } else { &GREEN });
+                }
+            }
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        let bytes = sink.into_bytes();
+
+        let pixel = |frame: &RawFrame, x: usize, y: usize| {
+            let offset = y * frame.stride(0) + x * 3;
+            frame.data(0)[offset..offset + 3].to_vec()
+        };
+        let is = |pixel: Vec<u8>, expected: [u8; 3]| {
+            pixel
+                .iter()
+                .zip(expected)
+                .all(|(component, expected)| (*component as i32 - expected as i32).abs() <= 16)
+        };
+
+        let frame = Decoder::from_bytes(bytes.clone())
+            .unwrap()
+            .decode_raw()
+            .unwrap();
+        assert_eq!((frame.width(), frame.height()), (64, 48));
+        assert!(is(pixel(&frame, 8, 24), GREEN));
+        assert!(is(pixel(&frame, 56, 24), RED));
+
+        let mut decoder = Decoder::from_bytes(bytes.clone())
+            .unwrap()
+            .with_transform(Transform::HFlip)
+            .unwrap();
+        let frame = decoder.decode_raw().unwrap();
+        assert!(is(pixel(&frame, 8, 24), RED));
+
+        // Rotating back gives the portrait frames.
+        let mut decoder = Decoder::from_bytes(bytes)
+            .unwrap()
+            .with_transform(Transform::Rotate270)
+            .unwrap();
+        assert_eq!(decoder.size(), (48, 64));
+        assert_eq!(decoder.size_out(), (48, 64));
+        let frame = decoder.decode_raw().unwrap();
+        assert!(is(pixel(&frame, 24, 8), RED));
+        assert!(is(pixel(&frame, 24, 56), GREEN));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_encode_yuv_and_decode_yuv_back() {
+        // H.264 requires even dimensions for 4:2:0, but the chroma planes of this size have odd
+        // dimensions.
+        let (width, height) = (66, 46);
+        let (mut encoder, sink) =
+            Encoder::new_in_memory(Settings::for_h264_yuv420p(width, height, false), "mp4")
+                .unwrap();
+        for i in 0..10 {
+            let mut frame = YuvFrame::zeros(width as u32, height as u32);
+            frame.y.fill(100);
+            frame.u.fill(90);
+            frame.v.fill(160);
+            let timestamp = Time::new(Some(i), AvRational::new(1, 30));
+            encoder.encode_yuv(&frame, &timestamp).unwrap();
+        }
+        assert!(matches!(
+            encoder.encode_yuv(&YuvFrame::zeros(64, 48), &Time::zero()),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::from_bytes(sink.into_bytes()).unwrap();
+        let (_, frame) = decoder.decode_yuv().unwrap();
+        assert_eq!(frame.size(), (66, 46));
+        assert_eq!(frame.u.dim(), (23, 33));
+        for (plane, expected) in [(&frame.y, 100), (&frame.u, 90), (&frame.v, 160)] {
+            assert!(plane.iter().all(|value| value.abs_diff(expected) <= 4));
+        }
+    }
+
+    #[test]
+    fn test_encode_hdr_metadata() {
+        let hdr_metadata = HdrMetadata {
+            primaries: [(0.708, 0.292), (0.17, 0.797), (0.131, 0.046)],
+            white_point: (0.3127, 0.329),
+            max_luminance: 1000.0,
+            min_luminance: 0.0001,
+            max_cll: 1000,
+            max_fall: 400,
+        };
+        let settings = |pixel_format: AvPixel| {
+            Settings::for_hevc_custom(64, 48, pixel_format, Options::new_hevc())
+                .with_hdr_metadata(hdr_metadata)
+        };
+        let path = TempPath::new("hdr.mkv");
+
+        // HDR metadata needs a high bit depth and an HDR transfer characteristic.
+        assert!(matches!(
+            Encoder::new(&path.locator(), settings(AvPixel::YUV420P10LE)),
+            Err(Error::InvalidOptions(_))
+        ));
+        assert!(matches!(
+            Encoder::new(
+                &path.locator(),
+                settings(AvPixel::YUV420P).with_color_spec(ColorSpec::bt2020_pq())
+            ),
+            Err(Error::InvalidOptions(_))
+        ));
+
+        let mut encoder = Encoder::new(
+            &path.locator(),
+            settings(AvPixel::YUV420P10LE).with_color_spec(ColorSpec::bt2020_pq()),
+        )
+        .unwrap();
+        testing::encode_frames(
+            &mut encoder,
+            0,
+            (0..5).map(|_| RawFrame::new(AvPixel::RGB48, 64, 48)),
+        );
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let approx_eq = |decoded: HdrMetadata| {
+            let close = |a: f64, b: f64| (a - b).abs() < 1e-4;
+            decoded
+                .primaries
+                .iter()
+                .chain([&decoded.white_point])
+                .zip(
+                    hdr_metadata
+                        .primaries
+                        .iter()
+                        .chain([&hdr_metadata.white_point]),
+                )
+                .all(|(a, b)| close(a.0, b.0) && close(a.1, b.1))
+                && close(decoded.max_luminance, hdr_metadata.max_luminance)
+                && close(decoded.min_luminance, hdr_metadata.min_luminance)
+                && decoded.max_cll == hdr_metadata.max_cll
+                && decoded.max_fall == hdr_metadata.max_fall
+        };
+        let mut decoder = Decoder::new(&path.locator()).unwrap();
+        assert_eq!(
+            decoder.color_transfer_characteristic(),
+            ColorTransferCharacteristic::SMPTE2084
+        );
+        assert!(approx_eq(
+            HdrMetadata::from_side_data(&decoder.stream_side_data()).unwrap()
+        ));
+        // The SEI messages of `libx265`.
+        let frame = decoder.decode_raw().unwrap();
+        assert!(approx_eq(
+            HdrMetadata::from_side_data(&frame.side_data()).unwrap()
+        ));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_encode_u16_keeps_high_bit_depth() {
+        let (width, height) = (64, 64);
+        let settings =
+            Settings::for_hevc_custom(width, height, PixelFormat::YUV420P10LE, Options::new_hevc());
+        let (mut encoder, sink) = Encoder::new_in_memory(settings, "mp4").unwrap();
+        // A gradient with 4096 levels, which does not fit in 8 bits.
+        let frame = FrameU16::from_shape_fn((height, width, 3), |(y, x, _)| {
+            ((y * width + x) * 16) as u16
+        });
+        for i in 0..5 {
+            let timestamp = Time::new(Some(i), AvRational::new(1, 30));
+            encoder.encode_u16(&frame, &timestamp).unwrap();
+        }
+        assert!(matches!(
+            encoder.encode_u16(&FrameU16::zeros((height, width, 2)), &Time::zero()),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::from_bytes(sink.into_bytes()).unwrap();
+        let (_, frame) = decoder.decode_u16().unwrap();
+        let mut levels = frame.iter().copied().collect::<Vec<_>>();
+        levels.sort_unstable();
+        levels.dedup();
+        assert!(levels.len() > 256, "only {} levels", levels.len());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_encode_alpha_and_decode_alpha_back() {
+        let (width, height) = (64, 48);
+        // Opaque red, with a transparent hole in the middle.
+        let frame = Frame::from_shape_fn((height, width, 4), |(y, x, c)| match c {
+            2 => 255,
+            3 if (16..32).contains(&y) && (16..48).contains(&x) => 0,
+            3 => 255,
+            _ => 0,
+        });
+
+        for (settings, extension) in [
+            (Settings::for_vp9_alpha(width, height), "webm"),
+            (Settings::for_prores_4444_alpha(width, height), "mov"),
+        ] {
+            let path = TempPath::new(&format!("alpha.{extension}"));
+            let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+            for i in 0..5 {
+                let timestamp = Time::new(Some(i), AvRational::new(1, 30));
+                encoder.encode(&frame, &timestamp).unwrap();
+            }
+            encoder.finish().unwrap();
+            drop(encoder);
+
+            let mut decoder = Decoder::new(&path.locator()).unwrap();
+            assert!(decoder.has_alpha());
+            let (_, decoded) = decoder.decode().unwrap();
+            assert_eq!(decoded.dim(), (height, width, 4));
+            assert!(decoded[[24, 32, 3]] < 16);
+            assert!(decoded[[4, 4, 3]] > 240);
+            assert!(decoded[[4, 4, 2]] > 200);
+        }
+    }
+
+    #[test]
+    fn test_encode_webm_packet_durations() {
+        let (width, height) = (64, 48);
+        // Timestamps in thirtieths of a second, with a frame left out.
+        let timestamps = [0, 1, 2, 4, 5];
+        // Without a constant frame rate, the gap is part of the frame before it.
+        for (settings, expected_durations) in [
+            (Settings::for_vp9_alpha(width, height), vec![1, 1, 2, 1, 1]),
+            (
+                Settings::for_vp9_alpha(width, height)
+                    .with_frame_rate(30, 1)
+                    .unwrap(),
+                vec![1; 5],
+            ),
+        ] {
+            let path = TempPath::new("durations.webm");
+            let mut encoder = Encoder::new(&path.locator(), settings).unwrap();
+            for i in timestamps {
+                let mut frame = RawFrame::new(AvPixel::RGBA, width as u32, height as u32);
+                frame.set_pts(
+                    Time::new(Some(i), AvRational::new(1, 30))
+                        .aligned_with_rational(encoder.time_base())
+                        .into_value(),
+                );
+                encoder.encode_raw(frame).unwrap();
+            }
+            encoder.finish().unwrap();
+            drop(encoder);
+
+            // The file lasts until the end of the last frame.
+            let mut reader = crate::Reader::new(&path.locator()).unwrap();
+            let duration = reader.input.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE);
+            assert!(
+                (duration - 6.0 / 30.0).abs() < 0.002,
+                "duration: {duration}"
+            );
+            let stream_index = reader.best_video_stream_index().unwrap();
+            let durations = std::iter::from_fn(|| reader.read(stream_index).ok())
+                .map(|packet| (packet.duration().as_secs_f64() * 30.0).round() as i64)
+                .collect::<Vec<_>>();
+            assert_eq!(durations, expected_durations);
+
+            // Decoded frames carry the durations along.
+            let mut decoder = Decoder::new(&path.locator()).unwrap();
+            let frame = decoder.decode_raw().unwrap();
+            assert!(RawFrameMetadata::duration(&frame).is_some_and(|duration| duration > 0));
+        }
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_encode_image_and_decode_image_back() {
+        let colors = [[200, 40, 40], [40, 200, 40], [40, 40, 200]];
+        let (mut encoder, sink) =
+            Encoder::new_in_memory(Settings::for_h264_yuv420p(64, 48, false), "mp4").unwrap();
+        for (i, color) in colors.iter().enumerate() {
+            let image = image::RgbImage::from_pixel(64, 48, image::Rgb(*color));
+            let timestamp = Time::new(Some(i as i64), AvRational::new(1, 30));
+            encoder
+                .encode_image(&image::DynamicImage::ImageRgb8(image), &timestamp)
+                .unwrap();
+        }
+        // Images of other types are converted.
+        let image = image::RgbImage::from_pixel(64, 48, image::Rgb([128, 128, 128]));
+        let image = image::DynamicImage::ImageRgb8(image).to_rgba16();
+        encoder
+            .encode_image(
+                &image::DynamicImage::ImageRgba16(image),
+                &Time::new(Some(3), AvRational::new(1, 30)),
+            )
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let mut decoder = Decoder::from_bytes(sink.into_bytes()).unwrap();
+        for expected in colors.iter().chain([&[128, 128, 128]]) {
+            let (_, image) = decoder.decode_image().unwrap();
+            assert_eq!(image.dimensions(), (64, 48));
+            for (component, expected) in image.get_pixel(32, 24).0.iter().zip(expected) {
+                // Allow for compression and color conversion error.
+                assert!((*component as i32 - *expected as i32).abs() <= 8);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encode_to_pipe_and_decode_back() {
+        use std::os::fd::AsRawFd;
+
+        const NUM_FRAMES: usize = 30;
+
+        let (pipe_reader, pipe_writer) = std::io::pipe().unwrap();
+        let reader_locator = Locator::from_pipe_fd(pipe_reader.as_raw_fd());
+        let writer_locator = Locator::from_pipe_fd(pipe_writer.as_raw_fd());
+
+        let writer_thread = std::thread::spawn(move || {
+            let mut encoder = Encoder::new_with_format(
+                &writer_locator,
+                Settings::for_h264_yuv420p(64, 48, false),
+                "mpegts",
+            )
+            .unwrap();
+            for i in 0..NUM_FRAMES {
+                let mut frame = RawFrame::new(AvPixel::BGRA, 64, 48);
+                frame.data_mut(0).fill((i * 8) as u8);
+                frame.set_pts(
+                    Time::from_nth_of_a_second(30)
+                        .aligned_with_rational(encoder.time_base())
+                        .into_value()
+                        .map(|frame_duration| frame_duration * i as i64),
+                );
+                encoder.encode_raw(frame).unwrap();
+            }
+            encoder.finish().unwrap();
+            drop(encoder);
+            // Closing the write end signals end of stream to the reader.
+            drop(pipe_writer);
+        });
+
+        let reader_thread = std::thread::spawn(move || {
+            let mut decoder = Decoder::new(&reader_locator).unwrap();
+            let num_frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+            drop(decoder);
+            drop(pipe_reader);
+            num_frames
+        });
+
+        writer_thread.join().unwrap();
+        assert_eq!(reader_thread.join().unwrap(), NUM_FRAMES);
+    }
+
+    #[test]
+    fn test_raw_contexts() {
+        let path = TempPath::new("raw.mp4");
+        let mut encoder =
+            Encoder::new(&path.locator(), Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        assert_eq!(encoder.codec_context().id(), AvCodecId::H264);
+        let codec_context = unsafe { encoder.as_raw_codec_context() };
+        assert_eq!(
+            unsafe { ((*codec_context).width, (*codec_context).height) },
+            (64, 48)
+        );
+
+        let format_context = unsafe { encoder.as_raw_format_context() }.unwrap();
+        assert_eq!(unsafe { (*format_context).nb_streams }, 1);
+        assert_eq!(encoder.writer().unwrap().format_context().nb_streams(), 1);
+
+        encoder
+            .encode_raw(RawFrame::new(AvPixel::RGB24, 64, 48))
+            .unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_finish_across_formats() {
+        let dir = TempPath::new("formats");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Format, file name, and whether it needs global headers, has a trailer and holds more
+        // than one stream.
+        for (format, name, global_header, trailer, multiple_streams) in [
+            ("mp4", "out.mp4", true, true, true),
+            ("matroska", "out.mkv", true, true, true),
+            ("mpegts", "out.ts", false, false, true),
+            ("flv", "out.flv", true, true, true),
+            ("image2", "frame-%03d.264", false, false, false),
+        ] {
+            let settings = Settings::for_h264_yuv420p(64, 48, false);
+            let mut encoder =
+                Encoder::new_with_format(&dir.join(name).into(), settings, format).unwrap();
+            let caps = encoder.writer().unwrap().format_capabilities();
+            assert_eq!(caps.needs_global_header, global_header, "{format}");
+            assert_eq!(caps.has_trailer, trailer, "{format}");
+            assert_eq!(caps.supports_multiple_streams, multiple_streams, "{format}");
+            assert_eq!(caps.seekable_output_required, format == "mp4", "{format}");
+
+            testing::encode_frames(&mut encoder, 0, testing::frames(3));
+            encoder.finish().unwrap();
+        }
+        assert!(dir.join("frame-003.264").exists());
+    }
+
+    #[test]
+    fn test_write_header() {
+        use crate::TagTarget;
+
+        let dir = TempPath::new("header");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // By default, an encoder that never gets a frame writes nothing.
+        let lazy = dir.join("lazy.mkv");
+        let mut encoder = Encoder::new(
+            &lazy.clone().into(),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        encoder.finish().unwrap();
+        drop(encoder);
+        assert_eq!(std::fs::metadata(&lazy).unwrap().len(), 0);
+
+        // With the header written up front, it writes a valid container without frames.
+        let eager = dir.join("eager.mkv");
+        let mut encoder = Encoder::new(
+            &eager.clone().into(),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        encoder.write_header().unwrap();
+        encoder.write_header().unwrap();
+        assert!(matches!(
+            encoder.set_tag(TagTarget::Global, "title", "Empty"),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        encoder.finish().unwrap();
+        drop(encoder);
+        let mut decoder = Decoder::new(&eager.into()).unwrap();
+        assert!(matches!(decoder.decode_raw(), Err(Error::ReadExhausted)));
+
+        // A destination that cannot be written to fails before the first frame. Its directory is
+        // a file, since permissions do not keep the super user from writing to a read-only
+        // directory.
+        let unwritable = lazy.join("out.mkv");
+        assert!(Encoder::new(
+            &unwritable.into(),
+            Settings::for_h264_yuv420p(64, 48, false)
+        )
+        .and_then(|mut encoder| encoder.write_header())
+        .is_err());
+    }
+}
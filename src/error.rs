@@ -1,32 +1,280 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::util::error::{EACCES, ECONNREFUSED, ENOENT, ENOMEM, EPERM, ETIMEDOUT};
+use ffmpeg::util::format::Pixel as AvPixel;
 use ffmpeg::Error as FfmpegError;
 
-/// Represents video I/O Errors. Some errors are generated by the ffmpeg backend, and are wrapped in
-/// `BackendError`.
+/// Represents video I/O Errors.
+///
+/// Errors of the ffmpeg backend that can be acted upon, like [`Error::FileNotFound`] or
+/// [`Error::UnsupportedCodec`], have their own variants. Other backend errors are wrapped in
+/// [`Error::BackendError`]. Code that matches on the backend error codes that now have their own
+/// variants can use [`Error::backend_error`] while moving over to the new variants.
+///
+/// Errors of the backend may be wrapped in [`Error::Context`], which records the operation that
+/// failed. Use [`Error::without_context`] to match on the error itself.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Error {
     ReadExhausted,
+    ReadTimeout,
+    ReadInterrupted,
     WriteRetryLimitReached,
-    InvalidFrameFormat,
+    InvalidFrameFormat {
+        expected: String,
+        received: String,
+    },
     InvalidExtraData,
     MissingCodecParameters,
     UnsupportedCodecParameterSets,
     InvalidResizeParameters,
+    InvalidCropParameters,
+    OutputFormatRequired,
+    InvalidWriterConfiguration(String),
+    InvalidOptions(String),
+    InvalidKeyFrameInterval,
+    ConnectionFailed(FfmpegError),
+    ConnectionLost,
+    ProtocolNotSupported(String),
+    NoOutputs,
+    NoContainer,
+    NoInputs,
+    IncompatibleInputs(String),
+    InvalidPath,
+    InvalidDuration,
+    HeaderAlreadyWritten,
+    ChaptersNotSupported(String),
+    CoverArtNotSupported(String),
+    AttachmentsNotSupported(String),
+    ContainerDoesNotSupportReconfiguration(String),
+    DataStreamsNotSupported(String),
+    InvalidStreamMap(String),
+    WorkerStopped,
+    DeviceNotSupported(String),
+    InputFormatNotFound {
+        name: String,
+        similar: Vec<String>,
+    },
+    InvalidImageSequence(String),
+    MissingSequenceFrame(std::path::PathBuf),
+    InvalidRawVideoConfig(String),
+    IncompleteRawVideoFrame {
+        expected: usize,
+        received: usize,
+    },
+    RateControlNotSupported {
+        encoder: String,
+        mode: String,
+    },
+    QualityMetricNotSupported {
+        encoder: String,
+        metric: String,
+    },
+    ReconfigurationNotSupported {
+        encoder: String,
+        reason: String,
+    },
+    HardwareDecodingNotSupported {
+        codec: String,
+        device: String,
+    },
+    PixelFormatNotSupported {
+        requested: AvPixel,
+        supported: Vec<AvPixel>,
+    },
+    IncompatibleDecoderOptions(String),
+    NotAVideoStream(usize),
+    FinishTimeout,
+    InvalidSubtitleSource(String),
+    SubtitleBurnInNotSupported,
+    InvalidFilterGraph(String),
+    NotSeekable,
+    NotFollowable(String),
+    InvalidTimestamp {
+        frame_index: u64,
+        reason: String,
+    },
+    Cancelled,
+    DecodeFailed(Box<Error>),
+    EncodeFailed(Box<Error>),
+    FileNotFound,
+    PermissionDenied,
+    UnsupportedCodec {
+        name: String,
+    },
+    InvalidData,
+    ProtocolNotFound,
+    OutOfMemory,
     BackendError(FfmpegError),
+    Context {
+        context: ErrorContext,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Get the error without the context of the operation that failed.
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::Context { error, .. } => error.without_context(),
+            err => err,
+        }
+    }
+
+    /// Get the context of the operation that failed, if it is known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Get the operation that failed, like `encoder.send_frame`, if it is known.
+    pub fn operation(&self) -> Option<&'static str> {
+        self.context().map(|context| context.operation)
+    }
+
+    /// Get the index of the stream involved in the operation that failed, if it is known.
+    pub fn stream_index(&self) -> Option<usize> {
+        self.context().and_then(|context| context.stream_index)
+    }
+
+    /// Get the timestamp of the frame or packet involved in the operation that failed, if it is
+    /// known. The timestamp is in the time base of the operation.
+    pub fn pts(&self) -> Option<i64> {
+        self.context().and_then(|context| context.pts)
+    }
+
+    /// Get the error of the ffmpeg backend this error was converted from, if any. For the backend
+    /// errors that have their own variant, this is the backend error they correspond to, so that
+    /// code written for [`Error::BackendError`] keeps working.
+    pub fn backend_error(&self) -> Option<FfmpegError> {
+        match *self.without_context() {
+            Error::FileNotFound => Some(FfmpegError::Other { errno: ENOENT }),
+            Error::PermissionDenied => Some(FfmpegError::Other { errno: EACCES }),
+            Error::InvalidData => Some(FfmpegError::InvalidData),
+            Error::ProtocolNotFound => Some(FfmpegError::ProtocolNotFound),
+            Error::OutOfMemory => Some(FfmpegError::Other { errno: ENOMEM }),
+            Error::ConnectionFailed(internal) | Error::BackendError(internal) => Some(internal),
+            _ => None,
+        }
+    }
+
+    /// Create an error for a frame that does not have the expected format.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - Format the frame is expected to have, like its size or pixel format.
+    /// * `received` - Format the frame has.
+    pub(crate) fn invalid_frame_format(
+        expected: impl std::fmt::Display,
+        received: impl std::fmt::Display,
+    ) -> Error {
+        Error::InvalidFrameFormat {
+            expected: expected.to_string(),
+            received: received.to_string(),
+        }
+    }
+
+    /// Convert a backend error that occurred while opening a codec, so that a missing decoder or
+    /// encoder is reported with the name of the codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `internal` - Backend error.
+    /// * `codec_id` - Codec that was opened.
+    pub(crate) fn from_codec_error(internal: FfmpegError, codec_id: AvCodecId) -> Error {
+        match internal {
+            FfmpegError::DecoderNotFound | FfmpegError::EncoderNotFound => {
+                Error::UnsupportedCodec {
+                    name: codec_id.name().to_string(),
+                }
+            }
+            internal => internal.into(),
+        }
+    }
+
+    /// Report backend errors as connection failures. The context of the error is kept.
+    pub(crate) fn into_connection_error(self) -> Error {
+        match self {
+            Error::Context { context, error } => Error::Context {
+                context,
+                error: Box::new(error.into_connection_error()),
+            },
+            err => match err.backend_error() {
+                Some(internal) => Error::ConnectionFailed(internal),
+                None => err,
+            },
+        }
+    }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
             Error::ReadExhausted => None,
+            Error::ReadTimeout => None,
+            Error::ReadInterrupted => None,
             Error::WriteRetryLimitReached => None,
-            Error::InvalidFrameFormat => None,
+            Error::InvalidFrameFormat { .. } => None,
             Error::InvalidExtraData => None,
             Error::MissingCodecParameters => None,
             Error::UnsupportedCodecParameterSets => None,
             Error::InvalidResizeParameters => None,
+            Error::InvalidCropParameters => None,
+            Error::OutputFormatRequired => None,
+            Error::InvalidWriterConfiguration(_) => None,
+            Error::InvalidOptions(_) => None,
+            Error::InvalidKeyFrameInterval => None,
+            Error::ConnectionFailed(ref internal) => Some(internal),
+            Error::ConnectionLost => None,
+            Error::ProtocolNotSupported(_) => None,
+            Error::NoOutputs => None,
+            Error::NoContainer => None,
+            Error::NoInputs => None,
+            Error::IncompatibleInputs(_) => None,
+            Error::InvalidPath => None,
+            Error::InvalidDuration => None,
+            Error::HeaderAlreadyWritten => None,
+            Error::ChaptersNotSupported(_) => None,
+            Error::CoverArtNotSupported(_) => None,
+            Error::AttachmentsNotSupported(_) => None,
+            Error::ContainerDoesNotSupportReconfiguration(_) => None,
+            Error::DataStreamsNotSupported(_) => None,
+            Error::InvalidStreamMap(_) => None,
+            Error::WorkerStopped => None,
+            Error::DeviceNotSupported(_) => None,
+            Error::InputFormatNotFound { .. } => None,
+            Error::InvalidImageSequence(_) => None,
+            Error::MissingSequenceFrame(_) => None,
+            Error::InvalidRawVideoConfig(_) => None,
+            Error::IncompleteRawVideoFrame { .. } => None,
+            Error::RateControlNotSupported { .. } => None,
+            Error::QualityMetricNotSupported { .. } => None,
+            Error::ReconfigurationNotSupported { .. } => None,
+            Error::HardwareDecodingNotSupported { .. } => None,
+            Error::PixelFormatNotSupported { .. } => None,
+            Error::IncompatibleDecoderOptions(_) => None,
+            Error::NotAVideoStream(_) => None,
+            Error::FinishTimeout => None,
+            Error::InvalidSubtitleSource(_) => None,
+            Error::SubtitleBurnInNotSupported => None,
+            Error::InvalidFilterGraph(_) => None,
+            Error::NotSeekable => None,
+            Error::NotFollowable(_) => None,
+            Error::InvalidTimestamp { .. } => None,
+            Error::Cancelled => None,
+            Error::DecodeFailed(ref internal) => Some(internal.as_ref()),
+            Error::EncodeFailed(ref internal) => Some(internal.as_ref()),
+            Error::FileNotFound => None,
+            Error::PermissionDenied => None,
+            Error::UnsupportedCodec { .. } => None,
+            Error::InvalidData => None,
+            Error::ProtocolNotFound => None,
+            Error::OutOfMemory => None,
             Error::BackendError(ref internal) => Some(internal),
+            Error::Context { ref error, .. } => Some(error.as_ref()),
         }
     }
 }
@@ -35,12 +283,18 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match *self {
             Error::ReadExhausted => write!(f, "stream exhausted"),
+            Error::ReadTimeout => write!(f, "timed out while reading from stream"),
+            Error::ReadInterrupted => write!(f, "reading from stream was interrupted"),
             Error::WriteRetryLimitReached => {
                 write!(f, "cannot write to video stream, even after multiple tries")
             }
-            Error::InvalidFrameFormat => write!(
+            Error::InvalidFrameFormat {
+                ref expected,
+                ref received,
+            } => write!(
                 f,
-                "provided frame does not match expected dimensions and/or pixel format"
+                "provided frame does not match expected dimensions and/or pixel format \
+                 (expected {expected}, received {received})"
             ),
             Error::InvalidExtraData => write!(f, "codec parameters extradata is corrupted"),
             Error::MissingCodecParameters => write!(f, "codec parameters missing"),
@@ -51,13 +305,373 @@ impl std::fmt::Display for Error {
             Error::InvalidResizeParameters => {
                 write!(f, "cannot resize frame into provided dimensions")
             }
+            Error::InvalidCropParameters => {
+                write!(f, "crop region does not fit in frame dimensions")
+            }
+            Error::OutputFormatRequired => write!(
+                f,
+                "output format cannot be inferred and must be specified explicitly (for example \
+                 \"mpegts\" or \"matroska\" for pipes)"
+            ),
+            Error::InvalidWriterConfiguration(ref reason) => {
+                write!(f, "invalid writer configuration: {reason}")
+            }
+            Error::InvalidOptions(ref reason) => write!(f, "invalid options: {reason}"),
+            Error::InvalidKeyFrameInterval => write!(
+                f,
+                "key frame interval must be set explicitly and must not exceed two seconds"
+            ),
+            Error::ConnectionFailed(ref internal) => match *internal {
+                FfmpegError::HttpForbidden | FfmpegError::HttpUnauthorized => write!(
+                    f,
+                    "server rejected the connection (check the stream key or credentials): \
+                     {internal}"
+                ),
+                FfmpegError::InvalidData => {
+                    write!(f, "handshake with server failed: {internal}")
+                }
+                FfmpegError::Other { errno } if errno == ECONNREFUSED => {
+                    write!(f, "connection refused by server")
+                }
+                FfmpegError::Other { errno } if errno == ETIMEDOUT => {
+                    write!(f, "timed out while connecting to server")
+                }
+                _ => write!(f, "failed to connect to server: {internal}"),
+            },
+            Error::ConnectionLost => write!(f, "connection to source was lost"),
+            Error::ProtocolNotSupported(ref protocol) => {
+                write!(f, "protocol not supported by backend: {protocol}")
+            }
+            Error::NoOutputs => write!(f, "at least one output is required"),
+            Error::NoContainer => write!(f, "encoder has no container output"),
+            Error::NoInputs => write!(f, "at least one input is required"),
+            Error::IncompatibleInputs(ref mismatch) => {
+                write!(f, "inputs cannot be joined without re-encoding: {mismatch}")
+            }
+            Error::InvalidPath => write!(f, "path cannot be passed to the backend"),
+            Error::InvalidDuration => write!(
+                f,
+                "time cannot be converted to a duration because it is negative or has no value"
+            ),
+            Error::HeaderAlreadyWritten => {
+                write!(f, "header was already written and can no longer be changed")
+            }
+            Error::ChaptersNotSupported(ref format) => {
+                write!(f, "container format does not support chapters: {format}")
+            }
+            Error::CoverArtNotSupported(ref reason) => {
+                write!(f, "cover art not supported: {reason}")
+            }
+            Error::AttachmentsNotSupported(ref format) => {
+                write!(f, "container format does not support attachments: {format}")
+            }
+            Error::ContainerDoesNotSupportReconfiguration(ref format) => {
+                write!(
+                    f,
+                    "container format does not support changing the encoder size: {format}"
+                )
+            }
+            Error::DataStreamsNotSupported(ref format) => {
+                write!(
+                    f,
+                    "container format does not support data streams: {format}"
+                )
+            }
+            Error::InvalidStreamMap(ref reason) => write!(f, "invalid stream map: {reason}"),
+            Error::WorkerStopped => write!(f, "worker thread stopped"),
+            Error::DeviceNotSupported(ref device) => {
+                write!(f, "capture device not supported: {device}")
+            }
+            Error::InputFormatNotFound {
+                ref name,
+                ref similar,
+            } => {
+                write!(f, "input format not found: {name}")?;
+                if !similar.is_empty() {
+                    write!(f, " (similar formats: {})", similar.join(", "))?;
+                }
+                Ok(())
+            }
+            Error::InvalidImageSequence(ref reason) => {
+                write!(f, "invalid image sequence: {reason}")
+            }
+            Error::MissingSequenceFrame(ref path) => {
+                write!(f, "image sequence frame missing: {}", path.display())
+            }
+            Error::InvalidRawVideoConfig(ref reason) => {
+                write!(f, "invalid raw video configuration: {reason}")
+            }
+            Error::IncompleteRawVideoFrame { expected, received } => write!(
+                f,
+                "incomplete raw video frame: expected {expected} bytes per frame but received \
+                 {received} bytes (input size is not a multiple of the frame size)"
+            ),
+            Error::RateControlNotSupported {
+                ref encoder,
+                ref mode,
+            } => write!(f, "encoder {encoder} does not support {mode} rate control"),
+            Error::QualityMetricNotSupported {
+                ref encoder,
+                ref metric,
+            } => write!(f, "encoder {encoder} cannot report {metric}"),
+            Error::ReconfigurationNotSupported {
+                ref encoder,
+                ref reason,
+            } => write!(
+                f,
+                "encoder {encoder} cannot change its bitrate while encoding: {reason}"
+            ),
+            Error::HardwareDecodingNotSupported {
+                ref codec,
+                ref device,
+            } => write!(
+                f,
+                "decoder {codec} does not support {device} hardware decoding"
+            ),
+            Error::PixelFormatNotSupported {
+                requested,
+                ref supported,
+            } => write!(
+                f,
+                "encoder does not support pixel format {requested:?} (supported: {supported:?})"
+            ),
+            Error::IncompatibleDecoderOptions(ref reason) => {
+                write!(f, "incompatible decoder options: {reason}")
+            }
+            Error::NotAVideoStream(index) => write!(f, "stream {index} is not a video stream"),
+            Error::FinishTimeout => write!(f, "finishing output timed out"),
+            Error::InvalidSubtitleSource(ref reason) => {
+                write!(f, "invalid subtitle source: {reason}")
+            }
+            Error::SubtitleBurnInNotSupported => write!(
+                f,
+                "subtitle burn-in requires the subtitles filter (ffmpeg built with libass)"
+            ),
+            Error::InvalidFilterGraph(ref reason) => write!(f, "invalid filter graph: {reason}"),
+            Error::NotSeekable => write!(f, "source is not seekable"),
+            Error::NotFollowable(ref reason) => {
+                write!(f, "source cannot be followed while it grows: {reason}")
+            }
+            Error::InvalidTimestamp {
+                frame_index,
+                ref reason,
+            } => write!(f, "invalid timestamp of frame {frame_index}: {reason}"),
+            Error::Cancelled => write!(f, "operation cancelled"),
+            Error::DecodeFailed(ref internal) => write!(f, "decoding failed: {internal}"),
+            Error::EncodeFailed(ref internal) => write!(f, "encoding failed: {internal}"),
+            Error::FileNotFound => write!(f, "file not found"),
+            Error::PermissionDenied => write!(f, "permission denied"),
+            Error::UnsupportedCodec { ref name } => {
+                write!(f, "codec not supported by backend: {name}")
+            }
+            Error::InvalidData => write!(f, "invalid data found when processing input"),
+            Error::ProtocolNotFound => write!(f, "protocol not found"),
+            Error::OutOfMemory => write!(f, "out of memory"),
             Error::BackendError(ref internal) => internal.fmt(f),
+            Error::Context {
+                ref context,
+                ref error,
+            } => write!(f, "{context} failed: {error}"),
         }
     }
 }
 
 impl From<FfmpegError> for Error {
     fn from(internal: FfmpegError) -> Error {
-        Error::BackendError(internal)
+        match internal {
+            FfmpegError::Other { errno } if errno == ENOENT => Error::FileNotFound,
+            FfmpegError::Other { errno } if errno == EACCES || errno == EPERM => {
+                Error::PermissionDenied
+            }
+            FfmpegError::Other { errno } if errno == ENOMEM => Error::OutOfMemory,
+            FfmpegError::InvalidData => Error::InvalidData,
+            FfmpegError::ProtocolNotFound => Error::ProtocolNotFound,
+            // Without context, the name of the codec is not known.
+            FfmpegError::DecoderNotFound | FfmpegError::EncoderNotFound => {
+                Error::UnsupportedCodec {
+                    name: "unknown".to_string(),
+                }
+            }
+            internal => Error::BackendError(internal),
+        }
+    }
+}
+
+/// Context of an operation of the backend: the operation itself and, if any, the stream and the
+/// timestamp of the frame or packet involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Operation, like `encoder.send_frame` or `writer.write_header`.
+    pub operation: &'static str,
+    /// Index of the stream involved.
+    pub stream_index: Option<usize>,
+    /// Timestamp of the frame or packet involved.
+    pub pts: Option<i64>,
+}
+
+impl ErrorContext {
+    /// Create the context of an operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Operation, like `encoder.send_frame`.
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            stream_index: None,
+            pts: None,
+        }
+    }
+
+    /// Set the index of the stream involved.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Stream index.
+    pub fn with_stream_index(mut self, stream_index: usize) -> Self {
+        self.stream_index = Some(stream_index);
+        self
+    }
+
+    /// Set the timestamp of the frame or packet involved.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp, if the frame or packet has one.
+    pub fn with_pts(mut self, pts: Option<i64>) -> Self {
+        self.pts = pts;
+        self
+    }
+
+    /// Wrap an error in [`Error::Context`] with this context.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - Error of the operation.
+    pub(crate) fn wrap(self, error: impl Into<Error>) -> Error {
+        Error::Context {
+            context: self,
+            error: Box::new(error.into()),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.operation)?;
+        match (self.stream_index, self.pts) {
+            (Some(stream_index), Some(pts)) => write!(f, " (stream {stream_index}, pts {pts})"),
+            (Some(stream_index), None) => write!(f, " (stream {stream_index})"),
+            (None, Some(pts)) => write!(f, " (pts {pts})"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Attach the context of an operation to errors of the backend.
+pub(crate) trait WithContext<T> {
+    /// Convert the error of the backend, if any, and wrap it in [`Error::Context`].
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Context of the operation.
+    fn context(self, context: ErrorContext) -> Result<T, Error>;
+
+    /// Like [`WithContext::context`], with only the operation as context.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Operation, like `writer.write_header`.
+    fn operation(self, operation: &'static str) -> Result<T, Error>
+    where
+        Self: Sized,
+    {
+        self.context(ErrorContext::new(operation))
+    }
+}
+
+impl<T> WithContext<T> for Result<T, FfmpegError> {
+    fn context(self, context: ErrorContext) -> Result<T, Error> {
+        self.map_err(|internal| context.wrap(internal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_backend_error() {
+        assert!(matches!(
+            Error::from(FfmpegError::Other { errno: ENOENT }),
+            Error::FileNotFound
+        ));
+        assert!(matches!(
+            Error::from(FfmpegError::Other { errno: EPERM }),
+            Error::PermissionDenied
+        ));
+        assert!(matches!(
+            Error::from(FfmpegError::InvalidData),
+            Error::InvalidData
+        ));
+        assert!(matches!(
+            Error::from(FfmpegError::DecoderNotFound),
+            Error::UnsupportedCodec { .. }
+        ));
+        assert!(matches!(
+            Error::from(FfmpegError::Eof),
+            Error::BackendError(FfmpegError::Eof)
+        ));
+    }
+
+    #[test]
+    fn test_backend_error() {
+        assert_eq!(
+            Error::from(FfmpegError::InvalidData).backend_error(),
+            Some(FfmpegError::InvalidData)
+        );
+        assert_eq!(
+            Error::from(FfmpegError::Other { errno: ENOENT }).backend_error(),
+            Some(FfmpegError::Other { errno: ENOENT })
+        );
+        assert_eq!(Error::InvalidPath.backend_error(), None);
+        assert!(matches!(
+            Error::from(FfmpegError::InvalidData).into_connection_error(),
+            Error::ConnectionFailed(FfmpegError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn test_error_context() {
+        let result: Result<(), FfmpegError> = Err(FfmpegError::Other { errno: EPERM });
+        let err = result
+            .context(
+                ErrorContext::new("encoder.send_frame")
+                    .with_stream_index(1)
+                    .with_pts(Some(3000)),
+            )
+            .unwrap_err();
+        assert_eq!(err.operation(), Some("encoder.send_frame"));
+        assert_eq!(err.stream_index(), Some(1));
+        assert_eq!(err.pts(), Some(3000));
+        assert!(matches!(err.without_context(), Error::PermissionDenied));
+        assert_eq!(
+            err.to_string(),
+            "encoder.send_frame (stream 1, pts 3000) failed: permission denied"
+        );
+        assert_eq!(
+            err.backend_error(),
+            Some(FfmpegError::Other { errno: EACCES })
+        );
+        assert!(matches!(
+            err.into_connection_error().without_context(),
+            Error::ConnectionFailed(_)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_frame_format_display() {
+        let err = Error::invalid_frame_format("64x48", "32x24");
+        assert!(err.to_string().contains("expected 64x48, received 32x24"));
     }
 }
@@ -0,0 +1,545 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Space to reserve for the index regardless of the duration, for the headers of the tracks.
+const RESERVED_BASE_BYTES: u64 = 64 * 1024;
+
+/// Number of samples per second to reserve index space for: a video stream at 60 fps and an audio
+/// stream with about 50 frames per second (like AAC at 48 kHz).
+const RESERVED_SAMPLES_PER_SECOND: u64 = 110;
+
+/// Space to reserve per sample. The sample size (4 bytes) and composition offset (8 bytes) tables
+/// grow with every sample, and the chunk tables (up to 20 bytes) with every chunk.
+const RESERVED_BYTES_PER_SAMPLE: u64 = 24;
+
+/// Size of the blocks in which data is moved when rewriting.
+const BLOCK_SIZE: usize = 4 << 20;
+
+/// Boxes that hold the chunk offset tables (`stco` and `co64`), directly or further down.
+const CONTAINER_BOXES: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+/// How the index (`moov` atom) of an MP4 file was placed at the start of the file (see
+/// [`crate::WriterBuilder::faststart_with_reserved_moov`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FaststartPath {
+    /// The index fit in the reserved space, so only the index itself was written again.
+    Reserved,
+    /// The index did not fit in the reserved space, so the media data was moved to make room.
+    Rewritten,
+}
+
+/// Space reserved for the index at the start of an MP4 file.
+pub(crate) struct ReservedMoov {
+    /// Size of the reserved space in bytes.
+    pub(crate) bytes: u32,
+    /// Callback that receives the progress of a rewrite as a percentage from 0 to 100.
+    pub(crate) progress: Option<Box<dyn FnMut(f32) + Send>>,
+}
+
+/// Get the space to reserve for the index of an MP4 file.
+///
+/// # Arguments
+///
+/// * `estimated_duration` - Expected duration of the file.
+pub(crate) fn reserved_moov_size(estimated_duration: Duration) -> u32 {
+    let bytes = estimated_duration
+        .as_secs()
+        .saturating_add(1)
+        .saturating_mul(RESERVED_SAMPLES_PER_SECOND * RESERVED_BYTES_PER_SAMPLE)
+        .saturating_add(RESERVED_BASE_BYTES);
+    // The muxer takes the size as a signed 32-bit integer.
+    bytes.min(i32::MAX as u64) as u32
+}
+
+/// Move the index of an MP4 file, which the muxer wrote at the end, into the space that was
+/// reserved for it at the start. If it does not fit, the media data is moved back to make room,
+/// and the chunk offsets in the index are updated to match.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file.
+/// * `reserved` - Size of the reserved space in bytes. The reserved space is a hole of zeros.
+/// * `progress` - Callback that receives the progress of a rewrite as a percentage.
+///
+/// # Return value
+///
+/// Whether the index fit in the reserved space or the file was rewritten.
+pub(crate) fn place_moov(
+    path: &Path,
+    reserved: u64,
+    mut progress: Option<&mut (dyn FnMut(f32) + Send)>,
+) -> Result<FaststartPath> {
+    let io_error = io_error(path);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(io_error)?;
+    let len = file.metadata().map_err(io_error)?.len();
+    let layout = Layout::of(&mut file, len, reserved).map_err(|err| match err {
+        LayoutError::Io(err) => io_error(err),
+        LayoutError::Invalid => Error::InvalidData,
+    })?;
+    let mut moov = vec![0; layout.moov_size as usize];
+    read_at(&mut file, layout.moov_position, &mut moov).map_err(io_error)?;
+
+    let free_space = reserved as i64 - moov.len() as i64;
+    if free_space == 0 || free_space >= 8 {
+        write_at(&mut file, layout.gap_position, &moov).map_err(io_error)?;
+        if free_space > 0 {
+            let mut free = (free_space as u32).to_be_bytes().to_vec();
+            free.extend_from_slice(b"free");
+            write_at(&mut file, layout.gap_position + moov.len() as u64, &free)
+                .map_err(io_error)?;
+        }
+        file.set_len(layout.moov_position).map_err(io_error)?;
+        return Ok(FaststartPath::Reserved);
+    }
+
+    // Use 64-bit chunk offsets if the moved offsets no longer fit in 32 bits. This makes the index
+    // larger, which moves the data further.
+    let delta = moov.len() as i64 - reserved as i64;
+    let moov = match shift_chunk_offsets(&moov, delta, false)? {
+        Some(moov) => moov,
+        None => {
+            let size = shift_chunk_offsets(&moov, 0, true)?.map_or(0, |moov| moov.len());
+            shift_chunk_offsets(&moov, size as i64 - reserved as i64, true)?
+                .ok_or(Error::InvalidData)?
+        }
+    };
+    let delta = moov.len() as i64 - reserved as i64;
+    let data_position = layout.gap_position + reserved;
+    let data_size = layout.moov_position - data_position;
+    move_data(&mut file, data_position, data_size, delta, &mut progress).map_err(io_error)?;
+    write_at(&mut file, layout.gap_position, &moov).map_err(io_error)?;
+    file.set_len(layout.gap_position + moov.len() as u64 + data_size)
+        .map_err(io_error)?;
+    if let Some(progress) = progress {
+        progress(100.0);
+    }
+
+    Ok(FaststartPath::Rewritten)
+}
+
+/// Get a function that turns I/O errors on a file into errors.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file.
+fn io_error(path: &Path) -> impl Fn(std::io::Error) -> Error + Copy + '_ {
+    move |err| match err.kind() {
+        std::io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+        _ => Error::InvalidWriterConfiguration(format!("cannot rewrite {}: {err}", path.display())),
+    }
+}
+
+/// Error while finding the reserved space and the index in a file.
+enum LayoutError {
+    Io(std::io::Error),
+    /// The file does not look like a file written with reserved space.
+    Invalid,
+}
+
+impl From<std::io::Error> for LayoutError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Positions of the reserved space and the index in an MP4 file.
+#[derive(Debug, PartialEq)]
+struct Layout {
+    gap_position: u64,
+    moov_position: u64,
+    moov_size: u64,
+}
+
+impl Layout {
+    /// Find the reserved space and the index in an MP4 file by walking the top-level boxes. The
+    /// reserved space is where a box header of zeros is found, and the index must be the last box.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - File to walk.
+    /// * `len` - Length of the file.
+    /// * `reserved` - Size of the reserved space.
+    fn of(
+        file: &mut (impl Read + Seek),
+        len: u64,
+        reserved: u64,
+    ) -> std::result::Result<Self, LayoutError> {
+        let mut position = 0;
+        let mut gap_position = None;
+        let mut moov = None;
+        while position < len {
+            if len - position < 8 {
+                return Err(LayoutError::Invalid);
+            }
+            let mut header = [0; 16];
+            let header_len = (len - position).min(16) as usize;
+            read_at(file, position, &mut header[..header_len])?;
+            if header[..8] == [0; 8] && gap_position.is_none() {
+                gap_position = Some(position);
+                position += reserved;
+                continue;
+            }
+
+            let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+                0 => len - position,
+                1 if header_len == 16 => u64::from_be_bytes(header[8..].try_into().unwrap()),
+                1 => return Err(LayoutError::Invalid),
+                size => size as u64,
+            };
+            if size < 8 || size > len - position {
+                return Err(LayoutError::Invalid);
+            }
+            if &header[4..8] == b"moov" {
+                moov = Some((position, size));
+            }
+            position += size;
+        }
+
+        match (gap_position, moov) {
+            (Some(gap_position), Some((moov_position, moov_size)))
+                if moov_position >= gap_position + reserved && moov_position + moov_size == len =>
+            {
+                Ok(Self {
+                    gap_position,
+                    moov_position,
+                    moov_size,
+                })
+            }
+            _ => Err(LayoutError::Invalid),
+        }
+    }
+}
+
+/// Add an offset to the chunk offsets in an index.
+///
+/// # Arguments
+///
+/// * `moov` - Index (the `moov` box).
+/// * `delta` - Offset to add.
+/// * `co64` - Whether to turn 32-bit chunk offset tables (`stco`) into 64-bit ones (`co64`).
+///
+/// # Return value
+///
+/// The updated index, or `None` if a 32-bit chunk offset no longer fits.
+fn shift_chunk_offsets(moov: &[u8], delta: i64, co64: bool) -> Result<Option<Vec<u8>>> {
+    let mut out = Vec::with_capacity(moov.len());
+    Ok(shift_boxes(moov, delta, co64, &mut out)?.then_some(out))
+}
+
+/// Add an offset to the chunk offsets in a list of boxes.
+///
+/// # Arguments
+///
+/// * `data` - Boxes.
+/// * `delta` - Offset to add.
+/// * `co64` - Whether to turn 32-bit chunk offset tables into 64-bit ones.
+/// * `out` - Where to write the updated boxes.
+///
+/// # Return value
+///
+/// `false` if a 32-bit chunk offset no longer fits.
+fn shift_boxes(data: &[u8], delta: i64, co64: bool, out: &mut Vec<u8>) -> Result<bool> {
+    let mut position = 0;
+    while position < data.len() {
+        let rest = &data[position..];
+        if rest.len() < 8 {
+            return Err(Error::InvalidData);
+        }
+        let (header_len, size) = match u32::from_be_bytes(rest[..4].try_into().unwrap()) {
+            0 => (8, rest.len()),
+            1 if rest.len() >= 16 => (
+                16,
+                u64::from_be_bytes(rest[8..16].try_into().unwrap()) as usize,
+            ),
+            1 => return Err(Error::InvalidData),
+            size => (8, size as usize),
+        };
+        if size < header_len || size > rest.len() {
+            return Err(Error::InvalidData);
+        }
+        let kind: &[u8; 4] = rest[4..8].try_into().unwrap();
+        let body = &rest[header_len..size];
+
+        if CONTAINER_BOXES.contains(&kind) {
+            let mut children = Vec::with_capacity(body.len());
+            if !shift_boxes(body, delta, co64, &mut children)? {
+                return Ok(false);
+            }
+            write_box(out, kind, &children);
+        } else if kind == b"stco" || kind == b"co64" {
+            let entry_size = if kind == b"stco" { 4 } else { 8 };
+            if body.len() < 8 {
+                return Err(Error::InvalidData);
+            }
+            let count = u32::from_be_bytes(body[4..8].try_into().unwrap()) as usize;
+            let entries = body[8..]
+                .get(..count * entry_size)
+                .ok_or(Error::InvalidData)?;
+
+            let wide = kind == b"co64" || co64;
+            let mut table = body[..8].to_vec();
+            for entry in entries.chunks_exact(entry_size) {
+                let offset = if entry_size == 4 {
+                    u32::from_be_bytes(entry.try_into().unwrap()) as i64
+                } else {
+                    u64::from_be_bytes(entry.try_into().unwrap()) as i64
+                };
+                let offset = offset.checked_add(delta).ok_or(Error::InvalidData)?;
+                if wide {
+                    table.extend_from_slice(&(offset as u64).to_be_bytes());
+                } else {
+                    match u32::try_from(offset) {
+                        Ok(offset) => table.extend_from_slice(&offset.to_be_bytes()),
+                        Err(_) => return Ok(false),
+                    }
+                }
+            }
+            write_box(out, if wide { b"co64" } else { b"stco" }, &table);
+        } else {
+            out.extend_from_slice(&rest[..size]);
+        }
+
+        position += size;
+    }
+
+    Ok(true)
+}
+
+/// Write a box.
+///
+/// # Arguments
+///
+/// * `out` - Where to write the box.
+/// * `kind` - Type of the box.
+/// * `body` - Contents of the box.
+fn write_box(out: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    match u32::try_from(body.len() + 8) {
+        Ok(size) => {
+            out.extend_from_slice(&size.to_be_bytes());
+            out.extend_from_slice(kind);
+        }
+        Err(_) => {
+            out.extend_from_slice(&1u32.to_be_bytes());
+            out.extend_from_slice(kind);
+            out.extend_from_slice(&(body.len() as u64 + 16).to_be_bytes());
+        }
+    }
+    out.extend_from_slice(body);
+}
+
+/// Move a range of a file by an offset, in blocks, starting at the end that does not overlap the
+/// destination.
+///
+/// # Arguments
+///
+/// * `file` - File to move data in.
+/// * `position` - Start of the range.
+/// * `size` - Size of the range.
+/// * `delta` - Offset to move the range by.
+/// * `progress` - Callback that receives the progress as a percentage.
+fn move_data(
+    file: &mut File,
+    position: u64,
+    size: u64,
+    delta: i64,
+    progress: &mut Option<&mut (dyn FnMut(f32) + Send)>,
+) -> std::io::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+
+    let mut buffer = vec![0; BLOCK_SIZE.min(size as usize)];
+    let mut moved = 0;
+    while moved < size {
+        let block = (size - moved).min(BLOCK_SIZE as u64);
+        let from = if delta > 0 {
+            position + size - moved - block
+        } else {
+            position + moved
+        };
+        let buffer = &mut buffer[..block as usize];
+        read_at(file, from, buffer)?;
+        write_at(file, from.wrapping_add_signed(delta), buffer)?;
+        moved += block;
+        if let Some(progress) = progress.as_mut() {
+            progress((moved as f64 / size as f64 * 100.0) as f32);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read bytes from a position in a file.
+fn read_at(file: &mut (impl Read + Seek), position: u64, buffer: &mut [u8]) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(buffer)
+}
+
+/// Write bytes at a position in a file.
+fn write_at(file: &mut File, position: u64, buffer: &[u8]) -> std::io::Result<()> {
+    file.seek(SeekFrom::Start(position))?;
+    file.write_all(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempPath;
+
+    /// Build a box.
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_box(&mut out, kind, body);
+        out
+    }
+
+    /// Build an index with one track with the given 32-bit chunk offsets.
+    fn moov(offsets: &[u32]) -> Vec<u8> {
+        let mut stco = vec![0, 0, 0, 0];
+        stco.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+        for offset in offsets {
+            stco.extend_from_slice(&offset.to_be_bytes());
+        }
+        let stbl = [mp4_box(b"stsz", &[0; 12]), mp4_box(b"stco", &stco)].concat();
+        let minf = mp4_box(b"stbl", &stbl);
+        let mdia = [mp4_box(b"mdhd", &[0; 24]), mp4_box(b"minf", &minf)].concat();
+        let trak = mp4_box(b"mdia", &mdia);
+        mp4_box(
+            b"moov",
+            &[mp4_box(b"mvhd", &[0; 100]), mp4_box(b"trak", &trak)].concat(),
+        )
+    }
+
+    /// Write a file like the muxer does with reserved space: the file type, the reserved space,
+    /// the media data and then the index.
+    fn write_file(path: &Path, reserved: usize, samples: &[&[u8]]) -> Vec<u8> {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\x02\0isom");
+        let mdat_position = ftyp.len() + reserved + 8;
+        let mut offsets = Vec::new();
+        let mut mdat = Vec::new();
+        for sample in samples {
+            offsets.push((mdat_position + 8 + mdat.len()) as u32);
+            mdat.extend_from_slice(sample);
+        }
+        let moov = moov(&offsets);
+        let file = [
+            ftyp,
+            vec![0; reserved],
+            mp4_box(b"free", &[]),
+            mp4_box(b"mdat", &mdat),
+            moov.clone(),
+        ]
+        .concat();
+        std::fs::write(path, file).unwrap();
+        moov
+    }
+
+    /// Get the top-level boxes of a file, and the data at each chunk offset.
+    fn parse(path: &Path) -> (Vec<[u8; 4]>, Vec<u8>) {
+        let bytes = std::fs::read(path).unwrap();
+        let mut kinds = Vec::new();
+        let mut position = 0;
+        let mut samples = Vec::new();
+        while position < bytes.len() {
+            let size = u32::from_be_bytes(bytes[position..position + 4].try_into().unwrap());
+            let kind: [u8; 4] = bytes[position + 4..position + 8].try_into().unwrap();
+            if &kind == b"moov" {
+                let moov = &bytes[position..position + size as usize];
+                let stco = moov.windows(4).position(|kind| kind == b"stco").unwrap();
+                let count = u32::from_be_bytes(moov[stco + 8..stco + 12].try_into().unwrap());
+                for i in 0..count as usize {
+                    let entry = stco + 12 + i * 4;
+                    let offset = u32::from_be_bytes(moov[entry..entry + 4].try_into().unwrap());
+                    samples.push(bytes[offset as usize]);
+                }
+            }
+            kinds.push(kind);
+            position += size as usize;
+        }
+        (kinds, samples)
+    }
+
+    #[test]
+    fn test_place_moov() {
+        let path = TempPath::new("faststart.mp4");
+        let samples: &[&[u8]] = &[b"a", b"bb", b"ccc"];
+
+        // The index fits: it takes the place of the reserved space.
+        let moov = write_file(&path, 1024, samples);
+        assert_eq!(
+            place_moov(&path, 1024, None).unwrap(),
+            FaststartPath::Reserved
+        );
+        let (kinds, data) = parse(&path);
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"free", *b"free", *b"mdat"]);
+        assert_eq!(data, b"abc");
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len() as usize,
+            20 + 1024 + 8 + 8 + 6
+        );
+
+        // The index does not fit: the media data moves.
+        let reserved = moov.len() - 20;
+        write_file(&path, reserved, samples);
+        let mut percentages = Vec::new();
+        let mut progress = |percentage| percentages.push(percentage);
+        assert_eq!(
+            place_moov(&path, reserved as u64, Some(&mut progress)).unwrap(),
+            FaststartPath::Rewritten
+        );
+        assert_eq!(percentages.last(), Some(&100.0));
+        let (kinds, data) = parse(&path);
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"free", *b"mdat"]);
+        assert_eq!(data, b"abc");
+
+        // Files that were not written with reserved space are left alone.
+        std::fs::write(&path, [mp4_box(b"ftyp", b"isom"), moov].concat()).unwrap();
+        assert!(matches!(
+            place_moov(&path, 1024, None),
+            Err(Error::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn test_shift_chunk_offsets() {
+        let moov = moov(&[100, u32::MAX - 10]);
+        let shifted = shift_chunk_offsets(&moov, 5, false).unwrap().unwrap();
+        assert_eq!(shifted.len(), moov.len());
+        assert!(shifted.ends_with(&[0, 0, 0, 105, 255, 255, 255, 250]));
+
+        // Offsets that no longer fit need 64-bit chunk offsets.
+        assert_eq!(shift_chunk_offsets(&moov, 20, false).unwrap(), None);
+        let shifted = shift_chunk_offsets(&moov, 20, true).unwrap().unwrap();
+        assert_eq!(shifted.len(), moov.len() + 8);
+        assert!(shifted.windows(4).any(|kind| kind == b"co64"));
+        assert!(shifted.ends_with(&(u32::MAX as u64 + 10).to_be_bytes()));
+        assert_eq!(
+            u32::from_be_bytes(shifted[..4].try_into().unwrap()) as usize,
+            shifted.len()
+        );
+    }
+
+    #[test]
+    fn test_reserved_moov_size() {
+        assert_eq!(
+            reserved_moov_size(Duration::from_secs(3599)),
+            64 * 1024 + 3600 * 110 * 24
+        );
+        assert_eq!(
+            reserved_moov_size(Duration::from_secs(u64::MAX)),
+            i32::MAX as u32
+        );
+    }
+}
@@ -5,12 +5,19 @@ use ndarray::Array3;
 
 use ffmpeg::codec::codec::Codec;
 use ffmpeg::codec::context::Context;
+use ffmpeg::codec::packet::{Mut as _, Packet};
+use ffmpeg::codec::Parameters;
 use ffmpeg::encoder::video::Video;
-use ffmpeg::format::context::Output;
+use ffmpeg::format::context::{Input, Output};
+use ffmpeg::software::resampling::context::Context as ResamplingContext;
+use ffmpeg::software::scaling::context::Context as ScalingContext;
+use ffmpeg::util::frame::audio::Audio as AudioFrame;
+use ffmpeg::util::frame::side_data::Type as SideDataType;
 use ffmpeg::util::frame::video::Video as Frame;
-use ffmpeg::{Error, Rational};
+use ffmpeg::{Dictionary, Error, Rational};
+
+use crate::interrupt::Interrupt;
 
-#[cfg(feature = "ndarray")]
 use ffmpeg::util::format::Pixel;
 
 use ffmpeg::ffi::*;
@@ -31,7 +38,7 @@ use ffmpeg::ffi::*;
 /// ```ignore
 /// let output = ffi::output_raw("mp4");
 ///
-/// output_raw_buf_start(&mut output);
+/// output_raw_buf_start(&mut output)?;
 /// output.write_header()?;
 /// let buf output_raw_buf_end(&mut output);
 /// println!("{}", buf.len());
@@ -39,7 +46,7 @@ use ffmpeg::ffi::*;
 pub fn output_raw(format: &str) -> Result<Output, Error> {
     unsafe {
         let mut output_ptr = std::ptr::null_mut();
-        let format = std::ffi::CString::new(format).unwrap();
+        let format = std::ffi::CString::new(format).map_err(|_| Error::MuxerNotFound)?;
         match avformat_alloc_output_context2(
             &mut output_ptr,
             std::ptr::null_mut(),
@@ -52,6 +59,73 @@ pub fn output_raw(format: &str) -> Result<Output, Error> {
     }
 }
 
+/// This function is similar to `output_as_with` in ffmpeg-next, but it takes the path as a C string
+/// so that paths that are not valid UTF-8 can be opened, and the format is optional (if it is not
+/// given, it is guessed from the path). It also installs an interrupt callback on the output
+/// context, which the protocol consults while it is blocked on I/O.
+///
+/// The caller must make sure that `interrupt` outlives the returned output context, since the
+/// callback holds a pointer to it.
+///
+/// # Arguments
+///
+/// * `path` - Path or URL to open, in the encoding the backend expects.
+/// * `format` - String to indicate the container format, like "mp4".
+/// * `options` - Options to pass to the protocol.
+/// * `interrupt` - Interrupt state to consult from the callback.
+///
+/// # Return value
+///
+/// The output context and the options that the protocol did not consume.
+pub fn output_with_dictionary_and_interrupt(
+    path: &std::ffi::CStr,
+    format: Option<&str>,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Output, Dictionary<'static>), Error> {
+    let format = format
+        .map(std::ffi::CString::new)
+        .transpose()
+        .map_err(|_| Error::InvalidData)?;
+
+    unsafe {
+        let mut output_ptr = std::ptr::null_mut();
+        match avformat_alloc_output_context2(
+            &mut output_ptr,
+            std::ptr::null_mut(),
+            format
+                .as_ref()
+                .map_or(std::ptr::null(), |format| format.as_ptr()),
+            path.as_ptr(),
+        ) {
+            0 => {
+                (*output_ptr).interrupt_callback = AVIOInterruptCB {
+                    callback: Some(interrupt_callback),
+                    opaque: interrupt as *const Interrupt as *mut std::ffi::c_void,
+                };
+
+                let mut opts = options.disown();
+                let ret = avio_open2(
+                    &mut (*output_ptr).pb,
+                    path.as_ptr(),
+                    AVIO_FLAG_WRITE,
+                    &(*output_ptr).interrupt_callback,
+                    &mut opts,
+                );
+                let unconsumed = Dictionary::own(opts);
+
+                // Wrap the context first so that it is freed on failure.
+                let output = Output::wrap(output_ptr);
+                match ret {
+                    0 => Ok((output, unconsumed)),
+                    e => Err(Error::from(e)),
+                }
+            }
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
 /// This function initializes a dynamic buffer and inserts it into an output context to allow a
 /// write to happen. Afterwards, the callee can use `output_raw_buf_end` to retrieve what was
 /// written.
@@ -59,7 +133,7 @@ pub fn output_raw(format: &str) -> Result<Output, Error> {
 /// # Arguments
 ///
 /// * `output` - Output context to start write on.
-pub fn output_raw_buf_start(output: &mut Output) {
+pub fn output_raw_buf_start(output: &mut Output) -> Result<(), Error> {
     unsafe {
         // Here we initialize a raw pointer (mutable) as nullptr initially. We then call the
         // `avio_open_dyn_buf` which expects a ptr ptr, and place the result in p. In case of
@@ -68,10 +142,9 @@ pub fn output_raw_buf_start(output: &mut Output) {
         match avio_open_dyn_buf((&mut p) as *mut *mut AVIOContext) {
             0 => {
                 (*output.as_mut_ptr()).pb = p;
+                Ok(())
             }
-            _ => {
-                panic!("Failed to open dynamic buffer for output context.");
-            }
+            e => Err(Error::from(e)),
         }
     }
 }
@@ -89,11 +162,17 @@ pub fn output_raw_buf_end(output: &mut Output) -> Vec<u8> {
         // `close_dyn_buf` function will place a pointer to the starting address of the buffer in
         // `buffer_raw` through a ptr ptr. It also returns the size of that buffer.
         let output_pb = (*output.as_mut_ptr()).pb;
+        if output_pb.is_null() {
+            return Vec::new();
+        }
         let mut buffer_raw: *mut u8 = std::ptr::null_mut();
         let buffer_size = avio_close_dyn_buf(output_pb, (&mut buffer_raw) as *mut *mut u8) as usize;
 
         // Reset the `pb` field or `avformat_close` will try to free it!
         ((*output.as_mut_ptr()).pb) = std::ptr::null_mut::<AVIOContext>();
+        if buffer_raw.is_null() {
+            return Vec::new();
+        }
 
         // Create a Rust `Vec` from the buffer (copying).
         let buffer = std::slice::from_raw_parts(buffer_raw, buffer_size).to_vec();
@@ -125,14 +204,21 @@ pub fn output_raw_packetized_buf_start(
     output: &mut Output,
     packet_buffer: &mut Vec<Vec<u8>>,
     max_packet_size: usize,
-) {
+) -> Result<(), Error> {
+    let max_packet_size_c: std::ffi::c_int = max_packet_size
+        .try_into()
+        .map_err(|_| Error::from(AVERROR(ffmpeg::util::error::EINVAL)))?;
+
     unsafe {
         let buffer = av_malloc(max_packet_size) as *mut u8;
+        if buffer.is_null() {
+            return Err(Error::from(AVERROR(ffmpeg::util::error::ENOMEM)));
+        }
 
         // Create a custom IO context around our buffer.
         let io: *mut AVIOContext = avio_alloc_context(
             buffer,
-            max_packet_size.try_into().unwrap(),
+            max_packet_size_c,
             // Set stream to WRITE.
             1,
             // Pass on a pointer *UNSAFE* to the packet buffer, assuming the packet buffer will live
@@ -145,14 +231,20 @@ pub fn output_raw_packetized_buf_start(
             // No `seek`.
             None,
         );
+        if io.is_null() {
+            av_free(buffer as *mut std::ffi::c_void);
+            return Err(Error::from(AVERROR(ffmpeg::util::error::ENOMEM)));
+        }
 
         // Setting `max_packet_size` will let the underlying IO stream know that this buffer must be
         // treated as packetized.
-        (*io).max_packet_size = max_packet_size.try_into().unwrap();
+        (*io).max_packet_size = max_packet_size_c;
 
         // Assign IO to output context.
         (*output.as_mut_ptr()).pb = io;
     }
+
+    Ok(())
 }
 
 /// This function cleans up the IO context used for packetized writing created by
@@ -164,6 +256,9 @@ pub fn output_raw_packetized_buf_start(
 pub fn output_raw_packetized_buf_end(output: &mut Output) {
     unsafe {
         let output_pb = (*output.as_mut_ptr()).pb;
+        if output_pb.is_null() {
+            return;
+        }
 
         // One last flush (might incur write, most likely won't).
         avio_flush(output_pb);
@@ -199,268 +294,2475 @@ pub fn flush_output(output: &mut Output) -> Result<(), Error> {
     }
 }
 
-/// Initialize a new codec context using a specific codec.
+/// Write the packets that are still queued for interleaving, so that none are left behind before
+/// the trailer is written.
 ///
 /// # Arguments
 ///
-/// * `codec` - Codec to initialize with.
-pub fn codec_context_as(codec: &Codec) -> Result<Context, Error> {
+/// * `output` - Output context to flush.
+pub fn flush_interleaved(output: &mut Output) -> Result<(), Error> {
     unsafe {
-        let context_ptr = ffmpeg::ffi::avcodec_alloc_context3(codec.as_ptr());
-        if !context_ptr.is_null() {
-            Ok(Context::wrap(context_ptr, None))
-        } else {
-            Err(Error::Unknown)
+        match av_interleaved_write_frame(output.as_mut_ptr(), std::ptr::null_mut()) {
+            e if e < 0 => Err(Error::from(e)),
+            _ => Ok(()),
         }
     }
 }
 
-/// Set the `time_base` field of a decoder. (Not natively supported in the public API.)
+/// Flush the I/O context of the output, so that any buffered data is written to the underlying
+/// file or network protocol. Unlike `flush_output`, this does not affect the muxer.
 ///
 /// # Arguments
 ///
-/// * `decoder_context` - Decoder context.
-/// * `time_base` - Time base to assign.
-pub fn set_decoder_context_time_base(decoder_context: &mut Context, time_base: Rational) {
+/// * `output` - Output context to flush the I/O context of.
+pub fn flush_output_io(output: &mut Output) -> Result<(), Error> {
     unsafe {
-        (*decoder_context.as_mut_ptr()).time_base = time_base.into();
+        let pb = (*output.as_mut_ptr()).pb;
+        if pb.is_null() {
+            return Ok(());
+        }
+
+        avio_flush(pb);
+        match (*pb).error {
+            e if e < 0 => Err(Error::from(e)),
+            _ => Ok(()),
+        }
     }
 }
 
-/// Get the `time_base` field of an encoder. (Not natively supported in the public API.)
+/// This function is similar to `input_with_dictionary` in ffmpeg-next, but it also installs an
+/// interrupt callback on the input context before opening it. The backend invokes the callback
+/// while it is blocked on I/O and aborts the operation with `Error::Exit` when it returns `true`.
+///
+/// The caller must make sure that `interrupt` outlives the returned input context, since the
+/// context holds on to a raw pointer to it.
 ///
 /// # Arguments
 ///
-/// * `encoder` - Encoder to get `time_base` of.
-pub fn get_encoder_time_base(encoder: &Video) -> Rational {
-    unsafe { (*encoder.0.as_ptr()).time_base.into() }
+/// * `path` - Path or URL to open, in the encoding the backend expects.
+/// * `format` - Name of the demuxer to use (like `v4l2` for capture devices), or `None` to probe
+///   the input.
+/// * `options` - Options to pass to the demuxer.
+/// * `interrupt` - Interrupt state to consult from the callback.
+///
+/// # Return value
+///
+/// The input context and the options that neither the protocol nor the demuxer consumed.
+pub fn input_with_dictionary_and_interrupt(
+    path: &std::ffi::CStr,
+    format: Option<&str>,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Input, Dictionary<'static>), Error> {
+    let format = match format {
+        Some(format) => find_input_format(format)?,
+        None => std::ptr::null(),
+    };
+
+    unsafe { open_input(path, std::ptr::null_mut(), format, options, interrupt) }
 }
 
-/// Copy frame properties from `src` to `dst`.
+/// Find a demuxer by name.
 ///
 /// # Arguments
 ///
-/// * `src` - Frame to get properties from.
-/// * `dst` - Frame to copy properties to.
-pub fn copy_frame_props(src: &Frame, dst: &mut Frame) {
-    unsafe {
-        av_frame_copy_props(dst.as_mut_ptr(), src.as_ptr());
+/// * `name` - Name of the demuxer, like `v4l2`.
+fn find_input_format(name: &str) -> Result<*const AVInputFormat, Error> {
+    let name = std::ffi::CString::new(name).map_err(|_| Error::DemuxerNotFound)?;
+    let format = unsafe { av_find_input_format(name.as_ptr()) };
+    if format.is_null() {
+        Err(Error::DemuxerNotFound)
+    } else {
+        Ok(format)
     }
 }
 
-/// A frame array is the `ndarray` version of `AVFrame`. It is 3-dimensional array with dims `(H, W,
-/// C)` and type byte.
-#[cfg(feature = "ndarray")]
-pub type FrameArray = Array3<u8>;
-
-/// Converts an `ndarray` to an RGB24 video `AVFrame` for ffmpeg.
+/// List the sources (like cameras) that a device demuxer can capture from.
 ///
 /// # Arguments
 ///
-/// * `frame_array` - Video frame to convert. The frame format must be `(H, W, C)`.
+/// * `format` - Name of the device demuxer, like `v4l2`.
 ///
 /// # Return value
 ///
-/// An ffmpeg-native `AvFrame`.
-#[cfg(feature = "ndarray")]
-pub fn convert_ndarray_to_frame(frame_array: &FrameArray, pixel_format: AVPixelFormat) -> Result<Frame, Error> {
-    unsafe {
-        assert!(frame_array.is_standard_layout());
-
-        let (frame_height, frame_width, _) = frame_array.dim();
-
-        // Temporary frame structure to place correctly formatted data and linesize stuff in, which
-        // we'll copy later.
-        let mut frame_tmp = Frame::empty();
-        let frame_tmp_ptr = frame_tmp.as_mut_ptr();
-
-        // This does not copy the data, but it sets the `frame_tmp` data and linesize pointers
-        // correctly.
-        let bytes_copied = av_image_fill_arrays(
-            (*frame_tmp_ptr).data.as_ptr() as *mut *mut u8,
-            (*frame_tmp_ptr).linesize.as_ptr() as *mut i32,
-            frame_array.as_ptr(),
-            pixel_format,
-            frame_width as i32,
-            frame_height as i32,
-            1,
-        );
+/// The name and description of each source. Fails with `ENOSYS` if the demuxer does not support
+/// listing its sources.
+pub fn list_input_sources(format: &str) -> Result<Vec<(String, String)>, Error> {
+    let format = find_input_format(format)?;
 
-        if bytes_copied != frame_array.len() as i32 {
-            return Err(Error::from(bytes_copied));
+    unsafe {
+        let mut list = std::ptr::null_mut();
+        let ret =
+            avdevice_list_input_sources(format, std::ptr::null(), std::ptr::null_mut(), &mut list);
+        if ret < 0 {
+            avdevice_free_list_devices(&mut list);
+            return Err(Error::from(ret));
         }
 
-        let mut frame = Frame::new(Pixel::from(pixel_format), frame_width as u32, frame_height as u32);
-        let frame_ptr = frame.as_mut_ptr();
+        let sources = (0..(*list).nb_devices as usize)
+            .map(|index| {
+                let device = *(*list).devices.add(index);
+                (
+                    cstr_to_string((*device).device_name),
+                    cstr_to_string((*device).device_description),
+                )
+            })
+            .collect();
+        avdevice_free_list_devices(&mut list);
 
-        // Do the actual copying.
-        av_image_copy(
-            (*frame_ptr).data.as_ptr() as *mut *mut u8,
-            (*frame_ptr).linesize.as_ptr() as *mut i32,
-            (*frame_tmp_ptr).data.as_ptr() as *mut *const u8,
-            (*frame_tmp_ptr).linesize.as_ptr(),
-            pixel_format,
-            frame_width as i32,
-            frame_height as i32,
-        );
+        Ok(sources)
+    }
+}
 
-        Ok(frame)
+/// This function is similar to `input_with_dictionary_and_interrupt`, but it reads from a custom
+/// I/O context instead of opening a path or URL.
+///
+/// The caller must make sure that both `io` and `interrupt` outlive the returned input context,
+/// since the context holds on to raw pointers to them.
+///
+/// # Arguments
+///
+/// * `io` - Custom I/O context to read from.
+/// * `options` - Options to pass to the demuxer.
+/// * `interrupt` - Interrupt state to consult from the callback.
+pub fn input_from_io_with_dictionary_and_interrupt(
+    io: &mut Io,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Input, Dictionary<'static>), Error> {
+    unsafe {
+        open_input(
+            &std::ffi::CString::default(),
+            io.as_mut_ptr(),
+            std::ptr::null(),
+            options,
+            interrupt,
+        )
     }
 }
 
-/// Converts an RGB24 video `AVFrame` produced by ffmpeg to an `ndarray`.
+/// Open an input context with an interrupt callback and optionally a custom I/O context.
 ///
 /// # Arguments
 ///
-/// * `frame` - Video frame to convert.
+/// * `path` - Path or URL to open. Ignored by the backend if `pb` is not null.
+/// * `pb` - Custom I/O context or null.
+/// * `format` - Demuxer to use, or null to probe the input.
+/// * `options` - Options to pass to the demuxer.
+/// * `interrupt` - Interrupt state to consult from the callback.
 ///
 /// # Return value
 ///
-/// A three-dimensional `ndarray` with dimensions `(H, W, C)` and type byte.
-#[cfg(feature = "ndarray")]
-pub fn convert_frame_to_ndarray(frame: &mut Frame) -> Result<FrameArray, Error> {
-    unsafe {
-        let frame_ptr = frame.as_mut_ptr();
-        let frame_width: i32 = (*frame_ptr).width;
-        let frame_height: i32 = (*frame_ptr).height;
-        let frame_format =
-            std::mem::transmute::<std::ffi::c_int, AVPixelFormat>((*frame_ptr).format);
-        assert!(
-            frame_format == AVPixelFormat::AV_PIX_FMT_RGB24
-            || frame_format == AVPixelFormat::AV_PIX_FMT_BGRA,
-            "Invalid frame format"
-        );
+/// The input context and the options that were not consumed.
+unsafe fn open_input(
+    path: &std::ffi::CStr,
+    pb: *mut AVIOContext,
+    format: *const AVInputFormat,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Input, Dictionary<'static>), Error> {
+    let mut input_ptr = avformat_alloc_context();
+    if input_ptr.is_null() {
+        return Err(Error::Unknown);
+    }
 
-        let mut frame_array =
-            FrameArray::default((frame_height as usize, frame_width as usize, 3_usize));
+    (*input_ptr).interrupt_callback = AVIOInterruptCB {
+        callback: Some(interrupt_callback),
+        opaque: interrupt as *const Interrupt as *mut std::ffi::c_void,
+    };
+    // Note: `avformat_open_input` sets `AVFMT_FLAG_CUSTOM_IO` by itself when `pb` is set, so
+    // the custom I/O context will not be closed along with the input context.
+    (*input_ptr).pb = pb;
 
-        let bytes_copied = av_image_copy_to_buffer(
-            frame_array.as_mut_ptr(),
-            frame_array.len() as i32,
-            (*frame_ptr).data.as_ptr() as *const *const u8,
-            (*frame_ptr).linesize.as_ptr(),
-            frame_format,
-            frame_width,
-            frame_height,
-            1,
-        );
+    let mut opts = options.disown();
+    // Note: `avformat_open_input` frees the context on failure.
+    let ret = avformat_open_input(&mut input_ptr, path.as_ptr(), format, &mut opts);
 
-        if bytes_copied == frame_array.len() as i32 {
-            Ok(frame_array)
-        } else {
-            Err(Error::from(bytes_copied))
-        }
+    let unconsumed = Dictionary::own(opts);
+
+    match ret {
+        0 => match avformat_find_stream_info(input_ptr, std::ptr::null_mut()) {
+            r if r >= 0 => Ok((Input::wrap(input_ptr), unconsumed)),
+            e => {
+                avformat_close_input(&mut input_ptr);
+                Err(Error::from(e))
+            }
+        },
+        e => Err(Error::from(e)),
     }
 }
 
-/// Retrieve a reference to the extradata bytes in codec parameters of an output stream.
+/// Create an output context for the given format that writes to a custom I/O context.
+///
+/// The caller must make sure that `io` outlives the returned output context, and that the `pb`
+/// field of the output context is reset to null before it is dropped (or `avio_close` will try to
+/// free the custom I/O context).
 ///
 /// # Arguments
 ///
-/// * `output` - Output that contains stream to get extradata from.
-/// * `stream_index` - Index of stream.
-pub fn extradata(output: &Output, stream_index: usize) -> Result<&[u8], Error> {
-    let parameters = output
-        .stream(stream_index)
-        .map(|stream| stream.parameters())
-        .ok_or(Error::StreamNotFound)?;
+/// * `io` - Custom I/O context to write to.
+/// * `format` - Container format to use.
+pub fn output_to_io(io: &mut Io, format: &str) -> Result<Output, Error> {
+    let mut output = output_raw(format)?;
+    unsafe {
+        let output_ptr = output.as_mut_ptr();
+        (*output_ptr).pb = io.as_mut_ptr();
+        (*output_ptr).flags |= AVFMT_FLAG_CUSTOM_IO;
+    }
 
-    Ok(unsafe {
-        std::slice::from_raw_parts(
-            (*parameters.as_ptr()).extradata,
-            (*parameters.as_ptr()).extradata_size as usize,
-        )
-    })
+    Ok(output)
 }
 
-/// Whether or not the output format context is configured to use H.264 packetization mode 0.
+/// Make the muxer of an output context leave out everything that differs between runs, like the
+/// version of the library that wrote the output and random identifiers (the `bitexact` flag).
+/// This must be set before the header is written. (Not natively supported in the public API.)
 ///
 /// # Arguments
 ///
-/// * `output` - Output format context.
-pub fn rtp_h264_mode_0(output: &Output) -> bool {
+/// * `output` - Output context.
+pub fn output_set_bitexact(output: &mut Output) {
     unsafe {
-        av_opt_flag_is_set(
-            (*output.as_ptr()).priv_data,
-            "rtpflags".as_ptr() as *const std::ffi::c_char,
-            "h264_mode0".as_ptr() as *const std::ffi::c_char,
-        ) != 0
+        (*output.as_mut_ptr()).flags |= AVFMT_FLAG_BITEXACT;
     }
 }
 
-/// Get the current sequence number and timestamp of the RTP muxer.
+/// Set a private option of the muxer of an output context, like `moov_size` for MP4. Muxers
+/// read most of their options when the header is written, but some only when the trailer is.
 ///
-/// Note: This method is only safe to use on RTP output formats.
-pub fn rtp_seq_and_timestamp(output: &Output) -> (u16, u32) {
+/// # Arguments
+///
+/// * `output` - Output context.
+/// * `key` - Name of the option.
+/// * `value` - Value to set.
+pub fn output_set_muxer_option(output: &mut Output, key: &str, value: &str) -> Result<(), Error> {
+    let key = std::ffi::CString::new(key).map_err(|_| Error::OptionNotFound)?;
+    let value = std::ffi::CString::new(value).map_err(|_| Error::InvalidData)?;
     unsafe {
-        let rtp_mux_context = &*((*output.as_ptr()).priv_data as *const RTPMuxContext);
-        (rtp_mux_context.seq, rtp_mux_context.timestamp)
+        let priv_data = (*output.as_mut_ptr()).priv_data;
+        if priv_data.is_null() {
+            return Err(Error::OptionNotFound);
+        }
+        match av_opt_set(priv_data, key.as_ptr(), value.as_ptr(), 0) {
+            0.. => Ok(()),
+            err => Err(Error::from(err)),
+        }
     }
 }
 
-/// Create SDP file contents for the given output. Useful for RTP muxers.
-///
-/// A media entry will be created for each stream in the output. This function will take care of all
-/// details, such as setting the correct media attributes needed by any SDP consumers.
+/// Detach the custom I/O context from an output context created with `output_to_io`. This must be
+/// called before the output context is dropped.
 ///
 /// # Arguments
 ///
-/// * `output` - Output to generate SDP file for.
-///
-/// # Return value
-///
-/// A string with the SDP file contents.
-pub fn sdp(output: &Output) -> Result<String, Error> {
-    const BUF_SIZE: i32 = 4096;
-
+/// * `output` - Output context to detach custom I/O context from.
+pub fn output_detach_io(output: &mut Output) {
     unsafe {
-        let mut buf: [std::ffi::c_char; BUF_SIZE as usize] = [0; BUF_SIZE as usize];
-        let buf_ptr = &mut buf as *mut std::ffi::c_char;
-        let mut output_format_context = output.as_ptr();
-        let output_format_context_ptr = &mut output_format_context as *mut *const AVFormatContext;
-        // WARNING! Casting from const ptr to mutable ptr here!
-        let output_format_context_ptr = output_format_context_ptr as *mut *mut AVFormatContext;
-        let ret = av_sdp_create(output_format_context_ptr, 1, buf_ptr, BUF_SIZE);
-
-        if ret == 0 {
-            let sdp_c_str = std::ffi::CStr::from_ptr(buf_ptr);
-            let sdp = sdp_c_str.to_string_lossy().to_string();
-            Ok(sdp)
-        } else {
-            Err(Error::from(ret))
+        let output_ptr = output.as_mut_ptr();
+        if !(*output_ptr).pb.is_null() {
+            avio_flush((*output_ptr).pb);
         }
+        (*output_ptr).pb = std::ptr::null_mut();
     }
 }
 
-/// Initialize the logging handler. This will redirect all ffmpeg logging to the Rust `tracing`
-/// crate and any subscribers to it.
-pub fn init_logging() {
-    unsafe {
-        av_log_set_callback(Some(log_callback));
+/// Any type that can be used as the stream behind a readable custom I/O context.
+pub trait IoRead: std::io::Read + std::io::Seek + Send {}
+
+impl<T: std::io::Read + std::io::Seek + Send> IoRead for T {}
+
+/// Any type that can be used as the stream behind a writable custom I/O context.
+pub trait IoWrite: std::io::Write + std::io::Seek + Send {}
+
+impl<T: std::io::Write + std::io::Seek + Send> IoWrite for T {}
+
+/// Rust-side stream that is passed as `opaque` to the custom I/O callbacks.
+pub enum IoStream {
+    Read(Box<dyn IoRead>),
+    Write(Box<dyn IoWrite>),
+}
+
+impl IoStream {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        match self {
+            IoStream::Read(inner) => inner.seek(pos),
+            IoStream::Write(inner) => inner.seek(pos),
+        }
     }
 }
 
-/// Passthrough function that is passed to `libavformat` in `avio_alloc_context` and pushes buffers
-/// from a packetized stream onto the packet buffer held in `opaque`.
-extern "C" fn output_raw_buf_start_callback(
-    opaque: *mut std::ffi::c_void,
-    buffer: *mut u8,
-    buffer_size: i32,
-) -> i32 {
-    unsafe {
-        // Acquire a reference to the packet buffer transmuted from the `opaque` gotten through
-        // `libavformat`.
-        let packet_buffer: &mut Vec<Vec<u8>> = &mut *(opaque as *mut Vec<Vec<u8>>);
-        // Push the current packet onto the packet buffer.
-        packet_buffer.push(std::slice::from_raw_parts(buffer, buffer_size as usize).to_vec());
+/// Custom I/O context (`AVIOContext`) that forwards reads, writes and seeks to a Rust stream.
+///
+/// The stream is owned by the context, and is dropped along with it. Any input or output context
+/// that uses the I/O context must be dropped (or detached from it) first.
+pub struct Io {
+    context: *mut AVIOContext,
+    stream: *mut IoStream,
+}
+
+impl Io {
+    /// Size of the buffer that the backend uses for reading and writing.
+    const BUFFER_SIZE: usize = 32 * 1024;
+
+    /// Create a new custom I/O context.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream to read from or write to.
+    pub fn new(stream: IoStream) -> Result<Self, Error> {
+        let write = matches!(stream, IoStream::Write(_));
+        unsafe {
+            let buffer = av_malloc(Self::BUFFER_SIZE) as *mut u8;
+            if buffer.is_null() {
+                return Err(Error::from(AVERROR(ffmpeg::util::error::ENOMEM)));
+            }
+
+            let stream = Box::into_raw(Box::new(stream));
+            let context = avio_alloc_context(
+                buffer,
+                Self::BUFFER_SIZE as std::ffi::c_int,
+                write as std::ffi::c_int,
+                stream as *mut std::ffi::c_void,
+                if write { None } else { Some(io_read_callback) },
+                if write { Some(io_write_callback) } else { None },
+                Some(io_seek_callback),
+            );
+
+            if context.is_null() {
+                av_free(buffer as *mut std::ffi::c_void);
+                drop(Box::from_raw(stream));
+                return Err(Error::from(AVERROR(ffmpeg::util::error::ENOMEM)));
+            }
+
+            Ok(Self { context, stream })
+        }
     }
 
-    // Number of bytes written.
-    buffer_size
+    /// Get a mutable pointer to the underlying `AVIOContext`.
+    pub fn as_mut_ptr(&mut self) -> *mut AVIOContext {
+        self.context
+    }
 }
 
-/// Internal function with C-style callback behavior that receives all log messages from ffmpeg and
-/// handles them with the `log` crate, the Rust way.
+impl Drop for Io {
+    fn drop(&mut self) {
+        unsafe {
+            if (*self.context).write_flag != 0 {
+                avio_flush(self.context);
+            }
+            // Note: The backend may have replaced the buffer we allocated, so we free whatever
+            // buffer the context currently holds.
+            av_freep(&mut (*self.context).buffer as *mut *mut u8 as *mut std::ffi::c_void);
+            avio_context_free(&mut self.context);
+            drop(Box::from_raw(self.stream));
+        }
+    }
+}
+
+/// Map a Rust I/O error to a backend error code.
+///
+/// # Arguments
+///
+/// * `err` - Error to map.
+fn io_error_to_averror(err: &std::io::Error) -> std::ffi::c_int {
+    match (err.kind(), err.raw_os_error()) {
+        (std::io::ErrorKind::UnexpectedEof, _) => AVERROR_EOF,
+        (_, Some(errno)) => AVERROR(errno),
+        (std::io::ErrorKind::Unsupported, None) => AVERROR(ffmpeg::util::error::ENOSYS),
+        (_, None) => AVERROR(ffmpeg::util::error::EIO),
+    }
+}
+
+/// Read callback of [`Io`]. Panics in the Rust stream are caught and reported as errors to the
+/// backend, since unwinding into C code is not allowed.
+unsafe extern "C" fn io_read_callback(
+    opaque: *mut std::ffi::c_void,
+    buf: *mut u8,
+    buf_size: std::ffi::c_int,
+) -> std::ffi::c_int {
+    let stream = &mut *(opaque as *mut IoStream);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size.max(0) as usize);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match stream {
+        IoStream::Read(inner) => loop {
+            match inner.read(buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        },
+        IoStream::Write(_) => Err(std::io::ErrorKind::Unsupported.into()),
+    }));
+
+    match result {
+        Ok(Ok(0)) => AVERROR_EOF,
+        Ok(Ok(num_bytes)) => num_bytes as std::ffi::c_int,
+        Ok(Err(err)) => io_error_to_averror(&err),
+        Err(_) => AVERROR_EXTERNAL,
+    }
+}
+
+/// Write callback of [`Io`]. Panics in the Rust stream are caught and reported as errors to the
+/// backend, since unwinding into C code is not allowed.
+unsafe extern "C" fn io_write_callback(
+    opaque: *mut std::ffi::c_void,
+    buf: *mut u8,
+    buf_size: std::ffi::c_int,
+) -> std::ffi::c_int {
+    let stream = &mut *(opaque as *mut IoStream);
+    let buf = std::slice::from_raw_parts(buf, buf_size.max(0) as usize);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match stream {
+        IoStream::Write(inner) => inner.write_all(buf),
+        IoStream::Read(_) => Err(std::io::ErrorKind::Unsupported.into()),
+    }));
+
+    match result {
+        Ok(Ok(())) => buf_size,
+        Ok(Err(err)) => io_error_to_averror(&err),
+        Err(_) => AVERROR_EXTERNAL,
+    }
+}
+
+/// Seek callback of [`Io`]. Also handles `AVSEEK_SIZE`, which asks for the size of the stream.
+unsafe extern "C" fn io_seek_callback(
+    opaque: *mut std::ffi::c_void,
+    offset: i64,
+    whence: std::ffi::c_int,
+) -> i64 {
+    // Values of `whence` as defined by `stdio.h`.
+    const SEEK_SET: std::ffi::c_int = 0;
+    const SEEK_CUR: std::ffi::c_int = 1;
+    const SEEK_END: std::ffi::c_int = 2;
+
+    let stream = &mut *(opaque as *mut IoStream);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if whence & AVSEEK_SIZE != 0 {
+            let position = stream.seek(std::io::SeekFrom::Current(0))?;
+            let size = stream.seek(std::io::SeekFrom::End(0))?;
+            stream.seek(std::io::SeekFrom::Start(position))?;
+            return Ok(size);
+        }
+
+        let pos = match whence & !AVSEEK_FORCE {
+            SEEK_SET if offset >= 0 => std::io::SeekFrom::Start(offset as u64),
+            SEEK_CUR => std::io::SeekFrom::Current(offset),
+            SEEK_END => std::io::SeekFrom::End(offset),
+            _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+        };
+        stream.seek(pos)
+    }));
+
+    match result {
+        Ok(Ok(position)) => position as i64,
+        Ok(Err(err)) => io_error_to_averror(&err) as i64,
+        Err(_) => AVERROR_EXTERNAL as i64,
+    }
+}
+
+/// Initialize a new codec context using a specific codec.
+///
+/// # Arguments
+///
+/// * `codec` - Codec to initialize with.
+pub fn codec_context_as(codec: &Codec) -> Result<Context, Error> {
+    unsafe {
+        let context_ptr = ffmpeg::ffi::avcodec_alloc_context3(codec.as_ptr());
+        if !context_ptr.is_null() {
+            Ok(Context::wrap(context_ptr, None))
+        } else {
+            Err(Error::Unknown)
+        }
+    }
+}
+
+/// Set the `time_base` field of a decoder. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+/// * `time_base` - Time base to assign.
+pub fn set_decoder_context_time_base(decoder_context: &mut Context, time_base: Rational) {
+    unsafe {
+        (*decoder_context.as_mut_ptr()).time_base = time_base.into();
+    }
+}
+
+/// Get the `time_base` field of an encoder. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to get `time_base` of.
+pub fn get_encoder_time_base(encoder: &Video) -> Rational {
+    unsafe { (*encoder.0.as_ptr()).time_base.into() }
+}
+
+/// Get the number of frames an opened encoder declares to hold back before it outputs the first
+/// packet (the `delay` field). (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to get the delay of.
+pub fn encoder_delay(encoder: &Video) -> usize {
+    unsafe { (*encoder.0.as_ptr()).delay.max(0) as usize }
+}
+
+/// Whether or not an encoder reorders frames (with B-frames), so that the decoding order of its
+/// packets differs from the presentation order. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Opened encoder.
+pub fn encoder_reorders_frames(encoder: &Video) -> bool {
+    unsafe {
+        let context = encoder.0.as_ptr();
+        (*context).has_b_frames > 0 || (*context).max_b_frames > 0
+    }
+}
+
+/// Get the average bitrate, the maximum bitrate and the size of the rate control buffer of an
+/// encoder, in bits (per second), or zero where they are not set. (Not natively supported in the
+/// public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to get the rate control values of.
+pub fn encoder_rate_control(encoder: &Video) -> (u64, u64, u64) {
+    unsafe {
+        let context = encoder.0.as_ptr();
+        (
+            (*context).bit_rate.max(0) as u64,
+            (*context).rc_max_rate.max(0) as u64,
+            (*context).rc_buffer_size.max(0) as u64,
+        )
+    }
+}
+
+/// Set the average bitrate, the maximum bitrate and the size of the rate control buffer of an
+/// opened encoder. Encoders that support reconfiguration (like `libx264` and NVENC) pick up the
+/// new values with the next frame. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to set the rate control values of.
+/// * `bit_rate` - Average bitrate in bits per second.
+/// * `max_rate` - Maximum bitrate in bits per second.
+/// * `buffer_size` - Size of the rate control buffer in bits.
+pub fn encoder_set_rate_control(
+    encoder: &mut Video,
+    bit_rate: u64,
+    max_rate: u64,
+    buffer_size: u64,
+) {
+    unsafe {
+        let context = encoder.as_mut_ptr();
+        (*context).bit_rate = bit_rate as i64;
+        (*context).rc_max_rate = max_rate as i64;
+        (*context).rc_buffer_size = buffer_size.min(i32::MAX as u64) as i32;
+    }
+}
+
+/// Set the color primaries, transfer characteristic and color space that an encoder tags its
+/// output with. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to set the color properties of, before it is opened.
+/// * `primaries` - Color primaries.
+/// * `transfer_characteristic` - Color transfer characteristic.
+/// * `space` - Color space.
+pub fn encoder_set_color_properties(
+    encoder: &mut Video,
+    primaries: ffmpeg::util::color::Primaries,
+    transfer_characteristic: ffmpeg::util::color::TransferCharacteristic,
+    space: ffmpeg::util::color::Space,
+) {
+    unsafe {
+        let context = encoder.as_mut_ptr();
+        (*context).color_primaries = primaries.into();
+        (*context).color_trc = transfer_characteristic.into();
+        (*context).colorspace = space.into();
+    }
+}
+
+/// Look up color properties by their names (like `bt2020`, `smpte2084` and `bt2020nc`), the
+/// inverse of their `name` methods. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `primaries` - Name of the color primaries.
+/// * `transfer_characteristic` - Name of the color transfer characteristic.
+/// * `space` - Name of the color space.
+///
+/// # Return value
+///
+/// The color properties, or `None` if any of the names is unknown.
+#[cfg(feature = "serde")]
+pub fn color_properties_from_names(
+    primaries: &str,
+    transfer_characteristic: &str,
+    space: &str,
+) -> Option<(
+    ffmpeg::util::color::Primaries,
+    ffmpeg::util::color::TransferCharacteristic,
+    ffmpeg::util::color::Space,
+)> {
+    let lookup =
+        |name: &str,
+         from_name: unsafe extern "C" fn(*const std::ffi::c_char) -> std::ffi::c_int| {
+            let name = std::ffi::CString::new(name).ok()?;
+            let value = unsafe { from_name(name.as_ptr()) };
+            (value >= 0).then_some(value)
+        };
+    let primaries = lookup(primaries, av_color_primaries_from_name)?;
+    let transfer_characteristic = lookup(transfer_characteristic, av_color_transfer_from_name)?;
+    let space = lookup(space, av_color_space_from_name)?;
+    // Only values that have a name are returned, which are all variants of the enums. Newer
+    // versions of ffmpeg may know more of them though.
+    if primaries >= AVColorPrimaries::AVCOL_PRI_NB as i32
+        || transfer_characteristic >= AVColorTransferCharacteristic::AVCOL_TRC_NB as i32
+        || space >= AVColorSpace::AVCOL_SPC_NB as i32
+    {
+        return None;
+    }
+
+    unsafe {
+        Some((
+            std::mem::transmute::<i32, AVColorPrimaries>(primaries).into(),
+            std::mem::transmute::<i32, AVColorTransferCharacteristic>(transfer_characteristic)
+                .into(),
+            std::mem::transmute::<i32, AVColorSpace>(space).into(),
+        ))
+    }
+}
+
+/// Open a video encoder with options, like `open_with`, but hand back the options that the
+/// encoder did not consume. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to open.
+/// * `options` - Encoder options.
+///
+/// # Return value
+///
+/// The opened encoder and the options it did not recognize.
+pub fn encoder_open_with(
+    mut encoder: Video,
+    options: Dictionary,
+) -> Result<(ffmpeg::encoder::video::Encoder, Dictionary<'static>), Error> {
+    unsafe {
+        let mut opts = options.disown();
+        let ret = avcodec_open2(encoder.as_mut_ptr(), std::ptr::null(), &mut opts);
+        let unconsumed = Dictionary::own(opts);
+
+        match ret {
+            0 => Ok((ffmpeg::encoder::video::Encoder(encoder), unconsumed)),
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
+/// Copy frame properties from `src` to `dst`.
+///
+/// # Arguments
+///
+/// * `src` - Frame to get properties from.
+/// * `dst` - Frame to copy properties to.
+pub fn copy_frame_props(src: &Frame, dst: &mut Frame) {
+    unsafe {
+        av_frame_copy_props(dst.as_mut_ptr(), src.as_ptr());
+    }
+}
+
+/// Set the DTS of the packet that a frame was decoded from (`pkt_dts`).
+///
+/// # Arguments
+///
+/// * `frame` - Frame to set the DTS of.
+/// * `dts` - DTS to set, or `None` to unset it.
+pub fn set_frame_packet_dts(frame: &mut Frame, dts: Option<i64>) {
+    unsafe {
+        (*frame.as_mut_ptr()).pkt_dts = dts.unwrap_or(AV_NOPTS_VALUE);
+    }
+}
+
+/// Get the duration of a frame, in the time base of its timestamps.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to get the duration of.
+///
+/// # Return value
+///
+/// The duration, or `None` if it is not known.
+pub fn frame_duration(frame: &Frame) -> Option<i64> {
+    #[cfg(ffmpeg_6_0)]
+    let duration = unsafe { (*frame.as_ptr()).duration };
+    #[cfg(not(ffmpeg_6_0))]
+    let duration = unsafe { (*frame.as_ptr()).pkt_duration };
+    Some(duration).filter(|duration| *duration > 0)
+}
+
+/// Crop a frame without copying its data. The cropped frame references the same buffers as the
+/// original frame, with offset data pointers.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to crop.
+/// * `x` - Horizontal offset of the crop region.
+/// * `y` - Vertical offset of the crop region.
+/// * `width` - Width of the crop region.
+/// * `height` - Height of the crop region.
+pub fn crop_frame(frame: &Frame, x: u32, y: u32, width: u32, height: u32) -> Result<Frame, Error> {
+    unsafe {
+        let mut cropped = Frame::empty();
+        let ret = av_frame_ref(cropped.as_mut_ptr(), frame.as_ptr());
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        let cropped_ptr = cropped.as_mut_ptr();
+        (*cropped_ptr).crop_left = x as usize;
+        (*cropped_ptr).crop_top = y as usize;
+        (*cropped_ptr).crop_right = (frame.width() - x - width) as usize;
+        (*cropped_ptr).crop_bottom = (frame.height() - y - height) as usize;
+        // Without this flag the left and top offsets are rounded down to keep the data pointers
+        // aligned, which would move the crop region.
+        let ret = av_frame_apply_cropping(cropped_ptr, AV_FRAME_CROP_UNALIGNED as i32);
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        Ok(cropped)
+    }
+}
+
+/// Make sure the data of a frame is writable, copying it if the frame shares its buffers with
+/// other frames.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to make writable.
+pub fn frame_make_writable(frame: &mut Frame) -> Result<(), Error> {
+    unsafe {
+        match av_frame_make_writable(frame.as_mut_ptr()) {
+            0 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
+/// Get the layout of a plane of a video frame.
+///
+/// # Arguments
+///
+/// * `frame` - Video frame.
+/// * `plane` - Index of the plane.
+///
+/// # Return value
+///
+/// Number of bytes per row (without padding) and the number of bytes between the starts of two
+/// rows (the line size). `None` if the frame has no such plane, if the pixel format has no fixed
+/// row size or if rows are stored bottom to top (with a negative line size).
+#[cfg(feature = "ndarray")]
+pub fn frame_plane_layout(frame: &Frame, plane: usize) -> Option<(usize, usize)> {
+    unsafe {
+        let frame_ptr = frame.as_ptr();
+        if plane >= 4 || (*frame_ptr).data[plane].is_null() || (*frame_ptr).linesize[plane] <= 0 {
+            return None;
+        }
+
+        let mut row_lens = [0; 4];
+        let ret = av_image_fill_linesizes(
+            row_lens.as_mut_ptr(),
+            std::mem::transmute::<std::ffi::c_int, AVPixelFormat>((*frame_ptr).format),
+            (*frame_ptr).width,
+        );
+        if ret < 0 || row_lens[plane] <= 0 {
+            return None;
+        }
+
+        Some((
+            row_lens[plane] as usize,
+            (*frame_ptr).linesize[plane] as usize,
+        ))
+    }
+}
+
+/// Get the number of bytes in an image without any row padding, which is the size of a frame of
+/// raw video.
+///
+/// # Arguments
+///
+/// * `format` - Pixel format of the image.
+/// * `width` - Width of the image.
+/// * `height` - Height of the image.
+///
+/// # Return value
+///
+/// Number of bytes, or `None` if the pixel format has no fixed size or the dimensions are invalid.
+pub fn image_buffer_size(
+    format: ffmpeg::util::format::Pixel,
+    width: u32,
+    height: u32,
+) -> Option<usize> {
+    let (Ok(width), Ok(height)) = (i32::try_from(width), i32::try_from(height)) else {
+        return None;
+    };
+    let ret = unsafe { av_image_get_buffer_size(format.into(), width, height, 1) };
+    (ret > 0).then_some(ret as usize)
+}
+
+/// Copy the pixel data of a video frame into a tightly packed buffer, without any padding
+/// between rows or planes.
+///
+/// # Arguments
+///
+/// * `frame` - Video frame to copy.
+pub fn copy_frame_to_buffer(frame: &Frame) -> Result<Vec<u8>, Error> {
+    let size = image_buffer_size(frame.format(), frame.width(), frame.height())
+        .ok_or(Error::InvalidData)?;
+    let mut buffer = vec![0; size];
+    unsafe {
+        let frame_ptr = frame.as_ptr();
+        let bytes_copied = av_image_copy_to_buffer(
+            buffer.as_mut_ptr(),
+            size as i32,
+            (*frame_ptr).data.as_ptr() as *const *const u8,
+            (*frame_ptr).linesize.as_ptr(),
+            frame.format().into(),
+            (*frame_ptr).width,
+            (*frame_ptr).height,
+            1,
+        );
+        if bytes_copied == size as i32 {
+            Ok(buffer)
+        } else {
+            Err(Error::from(bytes_copied))
+        }
+    }
+}
+
+/// Whether or not an output format can hold a stream with the given codec, if the format knows.
+/// Formats without a list of supported codecs (like MPEG-TS) do not know, and accept most codecs.
+///
+/// # Arguments
+///
+/// * `output` - Output to check.
+/// * `codec_id` - Codec of the stream.
+pub fn output_format_supports_codec(output: &Output, codec_id: ffmpeg::codec::Id) -> Option<bool> {
+    match unsafe {
+        avformat_query_codec(
+            (*output.as_ptr()).oformat,
+            codec_id.into(),
+            FF_COMPLIANCE_NORMAL,
+        )
+    } {
+        1 => Some(true),
+        0 => Some(false),
+        _ => None,
+    }
+}
+
+/// Link the first output of a filter to the first input of another filter in the same graph.
+/// (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+/// * `src` - Name of the filter to link from.
+/// * `dst` - Name of the filter to link to.
+pub fn link_filters(graph: &mut ffmpeg::filter::Graph, src: &str, dst: &str) -> Result<(), Error> {
+    let mut filter_ptr = |name: &str| {
+        graph
+            .get(name)
+            .map(|mut context| unsafe { context.as_mut_ptr() })
+            .ok_or(Error::FilterNotFound)
+    };
+    let (src, dst) = (filter_ptr(src)?, filter_ptr(dst)?);
+    match unsafe { avfilter_link(src, 0, dst, 0) } {
+        0 => Ok(()),
+        e => Err(Error::from(e)),
+    }
+}
+
+/// Get the properties of the frames that come out of a buffer sink: width, height, pixel format,
+/// time base and frame rate (zero if it is not known). The graph must have been configured.
+///
+/// # Arguments
+///
+/// * `context` - Buffer sink filter context.
+pub fn buffersink_properties(
+    context: &mut ffmpeg::filter::Context,
+) -> (u32, u32, ffmpeg::util::format::Pixel, Rational, Rational) {
+    unsafe {
+        let context = context.as_mut_ptr();
+        let format = std::mem::transmute::<std::ffi::c_int, AVPixelFormat>(
+            av_buffersink_get_format(context),
+        );
+        (
+            av_buffersink_get_w(context) as u32,
+            av_buffersink_get_h(context) as u32,
+            format.into(),
+            av_buffersink_get_time_base(context).into(),
+            av_buffersink_get_frame_rate(context).into(),
+        )
+    }
+}
+
+/// Whether or not a pixel format has more than 8 bits per component.
+///
+/// # Arguments
+///
+/// * `format` - Pixel format to check.
+pub fn pixel_format_is_high_bit_depth(format: ffmpeg::util::format::Pixel) -> bool {
+    format
+        .descriptor()
+        .is_some_and(|descriptor| unsafe { (*descriptor.as_ptr()).comp[0].depth > 8 })
+}
+
+/// Where a component (like red or luma) of a pixel is stored in a frame, see
+/// [`pixel_format_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelComponent {
+    /// Plane that the component is in.
+    pub plane: usize,
+    /// Number of bytes between horizontally consecutive pixels.
+    pub step: usize,
+    /// Number of bytes before the component of the first pixel.
+    pub offset: usize,
+    /// Number of least significant bits below the value.
+    pub shift: u32,
+    /// Number of bits of the value.
+    pub depth: u32,
+}
+
+/// How the pixels of a pixel format are stored, see [`pixel_format_layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelLayout {
+    /// Components of a pixel: red, green and blue for RGB formats and luma and chroma otherwise,
+    /// followed by alpha if the format has it.
+    pub components: Vec<PixelComponent>,
+    /// Subsampling of the chroma components, as the base 2 logarithm of the horizontal and
+    /// vertical factor.
+    pub log2_chroma: (u32, u32),
+    /// Whether the components are red, green and blue.
+    pub rgb: bool,
+    /// Whether values of more than 8 bits are stored big-endian.
+    pub big_endian: bool,
+}
+
+/// Get how the pixels of a pixel format are stored. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `format` - Pixel format to get the layout of.
+///
+/// # Return value
+///
+/// The layout, or `None` for formats that are not stored as bytes or 16-bit words of samples,
+/// like hardware, palette and bitstream formats.
+pub fn pixel_format_layout(format: ffmpeg::util::format::Pixel) -> Option<PixelLayout> {
+    let descriptor = unsafe { &*format.descriptor()?.as_ptr() };
+    let unsupported =
+        (AV_PIX_FMT_FLAG_HWACCEL | AV_PIX_FMT_FLAG_PAL | AV_PIX_FMT_FLAG_BITSTREAM) as u64;
+    if descriptor.flags & unsupported != 0 {
+        return None;
+    }
+
+    let components = descriptor.comp[..descriptor.nb_components as usize]
+        .iter()
+        .map(|component| {
+            (component.shift + component.depth <= 16).then_some(PixelComponent {
+                plane: component.plane as usize,
+                step: component.step as usize,
+                offset: component.offset as usize,
+                shift: component.shift as u32,
+                depth: component.depth as u32,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(PixelLayout {
+        components,
+        log2_chroma: (
+            descriptor.log2_chroma_w as u32,
+            descriptor.log2_chroma_h as u32,
+        ),
+        rgb: descriptor.flags & AV_PIX_FMT_FLAG_RGB as u64 != 0,
+        big_endian: descriptor.flags & AV_PIX_FMT_FLAG_BE as u64 != 0,
+    })
+}
+
+/// Whether or not a pixel format has an alpha channel.
+///
+/// # Arguments
+///
+/// * `format` - Pixel format to check.
+pub fn pixel_format_has_alpha(format: ffmpeg::util::format::Pixel) -> bool {
+    format.descriptor().is_some_and(|descriptor| unsafe {
+        (*descriptor.as_ptr()).flags & AV_PIX_FMT_FLAG_ALPHA as u64 != 0
+    })
+}
+
+/// Set the color spaces (the matrices between RGB and YUV) that a scaler converts between, keeping
+/// the ranges it converts between. When both sides are YUV and their color spaces differ, the
+/// scaler converts through RGB. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `scaler` - Scaler to set the color spaces of.
+/// * `input` - Color space of the frames that are scaled.
+/// * `output` - Color space of the scaled frames.
+pub fn set_scaler_color_spaces(
+    scaler: &mut ScalingContext,
+    input: ffmpeg::util::color::Space,
+    output: ffmpeg::util::color::Space,
+) -> Result<(), Error> {
+    unsafe {
+        let context = scaler.as_mut_ptr();
+        let mut input_table = std::ptr::null_mut();
+        let mut output_table = std::ptr::null_mut();
+        let (mut input_range, mut output_range) = (0, 0);
+        let (mut brightness, mut contrast, mut saturation) = (0, 0, 0);
+        let ret = sws_getColorspaceDetails(
+            context,
+            &mut input_table,
+            &mut input_range,
+            &mut output_table,
+            &mut output_range,
+            &mut brightness,
+            &mut contrast,
+            &mut saturation,
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        // The coefficients are looked up by the number of the color space, which falls back to
+        // BT.601 for color spaces without coefficients of their own.
+        let ret = sws_setColorspaceDetails(
+            context,
+            sws_getCoefficients(AVColorSpace::from(input) as i32),
+            input_range,
+            sws_getCoefficients(AVColorSpace::from(output) as i32),
+            output_range,
+            brightness,
+            contrast,
+            saturation,
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy all side data (like captions and HDR metadata) from `src` to `dst`. Unlike
+/// [`copy_frame_props`], this leaves all other properties of `dst` alone.
+///
+/// # Arguments
+///
+/// * `src` - Frame to get side data from.
+/// * `dst` - Frame to copy side data to.
+pub fn copy_frame_side_data(src: &Frame, dst: &mut Frame) {
+    unsafe {
+        let src = src.as_ptr();
+        for index in 0..(*src).nb_side_data.max(0) as usize {
+            let side_data = *(*src).side_data.add(index);
+            let copy =
+                av_frame_new_side_data(dst.as_mut_ptr(), (*side_data).type_, (*side_data).size);
+            if !copy.is_null() {
+                std::ptr::copy_nonoverlapping((*side_data).data, (*copy).data, (*side_data).size);
+            }
+        }
+    }
+}
+
+/// Replace the side data of a type on a frame with a copy of `data`.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to set side data on.
+/// * `kind` - Type of side data.
+/// * `data` - Side data bytes.
+pub fn set_frame_side_data(
+    frame: &mut Frame,
+    kind: SideDataType,
+    data: &[u8],
+) -> Result<(), Error> {
+    frame.remove_side_data(kind);
+    let mut side_data = frame
+        .new_side_data(kind, data.len())
+        .ok_or_else(|| Error::from(AVERROR(ffmpeg::util::error::ENOMEM)))?;
+    unsafe {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), (*side_data.as_mut_ptr()).data, data.len());
+    }
+
+    Ok(())
+}
+
+/// Add side data (like HDR metadata) to a stream of an output, before the header is written.
+/// (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `output` - Output that has the stream.
+/// * `stream_index` - Index of the stream.
+/// * `kind` - Type of side data.
+/// * `data` - Side data bytes.
+pub fn add_stream_side_data(
+    output: &mut Output,
+    stream_index: usize,
+    kind: ffmpeg::codec::packet::side_data::Type,
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut stream = output
+        .stream_mut(stream_index)
+        .ok_or(Error::StreamNotFound)?;
+    unsafe {
+        let side_data = av_stream_new_side_data(stream.as_mut_ptr(), kind.into(), data.len());
+        if side_data.is_null() {
+            return Err(Error::from(AVERROR(ffmpeg::util::error::ENOMEM)));
+        }
+        std::ptr::copy_nonoverlapping(data.as_ptr(), side_data, data.len());
+    }
+
+    Ok(())
+}
+
+/// Layout of `AVMasteringDisplayMetadata` (from `libavutil/mastering_display_metadata.h`, which
+/// has no bindings).
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MasteringDisplayMetadataRaw {
+    pub display_primaries: [[AVRational; 2]; 3],
+    pub white_point: [AVRational; 2],
+    pub min_luminance: AVRational,
+    pub max_luminance: AVRational,
+    pub has_primaries: std::ffi::c_int,
+    pub has_luminance: std::ffi::c_int,
+}
+
+/// Layout of `AVContentLightMetadata` (from `libavutil/mastering_display_metadata.h`, which has
+/// no bindings).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentLightMetadataRaw {
+    pub max_cll: std::ffi::c_uint,
+    pub max_fall: std::ffi::c_uint,
+}
+
+/// Read `AVMasteringDisplayMetadata` from side data bytes.
+///
+/// # Arguments
+///
+/// * `data` - Side data bytes.
+pub fn mastering_display_metadata_from_bytes(data: &[u8]) -> Option<MasteringDisplayMetadataRaw> {
+    // The structure only holds integers, so any bytes are a valid value.
+    (data.len() >= std::mem::size_of::<MasteringDisplayMetadataRaw>())
+        .then(|| unsafe { std::ptr::read_unaligned(data.as_ptr() as *const _) })
+}
+
+/// Get the side data bytes of `AVMasteringDisplayMetadata`.
+///
+/// # Arguments
+///
+/// * `metadata` - Metadata to get bytes of.
+pub fn mastering_display_metadata_to_bytes(metadata: &MasteringDisplayMetadataRaw) -> Vec<u8> {
+    // The structure only holds 32-bit integers, so it has no padding.
+    unsafe {
+        std::slice::from_raw_parts(
+            metadata as *const MasteringDisplayMetadataRaw as *const u8,
+            std::mem::size_of::<MasteringDisplayMetadataRaw>(),
+        )
+        .to_vec()
+    }
+}
+
+/// Read the motion vectors from `AV_FRAME_DATA_MOTION_VECTORS` side data bytes.
+///
+/// # Arguments
+///
+/// * `data` - Side data bytes, an array of `AVMotionVector`.
+pub fn motion_vectors_from_bytes(data: &[u8]) -> Vec<AVMotionVector> {
+    // The structure only holds integers, so any bytes are a valid value.
+    data.chunks_exact(std::mem::size_of::<AVMotionVector>())
+        .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const AVMotionVector) })
+        .collect()
+}
+
+/// Make a decoder export motion vectors as frame side data, like `-flags2 +export_mvs` on the
+/// command line. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+pub fn set_decoder_export_motion_vectors(decoder_context: &mut Context) {
+    unsafe {
+        (*decoder_context.as_mut_ptr()).export_side_data |= AV_CODEC_EXPORT_DATA_MVS;
+    }
+}
+
+/// Make a decoder conceal errors in corrupt input and output the frames it could only partly
+/// decode, instead of failing on them. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context, before it is opened.
+pub fn set_decoder_error_concealment(decoder_context: &mut Context) {
+    unsafe {
+        let context = decoder_context.as_mut_ptr();
+        (*context).err_recognition = AV_EF_IGNORE_ERR;
+        (*context).error_concealment = FF_EC_GUESS_MVS | FF_EC_DEBLOCK | FF_EC_FAVOR_INTER;
+        (*context).flags |= AV_CODEC_FLAG_OUTPUT_CORRUPT as i32;
+    }
+}
+
+/// Make a decoder output each frame as soon as it can: frames are not held back for reordering,
+/// and frames are decoded with slice threads instead of frame threads, which would keep a frame
+/// per thread in flight. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context, before it is opened.
+pub fn set_decoder_low_latency(decoder_context: &mut Context) {
+    unsafe {
+        let context = decoder_context.as_mut_ptr();
+        (*context).flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
+        (*context).thread_type = FF_THREAD_SLICE;
+    }
+}
+
+/// Set which frames a decoder skips, and which decoding steps it skips for the frames it does
+/// decode. This can be changed after the decoder is opened, and applies from the next packet on.
+/// (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+/// * `skip_frame` - Frames to skip decoding altogether.
+/// * `skip_loop_filter` - Frames to skip the loop (deblocking) filter for.
+/// * `skip_idct` - Frames to skip the inverse transform for.
+pub fn set_decoder_discard(
+    decoder_context: &mut Context,
+    skip_frame: AVDiscard,
+    skip_loop_filter: AVDiscard,
+    skip_idct: AVDiscard,
+) {
+    unsafe {
+        let context = decoder_context.as_mut_ptr();
+        (*context).skip_frame = skip_frame;
+        (*context).skip_loop_filter = skip_loop_filter;
+        (*context).skip_idct = skip_idct;
+    }
+}
+
+/// Whether or not a decoder can decode with a type of hardware device. (Not natively supported in
+/// the public API.)
+///
+/// # Arguments
+///
+/// * `codec` - Decoder.
+/// * `device_type` - Type of hardware device.
+pub fn decoder_supports_hardware_device(codec: &Codec, device_type: AVHWDeviceType) -> bool {
+    unsafe {
+        (0..)
+            .map(|index| avcodec_get_hw_config(codec.as_ptr(), index))
+            .take_while(|config| !config.is_null())
+            .any(|config| {
+                (*config).methods & AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as i32 != 0
+                    && (*config).device_type == device_type
+            })
+    }
+}
+
+/// Create a hardware device and attach it to a decoder, so that it decodes into frames in the
+/// memory of the device. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context, before it is opened.
+/// * `device_type` - Type of hardware device.
+/// * `device` - Device to open, like `/dev/dri/renderD128` for VAAPI, or `None` for the default
+///   device.
+pub fn set_decoder_hardware_device(
+    decoder_context: &mut Context,
+    device_type: AVHWDeviceType,
+    device: Option<&str>,
+) -> Result<(), Error> {
+    let device = device
+        .map(std::ffi::CString::new)
+        .transpose()
+        .map_err(|_| Error::InvalidData)?;
+    unsafe {
+        let mut device_context = std::ptr::null_mut();
+        let ret = av_hwdevice_ctx_create(
+            &mut device_context,
+            device_type,
+            device
+                .as_ref()
+                .map_or(std::ptr::null(), |device| device.as_ptr()),
+            std::ptr::null_mut(),
+            0,
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        // The decoder context owns the reference from here on.
+        (*decoder_context.as_mut_ptr()).hw_device_ctx = device_context;
+    }
+
+    Ok(())
+}
+
+/// Whether or not a frame is in the memory of a hardware device.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to check.
+pub fn frame_is_hardware(frame: &Frame) -> bool {
+    unsafe { !(*frame.as_ptr()).hw_frames_ctx.is_null() }
+}
+
+/// Copy a frame from the memory of a hardware device to system memory, along with its properties.
+/// The frame gets the software pixel format that the device prefers, like NV12.
+///
+/// # Arguments
+///
+/// * `frame` - Frame in the memory of a hardware device.
+pub fn transfer_hardware_frame(frame: &Frame) -> Result<Frame, Error> {
+    unsafe {
+        let mut transferred = Frame::empty();
+        let ret = av_hwframe_transfer_data(transferred.as_mut_ptr(), frame.as_ptr(), 0);
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        av_frame_copy_props(transferred.as_mut_ptr(), frame.as_ptr());
+
+        Ok(transferred)
+    }
+}
+
+/// Read `AVContentLightMetadata` from side data bytes.
+///
+/// # Arguments
+///
+/// * `data` - Side data bytes.
+pub fn content_light_metadata_from_bytes(data: &[u8]) -> Option<ContentLightMetadataRaw> {
+    (data.len() >= std::mem::size_of::<ContentLightMetadataRaw>())
+        .then(|| unsafe { std::ptr::read_unaligned(data.as_ptr() as *const _) })
+}
+
+/// Get the side data bytes of `AVContentLightMetadata`.
+///
+/// # Arguments
+///
+/// * `metadata` - Metadata to get bytes of.
+pub fn content_light_metadata_to_bytes(metadata: &ContentLightMetadataRaw) -> Vec<u8> {
+    unsafe {
+        std::slice::from_raw_parts(
+            metadata as *const ContentLightMetadataRaw as *const u8,
+            std::mem::size_of::<ContentLightMetadataRaw>(),
+        )
+        .to_vec()
+    }
+}
+
+/// Copy audio frame properties from `src` to `dst`.
+///
+/// # Arguments
+///
+/// * `src` - Audio frame to get properties from.
+/// * `dst` - Audio frame to copy properties to.
+pub fn copy_audio_frame_props(src: &AudioFrame, dst: &mut AudioFrame) {
+    unsafe {
+        av_frame_copy_props(dst.as_mut_ptr(), src.as_ptr());
+    }
+}
+
+/// Get the maximum number of samples that a resampler outputs for a number of input samples,
+/// including the samples it still has buffered.
+///
+/// # Arguments
+///
+/// * `resampler` - Resampler.
+/// * `num_samples` - Number of input samples.
+pub fn resampler_output_capacity(resampler: &mut ResamplingContext, num_samples: usize) -> usize {
+    unsafe { swr_get_out_samples(resampler.as_mut_ptr(), num_samples as i32).max(0) as usize }
+}
+
+/// A frame array is the `ndarray` version of `AVFrame`. It is 3-dimensional array with dims `(H, W,
+/// C)` and type byte.
+#[cfg(feature = "ndarray")]
+pub type FrameArray = Array3<u8>;
+
+/// Converts an `ndarray` to a packed video `AVFrame` for ffmpeg, like RGB24 for `u8` components or
+/// RGB48 (native endianness) for `u16` components.
+///
+/// # Arguments
+///
+/// * `frame_array` - Video frame to convert. The frame format must be `(H, W, C)`.
+/// * `pixel_format` - Pixel format of the frame. Its components must have the size of `T`.
+///
+/// # Return value
+///
+/// An ffmpeg-native `AvFrame`.
+#[cfg(feature = "ndarray")]
+pub fn convert_ndarray_to_frame<T: Copy>(
+    frame_array: &Array3<T>,
+    pixel_format: AVPixelFormat,
+) -> Result<Frame, Error> {
+    unsafe {
+        assert!(frame_array.is_standard_layout());
+
+        let (frame_height, frame_width, _) = frame_array.dim();
+
+        // Temporary frame structure to place correctly formatted data and linesize stuff in, which
+        // we'll copy later.
+        let mut frame_tmp = Frame::empty();
+        let frame_tmp_ptr = frame_tmp.as_mut_ptr();
+
+        // This does not copy the data, but it sets the `frame_tmp` data and linesize pointers
+        // correctly.
+        let bytes_copied = av_image_fill_arrays(
+            (*frame_tmp_ptr).data.as_ptr() as *mut *mut u8,
+            (*frame_tmp_ptr).linesize.as_ptr() as *mut i32,
+            frame_array.as_ptr() as *const u8,
+            pixel_format,
+            frame_width as i32,
+            frame_height as i32,
+            1,
+        );
+
+        if bytes_copied != (frame_array.len() * std::mem::size_of::<T>()) as i32 {
+            return Err(Error::from(bytes_copied));
+        }
+
+        let mut frame = Frame::new(
+            Pixel::from(pixel_format),
+            frame_width as u32,
+            frame_height as u32,
+        );
+        let frame_ptr = frame.as_mut_ptr();
+
+        // Do the actual copying.
+        av_image_copy(
+            (*frame_ptr).data.as_ptr() as *mut *mut u8,
+            (*frame_ptr).linesize.as_ptr() as *mut i32,
+            (*frame_tmp_ptr).data.as_ptr() as *mut *const u8,
+            (*frame_tmp_ptr).linesize.as_ptr(),
+            pixel_format,
+            frame_width as i32,
+            frame_height as i32,
+        );
+
+        Ok(frame)
+    }
+}
+
+/// Converts an RGB24 (or BGRA) video `AVFrame` produced by ffmpeg to an `ndarray`.
+///
+/// # Arguments
+///
+/// * `frame` - Video frame to convert.
+///
+/// # Return value
+///
+/// A three-dimensional `ndarray` with dimensions `(H, W, C)` and type byte. There are three
+/// channels for RGB24 frames and four for BGRA frames.
+/// If the frame is not in RGB24 or BGRA format, this returns [`crate::Error::InvalidFrameFormat`].
+#[cfg(feature = "ndarray")]
+pub fn convert_frame_to_ndarray(frame: &mut Frame) -> Result<FrameArray, crate::Error> {
+    if !matches!(frame.format(), Pixel::RGB24 | Pixel::BGRA) {
+        return Err(crate::Error::invalid_frame_format(
+            "RGB24 or BGRA",
+            format!("{:?}", frame.format()),
+        ));
+    }
+
+    unsafe {
+        let frame_ptr = frame.as_mut_ptr();
+        let frame_width: i32 = (*frame_ptr).width;
+        let frame_height: i32 = (*frame_ptr).height;
+        let frame_format =
+            std::mem::transmute::<std::ffi::c_int, AVPixelFormat>((*frame_ptr).format);
+
+        let channels = if frame_format == AVPixelFormat::AV_PIX_FMT_BGRA {
+            4
+        } else {
+            3
+        };
+        let mut frame_array =
+            FrameArray::default((frame_height as usize, frame_width as usize, channels));
+
+        let bytes_copied = av_image_copy_to_buffer(
+            frame_array.as_mut_ptr(),
+            frame_array.len() as i32,
+            (*frame_ptr).data.as_ptr() as *const *const u8,
+            (*frame_ptr).linesize.as_ptr(),
+            frame_format,
+            frame_width,
+            frame_height,
+            1,
+        );
+
+        if bytes_copied == frame_array.len() as i32 {
+            Ok(frame_array)
+        } else {
+            Err(Error::from(bytes_copied).into())
+        }
+    }
+}
+
+/// A high bit depth frame array is the `u16` version of [`FrameArray`]. It is 3-dimensional array
+/// with dims `(H, W, C)` and type `u16`.
+#[cfg(feature = "ndarray")]
+pub type FrameArrayU16 = Array3<u16>;
+
+/// Converts an RGB48 (native endianness) `AVFrame` to an `ndarray` of type `u16`. All 16 bits of
+/// each component are preserved.
+///
+/// # Arguments
+///
+/// * `frame` - Video frame to convert.
+///
+/// # Return value
+///
+/// A three-dimensional `ndarray` with dimensions `(H, W, C)` and type `u16`.
+///
+/// If the frame is not in RGB48 format, this returns [`crate::Error::InvalidFrameFormat`].
+#[cfg(feature = "ndarray")]
+pub fn convert_frame_to_ndarray_u16(frame: &mut Frame) -> Result<FrameArrayU16, crate::Error> {
+    if frame.format() != Pixel::from(AV_PIX_FMT_RGB48) {
+        return Err(crate::Error::invalid_frame_format(
+            "RGB48",
+            format!("{:?}", frame.format()),
+        ));
+    }
+
+    unsafe {
+        let frame_ptr = frame.as_mut_ptr();
+        let frame_width: i32 = (*frame_ptr).width;
+        let frame_height: i32 = (*frame_ptr).height;
+        let frame_format =
+            std::mem::transmute::<std::ffi::c_int, AVPixelFormat>((*frame_ptr).format);
+
+        let mut frame_array =
+            FrameArrayU16::default((frame_height as usize, frame_width as usize, 3_usize));
+        let frame_array_size = (frame_array.len() * std::mem::size_of::<u16>()) as i32;
+
+        let bytes_copied = av_image_copy_to_buffer(
+            frame_array.as_mut_ptr() as *mut u8,
+            frame_array_size,
+            (*frame_ptr).data.as_ptr() as *const *const u8,
+            (*frame_ptr).linesize.as_ptr(),
+            frame_format,
+            frame_width,
+            frame_height,
+            1,
+        );
+
+        if bytes_copied == frame_array_size {
+            Ok(frame_array)
+        } else {
+            Err(Error::from(bytes_copied).into())
+        }
+    }
+}
+
+/// A floating point frame array is the `f32` version of [`FrameArray`]. It is 3-dimensional array
+/// with dims `(H, W, C)` or `(C, H, W)` and type `f32`.
+#[cfg(feature = "ndarray")]
+pub type FrameArrayF32 = Array3<f32>;
+
+/// Retrieve a reference to the extradata bytes in codec parameters of an output stream.
+///
+/// # Arguments
+///
+/// * `output` - Output that contains stream to get extradata from.
+/// * `stream_index` - Index of stream.
+pub fn extradata(output: &Output, stream_index: usize) -> Result<&[u8], Error> {
+    let parameters = output
+        .stream(stream_index)
+        .map(|stream| stream.parameters())
+        .ok_or(Error::StreamNotFound)?;
+
+    Ok(unsafe {
+        std::slice::from_raw_parts(
+            (*parameters.as_ptr()).extradata,
+            (*parameters.as_ptr()).extradata_size as usize,
+        )
+    })
+}
+
+/// Retrieve the start time of an input in `AV_TIME_BASE` units. This is `AV_NOPTS_VALUE` if the
+/// start time is not known.
+///
+/// # Arguments
+///
+/// * `input` - Input to get start time of.
+pub fn input_start_time(input: &Input) -> i64 {
+    unsafe { (*input.as_ptr()).start_time }
+}
+
+/// Retrieve the wall-clock time at which an input started, in microseconds since the Unix epoch,
+/// if the demuxer reports it (like RTSP does from its sender reports). (Not natively supported in
+/// the public API.)
+///
+/// # Arguments
+///
+/// * `input` - Input to get the start time of.
+pub fn input_start_time_realtime(input: &Input) -> Option<i64> {
+    let start_time_realtime = unsafe { (*input.as_ptr()).start_time_realtime };
+    (start_time_realtime != AV_NOPTS_VALUE).then_some(start_time_realtime)
+}
+
+/// Retrieve the position of the I/O of an input in bytes: how much of the input has been read
+/// into the I/O buffer so far, counted from the start of the input (so seeking moves it). (Not
+/// natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `input` - Input to get the position of.
+pub fn input_position(input: &Input) -> Option<u64> {
+    unsafe {
+        let pb = (*input.as_ptr()).pb;
+        (!pb.is_null()).then(|| (*pb).pos.max(0) as u64)
+    }
+}
+
+/// Retrieve the size of the I/O of an input in bytes, if it is known (like for files, and for
+/// HTTP responses with a content length). (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `input` - Input to get the size of.
+pub fn input_size(input: &Input) -> Option<u64> {
+    unsafe {
+        let pb = (*input.as_ptr()).pb;
+        if pb.is_null() {
+            return None;
+        }
+        u64::try_from(avio_size(pb)).ok().filter(|size| *size > 0)
+    }
+}
+
+/// Retrieve the keyframes in the index that the demuxer keeps of a stream, as timestamps (in the
+/// time base of the stream) and byte positions. Demuxers of containers that have an index (like
+/// MP4 and Matroska) fill it when the input is opened. Demuxers that build a generic index while
+/// reading instead (like MPEG-TS) have no complete index up front, for which this returns `None`.
+/// (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `input` - Input to get the index from.
+/// * `stream_index` - Index of the stream.
+pub fn stream_keyframe_index(input: &Input, stream_index: usize) -> Option<Vec<(i64, u64)>> {
+    unsafe {
+        let context = input.as_ptr();
+        let iformat = (*context).iformat;
+        if iformat.is_null()
+            || (*iformat).flags & AVFMT_GENERIC_INDEX != 0
+            || stream_index >= (*context).nb_streams as usize
+        {
+            return None;
+        }
+
+        let stream = *(*context).streams.add(stream_index);
+        // Before FFmpeg 5.0 (libavformat 58.78) there are no accessors for the index, but the
+        // entries are still public fields of the stream.
+        #[cfg(ffmpeg_5_0)]
+        let entries = (0..avformat_index_get_entries_count(stream).max(0))
+            .filter_map(|index| avformat_index_get_entry(stream, index).as_ref());
+        #[cfg(not(ffmpeg_5_0))]
+        let entries = (0..(*stream).nb_index_entries.max(0) as usize)
+            .filter_map(|index| (*stream).index_entries.add(index).as_ref());
+        Some(
+            entries
+                .filter(|entry| entry.flags() & AVINDEX_KEYFRAME != 0 && entry.pos >= 0)
+                .map(|entry| (entry.timestamp, entry.pos as u64))
+                .collect(),
+        )
+    }
+}
+
+/// Clear the end of file state of the I/O of an input, so that reading continues with whatever
+/// was written to it since. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `input` - Input to clear the end of file state of.
+pub fn input_clear_eof(input: &mut Input) {
+    unsafe {
+        let pb = (*input.as_mut_ptr()).pb;
+        if !pb.is_null() {
+            (*pb).eof_reached = 0;
+        }
+    }
+}
+
+/// Check whether an input can seek. Inputs that read through I/O (files and most network streams)
+/// can seek if the I/O can, and other inputs (like image sequences and RTSP streams) if their
+/// demuxer implements seeking.
+///
+/// # Arguments
+///
+/// * `input` - Input to check.
+pub fn input_is_seekable(input: &Input) -> bool {
+    unsafe {
+        let pb = (*input.as_ptr()).pb;
+        if !pb.is_null() {
+            return (*pb).seekable != 0;
+        }
+        let iformat = (*input.as_ptr()).iformat;
+        !iformat.is_null() && ((*iformat).read_seek.is_some() || (*iformat).read_seek2.is_some())
+    }
+}
+
+/// Retrieve a copy of the extradata bytes of a codec context. (Not natively supported in the
+/// public API.)
+///
+/// # Arguments
+///
+/// * `context` - Codec context to get extradata from.
+pub fn codec_context_extradata(context: &Context) -> Vec<u8> {
+    unsafe {
+        let context = context.as_ptr();
+        if (*context).extradata.is_null() || (*context).extradata_size <= 0 {
+            return Vec::new();
+        }
+
+        std::slice::from_raw_parts((*context).extradata, (*context).extradata_size as usize)
+            .to_vec()
+    }
+}
+
+/// Retrieve a copy of the extradata bytes in codec parameters.
+///
+/// # Arguments
+///
+/// * `parameters` - Codec parameters to get extradata from.
+pub fn parameters_extradata(parameters: &Parameters) -> Vec<u8> {
+    unsafe {
+        let parameters = parameters.as_ptr();
+        if (*parameters).extradata.is_null() {
+            return Vec::new();
+        }
+
+        std::slice::from_raw_parts(
+            (*parameters).extradata,
+            (*parameters).extradata_size as usize,
+        )
+        .to_vec()
+    }
+}
+
+/// Video and audio properties of codec parameters. Properties that do not apply to the media type
+/// of the parameters are zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParametersProperties {
+    pub width: u32,
+    pub height: u32,
+    /// Name of the pixel format (video) or sample format (audio), if it is known.
+    pub format: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u32,
+}
+
+/// Retrieve the video and audio properties of codec parameters.
+///
+/// # Arguments
+///
+/// * `parameters` - Codec parameters to get properties of.
+pub fn parameters_properties(parameters: &Parameters) -> ParametersProperties {
+    unsafe {
+        let parameters = parameters.as_ptr();
+        let format = (*parameters).format;
+        let format_name = match (*parameters).codec_type {
+            AVMediaType::AVMEDIA_TYPE_VIDEO
+                if (0..AVPixelFormat::AV_PIX_FMT_NB as i32).contains(&format) =>
+            {
+                av_get_pix_fmt_name(std::mem::transmute::<i32, AVPixelFormat>(format))
+            }
+            AVMediaType::AVMEDIA_TYPE_AUDIO
+                if (0..AVSampleFormat::AV_SAMPLE_FMT_NB as i32).contains(&format) =>
+            {
+                av_get_sample_fmt_name(std::mem::transmute::<i32, AVSampleFormat>(format))
+            }
+            _ => std::ptr::null(),
+        };
+
+        ParametersProperties {
+            width: (*parameters).width.max(0) as u32,
+            height: (*parameters).height.max(0) as u32,
+            format: (!format_name.is_null()).then(|| {
+                std::ffi::CStr::from_ptr(format_name)
+                    .to_string_lossy()
+                    .into_owned()
+            }),
+            sample_rate: (*parameters).sample_rate.max(0) as u32,
+            #[cfg(ffmpeg_5_1)]
+            channels: (*parameters).ch_layout.nb_channels.max(0) as u32,
+            #[cfg(not(ffmpeg_5_1))]
+            channels: (*parameters).channels.max(0) as u32,
+        }
+    }
+}
+
+/// Get the number of priming samples at the start and of padding samples at the end of an audio
+/// stream, as the container reports them in the codec parameters.
+///
+/// (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `parameters` - Codec parameters of the stream.
+///
+/// # Return value
+///
+/// The initial padding and the trailing padding, in samples.
+pub fn parameters_padding(parameters: &Parameters) -> (u32, u32) {
+    unsafe {
+        let parameters = parameters.as_ptr();
+        (
+            (*parameters).initial_padding.max(0) as u32,
+            (*parameters).trailing_padding.max(0) as u32,
+        )
+    }
+}
+
+/// Retrieve a copy of the attached picture (like cover art) of a stream in an input format context.
+/// This is empty if the stream has no attached picture.
+///
+/// # Arguments
+///
+/// * `input` - Input format context.
+/// * `stream_index` - Index of the stream.
+pub fn stream_attached_pic(input: &Input, stream_index: usize) -> Vec<u8> {
+    unsafe {
+        let input = input.as_ptr();
+        if stream_index >= (*input).nb_streams as usize {
+            return Vec::new();
+        }
+
+        let packet = &(**(*input).streams.add(stream_index)).attached_pic;
+        if packet.data.is_null() || packet.size <= 0 {
+            return Vec::new();
+        }
+
+        std::slice::from_raw_parts(packet.data, packet.size as usize).to_vec()
+    }
+}
+
+/// Turn a stream of an output format context into an attachment stream that holds `data` in its
+/// extradata, like Matroska attachments.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `stream_index` - Index of the stream.
+/// * `codec_id` - Codec of the data.
+/// * `data` - Data to attach.
+pub fn set_stream_attachment(
+    output: &mut Output,
+    stream_index: usize,
+    codec_id: AVCodecID,
+    data: &[u8],
+) -> Result<(), Error> {
+    unsafe {
+        let parameters = (**(*output.as_mut_ptr()).streams.add(stream_index)).codecpar;
+        (*parameters).codec_type = AVMediaType::AVMEDIA_TYPE_ATTACHMENT;
+        (*parameters).codec_id = codec_id;
+
+        replace_parameters_extradata(parameters, data)
+    }
+}
+
+/// Create the codec parameters of a video stream, for packets that were encoded elsewhere.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec of the packets.
+/// * `width` - Width of the video.
+/// * `height` - Height of the video.
+/// * `extradata` - Extradata of the codec, like the SPS and PPS of H.264. May be empty.
+pub fn video_codec_parameters(
+    codec_id: AVCodecID,
+    width: u32,
+    height: u32,
+    extradata: &[u8],
+) -> Result<Parameters, Error> {
+    let mut parameters = Parameters::new();
+    unsafe {
+        let raw = parameters.as_mut_ptr();
+        (*raw).codec_type = AVMediaType::AVMEDIA_TYPE_VIDEO;
+        (*raw).codec_id = codec_id;
+        (*raw).width = width as i32;
+        (*raw).height = height as i32;
+        if !extradata.is_empty() {
+            replace_parameters_extradata(raw, extradata)?;
+        }
+    }
+
+    Ok(parameters)
+}
+
+/// Replace the extradata of codec parameters with a padded copy of `data`.
+///
+/// # Safety
+///
+/// `parameters` must point to valid codec parameters.
+unsafe fn replace_parameters_extradata(
+    parameters: *mut AVCodecParameters,
+    data: &[u8],
+) -> Result<(), Error> {
+    av_freep(&mut (*parameters).extradata as *mut *mut u8 as *mut std::ffi::c_void);
+    let extradata = av_mallocz(data.len() + AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+    if extradata.is_null() {
+        (*parameters).extradata_size = 0;
+        return Err(Error::Other {
+            errno: ffmpeg::util::error::ENOMEM,
+        });
+    }
+    std::ptr::copy_nonoverlapping(data.as_ptr(), extradata, data.len());
+    (*parameters).extradata = extradata;
+    (*parameters).extradata_size = data.len() as i32;
+
+    Ok(())
+}
+
+/// Turn a stream of an output format context into an attached picture stream, like cover art in
+/// MP4. The picture itself must be written as the only packet of the stream.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `stream_index` - Index of the stream.
+/// * `codec_id` - Codec of the picture.
+/// * `width` - Width of the picture.
+/// * `height` - Height of the picture.
+pub fn set_stream_attached_pic(
+    output: &mut Output,
+    stream_index: usize,
+    codec_id: AVCodecID,
+    width: u32,
+    height: u32,
+) {
+    unsafe {
+        let stream = *(*output.as_mut_ptr()).streams.add(stream_index);
+        let parameters = (*stream).codecpar;
+        (*parameters).codec_type = AVMediaType::AVMEDIA_TYPE_VIDEO;
+        (*parameters).codec_id = codec_id;
+        (*parameters).width = width as i32;
+        (*parameters).height = height as i32;
+        (*stream).disposition |= AV_DISPOSITION_ATTACHED_PIC;
+    }
+}
+
+/// Set the disposition flags (like default and forced) of a stream in an output format context.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `stream_index` - Index of the stream.
+/// * `disposition` - Disposition flags.
+pub fn set_stream_disposition(output: &mut Output, stream_index: usize, disposition: i32) {
+    unsafe {
+        (**(*output.as_mut_ptr()).streams.add(stream_index)).disposition = disposition;
+    }
+}
+
+/// Remove all chapters from an output format context.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+pub fn output_clear_chapters(output: &mut Output) {
+    unsafe {
+        let output = output.as_mut_ptr();
+        for index in 0..(*output).nb_chapters as usize {
+            let chapter = *(*output).chapters.add(index);
+            av_dict_free(&mut (*chapter).metadata);
+            av_free(chapter as *mut std::ffi::c_void);
+        }
+        av_freep(&mut (*output).chapters as *mut *mut *mut AVChapter as *mut std::ffi::c_void);
+        (*output).nb_chapters = 0;
+    }
+}
+
+/// Clear the codec tag in codec parameters. Codec tags are container specific, so they must be
+/// cleared when copying a stream into another container, to let the muxer pick the right one.
+///
+/// # Arguments
+///
+/// * `parameters` - Codec parameters to clear codec tag of.
+pub fn clear_codec_tag(parameters: &mut Parameters) {
+    unsafe {
+        (*parameters.as_mut_ptr()).codec_tag = 0;
+    }
+}
+
+/// Whether or not the output format context is configured to use H.264 packetization mode 0.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+pub fn rtp_h264_mode_0(output: &Output) -> bool {
+    unsafe {
+        av_opt_flag_is_set(
+            (*output.as_ptr()).priv_data,
+            "rtpflags".as_ptr() as *const std::ffi::c_char,
+            "h264_mode0".as_ptr() as *const std::ffi::c_char,
+        ) != 0
+    }
+}
+
+/// Get the current sequence number and timestamp of the RTP muxer.
+///
+/// Note: This method is only safe to use on RTP output formats.
+pub fn rtp_seq_and_timestamp(output: &Output) -> (u16, u32) {
+    unsafe {
+        let rtp_mux_context = &*((*output.as_ptr()).priv_data as *const RTPMuxContext);
+        (rtp_mux_context.seq, rtp_mux_context.timestamp)
+    }
+}
+
+/// Create SDP file contents for the given output. Useful for RTP muxers.
+///
+/// A media entry will be created for each stream in the output. This function will take care of all
+/// details, such as setting the correct media attributes needed by any SDP consumers.
+///
+/// # Arguments
+///
+/// * `output` - Output to generate SDP file for.
+///
+/// # Return value
+///
+/// A string with the SDP file contents.
+pub fn sdp(output: &Output) -> Result<String, Error> {
+    const BUF_SIZE: i32 = 4096;
+
+    unsafe {
+        let mut buf: [std::ffi::c_char; BUF_SIZE as usize] = [0; BUF_SIZE as usize];
+        let buf_ptr = &mut buf as *mut std::ffi::c_char;
+        let mut output_format_context = output.as_ptr();
+        let output_format_context_ptr = &mut output_format_context as *mut *const AVFormatContext;
+        // WARNING! Casting from const ptr to mutable ptr here!
+        let output_format_context_ptr = output_format_context_ptr as *mut *mut AVFormatContext;
+        let ret = av_sdp_create(output_format_context_ptr, 1, buf_ptr, BUF_SIZE);
+
+        if ret == 0 {
+            let sdp_c_str = std::ffi::CStr::from_ptr(buf_ptr);
+            let sdp = sdp_c_str.to_string_lossy().to_string();
+            Ok(sdp)
+        } else {
+            Err(Error::from(ret))
+        }
+    }
+}
+
+/// Check whether or not the backend was built with support for a protocol.
+///
+/// # Arguments
+///
+/// * `name` - Name of the protocol, like `srt`.
+/// * `output` - Whether to check for output support (`true`) or input support (`false`).
+pub fn protocol_supported(name: &str, output: bool) -> bool {
+    let mut opaque = std::ptr::null_mut();
+    loop {
+        let protocol = unsafe { avio_enum_protocols(&mut opaque, output as std::ffi::c_int) };
+        if protocol.is_null() {
+            return false;
+        }
+        if unsafe { std::ffi::CStr::from_ptr(protocol) }.to_bytes() == name.as_bytes() {
+            return true;
+        }
+    }
+}
+
+/// Get all codecs (encoders and decoders) that ffmpeg was built with, in registration order. The
+/// ffmpeg-next crate does not provide bindings for iterating over codecs.
+pub fn codecs() -> Vec<Codec> {
+    let mut codecs = Vec::new();
+    let mut opaque = std::ptr::null_mut();
+    loop {
+        let codec = unsafe { av_codec_iterate(&mut opaque) };
+        if codec.is_null() {
+            return codecs;
+        }
+        codecs.push(unsafe { Codec::wrap(codec as *mut AVCodec) });
+    }
+}
+
+/// Whether or not a codec is backed by hardware, either completely or partially (hybrid).
+///
+/// # Arguments
+///
+/// * `codec` - Codec to check.
+pub fn codec_is_hardware(codec: &Codec) -> bool {
+    let capabilities = unsafe { (*codec.as_ptr()).capabilities } as u32;
+    capabilities & (AV_CODEC_CAP_HARDWARE | AV_CODEC_CAP_HYBRID) != 0
+}
+
+/// Option declared by the private class of a codec, with its strings copied.
+pub struct CodecOptionRaw {
+    pub name: String,
+    pub help: String,
+    pub kind: AVOptionType,
+    /// Default value for integer options and value of named constants.
+    pub default_int: i64,
+    /// Default value for floating point options.
+    pub default_double: f64,
+    /// Default value for rational options.
+    pub default_rational: Rational,
+    /// Default value for options that are parsed from a string, like strings and image sizes.
+    pub default_string: Option<String>,
+    pub min: f64,
+    pub max: f64,
+    /// Unit that the option shares with its named constants.
+    pub unit: Option<String>,
+}
+
+/// Get the options of the private class of a codec (the codec-specific options, like `preset` for
+/// `libx264`), including the named constants, in declaration order.
+///
+/// # Arguments
+///
+/// * `codec` - Codec to get options of.
+pub fn codec_private_options(codec: &Codec) -> Vec<CodecOptionRaw> {
+    let class = unsafe { (*codec.as_ptr()).priv_class };
+    if class.is_null() {
+        return Vec::new();
+    }
+
+    let mut options = Vec::new();
+    let mut option: *const AVOption = std::ptr::null();
+    loop {
+        // `av_opt_next` takes a pointer to an object, which starts with its class.
+        option = unsafe { av_opt_next(&class as *const *const AVClass as *const _, option) };
+        if option.is_null() {
+            return options;
+        }
+        let option = unsafe { &*option };
+        let default_string = match option.type_ {
+            AVOptionType::AV_OPT_TYPE_STRING
+            | AVOptionType::AV_OPT_TYPE_BINARY
+            | AVOptionType::AV_OPT_TYPE_DICT
+            | AVOptionType::AV_OPT_TYPE_IMAGE_SIZE
+            | AVOptionType::AV_OPT_TYPE_VIDEO_RATE
+            | AVOptionType::AV_OPT_TYPE_COLOR
+            | AVOptionType::AV_OPT_TYPE_CHLAYOUT => {
+                let default = unsafe { option.default_val.str_ };
+                (!default.is_null()).then(|| unsafe { cstr_to_string(default) })
+            }
+            _ => None,
+        };
+        options.push(CodecOptionRaw {
+            name: unsafe { cstr_to_string(option.name) },
+            help: unsafe { cstr_to_string(option.help) },
+            kind: option.type_,
+            // The other fields of the union hold plain numbers, so reading them is always valid.
+            default_int: unsafe { option.default_val.i64_ },
+            default_double: unsafe { option.default_val.dbl },
+            default_rational: unsafe { option.default_val.q }.into(),
+            default_string,
+            min: option.min,
+            max: option.max,
+            unit: (!option.unit.is_null()).then(|| unsafe { cstr_to_string(option.unit) }),
+        });
+    }
+}
+
+/// Find the pixel format among a list of pixel formats that loses the least when converting to it
+/// from another pixel format. Hardware pixel formats (like `cuda`) are skipped, since frames cannot
+/// be converted to them in software.
+///
+/// # Arguments
+///
+/// * `formats` - Pixel formats to choose from.
+/// * `source` - Pixel format to convert from.
+///
+/// # Return value
+///
+/// The best pixel format, or `None` if there are no formats to choose from.
+pub fn find_best_pixel_format(formats: &[Pixel], source: Pixel) -> Option<Pixel> {
+    let list = formats
+        .iter()
+        .filter(|format| {
+            format.descriptor().is_some_and(|descriptor| unsafe {
+                (*descriptor.as_ptr()).flags & AV_PIX_FMT_FLAG_HWACCEL as u64 == 0
+            })
+        })
+        .map(|&format| AVPixelFormat::from(format))
+        .chain([AVPixelFormat::AV_PIX_FMT_NONE])
+        .collect::<Vec<_>>();
+    let has_alpha = source.descriptor().is_some_and(|descriptor| unsafe {
+        (*descriptor.as_ptr()).flags & AV_PIX_FMT_FLAG_ALPHA as u64 != 0
+    });
+    let best = unsafe {
+        avcodec_find_best_pix_fmt_of_list(
+            list.as_ptr(),
+            source.into(),
+            has_alpha as std::ffi::c_int,
+            std::ptr::null_mut(),
+        )
+    };
+    match Pixel::from(best) {
+        Pixel::None => None,
+        best => Some(best),
+    }
+}
+
+/// Get the names of all muxers that ffmpeg was built with.
+pub fn muxer_names() -> Vec<String> {
+    let mut names = Vec::new();
+    let mut opaque = std::ptr::null_mut();
+    loop {
+        let muxer = unsafe { av_muxer_iterate(&mut opaque) };
+        if muxer.is_null() {
+            return names;
+        }
+        names.push(unsafe { cstr_to_string((*muxer).name) });
+    }
+}
+
+/// Get the names of all demuxers that ffmpeg was built with. Some demuxers have multiple
+/// comma-separated names, like `mov,mp4,m4a,3gp,3g2,mj2`.
+pub fn demuxer_names() -> Vec<String> {
+    let mut names = Vec::new();
+    let mut opaque = std::ptr::null_mut();
+    loop {
+        let demuxer = unsafe { av_demuxer_iterate(&mut opaque) };
+        if demuxer.is_null() {
+            return names;
+        }
+        names.push(unsafe { cstr_to_string((*demuxer).name) });
+    }
+}
+
+/// Before FFmpeg 4.0, codecs, muxers and demuxers are iterated over with `av_codec_next`,
+/// `av_oformat_next` and `av_iformat_next` instead. These emulate the newer iteration functions by
+/// keeping the previous item in `opaque`.
+#[cfg(not(ffmpeg_4_0))]
+unsafe fn av_codec_iterate(opaque: *mut *mut std::ffi::c_void) -> *const AVCodec {
+    let codec = av_codec_next(*opaque as *const AVCodec);
+    *opaque = codec as *mut std::ffi::c_void;
+    codec
+}
+
+#[cfg(not(ffmpeg_4_0))]
+unsafe fn av_muxer_iterate(opaque: *mut *mut std::ffi::c_void) -> *const AVOutputFormat {
+    let muxer = av_oformat_next(*opaque as *const AVOutputFormat);
+    *opaque = muxer as *mut std::ffi::c_void;
+    muxer
+}
+
+#[cfg(not(ffmpeg_4_0))]
+unsafe fn av_demuxer_iterate(opaque: *mut *mut std::ffi::c_void) -> *const AVInputFormat {
+    let demuxer = av_iformat_next(*opaque as *const AVInputFormat);
+    *opaque = demuxer as *mut std::ffi::c_void;
+    demuxer
+}
+
+/// Copy a C string into an owned string.
+///
+/// # Safety
+///
+/// The pointer must be null or point to a valid nul-terminated string.
+unsafe fn cstr_to_string(ptr: *const std::ffi::c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        std::ffi::CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
+}
+
+/// Bitstream filter, like `h264_mp4toannexb`. The ffmpeg-next crate does not provide bindings for
+/// bitstream filters.
+pub struct BitstreamFilter(*mut AVBSFContext);
+
+impl BitstreamFilter {
+    /// Create and initialize a bitstream filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the bitstream filter.
+    /// * `parameters` - Codec parameters of the stream to filter.
+    /// * `time_base` - Time base of the packets to filter.
+    pub fn new(name: &str, parameters: &Parameters, time_base: Rational) -> Result<Self, Error> {
+        let name = std::ffi::CString::new(name).map_err(|_| Error::BsfNotFound)?;
+        unsafe {
+            let filter = av_bsf_get_by_name(name.as_ptr());
+            if filter.is_null() {
+                return Err(Error::BsfNotFound);
+            }
+
+            let mut context = std::ptr::null_mut();
+            match av_bsf_alloc(filter, &mut context) {
+                0 => {}
+                e => return Err(Error::from(e)),
+            }
+
+            // Wrap the context first so that it is freed on failure.
+            let bitstream_filter = Self(context);
+            match avcodec_parameters_copy((*context).par_in, parameters.as_ptr()) {
+                0 => {}
+                e => return Err(Error::from(e)),
+            }
+            (*context).time_base_in = time_base.into();
+
+            match av_bsf_init(context) {
+                0 => Ok(bitstream_filter),
+                e => Err(Error::from(e)),
+            }
+        }
+    }
+
+    /// Get the codec parameters of the filtered stream.
+    pub fn parameters_out(&self) -> Parameters {
+        let mut parameters = Parameters::new();
+        unsafe {
+            avcodec_parameters_copy(parameters.as_mut_ptr(), (*self.0).par_out);
+        }
+        parameters
+    }
+
+    /// Get the time base of the filtered packets.
+    pub fn time_base_out(&self) -> Rational {
+        unsafe { (*self.0).time_base_out.into() }
+    }
+
+    /// Send a packet to the filter. Pass `None` to signal the end of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to filter. The filter takes ownership of the packet data.
+    pub fn send(&mut self, packet: Option<&mut Packet>) -> Result<(), Error> {
+        let packet = packet.map_or(std::ptr::null_mut(), |packet| packet.as_mut_ptr());
+        match unsafe { av_bsf_send_packet(self.0, packet) } {
+            0 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+
+    /// Receive a filtered packet from the filter. This returns `EAGAIN` if the filter needs more
+    /// input, and `Error::Eof` if the filter has been flushed completely.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to receive into.
+    pub fn receive(&mut self, packet: &mut Packet) -> Result<(), Error> {
+        match unsafe { av_bsf_receive_packet(self.0, packet.as_mut_ptr()) } {
+            0 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+
+    /// Reset the internal state of the filter, dropping any packets that have not been received
+    /// yet. This should be done after seeking.
+    pub fn reset(&mut self) {
+        unsafe {
+            av_bsf_flush(self.0);
+        }
+    }
+}
+
+impl Drop for BitstreamFilter {
+    fn drop(&mut self) {
+        unsafe {
+            av_bsf_free(&mut self.0);
+        }
+    }
+}
+
+unsafe impl Send for BitstreamFilter {}
+unsafe impl Sync for BitstreamFilter {}
+
+/// Initialize the logging handler. This will redirect all ffmpeg logging to the Rust `tracing`
+/// crate and any subscribers to it.
+pub fn init_logging() {
+    unsafe {
+        av_log_set_callback(Some(log_callback));
+    }
+}
+
+/// Get the version of ffmpeg, as reported by `av_version_info`.
+pub fn version_info() -> String {
+    unsafe {
+        std::ffi::CStr::from_ptr(av_version_info())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Set the level of the messages that ffmpeg logs. Messages below this level are not formatted at
+/// all.
+///
+/// # Arguments
+///
+/// * `level` - One of the `AV_LOG_*` levels.
+pub fn set_log_level(level: std::ffi::c_int) {
+    LOG_LEVEL.store(level, std::sync::atomic::Ordering::Relaxed);
+    unsafe {
+        av_log_set_level(level);
+    }
+}
+
+/// Call a function and collect the error messages that ffmpeg logs on the current thread while it
+/// runs, for example to find out why a filter graph description could not be parsed. The messages
+/// are collected regardless of the log level, and are logged as usual as well.
+///
+/// # Arguments
+///
+/// * `f` - Function to call.
+///
+/// # Return value
+///
+/// The result of the function and the error messages, without their component prefix.
+pub fn capture_log_errors<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let previous = CAPTURED_ERRORS.replace(Some(Vec::new()));
+    let result = f();
+    let errors = CAPTURED_ERRORS.replace(previous).unwrap_or_default();
+    (result, errors)
+}
+
+thread_local! {
+    /// Error messages collected by [`capture_log_errors`], if it is running on this thread.
+    static CAPTURED_ERRORS: std::cell::RefCell<Option<Vec<String>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Level of the messages that the log callback passes on. Until the level is set explicitly, all
+/// messages that a `tracing` subscriber is interested in are passed on.
+static LOG_LEVEL: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(i32::MAX);
+
+/// Passthrough function that is passed to `libavformat` in `avio_alloc_context` and pushes buffers
+/// from a packetized stream onto the packet buffer held in `opaque`.
+extern "C" fn output_raw_buf_start_callback(
+    opaque: *mut std::ffi::c_void,
+    buffer: *mut u8,
+    buffer_size: i32,
+) -> i32 {
+    unsafe {
+        // Acquire a reference to the packet buffer transmuted from the `opaque` gotten through
+        // `libavformat`.
+        let packet_buffer: &mut Vec<Vec<u8>> = &mut *(opaque as *mut Vec<Vec<u8>>);
+        // Push the current packet onto the packet buffer.
+        packet_buffer.push(std::slice::from_raw_parts(buffer, buffer_size as usize).to_vec());
+    }
+
+    // Number of bytes written.
+    buffer_size
+}
+
+/// Interrupt callback that is passed to `libavformat` through `AVIOInterruptCB`. The `opaque`
+/// pointer points to the [`Interrupt`] state of the reader or writer.
+extern "C" fn interrupt_callback(opaque: *mut std::ffi::c_void) -> std::ffi::c_int {
+    let interrupt = unsafe { &*(opaque as *const Interrupt) };
+    interrupt.should_interrupt() as std::ffi::c_int
+}
+
+/// Internal function with C-style callback behavior that receives all log messages from ffmpeg and
+/// handles them with the `log` crate, the Rust way.
 ///
 /// # Arguments
 ///
@@ -475,7 +2777,24 @@ unsafe extern "C" fn log_callback(
     #[cfg(all(target_arch = "x86_64", target_family = "unix"))] vl: *mut __va_list_tag,
     #[cfg(not(all(target_arch = "x86_64", target_family = "unix")))] vl: va_list,
 ) {
-    static mut PRINT_PREFIX: std::ffi::c_int = 1;
+    thread_local! {
+        // Whether or not the next line starts with the component prefix, which depends on whether
+        // the previous line on the same thread ended with a newline. ffmpeg logs from multiple
+        // threads, so this must not be shared between threads.
+        static PRINT_PREFIX: std::cell::Cell<std::ffi::c_int> = const { std::cell::Cell::new(1) };
+        // Whether or not this thread is handling a log message already. Messages that are logged
+        // while handling a message (for example by a subscriber that calls into ffmpeg) are
+        // dropped instead of recursing.
+        static IN_CALLBACK: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+    }
+
+    let capturing =
+        level_no <= AV_LOG_ERROR && CAPTURED_ERRORS.with_borrow(|errors| errors.is_some());
+    if (level_no > LOG_LEVEL.load(std::sync::atomic::Ordering::Relaxed) && !capturing)
+        || IN_CALLBACK.replace(true)
+    {
+        return;
+    }
 
     // Check whether or not the message would be printed at all.
     let event_would_log = match level_no {
@@ -486,16 +2805,16 @@ unsafe extern "C" fn log_callback(
         // There is no "verbose" in `log`, so we just put it in the "debug" category.
         AV_LOG_VERBOSE | AV_LOG_DEBUG => tracing::enabled!(tracing::Level::DEBUG),
         AV_LOG_TRACE => tracing::enabled!(tracing::Level::TRACE),
-        _ => {
-            return;
-        }
+        _ => false,
     };
 
-    if event_would_log {
+    if event_would_log || capturing {
         // Allocate some memory for the log line (might be truncated). 1024 bytes is the number used
         // by ffmpeg itself, so it should be mostly fine.
         let mut line = [0; 1024];
-        // Use the ffmpeg default formatting.
+        let mut print_prefix = PRINT_PREFIX.get();
+        // Use the ffmpeg default formatting, which prefixes the line with the component, like
+        // `[libx264 @ 0x5581e1c0]`.
         let ret = av_log_format_line2(
             avcl,
             level_no,
@@ -503,32 +2822,71 @@ unsafe extern "C" fn log_callback(
             vl,
             line.as_mut_ptr(),
             (line.len()) as std::ffi::c_int,
-            (&mut PRINT_PREFIX) as *mut std::ffi::c_int,
+            &mut print_prefix,
         );
+        PRINT_PREFIX.set(print_prefix);
         // Simply discard the log message if formatting fails.
         if ret > 0 {
             if let Ok(line) = std::ffi::CStr::from_ptr(line.as_mut_ptr()).to_str() {
-                let line = line.trim();
-                if log_filter_hacks(line) {
+                // Log lines may contain URLs with credentials, which must not end up in the logs.
+                let line = crate::io::redact_credentials(line.trim());
+                let component = log_component(avcl).unwrap_or("ffmpeg");
+                if capturing {
+                    // Strip the component prefix, like `[Parsed_scale_0 @ 0x5581e1c0] `.
+                    let message = match line.strip_prefix('[').and_then(|l| l.split_once("] ")) {
+                        Some((_, message)) => message,
+                        None => line.as_ref(),
+                    };
+                    CAPTURED_ERRORS.with_borrow_mut(|errors| {
+                        if let Some(errors) = errors {
+                            errors.push(message.to_string());
+                        }
+                    });
+                }
+                if event_would_log && log_filter_hacks(&line) {
                     match level_no {
                         // These are all error states.
                         AV_LOG_PANIC | AV_LOG_FATAL | AV_LOG_ERROR => {
-                            tracing::error!(target: "video", "{}", line)
+                            tracing::error!(target: "video", component, "{}", line)
                         }
-                        AV_LOG_WARNING => tracing::warn!(target: "video", "{}", line),
-                        AV_LOG_INFO => tracing::info!(target: "video", "{}", line),
+                        AV_LOG_WARNING => tracing::warn!(target: "video", component, "{}", line),
+                        AV_LOG_INFO => tracing::info!(target: "video", component, "{}", line),
                         // There is no "verbose" in `log`, so we just put it in the "debug"
                         // category.
                         AV_LOG_VERBOSE | AV_LOG_DEBUG => {
-                            tracing::debug!(target: "video", "{}", line)
+                            tracing::debug!(target: "video", component, "{}", line)
                         }
-                        AV_LOG_TRACE => tracing::trace!(target: "video", "{}", line),
+                        AV_LOG_TRACE => tracing::trace!(target: "video", component, "{}", line),
                         _ => {}
                     };
                 }
             }
         }
     }
+
+    IN_CALLBACK.set(false);
+}
+
+/// Get the name of the component that logged a message, like `libx264` or
+/// `mov,mp4,m4a,3gp,3g2,mj2`.
+///
+/// # Arguments
+///
+/// * `avcl` - Struct that was passed to the log callback. If it is not null, it starts with a
+///   pointer to an `AVClass`.
+unsafe fn log_component<'a>(avcl: *mut std::ffi::c_void) -> Option<&'a str> {
+    if avcl.is_null() {
+        return None;
+    }
+    let class = *(avcl as *const *const AVClass);
+    if class.is_null() {
+        return None;
+    }
+    let name = (*class).item_name?(avcl);
+    if name.is_null() {
+        return None;
+    }
+    std::ffi::CStr::from_ptr(name).to_str().ok()
 }
 
 /// Helper function to filter out any lines that we don't want to log because they contaminate.
@@ -563,3 +2921,106 @@ struct RTPMuxContext {
     pub cur_timestamp: u32,
     pub max_payload_size: std::ffi::c_int,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Panicking;
+
+    impl std::io::Read for Panicking {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            panic!("read failed");
+        }
+    }
+
+    impl std::io::Seek for Panicking {
+        fn seek(&mut self, _pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            Err(std::io::ErrorKind::Unsupported.into())
+        }
+    }
+
+    #[test]
+    fn test_output_raw_invalid_format() {
+        assert!(output_raw("mp4\0").is_err());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_convert_frame_to_ndarray_invalid_format() {
+        let mut frame = Frame::new(Pixel::YUV420P, 64, 48);
+        assert!(matches!(
+            convert_frame_to_ndarray(&mut frame),
+            Err(crate::Error::InvalidFrameFormat { .. })
+        ));
+        assert!(matches!(
+            convert_frame_to_ndarray_u16(&mut frame),
+            Err(crate::Error::InvalidFrameFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_log_component() {
+        unsafe extern "C" fn item_name(_ctx: *mut std::ffi::c_void) -> *const std::ffi::c_char {
+            c"libx264".as_ptr()
+        }
+
+        let mut class: AVClass = unsafe { std::mem::zeroed() };
+        class.item_name = Some(item_name);
+        let mut context = &class as *const AVClass;
+        let avcl = &mut context as *mut *const AVClass as *mut std::ffi::c_void;
+        assert_eq!(unsafe { log_component(avcl) }, Some("libx264"));
+        assert_eq!(unsafe { log_component(std::ptr::null_mut()) }, None);
+    }
+
+    #[test]
+    fn test_io_read_and_seek_callbacks() {
+        let mut stream = IoStream::Read(Box::new(std::io::Cursor::new(vec![1_u8, 2, 3, 4])));
+        let opaque = &mut stream as *mut IoStream as *mut std::ffi::c_void;
+        let mut buf = [0_u8; 3];
+        unsafe {
+            assert_eq!(io_read_callback(opaque, buf.as_mut_ptr(), 3), 3);
+            assert_eq!(buf, [1, 2, 3]);
+            assert_eq!(io_seek_callback(opaque, 0, AVSEEK_SIZE), 4);
+            assert_eq!(io_read_callback(opaque, buf.as_mut_ptr(), 3), 1);
+            assert_eq!(buf[0], 4);
+            assert_eq!(io_read_callback(opaque, buf.as_mut_ptr(), 3), AVERROR_EOF);
+            assert_eq!(io_seek_callback(opaque, 1, 0), 1);
+            assert_eq!(io_seek_callback(opaque, -1, 2), 3);
+            assert!(io_seek_callback(opaque, -1, 0) < 0);
+        }
+    }
+
+    #[test]
+    fn test_io_write_callback() {
+        let cursor = std::io::Cursor::new(Vec::new());
+        let mut stream = IoStream::Write(Box::new(cursor));
+        let opaque = &mut stream as *mut IoStream as *mut std::ffi::c_void;
+        let mut buf = [5_u8, 6, 7];
+        unsafe {
+            assert_eq!(io_write_callback(opaque, buf.as_mut_ptr(), 3), 3);
+            assert_eq!(io_seek_callback(opaque, 0, AVSEEK_SIZE), 3);
+            assert_eq!(
+                io_read_callback(opaque, buf.as_mut_ptr(), 3),
+                AVERROR(ffmpeg::util::error::ENOSYS)
+            );
+        }
+    }
+
+    #[test]
+    fn test_io_callbacks_catch_panics() {
+        let mut stream = IoStream::Read(Box::new(Panicking));
+        let opaque = &mut stream as *mut IoStream as *mut std::ffi::c_void;
+        let mut buf = [0_u8; 1];
+        unsafe {
+            assert_eq!(
+                io_read_callback(opaque, buf.as_mut_ptr(), 1),
+                AVERROR_EXTERNAL
+            );
+            assert_eq!(
+                io_seek_callback(opaque, 0, 0),
+                AVERROR(ffmpeg::util::error::ENOSYS) as i64
+            );
+        }
+    }
+}
@@ -0,0 +1,373 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::ffi::AVPixelFormat;
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::ffi::{buffersink_properties, capture_log_errors};
+use crate::init;
+use crate::{PixelFormat, RawFrame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Describes the frames that go into a [`FilterGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterInput {
+    /// Width of the frames.
+    pub width: u32,
+    /// Height of the frames.
+    pub height: u32,
+    /// Pixel format of the frames.
+    pub pixel_format: PixelFormat,
+    /// Time base of the frame timestamps.
+    pub time_base: AvRational,
+    /// Frame rate of the frames, if it is known. Some filters (like `yadif` in field mode) use it
+    /// to compute the frame rate of their output.
+    pub frame_rate: Option<AvRational>,
+}
+
+impl FilterInput {
+    /// Get the arguments of the `buffer` filter that feeds the frames into the graph.
+    fn buffer_args(&self) -> String {
+        let mut args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect=1/1",
+            self.width,
+            self.height,
+            AVPixelFormat::from(self.pixel_format) as i32,
+            self.time_base.numerator(),
+            self.time_base.denominator(),
+        );
+        if let Some(frame_rate) = self.frame_rate.filter(|rate| rate.numerator() > 0) {
+            args.push_str(&format!(
+                ":frame_rate={}/{}",
+                frame_rate.numerator(),
+                frame_rate.denominator()
+            ));
+        }
+        args
+    }
+}
+
+/// Geometric transform of frames, applied with a filter (see [`crate::Decoder::with_transform`] and
+/// [`crate::Encoder::with_transform`]). Rotations are clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    /// Mirror the frames horizontally.
+    HFlip,
+    /// Mirror the frames vertically.
+    VFlip,
+    /// Rotate the frames by 90 degrees, which swaps their width and height.
+    Rotate90,
+    /// Rotate the frames by 180 degrees.
+    Rotate180,
+    /// Rotate the frames by 270 degrees, which swaps their width and height.
+    Rotate270,
+}
+
+impl Transform {
+    /// Whether or not the transform swaps the width and height of the frames.
+    pub fn swaps_dimensions(&self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270)
+    }
+
+    /// Get the description of the filters that apply the transform.
+    pub(crate) fn filter_spec(&self) -> &'static str {
+        match self {
+            Transform::HFlip => "hflip",
+            Transform::VFlip => "vflip",
+            Transform::Rotate90 => "transpose=clock",
+            Transform::Rotate180 => "hflip,vflip",
+            Transform::Rotate270 => "transpose=cclock",
+        }
+    }
+}
+
+/// When to deinterlace decoded frames (see [`crate::Decoder::with_deinterlace`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Deinterlace {
+    /// Deinterlace the frames that the decoder flags as interlaced, and pass progressive frames
+    /// through as is.
+    Auto,
+    /// Deinterlace every frame, even frames that are not flagged as interlaced.
+    Always,
+    /// Do not deinterlace.
+    #[default]
+    Never,
+}
+
+/// How many frames deinterlacing produces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeinterlaceMode {
+    /// One progressive frame for every interlaced frame, at the same frame rate.
+    #[default]
+    Frame,
+    /// One progressive frame for every field ("bob"), which doubles the frame rate. This keeps
+    /// the motion of the fields smooth.
+    Field,
+}
+
+impl Deinterlace {
+    /// Get the description of the filter that deinterlaces, or [`None`] if frames are not
+    /// deinterlaced. The field order is taken from the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - How many frames to produce.
+    pub(crate) fn filter_spec(&self, mode: DeinterlaceMode) -> Option<String> {
+        let deint = match self {
+            Deinterlace::Auto => "interlaced",
+            Deinterlace::Always => "all",
+            Deinterlace::Never => return None,
+        };
+        let mode = match mode {
+            DeinterlaceMode::Frame => "send_frame",
+            DeinterlaceMode::Field => "send_field",
+        };
+        Some(format!("yadif=mode={mode}:parity=auto:deint={deint}"))
+    }
+}
+
+/// Append filters to a filter graph description.
+///
+/// # Arguments
+///
+/// * `spec` - Description of the filters so far, if any.
+/// * `filters` - Filters to apply after them.
+pub(crate) fn chain_filters(spec: Option<&str>, filters: &str) -> String {
+    match spec {
+        Some(spec) => format!("{spec},{filters}"),
+        None => filters.to_string(),
+    }
+}
+
+/// Chain of libavfilter filters, described like the `-vf` option on the command line.
+///
+/// Frames are pushed into the graph and pulled out of it. Filters do not necessarily produce a
+/// frame for every frame that goes in: `fps` drops and duplicates frames, and `yadif=1` produces
+/// two frames for every frame. Pull until the graph has no more frames after each push. Filters
+/// like `scale` and `fps` change the size and timing of the frames, which the output accessors
+/// reflect.
+///
+/// # Example
+///
+/// ```ignore
+/// let input = FilterInput {
+///     width: 1920,
+///     height: 1080,
+///     pixel_format: PixelFormat::YUV420P,
+///     time_base: Rational::new(1, 90000),
+///     frame_rate: Some(Rational::new(25, 1)),
+/// };
+/// let mut graph = FilterGraph::parse("yadif,scale=1280:720,fps=30", input).unwrap();
+/// assert_eq!(graph.output_size(), (1280, 720));
+///
+/// graph.push(frame).unwrap();
+/// while let Some(frame) = graph.pull().unwrap() {
+///     // Do something with the filtered frame...
+/// }
+/// ```
+pub struct FilterGraph {
+    graph: AvFilterGraph,
+    spec: String,
+    input: FilterInput,
+    output_size: (u32, u32),
+    output_format: PixelFormat,
+    output_time_base: AvRational,
+    output_frame_rate: Option<AvRational>,
+    flushed: bool,
+}
+
+impl FilterGraph {
+    /// Set up a filter graph from its description.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filters to apply, separated by commas, like `yadif,scale=1280:720,fps=30`.
+    /// * `input` - Frames that go into the graph.
+    ///
+    /// # Return value
+    ///
+    /// The filter graph, or [`Error::InvalidFilterGraph`] with the reason ffmpeg gives if the
+    /// description cannot be parsed or the filters cannot handle the input.
+    pub fn parse(spec: &str, input: FilterInput) -> Result<Self> {
+        init::initialize()?;
+
+        let filter = |name: &str| ffmpeg::filter::find(name).ok_or(AvError::FilterNotFound);
+        let mut graph = AvFilterGraph::new();
+        graph.add(&filter("buffer")?, "in", &input.buffer_args())?;
+        graph.add(&filter("buffersink")?, "out", "")?;
+
+        let (result, errors) = capture_log_errors(|| {
+            graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+            graph.validate()
+        });
+        if let Err(err) = result {
+            let reason = if errors.is_empty() {
+                format!("{spec}: {err}")
+            } else {
+                format!("{spec}: {} ({err})", errors.join("; "))
+            };
+            return Err(Error::InvalidFilterGraph(reason));
+        }
+
+        let (width, height, format, time_base, frame_rate) =
+            buffersink_properties(&mut graph.get("out").ok_or(AvError::FilterNotFound)?);
+
+        Ok(Self {
+            graph,
+            spec: spec.to_string(),
+            input,
+            output_size: (width, height),
+            output_format: format,
+            output_time_base: time_base,
+            output_frame_rate: Some(frame_rate).filter(|rate| rate.numerator() > 0),
+            flushed: false,
+        })
+    }
+
+    /// Get the description the graph was set up from.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// Get the description of the frames that go into the graph.
+    pub fn input(&self) -> &FilterInput {
+        &self.input
+    }
+
+    /// Get the size of the frames that come out of the graph: width and height.
+    pub fn output_size(&self) -> (u32, u32) {
+        self.output_size
+    }
+
+    /// Get the pixel format of the frames that come out of the graph.
+    pub fn output_format(&self) -> PixelFormat {
+        self.output_format
+    }
+
+    /// Get the time base of the timestamps of the frames that come out of the graph. Filters like
+    /// `fps` change the time base.
+    pub fn output_time_base(&self) -> AvRational {
+        self.output_time_base
+    }
+
+    /// Get the frame rate of the frames that come out of the graph, if it is known.
+    pub fn output_frame_rate(&self) -> Option<AvRational> {
+        self.output_frame_rate
+    }
+
+    /// Push a frame into the graph. The frame must match the input of the graph, and its
+    /// timestamp must be in the input time base.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to filter.
+    pub fn push(&mut self, frame: RawFrame) -> Result<()> {
+        self.graph
+            .get("in")
+            .ok_or(AvError::FilterNotFound)?
+            .source()
+            .add(&frame)?;
+        Ok(())
+    }
+
+    /// Signal that no more frames will be pushed, so that filters that hold frames back (like
+    /// `fps`) release them. Pull until the graph has no more frames afterwards.
+    pub fn flush(&mut self) -> Result<()> {
+        if !std::mem::replace(&mut self.flushed, true) {
+            self.graph
+                .get("in")
+                .ok_or(AvError::FilterNotFound)?
+                .source()
+                .flush()?;
+        }
+        Ok(())
+    }
+
+    /// Whether or not [`FilterGraph::flush`] has been called.
+    pub fn is_flushed(&self) -> bool {
+        self.flushed
+    }
+
+    /// Pull a filtered frame out of the graph. Its timestamp is in the output time base (see
+    /// [`FilterGraph::output_time_base`]).
+    ///
+    /// # Return value
+    ///
+    /// The frame, or [`None`] if the graph needs more frames first (or has been flushed
+    /// completely).
+    pub fn pull(&mut self) -> Result<Option<RawFrame>> {
+        let mut frame = RawFrame::empty();
+        match self
+            .graph
+            .get("out")
+            .ok_or(AvError::FilterNotFound)?
+            .sink()
+            .frame(&mut frame)
+        {
+            Ok(()) => Ok(Some(frame)),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(AvError::Eof) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+unsafe impl Send for FilterGraph {}
+unsafe impl Sync for FilterGraph {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform_filter_spec() {
+        assert_eq!(Transform::Rotate90.filter_spec(), "transpose=clock");
+        assert_eq!(Transform::Rotate270.filter_spec(), "transpose=cclock");
+        assert!(Transform::Rotate90.swaps_dimensions());
+        assert!(!Transform::Rotate180.swaps_dimensions());
+        assert_eq!(
+            chain_filters(Some("yadif"), Transform::HFlip.filter_spec()),
+            "yadif,hflip"
+        );
+        assert_eq!(chain_filters(None, "vflip"), "vflip");
+    }
+
+    #[test]
+    fn test_deinterlace_filter_spec() {
+        assert_eq!(
+            Deinterlace::Auto.filter_spec(DeinterlaceMode::Frame),
+            Some("yadif=mode=send_frame:parity=auto:deint=interlaced".to_string())
+        );
+        assert_eq!(
+            Deinterlace::Always.filter_spec(DeinterlaceMode::Field),
+            Some("yadif=mode=send_field:parity=auto:deint=all".to_string())
+        );
+        assert_eq!(Deinterlace::Never.filter_spec(DeinterlaceMode::Field), None);
+    }
+
+    #[test]
+    fn test_buffer_args() {
+        let input = FilterInput {
+            width: 1920,
+            height: 1080,
+            pixel_format: PixelFormat::YUV420P,
+            time_base: AvRational::new(1, 90000),
+            frame_rate: None,
+        };
+        assert_eq!(
+            input.buffer_args(),
+            "video_size=1920x1080:pix_fmt=0:time_base=1/90000:pixel_aspect=1/1"
+        );
+        assert_eq!(
+            FilterInput {
+                frame_rate: Some(AvRational::new(30000, 1001)),
+                ..input
+            }
+            .buffer_args(),
+            "video_size=1920x1080:pix_fmt=0:time_base=1/90000:pixel_aspect=1/1:frame_rate=30000/1001"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+
+use crate::interrupt::Interrupt;
+use crate::io::Reader;
+use crate::{Error, Locator};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Determines how a [`crate::Decoder`] (or [`Reader`]) that follows a growing file waits for more
+/// data (see [`crate::Decoder::follow`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FollowPolicy {
+    /// How long to wait before reading again after reaching the end of what was written so far.
+    pub poll_interval: Duration,
+    /// Treat the input as finished once no new data has come in for this long, or `None` to
+    /// follow until cancelled through [`crate::Decoder::interrupt_handle`].
+    pub inactivity_timeout: Option<Duration>,
+    /// Stop following after this long in total, after which reading fails with
+    /// [`Error::ReadTimeout`], or `None` to follow for as long as the input grows.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for FollowPolicy {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(100),
+            inactivity_timeout: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Keeps track of how long a reader has been following its input, and since when no new data has
+/// come in.
+#[derive(Debug, Clone)]
+pub(crate) struct Follow {
+    policy: FollowPolicy,
+    started: Instant,
+    last_data: Instant,
+}
+
+impl Follow {
+    /// Start following.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - How to wait for more data.
+    pub(crate) fn new(policy: FollowPolicy) -> Self {
+        let now = Instant::now();
+        Self {
+            policy,
+            started: now,
+            last_data: now,
+        }
+    }
+
+    /// Note that new data came in.
+    pub(crate) fn data_received(&mut self) {
+        self.last_data = Instant::now();
+    }
+
+    /// Whether or not no new data has come in for longer than the inactivity timeout, after which
+    /// the input is treated as finished.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.policy
+            .inactivity_timeout
+            .is_some_and(|timeout| self.last_data.elapsed() >= timeout)
+    }
+
+    /// Wait for the poll interval before reading again. Waiting ends early when the caller
+    /// cancels, which the next read reports.
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt state of the reader.
+    ///
+    /// # Return value
+    ///
+    /// [`Error::ReadTimeout`] if the overall timeout passed.
+    pub(crate) fn wait(&self, interrupt: &Interrupt) -> Result<()> {
+        /// Longest sleep between checks for cancellation.
+        const MAX_SLEEP: Duration = Duration::from_millis(50);
+
+        let wait_until = Instant::now() + self.policy.poll_interval;
+        let deadline = self.policy.timeout.map(|timeout| self.started + timeout);
+        loop {
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Err(Error::ReadTimeout);
+            }
+            if now >= wait_until || interrupt.is_cancelled() {
+                return Ok(());
+            }
+            let until = deadline.map_or(wait_until, |deadline| deadline.min(wait_until));
+            std::thread::sleep((until - now).min(MAX_SLEEP));
+        }
+    }
+}
+
+/// Check that the input of a reader can be followed while it grows. Plain MP4 files cannot: their
+/// index (the `moov` box) describes all frames up front, so frames that are written after it are
+/// never read. Fragmented MP4 files, MPEG-TS and most other formats can.
+///
+/// # Arguments
+///
+/// * `reader` - Reader to check.
+pub(crate) fn check_followable(reader: &Reader) -> Result<()> {
+    let (format, _) = reader.container_format();
+    if !format.split(',').any(|name| name == "mov" || name == "mp4") {
+        return Ok(());
+    }
+    // Only files can be checked, other sources are taken as they are.
+    let Some(Locator::Path(path)) = reader.source.as_ref() else {
+        return Ok(());
+    };
+
+    let fragmented = std::fs::File::open(path)
+        .and_then(|file| mp4_is_fragmented(std::io::BufReader::new(file)))
+        .map_err(|err| Error::NotFollowable(format!("cannot read {}: {err}", path.display())))?;
+    if fragmented {
+        Ok(())
+    } else {
+        Err(Error::NotFollowable(format!(
+            "{} is not a fragmented MP4 file, so frames written after its index are never read",
+            path.display()
+        )))
+    }
+}
+
+/// Whether or not an MP4 file is fragmented, which is when its `moov` box has an `mvex` box (which
+/// announces fragments), or when it has a fragment (a `moof` box).
+///
+/// # Arguments
+///
+/// * `file` - File to check, at the start.
+fn mp4_is_fragmented(mut file: impl Read + Seek) -> std::io::Result<bool> {
+    while let Some((kind, size)) = read_box_header(&mut file)? {
+        match &kind {
+            b"moof" | b"mvex" => return Ok(true),
+            // The children of `moov` are read in place, as if they were top-level boxes.
+            b"moov" => {}
+            _ => match size {
+                Some(size) => {
+                    file.seek(SeekFrom::Current(size as i64))?;
+                }
+                None => break,
+            },
+        }
+    }
+
+    Ok(false)
+}
+
+/// Read the header of an MP4 box.
+///
+/// # Arguments
+///
+/// * `file` - File to read from, at the start of a box.
+///
+/// # Return value
+///
+/// The type of the box and the size of its contents (or `None` if the box extends to the end of
+/// the file), or `None` at the end of the file.
+fn read_box_header(file: &mut impl Read) -> std::io::Result<Option<([u8; 4], Option<u64>)>> {
+    let mut header = [0; 8];
+    match file.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let kind = [header[4], header[5], header[6], header[7]];
+    let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+        0 => None,
+        1 => {
+            let mut large_size = [0; 8];
+            file.read_exact(&mut large_size)?;
+            Some(u64::from_be_bytes(large_size).saturating_sub(16))
+        }
+        size => Some((size as u64).saturating_sub(8)),
+    };
+
+    Ok(Some((kind, size)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    fn is_fragmented(boxes: &[Vec<u8>]) -> bool {
+        mp4_is_fragmented(std::io::Cursor::new(boxes.concat())).unwrap()
+    }
+
+    #[test]
+    fn test_mp4_is_fragmented() {
+        let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        let mvhd = mp4_box(b"mvhd", &[0; 16]);
+        let mvex = mp4_box(b"mvex", &mp4_box(b"trex", &[0; 8]));
+        let plain_moov = mp4_box(b"moov", &mvhd);
+        let fragmented_moov = mp4_box(b"moov", &[mvhd.clone(), mvex].concat());
+        let mdat = mp4_box(b"mdat", &[0; 64]);
+        let moof = mp4_box(b"moof", &mp4_box(b"mfhd", &[0; 8]));
+
+        // Index at the end or at the start.
+        assert!(!is_fragmented(&[
+            ftyp.clone(),
+            mdat.clone(),
+            plain_moov.clone()
+        ]));
+        assert!(!is_fragmented(&[ftyp.clone(), plain_moov, mdat.clone()]));
+        // Fragmented, before and after the first fragment was written.
+        assert!(is_fragmented(&[ftyp.clone(), fragmented_moov.clone()]));
+        assert!(is_fragmented(&[ftyp.clone(), fragmented_moov, moof, mdat]));
+        // A box that is still being written.
+        let mut partial = mp4_box(b"mdat", &[0; 64]);
+        partial.truncate(20);
+        assert!(!is_fragmented(&[ftyp, partial]));
+    }
+
+    #[test]
+    fn test_follow_inactivity() {
+        let interrupt = Interrupt::new(None);
+        let follow = Follow::new(FollowPolicy {
+            poll_interval: Duration::from_millis(1),
+            ..Default::default()
+        });
+        assert!(!follow.is_idle());
+        follow.wait(&interrupt).unwrap();
+
+        let mut follow = Follow::new(FollowPolicy {
+            poll_interval: Duration::ZERO,
+            inactivity_timeout: Some(Duration::ZERO),
+            timeout: None,
+        });
+        assert!(follow.is_idle());
+        follow.policy.inactivity_timeout = Some(Duration::from_secs(60));
+        follow.data_received();
+        assert!(!follow.is_idle());
+
+        let follow = Follow::new(FollowPolicy {
+            poll_interval: Duration::from_secs(60),
+            inactivity_timeout: None,
+            timeout: Some(Duration::ZERO),
+        });
+        assert!(matches!(follow.wait(&interrupt), Err(Error::ReadTimeout)));
+    }
+}
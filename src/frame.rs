@@ -1,11 +1,130 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use ffmpeg::util::color::{
+    Primaries as AvColorPrimaries, Range as AvColorRange, Space as AvColorSpace,
+    TransferCharacteristic as AvColorTransferCharacteristic,
+};
 use ffmpeg::util::format::Pixel as AvPixel;
 use ffmpeg::util::frame::Video as AvFrame;
 
+#[cfg(feature = "ndarray")]
+use ndarray::{Array2, ArrayView2, ArrayView3, ShapeBuilder};
+
+#[cfg(feature = "ndarray")]
+use crate::{ffi::frame_plane_layout, Error};
+
 /// Re-export internal `AvPixel` as `PixelFormat` for callers.
 pub type PixelFormat = AvPixel;
 
+/// Re-export internal `AvColorPrimaries` as `ColorPrimaries` for callers.
+pub type ColorPrimaries = AvColorPrimaries;
+
+/// Re-export internal `AvColorTransferCharacteristic` as `ColorTransferCharacteristic` for callers.
+pub type ColorTransferCharacteristic = AvColorTransferCharacteristic;
+
+/// Re-export internal `AvColorSpace` as `ColorSpace` for callers.
+pub type ColorSpace = AvColorSpace;
+
+/// Re-export internal `AvColorRange` as `ColorRange` for callers.
+pub type ColorRange = AvColorRange;
+
+/// Color properties that an encoder tags its output with (see
+/// [`crate::EncoderSettings::with_color_spec`]). The tags tell players how to interpret the
+/// values of the frames. Frames that already have the pixel format and size of the encoder are
+/// not converted, while frames that the encoder converts (like RGB frames) are converted to the
+/// tagged color space (see [`crate::ScaleColorSpaces`]).
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = EncoderSettings::for_hevc_custom(
+///     3840,
+///     2160,
+///     PixelFormat::YUV420P10LE,
+///     Options::new_hevc(),
+/// )
+/// .with_color_spec(ColorSpec::bt2020_pq());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorSpec {
+    /// Color primaries.
+    pub primaries: ColorPrimaries,
+    /// Color transfer characteristic.
+    pub transfer_characteristic: ColorTransferCharacteristic,
+    /// Color space (the matrix between RGB and YUV).
+    pub space: ColorSpace,
+}
+
+impl ColorSpec {
+    /// BT.2020 primaries with the PQ (SMPTE ST 2084) transfer characteristic, as used by HDR10.
+    pub fn bt2020_pq() -> Self {
+        Self {
+            primaries: ColorPrimaries::BT2020,
+            transfer_characteristic: ColorTransferCharacteristic::SMPTE2084,
+            space: ColorSpace::BT2020NCL,
+        }
+    }
+
+    /// BT.2020 primaries with the HLG (ARIB STD-B67) transfer characteristic.
+    pub fn bt2020_hlg() -> Self {
+        Self {
+            primaries: ColorPrimaries::BT2020,
+            transfer_characteristic: ColorTransferCharacteristic::ARIB_STD_B67,
+            space: ColorSpace::BT2020NCL,
+        }
+    }
+
+    /// Whether or not the transfer characteristic is one for HDR content (PQ or HLG).
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.transfer_characteristic,
+            ColorTransferCharacteristic::SMPTE2084 | ColorTransferCharacteristic::ARIB_STD_B67
+        )
+    }
+}
+
+/// Serialized form of [`ColorSpec`], which names the color properties the way ffmpeg does (like
+/// `bt2020`), so that it does not depend on the version of ffmpeg. Unspecified properties have no
+/// name.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ColorSpecRepr {
+    primaries: Option<String>,
+    transfer_characteristic: Option<String>,
+    space: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ColorSpec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ColorSpecRepr {
+            primaries: self.primaries.name().map(str::to_string),
+            transfer_characteristic: self.transfer_characteristic.name().map(str::to_string),
+            space: self.space.name().map(str::to_string),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ColorSpec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ColorSpecRepr::deserialize(deserializer)?;
+        let (primaries, transfer_characteristic, space) = crate::ffi::color_properties_from_names(
+            repr.primaries.as_deref().unwrap_or("unknown"),
+            repr.transfer_characteristic.as_deref().unwrap_or("unknown"),
+            repr.space.as_deref().unwrap_or("unknown"),
+        )
+        .ok_or_else(|| serde::de::Error::custom("unknown color properties"))?;
+
+        Ok(Self {
+            primaries,
+            transfer_characteristic,
+            space,
+        })
+    }
+}
+
 /// Re-export internal `AvFrame` for caller to use.
 pub type RawFrame = AvFrame;
 
@@ -13,5 +132,667 @@ pub type RawFrame = AvFrame;
 #[cfg(feature = "ndarray")]
 pub type Frame = crate::ffi::FrameArray;
 
+/// Re-export high bit depth frame type as ndarray.
+#[cfg(feature = "ndarray")]
+pub type FrameU16 = crate::ffi::FrameArrayU16;
+
+/// Represents a frame with separate Y, U and V planes, with 4:2:0 chroma subsampling (like
+/// YUV420p). The chroma planes have half the width and height of the luma plane, rounded up.
+///
+/// # Example
+///
+/// ```ignore
+/// let (ts, frame) = decoder.decode_yuv().unwrap();
+/// let luma = frame.y.view();
+/// // Run a kernel on the planes...
+/// encoder.encode_yuv(&frame, &ts).unwrap();
+/// ```
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YuvFrame {
+    /// Luma plane with dimensions `(H, W)`.
+    pub y: Array2<u8>,
+    /// Chroma (blue difference) plane with dimensions `((H + 1) / 2, (W + 1) / 2)`.
+    pub u: Array2<u8>,
+    /// Chroma (red difference) plane with dimensions `((H + 1) / 2, (W + 1) / 2)`.
+    pub v: Array2<u8>,
+}
+
+#[cfg(feature = "ndarray")]
+impl YuvFrame {
+    /// Create a frame from its planes.
+    ///
+    /// # Arguments
+    ///
+    /// * `y` - Luma plane with dimensions `(H, W)`.
+    /// * `u` - Blue difference chroma plane with dimensions `((H + 1) / 2, (W + 1) / 2)`.
+    /// * `v` - Red difference chroma plane with dimensions `((H + 1) / 2, (W + 1) / 2)`.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or [`Error::InvalidFrameFormat`] if the chroma planes do not have the right
+    /// dimensions for the luma plane.
+    pub fn new(y: Array2<u8>, u: Array2<u8>, v: Array2<u8>) -> Result<Self, Error> {
+        let frame = Self { y, u, v };
+        frame.validate()?;
+        Ok(frame)
+    }
+
+    /// Create a frame with all planes filled with zeros.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the frame.
+    /// * `height` - Height of the frame.
+    pub fn zeros(width: u32, height: u32) -> Self {
+        let (chroma_width, chroma_height) = Self::chroma_size(width, height);
+        Self {
+            y: Array2::zeros((height as usize, width as usize)),
+            u: Array2::zeros((chroma_height as usize, chroma_width as usize)),
+            v: Array2::zeros((chroma_height as usize, chroma_width as usize)),
+        }
+    }
+
+    /// Get the frame size (the size of the luma plane): width and height.
+    pub fn size(&self) -> (u32, u32) {
+        let (height, width) = self.y.dim();
+        (width as u32, height as u32)
+    }
+
+    /// Get the size of the chroma planes of a frame: half the width and height, rounded up.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the frame.
+    /// * `height` - Height of the frame.
+    pub fn chroma_size(width: u32, height: u32) -> (u32, u32) {
+        (width.div_ceil(2), height.div_ceil(2))
+    }
+
+    /// Check that the chroma planes have the right dimensions for the luma plane.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let (width, height) = self.size();
+        let (chroma_width, chroma_height) = Self::chroma_size(width, height);
+        let chroma_dim = (chroma_height as usize, chroma_width as usize);
+        if width > 0 && height > 0 && self.u.dim() == chroma_dim && self.v.dim() == chroma_dim {
+            Ok(())
+        } else {
+            Err(Error::invalid_frame_format(
+                format!("chroma planes of {chroma_width}x{chroma_height}"),
+                format!(
+                    "chroma planes of {}x{} and {}x{}",
+                    self.u.ncols(),
+                    self.u.nrows(),
+                    self.v.ncols(),
+                    self.v.nrows()
+                ),
+            ))
+        }
+    }
+
+    /// Copy the planes of a YUV420p frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame in YUV420p (or full range YUVJ420p) format.
+    pub(crate) fn from_raw_frame(frame: &RawFrame) -> Result<Self, Error> {
+        if !matches!(frame.format(), AvPixel::YUV420P | AvPixel::YUVJ420P) {
+            return Err(Error::invalid_frame_format(
+                "YUV420P or YUVJ420P",
+                format!("{:?}", frame.format()),
+            ));
+        }
+
+        let plane = |index: usize| {
+            let width = frame.plane_width(index) as usize;
+            let height = frame.plane_height(index) as usize;
+            let data = pack_rows(frame.data(index), frame.stride(index), width, height);
+            Array2::from_shape_vec((height, width), data).map_err(|err| {
+                Error::invalid_frame_format(format!("plane of {width}x{height}"), err)
+            })
+        };
+
+        Self::new(plane(0)?, plane(1)?, plane(2)?)
+    }
+
+    /// Copy the planes into a new YUV420p frame.
+    pub(crate) fn to_raw_frame(&self) -> Result<RawFrame, Error> {
+        self.validate()?;
+
+        let (width, height) = self.size();
+        let mut frame = RawFrame::new(AvPixel::YUV420P, width, height);
+        for (index, plane) in [&self.y, &self.u, &self.v].into_iter().enumerate() {
+            let stride = frame.stride(index);
+            // Rows are copied one by one, since the planes may not be in standard layout.
+            for (row, frame_row) in plane
+                .rows()
+                .into_iter()
+                .zip(frame.data_mut(index).chunks_mut(stride))
+            {
+                for (value, frame_value) in row.iter().zip(frame_row) {
+                    *frame_value = *value;
+                }
+            }
+        }
+
+        Ok(frame)
+    }
+}
+
 /// Default frame pixel format.
 pub(crate) const FRAME_PIXEL_FORMAT: AvPixel = AvPixel::RGB24;
+
+/// High bit depth frame pixel format (16 bits per component, native endianness).
+pub(crate) const FRAME_PIXEL_FORMAT_U16: AvPixel = AvPixel::RGB48;
+
+/// Re-export floating point frame type as ndarray. Depending on the [`FrameLayout`] it was
+/// produced with, it has dimensions `(H, W, C)` or `(C, H, W)`.
+#[cfg(feature = "ndarray")]
+pub type Frame32 = crate::ffi::FrameArrayF32;
+
+/// Order of the dimensions of a [`Frame32`].
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameLayout {
+    /// Interleaved, with dimensions `(H, W, C)`, like [`Frame`].
+    #[default]
+    Hwc,
+    /// Planar, with dimensions `(C, H, W)`, as most machine learning models expect.
+    Chw,
+}
+
+/// Describes how 8-bit RGB components are converted to floating point values and back.
+///
+/// Each component is first scaled to the range `[0, 1]`, and then normalized with the mean and
+/// standard deviation of its channel: `(value / 255 - mean) / std`.
+///
+/// # Example
+///
+/// ```ignore
+/// let normalization = Normalization::new()
+///     .with_layout(FrameLayout::Chw)
+///     .with_mean_and_std([0.5, 0.5, 0.5], [0.5, 0.5, 0.5]);
+/// let (ts, frame) = decoder.decode_f32(&normalization).unwrap();
+/// ```
+#[cfg(feature = "ndarray")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normalization {
+    layout: FrameLayout,
+    mean: [f32; 3],
+    std: [f32; 3],
+}
+
+#[cfg(feature = "ndarray")]
+impl Normalization {
+    /// Create a normalization that only scales components to the range `[0, 1]`, with the `HWC`
+    /// layout.
+    pub fn new() -> Self {
+        Self {
+            layout: FrameLayout::Hwc,
+            mean: [0.0; 3],
+            std: [1.0; 3],
+        }
+    }
+
+    /// Create the normalization that is commonly used for models trained on ImageNet, with the
+    /// `CHW` layout.
+    pub fn imagenet() -> Self {
+        Self::new()
+            .with_layout(FrameLayout::Chw)
+            .with_mean_and_std([0.485, 0.456, 0.406], [0.229, 0.224, 0.225])
+    }
+
+    /// Set the layout of the floating point frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - Order of the dimensions.
+    pub fn with_layout(mut self, layout: FrameLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Set the mean and standard deviation of each channel (in RGB order), which apply to
+    /// components after scaling them to the range `[0, 1]`.
+    ///
+    /// # Arguments
+    ///
+    /// * `mean` - Mean of each channel.
+    /// * `std` - Standard deviation of each channel.
+    pub fn with_mean_and_std(mut self, mean: [f32; 3], std: [f32; 3]) -> Self {
+        self.mean = mean;
+        self.std = std;
+        self
+    }
+
+    /// Layout of the floating point frames.
+    pub fn layout(&self) -> FrameLayout {
+        self.layout
+    }
+
+    /// Width and height of a floating point frame with this layout, or `None` if it does not have
+    /// three channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Floating point frame.
+    pub(crate) fn frame_size(&self, frame: &Frame32) -> Option<(usize, usize)> {
+        match (self.layout, frame.dim()) {
+            (FrameLayout::Hwc, (height, width, 3)) => Some((width, height)),
+            (FrameLayout::Chw, (3, height, width)) => Some((width, height)),
+            _ => None,
+        }
+    }
+
+    /// Precompute the floating point value of every possible component, so that converting a frame
+    /// only takes a lookup per component.
+    fn lookup_table(&self) -> [[f32; 256]; 3] {
+        let mut table = [[0.0; 256]; 3];
+        for (channel, values) in table.iter_mut().enumerate() {
+            for (component, value) in values.iter_mut().enumerate() {
+                *value = (component as f32 / 255.0 - self.mean[channel]) / self.std[channel];
+            }
+        }
+        table
+    }
+
+    /// Convert a floating point value back to a component, clamping it to the valid range.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Normalized value.
+    /// * `channel` - Channel the value belongs to.
+    fn denormalize(&self, value: f32, channel: usize) -> u8 {
+        ((value * self.std[channel] + self.mean[channel]) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl Default for Normalization {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Convert the packed RGB24 data of a frame to a floating point frame, normalizing each component
+/// on the way.
+///
+/// # Arguments
+///
+/// * `data` - RGB24 data of the frame.
+/// * `stride` - Number of bytes per row in `data`, including padding.
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+/// * `normalization` - How to convert components.
+#[cfg(feature = "ndarray")]
+pub(crate) fn convert_rgb24_to_ndarray_f32(
+    data: &[u8],
+    stride: usize,
+    width: usize,
+    height: usize,
+    normalization: &Normalization,
+) -> Frame32 {
+    let table = normalization.lookup_table();
+    let plane_len = width * height;
+    // Every value is written below, so the buffer is filled directly instead of through
+    // intermediate arrays.
+    let mut values = vec![0.0; plane_len * 3];
+
+    for (y, row) in data.chunks(stride).take(height).enumerate() {
+        for (x, pixel) in row[..width * 3].chunks_exact(3).enumerate() {
+            for (channel, component) in pixel.iter().enumerate() {
+                let index = match normalization.layout {
+                    FrameLayout::Hwc => (y * width + x) * 3 + channel,
+                    FrameLayout::Chw => channel * plane_len + y * width + x,
+                };
+                values[index] = table[channel][*component as usize];
+            }
+        }
+    }
+
+    let shape = match normalization.layout {
+        FrameLayout::Hwc => (height, width, 3),
+        FrameLayout::Chw => (3, height, width),
+    };
+    Frame32::from_shape_vec(shape, values).expect("frame shape matches number of values")
+}
+
+/// Convert a floating point frame to packed RGB24 data, clamping values that fall outside the
+/// range of a component.
+///
+/// # Arguments
+///
+/// * `frame` - Floating point frame. Its dimensions must match `width` and `height`.
+/// * `normalization` - How the components were converted.
+/// * `data` - RGB24 data to write to.
+/// * `stride` - Number of bytes per row in `data`, including padding.
+#[cfg(feature = "ndarray")]
+pub(crate) fn convert_ndarray_f32_to_rgb24(
+    frame: &Frame32,
+    normalization: &Normalization,
+    data: &mut [u8],
+    stride: usize,
+) {
+    for ((y, x, channel), value) in frame.indexed_iter() {
+        let (y, x, channel) = match normalization.layout {
+            FrameLayout::Hwc => (y, x, channel),
+            // The dimensions are `(C, H, W)`.
+            FrameLayout::Chw => (x, channel, y),
+        };
+        data[y * stride + x * 3 + channel] = normalization.denormalize(*value, channel);
+    }
+}
+
+/// Borrowed `ndarray` views over the data of a [`RawFrame`], for inspecting frames without
+/// copying them.
+///
+/// The views borrow the frame, so the frame (and its data) cannot be changed or freed while a
+/// view is alive. Frames may have padding at the end of each row: the views leave out the padding
+/// columns, even though the rows in memory include them.
+///
+/// # Example
+///
+/// ```ignore
+/// let frame = decoder.decode_raw().unwrap();
+/// let luma = frame.plane_view(0).unwrap();
+/// let brightness = luma.iter().map(|value| *value as f64).sum::<f64>() / luma.len() as f64;
+/// ```
+#[cfg(feature = "ndarray")]
+pub trait RawFrameExt {
+    /// Get a view over a plane of the frame, with dimensions `(rows, bytes per row)`. For packed
+    /// formats (like RGB24) a row holds all components of each pixel, and for planar formats (like
+    /// YUV420p) it holds the components of that plane.
+    ///
+    /// # Arguments
+    ///
+    /// * `plane` - Index of the plane.
+    ///
+    /// # Return value
+    ///
+    /// The view, or [`Error::InvalidFrameFormat`] if the frame has no such plane or the plane
+    /// cannot be viewed (like planes with rows stored bottom to top).
+    fn plane_view(&self, plane: usize) -> Result<ArrayView2<'_, u8>, Error>;
+
+    /// Get a view over a frame with a packed pixel format, with dimensions `(H, W, C)` like
+    /// [`Frame`]. Supported formats are RGB24 and BGR24 (three channels), RGBA, BGRA, ARGB and
+    /// ABGR (four channels) and GRAY8 (one channel).
+    ///
+    /// # Return value
+    ///
+    /// The view, or [`Error::InvalidFrameFormat`] if the pixel format is not supported.
+    fn view_hwc(&self) -> Result<ArrayView3<'_, u8>, Error>;
+}
+
+#[cfg(feature = "ndarray")]
+impl RawFrameExt for RawFrame {
+    fn plane_view(&self, plane: usize) -> Result<ArrayView2<'_, u8>, Error> {
+        let (row_len, stride) = frame_plane_layout(self, plane).ok_or_else(|| {
+            Error::invalid_frame_format(
+                format!("frame with plane {plane}"),
+                format!("{:?} frame", self.format()),
+            )
+        })?;
+        let rows = self.plane_height(plane) as usize;
+
+        ArrayView2::from_shape((rows, row_len).strides((stride, 1)), self.data(plane)).map_err(
+            |err| Error::invalid_frame_format(format!("plane of {row_len}x{rows} bytes"), err),
+        )
+    }
+
+    fn view_hwc(&self) -> Result<ArrayView3<'_, u8>, Error> {
+        let channels = match self.format() {
+            AvPixel::RGB24 | AvPixel::BGR24 => 3,
+            AvPixel::RGBA | AvPixel::BGRA | AvPixel::ARGB | AvPixel::ABGR => 4,
+            AvPixel::GRAY8 => 1,
+            format => {
+                return Err(Error::invalid_frame_format(
+                    "packed RGB or gray frame",
+                    format!("{format:?} frame"),
+                ))
+            }
+        };
+        let (_, stride) = frame_plane_layout(self, 0).ok_or_else(|| {
+            Error::invalid_frame_format("frame with data", format!("{:?} frame", self.format()))
+        })?;
+        let (width, height) = (self.width() as usize, self.height() as usize);
+
+        ArrayView3::from_shape(
+            (height, width, channels).strides((stride, channels, 1)),
+            self.data(0),
+        )
+        .map_err(|err| {
+            Error::invalid_frame_format(format!("frame of {width}x{height}x{channels}"), err)
+        })
+    }
+}
+
+/// Copy the rows of a frame plane into a tightly packed buffer, like the buffer of an image.
+///
+/// # Arguments
+///
+/// * `data` - Plane data of the frame.
+/// * `stride` - Number of bytes per row in `data`, including padding.
+/// * `row_len` - Number of bytes per row without padding.
+/// * `height` - Number of rows.
+#[cfg(any(feature = "ndarray", feature = "image"))]
+pub(crate) fn pack_rows(data: &[u8], stride: usize, row_len: usize, height: usize) -> Vec<u8> {
+    if stride == row_len {
+        return data[..row_len * height].to_vec();
+    }
+
+    let mut packed = Vec::with_capacity(row_len * height);
+    for row in data.chunks(stride).take(height) {
+        packed.extend_from_slice(&row[..row_len]);
+    }
+    packed
+}
+
+/// Copy a tightly packed buffer, like the buffer of an image, into the rows of a frame plane.
+///
+/// # Arguments
+///
+/// * `packed` - Tightly packed rows.
+/// * `row_len` - Number of bytes per row in `packed`.
+/// * `data` - Plane data of the frame to copy to.
+/// * `stride` - Number of bytes per row in `data`, including padding.
+#[cfg(feature = "image")]
+pub(crate) fn unpack_rows(packed: &[u8], row_len: usize, data: &mut [u8], stride: usize) {
+    for (row, packed_row) in data.chunks_mut(stride).zip(packed.chunks_exact(row_len)) {
+        row[..row_len].copy_from_slice(packed_row);
+    }
+}
+
+/// Serializes pixel formats by their ffmpeg name (like `yuv420p`), which stays the same across
+/// versions of ffmpeg, unlike the number of the format. Use with `#[serde(with = "...")]`.
+#[cfg(feature = "serde")]
+pub(crate) mod pixel_format_name {
+    use super::AvPixel;
+
+    pub(crate) fn serialize<S: serde::Serializer>(
+        pixel_format: &AvPixel,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let descriptor = pixel_format
+            .descriptor()
+            .ok_or_else(|| serde::ser::Error::custom("pixel format has no name"))?;
+        serializer.serialize_str(descriptor.name())
+    }
+
+    pub(crate) fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<AvPixel, D::Error> {
+        let name = <String as serde::Deserialize>::deserialize(deserializer)?;
+        name.parse()
+            .map_err(|_| serde::de::Error::custom(format!("unknown pixel format: {name}")))
+    }
+}
+
+#[cfg(all(test, any(feature = "ndarray", feature = "image")))]
+mod tests {
+    use super::*;
+
+    /// Frame of 2 by 2 pixels, with 2 bytes of padding per row.
+    #[cfg(feature = "ndarray")]
+    const DATA: [u8; 16] = [
+        0, 51, 102, 153, 204, 255, 0, 0, //
+        255, 0, 0, 0, 255, 0, 0, 0,
+    ];
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_convert_rgb24_to_ndarray_f32_layouts() {
+        let hwc = convert_rgb24_to_ndarray_f32(&DATA, 8, 2, 2, &Normalization::new());
+        assert_eq!(hwc.dim(), (2, 2, 3));
+        assert_eq!(hwc[[0, 0, 1]], 0.2);
+        assert_eq!(hwc[[0, 1, 2]], 1.0);
+        assert_eq!(hwc[[1, 0, 0]], 1.0);
+
+        let chw = convert_rgb24_to_ndarray_f32(
+            &DATA,
+            8,
+            2,
+            2,
+            &Normalization::new().with_layout(FrameLayout::Chw),
+        );
+        assert_eq!(chw.dim(), (3, 2, 2));
+        assert_eq!(chw, hwc.permuted_axes([2, 0, 1]));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_convert_rgb24_to_ndarray_f32_normalizes() {
+        let normalization = Normalization::new().with_mean_and_std([0.5; 3], [0.5; 3]);
+        let frame = convert_rgb24_to_ndarray_f32(&DATA, 8, 2, 2, &normalization);
+        assert_eq!(frame[[0, 0, 0]], -1.0);
+        assert_eq!(frame[[0, 1, 2]], 1.0);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_convert_ndarray_f32_to_rgb24_round_trip() {
+        for normalization in [Normalization::new(), Normalization::imagenet()] {
+            let frame = convert_rgb24_to_ndarray_f32(&DATA, 8, 2, 2, &normalization);
+            assert_eq!(normalization.frame_size(&frame), Some((2, 2)));
+
+            let mut data = [0; 16];
+            convert_ndarray_f32_to_rgb24(&frame, &normalization, &mut data, 8);
+            for row in 0..2 {
+                assert_eq!(data[row * 8..row * 8 + 6], DATA[row * 8..row * 8 + 6]);
+            }
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_convert_ndarray_f32_to_rgb24_clamps() {
+        let frame = Frame32::from_shape_vec((1, 1, 3), vec![-0.5, 0.5, 1.5]).unwrap();
+        let mut data = [0; 3];
+        convert_ndarray_f32_to_rgb24(&frame, &Normalization::new(), &mut data, 3);
+        assert_eq!(data, [0, 128, 255]);
+    }
+
+    #[test]
+    fn test_pack_rows() {
+        // Two rows of one RGB pixel, with one byte of padding per row.
+        let data = [1, 2, 3, 0, 4, 5, 6, 0];
+        assert_eq!(pack_rows(&data, 4, 3, 2), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(pack_rows(&data, 4, 3, 1), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_raw_frame_views() {
+        let mut frame = RawFrame::new(AvPixel::YUV420P, 30, 20);
+        for (plane, value) in [(0, 16), (1, 128), (2, 240)] {
+            frame.data_mut(plane).fill(value);
+        }
+        // The rows are padded to an aligned line size.
+        assert!(frame.stride(0) > 30);
+
+        let luma = frame.plane_view(0).unwrap();
+        assert_eq!(luma.dim(), (20, 30));
+        assert!(luma.iter().all(|value| *value == 16));
+        let chroma = frame.plane_view(2).unwrap();
+        assert_eq!(chroma.dim(), (10, 15));
+        assert!(chroma.iter().all(|value| *value == 240));
+        assert!(frame.plane_view(3).is_err());
+        assert!(frame.view_hwc().is_err());
+
+        let mut frame = RawFrame::new(AvPixel::RGB24, 30, 20);
+        let stride = frame.stride(0);
+        frame.data_mut(0)[stride + 3 * 2 + 1] = 42;
+        let view = frame.view_hwc().unwrap();
+        assert_eq!(view.dim(), (20, 30, 3));
+        assert_eq!(view[[1, 2, 1]], 42);
+        assert_eq!(view.iter().filter(|value| **value == 42).count(), 1);
+        assert_eq!(frame.plane_view(0).unwrap().dim(), (20, 90));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_yuv_frame_chroma_size() {
+        assert_eq!(YuvFrame::chroma_size(1920, 1080), (960, 540));
+        assert_eq!(YuvFrame::chroma_size(1920, 817), (960, 409));
+        assert_eq!(YuvFrame::chroma_size(1, 1), (1, 1));
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_yuv_frame_new_validates_planes() {
+        let frame = YuvFrame::zeros(1920, 817);
+        assert_eq!(frame.size(), (1920, 817));
+        assert_eq!(frame.u.dim(), (409, 960));
+        assert!(YuvFrame::new(frame.y.clone(), frame.u.clone(), frame.v.clone()).is_ok());
+
+        // Chroma planes rounded down instead of up.
+        assert!(matches!(
+            YuvFrame::new(
+                frame.y.clone(),
+                Array2::zeros((408, 960)),
+                Array2::zeros((408, 960))
+            ),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+        // Mismatched chroma planes.
+        assert!(YuvFrame::new(frame.y, frame.u, Array2::zeros((409, 959))).is_err());
+        assert!(YuvFrame::new(
+            Array2::zeros((0, 0)),
+            Array2::zeros((0, 0)),
+            Array2::zeros((0, 0))
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_yuv_frame_raw_frame_round_trip() {
+        let mut frame = YuvFrame::zeros(1920, 817);
+        frame.y[[816, 1919]] = 200;
+        frame.u[[408, 959]] = 100;
+        frame.v[[0, 0]] = 50;
+        // A transposed view is not in standard layout.
+        frame.u = frame.u.t().to_owned().reversed_axes();
+
+        let raw_frame = frame.to_raw_frame().unwrap();
+        assert_eq!(raw_frame.format(), AvPixel::YUV420P);
+        assert_eq!((raw_frame.width(), raw_frame.height()), (1920, 817));
+        assert_eq!(raw_frame.plane_height(1), 409);
+        assert_eq!(YuvFrame::from_raw_frame(&raw_frame).unwrap(), frame);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_pack_and_unpack_rows() {
+        // Two rows of two RGB pixels, with two bytes of padding per row.
+        let data = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0];
+        let packed = pack_rows(&data, 8, 6, 2);
+        assert_eq!(packed, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(pack_rows(&packed, 6, 6, 2), packed);
+
+        let mut unpacked = [0; 16];
+        unpack_rows(&packed, 6, &mut unpacked, 8);
+        assert_eq!(unpacked, data);
+    }
+}
@@ -0,0 +1,205 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Converts a stream of frames to a constant frame rate, by duplicating and dropping frames (like
+/// the `fps` filter).
+///
+/// Output frames sit on a grid of slots of `1 / frame_rate` seconds, and each slot gets the input
+/// frame with the timestamp closest to it. Input frames that are not the closest to any slot are
+/// dropped, and frames that are the closest to several slots are duplicated. Slots are computed
+/// from the timestamp of each frame, not by adding up frame durations, so variable frame rate
+/// input does not drift, however long it is. The grid starts at the slot of the first frame, so
+/// the output starts at the same time as the input.
+pub(crate) struct FrameRateConverter<F> {
+    frame_rate: f64,
+    time_base: AvRational,
+    /// Frame that waits for the next frame, which decides how many slots it fills, along with its
+    /// position on the grid (in slots).
+    pending: Option<(F, f64)>,
+    /// Next slot to fill.
+    next_slot: Option<i64>,
+    /// Duration of the last input frame, in slots.
+    last_duration: Option<f64>,
+}
+
+impl<F: Clone> FrameRateConverter<F> {
+    /// Create a frame rate converter.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frames per second of the output.
+    /// * `time_base` - Time base of the input and output timestamps.
+    pub(crate) fn new(frame_rate: f64, time_base: AvRational) -> Result<Self> {
+        if !(frame_rate.is_finite() && frame_rate > 0.0) {
+            return Err(Error::InvalidOptions(format!(
+                "invalid output frame rate: {frame_rate}"
+            )));
+        }
+
+        Ok(Self {
+            frame_rate,
+            time_base,
+            pending: None,
+            next_slot: None,
+            last_duration: None,
+        })
+    }
+
+    /// Pass a frame through the converter.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Input frame.
+    /// * `pts` - Timestamp of the frame. Frames without a timestamp are taken to be one slot after
+    ///   the previous frame.
+    ///
+    /// # Return value
+    ///
+    /// The output frames that are complete, along with their timestamps, in order.
+    pub(crate) fn push(&mut self, frame: F, pts: Option<i64>) -> Vec<(F, i64)> {
+        let Some((pending, pending_position)) = self.pending.take() else {
+            let position = pts.map(|pts| self.position_of(pts)).unwrap_or(0.0);
+            self.next_slot = Some(position.round() as i64);
+            self.pending = Some((frame, position));
+            return Vec::new();
+        };
+
+        // Timestamps that go back are moved up to the previous frame, so that the output never
+        // goes back.
+        let position = match pts {
+            Some(pts) => self.position_of(pts).max(pending_position),
+            None => pending_position + 1.0,
+        };
+        if position > pending_position {
+            self.last_duration = Some(position - pending_position);
+        }
+        self.pending = Some((frame, position));
+
+        // The pending frame is the closest to the slots up to halfway to this frame.
+        let end_slot = ((pending_position + position) / 2.0).ceil() as i64;
+        self.fill(pending, end_slot)
+    }
+
+    /// Flush the last frame. It fills the slots up to the end of the input, which is one frame
+    /// duration (the difference of the last two timestamps) after its timestamp.
+    ///
+    /// # Return value
+    ///
+    /// The remaining output frames, along with their timestamps, in order.
+    pub(crate) fn flush(&mut self) -> Vec<(F, i64)> {
+        let Some((frame, position)) = self.pending.take() else {
+            return Vec::new();
+        };
+        let end_slot = (position + self.last_duration.unwrap_or(1.0)).round() as i64;
+
+        self.fill(frame, end_slot)
+    }
+
+    /// Fill the slots from the next slot up to (not including) an end slot with a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to fill the slots with.
+    /// * `end_slot` - Slot to stop at.
+    fn fill(&mut self, frame: F, end_slot: i64) -> Vec<(F, i64)> {
+        let next_slot = self.next_slot.unwrap_or(end_slot);
+        let output = (next_slot..end_slot)
+            .map(|slot| (frame.clone(), self.pts_of(slot)))
+            .collect::<Vec<_>>();
+        self.next_slot = Some(next_slot.max(end_slot));
+
+        output
+    }
+
+    /// Get the position of a timestamp on the grid, in slots.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp in `time_base` units.
+    fn position_of(&self, pts: i64) -> f64 {
+        pts as f64 * self.time_base.numerator() as f64 / self.time_base.denominator() as f64
+            * self.frame_rate
+    }
+
+    /// Get the timestamp of a slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - Slot on the grid.
+    fn pts_of(&self, slot: i64) -> i64 {
+        (slot as f64 / self.frame_rate * self.time_base.denominator() as f64
+            / self.time_base.numerator() as f64)
+            .round() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert frames at some timestamps, and get the input frame and timestamp of each output
+    /// frame.
+    fn convert(frame_rate: f64, time_base: AvRational, timestamps: &[i64]) -> Vec<(usize, i64)> {
+        let mut converter = FrameRateConverter::new(frame_rate, time_base).unwrap();
+        let mut output = Vec::new();
+        for (i, &pts) in timestamps.iter().enumerate() {
+            output.extend(converter.push(i, Some(pts)));
+        }
+        output.extend(converter.flush());
+        output
+    }
+
+    #[test]
+    fn test_convert_23_976_to_30() {
+        // An hour at 24000/1001 fps.
+        const NUM_FRAMES: i64 = 86314;
+        let timestamps = (0..NUM_FRAMES).map(|i| i * 1001).collect::<Vec<_>>();
+        let output = convert(30.0, AvRational::new(1, 24000), &timestamps);
+
+        let duration = NUM_FRAMES as f64 * 1001.0 / 24000.0;
+        assert_eq!(output.len() as f64, (duration * 30.0).round());
+        assert!(output
+            .iter()
+            .enumerate()
+            .all(|(slot, (_, pts))| *pts == slot as i64 * 800));
+        // Every input frame is used, some of them twice.
+        let mut inputs = output.iter().map(|(i, _)| *i).collect::<Vec<_>>();
+        inputs.dedup();
+        assert_eq!(inputs.len() as i64, NUM_FRAMES);
+    }
+
+    #[test]
+    fn test_convert_picks_closest_frame() {
+        // Variable frame rate input in milliseconds, converted to 10 fps. Frame 1 is dropped and
+        // frames 4 and 5 are duplicated.
+        let output = convert(
+            10.0,
+            AvRational::new(1, 1000),
+            &[1000, 1040, 1090, 1260, 1300, 1520],
+        );
+        assert_eq!(
+            output,
+            vec![
+                (0, 1000),
+                (2, 1100),
+                (3, 1200),
+                (4, 1300),
+                (4, 1400),
+                (5, 1500),
+                (5, 1600)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_never_goes_back() {
+        let output = convert(10.0, AvRational::new(1, 1000), &[0, 200, 100, 300]);
+        assert!(output.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert!(FrameRateConverter::<usize>::new(0.0, AvRational::new(1, 1000)).is_err());
+    }
+}
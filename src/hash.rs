@@ -0,0 +1,273 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use crate::convert::convert_frame;
+use crate::decode::Decoder;
+use crate::error::Error;
+use crate::ffi::copy_frame_to_buffer;
+use crate::io::Locator;
+use crate::time::Time;
+use crate::{PixelFormat, RawFrame};
+
+#[cfg(feature = "ndarray")]
+use crate::Frame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Format in which [`hash_video_with_format`] hashes the decoded frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashFormat {
+    /// Hash the frames in the pixel format the decoder produces them in. This is the fastest, but
+    /// the hashes change when the decoder changes its output format.
+    #[default]
+    Native,
+    /// Convert the frames to RGB24 first, so that the same visual content hashes identically
+    /// regardless of the output format of the decoder. The hashes are the same as those of
+    /// [`hash_frame`] for the frames of [`Decoder::decode`].
+    Rgb24,
+}
+
+/// Compute a deterministic hash of a frame, for comparing frames against golden values in tests.
+///
+/// The hash is the 64-bit xxHash (XXH64, seed zero) of the dimensions and pixel layout of the
+/// frame, followed by its pixel data in the channel order of the frame (row by row, RGB for the
+/// frames of [`Decoder::decode`]). It does not depend on the memory layout of the array.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to hash.
+///
+/// # Example
+///
+/// ```ignore
+/// let (_, frame) = decoder.decode().unwrap();
+/// assert_eq!(hash_frame(&frame), 0x6b7e_1f5e_0c2a_9d41);
+/// ```
+#[cfg(feature = "ndarray")]
+pub fn hash_frame(frame: &Frame) -> u64 {
+    let (height, width, channels) = frame.dim();
+    let layout = match channels {
+        3 => "rgb24".to_string(),
+        4 => "bgra".to_string(),
+        channels => format!("{channels}ch"),
+    };
+    let mut data = header(width as u32, height as u32, &layout);
+    data.extend(frame.iter());
+
+    xxh64(&data, 0)
+}
+
+/// Compute a deterministic hash of a raw frame in its own pixel format. Padding at the end of the
+/// rows of the frame is left out, so the hash only depends on the dimensions, the pixel format
+/// and the pixel data. An RGB24 or BGRA frame hashes the same as its [`crate::Frame`] counterpart
+/// with [`hash_frame`].
+///
+/// # Arguments
+///
+/// * `frame` - Frame to hash.
+pub fn hash_raw_frame(frame: &RawFrame) -> Result<u64> {
+    let layout = frame
+        .format()
+        .descriptor()
+        .map(|descriptor| descriptor.name())
+        .ok_or_else(|| Error::InvalidFrameFormat {
+            expected: "a known pixel format".to_string(),
+            received: format!("{:?}", frame.format()),
+        })?;
+    let mut data = header(frame.width(), frame.height(), layout);
+    data.extend(copy_frame_to_buffer(frame)?);
+
+    Ok(xxh64(&data, 0))
+}
+
+/// Decode every frame of a video and hash it in the pixel format of the decoder (see
+/// [`hash_raw_frame`]). Use [`hash_video_with_format`] to hash frames in a normalized format.
+///
+/// # Arguments
+///
+/// * `source` - Source to decode.
+///
+/// # Return value
+///
+/// The timestamp and hash of each frame, in decoding order.
+///
+/// # Example
+///
+/// ```ignore
+/// let hashes = hash_video(&PathBuf::from("golden.mp4").into()).unwrap();
+/// assert_eq!(hashes, hash_video(&PathBuf::from("output.mp4").into()).unwrap());
+/// ```
+pub fn hash_video(source: &Locator) -> Result<Vec<(Time, u64)>> {
+    hash_video_with_format(source, HashFormat::Native)
+}
+
+/// Decode every frame of a video and hash it in the given format.
+///
+/// # Arguments
+///
+/// * `source` - Source to decode.
+/// * `format` - Format to hash the frames in.
+///
+/// # Return value
+///
+/// The timestamp and hash of each frame, in decoding order.
+pub fn hash_video_with_format(source: &Locator, format: HashFormat) -> Result<Vec<(Time, u64)>> {
+    let (mut decoder, mut reader, stream_index) = Decoder::new(source)?.into_parts();
+    let time_base = decoder.time_base();
+    let hash = |frame: RawFrame| -> Result<(Time, u64)> {
+        let timestamp = Time::new(Some(frame.packet().dts), time_base);
+        let frame = match format {
+            HashFormat::Rgb24 if frame.format() != PixelFormat::RGB24 => {
+                convert_frame(&frame, PixelFormat::RGB24)?
+            }
+            _ => frame,
+        };
+        Ok((timestamp, hash_raw_frame(&frame)?))
+    };
+
+    let mut hashes = Vec::new();
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) => {
+                if let Some(frame) = decoder.decode_unscaled(packet)? {
+                    hashes.push(hash(frame)?);
+                }
+            }
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    while let Some(frame) = decoder.drain_unscaled()? {
+        hashes.push(hash(frame)?);
+    }
+
+    Ok(hashes)
+}
+
+/// Get the bytes that precede the pixel data in a hash: the dimensions and pixel layout, so that
+/// frames with the same bytes but a different shape do not collide.
+///
+/// # Arguments
+///
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+/// * `layout` - Name of the pixel layout, like `rgb24`.
+fn header(width: u32, height: u32, layout: &str) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend(width.to_le_bytes());
+    header.extend(height.to_le_bytes());
+    header.extend(layout.as_bytes());
+    header.push(0);
+    header
+}
+
+const PRIME_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+/// Compute the 64-bit xxHash (XXH64) of some data.
+///
+/// # Arguments
+///
+/// * `data` - Data to hash.
+/// * `seed` - Seed of the hash.
+fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap()) as u64;
+    let round = |acc: u64, lane: u64| {
+        acc.wrapping_add(lane.wrapping_mul(PRIME_2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME_1)
+    };
+    let merge = |hash: u64, acc: u64| {
+        (hash ^ round(0, acc))
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4)
+    };
+
+    let mut stripes = data.chunks_exact(32);
+    let mut hash = if data.len() >= 32 {
+        let mut acc = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        for stripe in &mut stripes {
+            for (i, acc) in acc.iter_mut().enumerate() {
+                *acc = round(*acc, read_u64(&stripe[i * 8..]));
+            }
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.into_iter().fold(hash, merge)
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    let mut rest = stripes.remainder();
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, read_u64(rest)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME_1)
+            .wrapping_add(PRIME_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        hash = (hash ^ read_u32(rest).wrapping_mul(PRIME_1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME_2)
+            .wrapping_add(PRIME_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = (hash ^ (byte as u64).wrapping_mul(PRIME_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xxh64() {
+        assert_eq!(xxh64(b"", 0), 0xef46_db37_51d8_e999);
+        assert_eq!(xxh64(b"abc", 0), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(
+            xxh64(b"Nobody inspects the spammish repetition", 0),
+            0xfbce_a83c_8a37_8bf1
+        );
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_hash_frame() {
+        let frame = Frame::from_shape_fn((48, 64, 3), |(y, x, c)| (y + x + c) as u8);
+        assert_eq!(hash_frame(&frame), hash_frame(&frame.clone()));
+
+        // The hash does not depend on the memory layout of the array.
+        let mut transposed = Frame::zeros((64, 48, 3));
+        transposed.swap_axes(0, 1);
+        transposed.assign(&frame);
+        assert_eq!(hash_frame(&frame), hash_frame(&transposed));
+
+        let mut changed = frame.clone();
+        changed[[47, 63, 2]] ^= 1;
+        assert_ne!(hash_frame(&frame), hash_frame(&changed));
+        let reshaped = frame.clone().into_shape((64, 48, 3)).unwrap();
+        assert_ne!(hash_frame(&frame), hash_frame(&reshaped));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::packet::Packet;
+
+/// Time constant of the bit rate over the last while, in seconds. Bits that came in longer ago
+/// count for exponentially less.
+const BITRATE_TIME_CONSTANT: f64 = 1.0;
+
+/// Decode errors are counted over this window for the rate per minute.
+const ERROR_WINDOW: Duration = Duration::from_secs(60);
+
+/// Health of the video stream of a [`crate::Decoder`], for monitoring live inputs (see
+/// [`crate::Decoder::health`]). All figures are measured in wall clock time as packets are read,
+/// since the start or the last reset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamHealth {
+    /// Input bit rate of the stream in bits per second, averaged over about the last second
+    /// (with exponentially decaying weights).
+    pub bitrate: f64,
+    /// Input bit rate of the stream in bits per second, averaged since the first packet.
+    pub average_bitrate: f64,
+    /// Jitter of the arrival of packets: the smoothed difference between the time between the
+    /// arrival of two packets and the time between their timestamps, like the interarrival
+    /// jitter of RTP (RFC 3550).
+    pub jitter: Duration,
+    /// Number of packets that failed to decode or were skipped as corrupt in the last minute.
+    pub decode_errors_per_minute: u32,
+    /// Number of packets that failed to decode or were skipped as corrupt.
+    pub decode_errors: u64,
+    /// Number of times that the timestamps jumped (forward or back) by more than the
+    /// discontinuity threshold (see [`crate::Decoder::with_discontinuity_threshold`]).
+    pub discontinuities: u64,
+    /// Number of packets read.
+    pub packets: u64,
+}
+
+/// Keeps the health figures of a stream up to date as packets come in.
+#[derive(Debug, Clone)]
+pub(crate) struct HealthMonitor {
+    discontinuity_threshold: Duration,
+    /// Time of the first packet.
+    first_arrival: Option<Instant>,
+    /// Time of the last packet.
+    last_arrival: Option<Instant>,
+    /// Timestamp and duration of the last packet with a timestamp, in seconds.
+    last_timestamp: Option<(f64, f64)>,
+    /// Bits so far, each decayed by the time since it came in (up to the last packet).
+    decayed_bits: f64,
+    total_bits: f64,
+    /// Jitter in seconds.
+    jitter: f64,
+    /// Times of the decode errors in the last minute (up to the last error).
+    recent_errors: VecDeque<Instant>,
+    decode_errors: u64,
+    discontinuities: u64,
+    packets: u64,
+}
+
+impl HealthMonitor {
+    /// Default threshold for timestamp jumps that count as discontinuities.
+    pub(crate) const DEFAULT_DISCONTINUITY_THRESHOLD: Duration = Duration::from_secs(1);
+
+    /// Start monitoring.
+    ///
+    /// # Arguments
+    ///
+    /// * `discontinuity_threshold` - Smallest timestamp jump that counts as a discontinuity.
+    pub(crate) fn new(discontinuity_threshold: Duration) -> Self {
+        Self {
+            discontinuity_threshold,
+            first_arrival: None,
+            last_arrival: None,
+            last_timestamp: None,
+            decayed_bits: 0.0,
+            total_bits: 0.0,
+            jitter: 0.0,
+            recent_errors: VecDeque::new(),
+            decode_errors: 0,
+            discontinuities: 0,
+            packets: 0,
+        }
+    }
+
+    /// Set the smallest timestamp jump that counts as a discontinuity.
+    pub(crate) fn set_discontinuity_threshold(&mut self, threshold: Duration) {
+        self.discontinuity_threshold = threshold;
+    }
+
+    /// Start over, as if no packets came in yet. The discontinuity threshold is kept.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.discontinuity_threshold);
+    }
+
+    /// Note that a packet of the stream was read. Its timestamp is the decoding timestamp, or the
+    /// presentation timestamp if it has none.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet that was read, with the timestamps of the input.
+    pub(crate) fn packet_read(&mut self, packet: &Packet) {
+        let timestamp = if packet.dts().has_value() {
+            Some(packet.dts())
+        } else if packet.pts().has_value() {
+            Some(packet.pts())
+        } else {
+            None
+        };
+        self.record_packet(
+            Instant::now(),
+            packet.size(),
+            timestamp.map(|timestamp| timestamp.as_secs_f64()),
+            packet.duration().as_secs_f64(),
+        );
+    }
+
+    /// Note that a packet failed to decode, or was skipped as corrupt.
+    pub(crate) fn decode_error(&mut self) {
+        self.record_decode_error(Instant::now());
+    }
+
+    /// Get the health figures.
+    pub(crate) fn health(&self) -> StreamHealth {
+        self.health_at(Instant::now())
+    }
+
+    /// Note that a packet was read.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time at which the packet came in.
+    /// * `size` - Size of the packet in bytes.
+    /// * `timestamp` - Timestamp of the packet in seconds, if it has one.
+    /// * `duration` - Duration of the packet in seconds, or zero if not known.
+    fn record_packet(&mut self, now: Instant, size: usize, timestamp: Option<f64>, duration: f64) {
+        let bits = size as f64 * 8.0;
+        self.decayed_bits = self.decayed_bits_at(now) + bits;
+        self.total_bits += bits;
+        self.packets += 1;
+
+        if let (Some(timestamp), Some((last_timestamp, last_duration))) =
+            (timestamp, self.last_timestamp)
+        {
+            let jump = timestamp - (last_timestamp + last_duration);
+            if jump.abs() > self.discontinuity_threshold.as_secs_f64() {
+                self.discontinuities += 1;
+            } else if let Some(last_arrival) = self.last_arrival {
+                let deviation = (now.saturating_duration_since(last_arrival).as_secs_f64()
+                    - (timestamp - last_timestamp))
+                    .abs();
+                self.jitter += (deviation - self.jitter) / 16.0;
+            }
+        }
+
+        if let Some(timestamp) = timestamp {
+            self.last_timestamp = Some((timestamp, duration.max(0.0)));
+        }
+        self.first_arrival.get_or_insert(now);
+        self.last_arrival = Some(now);
+    }
+
+    /// Note that a packet failed to decode.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time at which decoding failed.
+    fn record_decode_error(&mut self, now: Instant) {
+        self.decode_errors += 1;
+        self.recent_errors.push_back(now);
+        while self
+            .recent_errors
+            .front()
+            .is_some_and(|&error| now.saturating_duration_since(error) > ERROR_WINDOW)
+        {
+            self.recent_errors.pop_front();
+        }
+    }
+
+    /// Get the health figures at a point in time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time to get the figures at.
+    fn health_at(&self, now: Instant) -> StreamHealth {
+        let elapsed = self.first_arrival.map_or(0.0, |first| {
+            now.saturating_duration_since(first).as_secs_f64()
+        });
+
+        StreamHealth {
+            bitrate: self.decayed_bits_at(now) / BITRATE_TIME_CONSTANT,
+            average_bitrate: if elapsed > 0.0 {
+                self.total_bits / elapsed
+            } else {
+                0.0
+            },
+            jitter: Duration::from_secs_f64(self.jitter),
+            decode_errors_per_minute: self
+                .recent_errors
+                .iter()
+                .filter(|&&error| now.saturating_duration_since(error) <= ERROR_WINDOW)
+                .count() as u32,
+            decode_errors: self.decode_errors,
+            discontinuities: self.discontinuities,
+            packets: self.packets,
+        }
+    }
+
+    /// Get the decayed bits at a point in time after the last packet.
+    fn decayed_bits_at(&self, now: Instant) -> f64 {
+        match self.last_arrival {
+            Some(last_arrival) => {
+                let elapsed = now.saturating_duration_since(last_arrival).as_secs_f64();
+                self.decayed_bits * (-elapsed / BITRATE_TIME_CONSTANT).exp()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_monitor() {
+        let start = Instant::now();
+        let at = |secs: f64| start + Duration::from_secs_f64(secs);
+        let mut monitor = HealthMonitor::new(HealthMonitor::DEFAULT_DISCONTINUITY_THRESHOLD);
+
+        // 1000 byte packets at 25 fps, arriving on time.
+        for i in 0..250 {
+            let time = i as f64 / 25.0;
+            monitor.record_packet(at(time), 1000, Some(time), 1.0 / 25.0);
+        }
+        let health = monitor.health_at(at(10.0));
+        assert!(
+            (health.average_bitrate - 200_000.0).abs() < 1000.0,
+            "{health:?}"
+        );
+        assert!((health.bitrate - 200_000.0).abs() < 10_000.0, "{health:?}");
+        assert!(health.jitter < Duration::from_millis(1));
+        assert_eq!(health.discontinuities, 0);
+        assert_eq!(health.packets, 250);
+
+        // The timestamps jump by a minute, while packets keep coming in steadily.
+        for i in 250..300 {
+            let time = i as f64 / 25.0;
+            monitor.record_packet(at(time), 1000, Some(time + 60.0), 1.0 / 25.0);
+        }
+        assert_eq!(monitor.health_at(at(12.0)).discontinuities, 1);
+
+        // Packets arrive in bursts.
+        for i in 300..400 {
+            let time = i as f64 / 25.0;
+            let arrival = (i / 10 * 10) as f64 / 25.0;
+            monitor.record_packet(at(arrival), 1000, Some(time + 60.0), 1.0 / 25.0);
+        }
+        let health = monitor.health_at(at(16.0));
+        assert!(health.jitter > Duration::from_millis(20), "{health:?}");
+        assert_eq!(health.discontinuities, 1);
+
+        // Nothing comes in anymore.
+        assert!(monitor.health_at(at(30.0)).bitrate < 1.0);
+
+        // Errors count for a minute.
+        monitor.record_decode_error(at(20.0));
+        monitor.record_decode_error(at(50.0));
+        assert_eq!(monitor.health_at(at(60.0)).decode_errors_per_minute, 2);
+        assert_eq!(monitor.health_at(at(90.0)).decode_errors_per_minute, 1);
+        assert_eq!(monitor.health_at(at(90.0)).decode_errors, 2);
+
+        monitor.reset();
+        let health = monitor.health_at(at(100.0));
+        assert_eq!(health.packets, 0);
+        assert_eq!(health.decode_errors, 0);
+        assert_eq!(health.bitrate, 0.0);
+    }
+}
@@ -0,0 +1,103 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::ffi::AVHWDeviceType;
+
+/// Type of hardware device to decode with. Which types are available depends on the platform and
+/// on how ffmpeg was built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardwareDeviceType {
+    /// NVIDIA CUDA (NVDEC).
+    Cuda,
+    /// Video Acceleration API, on Linux.
+    Vaapi,
+    /// Intel Quick Sync Video.
+    Qsv,
+    /// Apple VideoToolbox, on macOS and iOS.
+    VideoToolbox,
+    /// Direct3D 11, on Windows.
+    D3d11va,
+    /// DirectX Video Acceleration 2, on Windows.
+    Dxva2,
+    /// Video Decode and Presentation API for Unix.
+    Vdpau,
+    /// Vulkan video.
+    Vulkan,
+}
+
+impl HardwareDeviceType {
+    /// Get the name of the device type, as ffmpeg calls it (like `cuda`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            HardwareDeviceType::Cuda => "cuda",
+            HardwareDeviceType::Vaapi => "vaapi",
+            HardwareDeviceType::Qsv => "qsv",
+            HardwareDeviceType::VideoToolbox => "videotoolbox",
+            HardwareDeviceType::D3d11va => "d3d11va",
+            HardwareDeviceType::Dxva2 => "dxva2",
+            HardwareDeviceType::Vdpau => "vdpau",
+            HardwareDeviceType::Vulkan => "vulkan",
+        }
+    }
+
+    /// Get the native device type.
+    pub(crate) fn as_av(&self) -> AVHWDeviceType {
+        match self {
+            HardwareDeviceType::Cuda => AVHWDeviceType::AV_HWDEVICE_TYPE_CUDA,
+            HardwareDeviceType::Vaapi => AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+            HardwareDeviceType::Qsv => AVHWDeviceType::AV_HWDEVICE_TYPE_QSV,
+            HardwareDeviceType::VideoToolbox => AVHWDeviceType::AV_HWDEVICE_TYPE_VIDEOTOOLBOX,
+            HardwareDeviceType::D3d11va => AVHWDeviceType::AV_HWDEVICE_TYPE_D3D11VA,
+            HardwareDeviceType::Dxva2 => AVHWDeviceType::AV_HWDEVICE_TYPE_DXVA2,
+            HardwareDeviceType::Vdpau => AVHWDeviceType::AV_HWDEVICE_TYPE_VDPAU,
+            HardwareDeviceType::Vulkan => AVHWDeviceType::AV_HWDEVICE_TYPE_VULKAN,
+        }
+    }
+}
+
+/// Hardware device to decode with (see [`crate::DecoderBuilder::hardware`]).
+///
+/// # Example
+///
+/// ```ignore
+/// let device = HardwareDevice::new(HardwareDeviceType::Vaapi).with_device("/dev/dri/renderD128");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardwareDevice {
+    device_type: HardwareDeviceType,
+    device: Option<String>,
+}
+
+impl HardwareDevice {
+    /// Use the default device of a type.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_type` - Type of device.
+    pub fn new(device_type: HardwareDeviceType) -> Self {
+        Self {
+            device_type,
+            device: None,
+        }
+    }
+
+    /// Use a specific device, like `/dev/dri/renderD128` for VAAPI or the index of the GPU (like
+    /// `1`) for CUDA.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Device to open.
+    pub fn with_device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    /// Get the type of device.
+    pub fn device_type(&self) -> HardwareDeviceType {
+        self.device_type
+    }
+
+    /// Get the device to open, or `None` for the default device.
+    pub(crate) fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+}
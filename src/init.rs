@@ -1,14 +1,234 @@
 extern crate ffmpeg_next as ffmpeg;
 
-use crate::ffi::init_logging;
+use std::sync::{Once, OnceLock};
+
+use ffmpeg::ffi::{
+    AV_LOG_DEBUG, AV_LOG_ERROR, AV_LOG_INFO, AV_LOG_QUIET, AV_LOG_TRACE, AV_LOG_VERBOSE,
+    AV_LOG_WARNING,
+};
+
+use crate::ffi;
+
+/// Result of the global initialization, which is only done once.
+static INIT: OnceLock<Result<(), ffmpeg::Error>> = OnceLock::new();
+
+/// Guards the network initialization, which is only done once.
+static NETWORK_INIT: Once = Once::new();
 
 /// Initialize global ffmpeg settings. This also intializes the
 /// logging capability and redirect it to `tracing`.
+///
+/// This is the same as [`init_with`] with the default [`InitConfig`], so it initializes the
+/// network as well. It is safe to call this more than once.
 pub fn init() -> Result<(), Box<dyn std::error::Error>> {
-    ffmpeg::init()?;
+    init_with(InitConfig::default())
+}
 
-    // Redirect logging to the Rust `tracing` crate.
-    init_logging();
+/// Initialize global ffmpeg settings with the given configuration. The global initialization is
+/// only done once, no matter how often this is called (or how many libraries call it), so this can
+/// be called defensively. The network is initialized the first time it is requested, and the log
+/// level is set on every call that specifies one.
+///
+/// Readers and writers initialize ffmpeg by themselves if this has not been done yet, but without
+/// the network initialization.
+///
+/// # Arguments
+///
+/// * `config` - What to initialize.
+///
+/// # Example
+///
+/// ```ignore
+/// video_rs::init_with(InitConfig {
+///     log_level: Some(LogLevel::Warning),
+///     network: true,
+/// })
+/// .unwrap();
+/// ```
+pub fn init_with(config: InitConfig) -> Result<(), Box<dyn std::error::Error>> {
+    initialize()?;
+
+    if config.network {
+        NETWORK_INIT.call_once(ffmpeg::format::network::init);
+    }
+    if let Some(log_level) = config.log_level {
+        set_log_level(log_level);
+    }
 
     Ok(())
 }
+
+/// Initialize ffmpeg and redirect its logging to `tracing`, unless this has been done before.
+pub(crate) fn initialize() -> Result<(), ffmpeg::Error> {
+    *INIT.get_or_init(|| {
+        ffmpeg::init()?;
+
+        // Redirect logging to the Rust `tracing` crate.
+        init_logging();
+
+        Ok(())
+    })
+}
+
+/// Configuration of the global initialization. See [`init_with`].
+#[derive(Debug, Clone)]
+pub struct InitConfig {
+    /// Level of the messages that ffmpeg logs, or `None` to keep the current level.
+    pub log_level: Option<LogLevel>,
+    /// Whether or not to initialize the network libraries (`avformat_network_init`). Some
+    /// protocols (like TLS) misbehave when this is not done.
+    pub network: bool,
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self {
+            log_level: None,
+            network: true,
+        }
+    }
+}
+
+/// Redirect ffmpeg logging to the Rust `tracing` crate, without initializing anything else. This
+/// is done by [`init`] as well.
+///
+/// The component that logged a message (like `libx264`) is recorded in the `component` field of
+/// the event. With the `log` feature, the events are passed on to the `log` crate when no
+/// `tracing` subscriber is installed.
+pub fn init_logging() {
+    ffi::init_logging();
+}
+
+/// Set the level of the messages that ffmpeg logs. Messages below this level are dropped before
+/// they are formatted. By default, all messages for which a `tracing` subscriber is enabled are
+/// passed on.
+///
+/// # Arguments
+///
+/// * `level` - Least severe level to log.
+///
+/// # Example
+///
+/// ```ignore
+/// video_rs::init().unwrap();
+/// video_rs::set_log_level(LogLevel::Warning);
+/// ```
+pub fn set_log_level(level: LogLevel) {
+    ffi::set_log_level(level.as_raw());
+}
+
+/// Level of ffmpeg log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Do not log anything.
+    Quiet,
+    /// Errors, including unrecoverable errors.
+    Error,
+    /// Warnings, like "non-monotonous DTS".
+    Warning,
+    /// Informational messages.
+    Info,
+    /// Detailed informational messages.
+    Verbose,
+    /// Messages that are only useful to ffmpeg developers.
+    Debug,
+    /// Extremely verbose debugging messages.
+    Trace,
+}
+
+impl LogLevel {
+    fn as_raw(self) -> std::ffi::c_int {
+        (match self {
+            LogLevel::Quiet => AV_LOG_QUIET,
+            LogLevel::Error => AV_LOG_ERROR,
+            LogLevel::Warning => AV_LOG_WARNING,
+            LogLevel::Info => AV_LOG_INFO,
+            LogLevel::Verbose => AV_LOG_VERBOSE,
+            LogLevel::Debug => AV_LOG_DEBUG,
+            LogLevel::Trace => AV_LOG_TRACE,
+        }) as std::ffi::c_int
+    }
+}
+
+/// Get the version of ffmpeg that is linked, like `6.1.1` (or a git revision for development
+/// builds).
+pub fn ffmpeg_version() -> String {
+    ffi::version_info()
+}
+
+/// Get the versions and build configurations of the linked ffmpeg libraries, for diagnostics.
+pub fn ffmpeg_libraries() -> Vec<LibraryInfo> {
+    [
+        (
+            "libavutil",
+            ffmpeg::util::version(),
+            ffmpeg::util::configuration(),
+            ffmpeg::util::license(),
+        ),
+        (
+            "libavcodec",
+            ffmpeg::codec::version(),
+            ffmpeg::codec::configuration(),
+            ffmpeg::codec::license(),
+        ),
+        (
+            "libavformat",
+            ffmpeg::format::version(),
+            ffmpeg::format::configuration(),
+            ffmpeg::format::license(),
+        ),
+        (
+            "libswscale",
+            ffmpeg::software::scaling::version(),
+            ffmpeg::software::scaling::configuration(),
+            ffmpeg::software::scaling::license(),
+        ),
+        (
+            "libswresample",
+            ffmpeg::software::resampling::version(),
+            ffmpeg::software::resampling::configuration(),
+            ffmpeg::software::resampling::license(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, version, configuration, license)| LibraryInfo {
+        name,
+        version: unpack_version(version),
+        configuration,
+        license,
+    })
+    .collect()
+}
+
+/// Version and build configuration of an ffmpeg library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LibraryInfo {
+    /// Name of the library, like `libavcodec`.
+    pub name: &'static str,
+    /// Major, minor and micro version of the library.
+    pub version: (u32, u32, u32),
+    /// Flags that the library was configured with, like `--enable-gpl --enable-libx264`.
+    pub configuration: &'static str,
+    /// License of the library, like `GPL version 2 or later`.
+    pub license: &'static str,
+}
+
+/// Split a version number of an ffmpeg library (`AV_VERSION_INT`) into its parts.
+///
+/// # Arguments
+///
+/// * `version` - Version number.
+fn unpack_version(version: u32) -> (u32, u32, u32) {
+    (version >> 16, (version >> 8) & 0xff, version & 0xff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpack_version() {
+        // libavcodec of ffmpeg 6.1.
+        assert_eq!(unpack_version((60 << 16) | (31 << 8) | 102), (60, 31, 102));
+    }
+}
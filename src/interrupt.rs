@@ -0,0 +1,285 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Shared state behind the interrupt callback that is installed on every input context. The
+/// backend invokes the callback periodically while it is blocked on I/O, and aborts the current
+/// operation if it returns `true`.
+pub(crate) struct Interrupt {
+    /// Read timeout, if any.
+    timeout: Option<Duration>,
+    /// Reference point for `last_activity_millis`.
+    epoch: Instant,
+    /// Milliseconds since `epoch` at which the last read started or completed.
+    last_activity_millis: AtomicU64,
+    /// Milliseconds since `epoch` after which any operation is aborted, or `u64::MAX` if there is
+    /// no deadline.
+    deadline_millis: AtomicU64,
+    /// Set when the read timeout or the deadline fired.
+    timed_out: AtomicBool,
+    /// Set when the caller cancelled through an [`InterruptHandle`].
+    cancelled: AtomicBool,
+    /// Token of the operation that is running, if it can be cancelled.
+    token: Mutex<Option<CancellationToken>>,
+}
+
+impl Interrupt {
+    /// Create new interrupt state.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum amount of time a single read may block before it is aborted.
+    pub(crate) fn new(timeout: Option<Duration>) -> Arc<Self> {
+        Arc::new(Self {
+            timeout,
+            epoch: Instant::now(),
+            last_activity_millis: AtomicU64::new(0),
+            deadline_millis: AtomicU64::new(u64::MAX),
+            timed_out: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Reset the timeout timer. This must be called before each read and after each successful
+    /// read.
+    pub(crate) fn touch(&self) {
+        self.timed_out.store(false, Ordering::SeqCst);
+        self.last_activity_millis
+            .store(self.elapsed_millis(), Ordering::SeqCst);
+    }
+
+    /// Whether or not the backend should abort the current operation. This is what the interrupt
+    /// callback returns.
+    pub(crate) fn should_interrupt(&self) -> bool {
+        if self.is_cancelled() {
+            return true;
+        }
+
+        if self.elapsed_millis() >= self.deadline_millis.load(Ordering::SeqCst) {
+            self.timed_out.store(true, Ordering::SeqCst);
+            return true;
+        }
+
+        if let Some(timeout) = self.timeout {
+            let idle = self
+                .elapsed_millis()
+                .saturating_sub(self.last_activity_millis.load(Ordering::SeqCst));
+            if idle >= timeout.as_millis() as u64 {
+                self.timed_out.store(true, Ordering::SeqCst);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Abort any operation once the given amount of time has passed from now, regardless of
+    /// activity.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time from now after which to abort, or `None` to remove the deadline.
+    pub(crate) fn set_deadline(&self, timeout: Option<Duration>) {
+        let deadline_millis = match timeout {
+            Some(timeout) => self
+                .elapsed_millis()
+                .saturating_add(timeout.as_millis() as u64),
+            None => u64::MAX,
+        };
+        self.timed_out.store(false, Ordering::SeqCst);
+        self.deadline_millis
+            .store(deadline_millis, Ordering::SeqCst);
+    }
+
+    /// Whether or not the last interruption was caused by the read timeout or the deadline.
+    #[inline]
+    pub(crate) fn is_timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::SeqCst)
+    }
+
+    /// Whether or not the caller cancelled, through an [`InterruptHandle`] or the token of the
+    /// operation that is running.
+    #[inline]
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .token
+                .lock()
+                .is_ok_and(|token| token.as_ref().is_some_and(CancellationToken::is_cancelled))
+    }
+
+    /// Abort reading when a token is cancelled, for as long as the returned guard exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt state to link the token to.
+    /// * `token` - Token of the operation.
+    pub(crate) fn link(interrupt: &Arc<Self>, token: &CancellationToken) -> CancellationGuard {
+        if let Ok(mut linked) = interrupt.token.lock() {
+            *linked = Some(token.clone());
+        }
+        CancellationGuard(interrupt.clone())
+    }
+
+    fn elapsed_millis(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+}
+
+/// Handle that can be used to cancel reading from another thread. Cancelling aborts any read that
+/// is in progress and causes all subsequent reads to fail with [`crate::Error::ReadInterrupted`].
+///
+/// The handle stays valid for as long as it exists, even after the reader it belongs to has been
+/// dropped (in which case cancelling does nothing).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = Decoder::new(&source).unwrap();
+/// let handle = decoder.interrupt_handle();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_secs(10));
+///     handle.cancel();
+/// });
+/// ```
+#[derive(Clone)]
+pub struct InterruptHandle(Arc<Interrupt>);
+
+impl InterruptHandle {
+    /// Cancel reading. Any read that is in progress will be aborted.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether or not [`InterruptHandle::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn new(interrupt: Arc<Interrupt>) -> Self {
+        Self(interrupt)
+    }
+}
+
+/// Token to cancel a long-running operation, like [`crate::Transcoder::run_with_cancel`], from
+/// another thread (for example when the user clicks cancel). Clones share their state, so a clone
+/// can be handed to the operation while the original is kept to cancel it.
+///
+/// Cancelling is checked between frames, and also aborts a read that is blocked, like a read from
+/// a network source that stalled. Cancelled operations fail with [`crate::Error::Cancelled`]. A
+/// token stays cancelled, so use a new token for each operation.
+///
+/// # Example
+///
+/// ```ignore
+/// let token = CancellationToken::new();
+/// let cancel = token.clone();
+/// cancel_button.on_click(move || cancel.cancel());
+/// match transcoder.run_with_cancel(&token) {
+///     Err(Error::Cancelled) => println!("cancelled"),
+///     result => result.unwrap(),
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the operations that use this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether or not [`CancellationToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Unlinks the token of an operation from the interrupt state when the operation is done, see
+/// [`Interrupt::link`].
+pub(crate) struct CancellationGuard(Arc<Interrupt>);
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if let Ok(mut linked) = self.0.token.lock() {
+            *linked = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_timeout() {
+        let interrupt = Interrupt::new(None);
+        interrupt.touch();
+        assert!(!interrupt.should_interrupt());
+        assert!(!interrupt.is_timed_out());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let interrupt = Interrupt::new(Some(Duration::from_millis(0)));
+        interrupt.touch();
+        assert!(interrupt.should_interrupt());
+        assert!(interrupt.is_timed_out());
+        assert!(!interrupt.is_cancelled());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let interrupt = Interrupt::new(None);
+        interrupt.set_deadline(Some(Duration::from_secs(60)));
+        assert!(!interrupt.should_interrupt());
+        interrupt.set_deadline(Some(Duration::ZERO));
+        assert!(interrupt.should_interrupt());
+        assert!(interrupt.is_timed_out());
+        interrupt.set_deadline(None);
+        assert!(!interrupt.should_interrupt());
+        assert!(!interrupt.is_timed_out());
+    }
+
+    #[test]
+    fn test_touch_resets_timeout() {
+        let interrupt = Interrupt::new(Some(Duration::from_secs(60)));
+        interrupt.touch();
+        assert!(!interrupt.should_interrupt());
+        interrupt.timed_out.store(true, Ordering::SeqCst);
+        interrupt.touch();
+        assert!(!interrupt.is_timed_out());
+    }
+
+    #[test]
+    fn test_cancel_from_handle() {
+        let interrupt = Interrupt::new(None);
+        let handle = InterruptHandle::new(interrupt.clone());
+        std::thread::spawn(move || handle.cancel()).join().unwrap();
+        assert!(interrupt.is_cancelled());
+        assert!(interrupt.should_interrupt());
+        interrupt.touch();
+        assert!(interrupt.should_interrupt());
+    }
+
+    #[test]
+    fn test_cancellation_token() {
+        let interrupt = Interrupt::new(None);
+        let token = CancellationToken::new();
+        let guard = Interrupt::link(&interrupt, &token);
+        assert!(!interrupt.should_interrupt());
+        token.clone().cancel();
+        assert!(interrupt.should_interrupt());
+        assert!(!InterruptHandle::new(interrupt.clone()).is_cancelled());
+
+        // The interrupt state no longer follows the token after the operation.
+        drop(guard);
+        assert!(!interrupt.should_interrupt());
+    }
+}
@@ -0,0 +1,143 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::Error as AvError;
+
+use crate::ffi::stream_keyframe_index;
+use crate::io::Reader;
+use crate::{Error, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Keyframe of a stream, as listed by [`Reader::build_keyframe_index`].
+#[derive(Debug, Clone)]
+pub struct KeyframeEntry {
+    /// Timestamp of the keyframe. This is the presentation timestamp, except for MP4 and
+    /// QuickTime, which index frames by their decoding timestamp (which comes earlier if the
+    /// stream has B-frames).
+    pub time: Time,
+    /// Position in the input in bytes from where the keyframe can be read. For Matroska, this is
+    /// the position of the cluster that holds the keyframe.
+    pub byte_offset: u64,
+}
+
+/// Get the keyframes of a stream from the index of the container, if the container has one.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `stream_index` - Index of the stream.
+///
+/// # Return value
+///
+/// The keyframes in the index, or `None` if the container has no index (or an empty one).
+pub(crate) fn container_keyframe_index(
+    reader: &Reader,
+    stream_index: usize,
+) -> Result<Option<Vec<KeyframeEntry>>> {
+    let time_base = reader
+        .input
+        .stream(stream_index)
+        .ok_or(AvError::StreamNotFound)?
+        .time_base();
+
+    Ok(stream_keyframe_index(&reader.input, stream_index)
+        .filter(|entries| !entries.is_empty())
+        .map(|entries| {
+            entries
+                .into_iter()
+                .map(|(timestamp, byte_offset)| KeyframeEntry {
+                    time: Time::new(Some(timestamp), time_base),
+                    byte_offset,
+                })
+                .collect()
+        }))
+}
+
+/// Find the keyframes of a stream by reading all of its packets (without decoding them). This
+/// reads the reader up to the end. Keyframes of which the position is unknown are left out.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `stream_index` - Index of the stream.
+pub(crate) fn scan_keyframes(
+    reader: &mut Reader,
+    stream_index: usize,
+) -> Result<Vec<KeyframeEntry>> {
+    let mut keyframes = Vec::new();
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) if packet.is_key() => {
+                if let (Some(_), Some(byte_offset)) = (packet.pts().into_value(), packet.position())
+                {
+                    keyframes.push(KeyframeEntry {
+                        time: packet.pts(),
+                        byte_offset,
+                    });
+                }
+            }
+            Ok(_) => {}
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(keyframes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    #[test]
+    fn test_build_keyframe_index() {
+        const NUM_FRAMES: i64 = 50;
+        const KEY_FRAME_INTERVAL: i64 = 10;
+
+        for format in ["mkv", "ts"] {
+            let path = TempPath::new(&format!("keyframes.{format}"));
+            let locator = path.locator();
+            testing::write_test_video_with_settings(
+                &locator,
+                testing::settings().with_key_frame_interval(KEY_FRAME_INTERVAL as u64),
+                (0..NUM_FRAMES).map(|i| testing::filled_frame((i * 4) as u8)),
+            );
+
+            let mut reader = Reader::new(&locator).unwrap();
+            let stream_index = reader.best_video_stream_index().unwrap();
+            let first = reader.read(stream_index).unwrap();
+            let index = reader.build_keyframe_index().unwrap();
+            // Matroska has an index, MPEG-TS is scanned.
+            assert_eq!(
+                container_keyframe_index(&reader, stream_index)
+                    .unwrap()
+                    .is_some(),
+                format == "mkv"
+            );
+
+            // The keyframes are the ones that the encoder placed.
+            let frame_duration = 1.0 / 30.0;
+            let start = index[0].time.as_secs_f64();
+            let keyframes = index
+                .iter()
+                .map(|entry| ((entry.time.as_secs_f64() - start) / frame_duration).round() as i64)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                keyframes,
+                (0..NUM_FRAMES)
+                    .step_by(KEY_FRAME_INTERVAL as usize)
+                    .collect::<Vec<_>>()
+            );
+            assert!(index
+                .windows(2)
+                .all(|entries| entries[0].byte_offset < entries[1].byte_offset));
+
+            // Reading carries on where it was.
+            let second = reader.read(stream_index).unwrap();
+            assert!(second.dts().as_secs_f64() > first.dts().as_secs_f64());
+            assert!(second.position() > first.position());
+        }
+    }
+}
@@ -1,35 +1,170 @@
+// `is_multiple_of` is only stable since Rust 1.87, so divisibility is checked with `%`.
+#![allow(clippy::manual_is_multiple_of)]
+
+mod analysis;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod attachment;
+mod audio;
+mod backend;
+mod bitstream;
+mod capabilities;
+mod chapter;
+mod concat;
+mod convert;
+mod crop;
 mod decode;
+mod device;
 mod encode;
 mod error;
 mod extradata;
+mod faststart;
 mod ffi;
+mod filter;
+mod follow;
 mod frame;
+mod framerate;
+mod hash;
+mod health;
+mod hwaccel;
 mod init;
+mod interrupt;
 mod io;
+mod keyframe;
+mod loudness;
+#[cfg(feature = "ndarray")]
+mod media;
 mod mux;
 mod options;
+mod overlay;
 mod packet;
+mod parallel;
+mod pipeline;
+#[cfg(feature = "ndarray")]
+mod poster;
+mod randomaccess;
+mod ratecontrol;
+mod rawvideo;
+mod remux;
+mod resample;
 mod resize;
 mod rtp;
+mod scene;
+mod segment;
+mod sequence;
+mod sidedata;
+mod smartcut;
+mod source;
+mod stamp;
+mod storyboard;
 mod stream;
+mod subtitle;
+mod sync;
+mod tag;
+#[cfg(test)]
+mod testing;
 mod time;
+mod transcode;
 
-pub use decode::{Decoder, DecoderSplit};
-pub use encode::{Encoder, Settings as EncoderSettings};
-pub use error::Error;
-pub use extradata::{Pps, Sps};
+pub use analysis::{AnalysisConfig, AnalysisReport, BlackParams, FreezeParams, TimeSpan};
+pub use attachment::Attachment;
+pub use audio::{AudioDecoder, AudioDecoderSplit, RawSamples};
+pub use backend::{BackendDecoder, BackendEncoder, VideoBackend};
+pub use bitstream::BitstreamFilter;
+pub use capabilities::{
+    capabilities, Capabilities, CodecId, DecoderInfo, EncoderInfo, FormatCaps, NamedConstant,
+    OptionKind, OptionSpec, OptionValue,
+};
+pub use chapter::Chapter;
+pub use concat::{concat, concat_with_settings};
+pub use convert::{convert_frame, Converter, ScaleColorSpaces, ScaleQuality};
+pub use decode::{
+    DecodeSpeed, Decoder, DecoderBuilder, DecoderSplit, ReconnectEvent, ReconnectPolicy,
+    ResumeBehavior, TimestampPolicy,
+};
+pub use device::{
+    list_capture_devices, list_capture_devices_for, CaptureDevice, DeviceKind, DeviceSource, Rect,
+    ScreenCaptureConfig,
+};
+pub use encode::{
+    EncodeStats, Encoder, FailurePolicy, FlushInterval, HlsOutput, OutputSpec, QualityMetrics,
+    RetryPolicy, Settings as EncoderSettings,
+};
+pub use error::{Error, ErrorContext};
+pub use extradata::{extract_parameter_sets_h264, Pps, Sps};
+pub use faststart::FaststartPath;
+pub use filter::{Deinterlace, DeinterlaceMode, FilterGraph, FilterInput, Transform};
+pub use follow::FollowPolicy;
 pub use frame::PixelFormat;
 pub use frame::RawFrame;
-pub use init::init;
-pub use io::{Buf, Reader, Write, Writer};
+pub use frame::{ColorPrimaries, ColorRange, ColorSpace, ColorSpec, ColorTransferCharacteristic};
+pub use hash::{hash_raw_frame, hash_video, hash_video_with_format, HashFormat};
+pub use health::StreamHealth;
+pub use hwaccel::{HardwareDevice, HardwareDeviceType};
+pub use init::{
+    ffmpeg_libraries, ffmpeg_version, init, init_logging, init_with, set_log_level, InitConfig,
+    LibraryInfo, LogLevel,
+};
+pub use interrupt::{CancellationToken, InterruptHandle};
+pub use io::{Buf, MemorySink, Reader, Write, Writer, WriterBuilder};
 pub use io::{Locator, Url};
+pub use keyframe::KeyframeEntry;
+pub use loudness::{measure_loudness, LoudnessReport};
 pub use mux::{BufMuxer, FileMuxer, PacketizedBufMuxer};
-pub use options::Options;
-pub use packet::Packet;
+pub use options::{MovFlag, Options, OptionsBuilder, Preset, SrtMode, SrtOptions, Tune};
+pub use overlay::Overlay;
+pub use packet::{EncodedPacket, Packet};
+pub use parallel::transcode_parallel;
+pub use pipeline::PipelinedEncoder;
+pub use randomaccess::RandomAccessDecoder;
+pub use ratecontrol::RateControl;
+pub use rawvideo::RawVideoConfig;
+pub use remux::{
+    extract_segment, extract_segment_with_alignment, KeyFrameAlignment, RemuxStats, Remuxer,
+};
+pub use resample::{Resampler, SampleSpec};
 pub use resize::Resize;
 pub use rtp::{RtpBuf, RtpMuxer};
-pub use stream::StreamInfo;
-pub use time::{Aligned, Time};
+pub use segment::{CompletedSegment, SegmentLimit, SegmentPolicy};
+pub use sequence::{ImageSequence, MissingFrames};
+pub use sidedata::{
+    Chromaticities, ContentLightLevel, HdrMetadata, Luminance, MasteringDisplayMetadata,
+    MotionVector, RawFrameMetadata, RawFrameSideData, SideData,
+};
+pub use smartcut::{smart_cut, SmartCutStats};
+pub use source::{RtspTransport, Source};
+pub use stamp::{StampConfig, StampPosition};
+pub use storyboard::{generate_storyboard, StoryboardConfig};
+pub use stream::{CodecParams, Disposition, DispositionFlag, StreamInfo, StreamMap};
+pub use subtitle::SubtitleSource;
+pub use sync::{
+    verify_sync, verify_sync_with_config, FileTiming, FrameMismatch, StreamTiming, SyncConfig,
+    SyncReport,
+};
+pub use tag::TagTarget;
+pub use time::{Aligned, Time, Timestamper};
+pub use transcode::Transcoder;
+
+#[cfg(feature = "ndarray")]
+pub use convert::{frame_to_ndarray, resize_frame};
+
+#[cfg(feature = "ndarray")]
+pub use hash::hash_frame;
+
+#[cfg(feature = "ndarray")]
+pub use frame::{Frame, Frame32, FrameLayout, FrameU16, Normalization, RawFrameExt, YuvFrame};
+
+#[cfg(feature = "ndarray")]
+pub use audio::Samples;
+
+#[cfg(feature = "ndarray")]
+pub use poster::{frame_brightness, frame_sharpness, select_poster_frame, PosterConfig};
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncDecoder, AsyncEncoder};
+
+#[cfg(feature = "backend-ffmpeg")]
+pub use backend::FfmpegBackend;
 
 #[cfg(feature = "ndarray")]
-pub use frame::Frame;
+pub use media::{MediaDecoder, MediaItem};
@@ -0,0 +1,927 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::codec::encoder::audio::Encoder as AvAudioEncoder;
+use ffmpeg::codec::flag::Flags as AvCodecFlags;
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Parameters as AvParameters;
+use ffmpeg::software::resampling::context::Context as AvResampler;
+use ffmpeg::util::channel_layout::ChannelLayout as AvChannelLayout;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::format::sample::{Sample as AvSample, Type as AvSampleType};
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::audio::{AudioDecoderSplit, RawSamples, SAMPLE_FORMAT};
+use crate::encode::Encoder;
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::codec_context_as;
+use crate::io::Reader;
+use crate::packet::Packet;
+use crate::resample::{Resampler, SampleSpec};
+use crate::stream::StreamInfo;
+use crate::transcode::{decode_error, encode_error};
+use crate::{Error, Locator};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Blocks quieter than this (in LUFS) are left out of the integrated loudness and loudness range.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Sample peaks of normalized audio are limited to this level (in dBFS), which leaves headroom
+/// for the peaks between samples.
+const LIMITER_CEILING: f64 = -1.0;
+
+/// Loudness of audio according to EBU R128 (ITU-R BS.1770), as measured by [`measure_loudness`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReport {
+    /// Integrated (gated) loudness over the whole stream in LUFS. This is negative infinity for
+    /// silence, or audio shorter than 400 ms.
+    pub integrated_lufs: f64,
+    /// Highest peak in dBTP, including the peaks between samples (with four times oversampling).
+    pub true_peak: f64,
+    /// Loudness range (LRA) in LU: the spread of the short-term loudness, from the 10th to the
+    /// 95th percentile.
+    pub lra: f64,
+}
+
+/// Measure the loudness of the best audio stream of a source according to EBU R128, by decoding
+/// it in full.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = measure_loudness(&PathBuf::from("video.mp4").into()).unwrap();
+/// println!("{:.1} LUFS, {:.1} dBTP", report.integrated_lufs, report.true_peak);
+/// ```
+pub fn measure_loudness(source: &Locator) -> Result<LoudnessReport> {
+    let mut reader = Reader::new(source)?;
+    let stream_index = reader.best_audio_stream_index()?;
+    measure_reader_loudness(&mut reader, stream_index)
+}
+
+/// Measure the loudness of an audio stream of a source.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `stream_index` - Index of the audio stream.
+pub(crate) fn measure_stream_loudness(
+    source: &Locator,
+    stream_index: usize,
+) -> Result<LoudnessReport> {
+    measure_reader_loudness(&mut Reader::new(source)?, stream_index)
+}
+
+/// Measure the loudness of an audio stream by decoding all of its packets.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `stream_index` - Index of the audio stream.
+fn measure_reader_loudness(reader: &mut Reader, stream_index: usize) -> Result<LoudnessReport> {
+    let mut decoder = AudioDecoderSplit::new(reader, stream_index)?;
+    let mut meter = LoudnessMeter::new(decoder.sample_rate(), decoder.channels());
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) => {
+                if let Some(samples) = decoder.decode_raw(packet)? {
+                    meter.push(&interleaved_samples(&samples));
+                }
+            }
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    while let Some(samples) = decoder.drain_raw()? {
+        meter.push(&interleaved_samples(&samples));
+    }
+
+    Ok(meter.finish())
+}
+
+/// Gain in dB that brings audio from its measured loudness to a target. Silence is left as it
+/// is.
+///
+/// # Arguments
+///
+/// * `report` - Measured loudness.
+/// * `target_lufs` - Target integrated loudness in LUFS.
+pub(crate) fn normalization_gain(report: &LoudnessReport, target_lufs: f64) -> f64 {
+    if report.integrated_lufs.is_finite() {
+        target_lufs - report.integrated_lufs
+    } else {
+        0.0
+    }
+}
+
+/// Measures loudness (EBU R128) of interleaved `f32` samples.
+struct LoudnessMeter {
+    num_channels: usize,
+    /// Weight of each channel in the sum (BS.1770): the LFE channel is left out, and surround
+    /// channels count for 1.5 dB more.
+    weights: Vec<f64>,
+    /// K-weighting filter (a high shelf and a high pass) of each channel.
+    filters: Vec<[Biquad; 2]>,
+    true_peak: TruePeakMeter,
+    /// Number of samples in a segment of 100 ms.
+    segment_len: usize,
+    /// Number of samples in the current segment so far.
+    segment_fill: usize,
+    /// Sum of the squared K-weighted samples of each channel in the current segment.
+    segment_energy: Vec<f64>,
+    /// Weighted mean square of each completed segment. Blocks of 400 ms (momentary) and 3 s
+    /// (short-term) consist of whole segments.
+    segments: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    /// Create a loudness meter.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the samples.
+    /// * `channels` - Number of channels, in the default order for the number of channels.
+    fn new(sample_rate: u32, channels: u16) -> Self {
+        let num_channels = channels.max(1) as usize;
+        let weights = (0..num_channels)
+            .map(|channel| match (num_channels, channel) {
+                (6.., 3) => 0.0,
+                (6.., 4..) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        let sample_rate = sample_rate.max(1) as f64;
+
+        Self {
+            num_channels,
+            weights,
+            filters: vec![Biquad::k_weighting(sample_rate); num_channels],
+            true_peak: TruePeakMeter::new(num_channels),
+            segment_len: ((sample_rate / 10.0).round() as usize).max(1),
+            segment_fill: 0,
+            segment_energy: vec![0.0; num_channels],
+            segments: Vec::new(),
+        }
+    }
+
+    /// Measure samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples.
+    fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.num_channels) {
+            self.true_peak.push(frame);
+            for (channel, &sample) in frame.iter().enumerate() {
+                let [shelf, high_pass] = &mut self.filters[channel];
+                let weighted = high_pass.process(shelf.process(sample as f64));
+                self.segment_energy[channel] += weighted * weighted;
+            }
+
+            self.segment_fill += 1;
+            if self.segment_fill == self.segment_len {
+                let power = self
+                    .segment_energy
+                    .iter()
+                    .zip(&self.weights)
+                    .map(|(energy, weight)| weight * energy / self.segment_len as f64)
+                    .sum();
+                self.segments.push(power);
+                self.segment_fill = 0;
+                self.segment_energy.fill(0.0);
+            }
+        }
+    }
+
+    /// Compute the loudness of all samples. Samples at the end that do not make a whole segment
+    /// of 100 ms are left out of the loudness (not of the true peak).
+    fn finish(self) -> LoudnessReport {
+        let blocks = |num_segments: usize| {
+            self.segments
+                .windows(num_segments)
+                .map(mean)
+                .collect::<Vec<_>>()
+        };
+
+        LoudnessReport {
+            integrated_lufs: integrated_loudness(&blocks(4)),
+            true_peak: to_db(self.true_peak.peak),
+            lra: loudness_range(&blocks(30)),
+        }
+    }
+}
+
+/// Compute the integrated loudness from the power of the momentary (400 ms) blocks, with an
+/// absolute gate at -70 LUFS and a relative gate 10 LU below the loudness of the blocks that pass
+/// the absolute gate.
+///
+/// # Arguments
+///
+/// * `blocks` - Weighted mean square of each block.
+fn integrated_loudness(blocks: &[f64]) -> f64 {
+    let gated = gate(blocks, 10.0);
+    if gated.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+    loudness(mean(&gated))
+}
+
+/// Compute the loudness range from the power of the short-term (3 s) blocks (EBU Tech 3342), with
+/// an absolute gate at -70 LUFS and a relative gate 20 LU below the loudness of the blocks that
+/// pass the absolute gate.
+///
+/// # Arguments
+///
+/// * `blocks` - Weighted mean square of each block.
+fn loudness_range(blocks: &[f64]) -> f64 {
+    let mut values = gate(blocks, 20.0)
+        .into_iter()
+        .map(loudness)
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let percentile = |p: f64| values[((values.len() - 1) as f64 * p).round() as usize];
+
+    percentile(0.95) - percentile(0.10)
+}
+
+/// Keep the blocks that pass the absolute gate, and the relative gate below their loudness.
+///
+/// # Arguments
+///
+/// * `blocks` - Weighted mean square of each block.
+/// * `relative_gate` - Distance of the relative gate in LU.
+fn gate(blocks: &[f64], relative_gate: f64) -> Vec<f64> {
+    let above_absolute = blocks
+        .iter()
+        .copied()
+        .filter(|&block| loudness(block) > ABSOLUTE_GATE)
+        .collect::<Vec<_>>();
+    if above_absolute.is_empty() {
+        return above_absolute;
+    }
+    let relative_gate = loudness(mean(&above_absolute)) - relative_gate;
+
+    above_absolute
+        .into_iter()
+        .filter(|&block| loudness(block) > relative_gate)
+        .collect()
+}
+
+/// Loudness in LUFS of a weighted mean square.
+fn loudness(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+/// Level in dB of an amplitude.
+fn to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Second order IIR filter (transposed direct form II).
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+impl Biquad {
+    /// Create the two stages of the K-weighting filter of BS.1770 for a sample rate: a high shelf
+    /// of about +4 dB above 1.5 kHz (for the head), and a high pass at 38 Hz. The coefficients
+    /// are derived for any sample rate, and match those in BS.1770 at 48 kHz.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate in Hz.
+    fn k_weighting(sample_rate: f64) -> [Self; 2] {
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Self::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Self::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        [shelf, high_pass]
+    }
+
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.state[0];
+        self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// Measures the true peak of samples, by interpolating three points between each pair of samples
+/// (four times oversampling) with a windowed sinc filter.
+struct TruePeakMeter {
+    /// Filter taps of each of the interpolated points.
+    phases: [[f64; TruePeakMeter::TAPS]; TruePeakMeter::FACTOR],
+    /// Last samples of each channel, the latest first.
+    history: Vec<[f64; TruePeakMeter::TAPS]>,
+    /// Highest absolute value so far.
+    peak: f64,
+}
+
+impl TruePeakMeter {
+    /// Oversampling factor.
+    const FACTOR: usize = 4;
+    /// Number of filter taps for each interpolated point.
+    const TAPS: usize = 12;
+
+    fn new(num_channels: usize) -> Self {
+        let len = Self::FACTOR * Self::TAPS;
+        let center = (len - 1) as f64 / 2.0;
+        let mut phases = [[0.0; Self::TAPS]; Self::FACTOR];
+        for (phase, taps) in phases.iter_mut().enumerate() {
+            for (tap, coefficient) in taps.iter_mut().enumerate() {
+                let n = tap * Self::FACTOR + phase;
+                let t = (n as f64 - center) / Self::FACTOR as f64;
+                let sinc = if t == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t)
+                };
+                let window =
+                    0.5 - 0.5 * (2.0 * std::f64::consts::PI * (n as f64 + 0.5) / len as f64).cos();
+                *coefficient = sinc * window;
+            }
+            // Each point is a weighted average of the samples around it.
+            let sum = taps.iter().sum::<f64>();
+            taps.iter_mut().for_each(|coefficient| *coefficient /= sum);
+        }
+
+        Self {
+            phases,
+            history: vec![[0.0; Self::TAPS]; num_channels],
+            peak: 0.0,
+        }
+    }
+
+    /// Measure a frame of samples (one sample of each channel).
+    fn push(&mut self, frame: &[f32]) {
+        for (history, &sample) in self.history.iter_mut().zip(frame) {
+            history.copy_within(..Self::TAPS - 1, 1);
+            history[0] = sample as f64;
+            self.peak = self.peak.max(history[0].abs());
+            for taps in &self.phases {
+                let interpolated = taps
+                    .iter()
+                    .zip(history.iter())
+                    .map(|(coefficient, sample)| coefficient * sample)
+                    .sum::<f64>();
+                self.peak = self.peak.max(interpolated.abs());
+            }
+        }
+    }
+}
+
+/// Applies a gain to interleaved samples, and limits their peaks so that the gain does not make
+/// them clip. The limiter reduces the gain at once when a sample would go over the ceiling, and
+/// recovers over about 50 ms.
+#[derive(Debug, Clone)]
+pub(crate) struct GainLimiter {
+    gain: f32,
+    ceiling: f32,
+    /// Current gain reduction of the limiter (1 for none).
+    reduction: f32,
+    /// Part of the gain reduction that remains after a sample.
+    release: f32,
+}
+
+impl GainLimiter {
+    /// Create a gain stage with a limiter at -1 dBFS.
+    ///
+    /// # Arguments
+    ///
+    /// * `gain_db` - Gain in dB.
+    /// * `sample_rate` - Sample rate of the samples.
+    pub(crate) fn new(gain_db: f64, sample_rate: u32) -> Self {
+        Self {
+            gain: 10f64.powf(gain_db / 20.0) as f32,
+            ceiling: 10f64.powf(LIMITER_CEILING / 20.0) as f32,
+            reduction: 1.0,
+            release: (-1.0 / (0.05 * sample_rate.max(1) as f64)).exp() as f32,
+        }
+    }
+
+    /// Apply the gain to samples in place.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples.
+    /// * `channels` - Number of channels. Channels are limited together, so that the stereo image
+    ///   stays the same.
+    pub(crate) fn apply(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_exact_mut(channels.max(1)) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+                * self.gain;
+            self.reduction = 1.0 - (1.0 - self.reduction) * self.release;
+            if peak * self.reduction > self.ceiling {
+                self.reduction = self.ceiling / peak;
+            }
+            let gain = self.gain * self.reduction;
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+}
+
+/// Re-encodes an audio stream of a transcode with a gain, to normalize its loudness (see
+/// [`crate::Transcoder::normalize_loudness`]).
+///
+/// The stream is encoded with the codec of the source if the destination container supports it,
+/// or with the default audio codec of the container otherwise, at the same sample rate (if the
+/// codec supports it) and channels.
+pub(crate) struct LoudnessNormalizer {
+    decoder: AudioDecoderSplit,
+    limiter: GainLimiter,
+    /// Converts to the sample rate of the encoder, if it does not support the source rate.
+    resampler: Option<Resampler>,
+    /// Converts packed `f32` samples to the sample format of the encoder, if it differs.
+    converter: Option<AvResampler>,
+    encoder: AvAudioEncoder,
+    channel_layout: AvChannelLayout,
+    num_channels: usize,
+    frame_size: usize,
+    /// Samples that do not fill a frame of the encoder yet.
+    pending: Vec<f32>,
+    /// Timestamp of the next frame to encode, in the time base of the encoder.
+    next_pts: Option<i64>,
+    time_base: AvRational,
+    writer_stream_index: usize,
+}
+
+impl LoudnessNormalizer {
+    /// Set up the re-encode of an audio stream, and add its output stream to the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader of the source.
+    /// * `stream_info` - Audio stream of the source, with the disposition and metadata of the
+    ///   output stream.
+    /// * `gain_db` - Gain to apply in dB.
+    /// * `output` - Encoder of the destination.
+    pub(crate) fn new(
+        reader: &Reader,
+        stream_info: StreamInfo,
+        gain_db: f64,
+        output: &mut Encoder,
+    ) -> Result<Self> {
+        let decoder = AudioDecoderSplit::new(reader, stream_info.index).map_err(decode_error)?;
+        let num_channels = decoder.channels() as usize;
+        let source_codec_id = reader
+            .input
+            .stream(stream_info.index)
+            .ok_or(AvError::StreamNotFound)
+            .map_err(|err| decode_error(err.into()))?
+            .parameters()
+            .id();
+
+        let (codec_id, global_header) = output
+            .audio_codec_for(source_codec_id)
+            .map_err(encode_error)?;
+        let codec = ffmpeg::encoder::find(codec_id)
+            .ok_or(AvError::EncoderNotFound)
+            .map_err(|err| encode_error(err.into()))?;
+        let (sample_rate, sample_format) = match codec.audio() {
+            Ok(audio) => (
+                pick_sample_rate(audio.rates(), decoder.sample_rate()),
+                pick_sample_format(audio.formats()),
+            ),
+            Err(_) => (decoder.sample_rate(), SAMPLE_FORMAT),
+        };
+        let channel_layout = AvChannelLayout::default(num_channels as i32);
+        let time_base = AvRational::new(1, sample_rate as i32);
+
+        let mut context = codec_context_as(&codec)
+            .and_then(|context| context.encoder().audio())
+            .map_err(|err| encode_error(err.into()))?;
+        context.set_rate(sample_rate as i32);
+        context.set_channel_layout(channel_layout);
+        context.set_channels(num_channels as i32);
+        context.set_format(sample_format);
+        context.set_time_base(time_base);
+        if global_header {
+            context.set_flags(AvCodecFlags::GLOBAL_HEADER);
+        }
+        let encoder = context.open_as(codec).map_err(|err| {
+            encode_error(
+                ErrorContext::new("encoder.open")
+                    .with_stream_index(stream_info.index)
+                    .wrap(Error::from_codec_error(err, codec_id)),
+            )
+        })?;
+
+        let resampler = match sample_rate == decoder.sample_rate() {
+            true => None,
+            false => Some(
+                Resampler::new(
+                    decoder.sample_spec(),
+                    SampleSpec {
+                        sample_rate,
+                        channels: decoder.channels(),
+                    },
+                )
+                .map_err(encode_error)?,
+            ),
+        };
+        let converter = match sample_format == SAMPLE_FORMAT {
+            true => None,
+            false => Some(
+                AvResampler::get(
+                    SAMPLE_FORMAT,
+                    channel_layout,
+                    sample_rate,
+                    sample_format,
+                    channel_layout,
+                    sample_rate,
+                )
+                .map_err(|err| encode_error(err.into()))?,
+            ),
+        };
+        // Encoders that take any number of samples per frame report a frame size of zero.
+        let frame_size = match encoder.frame_size() {
+            0 => 1024,
+            frame_size => frame_size as usize,
+        };
+
+        let writer_stream_index = output
+            .add_copy_stream(
+                stream_info.with_codec_parameters(AvParameters::from(&encoder), time_base),
+            )
+            .map_err(encode_error)?;
+
+        Ok(Self {
+            limiter: GainLimiter::new(gain_db, sample_rate),
+            decoder,
+            resampler,
+            converter,
+            encoder,
+            channel_layout,
+            num_channels,
+            frame_size,
+            pending: Vec::new(),
+            next_pts: None,
+            time_base,
+            writer_stream_index,
+        })
+    }
+
+    /// Decode a packet of the source stream, and encode the samples that fill whole frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet of the source stream.
+    /// * `output` - Encoder of the destination.
+    pub(crate) fn push(&mut self, packet: Packet, output: &mut Encoder) -> Result<()> {
+        if let Some(samples) = self.decoder.decode_raw(packet).map_err(decode_error)? {
+            self.process(samples, output)?;
+        }
+        Ok(())
+    }
+
+    /// Decode and encode the samples that the decoder and encoder still hold, after the last
+    /// packet of the source stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Encoder of the destination.
+    pub(crate) fn finish(&mut self, output: &mut Encoder) -> Result<()> {
+        while let Some(samples) = self.decoder.drain_raw().map_err(decode_error)? {
+            self.process(samples, output)?;
+        }
+        if let Some(resampler) = self.resampler.as_mut() {
+            let samples = resampler.flush_raw().map_err(encode_error)?;
+            self.buffer(&samples);
+        }
+        self.encode_pending(output, true)?;
+
+        self.encoder
+            .send_eof()
+            .context(ErrorContext::new("encoder.send_eof"))
+            .map_err(encode_error)?;
+        self.write_packets(output)
+    }
+
+    /// Apply the gain to decoded samples and encode them.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Decoded samples in packed `f32` format.
+    /// * `output` - Encoder of the destination.
+    fn process(&mut self, samples: RawSamples, output: &mut Encoder) -> Result<()> {
+        if self.next_pts.is_none() {
+            self.next_pts = Some(
+                samples
+                    .pts()
+                    .unwrap_or_default()
+                    .rescale(self.decoder.time_base(), self.time_base),
+            );
+        }
+        match self.resampler.as_mut() {
+            Some(resampler) => {
+                let samples = resampler.convert_raw(&samples).map_err(encode_error)?;
+                self.buffer(&samples);
+            }
+            None => self.buffer(&samples),
+        }
+
+        self.encode_pending(output, false)
+    }
+
+    /// Apply the gain to samples and add them to the pending samples.
+    fn buffer(&mut self, samples: &RawSamples) {
+        let start = self.pending.len();
+        self.pending.extend(interleaved_samples(samples));
+        self.limiter
+            .apply(&mut self.pending[start..], self.num_channels);
+    }
+
+    /// Encode the pending samples in frames of the frame size of the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Encoder of the destination.
+    /// * `partial` - Whether or not to encode the samples that do not fill a whole frame too.
+    fn encode_pending(&mut self, output: &mut Encoder, partial: bool) -> Result<()> {
+        let frame_len = self.frame_size * self.num_channels;
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut offset = 0;
+        let result = (|| {
+            while pending.len() - offset >= frame_len || (partial && pending.len() > offset) {
+                let end = (offset + frame_len).min(pending.len());
+                let frame = self.frame(&pending[offset..end]).map_err(encode_error)?;
+                self.encoder
+                    .send_frame(&frame)
+                    .context(ErrorContext::new("encoder.send_frame").with_pts(frame.pts()))
+                    .map_err(encode_error)?;
+                self.write_packets(output)?;
+                offset = end;
+            }
+            Ok(())
+        })();
+        pending.drain(..offset);
+        self.pending = pending;
+
+        result
+    }
+
+    /// Create a frame for the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples, of at most the frame size of the encoder.
+    fn frame(&mut self, samples: &[f32]) -> Result<RawSamples> {
+        let num_samples = samples.len() / self.num_channels;
+        let mut frame = RawSamples::new(SAMPLE_FORMAT, num_samples, self.channel_layout);
+        frame.set_rate(self.encoder.rate());
+        for (bytes, sample) in frame
+            .data_mut(0)
+            .chunks_exact_mut(std::mem::size_of::<f32>())
+            .zip(samples)
+        {
+            bytes.copy_from_slice(&sample.to_ne_bytes());
+        }
+        if let Some(converter) = self.converter.as_mut() {
+            let mut converted = RawSamples::empty();
+            converter
+                .run(&frame, &mut converted)
+                .context(ErrorContext::new("resampler.run"))?;
+            frame = converted;
+        }
+        frame.set_pts(self.next_pts);
+        self.next_pts = self.next_pts.map(|pts| pts + num_samples as i64);
+
+        Ok(frame)
+    }
+
+    /// Write the packets that the encoder has ready to the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Encoder of the destination.
+    fn write_packets(&mut self, output: &mut Encoder) -> Result<()> {
+        loop {
+            let mut packet = AvPacket::empty();
+            match self.encoder.receive_packet(&mut packet) {
+                Ok(()) => output
+                    .write_copy(
+                        Packet::new(packet, self.time_base),
+                        self.writer_stream_index,
+                    )
+                    .map_err(encode_error)?,
+                Err(AvError::Eof) => return Ok(()),
+                Err(AvError::Other { errno }) if errno == EAGAIN => return Ok(()),
+                Err(err) => {
+                    return Err(encode_error(
+                        ErrorContext::new("encoder.receive_packet").wrap(err),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+unsafe impl Send for LoudnessNormalizer {}
+unsafe impl Sync for LoudnessNormalizer {}
+
+/// Pick the sample rate of an encoder: the source rate if the encoder supports it (or supports
+/// any rate), or else the closest one that it supports.
+///
+/// # Arguments
+///
+/// * `rates` - Sample rates that the encoder supports, if it only supports some.
+/// * `source_rate` - Sample rate of the source.
+fn pick_sample_rate(rates: Option<impl Iterator<Item = i32>>, source_rate: u32) -> u32 {
+    rates
+        .and_then(|rates| {
+            rates
+                .filter(|&rate| rate > 0)
+                .min_by_key(|&rate| (rate as i64 - source_rate as i64).abs())
+        })
+        .map_or(source_rate, |rate| rate as u32)
+}
+
+/// Pick the sample format of an encoder: `f32` (packed, then planar) if the encoder supports it,
+/// or else the first format that it supports.
+///
+/// # Arguments
+///
+/// * `formats` - Sample formats that the encoder supports, if known.
+fn pick_sample_format(formats: Option<impl Iterator<Item = AvSample>>) -> AvSample {
+    let Some(formats) = formats else {
+        return SAMPLE_FORMAT;
+    };
+    let formats = formats.collect::<Vec<_>>();
+    [SAMPLE_FORMAT, AvSample::F32(AvSampleType::Planar)]
+        .into_iter()
+        .find(|format| formats.contains(format))
+        .or(formats.first().copied())
+        .unwrap_or(SAMPLE_FORMAT)
+}
+
+/// Get the samples of packed `f32` raw samples.
+///
+/// # Arguments
+///
+/// * `samples` - Samples in packed `f32` format.
+fn interleaved_samples(samples: &RawSamples) -> Vec<f32> {
+    let len = samples.samples() * samples.channels() as usize;
+    samples
+        .data(0)
+        .chunks_exact(std::mem::size_of::<f32>())
+        .take(len)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generate seconds of a 1 kHz sine at 48 kHz with the same peak level in every channel.
+    fn tone(level_db: f64, secs: f64, channels: usize) -> Vec<f32> {
+        let amplitude = 10f64.powf(level_db / 20.0);
+        (0..(secs * 48000.0) as usize)
+            .flat_map(|i| {
+                let sample =
+                    amplitude * (2.0 * std::f64::consts::PI * 1000.0 * i as f64 / 48000.0).sin();
+                std::iter::repeat_n(sample as f32, channels)
+            })
+            .collect()
+    }
+
+    fn measure(samples: &[f32], channels: u16) -> LoudnessReport {
+        let mut meter = LoudnessMeter::new(48000, channels);
+        // In chunks that do not line up with segments.
+        for chunk in samples.chunks(999 * channels as usize) {
+            meter.push(chunk);
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn test_measure_loudness_of_tone() {
+        // EBU Tech 3341: a stereo 1 kHz sine at -23 dBFS reads -23 LUFS.
+        for level in [-23.0, -33.0] {
+            let report = measure(&tone(level, 5.0, 2), 2);
+            assert!((report.integrated_lufs - level).abs() < 0.1, "{report:?}");
+            assert!((report.true_peak - level).abs() < 0.1, "{report:?}");
+            assert!(report.lra.abs() < 0.1, "{report:?}");
+        }
+
+        // Mono counts once.
+        let report = measure(&tone(-20.0, 5.0, 1), 1);
+        assert!((report.integrated_lufs - (-20.0 - 3.01)).abs() < 0.1);
+
+        // Half loud, half 10 dB quieter: the range is the difference.
+        let mut samples = tone(-20.0, 6.0, 2);
+        samples.extend(tone(-30.0, 6.0, 2));
+        let report = measure(&samples, 2);
+        assert!((report.lra - 10.0).abs() < 0.5, "{report:?}");
+
+        let silence = measure(&vec![0.0; 48000 * 2], 2);
+        assert_eq!(silence.integrated_lufs, f64::NEG_INFINITY);
+        assert_eq!(silence.true_peak, f64::NEG_INFINITY);
+        assert_eq!(normalization_gain(&silence, -23.0), 0.0);
+    }
+
+    #[test]
+    fn test_true_peak_between_samples() {
+        // A sine at a quarter of the sample rate, sampled 45 degrees off its peaks: all samples
+        // are at 0.707 while the signal peaks at 1.
+        let samples = (0..4800)
+            .map(|i| {
+                (std::f64::consts::FRAC_PI_2 * i as f64 + std::f64::consts::FRAC_PI_4).sin() as f32
+            })
+            .collect::<Vec<_>>();
+        let report = measure(&samples, 1);
+        assert!(to_db(samples.iter().fold(0.0f32, |p, s| p.max(s.abs())) as f64) < -2.9);
+        assert!(report.true_peak.abs() < 0.5, "{report:?}");
+    }
+
+    #[test]
+    fn test_gain_limiter() {
+        // Normalize a quiet tone.
+        let mut samples = tone(-33.0, 5.0, 2);
+        let report = measure(&samples, 2);
+        let gain = normalization_gain(&report, -23.0);
+        GainLimiter::new(gain, 48000).apply(&mut samples, 2);
+        let normalized = measure(&samples, 2);
+        assert!(
+            (normalized.integrated_lufs + 23.0).abs() < 0.5,
+            "{normalized:?}"
+        );
+
+        // A gain that would clip is limited to the ceiling.
+        let mut samples = tone(-3.0, 2.0, 2);
+        GainLimiter::new(12.0, 48000).apply(&mut samples, 2);
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(to_db(peak as f64) <= LIMITER_CEILING + 1e-3, "{peak}");
+        assert!(to_db(peak as f64) > LIMITER_CEILING - 0.5, "{peak}");
+    }
+
+    #[test]
+    fn test_pick_sample_format_and_rate() {
+        let s16 = AvSample::I16(AvSampleType::Packed);
+        let f32_planar = AvSample::F32(AvSampleType::Planar);
+        assert_eq!(
+            pick_sample_format(Some([s16, f32_planar].into_iter())),
+            f32_planar
+        );
+        assert_eq!(pick_sample_format(Some([s16].into_iter())), s16);
+        assert_eq!(
+            pick_sample_format(None::<std::iter::Empty<AvSample>>),
+            SAMPLE_FORMAT
+        );
+
+        assert_eq!(
+            pick_sample_rate(None::<std::iter::Empty<i32>>, 44100),
+            44100
+        );
+        assert_eq!(
+            pick_sample_rate(Some([48000, 44100, 32000].into_iter()), 44100),
+            44100
+        );
+        assert_eq!(
+            pick_sample_rate(Some([48000, 24000].into_iter()), 44100),
+            48000
+        );
+    }
+}
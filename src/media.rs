@@ -0,0 +1,528 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::VecDeque;
+
+use ffmpeg::media::Type as AvMediaType;
+
+use crate::audio::{AudioDecoderSplit, Samples};
+use crate::decode::DecoderSplit;
+use crate::io::Reader;
+use crate::options::Options;
+use crate::{Error, Frame, Locator, StreamInfo, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Decoded item of a [`MediaDecoder`].
+pub enum MediaItem {
+    /// Decoded video frame and its timestamp.
+    Video(Time, Frame),
+    /// Decoded audio samples and their timestamp.
+    Audio(Time, Samples),
+    /// Stream that appeared in the source after it was opened (see
+    /// [`crate::Reader::take_new_streams`]), and the timestamp of the packet that was read when it
+    /// appeared. If the source had no audio stream so far, a new audio stream is decoded from then
+    /// on.
+    StreamAdded(Time, StreamInfo),
+}
+
+impl MediaItem {
+    /// Get the timestamp of the item (relative to the stream).
+    pub fn timestamp(&self) -> &Time {
+        match self {
+            MediaItem::Video(timestamp, _) => timestamp,
+            MediaItem::Audio(timestamp, _) => timestamp,
+            MediaItem::StreamAdded(timestamp, _) => timestamp,
+        }
+    }
+}
+
+/// Decode video and audio from a single source together. The source is demuxed once and each
+/// stream is decoded with its own decoder. Items are returned in timestamp order.
+///
+/// # Backpressure
+///
+/// To be able to return items in order, decoded items are buffered in a queue per stream. Each
+/// queue holds at most a fixed number of items (64 by default, see
+/// [`MediaDecoder::new_with_options_and_queue_capacity`]):
+///
+/// * [`MediaDecoder::decode`] never drops items. If one queue is full while the other stream has
+///   nothing to offer (for example because the audio stream ended early), items from the full
+///   queue are returned without waiting any further.
+/// * [`MediaDecoder::decode_video`] and [`MediaDecoder::decode_audio`] only return items of one
+///   stream. Items of the other stream are still decoded (since the source is demuxed only once),
+///   but when its queue is full, the oldest items are dropped.
+///
+/// # New streams
+///
+/// Some sources (like live MPEG-TS sources) announce streams only after they were opened.
+/// [`MediaDecoder::decode`] returns [`MediaItem::StreamAdded`] for each of them as soon as it
+/// appears, ahead of items that are still buffered. A source without audio stream starts decoding
+/// the first audio stream that appears this way.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = MediaDecoder::new(&PathBuf::from("video.mp4").into()).unwrap();
+/// decoder
+///     .decode_iter()
+///     .take_while(Result::is_ok)
+///     .map(Result::unwrap)
+///     .for_each(|item| match item {
+///         MediaItem::Video(ts, frame) => { /* Do something with frame... */ }
+///         MediaItem::Audio(ts, samples) => { /* Do something with samples... */ }
+///         MediaItem::StreamAdded(ts, stream_info) => { /* A stream appeared... */ }
+///     });
+/// ```
+pub struct MediaDecoder {
+    video_decoder: DecoderSplit,
+    video_stream_index: usize,
+    audio_decoder: Option<AudioDecoderSplit>,
+    audio_stream_index: Option<usize>,
+    queues: Queues<Frame, Samples>,
+    /// Streams that appeared after the source was opened, which have not been returned yet.
+    new_streams: VecDeque<(Time, StreamInfo)>,
+    exhausted: bool,
+    reader: Reader,
+}
+
+impl MediaDecoder {
+    /// Default maximum number of decoded items buffered per stream.
+    const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+    /// Create a new media decoder for the specified file.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    pub fn new(source: &Locator) -> Result<Self> {
+        Self::from_reader(Reader::new(source)?, Self::DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Create a new media decoder for the specified file with input options.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    /// * `options` - The input options.
+    pub fn new_with_options(source: &Locator, options: &Options) -> Result<Self> {
+        Self::from_reader(
+            Reader::new_with_options(source, options)?,
+            Self::DEFAULT_QUEUE_CAPACITY,
+        )
+    }
+
+    /// Create a new media decoder for the specified file with input options and a custom maximum
+    /// number of decoded items to buffer per stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to file to decode.
+    /// * `options` - The input options.
+    /// * `queue_capacity` - Maximum number of decoded items to buffer per stream (at least 1).
+    pub fn new_with_options_and_queue_capacity(
+        source: &Locator,
+        options: &Options,
+        queue_capacity: usize,
+    ) -> Result<Self> {
+        Self::from_reader(Reader::new_with_options(source, options)?, queue_capacity)
+    }
+
+    /// Whether or not the source has an audio stream. If not, only video items are returned.
+    #[inline]
+    pub fn has_audio(&self) -> bool {
+        self.audio_decoder.is_some()
+    }
+
+    /// Get the video decoder part.
+    #[inline]
+    pub fn video_decoder(&self) -> &DecoderSplit {
+        &self.video_decoder
+    }
+
+    /// Get the audio decoder part, if the source has an audio stream.
+    #[inline]
+    pub fn audio_decoder(&self) -> Option<&AudioDecoderSplit> {
+        self.audio_decoder.as_ref()
+    }
+
+    /// Decode items through iterator interface. This is similar to `decode` but it returns items
+    /// through an infinite iterator.
+    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<MediaItem>> + '_ {
+        std::iter::from_fn(move || Some(self.decode()))
+    }
+
+    /// Decode the next item in timestamp order.
+    ///
+    /// # Return value
+    ///
+    /// The next decoded video frame or audio samples. When all streams are exhausted and all
+    /// buffered items have been returned, [`Error::ReadExhausted`] is returned.
+    pub fn decode(&mut self) -> Result<MediaItem> {
+        loop {
+            if let Some((timestamp, stream_info)) = self.new_streams.pop_front() {
+                return Ok(MediaItem::StreamAdded(timestamp, stream_info));
+            }
+
+            if let Some(item) = self.queues.pop(self.exhausted) {
+                return Ok(match item {
+                    Item::Video(timestamp, frame) => MediaItem::Video(timestamp, frame),
+                    Item::Audio(timestamp, samples) => MediaItem::Audio(timestamp, samples),
+                });
+            }
+
+            if self.exhausted {
+                return Err(Error::ReadExhausted);
+            }
+
+            self.feed()?;
+        }
+    }
+
+    /// Decode the next video frame. Audio that is decoded in the meantime is buffered for later
+    /// calls to [`MediaDecoder::decode`] or [`MediaDecoder::decode_audio`], dropping the oldest
+    /// samples when the queue is full.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the frame timestamp (relative to the stream) and the frame itself.
+    pub fn decode_video(&mut self) -> Result<(Time, Frame)> {
+        loop {
+            if let Some(item) = self.queues.video.pop_front() {
+                return Ok(item);
+            }
+
+            if self.exhausted {
+                return Err(Error::ReadExhausted);
+            }
+
+            self.feed()?;
+        }
+    }
+
+    /// Decode the next audio samples. Video that is decoded in the meantime is buffered for later
+    /// calls to [`MediaDecoder::decode`] or [`MediaDecoder::decode_video`], dropping the oldest
+    /// frames when the queue is full.
+    ///
+    /// # Return value
+    ///
+    /// A tuple of the samples timestamp (relative to the stream) and the samples themselves. If
+    /// the source has no audio stream, [`Error::ReadExhausted`] is returned.
+    pub fn decode_audio(&mut self) -> Result<(Time, Samples)> {
+        loop {
+            if let Some(item) = self.queues.audio.pop_front() {
+                return Ok(item);
+            }
+
+            if self.exhausted || !self.has_audio() {
+                return Err(Error::ReadExhausted);
+            }
+
+            self.feed()?;
+        }
+    }
+
+    /// Read a single packet and feed it to the decoder of its stream. Decoded items are pushed onto
+    /// the corresponding queue.
+    fn feed(&mut self) -> Result<()> {
+        let (stream_index, packet) = match self.reader.read_any() {
+            Ok(packet) => packet,
+            Err(Error::ReadExhausted) => {
+                self.exhausted = true;
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        for new_stream_index in self.reader.take_new_streams() {
+            self.add_stream(new_stream_index, packet.pts())?;
+        }
+
+        if stream_index == self.video_stream_index {
+            if let Some((timestamp, frame)) = self.video_decoder.decode(packet)? {
+                self.queues.push_video(timestamp, frame);
+            }
+        } else if Some(stream_index) == self.audio_stream_index {
+            if let Some(audio_decoder) = self.audio_decoder.as_mut() {
+                if let Some((timestamp, samples)) = audio_decoder.decode(packet)? {
+                    self.queues.push_audio(timestamp, samples);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Note a stream that appeared after the source was opened, and start decoding it if it is the
+    /// first audio stream. A new audio stream that cannot be decoded is left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the new stream.
+    /// * `timestamp` - Timestamp of the packet that was read when the stream appeared.
+    fn add_stream(&mut self, stream_index: usize, timestamp: Time) -> Result<()> {
+        let stream_info = self.reader.stream_info(stream_index)?;
+        if stream_info.medium() == AvMediaType::Audio && self.audio_decoder.is_none() {
+            match AudioDecoderSplit::new(&self.reader, stream_index) {
+                Ok(audio_decoder) => {
+                    self.audio_decoder = Some(audio_decoder);
+                    self.audio_stream_index = Some(stream_index);
+                    self.queues.has_audio = true;
+                }
+                Err(err) => tracing::warn!(
+                    target: "video",
+                    "not decoding new audio stream {stream_index}: {err}",
+                ),
+            }
+        }
+        self.new_streams.push_back((timestamp, stream_info));
+
+        Ok(())
+    }
+
+    fn from_reader(reader: Reader, queue_capacity: usize) -> Result<Self> {
+        let video_stream_index = reader.best_video_stream_index()?;
+        let video_decoder = DecoderSplit::new(&reader, video_stream_index, None)?;
+
+        let (audio_decoder, audio_stream_index) = match reader.best_audio_stream_index() {
+            Ok(audio_stream_index) => (
+                Some(AudioDecoderSplit::new(&reader, audio_stream_index)?),
+                Some(audio_stream_index),
+            ),
+            Err(_) => (None, None),
+        };
+
+        Ok(Self {
+            video_decoder,
+            video_stream_index,
+            queues: Queues::new(queue_capacity, audio_decoder.is_some()),
+            audio_decoder,
+            audio_stream_index,
+            new_streams: VecDeque::new(),
+            exhausted: false,
+            reader,
+        })
+    }
+}
+
+unsafe impl Send for MediaDecoder {}
+unsafe impl Sync for MediaDecoder {}
+
+/// Item popped from [`Queues`].
+enum Item<V, A> {
+    Video(Time, V),
+    Audio(Time, A),
+}
+
+/// Bounded per-stream queues that merge video and audio items in timestamp order.
+struct Queues<V, A> {
+    video: VecDeque<(Time, V)>,
+    audio: VecDeque<(Time, A)>,
+    capacity: usize,
+    has_audio: bool,
+}
+
+impl<V, A> Queues<V, A> {
+    fn new(capacity: usize, has_audio: bool) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            video: VecDeque::with_capacity(capacity),
+            audio: VecDeque::with_capacity(capacity),
+            capacity,
+            has_audio,
+        }
+    }
+
+    /// Push a video item, dropping the oldest video item if the queue is full.
+    fn push_video(&mut self, timestamp: Time, item: V) {
+        if self.video.len() >= self.capacity {
+            self.video.pop_front();
+        }
+        self.video.push_back((timestamp, item));
+    }
+
+    /// Push an audio item, dropping the oldest audio item if the queue is full.
+    fn push_audio(&mut self, timestamp: Time, item: A) {
+        if self.audio.len() >= self.capacity {
+            self.audio.pop_front();
+        }
+        self.audio.push_back((timestamp, item));
+    }
+
+    /// Pop the next item in timestamp order. An item is only returned if it is certain that no
+    /// earlier item can arrive on the other stream, unless its queue is full or `flush` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `flush` - Whether or not the input is exhausted and all remaining items must be returned.
+    fn pop(&mut self, flush: bool) -> Option<Item<V, A>> {
+        let video_ready = flush || !self.has_audio || self.video.len() >= self.capacity;
+        let audio_ready = flush || self.audio.len() >= self.capacity;
+        let take_video = match (self.video.front(), self.audio.front()) {
+            (Some((video_ts, _)), Some((audio_ts, _))) => {
+                video_ts.as_secs_f64() <= audio_ts.as_secs_f64()
+            }
+            (Some(_), None) if video_ready => true,
+            (None, Some(_)) if audio_ready => false,
+            _ => return None,
+        };
+
+        if take_video {
+            self.video
+                .pop_front()
+                .map(|(timestamp, item)| Item::Video(timestamp, item))
+        } else {
+            self.audio
+                .pop_front()
+                .map(|(timestamp, item)| Item::Audio(timestamp, item))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queues: &mut Queues<u32, u32>, flush: bool) -> Vec<(bool, u32)> {
+        std::iter::from_fn(|| queues.pop(flush))
+            .map(|item| match item {
+                Item::Video(_, value) => (true, value),
+                Item::Audio(_, value) => (false, value),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_queues_ordered() {
+        let mut queues = Queues::new(8, true);
+        queues.push_video(Time::from_secs(0.0), 0);
+        queues.push_video(Time::from_secs(0.04), 1);
+        queues.push_audio(Time::from_secs(0.02), 2);
+        queues.push_audio(Time::from_secs(0.03), 3);
+        assert_eq!(
+            pop_all(&mut queues, false),
+            vec![(true, 0), (false, 2), (false, 3)]
+        );
+        queues.push_audio(Time::from_secs(0.05), 4);
+        assert_eq!(pop_all(&mut queues, false), vec![(true, 1)]);
+        assert_eq!(pop_all(&mut queues, true), vec![(false, 4)]);
+    }
+
+    #[test]
+    fn test_queues_wait_for_other_stream() {
+        let mut queues = Queues::new(2, true);
+        queues.push_video(Time::from_secs(0.0), 0);
+        assert!(queues.pop(false).is_none());
+        queues.push_video(Time::from_secs(0.04), 1);
+        assert_eq!(pop_all(&mut queues, false), vec![(true, 0)]);
+    }
+
+    #[test]
+    fn test_queues_without_audio() {
+        let mut queues = Queues::new(2, false);
+        queues.push_video(Time::from_secs(0.0), 0);
+        assert_eq!(pop_all(&mut queues, false), vec![(true, 0)]);
+    }
+
+    #[test]
+    fn test_decode_stream_added_later() {
+        use ffmpeg::Rational as AvRational;
+
+        use crate::testing::{self, TempPath};
+        use crate::{CodecId, CodecParams, Encoder};
+
+        // More frames than the demuxer analyzes when the source is opened.
+        const NUM_FRAMES_BEFORE: usize = 240;
+        const NUM_FRAMES_AFTER: usize = 30;
+
+        let dir = TempPath::new("new-stream");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // The first segment has a single stream.
+        let before = dir.join("before.ts");
+        testing::write_test_video(&before.clone().into(), testing::frames(NUM_FRAMES_BEFORE));
+
+        // The second segment continues where the first one ends, with a second stream.
+        let packets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut encoder =
+            Encoder::new_packetized(testing::settings(), AvRational::new(1, 90_000), {
+                let packets = packets.clone();
+                move |packet| packets.lock().unwrap().push(packet)
+            })
+            .unwrap();
+        testing::encode_frames(
+            &mut encoder,
+            NUM_FRAMES_BEFORE,
+            testing::frames(NUM_FRAMES_AFTER),
+        );
+        encoder.finish().unwrap();
+        drop(encoder);
+        let packets = std::mem::take(&mut *packets.lock().unwrap());
+        let after = dir.join("after.ts");
+        let mut encoder = Encoder::new(&after.clone().into(), testing::settings()).unwrap();
+        let stream_index = encoder
+            .add_preencoded_stream(&CodecParams::new_video(CodecId::H264, 64, 48))
+            .unwrap();
+        let frames_after = NUM_FRAMES_BEFORE..NUM_FRAMES_BEFORE + NUM_FRAMES_AFTER;
+        for (i, packet) in frames_after.zip(packets) {
+            let mut frame = testing::frame();
+            frame.set_pts(testing::pts(&encoder, i));
+            encoder.encode_raw(frame).unwrap();
+            encoder
+                .write_preencoded(
+                    stream_index,
+                    &packet.data,
+                    packet.pts,
+                    packet.dts,
+                    packet.is_key,
+                )
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let source = dir.join("source.ts");
+        std::fs::write(
+            &source,
+            [
+                std::fs::read(&before).unwrap(),
+                std::fs::read(&after).unwrap(),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+        let mut reader = Reader::new(&source.clone().into()).unwrap();
+        assert_eq!(reader.input.nb_streams(), 1);
+        let mut new_streams = Vec::new();
+        while reader.read_any().is_ok() {
+            new_streams.extend(reader.take_new_streams());
+        }
+        assert_eq!(new_streams, vec![1]);
+
+        let mut decoder = MediaDecoder::new(&source.into()).unwrap();
+        let mut num_frames = 0;
+        let mut added = Vec::new();
+        loop {
+            match decoder.decode() {
+                Ok(MediaItem::Video(..)) => num_frames += 1,
+                Ok(MediaItem::Audio(..)) => panic!("source has no audio"),
+                Ok(MediaItem::StreamAdded(_, stream_info)) => {
+                    added.push((stream_info.index, stream_info.media_type()))
+                }
+                Err(Error::ReadExhausted) => break,
+                Err(err) => panic!("{err}"),
+            }
+        }
+        assert_eq!(added, vec![(1, "video")]);
+        assert_eq!(num_frames, NUM_FRAMES_BEFORE + NUM_FRAMES_AFTER);
+    }
+
+    #[test]
+    fn test_queues_bounded() {
+        let mut queues = Queues::new(2, true);
+        queues.push_audio(Time::from_secs(0.0), 0);
+        queues.push_audio(Time::from_secs(0.02), 1);
+        queues.push_audio(Time::from_secs(0.04), 2);
+        assert_eq!(queues.audio.len(), 2);
+        assert_eq!(pop_all(&mut queues, true), vec![(false, 1), (false, 2)]);
+    }
+}
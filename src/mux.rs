@@ -2,9 +2,11 @@ extern crate ffmpeg_next as ffmpeg;
 
 use std::collections::HashMap;
 
+use ffmpeg::codec::packet::Packet as AvPacket;
 use ffmpeg::codec::Id as AvCodecId;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
+use crate::bitstream::BitstreamFilter;
 use crate::extradata::extract_parameter_sets_h264;
 use crate::ffi::extradata;
 use crate::io::{BufWriter, PacketizedBufWriter, Reader, Write, Writer};
@@ -178,6 +180,7 @@ impl<W: Write> Muxer<W> {
     /// * `stream_info` - Stream information. Usually this information is retrieved by calling
     ///   `reader.stream_info(index)`.
     pub fn with_stream(mut self, stream_info: StreamInfo) -> Result<Self> {
+        let source_stream_info = stream_info.clone();
         let (index, codec_parameters, reader_stream_time_base) = stream_info.into_parts();
 
         let mut writer_stream = self
@@ -189,6 +192,8 @@ impl<W: Write> Muxer<W> {
         let stream_description = StreamDescription {
             index: writer_stream.index(),
             source_time_base: reader_stream_time_base,
+            source_stream_info,
+            filter: None,
         };
 
         self.mapping.insert(index, stream_description);
@@ -211,6 +216,38 @@ impl<W: Write> Muxer<W> {
         Ok(self)
     }
 
+    /// Pass the packets of an input stream through a bitstream filter before muxing them, like
+    /// `h264_mp4toannexb` to mux H.264 from an MP4 file into a format that requires start codes.
+    /// The output stream takes on the codec parameters of the filtered stream, including any
+    /// extradata the filter changes.
+    ///
+    /// The stream must have been added with `with_stream` or `with_streams` first.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the input stream to filter.
+    /// * `name` - Name of the bitstream filter.
+    pub fn with_bitstream_filter(mut self, stream_index: usize, name: &str) -> Result<Self> {
+        if self.have_written_header {
+            return Err(Error::HeaderAlreadyWritten);
+        }
+
+        let stream_description = self
+            .mapping
+            .get_mut(&stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+        let filter = BitstreamFilter::new(name, &stream_description.source_stream_info)?;
+        let (_, codec_parameters, _) = filter.stream_info().clone().into_parts();
+        self.writer
+            .output_mut()
+            .stream_mut(stream_description.index)
+            .ok_or(AvError::StreamNotFound)?
+            .set_parameters(codec_parameters);
+        stream_description.filter = Some(filter);
+
+        Ok(self)
+    }
+
     /// Mux a single packet. This will mux a single packet.
     ///
     /// # Arguments
@@ -218,32 +255,21 @@ impl<W: Write> Muxer<W> {
     /// * `packet` - Packet to mux.
     pub fn mux(&mut self, packet: Packet) -> Result<W::Out> {
         if self.have_written_header {
-            let mut packet = packet.into_inner();
+            let packet = packet.into_inner();
             let stream_description = self
                 .mapping
-                .get(&packet.stream())
-                .ok_or(AvError::StreamNotFound)?;
-
-            let destination_stream = self
-                .writer
-                .output()
-                .stream(stream_description.index)
+                .get_mut(&packet.stream())
                 .ok_or(AvError::StreamNotFound)?;
+            let index = stream_description.index;
+            let source_time_base = stream_description.source_time_base;
 
-            packet.set_stream(destination_stream.index());
-            packet.set_position(-1);
-            packet.rescale_ts(
-                stream_description.source_time_base,
-                destination_stream.time_base(),
-            );
-
-            Ok({
-                if self.interleaved {
-                    self.writer.write_interleaved(&mut packet)?
-                } else {
-                    self.writer.write(&mut packet)?
+            match stream_description.filter.as_mut() {
+                Some(filter) => {
+                    let filtered = filter.filter(Some(Packet::new(packet, source_time_base)))?;
+                    self.write_all(index, filtered)
                 }
-            })
+                None => self.write(index, packet, source_time_base),
+            }
         } else {
             self.have_written_header = true;
             self.writer.write_header()?;
@@ -276,19 +302,82 @@ impl<W: Write> Muxer<W> {
     pub fn finish(&mut self) -> Result<Option<W::Out>> {
         if self.have_written_header && !self.have_written_trailer {
             self.have_written_trailer = true;
-            self.writer.write_trailer().map(Some)
+
+            // Write the packets the bitstream filters are still holding on to first.
+            let mut flushed = Vec::new();
+            for stream_description in self.mapping.values_mut() {
+                if let Some(filter) = stream_description.filter.as_mut() {
+                    flushed.push((stream_description.index, filter.filter(None)?));
+                }
+            }
+            let mut out = W::Out::default();
+            for (index, packets) in flushed {
+                W::append(&mut out, self.write_all(index, packets)?);
+            }
+
+            W::append(&mut out, self.writer.write_trailer()?);
+            Ok(Some(out))
         } else {
             Ok(None)
         }
     }
+
+    /// Write packets that came out of a bitstream filter to an output stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the output stream.
+    /// * `packets` - Filtered packets.
+    fn write_all(&mut self, index: usize, packets: Vec<Packet>) -> Result<W::Out> {
+        let mut out = W::Out::default();
+        for packet in packets {
+            let (packet, time_base) = packet.into_inner_parts();
+            W::append(&mut out, self.write(index, packet, time_base)?);
+        }
+        Ok(out)
+    }
+
+    /// Write a packet to an output stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the output stream.
+    /// * `packet` - Packet to write.
+    /// * `source_time_base` - Time base of the packet.
+    fn write(
+        &mut self,
+        index: usize,
+        mut packet: AvPacket,
+        source_time_base: AvRational,
+    ) -> Result<W::Out> {
+        let destination_stream = self
+            .writer
+            .output()
+            .stream(index)
+            .ok_or(AvError::StreamNotFound)?;
+
+        packet.set_stream(destination_stream.index());
+        packet.set_position(-1);
+        packet.rescale_ts(source_time_base, destination_stream.time_base());
+
+        Ok({
+            if self.interleaved {
+                self.writer.write_interleaved(&mut packet)?
+            } else {
+                self.writer.write(&mut packet)?
+            }
+        })
+    }
 }
 
 unsafe impl<W: Write> Send for Muxer<W> {}
 unsafe impl<W: Write> Sync for Muxer<W> {}
 
 /// Internal structure that holds the stream index and the time base of the source packet for
-/// rescaling.
+/// rescaling, and the bitstream filter for the stream if there is one.
 struct StreamDescription {
     index: usize,
     source_time_base: AvRational,
+    source_stream_info: StreamInfo,
+    filter: Option<BitstreamFilter>,
 }
@@ -1,9 +1,13 @@
 extern crate ffmpeg_next as ffmpeg;
 
 use std::collections::HashMap;
+use std::time::Duration;
 
 use ffmpeg::Dictionary as AvDictionary;
 
+use crate::capabilities::validate_encoder_options;
+use crate::Error;
+
 /// A wrapper type for ffmpeg options.
 pub struct Options<'a>(AvDictionary<'a>);
 
@@ -52,6 +56,27 @@ impl Options<'_> {
         Self(opts)
     }
 
+    /// Creates options for the MPEG-TS muxer that set the service ID and the PIDs of the program
+    /// map table and the elementary streams. Broadcast receivers are often configured to expect
+    /// fixed values.
+    ///
+    /// This sets `mpegts_service_id`, `mpegts_pmt_start_pid` and `mpegts_start_pid` in ffmpeg
+    /// options.
+    ///
+    /// # Arguments
+    ///
+    /// * `service_id` - Service ID (program number).
+    /// * `pmt_start_pid` - PID of the first program map table.
+    /// * `start_pid` - PID of the first elementary stream.
+    pub fn new_with_mpegts_service(service_id: u16, pmt_start_pid: u16, start_pid: u16) -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("mpegts_service_id", &service_id.to_string());
+        opts.set("mpegts_pmt_start_pid", &pmt_start_pid.to_string());
+        opts.set("mpegts_start_pid", &start_pid.to_string());
+
+        Self(opts)
+    }
+
     /// Default options for a H264 encoder.
     pub fn new_h264() -> Self {
         let mut opts = AvDictionary::new();
@@ -73,6 +98,54 @@ impl Options<'_> {
         Self(opts)
     }
 
+    /// Default options for a HEVC encoder (`libx265`).
+    pub fn new_hevc() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("preset", "medium");
+
+        Self(opts)
+    }
+
+    /// Options for a HEVC encoder (`libx265`) that are tuned for low-latency encoding such as for
+    /// real-time streaming.
+    pub fn new_hevc_realtime() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("preset", "medium");
+        // This disables frame threading, lookahead and B-frames in x265.
+        opts.set("tune", "zerolatency");
+
+        Self(opts)
+    }
+
+    /// Options for a VP9 encoder (`libvpx-vp9`) that are tuned for low-latency encoding such as
+    /// for real-time streaming.
+    ///
+    /// This sets `deadline` to `realtime` and `cpu-used` to `8` (fastest).
+    pub fn new_vp9_realtime() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("deadline", "realtime");
+        opts.set("cpu-used", "8");
+
+        Self(opts)
+    }
+
+    /// Options for an AV1 encoder (`libaom-av1`) that are tuned for low-latency encoding such as
+    /// for real-time streaming.
+    ///
+    /// This sets `usage` to `realtime` and `cpu-used` to `8`.
+    pub fn new_av1_realtime() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("usage", "realtime");
+        opts.set("cpu-used", "8");
+
+        Self(opts)
+    }
+
+    /// Create a builder for typed encoder options. See [`OptionsBuilder`].
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
     /// Create custom options from a `HashMap`.
     ///
     /// # Arguments
@@ -87,8 +160,124 @@ impl Options<'_> {
         Self(opts)
     }
 
+    /// Whether or not no options have been set.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().next().is_none()
+    }
+
+    /// Iterate over the options as key-value pairs, for example to log which options are passed to
+    /// ffmpeg.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter()
+    }
+
+    /// Set an option, unless it has already been set.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Option key.
+    /// * `value` - Option value.
+    pub(super) fn set_if_missing(&mut self, key: &str, value: &str) {
+        if self.0.get(key).is_none() {
+            self.0.set(key, value);
+        }
+    }
+
+    /// Get the value of an option, if it has been set.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Option key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)
+    }
+
+    /// Set an option, replacing the value it had before.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Option key.
+    /// * `value` - Option value.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.0.set(key, value);
+    }
+
+    /// Remove an option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Option key.
+    ///
+    /// # Return value
+    ///
+    /// The value the option had, or `None` if it was not set.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.0.get(key)?.to_string();
+        self.0 = self
+            .0
+            .iter()
+            .filter(|(existing_key, _)| *existing_key != key)
+            .collect();
+
+        Some(value)
+    }
+
+    /// Merge two sets of options, like company defaults with the overrides of a job. Options in
+    /// `other` replace options in `self` with the same key.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Options that take precedence.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let defaults: Options = "preset=medium:crf=23".parse().unwrap();
+    /// let overrides: Options = "crf=18".parse().unwrap();
+    /// let options = defaults.merge(overrides);
+    /// assert_eq!(options.get("crf"), Some("18"));
+    /// ```
+    pub fn merge(self, other: Options) -> Options<'static> {
+        let mut merged = self.into_owned();
+        for (key, value) in other.iter() {
+            merged.set(key, value);
+        }
+
+        merged
+    }
+
+    /// Check the options against the private options that an encoder declares (see
+    /// [`crate::EncoderInfo::options`]): numbers must lie in the declared range, and options with
+    /// named values must be set to one of them. Keys that are not private options of the encoder
+    /// are not checked. Encoders check their options like this when they are opened.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_name` - Name of the encoder, like `libx264`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let options: Options = "crf=70".parse().unwrap();
+    /// assert!(options.validate_for_encoder("libx264").is_err());
+    /// ```
+    pub fn validate_for_encoder(&self, codec_name: &str) -> Result<(), Error> {
+        validate_encoder_options(codec_name, self)
+    }
+
+    /// Convert into options that do not borrow anything, so that they can be stored in
+    /// long-lived structures.
+    pub fn into_owned(self) -> Options<'static> {
+        self.to_owned_options()
+    }
+
+    /// Create an owned copy of the options that does not borrow from `self`.
+    pub(super) fn to_owned_options(&self) -> Options<'static> {
+        Options(self.0.iter().collect())
+    }
+
     /// Convert back to ffmpeg native dictionary, which can be used with `ffmpeg_next` functions.
-    pub(super) fn to_dict(&self) -> AvDictionary {
+    pub(super) fn to_dict(&self) -> AvDictionary<'_> {
         self.0.clone()
     }
 }
@@ -124,5 +313,675 @@ impl From<HashMap<String, String>> for Options<'_> {
     }
 }
 
+impl FromIterator<(String, String)> for Options<'_> {
+    /// Collect key-value pairs into `Options`. Later pairs replace earlier pairs with the same key.
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        let mut opts = AvDictionary::new();
+        for (key, value) in iter {
+            opts.set(&key, &value);
+        }
+
+        Self(opts)
+    }
+}
+
+impl std::str::FromStr for Options<'_> {
+    type Err = Error;
+
+    /// Parse options from the `key=value` syntax of the ffmpeg command line, with pairs separated
+    /// by colons or commas, like `preset=slow:crf=20:profile=high`. Separators and backslashes in
+    /// keys or values can be escaped with a backslash, like `x264-params=keyint=60\:bframes=0`.
+    /// The characters after the first `=` of a pair belong to the value.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - String to parse.
+    ///
+    /// # Return value
+    ///
+    /// The options, or [`Error::InvalidOptions`] pointing at the pair that could not be parsed.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Ok(parse_pairs(s)?.into_iter().collect())
+    }
+}
+
+/// Options serialize as a map from keys to values, in the order in which they were set.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Options<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Options<'_> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OptionsVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for OptionsVisitor {
+            type Value = Vec<(String, String)>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a map of option keys to values")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut pairs = Vec::new();
+                while let Some(pair) = map.next_entry()? {
+                    pairs.push(pair);
+                }
+                Ok(pairs)
+            }
+        }
+
+        Ok(deserializer
+            .deserialize_map(OptionsVisitor)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// Collect the keys of options that were not recognized, and log a warning for each of them.
+/// Options are matched by name, so a misspelled option (or one that was renamed in another version
+/// of ffmpeg) would be ignored silently otherwise.
+///
+/// # Arguments
+///
+/// * `stage` - What ignored the options, like `demuxer`.
+/// * `keys` - Keys of the options that were left over.
+pub(crate) fn ignored_option_keys<'k>(
+    stage: &str,
+    keys: impl IntoIterator<Item = &'k str>,
+) -> Vec<String> {
+    keys.into_iter()
+        .map(|key| {
+            tracing::warn!(target: "video", "option not recognized by {stage}: {key}");
+            key.to_string()
+        })
+        .collect()
+}
+
+/// Parse `key=value` pairs separated by colons or commas. See [`Options::from_str`].
+///
+/// # Arguments
+///
+/// * `s` - String to parse.
+fn parse_pairs(s: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut pairs = Vec::new();
+    let mut chars = s.char_indices();
+    loop {
+        let start = chars.offset();
+        let mut key = String::new();
+        let mut value: Option<String> = None;
+        let mut end = None;
+        while let Some((index, c)) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => escaped,
+                    None => {
+                        return Err(Error::InvalidOptions(format!(
+                            "dangling escape at end of `{}` (position {start})",
+                            &s[start..],
+                        )))
+                    }
+                },
+                ':' | ',' => {
+                    end = Some(index);
+                    break;
+                }
+                '=' if value.is_none() => {
+                    value = Some(String::new());
+                    continue;
+                }
+                c => c,
+            };
+            match value.as_mut() {
+                Some(value) => value.push(c),
+                None => key.push(c),
+            }
+        }
+
+        let token = &s[start..end.unwrap_or(s.len())];
+        if !token.is_empty() {
+            match value {
+                Some(value) if !key.is_empty() => pairs.push((key, value)),
+                _ => {
+                    return Err(Error::InvalidOptions(format!(
+                        "expected `key=value` but got `{token}` (position {start})"
+                    )))
+                }
+            }
+        }
+        if end.is_none() {
+            break;
+        }
+    }
+
+    Ok(pairs)
+}
+
+impl From<SrtOptions> for Options<'_> {
+    /// Converts from [`SrtOptions`] to `Options`.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to convert from.
+    fn from(item: SrtOptions) -> Self {
+        let mut opts = AvDictionary::new();
+        if let Some(mode) = item.mode {
+            opts.set("mode", mode.as_str());
+        }
+        if let Some(latency) = item.latency {
+            // The backend expects the latency in microseconds.
+            opts.set("latency", &latency.as_micros().to_string());
+        }
+        if let Some(passphrase) = &item.passphrase {
+            opts.set("passphrase", passphrase);
+        }
+        if let Some(stream_id) = &item.stream_id {
+            opts.set("streamid", stream_id);
+        }
+
+        Self(opts)
+    }
+}
+
 unsafe impl Send for Options<'_> {}
 unsafe impl Sync for Options<'_> {}
+
+/// Connection mode of an SRT socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrtMode {
+    /// Connect to a listener.
+    Caller,
+    /// Wait for a caller to connect.
+    Listener,
+    /// Connect to a peer that is also in rendezvous mode.
+    Rendezvous,
+}
+
+impl SrtMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SrtMode::Caller => "caller",
+            SrtMode::Listener => "listener",
+            SrtMode::Rendezvous => "rendezvous",
+        }
+    }
+}
+
+/// Typed options for the SRT protocol. This requires the backend to be built with `libsrt`.
+/// Convert to [`Options`] to use them.
+///
+/// # Example
+///
+/// ```ignore
+/// let options: Options = SrtOptions::new()
+///     .with_mode(SrtMode::Caller)
+///     .with_latency(Duration::from_millis(200))
+///     .with_passphrase("correct horse battery")
+///     .into();
+/// let decoder = Decoder::new_with_options(
+///     &Url::parse("srt://ingest.example.com:9000").unwrap().into(),
+///     &options,
+/// )
+/// .unwrap();
+/// ```
+#[derive(Clone, Default)]
+pub struct SrtOptions {
+    mode: Option<SrtMode>,
+    latency: Option<Duration>,
+    passphrase: Option<String>,
+    stream_id: Option<String>,
+}
+
+impl SrtOptions {
+    /// Create empty SRT options. The backend defaults are used for anything that is not set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the connection mode (`mode`).
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Connection mode.
+    pub fn with_mode(mut self, mode: SrtMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the receiver latency (`latency`).
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - Latency.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Set the passphrase for encryption (`passphrase`). It must be between 10 and 79 characters
+    /// long.
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - Passphrase.
+    pub fn with_passphrase(mut self, passphrase: &str) -> Self {
+        self.passphrase = Some(passphrase.to_string());
+        self
+    }
+
+    /// Set the stream ID (`streamid`), which the remote end can use to select a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_id` - Stream ID.
+    pub fn with_stream_id(mut self, stream_id: &str) -> Self {
+        self.stream_id = Some(stream_id.to_string());
+        self
+    }
+}
+
+impl std::fmt::Debug for SrtOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SrtOptions")
+            .field("mode", &self.mode)
+            .field("latency", &self.latency)
+            .field("passphrase", &self.passphrase.as_ref().map(|_| "***"))
+            .field("stream_id", &self.stream_id)
+            .finish()
+    }
+}
+
+/// Builder for typed encoder options, so that the common options do not require knowing the raw
+/// ffmpeg keys. The keys are the ones of `libx264`, but other encoders that share them (like the
+/// `preset` of NVENC) can be configured with the same builder.
+///
+/// Options can also be set by their raw key with [`OptionsBuilder::raw`]. Raw options are applied
+/// last, so they override typed options with the same key.
+///
+/// # Example
+///
+/// ```ignore
+/// let options = Options::builder()
+///     .preset(Preset::Veryfast)
+///     .tune(Tune::Film)
+///     .crf(23)
+///     .x264_params("keyint=120:min-keyint=120")
+///     .build()
+///     .unwrap();
+/// let settings = EncoderSettings::for_h264_custom(1280, 720, PixelFormat::YUV420P, options);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OptionsBuilder {
+    preset: Option<Preset>,
+    tune: Option<Tune>,
+    crf: Option<u8>,
+    x264_params: Option<String>,
+    raw: Vec<(String, String)>,
+}
+
+impl OptionsBuilder {
+    /// Maximum constant rate factor for 8-bit content.
+    const MAX_CRF: u8 = 51;
+
+    /// Set the encoding preset (`preset`), which trades encoding speed for compression.
+    ///
+    /// # Arguments
+    ///
+    /// * `preset` - Preset to use.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    /// Set the tuning (`tune`) for a kind of content or use case.
+    ///
+    /// # Arguments
+    ///
+    /// * `tune` - Tuning to use.
+    pub fn tune(mut self, tune: Tune) -> Self {
+        self.tune = Some(tune);
+        self
+    }
+
+    /// Set the constant rate factor (`crf`) for constant quality encoding. Lower values give
+    /// higher quality. It must be between 0 and 51.
+    ///
+    /// # Arguments
+    ///
+    /// * `crf` - Constant rate factor.
+    pub fn crf(mut self, crf: u8) -> Self {
+        self.crf = Some(crf);
+        self
+    }
+
+    /// Set parameters that are passed to `libx264` as is (`x264-params`), separated by colons.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters, like `keyint=120:min-keyint=120`.
+    pub fn x264_params(mut self, params: &str) -> Self {
+        self.x264_params = Some(params.to_string());
+        self
+    }
+
+    /// Set an option by its raw ffmpeg key. This replaces the value of the key if it was set
+    /// before.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Option key.
+    /// * `value` - Option value.
+    pub fn raw(mut self, key: &str, value: &str) -> Self {
+        self.raw.retain(|(raw_key, _)| raw_key != key);
+        self.raw.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Build the options.
+    ///
+    /// # Return value
+    ///
+    /// The options, or [`Error::InvalidOptions`] if a value is out of range.
+    pub fn build(self) -> Result<Options<'static>, Error> {
+        let mut opts = AvDictionary::new();
+        if let Some(preset) = &self.preset {
+            opts.set("preset", preset.as_str());
+        }
+        if let Some(tune) = &self.tune {
+            opts.set("tune", tune.as_str());
+        }
+        if let Some(crf) = self.crf {
+            if crf > Self::MAX_CRF {
+                return Err(Error::InvalidOptions(format!(
+                    "crf must be between 0 and {}, got {crf}",
+                    Self::MAX_CRF
+                )));
+            }
+            opts.set("crf", &crf.to_string());
+        }
+        if let Some(x264_params) = &self.x264_params {
+            opts.set("x264-params", x264_params);
+        }
+        for (key, value) in &self.raw {
+            opts.set(key, value);
+        }
+
+        Ok(Options(opts))
+    }
+}
+
+/// Encoding presets (`preset`) of `libx264`, from fastest to best compression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preset {
+    Ultrafast,
+    Superfast,
+    Veryfast,
+    Faster,
+    Fast,
+    Medium,
+    Slow,
+    Slower,
+    Veryslow,
+    Placebo,
+    /// Preset of another encoder, like `p4` for NVENC.
+    Other(String),
+}
+
+impl Preset {
+    fn as_str(&self) -> &str {
+        match self {
+            Preset::Ultrafast => "ultrafast",
+            Preset::Superfast => "superfast",
+            Preset::Veryfast => "veryfast",
+            Preset::Faster => "faster",
+            Preset::Fast => "fast",
+            Preset::Medium => "medium",
+            Preset::Slow => "slow",
+            Preset::Slower => "slower",
+            Preset::Veryslow => "veryslow",
+            Preset::Placebo => "placebo",
+            Preset::Other(preset) => preset,
+        }
+    }
+}
+
+/// Tunings (`tune`) of `libx264`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tune {
+    /// High quality film content.
+    Film,
+    /// Cartoons and other animated content.
+    Animation,
+    /// Preserve the grain structure of grainy film.
+    Grain,
+    /// Slideshow-like content.
+    Stillimage,
+    /// Allow faster decoding by disabling some filters.
+    Fastdecode,
+    /// Low latency encoding, for real-time streaming.
+    Zerolatency,
+    /// Optimize for PSNR (only for codec comparisons).
+    Psnr,
+    /// Optimize for SSIM (only for codec comparisons).
+    Ssim,
+    /// Tuning of another encoder, like `ll` (low latency) for NVENC.
+    Other(String),
+}
+
+impl Tune {
+    fn as_str(&self) -> &str {
+        match self {
+            Tune::Film => "film",
+            Tune::Animation => "animation",
+            Tune::Grain => "grain",
+            Tune::Stillimage => "stillimage",
+            Tune::Fastdecode => "fastdecode",
+            Tune::Zerolatency => "zerolatency",
+            Tune::Psnr => "psnr",
+            Tune::Ssim => "ssim",
+            Tune::Other(tune) => tune,
+        }
+    }
+}
+
+/// Flags for the MP4 and QuickTime muxers (`movflags`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MovFlag {
+    /// Move the index (moov atom) to the start of the file when finishing, so that playback can
+    /// start before the whole file has been downloaded. This requires a seekable output.
+    Faststart,
+    /// Start a new fragment at each key frame.
+    FragKeyframe,
+    /// Write an initial moov atom without samples, as required for fragmented output.
+    EmptyMoov,
+    /// Write a separate moof atom for each track.
+    SeparateMoof,
+    /// Make data offsets in fragments relative to the moof atom, as required by some players for
+    /// fragmented output.
+    DefaultBaseMoof,
+    /// Do not write the chapter list of Nero chapters.
+    DisableChpl,
+    /// Write a global sidx atom with an index of all fragments.
+    GlobalSidx,
+    /// Write all metadata tags as `mdta` keys, including custom tags, instead of only the tags
+    /// that QuickTime and iTunes know.
+    UseMetadataTags,
+}
+
+impl MovFlag {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            MovFlag::Faststart => "faststart",
+            MovFlag::FragKeyframe => "frag_keyframe",
+            MovFlag::EmptyMoov => "empty_moov",
+            MovFlag::SeparateMoof => "separate_moof",
+            MovFlag::DefaultBaseMoof => "default_base_moof",
+            MovFlag::DisableChpl => "disable_chpl",
+            MovFlag::GlobalSidx => "global_sidx",
+            MovFlag::UseMetadataTags => "use_metadata_tags",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srt_options() {
+        let options: Options = SrtOptions::new()
+            .with_mode(SrtMode::Listener)
+            .with_latency(Duration::from_millis(120))
+            .with_passphrase("0123456789")
+            .with_stream_id("camera-1")
+            .into();
+        assert_eq!(options.0.get("mode"), Some("listener"));
+        assert_eq!(options.0.get("latency"), Some("120000"));
+        assert_eq!(options.0.get("passphrase"), Some("0123456789"));
+        assert_eq!(options.0.get("streamid"), Some("camera-1"));
+    }
+
+    #[test]
+    fn test_codec_presets() {
+        let options = Options::new_hevc_realtime();
+        assert_eq!(options.get("tune"), Some("zerolatency"));
+        let options = Options::new_vp9_realtime();
+        assert_eq!(options.get("deadline"), Some("realtime"));
+        assert_eq!(options.get("cpu-used"), Some("8"));
+        let options = Options::new_av1_realtime();
+        assert_eq!(options.get("usage"), Some("realtime"));
+    }
+
+    #[test]
+    fn test_options_is_empty_and_iter() {
+        assert!(Options::default().is_empty());
+        let options = Options::new_vp9_realtime();
+        assert!(!options.is_empty());
+        let mut pairs = options.iter().collect::<Vec<_>>();
+        pairs.sort();
+        assert_eq!(pairs, vec![("cpu-used", "8"), ("deadline", "realtime")]);
+    }
+
+    #[test]
+    fn test_options_from_str() {
+        let options: Options = "preset=slow:crf=20,profile=high".parse().unwrap();
+        assert_eq!(options.get("preset"), Some("slow"));
+        assert_eq!(options.get("crf"), Some("20"));
+        assert_eq!(options.get("profile"), Some("high"));
+
+        let options: Options = r"x264-params=keyint=60\:bframes=0:path=C\\x\,y"
+            .parse()
+            .unwrap();
+        assert_eq!(options.get("x264-params"), Some("keyint=60:bframes=0"));
+        assert_eq!(options.get("path"), Some(r"C\x,y"));
+
+        assert!("".parse::<Options>().unwrap().is_empty());
+        assert_eq!("crf=20:".parse::<Options>().unwrap().get("crf"), Some("20"));
+    }
+
+    #[test]
+    fn test_options_from_str_invalid() {
+        match "preset=slow:crf:profile=high".parse::<Options>() {
+            Err(Error::InvalidOptions(reason)) => {
+                assert!(reason.contains("`crf`"));
+                assert!(reason.contains("position 12"));
+            }
+            _ => panic!("expected invalid options"),
+        }
+        assert!(matches!(
+            "=slow".parse::<Options>(),
+            Err(Error::InvalidOptions(_))
+        ));
+        assert!(matches!(
+            "preset=slow\\".parse::<Options>(),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_options_from_iter() {
+        let options: Options = vec![
+            ("preset".to_string(), "fast".to_string()),
+            ("preset".to_string(), "slow".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(options.get("preset"), Some("slow"));
+    }
+
+    #[test]
+    fn test_options_merge() {
+        let defaults: Options = "preset=medium:crf=23:tune=film".parse().unwrap();
+        let overrides: Options = "crf=18:profile=high".parse().unwrap();
+        let mut merged = defaults.merge(overrides);
+        assert_eq!(merged.remove("tune"), Some("film".to_string()));
+        assert_eq!(merged.remove("tune"), None);
+        merged.set("level", "4.1");
+
+        let dict = merged.to_dict();
+        let mut pairs = dict.iter().collect::<Vec<_>>();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            vec![
+                ("crf", "18"),
+                ("level", "4.1"),
+                ("preset", "medium"),
+                ("profile", "high"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options_builder() {
+        let options = Options::builder()
+            .preset(Preset::Veryfast)
+            .tune(Tune::Film)
+            .crf(23)
+            .x264_params("keyint=120:min-keyint=120")
+            .build()
+            .unwrap();
+        assert_eq!(options.get("preset"), Some("veryfast"));
+        assert_eq!(options.get("tune"), Some("film"));
+        assert_eq!(options.get("crf"), Some("23"));
+        assert_eq!(
+            options.get("x264-params"),
+            Some("keyint=120:min-keyint=120")
+        );
+    }
+
+    #[test]
+    fn test_options_builder_raw_overrides_typed() {
+        let options = Options::builder()
+            .preset(Preset::Other("p4".to_string()))
+            .raw("preset", "p7")
+            .raw("rc", "vbr")
+            .raw("rc", "cbr")
+            .build()
+            .unwrap();
+        assert_eq!(options.get("preset"), Some("p7"));
+        assert_eq!(options.get("rc"), Some("cbr"));
+        assert_eq!(options.get("tune"), None);
+    }
+
+    #[test]
+    fn test_options_builder_rejects_invalid_crf() {
+        assert!(Options::builder().crf(51).build().is_ok());
+        assert!(matches!(
+            Options::builder().crf(52).build(),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_srt_options_debug_redacts_passphrase() {
+        let options = SrtOptions::new().with_passphrase("0123456789");
+        assert!(!format!("{options:?}").contains("0123456789"));
+    }
+}
@@ -0,0 +1,294 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::pixel::Pixel as AvPixel;
+
+use crate::ffi::frame_make_writable;
+use crate::{Error, RawFrame};
+
+#[cfg(feature = "ndarray")]
+use crate::Frame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Represents an RGBA image that is alpha-composited onto frames, like a logo or a timestamp
+/// burn-in. See [`Encoder::with_overlay`](crate::Encoder::with_overlay).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlay {
+    /// Packed RGBA pixels.
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    position: (u32, u32),
+}
+
+impl Overlay {
+    /// Create an overlay from an `ndarray` image.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image in `HWC` format with four channels: red, green, blue and alpha.
+    /// * `position` - Position of the top left corner of the overlay on the frame.
+    #[cfg(feature = "ndarray")]
+    pub fn new(image: &Frame, position: (u32, u32)) -> Result<Self> {
+        let (height, width, channels) = image.dim();
+        if channels != 4 {
+            return Err(Error::invalid_frame_format(
+                "4 channels",
+                format!("{channels} channels"),
+            ));
+        }
+
+        Ok(Self {
+            data: image.iter().copied().collect(),
+            width: width as u32,
+            height: height as u32,
+            position,
+        })
+    }
+
+    /// Create an overlay from packed RGBA pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Pixels, row by row, with four bytes per pixel: red, green, blue and alpha.
+    /// * `width` - Width of the overlay.
+    /// * `height` - Height of the overlay.
+    /// * `position` - Position of the top left corner of the overlay on the frame.
+    pub fn from_rgba(data: Vec<u8>, width: u32, height: u32, position: (u32, u32)) -> Result<Self> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(Error::invalid_frame_format(
+                format!("{} bytes", width as usize * height as usize * 4),
+                format!("{} bytes", data.len()),
+            ));
+        }
+
+        Ok(Self {
+            data,
+            width,
+            height,
+            position,
+        })
+    }
+
+    /// Get the size of the overlay: width and height.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Get the position of the top left corner of the overlay on the frame.
+    pub fn position(&self) -> (u32, u32) {
+        self.position
+    }
+
+    /// Composite the overlay onto a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame in RGB24, BGRA, RGBA or GRAY8 format.
+    /// * `offset` - Offset to add to the position of the overlay.
+    pub(crate) fn composite(&self, frame: &mut RawFrame, offset: (u32, u32)) -> Result<()> {
+        let layout = match frame.format() {
+            AvPixel::RGB24 => Layout::Rgb,
+            AvPixel::BGRA => Layout::Bgra,
+            AvPixel::RGBA => Layout::Rgba,
+            AvPixel::GRAY8 => Layout::Gray,
+            format => {
+                return Err(Error::invalid_frame_format(
+                    "RGB24, BGRA, RGBA or GRAY8",
+                    format!("{format:?}"),
+                ))
+            }
+        };
+
+        frame_make_writable(frame).map_err(Error::from)?;
+        let size = (frame.width(), frame.height());
+        let stride = frame.stride(0);
+        self.composite_onto(frame.data_mut(0), stride, size, layout, offset);
+
+        Ok(())
+    }
+
+    /// Composite the overlay onto packed pixels. Parts of the overlay that fall outside of the
+    /// frame are clipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Pixels of the frame.
+    /// * `stride` - Number of bytes per row of the frame.
+    /// * `size` - Dimensions of the frame.
+    /// * `layout` - Layout of the pixels of the frame.
+    /// * `offset` - Offset to add to the position of the overlay.
+    fn composite_onto(
+        &self,
+        data: &mut [u8],
+        stride: usize,
+        size: (u32, u32),
+        layout: Layout,
+        offset: (u32, u32),
+    ) {
+        let x = self.position.0.saturating_add(offset.0);
+        let y = self.position.1.saturating_add(offset.1);
+        let width = self.width.min(size.0.saturating_sub(x)) as usize;
+        let height = self.height.min(size.1.saturating_sub(y)) as usize;
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let bytes_per_pixel = layout.bytes_per_pixel();
+        let overlay_rows = self.data.chunks_exact(self.width as usize * 4);
+        let frame_rows = data[y as usize * stride..].chunks_mut(stride);
+        for (overlay_row, frame_row) in overlay_rows.zip(frame_rows).take(height) {
+            let frame_row = &mut frame_row[x as usize * bytes_per_pixel..];
+            for (src, dst) in overlay_row
+                .chunks_exact(4)
+                .zip(frame_row.chunks_exact_mut(bytes_per_pixel))
+                .take(width)
+            {
+                layout.blend(src, dst);
+            }
+        }
+    }
+}
+
+/// Layouts of packed pixels that overlays can be composited onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    Rgb,
+    Bgra,
+    Rgba,
+    Gray,
+}
+
+impl Layout {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Layout::Rgb => 3,
+            Layout::Bgra | Layout::Rgba => 4,
+            Layout::Gray => 1,
+        }
+    }
+
+    /// Blend an RGBA pixel over a pixel with this layout.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - Overlay pixel: red, green, blue and alpha.
+    /// * `dst` - Frame pixel to blend onto.
+    fn blend(self, src: &[u8], dst: &mut [u8]) {
+        let alpha = src[3] as u32;
+        // Rounded integer blending keeps the frame exactly as is under fully transparent pixels,
+        // and replaces it exactly under fully opaque pixels.
+        let mix = |src: u8, dst: &mut u8| {
+            *dst = ((src as u32 * alpha + *dst as u32 * (255 - alpha) + 127) / 255) as u8;
+        };
+
+        match self {
+            Layout::Rgb | Layout::Rgba => {
+                mix(src[0], &mut dst[0]);
+                mix(src[1], &mut dst[1]);
+                mix(src[2], &mut dst[2]);
+            }
+            Layout::Bgra => {
+                mix(src[2], &mut dst[0]);
+                mix(src[1], &mut dst[1]);
+                mix(src[0], &mut dst[2]);
+            }
+            Layout::Gray => {
+                // Luma with BT.601 weights.
+                let luma =
+                    (77 * src[0] as u32 + 150 * src[1] as u32 + 29 * src[2] as u32 + 128) >> 8;
+                mix(luma as u8, &mut dst[0]);
+            }
+        }
+
+        if matches!(self, Layout::Bgra | Layout::Rgba) {
+            // Alpha of the composite, as with compositing one translucent layer over another.
+            dst[3] = (alpha + (dst[3] as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlay_from_rgba() {
+        assert!(Overlay::from_rgba(vec![0; 16], 2, 2, (0, 0)).is_ok());
+        assert!(matches!(
+            Overlay::from_rgba(vec![0; 15], 2, 2, (0, 0)),
+            Err(Error::InvalidFrameFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_overlay_composite_exact() {
+        // Opaque red, fully transparent, half transparent white and opaque blue.
+        let overlay = Overlay::from_rgba(
+            vec![
+                255, 0, 0, 255, 0, 255, 0, 0, 255, 255, 255, 128, 0, 0, 255, 255,
+            ],
+            2,
+            2,
+            (1, 1),
+        )
+        .unwrap();
+
+        // A 3x3 RGB frame with a stride of 10 bytes filled with gray.
+        let mut data = vec![100; 30];
+        overlay.composite_onto(&mut data, 10, (3, 3), Layout::Rgb, (0, 0));
+        let pixel =
+            |data: &[u8], x: usize, y: usize| data[y * 10 + x * 3..y * 10 + x * 3 + 3].to_vec();
+        assert_eq!(pixel(&data, 0, 0), [100, 100, 100]);
+        assert_eq!(pixel(&data, 1, 1), [255, 0, 0]);
+        assert_eq!(pixel(&data, 2, 1), [100, 100, 100]);
+        // (255 * 128 + 100 * 127 + 127) / 255 = 178.
+        assert_eq!(pixel(&data, 1, 2), [178, 178, 178]);
+        assert_eq!(pixel(&data, 2, 2), [0, 0, 255]);
+        // Padding at the end of each row is not touched.
+        assert!(data.chunks(10).all(|row| row[9] == 100));
+    }
+
+    #[test]
+    fn test_overlay_composite_clips_at_border() {
+        let overlay = Overlay::from_rgba(vec![200; 4 * 4 * 4], 4, 4, (2, 1)).unwrap();
+
+        let mut data = vec![0; 3 * 3 * 4];
+        overlay.composite_onto(&mut data, 12, (3, 3), Layout::Bgra, (0, 0));
+        for (index, pixel) in data.chunks(4).enumerate() {
+            let (x, y) = (index % 3, index / 3);
+            if x >= 2 && y >= 1 {
+                // (200 * 200 + 127) / 255 = 157, and the alpha becomes 200.
+                assert_eq!(pixel, [157, 157, 157, 200]);
+            } else {
+                assert_eq!(pixel, [0, 0, 0, 0]);
+            }
+        }
+
+        // An overlay that falls completely outside of the frame does nothing.
+        let mut data = vec![0; 3 * 3 * 4];
+        overlay.composite_onto(&mut data, 12, (3, 3), Layout::Bgra, (1, 0));
+        assert!(data.iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn test_overlay_composite_gray() {
+        let overlay =
+            Overlay::from_rgba(vec![255, 255, 255, 255, 0, 0, 0, 0], 2, 1, (0, 0)).unwrap();
+
+        let mut data = vec![50, 50];
+        overlay.composite_onto(&mut data, 2, (2, 1), Layout::Gray, (0, 0));
+        assert_eq!(data, [255, 50]);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_overlay_new() {
+        let image = Frame::from_shape_fn((2, 3, 4), |(y, x, c)| (y * 12 + x * 4 + c) as u8);
+        let overlay = Overlay::new(&image, (5, 6)).unwrap();
+        assert_eq!(overlay.size(), (3, 2));
+        assert_eq!(overlay.position(), (5, 6));
+        assert_eq!(overlay.data, (0..24).collect::<Vec<u8>>());
+        assert!(Overlay::new(&Frame::zeros((2, 3, 3)), (0, 0)).is_err());
+    }
+}
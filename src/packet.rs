@@ -25,12 +25,30 @@ impl Packet {
         Time::new(self.inner.dts(), self.time_base)
     }
 
-    /// Get packet duration.
+    /// Get packet duration. The duration is zero if the container does not store it.
     #[inline]
     pub fn duration(&self) -> Time {
         Time::new(Some(self.inner.duration()), self.time_base)
     }
 
+    /// Get packet size in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Get the packet data.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        self.inner.data().unwrap_or_default()
+    }
+
+    /// Get the byte position of the packet in the input, if the demuxer reports it.
+    #[inline]
+    pub fn position(&self) -> Option<u64> {
+        u64::try_from(self.inner.position()).ok()
+    }
+
     // Check whether packet is key.
     #[inline]
     pub fn is_key(&self) -> bool {
@@ -82,3 +100,37 @@ impl Packet {
 
 unsafe impl Send for Packet {}
 unsafe impl Sync for Packet {}
+
+/// Encoded packet of an encoder without a container (see [`crate::Encoder::new_packetized`]). The
+/// packet owns its data, so that it can be handed to any transport.
+#[derive(Debug, Clone)]
+pub struct EncodedPacket {
+    /// Encoded data, like an H.264 access unit in Annex B format.
+    pub data: Vec<u8>,
+    /// Presentation timestamp.
+    pub pts: Time,
+    /// Decoder timestamp.
+    pub dts: Time,
+    /// Duration.
+    pub duration: Time,
+    /// Whether or not the packet is a key frame.
+    pub is_key: bool,
+}
+
+impl EncodedPacket {
+    /// Create an encoded packet from a native packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Native packet with timestamps in `time_base`.
+    /// * `time_base` - Time base of the packet timestamps.
+    pub(crate) fn new(packet: &AvPacket, time_base: AvRational) -> Self {
+        Self {
+            data: packet.data().unwrap_or_default().to_vec(),
+            pts: Time::new(packet.pts(), time_base),
+            dts: Time::new(packet.dts(), time_base),
+            duration: Time::new(Some(packet.duration()), time_base),
+            is_key: packet.is_key(),
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ffmpeg_next::Error as AvError;
+
+use crate::concat::concat;
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::ffi::input_is_seekable;
+use crate::io::Reader;
+use crate::options::Options;
+use crate::transcode::{decode_error, encode_error};
+use crate::{Error, Locator, Resize, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Number of parallel transcodes so far, to give each one its own directory of segments.
+static NUM_PARALLEL_TRANSCODES: AtomicUsize = AtomicUsize::new(0);
+
+/// Re-encode the video stream of a file on multiple threads, for offline transcodes that would
+/// otherwise use a single core.
+///
+/// The source is split at keyframes into `jobs` chunks of about the same number of frames. Each
+/// chunk is decoded and encoded on its own thread into a temporary segment, and the segments are
+/// joined into the destination without re-encoding (see [`crate::concat`]). Chunks run from a
+/// keyframe up to (not including) the keyframe that starts the next chunk, by presentation
+/// timestamp, so every frame ends up in exactly one chunk. Frames are resized to the size in the
+/// settings, like [`crate::Transcoder`] does.
+///
+/// Each chunk is rate controlled on its own, so use a constant quality mode (like
+/// [`crate::RateControl::Quality`]) for the same quality throughout. Bitrate targets are met per
+/// chunk rather than for the whole file, and two-pass encoding (which needs statistics of the
+/// whole file) is not supported: it fails with [`Error::InvalidOptions`].
+///
+/// Only the video stream is transcoded: audio and other streams are left out. The source must be
+/// a file (or another source that can seek), otherwise this fails with [`Error::NotSeekable`].
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `dest` - Locator to write to.
+/// * `settings` - Encoder settings to use for every chunk.
+/// * `jobs` - Number of chunks to transcode in parallel. Sources with fewer keyframes are split
+///   into fewer chunks.
+///
+/// # Example
+///
+/// ```ignore
+/// transcode_parallel(
+///     &PathBuf::from("input.mkv").into(),
+///     &PathBuf::from("output.mp4").into(),
+///     EncoderSettings::for_h264_yuv420p(1920, 1080, false).with_rate_control(RateControl::Quality(23)),
+///     32,
+/// )
+/// .unwrap();
+/// ```
+pub fn transcode_parallel(
+    source: &Locator,
+    dest: &Locator,
+    settings: Settings,
+    jobs: usize,
+) -> Result<()> {
+    if jobs == 0 {
+        return Err(Error::InvalidOptions(
+            "parallel transcode needs at least one job".to_string(),
+        ));
+    }
+    if settings.is_two_pass() {
+        return Err(Error::InvalidOptions(
+            "two-pass encoding cannot be split into chunks".to_string(),
+        ));
+    }
+
+    let mut reader = Reader::new(source).map_err(decode_error)?;
+    if !input_is_seekable(&reader.input) {
+        return Err(decode_error(Error::NotSeekable));
+    }
+    let stream_index = reader.best_video_stream_index().map_err(decode_error)?;
+    let ranges = chunk_ranges(&mut reader, stream_index, jobs).map_err(decode_error)?;
+    drop(reader);
+
+    let segment_dir = SegmentDir::new().map_err(encode_error)?;
+    let segments = (0..ranges.len())
+        .map(|index| Locator::from(segment_dir.path.join(format!("{index}.mkv"))))
+        .collect::<Vec<_>>();
+    std::thread::scope(|scope| {
+        let chunks = ranges
+            .into_iter()
+            .zip(&segments)
+            .map(|((start, end), segment)| {
+                let settings = settings.to_owned_settings();
+                scope.spawn(move || transcode_chunk(source, segment, settings, start, end))
+            })
+            .collect::<Vec<_>>();
+        chunks
+            .into_iter()
+            .map(|chunk| {
+                chunk
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    concat(&segments, dest).map_err(encode_error)
+}
+
+/// Transcode the frames of a time range of the source into a segment.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `segment` - Locator to write the segment to.
+/// * `settings` - Encoder settings to use.
+/// * `start` - Start of the range (inclusive).
+/// * `end` - End of the range (exclusive).
+fn transcode_chunk(
+    source: &Locator,
+    segment: &Locator,
+    settings: Settings,
+    start: Time,
+    end: Time,
+) -> Result<()> {
+    let (width, height) = settings.size();
+    let mut decoder = Decoder::new_with_options_and_resize(
+        source,
+        &Options::default(),
+        Resize::Exact(width, height),
+    )
+    .map_err(decode_error)?;
+    let decoder_time_base = decoder.time_base();
+    let mut encoder = Encoder::new(segment, settings).map_err(encode_error)?;
+    let encoder_time_base = encoder.time_base();
+
+    for frame in decoder
+        .decode_raw_between(start, end, false)
+        .map_err(decode_error)?
+    {
+        let mut frame = frame.map_err(decode_error)?;
+        let timestamp = Time::new(frame.pts(), decoder_time_base);
+        frame.set_pts(
+            timestamp
+                .aligned_with_rational(encoder_time_base)
+                .into_value(),
+        );
+        encoder.encode_raw(frame).map_err(encode_error)?;
+    }
+
+    encoder.finish().map_err(encode_error)
+}
+
+/// Find the time ranges of the chunks of the video stream, by reading its packets without
+/// decoding them.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `stream_index` - Index of the video stream.
+/// * `jobs` - Number of chunks to split into, at most.
+///
+/// # Return value
+///
+/// The start (inclusive) and end (exclusive) of each chunk, in order. The first chunk starts at
+/// the earliest frame and the last chunk ends after the latest frame.
+fn chunk_ranges(
+    reader: &mut Reader,
+    stream_index: usize,
+    jobs: usize,
+) -> Result<Vec<(Time, Time)>> {
+    let time_base = reader
+        .input
+        .stream(stream_index)
+        .ok_or(AvError::StreamNotFound)?
+        .time_base();
+
+    let mut keyframes = Vec::new();
+    let mut num_packets = 0;
+    let mut earliest = i64::MAX;
+    let mut latest = i64::MIN;
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) if packet.size() > 0 => {
+                if let Some(pts) = packet.pts().into_value() {
+                    earliest = earliest.min(pts);
+                    latest = latest.max(pts);
+                    if packet.is_key() {
+                        keyframes.push((num_packets, pts));
+                    }
+                }
+                num_packets += 1;
+            }
+            Ok(_) => {}
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    if earliest > latest {
+        return Err(Error::ReadExhausted);
+    }
+
+    let mut starts = vec![earliest];
+    starts.extend(split_points(&keyframes, num_packets, jobs));
+    let ends = starts.iter().skip(1).copied().chain([latest + 1]);
+
+    Ok(starts
+        .iter()
+        .zip(ends)
+        .map(|(&start, end)| {
+            (
+                Time::new(Some(start), time_base),
+                Time::new(Some(end), time_base),
+            )
+        })
+        .collect())
+}
+
+/// Pick the keyframes that start the chunks after the first one, so that the chunks have about
+/// the same number of packets.
+///
+/// # Arguments
+///
+/// * `keyframes` - Number of the packet (in decoding order) and presentation timestamp of each
+///   keyframe, in decoding order.
+/// * `num_packets` - Number of packets of the stream.
+/// * `jobs` - Number of chunks to split into, at most.
+///
+/// # Return value
+///
+/// Presentation timestamps of the keyframes that start the chunks, in increasing order.
+fn split_points(keyframes: &[(usize, i64)], num_packets: usize, jobs: usize) -> Vec<i64> {
+    let mut split_points: Vec<i64> = Vec::new();
+    for job in 1..jobs {
+        let target = job * num_packets / jobs;
+        let Some(&(_, pts)) = keyframes
+            .iter()
+            .find(|(packet, _)| *packet > 0 && *packet >= target)
+        else {
+            break;
+        };
+        if split_points.last().is_none_or(|&last| pts > last) {
+            split_points.push(pts);
+        }
+    }
+
+    split_points
+}
+
+/// Temporary directory for the segments of a parallel transcode, which is removed along with the
+/// segments when it is dropped.
+struct SegmentDir {
+    path: PathBuf,
+}
+
+impl SegmentDir {
+    /// Create a new temporary directory.
+    fn new() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "video-rs-parallel-{}-{}",
+            std::process::id(),
+            NUM_PARALLEL_TRANSCODES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+            _ => Error::InvalidWriterConfiguration(format!(
+                "cannot create {}: {err}",
+                path.display()
+            )),
+        })?;
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for SegmentDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg_next::util::format::Pixel as AvPixel;
+
+    use crate::testing::{self, TempPath};
+    use crate::{hash_video, Transcoder};
+
+    #[test]
+    fn test_split_points() {
+        // A keyframe every 10 packets.
+        let keyframes = (0..10)
+            .map(|i| (i * 10, i as i64 * 1000))
+            .collect::<Vec<_>>();
+        assert_eq!(split_points(&keyframes, 100, 1), Vec::<i64>::new());
+        assert_eq!(split_points(&keyframes, 100, 2), vec![5000]);
+        assert_eq!(split_points(&keyframes, 100, 3), vec![4000, 7000]);
+        // There are not enough keyframes for as many chunks.
+        assert_eq!(
+            split_points(&keyframes, 100, 32),
+            (1..10).map(|i| i * 1000).collect::<Vec<_>>()
+        );
+        // Only the first packet is a keyframe.
+        assert_eq!(split_points(&[(0, 0)], 100, 4), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn test_transcode_parallel_matches_serial() {
+        let dir = TempPath::new("chunks");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let serial: Locator = dir.join("serial.mkv").into();
+        let parallel: Locator = dir.join("parallel.mkv").into();
+
+        testing::write_test_video_with_settings(
+            &source,
+            testing::settings().with_key_frame_interval(10),
+            (0..95).map(|i| testing::filled_frame((i * 2) as u8)),
+        );
+
+        // Lossless, so that the decoded frames of both transcodes are the same.
+        let lossless = || {
+            let mut options = Options::new_h264();
+            options.set("qp", "0");
+            Settings::for_h264_custom(64, 48, AvPixel::YUV420P, options)
+        };
+        Transcoder::new(&source, &serial, lossless())
+            .unwrap()
+            .run()
+            .unwrap();
+        transcode_parallel(&source, &parallel, lossless(), 4).unwrap();
+
+        let hashes = |locator: &Locator| {
+            hash_video(locator)
+                .unwrap()
+                .into_iter()
+                .map(|(_, hash)| hash)
+                .collect::<Vec<_>>()
+        };
+        let serial_hashes = hashes(&serial);
+        assert_eq!(serial_hashes.len(), 95);
+        assert_eq!(hashes(&parallel), serial_hashes);
+
+        assert!(matches!(
+            transcode_parallel(&source, &parallel, lossless(), 0),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::JoinHandle;
+
+use ffmpeg::Rational as AvRational;
+
+use crate::encode::Encoder;
+use crate::error::Error;
+use crate::frame::RawFrame;
+
+#[cfg(feature = "ndarray")]
+use crate::{encode::ndarray_to_raw_frame, frame::Frame, time::Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Default number of frames that can be queued between the caller and the worker thread.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Encoder that scales, encodes and writes frames on a dedicated worker thread, so that the
+/// caller can prepare the next frame in the meantime.
+///
+/// Frames are queued to the worker, up to a bounded number of frames. Encoding calls only wait
+/// when the queue is full, which keeps memory use in check when the caller produces frames faster
+/// than they can be encoded. Since frames are encoded in the background, an encoding error is
+/// returned by the next call after it occurred (or by [`PipelinedEncoder::finish`]).
+///
+/// Dropping the encoder without calling [`PipelinedEncoder::finish`] encodes the frames that are
+/// still queued and finishes the encoder, but any error is lost.
+///
+/// # Example
+///
+/// ```ignore
+/// let encoder = Encoder::new(&PathBuf::from("video.mp4").into(), settings).unwrap();
+/// let mut encoder = PipelinedEncoder::new(encoder);
+/// encoder.encode(&frame, &timestamp).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+pub struct PipelinedEncoder {
+    worker: Worker<RawFrame>,
+    time_base: AvRational,
+    #[cfg_attr(not(feature = "ndarray"), allow(dead_code))]
+    input_size: (u32, u32),
+}
+
+impl PipelinedEncoder {
+    /// Create a new pipelined encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder to move to the worker thread.
+    pub fn new(encoder: Encoder) -> Self {
+        Self::new_with_capacity(encoder, CHANNEL_CAPACITY)
+    }
+
+    /// Create a new pipelined encoder that queues at most `capacity` frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder to move to the worker thread.
+    /// * `capacity` - Maximum number of frames to queue. With `0`, every call waits until the
+    ///   worker takes the frame.
+    pub fn new_with_capacity(encoder: Encoder, capacity: usize) -> Self {
+        let time_base = encoder.time_base();
+        let input_size = encoder.input_size();
+        let worker = Worker::spawn(
+            encoder,
+            capacity,
+            |encoder, frame| encoder.encode_raw(frame),
+            Encoder::finish,
+        );
+
+        Self {
+            worker,
+            time_base,
+            input_size,
+        }
+    }
+
+    /// Encode a single `ndarray` frame. The frame is converted on the caller's thread, and
+    /// scaled and encoded on the worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode in `HWC` format and standard layout.
+    /// * `source_timestamp` - Frame timestamp of original source. This is necessary to make sure
+    ///   the output will be timed correctly.
+    #[cfg(feature = "ndarray")]
+    pub fn encode(&mut self, frame: &Frame, source_timestamp: &Time) -> Result<()> {
+        let frame = ndarray_to_raw_frame(frame, source_timestamp, self.time_base, self.input_size)?;
+        self.worker.send(frame)
+    }
+
+    /// Encode a single raw frame. See [`Encoder::encode_raw`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    pub fn encode_raw(&mut self, frame: RawFrame) -> Result<()> {
+        self.worker.send(frame)
+    }
+
+    /// Get encoder time base.
+    pub fn time_base(&self) -> AvRational {
+        self.time_base
+    }
+
+    /// Encode the frames that are still queued and finish the encoder. This flushes the encoder
+    /// and writes the trailer.
+    pub fn finish(mut self) -> Result<()> {
+        self.worker.finish()
+    }
+}
+
+/// Worker thread that processes the items sent to it through a bounded channel.
+struct Worker<T> {
+    sender: Option<SyncSender<T>>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl<T: Send + 'static> Worker<T> {
+    /// Spawn the worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - State to move to the worker thread (usually the encoder).
+    /// * `capacity` - Maximum number of items to queue.
+    /// * `process` - Function that processes a single item.
+    /// * `finish` - Function to call once all items have been processed.
+    fn spawn<S: Send + 'static>(
+        mut state: S,
+        capacity: usize,
+        mut process: impl FnMut(&mut S, T) -> Result<()> + Send + 'static,
+        finish: impl FnOnce(&mut S) -> Result<()> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(capacity);
+        let handle = std::thread::Builder::new()
+            .name("video-rs-pipeline".to_string())
+            .spawn(move || {
+                // This stops once the sending end is dropped, either by `finish` or because the
+                // worker itself was dropped.
+                for item in receiver {
+                    process(&mut state, item)?;
+                }
+
+                finish(&mut state)
+            })
+            .expect("failed to spawn worker thread");
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    /// Queue an item to the worker thread. This waits if the queue is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to queue.
+    fn send(&mut self, item: T) -> Result<()> {
+        let sender = self.sender.as_ref().ok_or(Error::WorkerStopped)?;
+        if sender.send(item).is_err() {
+            // The worker only stops early if processing failed.
+            return match self.finish() {
+                Err(err) => Err(err),
+                Ok(()) => Err(Error::WorkerStopped),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Process the items that are still queued, and wait for the worker thread to stop.
+    fn finish(&mut self) -> Result<()> {
+        self.sender = None;
+        match self.handle.take() {
+            Some(handle) => handle.join().unwrap_or(Err(Error::WorkerStopped)),
+            None => Err(Error::WorkerStopped),
+        }
+    }
+}
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        // Dropping the sending end makes the worker stop after the queued items.
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_worker_processes_in_order() {
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut worker = Worker::spawn(
+            (processed.clone(), finished.clone()),
+            1,
+            |(processed, _), item: u32| {
+                processed.lock().unwrap().push(item);
+                Ok(())
+            },
+            |(_, finished)| {
+                finished.store(true, Ordering::SeqCst);
+                Ok(())
+            },
+        );
+        for item in 0..10 {
+            worker.send(item).unwrap();
+        }
+        worker.finish().unwrap();
+
+        assert_eq!(*processed.lock().unwrap(), (0..10).collect::<Vec<_>>());
+        assert!(finished.load(Ordering::SeqCst));
+        assert!(matches!(worker.send(10), Err(Error::WorkerStopped)));
+    }
+
+    #[test]
+    fn test_worker_propagates_error() {
+        let mut worker = Worker::spawn(
+            (),
+            1,
+            |_, item: u32| {
+                if item == 2 {
+                    Err(Error::MissingCodecParameters)
+                } else {
+                    Ok(())
+                }
+            },
+            |_| Ok(()),
+        );
+
+        // The error is returned by one of the calls after the failing item, once the worker has
+        // stopped.
+        let result = (0..100).try_for_each(|item| worker.send(item));
+        assert!(matches!(result, Err(Error::MissingCodecParameters)));
+        assert!(matches!(worker.finish(), Err(Error::WorkerStopped)));
+    }
+
+    #[test]
+    fn test_worker_drop_finishes() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let mut worker = Worker::spawn(
+            finished.clone(),
+            4,
+            |_, _item: u32| Ok(()),
+            |finished| {
+                finished.store(true, Ordering::SeqCst);
+                Ok(())
+            },
+        );
+        worker.send(0).unwrap();
+        drop(worker);
+
+        assert!(finished.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::decode::Decoder;
+use crate::io::Reader;
+use crate::transcode::{decode_error, source_start_and_duration};
+use crate::{Error, Frame, Locator, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Configuration of the search for a poster frame (see [`select_poster_frame`]).
+#[derive(Debug, Clone)]
+pub struct PosterConfig {
+    /// How far into the video to look for a poster frame, from the start. Videos that are shorter
+    /// are searched as a whole. Defaults to 10 seconds.
+    pub search_window: Time,
+    /// Number of frames to sample, spread evenly over the search window. Defaults to 10.
+    pub samples: usize,
+}
+
+impl Default for PosterConfig {
+    fn default() -> Self {
+        Self {
+            search_window: Time::from_secs(10.0),
+            samples: 10,
+        }
+    }
+}
+
+/// Pick a frame to use as the thumbnail (poster frame) of a video, instead of the first frame,
+/// which is often black or still fading in.
+///
+/// A number of frames is sampled from the start of the video, at the middle of equal parts of the
+/// search window. Every sample seeks to the keyframe before it and decodes only up to the
+/// sampled frame, so this is fast even for long videos. The samples are scored on how far their
+/// [`frame_brightness`] is from black and white, on the contrast of their luma (the standard
+/// deviation, which is low for flat frames) and on their [`frame_sharpness`]. The frame with the
+/// best score is returned.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from. It must be able to seek.
+/// * `config` - Search window and number of samples.
+///
+/// # Return value
+///
+/// The timestamp of the best frame (relative to the stream, like those of [`Decoder::decode`])
+/// and the frame itself.
+///
+/// # Example
+///
+/// ```ignore
+/// let (timestamp, frame) = select_poster_frame(
+///     &PathBuf::from("video.mp4").into(),
+///     PosterConfig {
+///         search_window: Time::from_secs(30.0),
+///         samples: 8,
+///     },
+/// )
+/// .unwrap();
+/// ```
+pub fn select_poster_frame(source: &Locator, config: PosterConfig) -> Result<(Time, Frame)> {
+    let window = config.search_window.as_secs_f64();
+    if window.is_nan() || window <= 0.0 {
+        return Err(Error::InvalidOptions(
+            "poster search window must be positive".to_string(),
+        ));
+    }
+    if config.samples == 0 {
+        return Err(Error::InvalidOptions(
+            "poster search needs at least one sample".to_string(),
+        ));
+    }
+
+    let (start, duration) = source_start_and_duration(&Reader::new(source).map_err(decode_error)?);
+    let window = duration.map_or(window, |duration| window.min(duration));
+    let step = window / config.samples as f64;
+    let mut decoder = Decoder::new(source).map_err(decode_error)?;
+
+    let mut best: Option<(f32, Time, Frame)> = None;
+    for index in 0..config.samples {
+        let target = start + (index as f64 + 0.5) * step;
+        let sample = decoder
+            .decode_between(
+                Time::from_secs_f64(target),
+                Time::from_secs_f64(target + step),
+                false,
+            )
+            .map_err(decode_error)?
+            .next()
+            .transpose()
+            .map_err(decode_error)?;
+        let Some((timestamp, frame)) = sample else {
+            continue;
+        };
+        let score = poster_score(&frame);
+        if best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+            best = Some((score, timestamp, frame));
+        }
+    }
+
+    // Videos with very few frames may have none in the middle of the parts of the window.
+    match best {
+        Some((_, timestamp, frame)) => Ok((timestamp, frame)),
+        None => decoder.decode().map_err(decode_error),
+    }
+}
+
+/// Get the mean brightness (luma) of a frame, as a fraction of the full range: `0.0` for a black
+/// frame and `1.0` for a white frame. Brightness is computed with the BT.601 luma weights.
+///
+/// # Arguments
+///
+/// * `frame` - Frame with RGB (or BGRA) channels, like those of [`Decoder::decode`].
+pub fn frame_brightness(frame: &Frame) -> f32 {
+    let (luma, _) = luma_plane(frame);
+    if luma.is_empty() {
+        return 0.0;
+    }
+
+    luma.iter().sum::<f32>() / luma.len() as f32
+}
+
+/// Get the sharpness of a frame: the mean absolute difference in luma between neighbouring
+/// pixels (horizontally and vertically), as a fraction of the full range. Blurry and flat frames
+/// score close to `0.0`, frames with much fine detail score higher. Scores are only comparable
+/// between frames of the same size, since scaling a frame down makes it look sharper.
+///
+/// # Arguments
+///
+/// * `frame` - Frame with RGB (or BGRA) channels, like those of [`Decoder::decode`].
+pub fn frame_sharpness(frame: &Frame) -> f32 {
+    let (luma, width) = luma_plane(frame);
+    sharpness(&luma, width)
+}
+
+/// Score a frame as a poster frame. Higher is better.
+///
+/// # Arguments
+///
+/// * `frame` - Sampled frame.
+fn poster_score(frame: &Frame) -> f32 {
+    let (luma, width) = luma_plane(frame);
+    if luma.is_empty() {
+        return 0.0;
+    }
+
+    let mean = luma.iter().sum::<f32>() / luma.len() as f32;
+    let variance = luma.iter().map(|y| (y - mean).powi(2)).sum::<f32>() / luma.len() as f32;
+    // Close to one for most of the range, and falling to zero towards black and white.
+    let exposure = 1.0 - (2.0 * mean - 1.0).powi(4);
+
+    exposure * variance.sqrt() * sharpness(&luma, width)
+}
+
+/// Get the sharpness of a luma plane (see [`frame_sharpness`]).
+///
+/// # Arguments
+///
+/// * `luma` - Luma samples row by row, as fractions of the full range.
+/// * `width` - Number of samples per row.
+fn sharpness(luma: &[f32], width: usize) -> f32 {
+    if width == 0 {
+        return 0.0;
+    }
+
+    let rows = luma.chunks_exact(width).collect::<Vec<_>>();
+    let horizontal = rows
+        .iter()
+        .flat_map(|row| row.windows(2).map(|pair| (pair[1] - pair[0]).abs()));
+    let vertical = rows.windows(2).flat_map(|pair| {
+        pair[0]
+            .iter()
+            .zip(pair[1])
+            .map(|(above, below)| (below - above).abs())
+    });
+    let (sum, count) = horizontal
+        .chain(vertical)
+        .fold((0.0, 0usize), |(sum, count), difference| {
+            (sum + difference, count + 1)
+        });
+
+    if count > 0 {
+        sum / count as f32
+    } else {
+        0.0
+    }
+}
+
+/// Get the luma of a frame row by row, as fractions of the full range, along with the width of
+/// the frame.
+///
+/// # Arguments
+///
+/// * `frame` - Frame with RGB channels, BGRA channels or a single gray channel.
+fn luma_plane(frame: &Frame) -> (Vec<f32>, usize) {
+    let (height, width, channels) = frame.dim();
+    let mut luma = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let channel = |c: usize| frame[[y, x, c]] as f32;
+            let (r, g, b) = match channels {
+                3 => (channel(0), channel(1), channel(2)),
+                4 => (channel(2), channel(1), channel(0)),
+                _ => (channel(0), channel(0), channel(0)),
+            };
+            luma.push((0.299 * r + 0.587 * g + 0.114 * b) / u8::MAX as f32);
+        }
+    }
+
+    (luma, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_scores() {
+        let black = Frame::zeros((48, 64, 3));
+        assert_eq!(frame_brightness(&black), 0.0);
+        assert_eq!(frame_sharpness(&black), 0.0);
+        assert_eq!(poster_score(&black), 0.0);
+
+        let white = Frame::from_elem((48, 64, 3), 255);
+        assert!((frame_brightness(&white) - 1.0).abs() < 1e-3);
+        assert_eq!(poster_score(&white), 0.0);
+
+        // A checkerboard of 4x4 squares is sharp, a smooth ramp of the same contrast is not.
+        let checkerboard =
+            Frame::from_shape_fn((48, 64, 3), |(y, x, _)| ((x / 4 + y / 4) % 2 * 255) as u8);
+        let ramp = Frame::from_shape_fn((48, 64, 3), |(_, x, _)| (x * 255 / 63) as u8);
+        assert!((frame_brightness(&checkerboard) - 0.5).abs() < 1e-3);
+        assert!((frame_sharpness(&checkerboard) - 0.25).abs() < 0.02);
+        assert!(frame_sharpness(&ramp) < 0.01);
+        assert!(poster_score(&checkerboard) > poster_score(&ramp));
+
+        // A dim checkerboard scores below a well exposed one.
+        let dim = checkerboard.mapv(|value| value / 8);
+        assert!(poster_score(&dim) < poster_score(&checkerboard));
+    }
+
+    #[test]
+    fn test_select_poster_frame() {
+        use crate::testing::{self, TempPath};
+
+        // A second of black, followed by two seconds of a checkerboard.
+        let path = TempPath::new("poster.mkv");
+        let source = path.locator();
+        let frames = (0..90).map(|i| {
+            let mut frame = testing::frame();
+            if i >= 30 {
+                let stride = frame.stride(0);
+                for (index, value) in frame.data_mut(0).iter_mut().enumerate() {
+                    let (y, x) = (index / stride, index % stride / 3);
+                    *value = ((x / 4 + y / 4) % 2 * 255) as u8;
+                }
+            }
+            frame
+        });
+        testing::write_test_video(&source, frames);
+
+        let (timestamp, frame) = select_poster_frame(
+            &source,
+            PosterConfig {
+                search_window: Time::from_secs(3.0),
+                samples: 3,
+            },
+        )
+        .unwrap();
+        assert!(timestamp.as_secs_f64() >= 1.0, "{timestamp}");
+        assert_eq!(frame.dim(), (48, 64, 3));
+        assert!(frame_sharpness(&frame) > 0.1);
+
+        assert!(matches!(
+            select_poster_frame(
+                &source,
+                PosterConfig {
+                    samples: 0,
+                    ..Default::default()
+                }
+            ),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+}
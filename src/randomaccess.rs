@@ -0,0 +1,332 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ffmpeg_next::Rational as AvRational;
+
+use crate::decode::{Decoder, END_OF_VIDEO_SECS};
+use crate::ffi::input_is_seekable;
+use crate::io::Reader;
+use crate::{Error, Locator, RawFrame, Time};
+
+#[cfg(feature = "ndarray")]
+use crate::{convert::frame_to_ndarray, Frame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Decodes frames at arbitrary timestamps of a file in batches, for workloads that fetch random
+/// frames, like loading training data.
+///
+/// Decoding a single frame at a random timestamp means seeking to the keyframe before it and
+/// decoding the frames up to it. For a batch of timestamps, [`RandomAccessDecoder`] groups the
+/// timestamps by GOP (with the keyframe index of the file, see [`Reader::build_keyframe_index`]),
+/// so that every GOP is sought to and decoded once for all of its timestamps. The GOPs are
+/// decoded in parallel, each by a worker with its own decoder.
+///
+/// For every timestamp, the frame is the first frame at or after it, which is the same frame
+/// that seeking a [`Decoder`] and decoding gives (see [`Decoder::decode_raw_between`]).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = RandomAccessDecoder::new(&PathBuf::from("video.mp4").into())
+///     .unwrap()
+///     .with_max_gops_in_flight(2);
+/// let frames = decoder
+///     .decode_raw_batch(&[Time::from_secs(12.0), Time::from_secs(1.5), Time::from_secs(12.1)])
+///     .unwrap();
+/// ```
+pub struct RandomAccessDecoder {
+    source: Locator,
+    /// Timestamps of the keyframes in seconds, in increasing order.
+    keyframes: Vec<f64>,
+    time_base: AvRational,
+    max_gops_in_flight: usize,
+    /// Decoders of the workers, which are kept between batches.
+    decoders: Vec<Decoder<'static>>,
+}
+
+impl RandomAccessDecoder {
+    /// Create a random access decoder for the best video stream of a file. This builds the
+    /// keyframe index of the file, which reads the whole file if the container has no index
+    /// (like MPEG-TS).
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to read from. It must be able to seek.
+    pub fn new(source: &Locator) -> Result<Self> {
+        let mut reader = Reader::new(source)?;
+        if !input_is_seekable(&reader.input) {
+            return Err(Error::NotSeekable);
+        }
+        let mut keyframes = reader
+            .build_keyframe_index()?
+            .into_iter()
+            .map(|keyframe| keyframe.time.as_secs_f64())
+            .collect::<Vec<_>>();
+        keyframes.sort_by(f64::total_cmp);
+        drop(reader);
+
+        let decoder = Decoder::new(source)?;
+        Ok(Self {
+            source: source.clone(),
+            keyframes,
+            time_base: decoder.time_base(),
+            max_gops_in_flight: std::thread::available_parallelism()
+                .map_or(1, |parallelism| parallelism.get())
+                .min(4),
+            decoders: vec![decoder],
+        })
+    }
+
+    /// Set the maximum number of GOPs that are decoded at the same time. Each GOP is decoded by
+    /// a worker thread with its own decoder, so this bounds the number of threads and the memory
+    /// that the decoders hold (their reference frames), besides the frames that are returned.
+    /// Defaults to the number of cores, up to 4.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_gops_in_flight` - Maximum number of GOPs decoded at the same time. Zero is taken as
+    ///   one.
+    pub fn with_max_gops_in_flight(mut self, max_gops_in_flight: usize) -> Self {
+        self.max_gops_in_flight = max_gops_in_flight.max(1);
+        self
+    }
+
+    /// Get the time base of the frames (see [`RawFrame::pts`]).
+    pub fn time_base(&self) -> AvRational {
+        self.time_base
+    }
+
+    /// Decode the frames at a batch of timestamps. This is the raw variant of
+    /// [`RandomAccessDecoder::decode_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamps` - Timestamps of the frames, in any order. Timestamps may repeat.
+    ///
+    /// # Return value
+    ///
+    /// The frame at or after each timestamp, in the order of the timestamps, or
+    /// [`Error::ReadExhausted`] if a timestamp is after the last frame.
+    pub fn decode_raw_batch(&mut self, timestamps: &[Time]) -> Result<Vec<RawFrame>> {
+        let gops = group_by_gop(&self.keyframes, timestamps);
+        while self.decoders.len() < self.max_gops_in_flight.min(gops.len()) {
+            self.decoders.push(Decoder::new(&self.source)?);
+        }
+
+        let next_gop = AtomicUsize::new(0);
+        let decoded = std::thread::scope(|scope| {
+            let workers = self
+                .decoders
+                .iter_mut()
+                .take(self.max_gops_in_flight.min(gops.len()))
+                .map(|decoder| {
+                    let (gops, next_gop) = (&gops, &next_gop);
+                    scope.spawn(move || {
+                        let mut frames = Vec::new();
+                        while let Some(gop) = gops.get(next_gop.fetch_add(1, Ordering::Relaxed)) {
+                            frames.extend(decode_gop(decoder, gop)?);
+                        }
+                        Ok(frames)
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect::<Result<Vec<Vec<_>>>>()
+        })?;
+
+        let mut frames = vec![None; timestamps.len()];
+        for (index, frame) in decoded.into_iter().flatten() {
+            frames[index] = Some(frame);
+        }
+        frames
+            .into_iter()
+            .map(|frame| frame.ok_or(Error::ReadExhausted))
+            .collect()
+    }
+
+    /// Decode the frames at a batch of timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamps` - Timestamps of the frames, in any order. Timestamps may repeat.
+    ///
+    /// # Return value
+    ///
+    /// The timestamp and frame at or after each timestamp (see [`Decoder::decode`]), in the order
+    /// of the timestamps, or [`Error::ReadExhausted`] if a timestamp is after the last frame.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_batch(&mut self, timestamps: &[Time]) -> Result<Vec<(Time, Frame)>> {
+        let time_base = self.time_base;
+        self.decode_raw_batch(timestamps)?
+            .iter()
+            .map(|frame| Ok((Time::new(frame.pts(), time_base), frame_to_ndarray(frame)?)))
+            .collect()
+    }
+}
+
+/// Requested timestamps of a GOP.
+#[derive(Debug, Clone, PartialEq)]
+struct Gop {
+    /// Index of each request and its timestamp in seconds, by increasing timestamp.
+    requests: Vec<(usize, f64)>,
+}
+
+/// Group the requested timestamps by the GOP that they fall in: the GOP of the last keyframe at
+/// or before the timestamp. Timestamps before the first keyframe are grouped with it.
+///
+/// # Arguments
+///
+/// * `keyframes` - Timestamps of the keyframes in seconds, in increasing order.
+/// * `timestamps` - Requested timestamps.
+///
+/// # Return value
+///
+/// The GOPs that have requested timestamps, in order.
+fn group_by_gop(keyframes: &[f64], timestamps: &[Time]) -> Vec<Gop> {
+    let mut requests = timestamps
+        .iter()
+        .map(Time::as_secs_f64)
+        .enumerate()
+        .collect::<Vec<_>>();
+    requests.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let mut gops: Vec<(usize, Gop)> = Vec::new();
+    for (index, time) in requests {
+        let keyframe = keyframes
+            .partition_point(|&keyframe| keyframe <= time)
+            .saturating_sub(1);
+        match gops.last_mut() {
+            Some((last, gop)) if *last == keyframe => gop.requests.push((index, time)),
+            _ => gops.push((
+                keyframe,
+                Gop {
+                    requests: vec![(index, time)],
+                },
+            )),
+        }
+    }
+
+    gops.into_iter().map(|(_, gop)| gop).collect()
+}
+
+/// Decode the frames of the requested timestamps of a GOP. Decoding starts at the keyframe
+/// before the first timestamp, and stops once the frame of the last timestamp is found.
+///
+/// # Arguments
+///
+/// * `decoder` - Decoder of the worker.
+/// * `gop` - Requested timestamps of the GOP.
+///
+/// # Return value
+///
+/// The index of each request and its frame. Requests after the last frame are left out.
+fn decode_gop(decoder: &mut Decoder, gop: &Gop) -> Result<Vec<(usize, RawFrame)>> {
+    let Some(&(_, first)) = gop.requests.first() else {
+        return Ok(Vec::new());
+    };
+    let time_base = decoder.time_base();
+
+    let mut frames = Vec::with_capacity(gop.requests.len());
+    let mut requests = gop.requests.iter().peekable();
+    for frame in decoder.decode_raw_between(
+        Time::from_secs_f64(first),
+        Time::from_secs_f64(END_OF_VIDEO_SECS),
+        false,
+    )? {
+        let frame = frame?;
+        let time = Time::new(frame.pts(), time_base).as_secs_f64();
+        while let Some(&(index, _)) = requests.next_if(|(_, request)| *request <= time) {
+            frames.push((index, frame.clone()));
+        }
+        if requests.peek().is_none() {
+            break;
+        }
+    }
+
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_gop() {
+        let keyframes = [0.0, 2.0, 4.0];
+        let timestamps = [5.0, 0.5, 2.0, 1.9, 5.0, -1.0]
+            .map(Time::from_secs_f64)
+            .to_vec();
+        assert_eq!(
+            group_by_gop(&keyframes, &timestamps),
+            vec![
+                Gop {
+                    requests: vec![(5, -1.0), (1, 0.5), (3, 1.9)]
+                },
+                Gop {
+                    requests: vec![(2, 2.0)]
+                },
+                Gop {
+                    requests: vec![(0, 5.0), (4, 5.0)]
+                },
+            ]
+        );
+        assert!(group_by_gop(&keyframes, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_decode_raw_batch() {
+        use crate::hash_raw_frame;
+        use crate::testing::{self, TempPath};
+
+        // Four seconds at 30 fps with a keyframe every second, and a different gray level for
+        // every frame.
+        let path = TempPath::new("random.mkv");
+        let source = path.locator();
+        testing::write_test_video_with_settings(
+            &source,
+            testing::settings().force_keyframe_every(std::time::Duration::from_secs(1)),
+            (0..120).map(|i| testing::filled_frame((i * 2) as u8)),
+        );
+
+        let timestamps = [3.5, 0.0, 1.2, 3.5, 0.7, 2.95, 1.0]
+            .map(Time::from_secs_f64)
+            .to_vec();
+        let mut decoder = RandomAccessDecoder::new(&source)
+            .unwrap()
+            .with_max_gops_in_flight(2);
+        let frames = decoder.decode_raw_batch(&timestamps).unwrap();
+        assert_eq!(frames.len(), timestamps.len());
+
+        // The same frames as seeking and decoding one at a time.
+        let mut sequential = Decoder::new(&source).unwrap();
+        for (timestamp, frame) in timestamps.iter().zip(&frames) {
+            let expected = sequential
+                .decode_raw_between(
+                    timestamp.clone(),
+                    Time::from_secs_f64(END_OF_VIDEO_SECS),
+                    false,
+                )
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(frame.pts(), expected.pts(), "{timestamp}");
+            assert_eq!(
+                hash_raw_frame(frame).unwrap(),
+                hash_raw_frame(&expected).unwrap(),
+                "{timestamp}"
+            );
+        }
+
+        assert!(matches!(
+            decoder.decode_raw_batch(&[Time::from_secs(10.0)]),
+            Err(Error::ReadExhausted)
+        ));
+    }
+}
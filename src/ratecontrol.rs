@@ -0,0 +1,260 @@
+use crate::error::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Rate control mode of an encoder, expressed independently of the encoder.
+///
+/// Every encoder has its own options for rate control (`crf` for `libx264`, `cq` for NVENC, `qp`
+/// for VAAPI, `crf` with a bitrate of zero for `libvpx-vp9`), so the mode is translated to the
+/// options of the encoder that is eventually used. Setting up an encoder fails with
+/// [`Error::RateControlNotSupported`] if that encoder cannot express the mode.
+///
+/// # Example
+///
+/// ```ignore
+/// let settings = Settings::for_h264_yuv420p(1920, 1080, false)
+///     .with_rate_control(RateControl::ConstrainedQuality {
+///         quality: 23,
+///         max_bitrate: 8_000_000,
+///     });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RateControl {
+    /// Constant quality, on the scale of the encoder (like CRF for `libx264`). Lower is better.
+    Quality(u8),
+    /// Average bitrate in bits per second.
+    TargetBitrate(u64),
+    /// Constant quality, but never more than the maximum bitrate in bits per second.
+    ConstrainedQuality { quality: u8, max_bitrate: u64 },
+}
+
+/// Groups of encoders that share their rate control options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EncoderFamily {
+    /// `libx264` and `libx265`.
+    X26x,
+    /// NVIDIA NVENC encoders, like `h264_nvenc`.
+    Nvenc,
+    /// VAAPI encoders, like `h264_vaapi`.
+    Vaapi,
+    /// Intel Quick Sync Video encoders, like `h264_qsv`.
+    Qsv,
+    /// `libvpx`, `libvpx-vp9` and `libaom-av1`.
+    Vpx,
+    /// `libsvtav1`.
+    SvtAv1,
+    /// Any other encoder, which only gets the generic bitrate option.
+    Other,
+}
+
+impl EncoderFamily {
+    /// Get the family of an encoder by its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Name of the encoder, like `libx264`.
+    fn of(encoder: &str) -> Self {
+        match encoder {
+            "libx264" | "libx264rgb" | "libx265" => EncoderFamily::X26x,
+            "libvpx" | "libvpx-vp9" | "libaom-av1" => EncoderFamily::Vpx,
+            "libsvtav1" => EncoderFamily::SvtAv1,
+            _ if encoder.ends_with("_nvenc") => EncoderFamily::Nvenc,
+            _ if encoder.ends_with("_vaapi") => EncoderFamily::Vaapi,
+            _ if encoder.ends_with("_qsv") => EncoderFamily::Qsv,
+            _ => EncoderFamily::Other,
+        }
+    }
+
+    /// Get the highest (worst) quality value of the encoders in the family, if they have a
+    /// quality scale.
+    fn max_quality(&self) -> Option<u8> {
+        match self {
+            EncoderFamily::X26x | EncoderFamily::Nvenc | EncoderFamily::Qsv => Some(51),
+            EncoderFamily::Vaapi => Some(52),
+            EncoderFamily::Vpx | EncoderFamily::SvtAv1 => Some(63),
+            EncoderFamily::Other => None,
+        }
+    }
+}
+
+impl RateControl {
+    /// Get the name of the mode, for error messages.
+    fn name(&self) -> &'static str {
+        match self {
+            RateControl::Quality(_) => "constant quality",
+            RateControl::TargetBitrate(_) => "target bitrate",
+            RateControl::ConstrainedQuality { .. } => "constrained quality",
+        }
+    }
+
+    /// Translate the mode to the options of an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Name of the encoder, like `libx264`.
+    ///
+    /// # Return value
+    ///
+    /// The options to pass to the encoder, or [`Error::RateControlNotSupported`] if the encoder
+    /// cannot express the mode.
+    pub(crate) fn options_for(&self, encoder: &str) -> Result<Vec<(&'static str, String)>> {
+        let family = EncoderFamily::of(encoder);
+        let not_supported = || Error::RateControlNotSupported {
+            encoder: encoder.to_string(),
+            mode: self.name().to_string(),
+        };
+
+        if let RateControl::Quality(quality) | RateControl::ConstrainedQuality { quality, .. } =
+            *self
+        {
+            let max_quality = family.max_quality().ok_or_else(not_supported)?;
+            if quality > max_quality {
+                return Err(Error::InvalidOptions(format!(
+                    "quality {quality} is out of range for {encoder} (0 to {max_quality})"
+                )));
+            }
+        }
+        if let RateControl::TargetBitrate(0)
+        | RateControl::ConstrainedQuality { max_bitrate: 0, .. } = *self
+        {
+            return Err(Error::InvalidOptions(
+                "bitrate must not be zero".to_string(),
+            ));
+        }
+
+        let options = match (family, *self) {
+            (EncoderFamily::Nvenc, RateControl::TargetBitrate(bitrate)) => {
+                vec![("rc", "cbr".to_string()), ("b", bitrate.to_string())]
+            }
+            (EncoderFamily::Vaapi, RateControl::TargetBitrate(bitrate)) => {
+                vec![("rc_mode", "CBR".to_string()), ("b", bitrate.to_string())]
+            }
+            (_, RateControl::TargetBitrate(bitrate)) => vec![("b", bitrate.to_string())],
+            (EncoderFamily::X26x | EncoderFamily::SvtAv1, RateControl::Quality(quality)) => {
+                vec![("crf", quality.to_string())]
+            }
+            (
+                EncoderFamily::X26x | EncoderFamily::SvtAv1,
+                RateControl::ConstrainedQuality {
+                    quality,
+                    max_bitrate,
+                },
+            ) => vec![
+                ("crf", quality.to_string()),
+                ("maxrate", max_bitrate.to_string()),
+                ("bufsize", (max_bitrate * 2).to_string()),
+            ],
+            (EncoderFamily::Nvenc, RateControl::Quality(quality)) => vec![
+                ("rc", "vbr".to_string()),
+                ("cq", quality.to_string()),
+                ("b", "0".to_string()),
+            ],
+            (
+                EncoderFamily::Nvenc,
+                RateControl::ConstrainedQuality {
+                    quality,
+                    max_bitrate,
+                },
+            ) => vec![
+                ("rc", "vbr".to_string()),
+                ("cq", quality.to_string()),
+                ("b", "0".to_string()),
+                ("maxrate", max_bitrate.to_string()),
+                ("bufsize", (max_bitrate * 2).to_string()),
+            ],
+            (EncoderFamily::Vaapi, RateControl::Quality(quality)) => {
+                vec![("rc_mode", "CQP".to_string()), ("qp", quality.to_string())]
+            }
+            (EncoderFamily::Qsv, RateControl::Quality(quality)) => {
+                vec![("global_quality", quality.to_string())]
+            }
+            (EncoderFamily::Vpx, RateControl::Quality(quality)) => {
+                vec![("crf", quality.to_string()), ("b", "0".to_string())]
+            }
+            // Constrained quality is the native mode of libvpx: the bitrate is the maximum.
+            (
+                EncoderFamily::Vpx,
+                RateControl::ConstrainedQuality {
+                    quality,
+                    max_bitrate,
+                },
+            ) => vec![("crf", quality.to_string()), ("b", max_bitrate.to_string())],
+            _ => return Err(not_supported()),
+        };
+
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options_for() {
+        assert_eq!(
+            RateControl::Quality(23).options_for("libx264").unwrap(),
+            vec![("crf", "23".to_string())]
+        );
+        assert_eq!(
+            RateControl::Quality(23).options_for("hevc_nvenc").unwrap(),
+            vec![
+                ("rc", "vbr".to_string()),
+                ("cq", "23".to_string()),
+                ("b", "0".to_string())
+            ]
+        );
+        assert_eq!(
+            RateControl::Quality(23).options_for("h264_vaapi").unwrap(),
+            vec![("rc_mode", "CQP".to_string()), ("qp", "23".to_string())]
+        );
+        assert_eq!(
+            RateControl::Quality(31).options_for("libvpx-vp9").unwrap(),
+            vec![("crf", "31".to_string()), ("b", "0".to_string())]
+        );
+        assert_eq!(
+            RateControl::ConstrainedQuality {
+                quality: 23,
+                max_bitrate: 4_000_000
+            }
+            .options_for("libx264")
+            .unwrap(),
+            vec![
+                ("crf", "23".to_string()),
+                ("maxrate", "4000000".to_string()),
+                ("bufsize", "8000000".to_string())
+            ]
+        );
+        assert_eq!(
+            RateControl::TargetBitrate(2_000_000)
+                .options_for("libopenh264")
+                .unwrap(),
+            vec![("b", "2000000".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_options_for_not_supported() {
+        assert!(matches!(
+            RateControl::Quality(23).options_for("libopenh264"),
+            Err(Error::RateControlNotSupported { .. })
+        ));
+        assert!(matches!(
+            RateControl::ConstrainedQuality {
+                quality: 23,
+                max_bitrate: 4_000_000
+            }
+            .options_for("h264_vaapi"),
+            Err(Error::RateControlNotSupported { .. })
+        ));
+        assert!(matches!(
+            RateControl::Quality(60).options_for("libx264"),
+            Err(Error::InvalidOptions(_))
+        ));
+        assert!(matches!(
+            RateControl::TargetBitrate(0).options_for("libx264"),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::Error;
+use crate::ffi;
+use crate::options::Options;
+use crate::PixelFormat;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Describes headerless raw video, like frames piped in from another process. Raw video carries
+/// no information about its frames, so the geometry, pixel format and frame rate must be given
+/// explicitly (like `-f rawvideo -pix_fmt yuv420p -s 1920x1080 -r 30` on the command line).
+///
+/// # Example
+///
+/// ```ignore
+/// let config = RawVideoConfig {
+///     width: 1920,
+///     height: 1080,
+///     pixel_format: PixelFormat::YUV420P,
+///     frame_rate: 30.0,
+/// };
+/// let mut decoder = Decoder::new_raw_video(&Locator::from_pipe_stdin(), config).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawVideoConfig {
+    /// Width of the frames.
+    pub width: u32,
+    /// Height of the frames.
+    pub height: u32,
+    /// Pixel format of the frames.
+    pub pixel_format: PixelFormat,
+    /// Frames per second.
+    pub frame_rate: f64,
+}
+
+impl RawVideoConfig {
+    /// Get the number of bytes in a single frame. Frames are tightly packed, without any padding
+    /// between rows or planes.
+    pub fn frame_size(&self) -> Result<usize> {
+        self.validate()?;
+        ffi::image_buffer_size(self.pixel_format, self.width, self.height).ok_or_else(|| {
+            Error::InvalidRawVideoConfig(format!(
+                "pixel format {:?} has no fixed frame size",
+                self.pixel_format
+            ))
+        })
+    }
+
+    /// Get the `rawvideo` demuxer options that describe the frames.
+    pub(crate) fn options(&self) -> Result<Options<'static>> {
+        self.validate()?;
+        let pixel_format = self
+            .pixel_format
+            .descriptor()
+            .map(|descriptor| descriptor.name())
+            .ok_or_else(|| {
+                Error::InvalidRawVideoConfig(format!(
+                    "unknown pixel format: {:?}",
+                    self.pixel_format
+                ))
+            })?;
+
+        let mut options = Options::default();
+        options.set("video_size", &format!("{}x{}", self.width, self.height));
+        options.set("pixel_format", pixel_format);
+        options.set("framerate", &self.frame_rate.to_string());
+
+        Ok(options)
+    }
+
+    /// Check the dimensions and frame rate.
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidRawVideoConfig(format!(
+                "invalid frame size: {}x{}",
+                self.width, self.height
+            )));
+        }
+        if !(self.frame_rate.is_finite() && self.frame_rate > 0.0) {
+            return Err(Error::InvalidRawVideoConfig(format!(
+                "invalid frame rate: {}",
+                self.frame_rate
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config = RawVideoConfig {
+            width: 1920,
+            height: 1080,
+            pixel_format: PixelFormat::YUV420P,
+            frame_rate: 30.0,
+        };
+        assert!(config.validate().is_ok());
+        assert!(matches!(
+            RawVideoConfig { width: 0, ..config }.validate(),
+            Err(Error::InvalidRawVideoConfig(_))
+        ));
+        assert!(matches!(
+            RawVideoConfig {
+                frame_rate: f64::NAN,
+                ..config
+            }
+            .validate(),
+            Err(Error::InvalidRawVideoConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,836 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::{HashMap, HashSet};
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::ffi::{
+    clear_codec_tag, input_start_time, output_format_supports_codec, parameters_extradata,
+    set_stream_disposition, BitstreamFilter,
+};
+use crate::io::{private::Write, Reader, Writer};
+use crate::stream::MappedStream;
+use crate::{Error, Locator, StreamMap, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Container formats that require H.264 and H.265 streams in Annex B format (with start codes).
+const ANNEX_B_FORMATS: &[&str] = &["mpegts", "rtp_mpegts", "h264", "hevc"];
+
+/// Copies streams from one container into another without re-encoding (also known as stream copy),
+/// for example to rewrap an MKV file into MP4.
+///
+/// # Example
+///
+/// ```ignore
+/// let stats = Remuxer::new(
+///     &PathBuf::from("video.mkv").into(),
+///     &PathBuf::from("video.mp4").into(),
+/// )
+/// .unwrap()
+/// .run()
+/// .unwrap();
+/// println!("Copied {} seconds", stats.duration.as_secs());
+/// ```
+pub struct Remuxer {
+    reader: Reader,
+    writer: Writer,
+    stream_map: Option<StreamMap>,
+    segment: Option<Segment>,
+}
+
+impl Remuxer {
+    /// Create a new remuxer. The destination container format is inferred from the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to read from.
+    /// * `dest` - Locator to write to.
+    pub fn new(source: &Locator, dest: &Locator) -> Result<Self> {
+        Ok(Self {
+            reader: Reader::new(source)?,
+            writer: Writer::new(dest)?,
+            stream_map: None,
+            segment: None,
+        })
+    }
+
+    /// Create a new remuxer with a custom destination container format.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to read from.
+    /// * `dest` - Locator to write to.
+    /// * `format` - Container format to use.
+    pub fn new_with_format(source: &Locator, dest: &Locator, format: &str) -> Result<Self> {
+        Ok(Self {
+            reader: Reader::new(source)?,
+            writer: Writer::new_with_format(dest, format)?,
+            stream_map: None,
+            segment: None,
+        })
+    }
+
+    /// Select the streams to copy. By default, all video and audio streams are copied, along with
+    /// the data streams (like KLV metadata) if the destination container supports them.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_indices` - Indices of the source streams to copy.
+    pub fn with_streams(self, stream_indices: &[usize]) -> Self {
+        let stream_map = stream_indices
+            .iter()
+            .fold(StreamMap::new(), |stream_map, &stream_index| {
+                stream_map.map_stream(stream_index)
+            });
+        self.with_stream_map(stream_map)
+    }
+
+    /// Select the streams to copy and their order with a stream map. Output streams are numbered as
+    /// described in [`StreamMap`]. By default, all video and audio streams are copied, along with
+    /// the data streams (like KLV metadata) if the destination container supports them.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_map` - Stream map to use.
+    pub fn with_stream_map(mut self, stream_map: StreamMap) -> Self {
+        self.stream_map = Some(stream_map);
+        self
+    }
+
+    /// Only copy a segment of the source, from `start` to `end` (relative to the start of the
+    /// source). Timestamps are rewritten so that the output starts near zero.
+    ///
+    /// Without re-encoding, video can only be cut at key frames, so the output starts at a key
+    /// frame near `start` (as determined by `alignment`), and may run slightly past `end` to keep
+    /// frames that depend on earlier frames decodable. The output duration is within one GOP of the
+    /// requested range. Other streams (like audio) are cut where the video starts.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start of the segment.
+    /// * `end` - End of the segment.
+    /// * `alignment` - Which key frame to start the segment at.
+    pub fn with_segment(mut self, start: Time, end: Time, alignment: KeyFrameAlignment) -> Self {
+        self.segment = Some(Segment {
+            start,
+            end,
+            alignment,
+        });
+        self
+    }
+
+    /// Copy the selected streams until the source is exhausted.
+    ///
+    /// # Return value
+    ///
+    /// Statistics about the copied streams.
+    pub fn run(mut self) -> Result<RemuxStats> {
+        let mapped_streams = match self.stream_map.take() {
+            Some(stream_map) => stream_map.resolve(&self.reader.input)?,
+            None => self
+                .reader
+                .input
+                .streams()
+                .filter(|stream| {
+                    let parameters = stream.parameters();
+                    match parameters.medium() {
+                        AvMediaType::Video | AvMediaType::Audio => true,
+                        AvMediaType::Data => {
+                            parameters.id() != AvCodecId::None
+                                && output_format_supports_codec(
+                                    &self.writer.output,
+                                    parameters.id(),
+                                ) != Some(false)
+                        }
+                        _ => false,
+                    }
+                })
+                .map(|stream| MappedStream {
+                    index: stream.index(),
+                    disposition: stream.disposition().bits(),
+                })
+                .collect(),
+        };
+        let stream_indices = mapped_streams
+            .iter()
+            .map(|stream| stream.index)
+            .collect::<Vec<_>>();
+
+        let mut trim = match self.segment.take() {
+            Some(segment) => Some(self.seek_to_segment(segment, &stream_indices)?),
+            None => None,
+        };
+
+        let format = self.writer.output.format().name().to_string();
+        let mut streams = HashMap::new();
+        for mapped_stream in &mapped_streams {
+            let stream_index = mapped_stream.index;
+            let stream = &self
+                .reader
+                .input
+                .stream(stream_index)
+                .ok_or(AvError::StreamNotFound)?;
+            let parameters = stream.parameters();
+            if parameters.medium() == AvMediaType::Data
+                && output_format_supports_codec(&self.writer.output, parameters.id()) == Some(false)
+            {
+                return Err(Error::DataStreamsNotSupported(format));
+            }
+            let filter =
+                bitstream_filter_name(parameters.id(), &parameters_extradata(&parameters), &format)
+                    .map(|name| BitstreamFilter::new(name, &parameters, stream.time_base()))
+                    .transpose()?;
+
+            let mut writer_parameters = match filter.as_ref() {
+                Some(filter) => filter.parameters_out(),
+                None => parameters.clone(),
+            };
+            clear_codec_tag(&mut writer_parameters);
+
+            let mut writer_stream = self
+                .writer
+                .output
+                .add_stream(ffmpeg::encoder::find(parameters.id()))?;
+            writer_stream.set_parameters(writer_parameters);
+            let writer_stream_index = writer_stream.index();
+            set_stream_disposition(
+                &mut self.writer.output,
+                writer_stream_index,
+                mapped_stream.disposition,
+            );
+
+            streams.insert(
+                stream_index,
+                RemuxStream {
+                    index: writer_stream_index,
+                    source_time_base: filter
+                        .as_ref()
+                        .map_or(stream.time_base(), BitstreamFilter::time_base_out),
+                    // Set after writing the header, since the muxer may change it.
+                    destination_time_base: AvRational::new(0, 1),
+                    filter,
+                    packets: 0,
+                    start: None,
+                    end: None,
+                },
+            );
+        }
+
+        self.writer.write_header()?;
+        for stream in streams.values_mut() {
+            stream.destination_time_base = self
+                .writer
+                .output
+                .stream(stream.index)
+                .ok_or(AvError::StreamNotFound)?
+                .time_base();
+        }
+
+        loop {
+            match self.reader.read_any() {
+                Ok((stream_index, packet)) => {
+                    if !streams.contains_key(&stream_index) {
+                        continue;
+                    }
+
+                    let (packet, time_base) = packet.into_inner_parts();
+                    let packets = match trim.as_mut() {
+                        Some(trim) => trim.process(stream_index, packet, time_base),
+                        None => vec![(stream_index, packet)],
+                    };
+                    for (stream_index, packet) in packets {
+                        if let Some(stream) = streams.get_mut(&stream_index) {
+                            stream.copy(Some(packet), &mut self.writer)?;
+                        }
+                    }
+
+                    if trim.as_ref().is_some_and(Trim::is_done) {
+                        break;
+                    }
+                }
+                Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        for stream in streams.values_mut() {
+            stream.copy(None, &mut self.writer)?;
+        }
+        self.writer.write_trailer()?;
+
+        Ok(RemuxStats {
+            packets: streams
+                .iter()
+                .map(|(&stream_index, stream)| (stream_index, stream.packets))
+                .collect(),
+            duration: streams
+                .values()
+                .map(RemuxStream::duration)
+                .max_by(|a, b| a.as_secs_f64().total_cmp(&b.as_secs_f64()))
+                .unwrap_or_else(Time::zero),
+        })
+    }
+
+    /// Seek to the start of a segment and prepare trimming it.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment` - Segment to copy.
+    /// * `stream_indices` - Indices of the source streams that are copied.
+    fn seek_to_segment(&mut self, segment: Segment, stream_indices: &[usize]) -> Result<Trim> {
+        // Packet timestamps are offset by the start time of the source.
+        let source_start = match input_start_time(&self.reader.input) {
+            ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+            source_start => source_start,
+        };
+        let to_source_time = |time: &Time| {
+            time.aligned_with_rational(TIME_BASE)
+                .into_value()
+                .unwrap_or(0)
+                + source_start
+        };
+        let start = to_source_time(&segment.start);
+        let end = to_source_time(&segment.end);
+
+        let video_stream_index = self
+            .reader
+            .best_video_stream_index()
+            .ok()
+            .filter(|index| stream_indices.contains(index));
+        // The backend treats the bounds of the range as inclusive.
+        match (segment.alignment, video_stream_index) {
+            (KeyFrameAlignment::After, Some(_)) => self.reader.input.seek(start, start..)?,
+            _ => self.reader.input.seek(start, ..start)?,
+        }
+
+        Ok(Trim::new(
+            start,
+            end,
+            segment.alignment,
+            video_stream_index,
+            stream_indices,
+        ))
+    }
+}
+
+/// Extract a segment from `start` to `end` (relative to the start of the source) into a new
+/// container, without re-encoding. The segment starts at the key frame at or before `start`. See
+/// [`Remuxer::with_segment`] for details on accuracy.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `dest` - Locator to write to.
+/// * `start` - Start of the segment.
+/// * `end` - End of the segment.
+///
+/// # Example
+///
+/// ```ignore
+/// extract_segment(
+///     &PathBuf::from("recording.mp4").into(),
+///     &PathBuf::from("clip.mp4").into(),
+///     Time::from_secs(600.0),
+///     Time::from_secs(610.0),
+/// )
+/// .unwrap();
+/// ```
+pub fn extract_segment(
+    source: &Locator,
+    dest: &Locator,
+    start: Time,
+    end: Time,
+) -> Result<RemuxStats> {
+    extract_segment_with_alignment(source, dest, start, end, KeyFrameAlignment::Before)
+}
+
+/// Extract a segment from `start` to `end` into a new container, without re-encoding, with a
+/// custom key frame alignment. See [`Remuxer::with_segment`] for details on accuracy.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `dest` - Locator to write to.
+/// * `start` - Start of the segment.
+/// * `end` - End of the segment.
+/// * `alignment` - Which key frame to start the segment at.
+pub fn extract_segment_with_alignment(
+    source: &Locator,
+    dest: &Locator,
+    start: Time,
+    end: Time,
+    alignment: KeyFrameAlignment,
+) -> Result<RemuxStats> {
+    Remuxer::new(source, dest)?
+        .with_segment(start, end, alignment)
+        .run()
+}
+
+/// Which key frame a segment starts at when copying it without re-encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFrameAlignment {
+    /// Start at the key frame at or before the requested start. The segment may start somewhat
+    /// early, but contains everything in the requested range.
+    Before,
+    /// Start at the first key frame at or after the requested start. The segment does not contain
+    /// anything before the requested start, but may miss the beginning of the requested range.
+    After,
+}
+
+/// Statistics about a finished remux.
+#[derive(Debug, Clone)]
+pub struct RemuxStats {
+    /// Number of packets copied, by source stream index.
+    pub packets: HashMap<usize, u64>,
+    /// Duration of the longest copied stream.
+    pub duration: Time,
+}
+
+/// Segment of the source to copy.
+struct Segment {
+    start: Time,
+    end: Time,
+    alignment: KeyFrameAlignment,
+}
+
+/// Internal state for trimming packets to a segment. All timestamps are in `TIME_BASE` units.
+struct Trim {
+    start: i64,
+    end: i64,
+    alignment: KeyFrameAlignment,
+    /// Index of the video stream that determines where the segment starts, if there is one.
+    video_stream_index: Option<usize>,
+    /// Where the segment starts, once it is known.
+    cut: Option<Cut>,
+    /// Packets of other streams that were read before the segment start was known.
+    pending: Vec<(usize, AvPacket, AvRational)>,
+    /// Streams that have not reached the end of the segment yet.
+    remaining: HashSet<usize>,
+}
+
+/// Where a segment starts.
+#[derive(Clone, Copy)]
+struct Cut {
+    /// Presentation timestamp of the first frame. Packets of other streams before this are dropped.
+    pts: i64,
+    /// Offset to subtract from all timestamps.
+    offset: i64,
+}
+
+impl Trim {
+    /// Create new trimming state.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - Start of the segment.
+    /// * `end` - End of the segment.
+    /// * `alignment` - Which key frame to start the segment at.
+    /// * `video_stream_index` - Index of the video stream, if there is one.
+    /// * `stream_indices` - Indices of all streams that are copied.
+    fn new(
+        start: i64,
+        end: i64,
+        alignment: KeyFrameAlignment,
+        video_stream_index: Option<usize>,
+        stream_indices: &[usize],
+    ) -> Self {
+        Self {
+            start,
+            end,
+            alignment,
+            video_stream_index,
+            // Without video, there is no key frame to wait for.
+            cut: video_stream_index.is_none().then_some(Cut {
+                pts: start,
+                offset: start,
+            }),
+            pending: Vec::new(),
+            remaining: stream_indices.iter().copied().collect(),
+        }
+    }
+
+    /// Process a packet read from the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream the packet belongs to.
+    /// * `packet` - Packet to process.
+    /// * `time_base` - Time base of the packet.
+    ///
+    /// # Return value
+    ///
+    /// Packets that are part of the segment (with their stream index), with their timestamps
+    /// rewritten.
+    fn process(
+        &mut self,
+        stream_index: usize,
+        packet: AvPacket,
+        time_base: AvRational,
+    ) -> Vec<(usize, AvPacket)> {
+        let to_time_base = |ts: i64| ts.rescale(time_base, TIME_BASE);
+        let pts = packet.pts().map(to_time_base);
+        let is_video = Some(stream_index) == self.video_stream_index;
+
+        // Video is cut by decoding timestamp, to keep frames that are needed to decode the frames
+        // in the segment.
+        let end_timestamp = if is_video {
+            packet.dts().map(to_time_base).or(pts)
+        } else {
+            pts
+        };
+        if end_timestamp.is_some_and(|timestamp| timestamp >= self.end) {
+            self.remaining.remove(&stream_index);
+            return Vec::new();
+        }
+
+        let packets = match self.cut {
+            Some(cut) => {
+                if !is_video && pts.is_some_and(|pts| pts < cut.pts) {
+                    return Vec::new();
+                }
+
+                vec![(stream_index, packet, time_base)]
+            }
+            None if is_video => {
+                let is_start = packet.is_key()
+                    && match self.alignment {
+                        KeyFrameAlignment::Before => true,
+                        KeyFrameAlignment::After => pts.is_some_and(|pts| pts >= self.start),
+                    };
+                if !is_start {
+                    return Vec::new();
+                }
+
+                let cut_pts = pts.unwrap_or(self.start);
+                let cut = Cut {
+                    pts: cut_pts,
+                    offset: packet.dts().map(to_time_base).unwrap_or(cut_pts),
+                };
+                self.cut = Some(cut);
+
+                let mut packets = std::mem::take(&mut self.pending)
+                    .into_iter()
+                    .filter(|(_, packet, time_base)| {
+                        packet
+                            .pts()
+                            .is_none_or(|pts| pts.rescale(*time_base, TIME_BASE) >= cut.pts)
+                    })
+                    .collect::<Vec<_>>();
+                packets.push((stream_index, packet, time_base));
+                packets
+            }
+            None => {
+                self.pending.push((stream_index, packet, time_base));
+                return Vec::new();
+            }
+        };
+
+        let offset = self.cut.map_or(0, |cut| cut.offset);
+        packets
+            .into_iter()
+            .map(|(stream_index, mut packet, time_base)| {
+                let offset = offset.rescale(TIME_BASE, time_base);
+                packet.set_pts(packet.pts().map(|pts| pts - offset));
+                packet.set_dts(packet.dts().map(|dts| dts - offset));
+                (stream_index, packet)
+            })
+            .collect()
+    }
+
+    /// Whether or not all streams have reached the end of the segment.
+    fn is_done(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+/// Internal state of a stream that is being copied.
+struct RemuxStream {
+    index: usize,
+    source_time_base: AvRational,
+    destination_time_base: AvRational,
+    filter: Option<BitstreamFilter>,
+    packets: u64,
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+impl RemuxStream {
+    /// Copy a packet to the destination stream, passing it through the bitstream filter if there is
+    /// one.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to copy, or `None` to flush the bitstream filter at the end of the
+    ///   stream.
+    /// * `writer` - Writer to write to.
+    fn copy(&mut self, packet: Option<AvPacket>, writer: &mut Writer) -> Result<()> {
+        let Some(filter) = self.filter.as_mut() else {
+            return match packet {
+                Some(packet) => self.write(packet, writer),
+                None => Ok(()),
+            };
+        };
+
+        match packet {
+            Some(mut packet) => filter.send(Some(&mut packet))?,
+            None => filter.send(None)?,
+        }
+
+        let mut filtered = Vec::new();
+        loop {
+            let mut packet = AvPacket::empty();
+            match filter.receive(&mut packet) {
+                Ok(()) => filtered.push(packet),
+                Err(AvError::Eof) => break,
+                Err(AvError::Other { errno }) if errno == EAGAIN => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        filtered
+            .into_iter()
+            .try_for_each(|packet| self.write(packet, writer))
+    }
+
+    /// Write a packet to the destination stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write.
+    /// * `writer` - Writer to write to.
+    fn write(&mut self, mut packet: AvPacket, writer: &mut Writer) -> Result<()> {
+        packet.set_stream(self.index);
+        packet.set_position(-1);
+        packet.rescale_ts(self.source_time_base, self.destination_time_base);
+
+        if let Some(pts) = packet.pts() {
+            let end = pts + packet.duration();
+            self.start = Some(self.start.map_or(pts, |start| start.min(pts)));
+            self.end = Some(self.end.map_or(end, |current| current.max(end)));
+        }
+        self.packets += 1;
+
+        writer.write_interleaved(&mut packet)
+    }
+
+    /// Duration of the copied stream.
+    fn duration(&self) -> Time {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => Time::new(Some(end - start), self.destination_time_base),
+            _ => Time::zero(),
+        }
+    }
+}
+
+/// Determine which bitstream filter (if any) is needed to copy a stream into a container format.
+///
+/// Streams in MP4 and MKV store H.264 and H.265 as length-prefixed NAL units, while formats like
+/// MPEG-TS require Annex B (start codes). The opposite direction does not require a filter, since
+/// the MP4 and MKV muxers convert Annex B themselves.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec of the stream.
+/// * `extradata` - Extradata of the stream.
+/// * `format` - Name of the destination container format.
+fn bitstream_filter_name(
+    codec_id: AvCodecId,
+    extradata: &[u8],
+    format: &str,
+) -> Option<&'static str> {
+    // Length-prefixed extradata (`avcC` or `hvcC`) starts with version 1, whereas Annex B
+    // extradata starts with a start code.
+    let length_prefixed = extradata.first() == Some(&1);
+    if !length_prefixed || !ANNEX_B_FORMATS.contains(&format) {
+        return None;
+    }
+
+    match codec_id {
+        AvCodecId::H264 => Some("h264_mp4toannexb"),
+        AvCodecId::HEVC => Some("hevc_mp4toannexb"),
+        _ => None,
+    }
+}
+
+unsafe impl Send for Remuxer {}
+unsafe impl Sync for Remuxer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    #[test]
+    fn test_bitstream_filter_name() {
+        let avcc = [1, 100, 0, 31];
+        let annex_b = [0, 0, 0, 1, 103];
+        assert_eq!(
+            bitstream_filter_name(AvCodecId::H264, &avcc, "mpegts"),
+            Some("h264_mp4toannexb")
+        );
+        assert_eq!(
+            bitstream_filter_name(AvCodecId::HEVC, &avcc, "hevc"),
+            Some("hevc_mp4toannexb")
+        );
+        assert_eq!(bitstream_filter_name(AvCodecId::H264, &avcc, "mp4"), None);
+        assert_eq!(
+            bitstream_filter_name(AvCodecId::H264, &annex_b, "mpegts"),
+            None
+        );
+        assert_eq!(bitstream_filter_name(AvCodecId::H264, &[], "mpegts"), None);
+        assert_eq!(bitstream_filter_name(AvCodecId::AAC, &avcc, "mpegts"), None);
+    }
+
+    /// Feed packets of a video stream (index 0, key frame every second, a frame every 100 ms)
+    /// and an audio stream (index 1, a packet every 50 ms) from 1.9 s up to 5 s to the trimmer,
+    /// in milliseconds.
+    fn trim_test_packets(trim: &mut Trim) -> Vec<(usize, i64, i64)> {
+        const TIME_BASE_MS: AvRational = AvRational(1, 1000);
+
+        let mut output = Vec::new();
+        for ms in (1900..5000).step_by(50) {
+            let mut packets = Vec::new();
+            if ms % 100 == 0 {
+                let mut packet = AvPacket::empty();
+                packet.set_pts(Some(ms));
+                packet.set_dts(Some(ms - 100));
+                if ms % 1000 == 0 {
+                    packet.set_flags(ffmpeg::codec::packet::Flags::KEY);
+                }
+                packets.push((0, packet));
+            }
+            let mut packet = AvPacket::empty();
+            packet.set_pts(Some(ms));
+            packet.set_dts(Some(ms));
+            packets.push((1, packet));
+
+            for (stream_index, packet) in packets {
+                output.extend(
+                    trim.process(stream_index, packet, TIME_BASE_MS)
+                        .into_iter()
+                        .map(|(stream_index, packet)| {
+                            (stream_index, packet.pts().unwrap(), packet.dts().unwrap())
+                        }),
+                );
+            }
+        }
+
+        output
+    }
+
+    #[test]
+    fn test_trim_key_frame_before() {
+        let mut trim = Trim::new(
+            2_500_000,
+            4_200_000,
+            KeyFrameAlignment::Before,
+            Some(0),
+            &[0, 1],
+        );
+        let output = trim_test_packets(&mut trim);
+        assert!(trim.is_done());
+
+        // The segment starts at the key frame at 2 s, with timestamps starting near zero.
+        assert_eq!(output.first(), Some(&(0, 100, 0)));
+        let video = output.iter().filter(|(index, _, _)| *index == 0);
+        assert_eq!(video.count(), 23);
+        let audio = output
+            .iter()
+            .filter(|(index, _, _)| *index == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(audio.first(), Some(&&(1, 100, 100)));
+        assert_eq!(audio.last(), Some(&&(1, 2250, 2250)));
+    }
+
+    #[test]
+    fn test_trim_key_frame_after() {
+        let mut trim = Trim::new(
+            2_500_000,
+            4_200_000,
+            KeyFrameAlignment::After,
+            Some(0),
+            &[0, 1],
+        );
+        let output = trim_test_packets(&mut trim);
+
+        // The segment starts at the key frame at 3 s.
+        assert_eq!(output.first(), Some(&(0, 100, 0)));
+        let audio = output
+            .iter()
+            .filter(|(index, _, _)| *index == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(audio.first(), Some(&&(1, 100, 100)));
+        assert_eq!(audio.last(), Some(&&(1, 1250, 1250)));
+    }
+
+    #[test]
+    fn test_trim_without_video() {
+        let mut trim = Trim::new(2_500_000, 4_200_000, KeyFrameAlignment::Before, None, &[1]);
+        let output = trim_test_packets(&mut trim)
+            .into_iter()
+            .filter(|(index, _, _)| *index == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(output.first(), Some(&(1, 0, 0)));
+        assert_eq!(output.last(), Some(&(1, 1650, 1650)));
+    }
+
+    #[test]
+    fn test_extract_segment_within_one_gop() {
+        const NUM_FRAMES: usize = 300;
+
+        let dir = TempPath::new("trim");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mp4").into();
+        let dest: Locator = dir.join("segment.mp4").into();
+
+        testing::write_test_video(
+            &source,
+            (0..NUM_FRAMES).map(|i| testing::filled_frame(i as u8)),
+        );
+
+        let stats =
+            extract_segment(&source, &dest, Time::from_secs(4.0), Time::from_secs(6.0)).unwrap();
+        // The encoder produces a key frame every 12 frames.
+        let gop = 12.0 / 30.0;
+        assert!((stats.duration.as_secs_f64() - 2.0).abs() <= gop + 0.1);
+
+        let mut reader = Reader::new(&dest).unwrap();
+        let (_, packet) = reader.read_any().unwrap();
+        assert!(packet.is_key());
+        assert!(packet.pts().as_secs_f64() < 0.1);
+    }
+
+    #[test]
+    fn test_remux_round_trip() {
+        const NUM_FRAMES: usize = 30;
+
+        let dir = TempPath::new("remux");
+        std::fs::create_dir_all(&dir).unwrap();
+        let original: Locator = dir.join("original.mkv").into();
+        let mp4: Locator = dir.join("remuxed.mp4").into();
+        let mkv: Locator = dir.join("remuxed.mkv").into();
+
+        testing::write_test_video(
+            &original,
+            (0..NUM_FRAMES).map(|i| testing::filled_frame(i as u8)),
+        );
+
+        let stats = Remuxer::new(&original, &mp4).unwrap().run().unwrap();
+        assert_eq!(stats.packets.get(&0), Some(&(NUM_FRAMES as u64)));
+        assert!((stats.duration.as_secs_f64() - 1.0).abs() < 0.1);
+
+        let stats = Remuxer::new(&mp4, &mkv).unwrap().run().unwrap();
+        assert_eq!(stats.packets.get(&0), Some(&(NUM_FRAMES as u64)));
+
+        let reader = Reader::new(&mkv).unwrap();
+        let stream = reader.input.stream(0).unwrap();
+        assert_eq!(stream.parameters().id(), AvCodecId::H264);
+    }
+}
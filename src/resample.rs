@@ -0,0 +1,278 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::software::resampling::context::Context as AvResampler;
+use ffmpeg::util::channel_layout::ChannelLayout as AvChannelLayout;
+
+use crate::audio::{RawSamples, SAMPLE_FORMAT};
+use crate::error::{ErrorContext, WithContext};
+use crate::ffi::resampler_output_capacity;
+use crate::Error;
+
+#[cfg(feature = "ndarray")]
+use crate::audio::{convert_samples_to_ndarray, Samples};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Sample rate and channels of audio samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSpec {
+    /// Samples per second.
+    pub sample_rate: u32,
+    /// Number of channels. Channels are in the default order for the number of channels, like
+    /// 5.1 (`FL FR FC LFE SL SR`) for six channels.
+    pub channels: u16,
+}
+
+/// Converts audio samples to another sample rate and channel layout.
+///
+/// The resampler keeps state between calls: samples that cannot be converted yet (because the
+/// filter needs samples that come after them) are buffered, and come out with the next call. Call
+/// [`Resampler::flush`] after the last samples to get the samples that are still buffered, so that
+/// no samples are lost at the end. The number of output samples thus follows the number of input
+/// samples exactly, across calls.
+///
+/// Channels are mixed with the default matrix of ffmpeg, which mixes the center and surround
+/// channels into the front channels when downmixing (like 5.1 to stereo).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = AudioDecoder::new(&PathBuf::from("surround.mkv").into()).unwrap();
+/// let mut resampler = Resampler::new(
+///     decoder.sample_spec(),
+///     SampleSpec {
+///         sample_rate: 48000,
+///         channels: 2,
+///     },
+/// )
+/// .unwrap();
+/// for samples in decoder.decode_iter().take_while(Result::is_ok) {
+///     let (_, samples) = samples.unwrap();
+///     let samples = resampler.convert(&samples).unwrap();
+/// }
+/// let tail = resampler.flush().unwrap();
+/// ```
+pub struct Resampler {
+    resampler: AvResampler,
+    input: SampleSpec,
+    output: SampleSpec,
+    output_layout: AvChannelLayout,
+}
+
+impl Resampler {
+    /// Create a resampler. Samples are in packed `f32` format on both sides, like the samples of
+    /// [`crate::AudioDecoder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Sample rate and channels of the input samples.
+    /// * `output` - Sample rate and channels to convert to.
+    pub fn new(input: SampleSpec, output: SampleSpec) -> Result<Self> {
+        for spec in [input, output] {
+            if spec.sample_rate == 0 || spec.channels == 0 {
+                return Err(Error::InvalidOptions(format!(
+                    "invalid sample spec: {} Hz with {} channels",
+                    spec.sample_rate, spec.channels
+                )));
+            }
+        }
+
+        let input_layout = AvChannelLayout::default(input.channels as i32);
+        let output_layout = AvChannelLayout::default(output.channels as i32);
+        let resampler = AvResampler::get(
+            SAMPLE_FORMAT,
+            input_layout,
+            input.sample_rate,
+            SAMPLE_FORMAT,
+            output_layout,
+            output.sample_rate,
+        )?;
+
+        Ok(Self {
+            resampler,
+            input,
+            output,
+            output_layout,
+        })
+    }
+
+    /// Get the sample rate and channels of the input samples.
+    pub fn input(&self) -> SampleSpec {
+        self.input
+    }
+
+    /// Get the sample rate and channels of the output samples.
+    pub fn output(&self) -> SampleSpec {
+        self.output
+    }
+
+    /// Convert samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Samples with dims `(N, C)`, with the channels of the input spec.
+    ///
+    /// # Return value
+    ///
+    /// The samples that could be converted so far, with dims `(N, C)`. There may be fewer than
+    /// the input samples call for, since some are kept until the next call.
+    #[cfg(feature = "ndarray")]
+    pub fn convert(&mut self, samples: &Samples) -> Result<Samples> {
+        let (num_samples, num_channels) = samples.dim();
+        if num_channels != self.input.channels as usize {
+            return Err(Error::invalid_frame_format(
+                format!("{} channels", self.input.channels),
+                format!("{num_channels} channels"),
+            ));
+        }
+        if num_samples == 0 {
+            return Ok(Samples::zeros((0, self.output.channels as usize)));
+        }
+
+        let mut frame = RawSamples::new(
+            SAMPLE_FORMAT,
+            num_samples,
+            AvChannelLayout::default(self.input.channels as i32),
+        );
+        frame.set_rate(self.input.sample_rate);
+        for (bytes, sample) in frame
+            .data_mut(0)
+            .chunks_exact_mut(std::mem::size_of::<f32>())
+            .zip(samples.iter())
+        {
+            bytes.copy_from_slice(&sample.to_ne_bytes());
+        }
+
+        let frame = self.convert_raw(&frame)?;
+        self.to_ndarray(frame)
+    }
+
+    /// Get the samples that are still buffered, after the last samples were converted.
+    ///
+    /// # Return value
+    ///
+    /// The remaining samples, with dims `(N, C)`.
+    #[cfg(feature = "ndarray")]
+    pub fn flush(&mut self) -> Result<Samples> {
+        let frame = self.flush_raw()?;
+        self.to_ndarray(frame)
+    }
+
+    /// Convert raw samples. This is the raw variant of [`Resampler::convert`].
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Samples in packed `f32` format, with the sample rate and channels of the
+    ///   input spec.
+    ///
+    /// # Return value
+    ///
+    /// The samples that could be converted so far, in packed `f32` format.
+    pub fn convert_raw(&mut self, samples: &RawSamples) -> Result<RawSamples> {
+        if samples.format() != SAMPLE_FORMAT
+            || samples.rate() != self.input.sample_rate
+            || samples.channels() != self.input.channels
+        {
+            return Err(Error::invalid_frame_format(
+                format!(
+                    "{SAMPLE_FORMAT:?} at {} Hz with {} channels",
+                    self.input.sample_rate, self.input.channels
+                ),
+                format!(
+                    "{:?} at {} Hz with {} channels",
+                    samples.format(),
+                    samples.rate(),
+                    samples.channels()
+                ),
+            ));
+        }
+
+        // The output must have room for the samples that were buffered before as well.
+        let capacity = resampler_output_capacity(&mut self.resampler, samples.samples());
+        let mut output = RawSamples::new(SAMPLE_FORMAT, capacity.max(1), self.output_layout);
+        self.resampler
+            .run(samples, &mut output)
+            .context(ErrorContext::new("resampler.run").with_pts(samples.pts()))?;
+
+        Ok(output)
+    }
+
+    /// Get the samples that are still buffered, after the last samples were converted. This is
+    /// the raw variant of [`Resampler::flush`].
+    ///
+    /// # Return value
+    ///
+    /// The remaining samples, in packed `f32` format.
+    pub fn flush_raw(&mut self) -> Result<RawSamples> {
+        let capacity = resampler_output_capacity(&mut self.resampler, 0);
+        let mut output = RawSamples::new(SAMPLE_FORMAT, capacity.max(1), self.output_layout);
+        self.resampler
+            .flush(&mut output)
+            .context(ErrorContext::new("resampler.flush"))?;
+
+        Ok(output)
+    }
+
+    /// Convert output samples to an `ndarray`.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Output samples.
+    #[cfg(feature = "ndarray")]
+    fn to_ndarray(&self, frame: RawSamples) -> Result<Samples> {
+        if frame.samples() == 0 {
+            return Ok(Samples::zeros((0, self.output.channels as usize)));
+        }
+        convert_samples_to_ndarray(&frame)
+    }
+}
+
+unsafe impl Send for Resampler {}
+unsafe impl Sync for Resampler {}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resample_keeps_every_sample() {
+        const CHUNK: usize = 1024;
+
+        let spec = |sample_rate, channels| SampleSpec {
+            sample_rate,
+            channels,
+        };
+        let resample = |resampler: &mut Resampler, samples: &Samples| {
+            let mut output = Vec::new();
+            for chunk in samples.axis_chunks_iter(ndarray::Axis(0), CHUNK) {
+                output.push(resampler.convert(&chunk.to_owned()).unwrap());
+            }
+            output.push(resampler.flush().unwrap());
+            let views = output
+                .iter()
+                .map(|samples| samples.view())
+                .collect::<Vec<_>>();
+            ndarray::concatenate(ndarray::Axis(0), &views).unwrap()
+        };
+
+        // One second of a 440 Hz tone at 44.1 kHz stereo.
+        let tone = Samples::from_shape_fn((44100, 2), |(i, _)| {
+            (i as f32 / 44100.0 * 440.0 * std::f32::consts::TAU).sin() * 0.5
+        });
+        let mut resampler = Resampler::new(spec(44100, 2), spec(48000, 2)).unwrap();
+        let output = resample(&mut resampler, &tone);
+        assert_eq!(output.dim(), (48000, 2));
+
+        // One second of 5.1 at 48 kHz with only the front left channel.
+        let front_left =
+            Samples::from_shape_fn((48000, 6), |(_, channel)| (channel == 0) as u8 as f32 * 0.5);
+        let mut resampler = Resampler::new(spec(48000, 6), spec(48000, 2)).unwrap();
+        let output = resample(&mut resampler, &front_left);
+        assert_eq!(output.dim(), (48000, 2));
+        assert!(output.column(0).iter().all(|sample| *sample > 0.1));
+        assert!(output.column(1).iter().all(|sample| sample.abs() < 1e-3));
+
+        assert!(Resampler::new(spec(0, 2), spec(48000, 2)).is_err());
+        assert!(resampler.convert(&Samples::zeros((CHUNK, 2))).is_err());
+    }
+}
@@ -84,7 +84,7 @@ impl RtpMuxer {
     /// a=fmtp:96 packetization-mode=1
     /// ```
     pub fn sdp(&self) -> Result<String> {
-        sdp(&self.0.writer.output).map_err(Error::BackendError)
+        sdp(&self.0.writer.output).map_err(Error::from)
     }
 
     /// Mux a single packet. This will cause the muxer to try and read packets from the preferred
@@ -0,0 +1,169 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::pixel::Pixel as AvPixel;
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
+
+use crate::crop::CropScaler;
+use crate::error::Error;
+use crate::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Size of the luma plane that frames are compared at. Frames of any size (and aspect ratio) are
+/// scaled down to this size first, which makes the score independent of the resolution and takes
+/// out most of the noise.
+const ANALYSIS_SIZE: (u32, u32) = (64, 64);
+
+/// Scores how much each frame differs from the frame before it, to find scene changes (see
+/// [`crate::Decoder::detect_scene_changes`]).
+///
+/// The score is the mean absolute difference of the luma of both frames, scaled down to
+/// [`ANALYSIS_SIZE`], as a fraction of the full luma range: `0.0` for identical frames and `1.0`
+/// for a cut from black to white. Hard cuts between unrelated shots usually score well above
+/// `0.1`, while motion within a shot stays below that.
+pub(crate) struct SceneDetector {
+    sampler: LumaSampler,
+    previous: Option<Vec<u8>>,
+}
+
+impl SceneDetector {
+    /// Create a scene detector.
+    pub(crate) fn new() -> Self {
+        Self {
+            sampler: LumaSampler::new(),
+            previous: None,
+        }
+    }
+
+    /// Score a frame against the frame before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Next frame.
+    ///
+    /// # Return value
+    ///
+    /// The score of the frame, or `None` for the first frame, which has nothing to compare with.
+    pub(crate) fn score(&mut self, frame: &RawFrame) -> Result<Option<f32>> {
+        let luma = self.sampler.sample(frame)?;
+        let score = self
+            .previous
+            .as_ref()
+            .map(|previous| luma_difference(previous, &luma));
+        self.previous = Some(luma);
+
+        Ok(score)
+    }
+}
+
+/// Scales frames down to [`ANALYSIS_SIZE`] and gets their luma samples, for the analysis of
+/// frames (scene changes, black and frozen frames).
+pub(crate) struct LumaSampler {
+    /// Scaler to the analysis size, along with the pixel format and size of the frames it was set
+    /// up for.
+    scaler: Option<(CropScaler, AvPixel, (u32, u32))>,
+}
+
+impl LumaSampler {
+    /// Create a luma sampler.
+    pub(crate) fn new() -> Self {
+        Self { scaler: None }
+    }
+
+    /// Scale a frame down to the analysis size, and get its luma samples row by row. The samples
+    /// are full range, so black is `0` and white is `255`, whatever the range of the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to scale.
+    pub(crate) fn sample(&mut self, frame: &RawFrame) -> Result<Vec<u8>> {
+        let format = frame.format();
+        let size = (frame.width(), frame.height());
+        let scaler = match self.scaler {
+            Some((ref mut scaler, scaler_format, scaler_size))
+                if scaler_format == format && scaler_size == size =>
+            {
+                scaler
+            }
+            _ => {
+                let scaler = CropScaler::new(
+                    format,
+                    size,
+                    None,
+                    AvPixel::GRAY8,
+                    ANALYSIS_SIZE,
+                    AvScalerFlags::AREA,
+                )?;
+                &mut self.scaler.insert((scaler, format, size)).0
+            }
+        };
+
+        let scaled = scaler.run(frame)?;
+        let (width, height) = (ANALYSIS_SIZE.0 as usize, ANALYSIS_SIZE.1 as usize);
+        let stride = scaled.stride(0);
+        let data = scaled.data(0);
+
+        Ok((0..height)
+            .flat_map(|y| &data[y * stride..y * stride + width])
+            .copied()
+            .collect())
+    }
+}
+
+/// Get the mean of a plane of samples, as a fraction of the full range.
+///
+/// # Arguments
+///
+/// * `samples` - Samples of the plane.
+pub(crate) fn mean_luma(samples: &[u8]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    let sum = samples.iter().map(|&sample| sample as u64).sum::<u64>();
+    sum as f32 / (samples.len() as f32 * u8::MAX as f32)
+}
+
+/// Get the mean absolute difference of two planes of samples, as a fraction of the full range.
+///
+/// # Arguments
+///
+/// * `a` - Samples of the first plane.
+/// * `b` - Samples of the second plane, as many as the first.
+pub(crate) fn luma_difference(a: &[u8], b: &[u8]) -> f32 {
+    if a.is_empty() {
+        return 0.0;
+    }
+
+    let sum = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| a.abs_diff(b) as u64)
+        .sum::<u64>();
+    sum as f32 / (a.len() as f32 * u8::MAX as f32)
+}
+
+unsafe impl Send for LumaSampler {}
+unsafe impl Sync for LumaSampler {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luma_difference() {
+        assert_eq!(luma_difference(&[10; 16], &[10; 16]), 0.0);
+        assert_eq!(luma_difference(&[0; 16], &[255; 16]), 1.0);
+        assert_eq!(luma_difference(&[255; 16], &[0; 16]), 1.0);
+        assert_eq!(luma_difference(&[0, 0, 0, 0], &[0, 0, 255, 255]), 0.5);
+        assert_eq!(luma_difference(&[], &[]), 0.0);
+    }
+
+    #[test]
+    fn test_mean_luma() {
+        assert_eq!(mean_luma(&[0; 16]), 0.0);
+        assert_eq!(mean_luma(&[255; 16]), 1.0);
+        assert_eq!(mean_luma(&[0, 255, 0, 255]), 0.5);
+        assert_eq!(mean_luma(&[]), 0.0);
+    }
+}
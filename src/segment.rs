@@ -0,0 +1,288 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use ffmpeg::Rational as AvRational;
+
+use crate::encode::KeyFrameSchedule;
+use crate::error::Error;
+use crate::sequence::Pattern;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// When to start a new segment of segmented output (see [`crate::Encoder::new_segmented`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentLimit {
+    /// Start a new segment every so much presentation time. Key frames are forced on the segment
+    /// boundaries (see [`crate::EncoderSettings::force_keyframe_every`]).
+    Duration(Duration),
+    /// Start a new segment at the first key frame after the segment has this many bytes of encoded
+    /// data. A key frame is forced as soon as the limit is reached, so segments are slightly larger
+    /// than the limit. Container overhead is not included.
+    Size(u64),
+}
+
+/// Segmentation configuration for [`crate::Encoder::new_segmented`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPolicy {
+    /// When to start a new segment.
+    pub limit: SegmentLimit,
+    /// Whether or not the timestamps of each segment start at zero. Otherwise, segments keep the
+    /// timestamps of the encoded stream.
+    pub reset_timestamps: bool,
+}
+
+/// Segment of segmented output that has been written completely.
+#[derive(Debug, Clone)]
+pub struct CompletedSegment {
+    /// Path of the segment.
+    pub path: PathBuf,
+    /// Presentation timestamp of the first frame in the segment, in the timeline of the encoded
+    /// stream.
+    pub start: Time,
+    /// Presentation timestamp of the end of the segment, in the timeline of the encoded stream.
+    pub end: Time,
+}
+
+type SegmentFn = Box<dyn FnMut(&CompletedSegment) + Send + Sync>;
+
+/// Decides where segmented output is split, and keeps track of the current segment.
+pub(crate) struct Segmenter {
+    pattern: Pattern,
+    policy: SegmentPolicy,
+    time_base: AvRational,
+    number: u64,
+    schedule: Option<KeyFrameSchedule>,
+    start: Option<i64>,
+    end: Option<i64>,
+    size: u64,
+    on_complete: Option<SegmentFn>,
+}
+
+impl Segmenter {
+    /// Number of the first segment.
+    const FIRST_NUMBER: u64 = 1;
+
+    /// Create a segmenter. Packet timestamps are in the ffmpeg global time base, unless another
+    /// time base is set with [`Segmenter::with_time_base`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Path of the segments, with the segment number as `%d` or zero-padded as
+    ///   `%0Nd`.
+    /// * `policy` - When to start a new segment.
+    pub(crate) fn new(pattern: &Path, policy: SegmentPolicy) -> Result<Self> {
+        let pattern = Pattern::parse(pattern).map_err(|_| {
+            Error::InvalidOptions(format!(
+                "segment pattern must have a single %d or %0Nd in the file name: {}",
+                pattern.display()
+            ))
+        })?;
+        match policy.limit {
+            SegmentLimit::Duration(duration) if duration.is_zero() => {
+                return Err(Error::InvalidDuration);
+            }
+            SegmentLimit::Size(0) => {
+                return Err(Error::InvalidOptions(
+                    "segment size must not be zero".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            pattern,
+            policy,
+            time_base: TIME_BASE,
+            number: Self::FIRST_NUMBER,
+            schedule: None,
+            start: None,
+            end: None,
+            size: 0,
+            on_complete: None,
+        }
+        .with_time_base(TIME_BASE))
+    }
+
+    /// Set the time base of the packet timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base of the packet timestamps.
+    pub(crate) fn with_time_base(mut self, time_base: AvRational) -> Self {
+        self.time_base = time_base;
+        self.schedule = match self.policy.limit {
+            SegmentLimit::Duration(duration) => Some(KeyFrameSchedule::new(
+                Time::from(duration)
+                    .with_time_base(time_base)
+                    .into_value()
+                    .unwrap_or_default(),
+            )),
+            SegmentLimit::Size(_) => None,
+        };
+        self
+    }
+
+    /// Set the function that is called when a segment has been written completely.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_complete` - Function to call with the completed segment.
+    pub(crate) fn set_on_complete(&mut self, on_complete: SegmentFn) {
+        self.on_complete = Some(on_complete);
+    }
+
+    /// Get the path of the current segment.
+    pub(crate) fn path(&self) -> PathBuf {
+        self.pattern.path_of(self.number)
+    }
+
+    /// Whether or not a packet starts a new segment. Segments only start at key frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Presentation timestamp of the packet.
+    /// * `is_key` - Whether or not the packet is a key frame.
+    pub(crate) fn starts_segment(&mut self, pts: i64, is_key: bool) -> bool {
+        if self.start.is_none() {
+            // The first packet starts the first segment, and anchors the segment boundaries.
+            if let Some(schedule) = self.schedule.as_mut() {
+                schedule.is_due(pts);
+            }
+            self.start = Some(pts);
+            return false;
+        }
+        if !is_key {
+            return false;
+        }
+
+        match (self.policy.limit, self.schedule.as_mut()) {
+            (SegmentLimit::Duration(_), Some(schedule)) => schedule.is_due(pts),
+            (SegmentLimit::Size(limit), _) => self.size >= limit,
+            _ => false,
+        }
+    }
+
+    /// Get the offset to subtract from packet timestamps of the current segment.
+    pub(crate) fn timestamp_offset(&self) -> i64 {
+        match (self.policy.reset_timestamps, self.start) {
+            (true, Some(start)) => start,
+            _ => 0,
+        }
+    }
+
+    /// Account for a packet that has been written to the current segment.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Presentation timestamp of the packet.
+    /// * `duration` - Duration of the packet.
+    /// * `size` - Size of the packet in bytes.
+    ///
+    /// # Return value
+    ///
+    /// Whether or not the size limit has been reached, and a key frame should be forced.
+    pub(crate) fn record(&mut self, pts: i64, duration: i64, size: usize) -> bool {
+        self.end = self.end.max(Some(pts + duration));
+        self.size += size as u64;
+        matches!(self.policy.limit, SegmentLimit::Size(limit) if self.size >= limit)
+    }
+
+    /// Complete the current segment, and start the next one.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Presentation timestamp of the first packet of the next segment.
+    ///
+    /// # Return value
+    ///
+    /// Path of the next segment.
+    pub(crate) fn next(&mut self, pts: i64) -> PathBuf {
+        self.end = Some(pts);
+        self.complete();
+        self.number += 1;
+        self.start = Some(pts);
+        self.end = None;
+        self.size = 0;
+        self.path()
+    }
+
+    /// Complete the current segment, if it has any packets.
+    pub(crate) fn complete(&mut self) {
+        let Some(start) = self.start.take() else {
+            return;
+        };
+        let segment = CompletedSegment {
+            path: self.path(),
+            start: Time::new(Some(start), self.time_base),
+            end: Time::new(self.end.or(Some(start)), self.time_base),
+        };
+        if let Some(on_complete) = self.on_complete.as_mut() {
+            on_complete(&segment);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_by_duration() {
+        let policy = SegmentPolicy {
+            limit: SegmentLimit::Duration(Duration::from_secs(1)),
+            reset_timestamps: true,
+        };
+        let mut segmenter = Segmenter::new(Path::new("rec_%04d.mp4"), policy)
+            .unwrap()
+            .with_time_base(AvRational::new(1, 1000));
+        let completed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        segmenter.set_on_complete(Box::new({
+            let completed = completed.clone();
+            move |segment| completed.lock().unwrap().push(segment.clone())
+        }));
+        assert_eq!(segmenter.path(), PathBuf::from("rec_0001.mp4"));
+
+        assert!(!segmenter.starts_segment(100, true));
+        assert_eq!(segmenter.timestamp_offset(), 100);
+        assert!(!segmenter.record(100, 40, 1000));
+        assert!(!segmenter.starts_segment(600, true));
+        assert!(!segmenter.starts_segment(1100, false));
+        assert!(segmenter.starts_segment(1120, true));
+        assert_eq!(segmenter.next(1120), PathBuf::from("rec_0002.mp4"));
+        assert_eq!(segmenter.timestamp_offset(), 1120);
+        segmenter.record(1120, 40, 1000);
+        segmenter.complete();
+
+        let completed = completed.lock().unwrap();
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].path, PathBuf::from("rec_0001.mp4"));
+        assert_eq!(completed[0].start.clone().into_value(), Some(100));
+        assert_eq!(completed[0].end.clone().into_value(), Some(1120));
+        assert_eq!(completed[1].end.clone().into_value(), Some(1160));
+    }
+
+    #[test]
+    fn test_segment_by_size() {
+        let policy = SegmentPolicy {
+            limit: SegmentLimit::Size(2000),
+            reset_timestamps: false,
+        };
+        let mut segmenter = Segmenter::new(Path::new("rec_%d.ts"), policy)
+            .unwrap()
+            .with_time_base(AvRational::new(1, 1000));
+        assert!(!segmenter.starts_segment(0, true));
+        assert_eq!(segmenter.timestamp_offset(), 0);
+        assert!(!segmenter.record(0, 40, 1500));
+        assert!(!segmenter.starts_segment(40, true));
+        assert!(segmenter.record(40, 40, 1500));
+        assert!(!segmenter.starts_segment(80, false));
+        assert!(segmenter.starts_segment(120, true));
+        assert_eq!(segmenter.next(120), PathBuf::from("rec_2.ts"));
+
+        assert!(Segmenter::new(Path::new("rec.ts"), policy).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::io::Locator;
+use crate::options::Options;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What to do when an image sequence has gaps, for example `frame_000007.png` being missing while
+/// `frame_000008.png` exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingFrames {
+    /// Fail with [`Error::MissingSequenceFrame`] when the sequence is opened.
+    #[default]
+    Error,
+    /// Skip the missing frames. The frames after a gap directly follow the frames before it on
+    /// the frame rate grid. This requires a zero-padded pattern (like `%06d`), and globbing
+    /// support in the backend (which is not available on Windows).
+    Skip,
+}
+
+/// Describes a sequence of numbered images to decode as a video, like the frames a render farm
+/// produces.
+///
+/// Images have no timing of their own, so frames are timed on the grid of the given frame rate.
+///
+/// # Example
+///
+/// ```ignore
+/// let sequence = ImageSequence::new("renders/frame_%06d.png", 24.0)
+///     .with_missing_frames(MissingFrames::Skip);
+/// let mut decoder = Decoder::new_image_sequence(&sequence).unwrap();
+/// for frame in decoder.decode_iter() {
+///     let (timestamp, frame) = frame.unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageSequence {
+    pattern: PathBuf,
+    frame_rate: f64,
+    glob: bool,
+    missing_frames: MissingFrames,
+}
+
+impl ImageSequence {
+    /// Describe an image sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Path of the images, with the frame number as `%d` or zero-padded as `%0Nd`
+    ///   (use `%%` for a literal `%`), like `frame_%06d.png`. The sequence starts at the lowest
+    ///   frame number that exists.
+    /// * `frame_rate` - Frames per second to time the images with.
+    pub fn new(pattern: impl Into<PathBuf>, frame_rate: f64) -> Self {
+        Self {
+            pattern: pattern.into(),
+            frame_rate,
+            glob: false,
+            missing_frames: MissingFrames::default(),
+        }
+    }
+
+    /// Interpret the pattern as a glob pattern, like `frame_*.png`, instead. Images are read in
+    /// the (alphabetical) order of their file names. Globbing is not available on Windows.
+    pub fn with_glob(mut self) -> Self {
+        self.glob = true;
+        self
+    }
+
+    /// Set what to do when the sequence has gaps. This does not apply to glob patterns, which only
+    /// ever match images that exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `missing_frames` - What to do with missing frames.
+    pub fn with_missing_frames(mut self, missing_frames: MissingFrames) -> Self {
+        self.missing_frames = missing_frames;
+        self
+    }
+
+    /// Get the frame rate of the sequence.
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    /// Find the images of the sequence, and get the locator and `image2` demuxer options to open
+    /// it with.
+    pub(crate) fn resolve(&self) -> Result<(Locator, Options<'static>)> {
+        if !(self.frame_rate.is_finite() && self.frame_rate > 0.0) {
+            return Err(Error::InvalidImageSequence(format!(
+                "invalid frame rate: {}",
+                self.frame_rate
+            )));
+        }
+
+        let mut options = Options::default();
+        options.set("framerate", &self.frame_rate.to_string());
+        if self.glob {
+            options.set("pattern_type", "glob");
+            return Ok((Locator::Path(self.pattern.clone()), options));
+        }
+
+        let pattern = Pattern::parse(&self.pattern)?;
+        let numbers = pattern.frame_numbers()?;
+        let Some(&first) = numbers.first() else {
+            return Err(Error::InvalidImageSequence(format!(
+                "no images match {}",
+                self.pattern.display()
+            )));
+        };
+
+        match (first_missing(&numbers), self.missing_frames) {
+            (None, _) => {
+                options.set("pattern_type", "sequence");
+                options.set("start_number", &first.to_string());
+                Ok((Locator::Path(self.pattern.clone()), options))
+            }
+            (Some(missing), MissingFrames::Error) => {
+                Err(Error::MissingSequenceFrame(pattern.path_of(missing)))
+            }
+            (Some(_), MissingFrames::Skip) => {
+                options.set("pattern_type", "glob");
+                Ok((Locator::Path(pattern.to_glob()?), options))
+            }
+        }
+    }
+}
+
+/// Sequence pattern with a single frame number placeholder.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Pattern {
+    dir: PathBuf,
+    prefix: String,
+    width: Option<usize>,
+    suffix: String,
+}
+
+impl Pattern {
+    /// Parse a sequence pattern. Only the file name may contain the frame number.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Pattern to parse.
+    pub(crate) fn parse(pattern: &Path) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidImageSequence(format!(
+                "pattern must have a single %d or %0Nd in the file name: {}",
+                pattern.display()
+            ))
+        };
+        let file_name = pattern
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .ok_or_else(invalid)?;
+
+        let mut parts = [String::new(), String::new()];
+        let mut width = None;
+        let mut placeholders = 0;
+        let mut chars = file_name.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                parts[placeholders.min(1)].push(c);
+                continue;
+            }
+            if chars.next_if_eq(&'%').is_some() {
+                parts[placeholders.min(1)].push('%');
+                continue;
+            }
+
+            let mut digits = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                digits.push(digit);
+            }
+            if chars.next() != Some('d') || (!digits.is_empty() && !digits.starts_with('0')) {
+                return Err(invalid());
+            }
+            width = digits.parse().ok().filter(|width| *width > 0);
+            placeholders += 1;
+        }
+        if placeholders != 1 {
+            return Err(invalid());
+        }
+
+        let [prefix, suffix] = parts;
+        Ok(Self {
+            dir: pattern.parent().unwrap_or(Path::new("")).to_path_buf(),
+            prefix,
+            width,
+            suffix,
+        })
+    }
+
+    /// Get the frame number of a file name, if it matches the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_name` - File name to match.
+    fn frame_number(&self, file_name: &str) -> Option<u64> {
+        let digits = file_name
+            .strip_prefix(&self.prefix)?
+            .strip_suffix(&self.suffix)?;
+        if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        // Numbers are only padded up to the width, so longer numbers have no leading zeros.
+        let padded_as_expected = match self.width {
+            Some(width) => {
+                digits.len() == width || (digits.len() > width && !digits.starts_with('0'))
+            }
+            None => digits == "0" || !digits.starts_with('0'),
+        };
+        if !padded_as_expected {
+            return None;
+        }
+
+        digits.parse().ok()
+    }
+
+    /// Find the frame numbers of the images that exist, in order.
+    fn frame_numbers(&self) -> Result<Vec<u64>> {
+        let dir = if self.dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.dir
+        };
+        let entries = std::fs::read_dir(dir).map_err(|err| {
+            Error::InvalidImageSequence(format!("cannot read {}: {err}", dir.display()))
+        })?;
+
+        let mut numbers = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|n| self.frame_number(n))
+            })
+            .collect::<Vec<_>>();
+        numbers.sort_unstable();
+
+        Ok(numbers)
+    }
+
+    /// Get the path of the image with a frame number.
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - Frame number.
+    pub(crate) fn path_of(&self, number: u64) -> PathBuf {
+        let width = self.width.unwrap_or(0);
+        self.dir
+            .join(format!("{}{number:0width$}{}", self.prefix, self.suffix))
+    }
+
+    /// Convert the pattern to a glob pattern that matches the same images, in order. This only
+    /// works for zero-padded patterns, since other frame numbers do not sort alphabetically.
+    fn to_glob(&self) -> Result<PathBuf> {
+        let width = self.width.ok_or_else(|| {
+            Error::InvalidImageSequence(
+                "skipping missing frames requires a zero-padded pattern, like %06d".to_string(),
+            )
+        })?;
+        let escape = |part: &str| {
+            part.chars().fold(String::new(), |mut escaped, c| {
+                if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+                escaped
+            })
+        };
+        let dir = self.dir.to_str().ok_or(Error::InvalidPath)?;
+
+        Ok(Path::new(&escape(dir)).join(format!(
+            "{}{}{}",
+            escape(&self.prefix),
+            "[0-9]".repeat(width),
+            escape(&self.suffix)
+        )))
+    }
+}
+
+/// Find the first frame number that is missing from a sorted list of frame numbers.
+///
+/// # Arguments
+///
+/// * `numbers` - Frame numbers, in order.
+fn first_missing(numbers: &[u64]) -> Option<u64> {
+    numbers
+        .windows(2)
+        .find(|pair| pair[1] > pair[0] + 1)
+        .map(|pair| pair[0] + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TempPath;
+
+    #[test]
+    fn test_parse_pattern() {
+        let pattern = Pattern::parse(Path::new("renders/frame_%06d.png")).unwrap();
+        assert_eq!(
+            pattern,
+            Pattern {
+                dir: PathBuf::from("renders"),
+                prefix: "frame_".to_string(),
+                width: Some(6),
+                suffix: ".png".to_string(),
+            }
+        );
+        assert_eq!(pattern.frame_number("frame_000042.png"), Some(42));
+        assert_eq!(pattern.frame_number("frame_1000000.png"), Some(1000000));
+        assert_eq!(pattern.frame_number("frame_42.png"), None);
+        assert_eq!(pattern.frame_number("frame_00004a.png"), None);
+        assert_eq!(
+            pattern.path_of(7),
+            PathBuf::from("renders/frame_000007.png")
+        );
+        assert_eq!(
+            pattern.to_glob().unwrap(),
+            PathBuf::from("renders/frame_[0-9][0-9][0-9][0-9][0-9][0-9].png")
+        );
+
+        let pattern = Pattern::parse(Path::new("100%%_%d.jpg")).unwrap();
+        assert_eq!(pattern.prefix, "100%_");
+        assert_eq!(pattern.width, None);
+        assert_eq!(pattern.frame_number("100%_7.jpg"), Some(7));
+        assert_eq!(pattern.frame_number("100%_07.jpg"), None);
+        assert!(pattern.to_glob().is_err());
+
+        assert!(Pattern::parse(Path::new("frame.png")).is_err());
+        assert!(Pattern::parse(Path::new("%d_%d.png")).is_err());
+        assert!(Pattern::parse(Path::new("frame_%6d.png")).is_err());
+        assert!(Pattern::parse(Path::new("frame_%s.png")).is_err());
+    }
+
+    #[test]
+    fn test_resolve_missing_frames() {
+        let dir = TempPath::new("sequence");
+        std::fs::create_dir_all(&dir).unwrap();
+        for number in [3, 4, 6] {
+            std::fs::write(dir.join(format!("frame_{number:04}.png")), b"").unwrap();
+        }
+
+        let sequence = ImageSequence::new(dir.join("frame_%04d.png"), 24.0);
+        assert!(matches!(
+            sequence.resolve(),
+            Err(Error::MissingSequenceFrame(path)) if path == dir.join("frame_0005.png")
+        ));
+
+        let (locator, options) = sequence
+            .with_missing_frames(MissingFrames::Skip)
+            .resolve()
+            .unwrap();
+        assert!(locator
+            .to_string()
+            .ends_with("frame_[0-9][0-9][0-9][0-9].png"));
+        assert_eq!(options.get("pattern_type"), Some("glob"));
+        assert_eq!(options.get("framerate"), Some("24"));
+
+        std::fs::write(dir.join("frame_0005.png"), b"").unwrap();
+        let (_, options) = ImageSequence::new(dir.join("frame_%04d.png"), 24.0)
+            .resolve()
+            .unwrap();
+        assert_eq!(options.get("pattern_type"), Some("sequence"));
+        assert_eq!(options.get("start_number"), Some("3"));
+    }
+}
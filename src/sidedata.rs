@@ -0,0 +1,596 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::HashMap;
+
+use ffmpeg::codec::packet::side_data::Type as AvPacketSideDataType;
+use ffmpeg::ffi::AVRational;
+use ffmpeg::format::context::Output as AvOutput;
+use ffmpeg::util::frame::side_data::Type as AvSideDataType;
+use ffmpeg::util::frame::Frame as AvFrame;
+
+use crate::error::Error;
+use crate::ffi::{
+    add_stream_side_data, content_light_metadata_from_bytes, content_light_metadata_to_bytes,
+    frame_duration, mastering_display_metadata_from_bytes, mastering_display_metadata_to_bytes,
+    motion_vectors_from_bytes, set_frame_side_data, ContentLightMetadataRaw,
+    MasteringDisplayMetadataRaw,
+};
+use crate::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Side data of a frame, like closed captions and HDR metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SideData {
+    /// ATSC A/53 closed captions: the raw CEA-608/708 `cc_data` byte triplets, as carried in H.264
+    /// and HEVC SEI messages and MPEG-2 user data.
+    A53Captions(Vec<u8>),
+    /// Color volume of the display the content was mastered on (SMPTE ST 2086).
+    MasteringDisplayMetadata(MasteringDisplayMetadata),
+    /// Content light levels (CTA-861.3).
+    ContentLightLevel(ContentLightLevel),
+}
+
+/// Color volume of a mastering display (SMPTE ST 2086).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MasteringDisplayMetadata {
+    /// Chromaticities of the display, if known.
+    pub primaries: Option<Chromaticities>,
+    /// Luminance range of the display, if known.
+    pub luminance: Option<Luminance>,
+}
+
+/// CIE 1931 xy chromaticity coordinates of the primaries and white point of a display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chromaticities {
+    /// Chromaticity of the red primary.
+    pub red: (f64, f64),
+    /// Chromaticity of the green primary.
+    pub green: (f64, f64),
+    /// Chromaticity of the blue primary.
+    pub blue: (f64, f64),
+    /// Chromaticity of the white point.
+    pub white_point: (f64, f64),
+}
+
+/// Luminance range of a display in candela per square meter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Luminance {
+    /// Minimum luminance.
+    pub min: f64,
+    /// Maximum luminance.
+    pub max: f64,
+}
+
+/// Content light levels (CTA-861.3) in candela per square meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level (MaxCLL).
+    pub max_content: u32,
+    /// Maximum frame-average light level (MaxFALL).
+    pub max_frame_average: u32,
+}
+
+/// HDR metadata for an encoder to write to its output (see
+/// [`crate::EncoderSettings::with_hdr_metadata`]): the color volume of the mastering display and
+/// the content light levels.
+///
+/// # Example
+///
+/// ```ignore
+/// // A BT.2020 display with a peak of 1000 nits.
+/// let metadata = HdrMetadata {
+///     primaries: [(0.708, 0.292), (0.17, 0.797), (0.131, 0.046)],
+///     white_point: (0.3127, 0.329),
+///     max_luminance: 1000.0,
+///     min_luminance: 0.0001,
+///     max_cll: 1000,
+///     max_fall: 400,
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HdrMetadata {
+    /// CIE 1931 xy chromaticities of the red, green and blue primaries of the mastering display.
+    pub primaries: [(f64, f64); 3],
+    /// CIE 1931 xy chromaticity of the white point of the mastering display.
+    pub white_point: (f64, f64),
+    /// Maximum luminance of the mastering display in candela per square meter.
+    pub max_luminance: f64,
+    /// Minimum luminance of the mastering display in candela per square meter.
+    pub min_luminance: f64,
+    /// Maximum content light level (MaxCLL) in candela per square meter.
+    pub max_cll: u32,
+    /// Maximum frame-average light level (MaxFALL) in candela per square meter.
+    pub max_fall: u32,
+}
+
+impl HdrMetadata {
+    /// Get the HDR metadata from side data (like [`crate::Decoder::stream_side_data`]), if it has
+    /// both the mastering display metadata and the content light levels.
+    ///
+    /// # Arguments
+    ///
+    /// * `side_data` - Side data to look in.
+    pub fn from_side_data(side_data: &[SideData]) -> Option<Self> {
+        let (primaries, luminance) = side_data.iter().find_map(|side_data| match side_data {
+            SideData::MasteringDisplayMetadata(MasteringDisplayMetadata {
+                primaries: Some(primaries),
+                luminance: Some(luminance),
+            }) => Some((primaries, luminance)),
+            _ => None,
+        })?;
+        let light_level = side_data.iter().find_map(|side_data| match side_data {
+            SideData::ContentLightLevel(light_level) => Some(light_level),
+            _ => None,
+        })?;
+
+        Some(Self {
+            primaries: [primaries.red, primaries.green, primaries.blue],
+            white_point: primaries.white_point,
+            max_luminance: luminance.max,
+            min_luminance: luminance.min,
+            max_cll: light_level.max_content,
+            max_fall: light_level.max_frame_average,
+        })
+    }
+
+    /// Get the metadata as side data: the mastering display metadata and the content light
+    /// levels.
+    pub fn to_side_data(&self) -> [SideData; 2] {
+        [
+            SideData::MasteringDisplayMetadata(MasteringDisplayMetadata {
+                primaries: Some(Chromaticities {
+                    red: self.primaries[0],
+                    green: self.primaries[1],
+                    blue: self.primaries[2],
+                    white_point: self.white_point,
+                }),
+                luminance: Some(Luminance {
+                    min: self.min_luminance,
+                    max: self.max_luminance,
+                }),
+            }),
+            SideData::ContentLightLevel(ContentLightLevel {
+                max_content: self.max_cll,
+                max_frame_average: self.max_fall,
+            }),
+        ]
+    }
+
+    /// Get the metadata as `libx265` parameters (`master-display` and `max-cll`), in the units of
+    /// the SEI messages: 0.00002 for chromaticities and 0.0001 candela per square meter for
+    /// luminance.
+    pub(crate) fn x265_params(&self) -> String {
+        let xy = |(x, y): (f64, f64)| {
+            let den = CHROMATICITY_DENOMINATOR as f64;
+            format!("({},{})", (x * den).round(), (y * den).round())
+        };
+        let luminance = |value: f64| (value * LUMINANCE_DENOMINATOR as f64).round();
+        let [red, green, blue] = self.primaries;
+        format!(
+            "master-display=G{}B{}R{}WP{}L({},{}):max-cll={},{}",
+            xy(green),
+            xy(blue),
+            xy(red),
+            xy(self.white_point),
+            luminance(self.max_luminance),
+            luminance(self.min_luminance),
+            self.max_cll,
+            self.max_fall,
+        )
+    }
+}
+
+/// Get the side data of a stream that this crate knows about, like the HDR metadata that
+/// containers store (see [`crate::Decoder::stream_side_data`]).
+///
+/// # Arguments
+///
+/// * `stream` - Stream to get side data of.
+pub(crate) fn stream_side_data(stream: &ffmpeg::format::stream::Stream) -> Vec<SideData> {
+    stream
+        .side_data()
+        .filter_map(|side_data| match side_data.kind() {
+            AvPacketSideDataType::MasteringDisplayMetadata => {
+                mastering_display_metadata_from_bytes(side_data.data())
+                    .map(|metadata| SideData::MasteringDisplayMetadata(metadata.into()))
+            }
+            AvPacketSideDataType::ContentLightLevel => {
+                content_light_metadata_from_bytes(side_data.data())
+                    .map(|metadata| SideData::ContentLightLevel(metadata.into()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Add HDR metadata to a stream of an output, for containers that store it (like MP4 and
+/// Matroska).
+///
+/// # Arguments
+///
+/// * `output` - Output that has the stream.
+/// * `stream_index` - Index of the stream.
+/// * `metadata` - HDR metadata to add.
+pub(crate) fn add_stream_hdr_metadata(
+    output: &mut AvOutput,
+    stream_index: usize,
+    metadata: &HdrMetadata,
+) -> Result<()> {
+    for side_data in metadata.to_side_data() {
+        let (kind, data) = match side_data {
+            SideData::MasteringDisplayMetadata(metadata) => (
+                AvPacketSideDataType::MasteringDisplayMetadata,
+                mastering_display_metadata_to_bytes(&metadata.into()),
+            ),
+            SideData::ContentLightLevel(metadata) => (
+                AvPacketSideDataType::ContentLightLevel,
+                content_light_metadata_to_bytes(&metadata.into()),
+            ),
+            SideData::A53Captions(_) => continue,
+        };
+        add_stream_side_data(output, stream_index, kind, &data)?;
+    }
+
+    Ok(())
+}
+
+/// Motion vector of a block, as exported by the decoder (see
+/// [`crate::Decoder::with_motion_vectors`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionVector {
+    /// Horizontal position of the block in the reference frame. This may be outside the frame.
+    pub src_x: i16,
+    /// Vertical position of the block in the reference frame. This may be outside the frame.
+    pub src_y: i16,
+    /// Horizontal position of the block in this frame. This may be outside the frame.
+    pub dst_x: i16,
+    /// Vertical position of the block in this frame. This may be outside the frame.
+    pub dst_y: i16,
+    /// Width of the block.
+    pub w: u8,
+    /// Height of the block.
+    pub h: u8,
+    /// Where the block comes from: negative for a reference frame in the past, positive for a
+    /// reference frame in the future.
+    pub source: i32,
+}
+
+/// Access to the side data of a [`RawFrame`], like closed captions. Decoded frames carry the side
+/// data of the stream, and the encoder passes the side data of input frames on to the encoded
+/// stream (for encoders that support it, like `libx264` for captions).
+///
+/// # Example
+///
+/// ```ignore
+/// let frame = decoder.decode_raw().unwrap();
+/// for side_data in frame.side_data() {
+///     if let SideData::A53Captions(cc_data) = side_data {
+///         // Parse the CEA-608/708 byte triplets.
+///     }
+/// }
+/// ```
+pub trait RawFrameSideData {
+    /// Get the side data of the frame that this crate knows about.
+    fn side_data(&self) -> Vec<SideData>;
+
+    /// Get the motion vectors of the frame. Decoders only export motion vectors when asked to
+    /// (see [`crate::Decoder::with_motion_vectors`]), and frames without motion vectors (like
+    /// intra frames) have none.
+    fn motion_vectors(&self) -> Vec<MotionVector>;
+
+    /// Set side data on the frame, replacing any side data of the same kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `side_data` - Side data to set.
+    fn set_side_data(&mut self, side_data: &SideData) -> Result<()>;
+}
+
+impl RawFrameSideData for RawFrame {
+    fn side_data(&self) -> Vec<SideData> {
+        // The side data of the underlying frame, which this method shadows.
+        let frame: &AvFrame = self;
+        let mut side_data = Vec::new();
+        if let Some(captions) = frame.side_data(AvSideDataType::A53CC) {
+            side_data.push(SideData::A53Captions(captions.data().to_vec()));
+        }
+        if let Some(metadata) = frame
+            .side_data(AvSideDataType::MasteringDisplayMetadata)
+            .and_then(|data| mastering_display_metadata_from_bytes(data.data()))
+        {
+            side_data.push(SideData::MasteringDisplayMetadata(metadata.into()));
+        }
+        if let Some(metadata) = frame
+            .side_data(AvSideDataType::ContentLightLevel)
+            .and_then(|data| content_light_metadata_from_bytes(data.data()))
+        {
+            side_data.push(SideData::ContentLightLevel(metadata.into()));
+        }
+
+        side_data
+    }
+
+    fn motion_vectors(&self) -> Vec<MotionVector> {
+        let frame: &AvFrame = self;
+        frame
+            .side_data(AvSideDataType::MotionVectors)
+            .map(|data| motion_vectors_from_bytes(data.data()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|vector| MotionVector {
+                src_x: vector.src_x,
+                src_y: vector.src_y,
+                dst_x: vector.dst_x,
+                dst_y: vector.dst_y,
+                w: vector.w,
+                h: vector.h,
+                source: vector.source,
+            })
+            .collect()
+    }
+
+    fn set_side_data(&mut self, side_data: &SideData) -> Result<()> {
+        let (kind, data) = match side_data {
+            SideData::A53Captions(captions) => (AvSideDataType::A53CC, captions.clone()),
+            SideData::MasteringDisplayMetadata(metadata) => (
+                AvSideDataType::MasteringDisplayMetadata,
+                mastering_display_metadata_to_bytes(&(*metadata).into()),
+            ),
+            SideData::ContentLightLevel(metadata) => (
+                AvSideDataType::ContentLightLevel,
+                content_light_metadata_to_bytes(&(*metadata).into()),
+            ),
+        };
+        set_frame_side_data(self, kind, &data)?;
+
+        Ok(())
+    }
+}
+
+/// Access to the metadata dictionary of a [`RawFrame`], like the `lavfi.*` entries that filters
+/// set (such as `lavfi.scene_score` of the `scdet` filter).
+///
+/// Decoded frames carry the entries of the filters they passed through (see
+/// [`crate::Decoder::with_filter`]). The encoder passes the entries of input frames on to its
+/// filter graph (see [`crate::Encoder::with_filter`]) and to the encoder, through pixel format
+/// conversion. No codec or container stores frame metadata in the encoded stream though, so the
+/// entries do not survive encoding and decoding.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = Decoder::new(&PathBuf::from("video.mp4").into())
+///     .unwrap()
+///     .with_filter("scdet")
+///     .unwrap();
+/// let frame = decoder.decode_raw().unwrap();
+/// let score = frame.metadata().get("lavfi.scd.score").cloned();
+/// ```
+pub trait RawFrameMetadata {
+    /// Get the metadata entries of the frame.
+    fn metadata(&self) -> HashMap<String, String>;
+
+    /// Set a metadata entry on the frame, replacing the value it had.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the entry.
+    /// * `value` - Value of the entry.
+    fn set_metadata_entry(&mut self, key: &str, value: &str);
+
+    /// Get the byte position in the input of the packet that the frame was decoded from, if the
+    /// demuxer reports it (see [`crate::Packet::position`]). Together with
+    /// [`crate::Decoder::total_bytes`], this relates frames to byte ranges of the input.
+    fn byte_offset(&self) -> Option<u64>;
+
+    /// Get the duration of the frame, in the time base of its timestamp, if it is known. For
+    /// decoded frames, this is the duration of the packet the frame was decoded from (see
+    /// [`crate::Packet::duration`]).
+    fn duration(&self) -> Option<i64>;
+}
+
+impl RawFrameMetadata for RawFrame {
+    fn metadata(&self) -> HashMap<String, String> {
+        // The metadata of the underlying frame, which this method shadows.
+        let frame: &AvFrame = self;
+        frame
+            .metadata()
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn set_metadata_entry(&mut self, key: &str, value: &str) {
+        let frame: &mut AvFrame = self;
+        let mut metadata = frame.metadata().to_owned();
+        metadata.set(key, value);
+        frame.set_metadata(metadata);
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        let frame: &AvFrame = self;
+        u64::try_from(frame.packet().position).ok()
+    }
+
+    fn duration(&self) -> Option<i64> {
+        frame_duration(self)
+    }
+}
+
+/// Denominator of chromaticity coordinates, as used by H.264 and HEVC SEI messages.
+const CHROMATICITY_DENOMINATOR: i32 = 50_000;
+
+/// Denominator of luminance values, as used by H.264 and HEVC SEI messages.
+const LUMINANCE_DENOMINATOR: i32 = 10_000;
+
+/// Convert a rational to a floating point number.
+///
+/// # Arguments
+///
+/// * `rational` - Rational to convert.
+fn rational_to_f64(rational: AVRational) -> f64 {
+    if rational.den == 0 {
+        0.0
+    } else {
+        rational.num as f64 / rational.den as f64
+    }
+}
+
+/// Convert a floating point number to a rational with a fixed denominator.
+///
+/// # Arguments
+///
+/// * `value` - Number to convert.
+/// * `den` - Denominator of the rational.
+fn f64_to_rational(value: f64, den: i32) -> AVRational {
+    AVRational {
+        num: (value * den as f64).round() as i32,
+        den,
+    }
+}
+
+impl From<MasteringDisplayMetadataRaw> for MasteringDisplayMetadata {
+    fn from(raw: MasteringDisplayMetadataRaw) -> Self {
+        let xy = |[x, y]: [AVRational; 2]| (rational_to_f64(x), rational_to_f64(y));
+        Self {
+            primaries: (raw.has_primaries != 0).then(|| Chromaticities {
+                red: xy(raw.display_primaries[0]),
+                green: xy(raw.display_primaries[1]),
+                blue: xy(raw.display_primaries[2]),
+                white_point: xy(raw.white_point),
+            }),
+            luminance: (raw.has_luminance != 0).then(|| Luminance {
+                min: rational_to_f64(raw.min_luminance),
+                max: rational_to_f64(raw.max_luminance),
+            }),
+        }
+    }
+}
+
+impl From<MasteringDisplayMetadata> for MasteringDisplayMetadataRaw {
+    fn from(metadata: MasteringDisplayMetadata) -> Self {
+        let xy = |(x, y): (f64, f64)| {
+            [
+                f64_to_rational(x, CHROMATICITY_DENOMINATOR),
+                f64_to_rational(y, CHROMATICITY_DENOMINATOR),
+            ]
+        };
+        let zero = AVRational { num: 0, den: 1 };
+        let primaries = metadata.primaries;
+        let luminance = metadata.luminance;
+        Self {
+            display_primaries: primaries.map_or([[zero; 2]; 3], |primaries| {
+                [xy(primaries.red), xy(primaries.green), xy(primaries.blue)]
+            }),
+            white_point: primaries.map_or([zero; 2], |primaries| xy(primaries.white_point)),
+            min_luminance: luminance.map_or(zero, |luminance| {
+                f64_to_rational(luminance.min, LUMINANCE_DENOMINATOR)
+            }),
+            max_luminance: luminance.map_or(zero, |luminance| {
+                f64_to_rational(luminance.max, LUMINANCE_DENOMINATOR)
+            }),
+            has_primaries: primaries.is_some() as std::ffi::c_int,
+            has_luminance: luminance.is_some() as std::ffi::c_int,
+        }
+    }
+}
+
+impl From<ContentLightMetadataRaw> for ContentLightLevel {
+    fn from(raw: ContentLightMetadataRaw) -> Self {
+        Self {
+            max_content: raw.max_cll,
+            max_frame_average: raw.max_fall,
+        }
+    }
+}
+
+impl From<ContentLightLevel> for ContentLightMetadataRaw {
+    fn from(level: ContentLightLevel) -> Self {
+        Self {
+            max_cll: level.max_content,
+            max_fall: level.max_frame_average,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_metadata() {
+        let mut frame = RawFrame::new(ffmpeg::format::Pixel::RGB24, 16, 16);
+        assert!(RawFrameMetadata::metadata(&frame).is_empty());
+        frame.set_metadata_entry("lavfi.scene_score", "0.5");
+        frame.set_metadata_entry("camera", "front");
+        frame.set_metadata_entry("lavfi.scene_score", "0.75");
+        let metadata = RawFrameMetadata::metadata(&frame);
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["lavfi.scene_score"], "0.75");
+        assert_eq!(metadata["camera"], "front");
+    }
+
+    #[test]
+    fn test_hdr_metadata() {
+        let metadata = HdrMetadata {
+            primaries: [(0.708, 0.292), (0.17, 0.797), (0.131, 0.046)],
+            white_point: (0.3127, 0.329),
+            max_luminance: 1000.0,
+            min_luminance: 0.0001,
+            max_cll: 1000,
+            max_fall: 400,
+        };
+        assert_eq!(
+            metadata.x265_params(),
+            "master-display=G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)\
+             L(10000000,1):max-cll=1000,400"
+        );
+
+        let side_data = metadata.to_side_data();
+        assert_eq!(HdrMetadata::from_side_data(&side_data), Some(metadata));
+        // Both kinds of side data are needed.
+        assert_eq!(HdrMetadata::from_side_data(&side_data[..1]), None);
+        assert_eq!(
+            HdrMetadata::from_side_data(&[
+                SideData::A53Captions(Vec::new()),
+                side_data[1].clone(),
+                side_data[0].clone(),
+            ]),
+            Some(metadata)
+        );
+    }
+
+    #[test]
+    fn test_mastering_display_metadata_bytes() {
+        let metadata = MasteringDisplayMetadata {
+            primaries: Some(Chromaticities {
+                red: (0.708, 0.292),
+                green: (0.17, 0.797),
+                blue: (0.131, 0.046),
+                white_point: (0.3127, 0.329),
+            }),
+            luminance: Some(Luminance {
+                min: 0.005,
+                max: 1000.0,
+            }),
+        };
+        let bytes = mastering_display_metadata_to_bytes(&metadata.into());
+        let decoded: MasteringDisplayMetadata = mastering_display_metadata_from_bytes(&bytes)
+            .unwrap()
+            .into();
+        assert_eq!(decoded, metadata);
+        assert!(mastering_display_metadata_from_bytes(&bytes[1..]).is_none());
+
+        let metadata = MasteringDisplayMetadata {
+            primaries: None,
+            luminance: None,
+        };
+        let bytes = mastering_display_metadata_to_bytes(&metadata.into());
+        let decoded: MasteringDisplayMetadata = mastering_display_metadata_from_bytes(&bytes)
+            .unwrap()
+            .into();
+        assert_eq!(decoded, metadata);
+    }
+}
@@ -0,0 +1,668 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ffmpeg::codec::packet::{Flags as AvPacketFlags, Packet as AvPacket};
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::ffi::{
+    clear_codec_tag, input_is_seekable, parameters_extradata, BitstreamFilter as AvBitstreamFilter,
+};
+use crate::io::{private::Write, Reader, Writer};
+use crate::{EncodedPacket, Error, Locator, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Cut the frames from `start` up to (not including) `end` out of the video stream of a source,
+/// frame-accurately, while re-encoding as little as possible.
+///
+/// Only the frames from `start` up to the first key frame in the range are re-encoded (and the
+/// frames after the last key frame in the range, if the range ends in the middle of a group of
+/// pictures). The groups of pictures in between are copied without re-encoding, and the parts are
+/// spliced into one stream with continuous timestamps, starting at zero. A range without key
+/// frames is re-encoded completely.
+///
+/// The re-encoded parts must decode with the codec parameters of the copied part, so the settings
+/// must use the codec and size of the source, and should use the same profile and pixel format
+/// (otherwise this fails with [`Error::IncompatibleInputs`] when the codec or size differ). The
+/// re-encoded parts carry their own parameter sets (like the SPS and PPS of H.264) in the stream,
+/// and H.264 and HEVC parameter sets of the copied part are put in the stream as well, so that
+/// decoders switch between them at the splice points. The source must have closed groups of
+/// pictures (the default of `libx264`): frames that refer to frames before the key frame they
+/// follow are dropped.
+///
+/// Only the video stream is cut: audio and other streams are left out. The source must be a file
+/// (or another source that can seek), otherwise this fails with [`Error::NotSeekable`].
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from.
+/// * `dest` - Locator to write to.
+/// * `start` - Start of the range (inclusive), on the same timeline as the frame timestamps of
+///   [`Decoder::decode`].
+/// * `end` - End of the range (exclusive).
+/// * `settings` - Encoder settings to re-encode with.
+///
+/// # Return value
+///
+/// How many frames were re-encoded and how many packets were copied.
+///
+/// # Example
+///
+/// ```ignore
+/// let stats = smart_cut(
+///     &PathBuf::from("recording.mp4").into(),
+///     &PathBuf::from("clip.mp4").into(),
+///     Time::from_secs(600.2),
+///     Time::from_secs(610.7),
+///     EncoderSettings::for_h264_yuv420p(1920, 1080, false),
+/// )
+/// .unwrap();
+/// println!("Re-encoded {} frames", stats.frames_reencoded);
+/// ```
+pub fn smart_cut(
+    source: &Locator,
+    dest: &Locator,
+    start: Time,
+    end: Time,
+    settings: Settings,
+) -> Result<SmartCutStats> {
+    let mut reader = Reader::new(source)?;
+    if !input_is_seekable(&reader.input) {
+        return Err(Error::NotSeekable);
+    }
+    let stream_index = reader.best_video_stream_index()?;
+    let stream = reader
+        .input
+        .stream(stream_index)
+        .ok_or(AvError::StreamNotFound)?;
+    let time_base = stream.time_base();
+    let parameters = stream.parameters();
+    check_settings(&settings, &parameters)?;
+
+    let (frames, key_frame_dts) = scan_frames(&mut reader, stream_index)?;
+    let to_stream_time = |time: &Time| time.aligned_with_rational(time_base).into_value();
+    let plan = plan_cut(
+        &frames,
+        to_stream_time(&start).unwrap_or(i64::MIN),
+        to_stream_time(&end).unwrap_or(i64::MAX),
+    )
+    .ok_or_else(|| Error::InvalidOptions(format!("no frames between {start} and {end} to cut")))?;
+
+    // Settings for the re-encoded parts, with timestamps in the time base of the source. Without
+    // B-frames, the decoding timestamps of the packets are their presentation timestamps, which
+    // keeps them apart from the timestamps of the copied packets.
+    let settings = settings
+        .to_owned_settings()
+        .with_time_base(time_base.numerator(), time_base.denominator())?
+        .without_b_frames();
+    let mut decoder = Decoder::new(source)?;
+    let mut reencode = |range: (i64, i64)| {
+        reencode_range(&mut decoder, settings.to_owned_settings(), range, time_base)
+    };
+    let head = plan
+        .head
+        .map(&mut reencode)
+        .transpose()?
+        .unwrap_or_default();
+    let tail = plan
+        .tail
+        .map(&mut reencode)
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut splice = Splice::new(dest, &parameters, time_base, plan.first)?;
+    // The decoding timestamps of the head must stay before the decoding timestamp of the first
+    // copied key frame, which is earlier than its presentation timestamp if the source has
+    // B-frames. The tail starts at a key frame after all copied frames, so it needs no shift.
+    let head_dts_shift = match (head.last(), plan.copy) {
+        (Some(last), Some((copy_start, _))) => {
+            let copy_start_dts = key_frame_dts
+                .get(&copy_start)
+                .copied()
+                .unwrap_or(copy_start);
+            (last.dts().unwrap_or(i64::MIN) + 1 - copy_start_dts).max(0)
+        }
+        _ => 0,
+    };
+
+    let mut stats = SmartCutStats {
+        frames_reencoded: (head.len() + tail.len()) as u64,
+        packets_copied: 0,
+    };
+    for mut packet in head {
+        packet.set_dts(packet.dts().map(|dts| dts - head_dts_shift));
+        splice.write(packet)?;
+    }
+    if let Some((copy_start, copy_end)) = plan.copy {
+        stats.packets_copied = splice.copy(&mut reader, stream_index, copy_start, copy_end)?;
+    }
+    for packet in tail {
+        splice.write(packet)?;
+    }
+    splice.finish()?;
+
+    Ok(stats)
+}
+
+/// Statistics about a finished smart cut (see [`smart_cut`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmartCutStats {
+    /// Number of frames that were re-encoded, at the start and end of the range.
+    pub frames_reencoded: u64,
+    /// Number of packets that were copied without re-encoding.
+    pub packets_copied: u64,
+}
+
+/// Which parts of the range are re-encoded and which are copied. All timestamps are presentation
+/// timestamps in the time base of the source stream.
+#[derive(Debug, PartialEq, Eq)]
+struct CutPlan {
+    /// Timestamp of the first frame of the range.
+    first: i64,
+    /// Range of frames to re-encode at the start: start (inclusive) and end (exclusive).
+    head: Option<(i64, i64)>,
+    /// Key frame that starts the copied part, and the key frame at which it stops (exclusive), or
+    /// `None` to copy up to the end of the stream.
+    copy: Option<(i64, Option<i64>)>,
+    /// Range of frames to re-encode at the end: start (inclusive) and end (exclusive).
+    tail: Option<(i64, i64)>,
+}
+
+/// Find out which parts of a range to re-encode and which to copy.
+///
+/// # Arguments
+///
+/// * `frames` - Presentation timestamp of each frame and whether or not it is a key frame, in
+///   presentation order.
+/// * `start` - Start of the range (inclusive).
+/// * `end` - End of the range (exclusive).
+///
+/// # Return value
+///
+/// The plan, or `None` if the range has no frames.
+fn plan_cut(frames: &[(i64, bool)], start: i64, end: i64) -> Option<CutPlan> {
+    let in_range = frames
+        .iter()
+        .filter(|(pts, _)| (start..end).contains(pts))
+        .collect::<Vec<_>>();
+    let first = in_range.first()?.0;
+    let last = in_range.last()?.0;
+    let reencode_all = CutPlan {
+        first,
+        head: Some((first, last + 1)),
+        copy: None,
+        tail: None,
+    };
+
+    let mut key_frames = in_range
+        .iter()
+        .filter(|(_, is_key)| *is_key)
+        .map(|(pts, _)| *pts);
+    let Some(copy_start) = key_frames.next() else {
+        return Some(reencode_all);
+    };
+    let last_key_frame = key_frames.next_back().unwrap_or(copy_start);
+    let next_key_frame = frames
+        .iter()
+        .find(|(pts, is_key)| *is_key && *pts > last)
+        .map(|(pts, _)| *pts);
+    // The last group of pictures can be copied if the range ends where it ends.
+    let ends_in_group = frames.iter().any(|(pts, _)| {
+        *pts > last && next_key_frame.is_none_or(|next_key_frame| *pts < next_key_frame)
+    });
+    let (copy, tail) = if !ends_in_group {
+        ((copy_start, next_key_frame), None)
+    } else if last_key_frame > copy_start {
+        (
+            (copy_start, Some(last_key_frame)),
+            Some((last_key_frame, last + 1)),
+        )
+    } else {
+        return Some(reencode_all);
+    };
+
+    Some(CutPlan {
+        first,
+        head: (first < copy_start).then_some((first, copy_start)),
+        copy: Some(copy),
+        tail,
+    })
+}
+
+/// Check that settings encode with the codec and size of a stream, so that the re-encoded parts
+/// fit in with the copied part.
+///
+/// # Arguments
+///
+/// * `settings` - Encoder settings.
+/// * `parameters` - Codec parameters of the source stream.
+fn check_settings(settings: &Settings, parameters: &ffmpeg::codec::Parameters) -> Result<()> {
+    let (width, height) = settings.size();
+    let (source_width, source_height) = unsafe {
+        let parameters = parameters.as_ptr();
+        ((*parameters).width as u32, (*parameters).height as u32)
+    };
+    if settings.codec_id() != Some(parameters.id()) {
+        return Err(Error::IncompatibleInputs(format!(
+            "settings encode with {:?}, but the source is {:?}",
+            settings.codec_id(),
+            parameters.id()
+        )));
+    }
+    if (width, height) != (source_width, source_height) {
+        return Err(Error::IncompatibleInputs(format!(
+            "settings encode at {width}x{height}, but the source is \
+             {source_width}x{source_height}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Read the packets of the video stream without decoding them, and rewind the reader.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `stream_index` - Index of the video stream.
+///
+/// # Return value
+///
+/// The presentation timestamp of each frame and whether or not it is a key frame, in presentation
+/// order, and the decoding timestamp of each key frame by its presentation timestamp.
+#[allow(clippy::type_complexity)]
+fn scan_frames(
+    reader: &mut Reader,
+    stream_index: usize,
+) -> Result<(Vec<(i64, bool)>, HashMap<i64, i64>)> {
+    let mut frames = Vec::new();
+    let mut key_frame_dts = HashMap::new();
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) if packet.size() > 0 => {
+                let (packet, _) = packet.into_inner_parts();
+                if let Some(pts) = packet.pts() {
+                    frames.push((pts, packet.is_key()));
+                    if packet.is_key() {
+                        key_frame_dts.insert(pts, packet.dts().unwrap_or(pts));
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    frames.sort_unstable();
+    reader.seek_to_start()?;
+
+    Ok((frames, key_frame_dts))
+}
+
+/// Decode and re-encode the frames of a range.
+///
+/// # Arguments
+///
+/// * `decoder` - Decoder of the source.
+/// * `settings` - Encoder settings, with the time base of the source stream.
+/// * `range` - Start (inclusive) and end (exclusive) of the range.
+/// * `time_base` - Time base of the source stream.
+///
+/// # Return value
+///
+/// The encoded packets, in decoding order, with timestamps in the time base of the source stream.
+fn reencode_range(
+    decoder: &mut Decoder,
+    settings: Settings<'static>,
+    (start, end): (i64, i64),
+    time_base: AvRational,
+) -> Result<Vec<AvPacket>> {
+    let packets = Arc::new(Mutex::new(Vec::new()));
+    let mut encoder = Encoder::new_packetized(settings, time_base, {
+        let packets = packets.clone();
+        move |packet: EncodedPacket| packets.lock().unwrap().push(packet)
+    })?;
+    let decoder_time_base = decoder.time_base();
+    let encoder_time_base = encoder.time_base();
+    for frame in decoder.decode_raw_between(
+        Time::new(Some(start), time_base),
+        Time::new(Some(end), time_base),
+        false,
+    )? {
+        let mut frame = frame?;
+        let timestamp = Time::new(frame.pts(), decoder_time_base);
+        frame.set_pts(
+            timestamp
+                .aligned_with_rational(encoder_time_base)
+                .into_value(),
+        );
+        encoder.encode_raw(frame)?;
+    }
+    encoder.finish()?;
+    drop(encoder);
+
+    let packets = std::mem::take(&mut *packets.lock().unwrap());
+    Ok(packets
+        .into_iter()
+        .map(|encoded| {
+            let mut packet = AvPacket::copy(&encoded.data);
+            let to_stream_time = |time: Time| time.aligned_with_rational(time_base).into_value();
+            packet.set_pts(to_stream_time(encoded.pts));
+            packet.set_dts(to_stream_time(encoded.dts));
+            packet.set_duration(to_stream_time(encoded.duration).unwrap_or(0));
+            if encoded.is_key {
+                packet.set_flags(AvPacketFlags::KEY);
+            }
+            packet
+        })
+        .collect())
+}
+
+/// Writes the re-encoded and copied parts of a smart cut into one stream.
+struct Splice {
+    writer: Writer,
+    /// Time base of the source stream, which all packets are in.
+    time_base: AvRational,
+    /// Time base of the destination stream.
+    writer_time_base: AvRational,
+    /// Offset to subtract from all timestamps, so that the destination starts at zero.
+    offset: i64,
+    /// Filter that puts the parameter sets of the copied packets in the stream.
+    filter: Option<AvBitstreamFilter>,
+}
+
+impl Splice {
+    /// Create the destination with a stream for the cut.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest` - Locator to write to.
+    /// * `parameters` - Codec parameters of the source stream.
+    /// * `time_base` - Time base of the source stream.
+    /// * `offset` - Timestamp of the first frame of the cut.
+    fn new(
+        dest: &Locator,
+        parameters: &ffmpeg::codec::Parameters,
+        time_base: AvRational,
+        offset: i64,
+    ) -> Result<Self> {
+        // Length-prefixed extradata (`avcC` or `hvcC`) starts with version 1, and holds the only
+        // copy of the parameter sets of the copied packets. Converting to Annex B puts them in
+        // the stream before every key frame, like the re-encoded packets have them.
+        let length_prefixed = parameters_extradata(parameters).first() == Some(&1);
+        let filter = match parameters.id() {
+            AvCodecId::H264 if length_prefixed => Some("h264_mp4toannexb"),
+            AvCodecId::HEVC if length_prefixed => Some("hevc_mp4toannexb"),
+            _ => None,
+        }
+        .map(|name| AvBitstreamFilter::new(name, parameters, time_base))
+        .transpose()?;
+
+        let mut writer_parameters = match filter.as_ref() {
+            Some(filter) => filter.parameters_out(),
+            None => parameters.clone(),
+        };
+        clear_codec_tag(&mut writer_parameters);
+        let mut writer = Writer::new(dest)?;
+        let mut writer_stream = writer
+            .output
+            .add_stream(ffmpeg::encoder::find(parameters.id()))?;
+        writer_stream.set_parameters(writer_parameters);
+        let writer_stream_index = writer_stream.index();
+        writer.write_header()?;
+        // The muxer may change the time base while writing the header.
+        let writer_time_base = writer
+            .output
+            .stream(writer_stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .time_base();
+
+        Ok(Self {
+            writer,
+            time_base,
+            writer_time_base,
+            offset,
+            filter,
+        })
+    }
+
+    /// Copy the packets of the groups of pictures from one key frame up to another.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader of the source.
+    /// * `stream_index` - Index of the video stream.
+    /// * `start` - Presentation timestamp of the key frame to start at.
+    /// * `end` - Presentation timestamp of the key frame to stop at (exclusive), or `None` to copy
+    ///   up to the end of the stream.
+    ///
+    /// # Return value
+    ///
+    /// Number of copied packets.
+    fn copy(
+        &mut self,
+        reader: &mut Reader,
+        stream_index: usize,
+        start: i64,
+        end: Option<i64>,
+    ) -> Result<u64> {
+        reader.seek_to_keyframe_before(start.rescale(self.time_base, TIME_BASE))?;
+        let mut started = false;
+        let mut copied = 0;
+        loop {
+            let packet = match reader.read(stream_index) {
+                Ok(packet) => packet.into_inner_parts().0,
+                Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            };
+            let pts = packet.pts();
+            if packet.is_key() && pts.is_some() && pts == end {
+                break;
+            }
+            started |= packet.is_key() && pts == Some(start);
+            // Frames that refer to the group of pictures before the first key frame are dropped,
+            // since their frames are re-encoded.
+            if !started || pts.is_some_and(|pts| pts < start) {
+                continue;
+            }
+
+            self.copy_packet(Some(packet))?;
+            copied += 1;
+        }
+        self.copy_packet(None)?;
+
+        Ok(copied)
+    }
+
+    /// Write a copied packet, passing it through the filter if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write, or `None` to flush the filter.
+    fn copy_packet(&mut self, packet: Option<AvPacket>) -> Result<()> {
+        let Some(filter) = self.filter.as_mut() else {
+            return packet.map_or(Ok(()), |packet| self.write(packet));
+        };
+
+        match packet {
+            Some(mut packet) => filter.send(Some(&mut packet))?,
+            None => filter.send(None)?,
+        }
+        let mut filtered = Vec::new();
+        loop {
+            let mut packet = AvPacket::empty();
+            match filter.receive(&mut packet) {
+                Ok(()) => filtered.push(packet),
+                Err(AvError::Eof) => break,
+                Err(AvError::Other { errno }) if errno == EAGAIN => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        filtered
+            .into_iter()
+            .try_for_each(|packet| self.write(packet))
+    }
+
+    /// Write a packet with timestamps in the time base of the source stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write.
+    fn write(&mut self, mut packet: AvPacket) -> Result<()> {
+        packet.set_pts(packet.pts().map(|pts| pts - self.offset));
+        packet.set_dts(packet.dts().map(|dts| dts - self.offset));
+        packet.rescale_ts(self.time_base, self.writer_time_base);
+        packet.set_stream(0);
+        packet.set_position(-1);
+        self.writer.write_interleaved(&mut packet)
+    }
+
+    /// Finish the destination.
+    fn finish(mut self) -> Result<()> {
+        self.writer.write_trailer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg::util::format::Pixel as AvPixel;
+
+    use crate::hash_video;
+    use crate::options::Options;
+    use crate::testing::{self, TempPath};
+
+    /// Frames at timestamps 0 to 94, with a key frame every 10 frames.
+    fn frames() -> Vec<(i64, bool)> {
+        (0..95).map(|pts| (pts, pts % 10 == 0)).collect()
+    }
+
+    #[test]
+    fn test_plan_cut() {
+        let frames = frames();
+        // Starts and ends in the middle of a group of pictures.
+        assert_eq!(
+            plan_cut(&frames, 13, 57),
+            Some(CutPlan {
+                first: 13,
+                head: Some((13, 20)),
+                copy: Some((20, Some(50))),
+                tail: Some((50, 57)),
+            })
+        );
+        // Starts at a key frame and ends where a group of pictures ends.
+        assert_eq!(
+            plan_cut(&frames, 20, 60),
+            Some(CutPlan {
+                first: 20,
+                head: None,
+                copy: Some((20, Some(60))),
+                tail: None,
+            })
+        );
+        // Up to the end of the stream.
+        assert_eq!(
+            plan_cut(&frames, 85, 1000),
+            Some(CutPlan {
+                first: 85,
+                head: Some((85, 90)),
+                copy: Some((90, None)),
+                tail: None,
+            })
+        );
+        // Only one key frame, in the middle.
+        let reencode_all = |first, end| CutPlan {
+            first,
+            head: Some((first, end)),
+            copy: None,
+            tail: None,
+        };
+        assert_eq!(plan_cut(&frames, 15, 25), Some(reencode_all(15, 25)));
+        // No key frame at all.
+        assert_eq!(plan_cut(&frames, 11, 19), Some(reencode_all(11, 19)));
+        assert_eq!(plan_cut(&frames, 95, 100), None);
+    }
+
+    #[test]
+    fn test_smart_cut_matches_full_reencode() {
+        let dir = TempPath::new("smart-cut");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let cut: Locator = dir.join("cut.mp4").into();
+        let full: Locator = dir.join("full.mp4").into();
+
+        testing::write_test_video_with_settings(
+            &source,
+            testing::settings().with_key_frame_interval(10),
+            (0..95).map(|i| testing::filled_frame((i * 2) as u8)),
+        );
+
+        // Lossless, so that the re-encoded frames of both cuts are the same.
+        let lossless = || {
+            let mut options = Options::new_h264();
+            options.set("qp", "0");
+            Settings::for_h264_custom(64, 48, AvPixel::YUV420P, options)
+        };
+        // From frame 13 up to and including frame 56.
+        let start = Time::from_secs(12.5 / 30.0);
+        let end = Time::from_secs(56.5 / 30.0);
+        let stats = smart_cut(&source, &cut, start.clone(), end.clone(), lossless()).unwrap();
+        assert_eq!(
+            stats,
+            SmartCutStats {
+                frames_reencoded: 14,
+                packets_copied: 30,
+            }
+        );
+
+        let mut decoder = Decoder::new(&source).unwrap();
+        let decoder_time_base = decoder.time_base();
+        let mut encoder = Encoder::new(&full, lossless()).unwrap();
+        let encoder_time_base = encoder.time_base();
+        for frame in decoder.decode_raw_between(start, end, true).unwrap() {
+            let mut frame = frame.unwrap();
+            let timestamp = Time::new(frame.pts(), decoder_time_base);
+            frame.set_pts(
+                timestamp
+                    .aligned_with_rational(encoder_time_base)
+                    .into_value(),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let hashes = |locator: &Locator| {
+            hash_video(locator)
+                .unwrap()
+                .into_iter()
+                .map(|(_, hash)| hash)
+                .collect::<Vec<_>>()
+        };
+        let cut_hashes = hashes(&cut);
+        let full_hashes = hashes(&full);
+        assert_eq!(cut_hashes.len(), 44);
+        assert_eq!(full_hashes.len(), 44);
+        assert_eq!(cut_hashes.first(), full_hashes.first());
+        assert_eq!(cut_hashes.last(), full_hashes.last());
+
+        assert!(matches!(
+            smart_cut(
+                &source,
+                &cut,
+                Time::zero(),
+                Time::from_secs(1.0),
+                Settings::for_h264_yuv420p(32, 32, false)
+            ),
+            Err(Error::IncompatibleInputs(_))
+        ));
+    }
+}
@@ -0,0 +1,388 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::pixel::Pixel as AvPixel;
+
+use crate::ffi::{frame_make_writable, pixel_format_layout, PixelLayout};
+use crate::{Error, RawFrame, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Width and height of a glyph of the stamp font, in pixels before scaling.
+const GLYPH_SIZE: (u32, u32) = (3, 5);
+
+/// Corner of the frame that the debug stamp is drawn in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StampPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// How to draw the frame number and timestamp onto frames, see
+/// [`Encoder::with_debug_stamp`](crate::Encoder::with_debug_stamp).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StampConfig {
+    /// Corner of the frame to draw the stamp in.
+    pub position: StampPosition,
+    /// Size of a pixel of the font, in frame pixels. The font is 5 pixels high, so a scale of `2`
+    /// draws text that is 10 pixels high. Defaults to `2`.
+    pub scale: u32,
+    /// Color of the text: red, green and blue. Defaults to white.
+    pub color: [u8; 3],
+}
+
+impl Default for StampConfig {
+    fn default() -> Self {
+        Self {
+            position: StampPosition::TopLeft,
+            scale: 2,
+            color: [255, 255, 255],
+        }
+    }
+}
+
+/// Draws the frame number and timestamp onto frames in the pixel format of the encoder, with a
+/// bitmap font that is built in.
+pub(crate) struct DebugStamp {
+    config: StampConfig,
+    layout: PixelLayout,
+    /// Value of each component of the color of the text, at the depth of the component.
+    values: Vec<u16>,
+}
+
+impl DebugStamp {
+    /// Create a debug stamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - How to draw the stamp.
+    /// * `format` - Pixel format of the frames to draw onto.
+    pub(crate) fn new(config: StampConfig, format: AvPixel) -> Result<Self> {
+        if config.scale == 0 {
+            return Err(Error::InvalidOptions(
+                "debug stamp scale must be at least 1".to_string(),
+            ));
+        }
+        let layout = pixel_format_layout(format).ok_or_else(|| {
+            Error::invalid_frame_format(
+                "a pixel format with 8 or 16-bit samples",
+                format!("{format:?}"),
+            )
+        })?;
+
+        let [red, green, blue] = config.color;
+        let full_range = layout.components.len() <= 2
+            || format
+                .descriptor()
+                .is_some_and(|descriptor| descriptor.name().starts_with("yuvj"));
+        let color = if layout.rgb {
+            [red, green, blue]
+        } else {
+            rgb_to_ycbcr([red, green, blue], full_range)
+        };
+        let values = layout
+            .components
+            .iter()
+            .enumerate()
+            .map(|(index, component)| {
+                // The last component of formats with an odd number of components is alpha.
+                let value = match (index, layout.components.len()) {
+                    (1, 2) | (3, _) => u8::MAX,
+                    (index, _) => color[index],
+                };
+                if component.depth >= 8 {
+                    (value as u16) << (component.depth - 8)
+                } else {
+                    value as u16 >> (8 - component.depth)
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            config,
+            layout,
+            values,
+        })
+    }
+
+    /// Draw the stamp onto a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to draw onto, in the pixel format of the stamp.
+    /// * `frame_number` - Number of the frame, counting from zero.
+    /// * `timestamp` - Timestamp of the frame.
+    pub(crate) fn apply(
+        &self,
+        frame: &mut RawFrame,
+        frame_number: u64,
+        timestamp: &Time,
+    ) -> Result<()> {
+        let (mask_width, mask_height, mask) =
+            render(&stamp_text(frame_number, timestamp), self.config.scale);
+        let (width, height) = (frame.width(), frame.height());
+        let margin = 2 * self.config.scale;
+        let x = match self.config.position {
+            StampPosition::TopLeft | StampPosition::BottomLeft => margin,
+            StampPosition::TopRight | StampPosition::BottomRight => {
+                width.saturating_sub(mask_width + margin)
+            }
+        };
+        let y = match self.config.position {
+            StampPosition::TopLeft | StampPosition::TopRight => margin,
+            StampPosition::BottomLeft | StampPosition::BottomRight => {
+                height.saturating_sub(mask_height + margin)
+            }
+        };
+
+        frame_make_writable(frame).map_err(Error::from)?;
+        for (index, _) in mask.iter().enumerate().filter(|(_, &set)| set) {
+            let (pixel_x, pixel_y) = (x + index as u32 % mask_width, y + index as u32 / mask_width);
+            if pixel_x < width && pixel_y < height {
+                self.put_pixel(frame, pixel_x as usize, pixel_y as usize);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a pixel of a frame to the color of the text.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to draw onto.
+    /// * `x` - Horizontal position of the pixel.
+    /// * `y` - Vertical position of the pixel.
+    fn put_pixel(&self, frame: &mut RawFrame, x: usize, y: usize) {
+        for (index, (component, &value)) in
+            self.layout.components.iter().zip(&self.values).enumerate()
+        {
+            let is_chroma = !self.layout.rgb && (index == 1 || index == 2) && self.values.len() > 2;
+            let (x, y) = if is_chroma {
+                (
+                    x >> self.layout.log2_chroma.0,
+                    y >> self.layout.log2_chroma.1,
+                )
+            } else {
+                (x, y)
+            };
+            let stride = frame.stride(component.plane);
+            let data = frame.data_mut(component.plane);
+            let position = y * stride + x * component.step + component.offset;
+            let mask = ((1u32 << component.depth) - 1) << component.shift;
+            let value = (value as u32) << component.shift;
+            if component.shift + component.depth <= 8 {
+                if let Some(byte) = data.get_mut(position) {
+                    *byte = ((*byte as u32 & !mask) | value) as u8;
+                }
+            } else if let Some(word) = data.get_mut(position..position + 2) {
+                let old = if self.layout.big_endian {
+                    u16::from_be_bytes([word[0], word[1]])
+                } else {
+                    u16::from_le_bytes([word[0], word[1]])
+                };
+                let new = ((old as u32 & !mask) | value) as u16;
+                word.copy_from_slice(&if self.layout.big_endian {
+                    new.to_be_bytes()
+                } else {
+                    new.to_le_bytes()
+                });
+            }
+        }
+    }
+}
+
+/// Get the text of the stamp of a frame: its number and its timestamp as `HH:MM:SS.mmm`.
+///
+/// # Arguments
+///
+/// * `frame_number` - Number of the frame.
+/// * `timestamp` - Timestamp of the frame.
+fn stamp_text(frame_number: u64, timestamp: &Time) -> String {
+    let timecode = if timestamp.has_value() {
+        let millis = (timestamp.as_secs_f64() * 1000.0).round() as i64;
+        let sign = if millis < 0 { "-" } else { "" };
+        let millis = millis.unsigned_abs();
+        format!(
+            "{sign}{:02}:{:02}:{:02}.{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    } else {
+        "--:--:--.---".to_string()
+    };
+
+    format!("{frame_number} {timecode}")
+}
+
+/// Render text with the stamp font.
+///
+/// # Arguments
+///
+/// * `text` - Text to render. Characters that the font does not have are left blank.
+/// * `scale` - Size of a pixel of the font.
+///
+/// # Return value
+///
+/// The width and height of the text and whether each pixel is set, row by row.
+fn render(text: &str, scale: u32) -> (u32, u32, Vec<bool>) {
+    let num_chars = text.chars().count() as u32;
+    // One blank column between glyphs.
+    let width = (num_chars * (GLYPH_SIZE.0 + 1)).saturating_sub(1) * scale;
+    let height = GLYPH_SIZE.1 * scale;
+    let mut mask = vec![false; (width * height) as usize];
+    for (index, glyph) in text.chars().map(glyph).enumerate() {
+        let left = index as u32 * (GLYPH_SIZE.0 + 1) * scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_SIZE.0 {
+                if bits & (1 << (GLYPH_SIZE.0 - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    let y = row as u32 * scale + dy;
+                    let x = left + column * scale;
+                    let start = (y * width + x) as usize;
+                    mask[start..start + scale as usize].fill(true);
+                }
+            }
+        }
+    }
+
+    (width, height, mask)
+}
+
+/// Get the rows of a glyph of the stamp font, with the leftmost pixel in the highest bit.
+///
+/// # Arguments
+///
+/// * `c` - Character to get the glyph of.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
+
+/// Convert a color to luma and chroma with BT.709 coefficients.
+///
+/// # Arguments
+///
+/// * `rgb` - Red, green and blue.
+/// * `full_range` - Whether to use the full range, or the limited (TV) range otherwise.
+fn rgb_to_ycbcr(rgb: [u8; 3], full_range: bool) -> [u8; 3] {
+    let [red, green, blue] = rgb.map(|value| value as f32 / 255.0);
+    let luma = 0.2126 * red + 0.7152 * green + 0.0722 * blue;
+    let cb = (blue - luma) / 1.8556;
+    let cr = (red - luma) / 1.5748;
+    let (luma_range, chroma_range, offset) = if full_range {
+        (255.0, 255.0, 0.0)
+    } else {
+        (219.0, 224.0, 16.0)
+    };
+
+    [
+        (offset + luma * luma_range).round() as u8,
+        (128.0 + cb * chroma_range).round().clamp(0.0, 255.0) as u8,
+        (128.0 + cr * chroma_range).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    use ffmpeg::Rational as AvRational;
+
+    #[test]
+    fn test_stamp_text() {
+        let time_base = AvRational::new(1, 1000);
+        assert_eq!(
+            stamp_text(42, &Time::new(Some(3_723_456), time_base)),
+            "42 01:02:03.456"
+        );
+        assert_eq!(
+            stamp_text(0, &Time::new(Some(-40), time_base)),
+            "0 -00:00:00.040"
+        );
+        assert_eq!(stamp_text(7, &Time::new(None, time_base)), "7 --:--:--.---");
+    }
+
+    #[test]
+    fn test_render() {
+        let (width, height, mask) = render("1", 1);
+        assert_eq!((width, height), (3, 5));
+        let rows = mask
+            .chunks(3)
+            .map(|row| row.iter().map(|&set| if set { '#' } else { '.' }).collect())
+            .collect::<Vec<String>>();
+        assert_eq!(rows, [".#.", "##.", ".#.", ".#.", "###"]);
+
+        let (width, height, mask) = render("1.", 2);
+        assert_eq!((width, height), (14, 10));
+        // The dot is in the middle column of the second glyph, on the last row.
+        assert!(mask[9 * 14 + 10] && mask[9 * 14 + 11]);
+        assert_eq!(mask.iter().filter(|&&set| set).count(), (8 + 1) * 4);
+    }
+
+    #[test]
+    fn test_debug_stamp_only_changes_corner() {
+        use crate::{Decoder, Encoder, EncoderSettings, Locator, Options};
+
+        let dir = TempPath::new("stamp");
+        std::fs::create_dir_all(&dir).unwrap();
+        // Lossless, so that the frames only differ where the stamp is.
+        let encode = |name: &str, stamp: Option<StampConfig>| {
+            let dest: Locator = dir.join(name).into();
+            let mut options = Options::new_h264();
+            options.set("qp", "0");
+            let settings = EncoderSettings::for_h264_custom(64, 48, AvPixel::YUV444P, options);
+            let mut encoder = Encoder::new(&dest, settings).unwrap();
+            if let Some(stamp) = stamp {
+                encoder = encoder.with_debug_stamp(stamp).unwrap();
+            }
+            testing::encode_frames(&mut encoder, 0, (0..3).map(|_| testing::filled_frame(40)));
+            encoder.finish().unwrap();
+            drop(encoder);
+
+            let mut decoder = Decoder::new(&dest).unwrap();
+            let frame = decoder.decode_raw().unwrap();
+            let stride = frame.stride(0);
+            let data = frame.data(0);
+            (0..48)
+                .map(|y| data[y * stride..y * stride + 64 * 3].to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        let plain = encode("plain.mkv", None);
+        let stamped = encode("stamped.mkv", Some(StampConfig::default()));
+        // The stamp is 10 rows high, 4 rows from the top.
+        assert!((4..14).any(|y| stamped[y] != plain[y]));
+        assert_eq!(stamped[..4], plain[..4]);
+        assert_eq!(stamped[14..], plain[14..]);
+    }
+
+    #[test]
+    fn test_rgb_to_ycbcr() {
+        assert_eq!(rgb_to_ycbcr([0, 0, 0], false), [16, 128, 128]);
+        assert_eq!(rgb_to_ycbcr([255, 255, 255], false), [235, 128, 128]);
+        assert_eq!(rgb_to_ycbcr([255, 255, 255], true), [255, 128, 128]);
+    }
+}
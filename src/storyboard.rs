@@ -0,0 +1,376 @@
+use std::path::Path;
+
+use ffmpeg_next::util::format::Pixel as AvPixel;
+
+use crate::attachment::ImageFormat;
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::io::Reader;
+use crate::options::Options;
+use crate::transcode::{decode_error, encode_error, source_start_and_duration};
+use crate::{Error, Locator, RawFrame, Time};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Configuration of a storyboard (see [`generate_storyboard`]).
+#[derive(Debug, Clone)]
+pub struct StoryboardConfig {
+    /// Time between thumbnails.
+    pub interval: Time,
+    /// Width and height of each thumbnail.
+    pub thumb_size: (u32, u32),
+    /// Number of thumbnails per row of the sprite sheet.
+    pub columns: u32,
+}
+
+/// Thumbnail of a storyboard, in the sprite sheet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Tile {
+    /// Timestamp of the sampled frame in seconds, from the start of the video.
+    time: f64,
+    /// Horizontal offset in the sprite sheet.
+    x: u32,
+    /// Vertical offset in the sprite sheet.
+    y: u32,
+}
+
+/// Generate a storyboard for a video: a sprite sheet with a thumbnail for every interval, and a
+/// WebVTT file that maps time ranges to regions of the sheet (with `#xywh` fragments), as used by
+/// video players to preview the video when hovering the seek bar.
+///
+/// A frame is sampled at the start of every interval (at the first frame at or after it, within
+/// the interval), and resized to the thumbnail size. Thumbnails are laid out row by row, and the
+/// last row is left black where it is not filled. Videos that are shorter than one interval get
+/// a single thumbnail. The cues of the VTT file run from the timestamp of the frame that was
+/// actually sampled to that of the next thumbnail (or the end of the video), so they match the
+/// thumbnails even when the frames do not line up with the intervals.
+///
+/// The format of the sprite sheet follows from the extension of `dest_image`, which is either
+/// JPEG (`.jpg` or `.jpeg`) or PNG (`.png`). The VTT file refers to the sprite sheet by its file
+/// name, so both are expected to be in the same directory.
+///
+/// # Arguments
+///
+/// * `source` - Locator to read from. It must be able to seek.
+/// * `dest_image` - Path to write the sprite sheet to.
+/// * `dest_vtt` - Path to write the WebVTT file to.
+/// * `config` - Interval, thumbnail size and layout.
+///
+/// # Example
+///
+/// ```ignore
+/// generate_storyboard(
+///     &PathBuf::from("video.mp4").into(),
+///     Path::new("storyboard.jpg"),
+///     Path::new("storyboard.vtt"),
+///     StoryboardConfig {
+///         interval: Time::from_secs(10.0),
+///         thumb_size: (160, 90),
+///         columns: 10,
+///     },
+/// )
+/// .unwrap();
+/// ```
+pub fn generate_storyboard(
+    source: &Locator,
+    dest_image: &Path,
+    dest_vtt: &Path,
+    config: StoryboardConfig,
+) -> Result<()> {
+    let interval = config.interval.as_secs_f64();
+    let (width, height) = config.thumb_size;
+    if interval.is_nan() || interval <= 0.0 {
+        return Err(Error::InvalidOptions(
+            "storyboard interval must be positive".to_string(),
+        ));
+    }
+    if width == 0 || height == 0 || config.columns == 0 {
+        return Err(Error::InvalidOptions(
+            "storyboard thumbnails and columns must not be empty".to_string(),
+        ));
+    }
+    let image_format = ImageFormat::from_path(dest_image).ok_or_else(|| {
+        Error::InvalidOptions(format!(
+            "storyboard image must be a JPEG or PNG file: {}",
+            dest_image.display()
+        ))
+    })?;
+    let image_name = dest_image
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            Error::InvalidOptions(format!(
+                "storyboard image has no file name: {}",
+                dest_image.display()
+            ))
+        })?;
+
+    let (start, duration) = source_start_and_duration(&Reader::new(source).map_err(decode_error)?);
+    let mut decoder = Decoder::builder(source)
+        .output_format(AvPixel::RGB24)
+        .output_size(width, height)
+        .build()
+        .map_err(decode_error)?;
+    let time_base = decoder.time_base();
+
+    let mut thumbnails = Vec::new();
+    for index in 0.. {
+        let target = start + index as f64 * interval;
+        if duration.is_some_and(|duration| index > 0 && target >= start + duration) {
+            break;
+        }
+        let frame = decoder
+            .decode_raw_between(
+                Time::from_secs_f64(target),
+                Time::from_secs_f64(target + interval),
+                false,
+            )
+            .map_err(decode_error)?
+            .next()
+            .transpose()
+            .map_err(decode_error)?;
+        match frame {
+            Some(frame) => {
+                let time = Time::new(frame.pts(), time_base).as_secs_f64() - start;
+                thumbnails.push((time.max(0.0), frame));
+            }
+            // Without a duration, the end of the video is where frames run out.
+            None if duration.is_none() => break,
+            None => {}
+        }
+    }
+    if thumbnails.is_empty() {
+        return Err(decode_error(Error::ReadExhausted));
+    }
+
+    let tiles = layout(
+        thumbnails.iter().map(|(time, _)| *time),
+        config.thumb_size,
+        config.columns,
+    );
+    let sheet = sprite_sheet(&thumbnails, &tiles, config.thumb_size, config.columns);
+    write_image(dest_image, sheet, image_format).map_err(encode_error)?;
+
+    let end = match duration {
+        Some(duration) => duration,
+        None => tiles.last().map_or(0.0, |tile| tile.time) + interval,
+    };
+    std::fs::write(dest_vtt, vtt(&tiles, end, image_name, config.thumb_size)).map_err(|err| {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+            _ => Error::InvalidWriterConfiguration(format!(
+                "cannot write {}: {err}",
+                dest_vtt.display()
+            )),
+        }
+    })
+}
+
+/// Place the thumbnails in the sprite sheet, row by row.
+///
+/// # Arguments
+///
+/// * `times` - Timestamps of the thumbnails in seconds, in order.
+/// * `thumb_size` - Width and height of each thumbnail.
+/// * `columns` - Number of thumbnails per row.
+fn layout(times: impl Iterator<Item = f64>, thumb_size: (u32, u32), columns: u32) -> Vec<Tile> {
+    let (width, height) = thumb_size;
+    times
+        .enumerate()
+        .map(|(index, time)| Tile {
+            time,
+            x: (index as u32 % columns) * width,
+            y: (index as u32 / columns) * height,
+        })
+        .collect()
+}
+
+/// Tile the thumbnails into a sprite sheet. Regions without a thumbnail are black.
+///
+/// # Arguments
+///
+/// * `thumbnails` - Thumbnails (RGB24 frames of the thumbnail size).
+/// * `tiles` - Place of each thumbnail in the sheet.
+/// * `thumb_size` - Width and height of each thumbnail.
+/// * `columns` - Number of thumbnails per row.
+fn sprite_sheet(
+    thumbnails: &[(f64, RawFrame)],
+    tiles: &[Tile],
+    thumb_size: (u32, u32),
+    columns: u32,
+) -> RawFrame {
+    let (width, height) = thumb_size;
+    let num_columns = (thumbnails.len() as u32).min(columns);
+    let num_rows = (thumbnails.len() as u32).div_ceil(columns);
+    let mut sheet = RawFrame::new(AvPixel::RGB24, num_columns * width, num_rows * height);
+    sheet.data_mut(0).fill(0);
+
+    let sheet_stride = sheet.stride(0);
+    let row_size = width as usize * 3;
+    for ((_, thumbnail), tile) in thumbnails.iter().zip(tiles) {
+        let stride = thumbnail.stride(0);
+        let data = thumbnail.data(0);
+        for row in 0..height as usize {
+            let offset = (tile.y as usize + row) * sheet_stride + tile.x as usize * 3;
+            sheet.data_mut(0)[offset..offset + row_size]
+                .copy_from_slice(&data[row * stride..row * stride + row_size]);
+        }
+    }
+
+    sheet
+}
+
+/// Encode a frame to a single image file.
+///
+/// # Arguments
+///
+/// * `dest` - Path to write the image to.
+/// * `frame` - Frame to encode (RGB24).
+/// * `format` - Format of the image.
+fn write_image(dest: &Path, mut frame: RawFrame, format: ImageFormat) -> Result<()> {
+    let settings =
+        Settings::for_still_image(frame.width() as usize, frame.height() as usize, format);
+    // Write to exactly the given path, rather than a numbered sequence of images.
+    let mut options = Options::default();
+    options.set("update", "1");
+    let mut encoder = Encoder::new_with_format_and_options(
+        &dest.to_path_buf().into(),
+        settings,
+        "image2",
+        &options,
+    )?;
+    frame.set_pts(Some(0));
+    encoder.encode_raw(frame)?;
+    encoder.finish()
+}
+
+/// Write the WebVTT file of a storyboard.
+///
+/// # Arguments
+///
+/// * `tiles` - Thumbnails in the sprite sheet, in order.
+/// * `end` - End of the last cue in seconds.
+/// * `image_name` - File name of the sprite sheet.
+/// * `thumb_size` - Width and height of each thumbnail.
+fn vtt(tiles: &[Tile], end: f64, image_name: &str, thumb_size: (u32, u32)) -> String {
+    let (width, height) = thumb_size;
+    let mut vtt = "WEBVTT\n".to_string();
+    for (index, tile) in tiles.iter().enumerate() {
+        let cue_end = tiles
+            .get(index + 1)
+            .map_or(end.max(tile.time), |next| next.time);
+        vtt.push_str(&format!(
+            "\n{} --> {}\n{image_name}#xywh={},{},{width},{height}\n",
+            vtt_timestamp(tile.time),
+            vtt_timestamp(cue_end),
+            tile.x,
+            tile.y,
+        ));
+    }
+
+    vtt
+}
+
+/// Format a timestamp for WebVTT (as `HH:MM:SS.mmm`).
+///
+/// # Arguments
+///
+/// * `secs` - Timestamp in seconds.
+fn vtt_timestamp(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storyboard_vtt() {
+        assert_eq!(vtt_timestamp(0.0), "00:00:00.000");
+        assert_eq!(vtt_timestamp(3725.4567), "01:02:05.457");
+
+        // Five thumbnails in rows of two, of which the second was sampled late.
+        let tiles = layout([0.0, 1.2, 2.0, 3.0, 4.0].into_iter(), (16, 9), 2);
+        assert_eq!(
+            tiles[1],
+            Tile {
+                time: 1.2,
+                x: 16,
+                y: 0
+            }
+        );
+        assert_eq!(
+            tiles[4],
+            Tile {
+                time: 4.0,
+                x: 0,
+                y: 18
+            }
+        );
+        assert_eq!(
+            vtt(&tiles[..2], 1.5, "sheet.jpg", (16, 9)),
+            "WEBVTT\n\
+             \n00:00:00.000 --> 00:00:01.200\nsheet.jpg#xywh=0,0,16,9\n\
+             \n00:00:01.200 --> 00:00:01.500\nsheet.jpg#xywh=16,0,16,9\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_storyboard() {
+        use crate::testing::{self, TempPath};
+
+        let dir = TempPath::new("storyboard");
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = StoryboardConfig {
+            interval: Time::from_secs(1.0),
+            thumb_size: (32, 24),
+            columns: 3,
+        };
+
+        // 5 seconds (two rows, of which the last is partial) and half a second (a single
+        // thumbnail).
+        for (name, num_frames, num_thumbnails) in [("long", 150, 5), ("short", 15, 1)] {
+            let source: Locator = dir.join(format!("{name}.mkv")).into();
+            testing::write_test_video(&source, (0..num_frames).map(|_| testing::filled_frame(200)));
+
+            let image = dir.join(format!("{name}.png"));
+            let vtt_path = dir.join(format!("{name}.vtt"));
+            generate_storyboard(&source, &image, &vtt_path, config.clone()).unwrap();
+
+            let mut sheet = Decoder::new(&image.clone().into()).unwrap();
+            let (width, height) = sheet.size();
+            assert_eq!(width, 32 * num_thumbnails.min(3));
+            assert_eq!(height, 24 * num_thumbnails.div_ceil(3));
+            let frame = sheet.decode_raw().unwrap();
+            // The unfilled part of the last row is black, the thumbnails are not.
+            let last =
+                frame.data(0)[(height as usize - 1) * frame.stride(0) + width as usize * 3 - 1];
+            assert_eq!(last == 0, num_thumbnails == 5);
+            assert!(frame.data(0)[0] > 100);
+
+            let vtt = std::fs::read_to_string(&vtt_path).unwrap();
+            let cues = vtt.lines().filter(|line| line.contains(" --> ")).count();
+            assert_eq!(cues, num_thumbnails as usize);
+            assert!(vtt.starts_with("WEBVTT\n"));
+            assert!(vtt.contains(&format!("{name}.png#xywh=0,0,32,24")));
+        }
+
+        let source: Locator = dir.join("long.mkv").into();
+        assert!(matches!(
+            generate_storyboard(
+                &source,
+                &dir.join("sheet.gif"),
+                &dir.join("sheet.vtt"),
+                config
+            ),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+}
@@ -1,9 +1,22 @@
 extern crate ffmpeg_next as ffmpeg;
 
+use std::collections::{HashMap, HashSet};
+
 use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::ffi::{
+    AV_DISPOSITION_ATTACHED_PIC, AV_DISPOSITION_CAPTIONS, AV_DISPOSITION_CLEAN_EFFECTS,
+    AV_DISPOSITION_COMMENT, AV_DISPOSITION_DEFAULT, AV_DISPOSITION_DESCRIPTIONS,
+    AV_DISPOSITION_DUB, AV_DISPOSITION_FORCED, AV_DISPOSITION_HEARING_IMPAIRED,
+    AV_DISPOSITION_KARAOKE, AV_DISPOSITION_LYRICS, AV_DISPOSITION_METADATA,
+    AV_DISPOSITION_ORIGINAL, AV_DISPOSITION_VISUAL_IMPAIRED,
+};
+use ffmpeg::format::context::Input as AvInput;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::Dictionary as AvDictionary;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
-use crate::{io::Reader, Error};
+use crate::ffi::video_codec_parameters;
+use crate::{io::Reader, CodecId, Error};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -14,6 +27,8 @@ pub struct StreamInfo {
     pub index: usize,
     codec_parameters: AvCodecParameters,
     time_base: AvRational,
+    metadata: AvDictionary<'static>,
+    disposition: i32,
 }
 
 impl StreamInfo {
@@ -33,9 +48,90 @@ impl StreamInfo {
             index: stream_index,
             codec_parameters: stream.parameters(),
             time_base: stream.time_base(),
+            metadata: stream.metadata().to_owned(),
+            disposition: stream.disposition().bits(),
         })
     }
 
+    /// Media type of the stream.
+    pub(crate) fn medium(&self) -> AvMediaType {
+        self.codec_parameters.medium()
+    }
+
+    /// Name of the media type of the stream: `video`, `audio`, `subtitle`, `data`, `attachment`
+    /// or `unknown`.
+    pub fn media_type(&self) -> &'static str {
+        match self.medium() {
+            AvMediaType::Video => "video",
+            AvMediaType::Audio => "audio",
+            AvMediaType::Subtitle => "subtitle",
+            AvMediaType::Data => "data",
+            AvMediaType::Attachment => "attachment",
+            AvMediaType::Unknown => "unknown",
+        }
+    }
+
+    /// Name of the codec of the stream, like `h264` or `aac`.
+    pub fn codec_name(&self) -> &'static str {
+        self.codec_parameters.id().name()
+    }
+
+    /// Raw disposition flags of the stream, like the default and forced flags.
+    pub(crate) fn disposition(&self) -> i32 {
+        self.disposition
+    }
+
+    /// Disposition flags of the stream, like the default and forced flags.
+    pub fn disposition_flags(&self) -> Vec<DispositionFlag> {
+        DispositionFlag::from_bits(self.disposition)
+    }
+
+    /// Replace the raw disposition flags of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `disposition` - Raw disposition flags.
+    pub(crate) fn with_disposition(mut self, disposition: i32) -> Self {
+        self.disposition = disposition;
+        self
+    }
+
+    /// Replace the codec parameters and time base of the stream, for example after passing its
+    /// packets through a bitstream filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_parameters` - New codec parameters.
+    /// * `time_base` - New time base.
+    pub(crate) fn with_codec_parameters(
+        mut self,
+        codec_parameters: AvCodecParameters,
+        time_base: AvRational,
+    ) -> Self {
+        self.codec_parameters = codec_parameters;
+        self.time_base = time_base;
+        self
+    }
+
+    /// Stream metadata, like the language of the stream.
+    pub(crate) fn metadata(&self) -> &AvDictionary<'static> {
+        &self.metadata
+    }
+
+    /// Keep only the metadata entries with keys that a predicate accepts.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - Whether or not to keep the entry with a key.
+    pub(crate) fn retain_metadata(mut self, keep: impl Fn(&str) -> bool) -> Self {
+        let mut metadata = AvDictionary::new();
+        for (key, value) in self.metadata.iter().filter(|(key, _)| keep(key)) {
+            metadata.set(key, value);
+        }
+        self.metadata = metadata;
+        self
+    }
+
     /// Turn information back into parts for usage.
     ///
     /// Note: Consumes stream information object.
@@ -51,5 +147,614 @@ impl StreamInfo {
     }
 }
 
+/// Stream information serializes as a summary for inspection: the index, media type, codec name
+/// (like `h264`), time base, disposition flags and metadata of the stream. The codec parameters
+/// themselves are not serialized, so stream information cannot be deserialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for StreamInfo {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let metadata = self
+            .metadata
+            .iter()
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        let mut state = serializer.serialize_struct("StreamInfo", 6)?;
+        state.serialize_field("index", &self.index)?;
+        state.serialize_field("media_type", self.media_type())?;
+        state.serialize_field("codec", self.codec_name())?;
+        state.serialize_field(
+            "time_base",
+            &(self.time_base.numerator(), self.time_base.denominator()),
+        )?;
+        state.serialize_field("disposition", &self.disposition_flags())?;
+        state.serialize_field("metadata", &metadata)?;
+        state.end()
+    }
+}
+
+/// Selects which input streams end up in the output and in what order, like `-map` in ffmpeg.
+///
+/// Output streams are numbered in the order in which they are mapped: the streams selected by the
+/// first call to a `map_*` method come first (in input order if the call selects multiple
+/// streams), followed by the streams of the next call, and so on. If nothing is mapped explicitly,
+/// all video, audio and subtitle streams are mapped in input order.
+///
+/// Dispositions (like the default and forced flags) are carried over from the input streams unless
+/// they are overridden with [`StreamMap::with_disposition`].
+///
+/// # Example
+///
+/// ```ignore
+/// let stream_map = StreamMap::new()
+///     .map_video(0)
+///     .map_audio_by_language("eng")
+///     .skip_subtitles()
+///     .with_disposition(1, Disposition { default: true, forced: false });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamMap {
+    selectors: Vec<StreamSelector>,
+    skip_subtitles: bool,
+    dispositions: HashMap<usize, Disposition>,
+}
+
+impl StreamMap {
+    /// Create a new stream map that maps all video, audio and subtitle streams.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map the stream with the given index in the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream in the input.
+    pub fn map_stream(mut self, stream_index: usize) -> Self {
+        self.selectors.push(StreamSelector::Index(stream_index));
+        self
+    }
+
+    /// Map the `n`-th video stream of the input (counting from zero).
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Position of the stream among the video streams of the input.
+    pub fn map_video(mut self, n: usize) -> Self {
+        self.selectors
+            .push(StreamSelector::Nth(AvMediaType::Video, n));
+        self
+    }
+
+    /// Map the `n`-th audio stream of the input (counting from zero).
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Position of the stream among the audio streams of the input.
+    pub fn map_audio(mut self, n: usize) -> Self {
+        self.selectors
+            .push(StreamSelector::Nth(AvMediaType::Audio, n));
+        self
+    }
+
+    /// Map the `n`-th subtitle stream of the input (counting from zero).
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Position of the stream among the subtitle streams of the input.
+    pub fn map_subtitle(mut self, n: usize) -> Self {
+        self.selectors
+            .push(StreamSelector::Nth(AvMediaType::Subtitle, n));
+        self
+    }
+
+    /// Map the `n`-th data stream of the input (counting from zero), like a stream of KLV metadata.
+    /// Only some containers (like MPEG-TS) can hold data streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Position of the stream among the data streams of the input.
+    pub fn map_data(mut self, n: usize) -> Self {
+        self.selectors
+            .push(StreamSelector::Nth(AvMediaType::Data, n));
+        self
+    }
+
+    /// Map all audio streams of the input that are tagged with the given language.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - Language tag, usually an ISO 639-2 code like "eng".
+    pub fn map_audio_by_language(mut self, language: &str) -> Self {
+        self.selectors.push(StreamSelector::Language(
+            AvMediaType::Audio,
+            language.to_string(),
+        ));
+        self
+    }
+
+    /// Leave out all subtitle streams, even if they were mapped.
+    pub fn skip_subtitles(mut self) -> Self {
+        self.skip_subtitles = true;
+        self
+    }
+
+    /// Override the disposition of an output stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_stream_index` - Index of the stream in the output.
+    /// * `disposition` - Disposition to use.
+    pub fn with_disposition(
+        mut self,
+        output_stream_index: usize,
+        disposition: Disposition,
+    ) -> Self {
+        self.dispositions.insert(output_stream_index, disposition);
+        self
+    }
+
+    /// Resolve the map against an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Input format context.
+    ///
+    /// # Return value
+    ///
+    /// Mapped streams, in output order.
+    pub(crate) fn resolve(&self, input: &AvInput) -> Result<Vec<MappedStream>> {
+        let streams = input
+            .streams()
+            .map(|stream| StreamDescription {
+                medium: stream.parameters().medium(),
+                language: stream.metadata().get("language").map(str::to_string),
+                disposition: stream.disposition().bits(),
+            })
+            .collect::<Vec<_>>();
+        self.resolve_streams(&streams)
+    }
+
+    /// Resolve the map against the streams of an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `streams` - Descriptions of the input streams, by stream index.
+    fn resolve_streams(&self, streams: &[StreamDescription]) -> Result<Vec<MappedStream>> {
+        let indices_of = |medium: AvMediaType| {
+            streams
+                .iter()
+                .enumerate()
+                .filter(move |(_, stream)| stream.medium == medium)
+                .map(|(index, _)| index)
+        };
+
+        let mut indices = Vec::new();
+        if self.selectors.is_empty() {
+            indices.extend(
+                streams
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, stream)| {
+                        matches!(
+                            stream.medium,
+                            AvMediaType::Video | AvMediaType::Audio | AvMediaType::Subtitle
+                        )
+                    })
+                    .map(|(index, _)| index),
+            );
+        }
+        for selector in &self.selectors {
+            match selector {
+                StreamSelector::Index(index) => {
+                    if *index >= streams.len() {
+                        return Err(Error::InvalidStreamMap(format!(
+                            "input has no stream {index} (it has {} streams)",
+                            streams.len()
+                        )));
+                    }
+                    indices.push(*index);
+                }
+                StreamSelector::Nth(medium, n) => {
+                    let index = indices_of(*medium).nth(*n).ok_or_else(|| {
+                        Error::InvalidStreamMap(format!(
+                            "input has no {} stream {n} (it has {} {0} streams)",
+                            medium_name(*medium),
+                            indices_of(*medium).count()
+                        ))
+                    })?;
+                    indices.push(index);
+                }
+                StreamSelector::Language(medium, language) => {
+                    let matching = indices_of(*medium)
+                        .filter(|&index| streams[index].language.as_ref() == Some(language))
+                        .collect::<Vec<_>>();
+                    if matching.is_empty() {
+                        return Err(Error::InvalidStreamMap(format!(
+                            "input has no {} stream with language {language}",
+                            medium_name(*medium)
+                        )));
+                    }
+                    indices.extend(matching);
+                }
+            }
+        }
+
+        if self.skip_subtitles {
+            indices.retain(|&index| streams[index].medium != AvMediaType::Subtitle);
+        }
+        if indices.is_empty() {
+            return Err(Error::InvalidStreamMap("no streams are mapped".to_string()));
+        }
+        let mut seen = HashSet::new();
+        if let Some(index) = indices.iter().find(|&&index| !seen.insert(index)) {
+            return Err(Error::InvalidStreamMap(format!(
+                "stream {index} is mapped more than once"
+            )));
+        }
+
+        Ok(indices
+            .into_iter()
+            .enumerate()
+            .map(|(output_index, index)| MappedStream {
+                index,
+                disposition: match self.dispositions.get(&output_index) {
+                    Some(disposition) => disposition.apply(streams[index].disposition),
+                    None => streams[index].disposition,
+                },
+            })
+            .collect())
+    }
+}
+
+/// Codec parameters of a stream of packets that were encoded outside of ffmpeg, like by a hardware
+/// encoder (see [`crate::Encoder::add_preencoded_stream`]).
+///
+/// # Example
+///
+/// ```ignore
+/// let params = CodecParams::new_video(CodecId::H264, 1920, 1080).with_extradata(sps_and_pps);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecParams {
+    codec_id: CodecId,
+    width: u32,
+    height: u32,
+    extradata: Vec<u8>,
+}
+
+impl CodecParams {
+    /// Create the codec parameters of a video stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec of the packets, like [`CodecId::H264`].
+    /// * `width` - Width of the video.
+    /// * `height` - Height of the video.
+    pub fn new_video(codec_id: CodecId, width: u32, height: u32) -> Self {
+        Self {
+            codec_id,
+            width,
+            height,
+            extradata: Vec::new(),
+        }
+    }
+
+    /// Set the extradata of the codec, like the SPS and PPS of H.264, in Annex B or AVCC format.
+    /// Containers that need the parameter sets out of band (like MP4) require it. Without
+    /// extradata, the parameter sets must be in band, in the key frame packets (as in MPEG-TS).
+    ///
+    /// # Arguments
+    ///
+    /// * `extradata` - Extradata of the codec.
+    pub fn with_extradata(mut self, extradata: Vec<u8>) -> Self {
+        self.extradata = extradata;
+        self
+    }
+
+    /// Get the codec of the packets.
+    pub fn codec_id(&self) -> CodecId {
+        self.codec_id
+    }
+
+    /// Create the native codec parameters.
+    pub(crate) fn to_parameters(&self) -> Result<AvCodecParameters> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidOptions(format!(
+                "invalid video size: {}x{}",
+                self.width, self.height
+            )));
+        }
+
+        Ok(video_codec_parameters(
+            self.codec_id.into(),
+            self.width,
+            self.height,
+            &self.extradata,
+        )?)
+    }
+}
+
+/// Disposition flags of a stream that can be overridden in a [`StreamMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Disposition {
+    /// Whether or not the stream should be selected by default by players.
+    pub default: bool,
+    /// Whether or not the stream should always be displayed (for subtitles).
+    pub forced: bool,
+}
+
+impl Disposition {
+    /// Apply the flags to a raw disposition, keeping other flags intact.
+    ///
+    /// # Arguments
+    ///
+    /// * `disposition` - Raw disposition flags.
+    fn apply(&self, disposition: i32) -> i32 {
+        let set = |disposition: i32, flag: i32, enabled: bool| match enabled {
+            true => disposition | flag,
+            false => disposition & !flag,
+        };
+        set(
+            set(disposition, AV_DISPOSITION_DEFAULT, self.default),
+            AV_DISPOSITION_FORCED,
+            self.forced,
+        )
+    }
+}
+
+/// Disposition flag of an output stream (see [`crate::Encoder::set_stream_disposition`]). Players
+/// use these to pick the streams to play, for example the default audio track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DispositionFlag {
+    /// The stream should be selected by default by players.
+    Default,
+    /// The stream is a dub (a translation of the original).
+    Dub,
+    /// The stream is in the original language.
+    Original,
+    /// The stream holds commentary.
+    Comment,
+    /// The stream holds lyrics.
+    Lyrics,
+    /// The stream is a karaoke track.
+    Karaoke,
+    /// The stream should always be displayed (for subtitles).
+    Forced,
+    /// The stream is meant for the hearing impaired.
+    HearingImpaired,
+    /// The stream is meant for the visually impaired.
+    VisualImpaired,
+    /// The stream holds the audio without the dialogue.
+    CleanEffects,
+    /// The stream holds a single attached picture, like cover art.
+    AttachedPic,
+    /// The stream holds captions.
+    Captions,
+    /// The stream holds audio descriptions.
+    Descriptions,
+    /// The stream holds metadata, like timed text.
+    Metadata,
+}
+
+impl DispositionFlag {
+    /// All flags, in the order of their bits.
+    const ALL: [DispositionFlag; 14] = [
+        DispositionFlag::Default,
+        DispositionFlag::Dub,
+        DispositionFlag::Original,
+        DispositionFlag::Comment,
+        DispositionFlag::Lyrics,
+        DispositionFlag::Karaoke,
+        DispositionFlag::Forced,
+        DispositionFlag::HearingImpaired,
+        DispositionFlag::VisualImpaired,
+        DispositionFlag::CleanEffects,
+        DispositionFlag::AttachedPic,
+        DispositionFlag::Captions,
+        DispositionFlag::Descriptions,
+        DispositionFlag::Metadata,
+    ];
+
+    /// Get the raw disposition bit of the flag.
+    pub(crate) fn bits(&self) -> i32 {
+        match self {
+            DispositionFlag::Default => AV_DISPOSITION_DEFAULT,
+            DispositionFlag::Dub => AV_DISPOSITION_DUB,
+            DispositionFlag::Original => AV_DISPOSITION_ORIGINAL,
+            DispositionFlag::Comment => AV_DISPOSITION_COMMENT,
+            DispositionFlag::Lyrics => AV_DISPOSITION_LYRICS,
+            DispositionFlag::Karaoke => AV_DISPOSITION_KARAOKE,
+            DispositionFlag::Forced => AV_DISPOSITION_FORCED,
+            DispositionFlag::HearingImpaired => AV_DISPOSITION_HEARING_IMPAIRED,
+            DispositionFlag::VisualImpaired => AV_DISPOSITION_VISUAL_IMPAIRED,
+            DispositionFlag::CleanEffects => AV_DISPOSITION_CLEAN_EFFECTS,
+            DispositionFlag::AttachedPic => AV_DISPOSITION_ATTACHED_PIC,
+            DispositionFlag::Captions => AV_DISPOSITION_CAPTIONS,
+            DispositionFlag::Descriptions => AV_DISPOSITION_DESCRIPTIONS,
+            DispositionFlag::Metadata => AV_DISPOSITION_METADATA,
+        }
+    }
+
+    /// Combine flags into raw disposition flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - Flags to combine.
+    pub(crate) fn to_bits(flags: &[DispositionFlag]) -> i32 {
+        flags.iter().fold(0, |bits, flag| bits | flag.bits())
+    }
+
+    /// Split raw disposition flags into flags. Bits without a flag are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `bits` - Raw disposition flags.
+    pub(crate) fn from_bits(bits: i32) -> Vec<DispositionFlag> {
+        Self::ALL
+            .into_iter()
+            .filter(|flag| bits & flag.bits() != 0)
+            .collect()
+    }
+}
+
+/// Input stream that is mapped into the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MappedStream {
+    /// Index of the stream in the input.
+    pub(crate) index: usize,
+    /// Raw disposition flags to use in the output.
+    pub(crate) disposition: i32,
+}
+
+/// Way of selecting input streams in a [`StreamMap`].
+#[derive(Debug, Clone)]
+enum StreamSelector {
+    Index(usize),
+    Nth(AvMediaType, usize),
+    Language(AvMediaType, String),
+}
+
+/// Properties of an input stream that are used to resolve a [`StreamMap`].
+struct StreamDescription {
+    medium: AvMediaType,
+    language: Option<String>,
+    disposition: i32,
+}
+
+fn medium_name(medium: AvMediaType) -> &'static str {
+    match medium {
+        AvMediaType::Video => "video",
+        AvMediaType::Audio => "audio",
+        AvMediaType::Subtitle => "subtitle",
+        AvMediaType::Data => "data",
+        AvMediaType::Attachment => "attachment",
+        AvMediaType::Unknown => "unknown",
+    }
+}
+
 unsafe impl Send for StreamInfo {}
 unsafe impl Sync for StreamInfo {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One video stream, three audio streams and two subtitle streams.
+    fn test_streams() -> Vec<StreamDescription> {
+        let stream = |medium, language: Option<&str>, disposition| StreamDescription {
+            medium,
+            language: language.map(str::to_string),
+            disposition,
+        };
+        vec![
+            stream(AvMediaType::Video, None, AV_DISPOSITION_DEFAULT),
+            stream(AvMediaType::Audio, Some("deu"), AV_DISPOSITION_DEFAULT),
+            stream(AvMediaType::Audio, Some("eng"), 0),
+            stream(AvMediaType::Audio, Some("eng"), 0),
+            stream(AvMediaType::Subtitle, Some("eng"), AV_DISPOSITION_FORCED),
+            stream(AvMediaType::Subtitle, Some("deu"), 0),
+        ]
+    }
+
+    fn resolved_indices(stream_map: &StreamMap) -> Result<Vec<usize>> {
+        Ok(stream_map
+            .resolve_streams(&test_streams())?
+            .into_iter()
+            .map(|stream| stream.index)
+            .collect())
+    }
+
+    #[test]
+    fn test_stream_map_default() {
+        assert_eq!(
+            resolved_indices(&StreamMap::new()).unwrap(),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            resolved_indices(&StreamMap::new().skip_subtitles()).unwrap(),
+            vec![0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_stream_map_order() {
+        let stream_map = StreamMap::new()
+            .map_subtitle(1)
+            .map_audio_by_language("eng")
+            .map_video(0)
+            .map_stream(1);
+        assert_eq!(resolved_indices(&stream_map).unwrap(), vec![5, 2, 3, 0, 1]);
+        assert_eq!(
+            resolved_indices(&stream_map.skip_subtitles()).unwrap(),
+            vec![2, 3, 0, 1]
+        );
+    }
+
+    #[test]
+    fn test_stream_map_errors() {
+        let message = |stream_map: StreamMap| match resolved_indices(&stream_map) {
+            Err(Error::InvalidStreamMap(message)) => message,
+            result => panic!("unexpected result: {result:?}"),
+        };
+        assert_eq!(
+            message(StreamMap::new().map_video(1)),
+            "input has no video stream 1 (it has 1 video streams)"
+        );
+        assert_eq!(
+            message(StreamMap::new().map_audio_by_language("fra")),
+            "input has no audio stream with language fra"
+        );
+        assert_eq!(
+            message(StreamMap::new().map_stream(6)),
+            "input has no stream 6 (it has 6 streams)"
+        );
+        assert_eq!(
+            message(StreamMap::new().map_subtitle(0).skip_subtitles()),
+            "no streams are mapped"
+        );
+        assert_eq!(
+            message(StreamMap::new().map_audio(1).map_audio_by_language("eng")),
+            "stream 2 is mapped more than once"
+        );
+    }
+
+    #[test]
+    fn test_stream_map_dispositions() {
+        let stream_map = StreamMap::new()
+            .map_audio(1)
+            .map_subtitle(0)
+            .with_disposition(
+                0,
+                Disposition {
+                    default: true,
+                    forced: false,
+                },
+            );
+        let streams = stream_map.resolve_streams(&test_streams()).unwrap();
+        assert_eq!(streams[0].disposition, AV_DISPOSITION_DEFAULT);
+        // Dispositions of streams that are not overridden are carried over.
+        assert_eq!(streams[1].disposition, AV_DISPOSITION_FORCED);
+    }
+
+    #[test]
+    fn test_disposition_flag_bits() {
+        let flags = [DispositionFlag::Default, DispositionFlag::AttachedPic];
+        let bits = DispositionFlag::to_bits(&flags);
+        assert_eq!(bits, AV_DISPOSITION_DEFAULT | AV_DISPOSITION_ATTACHED_PIC);
+        assert_eq!(DispositionFlag::from_bits(bits), flags);
+        // Bits without a flag are left out.
+        assert_eq!(
+            DispositionFlag::from_bits(AV_DISPOSITION_FORCED | 1 << 30),
+            vec![DispositionFlag::Forced]
+        );
+        assert!(DispositionFlag::ALL
+            .iter()
+            .all(|flag| DispositionFlag::from_bits(flag.bits()) == vec![*flag]));
+    }
+}
@@ -0,0 +1,200 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::path::PathBuf;
+
+use ffmpeg::ffi::AVPixelFormat;
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+use crate::ffi::link_filters;
+use crate::io::Locator;
+use crate::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Subtitles to burn into the video frames (see [`crate::Transcoder::burn_subtitles`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubtitleSource {
+    /// The `n`-th subtitle stream of the source (counting from zero).
+    Stream(usize),
+    /// External subtitle file, like an `.srt` or `.ass` file. Cue times are relative to the start
+    /// of the source.
+    File(PathBuf),
+}
+
+impl SubtitleSource {
+    /// Get the arguments of the `subtitles` filter that renders the subtitles.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source of the video, which holds embedded subtitle streams.
+    fn filter_args(&self, source: &Locator) -> String {
+        match self {
+            SubtitleSource::Stream(n) => {
+                format!("filename={}:si={n}", quote(&source.unredacted()))
+            }
+            SubtitleSource::File(path) => {
+                format!("filename={}", quote(&path.to_string_lossy()))
+            }
+        }
+    }
+}
+
+/// Renders subtitle cues onto frames with the `subtitles` filter (which uses libass).
+///
+/// The filter renders the cues that are active at the timestamp of each frame: a cue from `start`
+/// to `end` is drawn on exactly the frames with `start <= pts < end`. Embedded subtitle streams
+/// are timed like the video stream, so frame timestamps are passed as is. External subtitle files
+/// are relative to the start of the source, so the start time of the source is subtracted first.
+pub(crate) struct SubtitleBurner {
+    args: String,
+    time_base: AvRational,
+    time_offset: i64,
+    /// Filter graph, along with the pixel format and size of the frames it was set up for. The
+    /// graph is set up when the first frame comes in.
+    graph: Option<(AvFilterGraph, AvPixel, (u32, u32))>,
+}
+
+impl SubtitleBurner {
+    /// Create a subtitle burner.
+    ///
+    /// # Arguments
+    ///
+    /// * `subtitles` - Subtitles to burn in.
+    /// * `source` - Source of the video.
+    /// * `time_base` - Time base of the frame timestamps.
+    /// * `start_time` - Start time of the source, in `time_base` units.
+    pub(crate) fn new(
+        subtitles: &SubtitleSource,
+        source: &Locator,
+        time_base: AvRational,
+        start_time: i64,
+    ) -> Result<Self> {
+        if ffmpeg::filter::find("subtitles").is_none() {
+            return Err(Error::SubtitleBurnInNotSupported);
+        }
+
+        Ok(Self {
+            args: subtitles.filter_args(source),
+            time_base,
+            time_offset: match subtitles {
+                SubtitleSource::Stream(_) => 0,
+                SubtitleSource::File(_) => start_time,
+            },
+            graph: None,
+        })
+    }
+
+    /// Render the active subtitle cues onto a frame. Frames without a timestamp are returned as
+    /// is.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to render onto.
+    pub(crate) fn burn(&mut self, mut frame: RawFrame) -> Result<RawFrame> {
+        let Some(pts) = frame.pts() else {
+            return Ok(frame);
+        };
+
+        let format = frame.format();
+        let size = (frame.width(), frame.height());
+        let graph = match self.graph {
+            Some((ref mut graph, graph_format, graph_size))
+                if graph_format == format && graph_size == size =>
+            {
+                graph
+            }
+            _ => {
+                let graph = Self::new_graph(&self.args, format, size, self.time_base)?;
+                &mut self.graph.insert((graph, format, size)).0
+            }
+        };
+
+        frame.set_pts(Some(pts - self.time_offset));
+        graph
+            .get("in")
+            .ok_or(ffmpeg::Error::FilterNotFound)?
+            .source()
+            .add(&frame)?;
+        let mut frame_out = RawFrame::empty();
+        graph
+            .get("out")
+            .ok_or(ffmpeg::Error::FilterNotFound)?
+            .sink()
+            .frame(&mut frame_out)?;
+        frame_out.set_pts(Some(pts));
+
+        Ok(frame_out)
+    }
+
+    /// Set up the filter graph: a buffer source, the `subtitles` filter and a buffer sink that
+    /// keeps the pixel format of the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Arguments of the `subtitles` filter.
+    /// * `format` - Pixel format of the frames.
+    /// * `size` - Size of the frames.
+    /// * `time_base` - Time base of the frame timestamps.
+    fn new_graph(
+        args: &str,
+        format: AvPixel,
+        (width, height): (u32, u32),
+        time_base: AvRational,
+    ) -> Result<AvFilterGraph> {
+        let filter = |name: &str| ffmpeg::filter::find(name).ok_or(ffmpeg::Error::FilterNotFound);
+
+        let mut graph = AvFilterGraph::new();
+        graph.add(
+            &filter("buffer")?,
+            "in",
+            &format!(
+                "video_size={width}x{height}:pix_fmt={}:time_base={}/{}:pixel_aspect=1/1",
+                AVPixelFormat::from(format) as i32,
+                time_base.numerator(),
+                time_base.denominator(),
+            ),
+        )?;
+        graph.add(&filter("subtitles")?, "subtitles", args)?;
+        graph
+            .add(&filter("buffersink")?, "out", "")?
+            .set_pixel_format(format);
+        link_filters(&mut graph, "in", "subtitles")?;
+        link_filters(&mut graph, "subtitles", "out")?;
+        graph.validate()?;
+
+        Ok(graph)
+    }
+}
+
+unsafe impl Send for SubtitleBurner {}
+unsafe impl Sync for SubtitleBurner {}
+
+/// Quote a filter option value, so that it is taken literally.
+///
+/// # Arguments
+///
+/// * `value` - Value to quote.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_args() {
+        let source = Locator::from(PathBuf::from("movie.mkv"));
+        assert_eq!(
+            SubtitleSource::Stream(1).filter_args(&source),
+            "filename='movie.mkv':si=1"
+        );
+        assert_eq!(
+            SubtitleSource::File(PathBuf::from("it's:here.srt")).filter_args(&source),
+            r"filename='it'\''s:here.srt'"
+        );
+    }
+}
@@ -0,0 +1,456 @@
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::hash::{hash_video_with_format, HashFormat};
+use crate::io::{Locator, Reader};
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What to compare in [`verify_sync_with_config`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncConfig {
+    /// Largest difference in timing between the two files that still counts as in sync. Defaults
+    /// to five milliseconds, which leaves room for the rounding of timestamps to the time base of
+    /// the container (like the milliseconds of Matroska).
+    pub tolerance: Duration,
+    /// Whether to also decode and hash every video frame of both files (see [`crate::hash_video`])
+    /// to find the first frame where they differ. This only makes sense for files that should
+    /// have identical frames, like a remux of the other, or the output of a lossless encode.
+    pub compare_frames: bool,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: Duration::from_millis(5),
+            compare_frames: false,
+        }
+    }
+}
+
+/// Timing of a stream: the smallest and the largest timestamp of its packets.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamTiming {
+    /// First (smallest) presentation timestamp.
+    pub first: Time,
+    /// Last (largest) presentation timestamp.
+    pub last: Time,
+}
+
+impl StreamTiming {
+    /// Get the time from the first to the last timestamp in seconds.
+    pub fn duration(&self) -> f64 {
+        self.last.as_secs_f64() - self.first.as_secs_f64()
+    }
+}
+
+/// Timing of the best video and audio stream of a file, if it has them.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileTiming {
+    pub video: Option<StreamTiming>,
+    pub audio: Option<StreamTiming>,
+}
+
+impl FileTiming {
+    /// Get the offset of audio relative to video at the start in seconds: how much later the
+    /// first audio timestamp is than the first video timestamp.
+    pub fn start_offset(&self) -> Option<f64> {
+        let (video, audio) = (self.video.as_ref()?, self.audio.as_ref()?);
+        Some(audio.first.as_secs_f64() - video.first.as_secs_f64())
+    }
+
+    /// Get the offset of audio relative to video at the end in seconds: how much later the last
+    /// audio timestamp is than the last video timestamp.
+    pub fn end_offset(&self) -> Option<f64> {
+        let (video, audio) = (self.video.as_ref()?, self.audio.as_ref()?);
+        Some(audio.last.as_secs_f64() - video.last.as_secs_f64())
+    }
+}
+
+/// First video frame that differs between two files (see [`SyncConfig::compare_frames`]).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameMismatch {
+    /// Index of the frame, counting from zero in decoding order.
+    pub index: usize,
+    /// Timestamp of the frame in the first file, or in the second file if the first file has
+    /// fewer frames.
+    pub time: Time,
+}
+
+/// Result of comparing the audio and video timing of two files with [`verify_sync`]. The
+/// differences are those of the second file relative to the first, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncReport {
+    /// Timing of the first file.
+    pub a: FileTiming,
+    /// Timing of the second file.
+    pub b: FileTiming,
+    /// Difference in the offset of audio relative to video at the start.
+    pub start_drift: Option<f64>,
+    /// Difference in the offset of audio relative to video at the end.
+    pub end_drift: Option<f64>,
+    /// Difference in the duration of the video streams.
+    pub video_duration_difference: Option<f64>,
+    /// Difference in the duration of the audio streams.
+    pub audio_duration_difference: Option<f64>,
+    /// First video frame that differs, if the frames were compared.
+    pub first_mismatch: Option<FrameMismatch>,
+    /// Whether both files have the same streams, all differences are within the tolerance and no
+    /// frames differ.
+    pub in_sync: bool,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let stream = |timing: &Option<StreamTiming>| match timing {
+            Some(timing) => format!("{} to {}", timing.first, timing.last),
+            None => "none".to_string(),
+        };
+        let seconds = |difference: Option<f64>| match difference {
+            Some(difference) => format!("{:+.3}s", difference),
+            None => "n/a".to_string(),
+        };
+
+        writeln!(
+            f,
+            "{}",
+            if self.in_sync {
+                "in sync"
+            } else {
+                "out of sync"
+            }
+        )?;
+        writeln!(
+            f,
+            "video: {} vs {} (duration {})",
+            stream(&self.a.video),
+            stream(&self.b.video),
+            seconds(self.video_duration_difference)
+        )?;
+        writeln!(
+            f,
+            "audio: {} vs {} (duration {})",
+            stream(&self.a.audio),
+            stream(&self.b.audio),
+            seconds(self.audio_duration_difference)
+        )?;
+        write!(
+            f,
+            "audio drift: {} at the start, {} at the end",
+            seconds(self.start_drift),
+            seconds(self.end_drift)
+        )?;
+        if let Some(mismatch) = &self.first_mismatch {
+            write!(
+                f,
+                "\nfirst mismatching frame: {} at {}",
+                mismatch.index, mismatch.time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the audio and video timing of two files, for example a source and its transcode, to
+/// check that audio and video did not drift apart: the offset of audio relative to video at the
+/// start and at the end, and the durations of the streams. The best video and audio stream of each
+/// file is compared. Only the packets are read, so this is fast.
+///
+/// # Arguments
+///
+/// * `a` - First file.
+/// * `b` - Second file.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = verify_sync(&source, &dest).unwrap();
+/// assert!(report.in_sync, "{report}");
+/// ```
+pub fn verify_sync(a: &Locator, b: &Locator) -> Result<SyncReport> {
+    verify_sync_with_config(a, b, &SyncConfig::default())
+}
+
+/// Compare the audio and video timing of two files like [`verify_sync`], with a custom tolerance,
+/// and optionally compare the video frames themselves.
+///
+/// # Arguments
+///
+/// * `a` - First file.
+/// * `b` - Second file.
+/// * `config` - What to compare.
+///
+/// # Example
+///
+/// ```ignore
+/// let config = SyncConfig {
+///     compare_frames: true,
+///     ..Default::default()
+/// };
+/// let report = verify_sync_with_config(&original, &remuxed, &config).unwrap();
+/// if let Some(mismatch) = report.first_mismatch {
+///     println!("frames differ from frame {} at {}", mismatch.index, mismatch.time);
+/// }
+/// ```
+pub fn verify_sync_with_config(
+    a: &Locator,
+    b: &Locator,
+    config: &SyncConfig,
+) -> Result<SyncReport> {
+    let timing = (file_timing(a)?, file_timing(b)?);
+    let first_mismatch = if config.compare_frames {
+        first_mismatch(
+            &hash_video_with_format(a, HashFormat::Rgb24)?,
+            &hash_video_with_format(b, HashFormat::Rgb24)?,
+            config.tolerance,
+        )
+    } else {
+        None
+    };
+
+    Ok(compare(
+        timing.0,
+        timing.1,
+        first_mismatch,
+        config.tolerance,
+    ))
+}
+
+/// Read the packets of a file to find the timing of its best video and audio stream.
+///
+/// # Arguments
+///
+/// * `source` - File to read.
+fn file_timing(source: &Locator) -> Result<FileTiming> {
+    let mut reader = Reader::new(source)?;
+    let video_index = reader.best_video_stream_index().ok();
+    let audio_index = reader.best_audio_stream_index().ok();
+
+    let mut timing = FileTiming {
+        video: None,
+        audio: None,
+    };
+    loop {
+        let (index, packet) = match reader.read_any() {
+            Ok(packet) => packet,
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        };
+        let stream = if Some(index) == video_index {
+            &mut timing.video
+        } else if Some(index) == audio_index {
+            &mut timing.audio
+        } else {
+            continue;
+        };
+        let timestamp = if packet.pts().has_value() {
+            packet.pts()
+        } else if packet.dts().has_value() {
+            packet.dts()
+        } else {
+            continue;
+        };
+
+        match stream {
+            Some(stream) => {
+                if timestamp.as_secs_f64() < stream.first.as_secs_f64() {
+                    stream.first = timestamp.clone();
+                }
+                if timestamp.as_secs_f64() > stream.last.as_secs_f64() {
+                    stream.last = timestamp;
+                }
+            }
+            None => {
+                *stream = Some(StreamTiming {
+                    first: timestamp.clone(),
+                    last: timestamp,
+                })
+            }
+        }
+    }
+
+    Ok(timing)
+}
+
+/// Find the first frame that differs between two videos: either the hashes differ, or the time
+/// since the first frame differs by more than the tolerance (a frame was dropped or duplicated).
+///
+/// # Arguments
+///
+/// * `a` - Timestamps and hashes of the frames of the first video.
+/// * `b` - Timestamps and hashes of the frames of the second video.
+/// * `tolerance` - Largest difference in timing that counts as the same.
+fn first_mismatch(
+    a: &[(Time, u64)],
+    b: &[(Time, u64)],
+    tolerance: Duration,
+) -> Option<FrameMismatch> {
+    let relative = |frames: &[(Time, u64)], index: usize| {
+        frames[index].0.as_secs_f64() - frames[0].0.as_secs_f64()
+    };
+
+    let index = (0..a.len().min(b.len()))
+        .find(|&index| {
+            a[index].1 != b[index].1
+                || (relative(a, index) - relative(b, index)).abs() > tolerance.as_secs_f64()
+        })
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))?;
+
+    Some(FrameMismatch {
+        index,
+        time: a.get(index).unwrap_or(&b[index]).0.clone(),
+    })
+}
+
+/// Compare the timing of two files.
+///
+/// # Arguments
+///
+/// * `a` - Timing of the first file.
+/// * `b` - Timing of the second file.
+/// * `first_mismatch` - First mismatching frame, if the frames were compared.
+/// * `tolerance` - Largest difference that counts as in sync.
+fn compare(
+    a: FileTiming,
+    b: FileTiming,
+    first_mismatch: Option<FrameMismatch>,
+    tolerance: Duration,
+) -> SyncReport {
+    let difference = |a: Option<f64>, b: Option<f64>| Some(b? - a?);
+    let duration = |timing: &Option<StreamTiming>| timing.as_ref().map(StreamTiming::duration);
+
+    let start_drift = difference(a.start_offset(), b.start_offset());
+    let end_drift = difference(a.end_offset(), b.end_offset());
+    let video_duration_difference = difference(duration(&a.video), duration(&b.video));
+    let audio_duration_difference = difference(duration(&a.audio), duration(&b.audio));
+    let same_streams =
+        a.video.is_some() == b.video.is_some() && a.audio.is_some() == b.audio.is_some();
+    let in_sync = same_streams
+        && first_mismatch.is_none()
+        && [
+            start_drift,
+            end_drift,
+            video_duration_difference,
+            audio_duration_difference,
+        ]
+        .into_iter()
+        .flatten()
+        .all(|difference| difference.abs() <= tolerance.as_secs_f64());
+
+    SyncReport {
+        a,
+        b,
+        start_drift,
+        end_drift,
+        video_duration_difference,
+        audio_duration_difference,
+        first_mismatch,
+        in_sync,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(video: (f64, f64), audio: Option<(f64, f64)>) -> FileTiming {
+        let stream = |(first, last)| StreamTiming {
+            first: Time::from_secs_f64(first),
+            last: Time::from_secs_f64(last),
+        };
+        FileTiming {
+            video: Some(stream(video)),
+            audio: audio.map(stream),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let tolerance = SyncConfig::default().tolerance;
+        let source = timing((1.4, 11.4), Some((1.6, 11.6)));
+
+        // The same timing, shifted as a whole.
+        let report = compare(
+            source.clone(),
+            timing((0.0, 10.0), Some((0.202, 10.2))),
+            None,
+            tolerance,
+        );
+        assert!(report.in_sync, "{report}");
+        assert!((report.start_drift.unwrap() - 0.002).abs() < 1e-6);
+
+        // Audio drifts by 40 ms over the duration.
+        let report = compare(
+            source.clone(),
+            timing((1.4, 11.4), Some((1.6, 11.64))),
+            None,
+            tolerance,
+        );
+        assert!(!report.in_sync);
+        assert!((report.end_drift.unwrap() - 0.04).abs() < 1e-6);
+        assert!((report.audio_duration_difference.unwrap() - 0.04).abs() < 1e-6);
+        assert_eq!(
+            report.to_string(),
+            "out of sync\n\
+             video: 00:00:01.400 to 00:00:11.400 vs 00:00:01.400 to 00:00:11.400 (duration +0.000s)\n\
+             audio: 00:00:01.600 to 00:00:11.600 vs 00:00:01.600 to 00:00:11.640 (duration +0.040s)\n\
+             audio drift: +0.000s at the start, +0.040s at the end"
+        );
+
+        // Audio went missing.
+        let report = compare(source, timing((1.4, 11.4), None), None, tolerance);
+        assert!(!report.in_sync);
+        assert_eq!(report.start_drift, None);
+    }
+
+    #[test]
+    fn test_first_mismatch() {
+        let frames = |hashes: &[u64], offset: f64| {
+            hashes
+                .iter()
+                .enumerate()
+                .map(|(i, &hash)| (Time::from_secs_f64(offset + i as f64 / 25.0), hash))
+                .collect::<Vec<_>>()
+        };
+        let tolerance = Duration::from_millis(5);
+
+        assert_eq!(
+            first_mismatch(
+                &frames(&[1, 2, 3], 0.0),
+                &frames(&[1, 2, 3], 1.0),
+                tolerance
+            ),
+            None
+        );
+        let mismatch = first_mismatch(
+            &frames(&[1, 2, 3], 0.0),
+            &frames(&[1, 5, 3], 0.0),
+            tolerance,
+        )
+        .unwrap();
+        assert_eq!(mismatch.index, 1);
+        assert!((mismatch.time.as_secs_f64() - 0.04).abs() < 1e-6);
+        assert_eq!(
+            first_mismatch(&frames(&[1, 2], 0.0), &frames(&[1, 2, 3], 0.0), tolerance)
+                .unwrap()
+                .index,
+            2
+        );
+
+        // A frame was dropped, so the next one comes a frame later.
+        let mut dropped = frames(&[1, 2, 3, 4], 0.0);
+        dropped.remove(2);
+        assert_eq!(
+            first_mismatch(&frames(&[1, 2, 4], 0.0), &dropped, tolerance)
+                .unwrap()
+                .index,
+            2
+        );
+    }
+}
@@ -0,0 +1,146 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use ffmpeg::format::context::{Input as AvInput, Output as AvOutput};
+use ffmpeg::Error as AvError;
+
+use crate::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What a tag (a metadata key and value) applies to.
+///
+/// Matroska (and WebM) stores tags in its `Tags` element, with the track as the target of stream
+/// tags. Other container formats store them as far as they can, like the metadata atoms of MP4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagTarget {
+    /// The file as a whole, like the title or the artist.
+    Global,
+    /// A single stream, by index, like the name of an audio track.
+    Stream(usize),
+}
+
+/// Set a tag of an output format context, replacing the value it has. This must be done before
+/// the header is written.
+///
+/// # Arguments
+///
+/// * `output` - Output format context.
+/// * `target` - What the tag applies to.
+/// * `key` - Key of the tag, like `ARTIST`.
+/// * `value` - Value of the tag.
+pub(crate) fn set_tag(
+    output: &mut AvOutput,
+    target: TagTarget,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    if key.is_empty() {
+        return Err(Error::InvalidOptions("tag key is empty".to_string()));
+    }
+
+    match target {
+        TagTarget::Global => {
+            let mut metadata = output.metadata().to_owned();
+            metadata.set(key, value);
+            output.set_metadata(metadata);
+        }
+        TagTarget::Stream(index) => {
+            let mut stream = output.stream_mut(index).ok_or(AvError::StreamNotFound)?;
+            let mut metadata = stream.metadata().to_owned();
+            metadata.set(key, value);
+            stream.set_metadata(metadata);
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the tags of an input format context.
+///
+/// # Arguments
+///
+/// * `input` - Input format context.
+/// * `target` - What to read the tags of.
+///
+/// # Return value
+///
+/// The key and value of each tag, in the order in which the container stores them.
+pub(crate) fn read_tags(input: &AvInput, target: TagTarget) -> Result<Vec<(String, String)>> {
+    let tags = |metadata: ffmpeg::DictionaryRef| {
+        metadata
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+
+    match target {
+        TagTarget::Global => Ok(tags(input.metadata())),
+        TagTarget::Stream(index) => Ok(tags(
+            input
+                .stream(index)
+                .ok_or(AvError::StreamNotFound)?
+                .metadata(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn test_tags_and_attachments_round_trip() {
+        let path = TempPath::new("tags.mkv");
+        let font = b"\x00\x01\x00\x00 not really a font".to_vec();
+
+        let mut encoder = Encoder::new(&path.locator(), testing::settings()).unwrap();
+        encoder
+            .set_tag(TagTarget::Global, "ARTIST", "Someone")
+            .unwrap();
+        encoder
+            .set_tag(TagTarget::Stream(0), "COMMENT", "Main camera")
+            .unwrap();
+        assert!(encoder
+            .set_tag(TagTarget::Stream(5), "COMMENT", "")
+            .is_err());
+        encoder
+            .add_attachment("font.ttf", "font/ttf", &font)
+            .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        assert!(matches!(
+            encoder.set_tag(TagTarget::Global, "ARTIST", "Someone else"),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        assert!(matches!(
+            encoder.add_attachment("font.ttf", "font/ttf", &font),
+            Err(Error::HeaderAlreadyWritten)
+        ));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        // Keys may come back in another case, depending on the container format.
+        let has_tag = |tags: &[(String, String)], key: &str, value: &str| {
+            tags.iter()
+                .any(|(k, v)| k.eq_ignore_ascii_case(key) && v == value)
+        };
+        let decoder = Decoder::new(&path.locator()).unwrap();
+        assert!(has_tag(
+            &decoder.tags(TagTarget::Global).unwrap(),
+            "ARTIST",
+            "Someone"
+        ));
+        assert!(has_tag(
+            &decoder.tags(TagTarget::Stream(0)).unwrap(),
+            "COMMENT",
+            "Main camera"
+        ));
+        let attachments = decoder.attachments();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].filename, "font.ttf");
+        assert_eq!(attachments[0].mime_type, "font/ttf");
+        assert_eq!(attachments[0].data, font);
+    }
+}
@@ -0,0 +1,160 @@
+//! Helpers for tests that encode small videos and read them back.
+
+extern crate ffmpeg_next as ffmpeg;
+
+use std::path::{Path, PathBuf};
+
+use ffmpeg::util::format::Pixel as AvPixel;
+
+use crate::encode::{Encoder, Settings};
+use crate::io::Locator;
+use crate::{RawFrame, Time};
+
+/// Width of the frames of test videos.
+pub(crate) const WIDTH: usize = 64;
+
+/// Height of the frames of test videos.
+pub(crate) const HEIGHT: usize = 48;
+
+/// Frame rate of test videos.
+pub(crate) const FRAME_RATE: usize = 30;
+
+/// Path of a file in the temporary directory, which is removed when the path is dropped (also when
+/// the test fails). The name includes the process ID, so that concurrent test runs do not clash.
+pub(crate) struct TempPath(PathBuf);
+
+impl TempPath {
+    /// Create a path for a file in the temporary directory, without creating the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the file, like `chapters.mkv`. The extension determines the container
+    ///   format of videos written to it.
+    pub(crate) fn new(name: &str) -> Self {
+        let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+        let name = format!("video-rs-{stem}-{}", std::process::id());
+        let name = if extension.is_empty() {
+            name
+        } else {
+            format!("{name}.{extension}")
+        };
+        Self(std::env::temp_dir().join(name))
+    }
+
+    /// Get the path as a locator.
+    pub(crate) fn locator(&self) -> Locator {
+        self.0.clone().into()
+    }
+}
+
+impl std::ops::Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            let _ = std::fs::remove_dir_all(&self.0);
+        } else {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+}
+
+/// Settings for H.264 test videos of [`WIDTH`] by [`HEIGHT`].
+pub(crate) fn settings() -> Settings<'static> {
+    Settings::for_h264_yuv420p(WIDTH, HEIGHT, false)
+}
+
+/// Create a black RGB24 frame of [`WIDTH`] by [`HEIGHT`].
+pub(crate) fn frame() -> RawFrame {
+    RawFrame::new(AvPixel::RGB24, WIDTH as u32, HEIGHT as u32)
+}
+
+/// Create an RGB24 frame of [`WIDTH`] by [`HEIGHT`] with all components set to a value.
+///
+/// # Arguments
+///
+/// * `value` - Value of every component.
+pub(crate) fn filled_frame(value: u8) -> RawFrame {
+    let mut frame = frame();
+    frame.data_mut(0).fill(value);
+    frame
+}
+
+/// Create black frames.
+///
+/// # Arguments
+///
+/// * `num_frames` - Number of frames.
+pub(crate) fn frames(num_frames: usize) -> impl Iterator<Item = RawFrame> {
+    (0..num_frames).map(|_| frame())
+}
+
+/// Get the timestamp of a frame at [`FRAME_RATE`], in the time base of an encoder.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to get the time base of.
+/// * `index` - Index of the frame.
+pub(crate) fn pts(encoder: &Encoder, index: usize) -> Option<i64> {
+    Time::from_nth_of_a_second(FRAME_RATE)
+        .aligned_with_rational(encoder.time_base())
+        .into_value()
+        .map(|frame_duration| frame_duration * index as i64)
+}
+
+/// Encode frames at [`FRAME_RATE`], following the frames the encoder has encoded so far.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to encode with.
+/// * `first` - Index of the first frame, to continue where an earlier call left off.
+/// * `frames` - Frames to encode.
+pub(crate) fn encode_frames(
+    encoder: &mut Encoder,
+    first: usize,
+    frames: impl IntoIterator<Item = RawFrame>,
+) {
+    for (index, mut frame) in frames.into_iter().enumerate() {
+        frame.set_pts(pts(encoder, first + index));
+        encoder.encode_raw(frame).unwrap();
+    }
+}
+
+/// Write a test video with H.264 at [`FRAME_RATE`].
+///
+/// # Arguments
+///
+/// * `dest` - Where to write the video to.
+/// * `frames` - Frames to encode.
+pub(crate) fn write_test_video(dest: &Locator, frames: impl IntoIterator<Item = RawFrame>) {
+    write_test_video_with_settings(dest, settings(), frames);
+}
+
+/// Write a test video at [`FRAME_RATE`] with the given encoder settings.
+///
+/// # Arguments
+///
+/// * `dest` - Where to write the video to.
+/// * `settings` - Encoder settings.
+/// * `frames` - Frames to encode.
+pub(crate) fn write_test_video_with_settings(
+    dest: &Locator,
+    settings: Settings,
+    frames: impl IntoIterator<Item = RawFrame>,
+) {
+    let mut encoder = Encoder::new(dest, settings).unwrap();
+    encode_frames(&mut encoder, 0, frames);
+    encoder.finish().unwrap();
+}
@@ -1,10 +1,21 @@
 extern crate ffmpeg_next as ffmpeg;
 
-use std::time::Duration;
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::time::{Duration, Instant, SystemTime};
 
+use ffmpeg::ffi::AV_NOPTS_VALUE;
 use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
 use ffmpeg::Rational as AvRational;
 
+use crate::Error;
+
+/// Time base of one nanosecond, the resolution of [`Duration`].
+const NANOSECONDS: AvRational = AvRational(1, 1_000_000_000);
+
+/// Time base of one millisecond.
+const MILLISECONDS: AvRational = AvRational(1, 1_000);
+
 /// Represents a time or duration.
 ///
 /// [`Time`] may represent a PTS (presentation timestamp), DTS (decoder timestamp) or a duration,
@@ -14,8 +25,14 @@ use ffmpeg::Rational as AvRational;
 /// `false`, and conversions to seconds will return `0.0`.
 ///
 /// A [`Time`] object may be aligned with another [`Time`] object, which produces an [`Aligned`]
-/// object, on which arithmetic operations can be performed.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// object, on which arithmetic operations can be performed. [`Time`] also implements [`Add`] and
+/// [`Sub`] directly. These are exact: times with the same time base are added as is, and times with
+/// different time bases are first converted to a time base that can represent both (if there is
+/// one that fits).
+///
+/// Times are compared by the point in time they represent, regardless of time base. A time without
+/// a value compares equal to other times without a value, and before all times with a value.
+#[derive(Debug, Clone)]
 pub struct Time {
     time: Option<i64>,
     time_base: AvRational,
@@ -26,10 +43,14 @@ impl Time {
     ///
     /// # Arguments
     ///
-    /// * `time` - Relative time in `time_base` units.
+    /// * `time` - Relative time in `time_base` units. The unset timestamp of ffmpeg
+    ///   (`AV_NOPTS_VALUE`) is the same as [`None`].
     /// * `time_base` - Time base of source.
     pub fn new(time: Option<i64>, time_base: AvRational) -> Time {
-        Self { time, time_base }
+        Self {
+            time: time.filter(|&time| time != AV_NOPTS_VALUE),
+            time_base,
+        }
     }
 
     /// Align the timestamp with a different time base.
@@ -80,6 +101,18 @@ impl Time {
         }
     }
 
+    /// Creates a new timestamp from a number of milliseconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `millis` - Number of milliseconds.
+    pub fn from_millis(millis: i64) -> Self {
+        Self {
+            time: Some(millis),
+            time_base: MILLISECONDS,
+        }
+    }
+
     /// Creates a new timestamp with `time` time units, each represents one / `base_den` seconds.
     ///
     /// # Arguments
@@ -139,13 +172,53 @@ impl Time {
     /// Get number of seconds as floating point value.
     pub fn as_secs_f64(&self) -> f64 {
         if let Some(time) = self.time {
-            (time as f64)
-                * (self.time_base.numerator() as f64 / self.time_base.denominator() as f64)
+            // Divide last, so that the result is rounded only once for the common time bases with
+            // a numerator of one.
+            (time as f64) * self.time_base.numerator() as f64 / self.time_base.denominator() as f64
         } else {
             0.0
         }
     }
 
+    /// Get the number of seconds as floating point value, or [`None`] if the time has no value or
+    /// is negative (like the timestamps of frames before the start of a stream).
+    pub fn as_secs_checked(&self) -> Option<f64> {
+        self.time
+            .filter(|&time| time >= 0)
+            .map(|_| self.as_secs_f64())
+    }
+
+    /// Get the number of milliseconds, rounded to the nearest millisecond.
+    ///
+    /// This is a lossy convenience: use the time value and time base (see [`Time::into_parts`])
+    /// for exact arithmetic. A time without a value is zero milliseconds, like it is zero seconds
+    /// for [`Time::as_secs`]. Use [`Time::as_millis_checked`] to tell those apart.
+    pub fn as_millis(&self) -> i64 {
+        let Some(time) = self.time else {
+            return 0;
+        };
+        let (num, den) = (
+            self.time_base.numerator() as i128,
+            self.time_base.denominator() as i128,
+        );
+        let scaled = time as i128 * num * 1000;
+        // Round half away from zero, like `av_rescale_q` does.
+        let millis = (scaled.abs() * 2 + den.abs()) / (den.abs() * 2);
+        if (scaled < 0) != (den < 0) {
+            -millis as i64
+        } else {
+            millis as i64
+        }
+    }
+
+    /// Get the number of milliseconds, rounded to the nearest millisecond, or [`None`] if the time
+    /// has no value or is negative.
+    pub fn as_millis_checked(&self) -> Option<u64> {
+        self.time
+            .filter(|&time| time >= 0)
+            .map(|_| self.as_millis() as u64)
+    }
+
     /// Convert to underlying parts: the `time` and `time_base`.
     pub fn into_parts(self) -> (Option<i64>, AvRational) {
         (self.time, self.time_base)
@@ -161,6 +234,54 @@ impl Time {
         self.time
     }
 
+    /// Anchor the timestamp to the wall-clock time at which the stream started, for example the
+    /// time at which a live recording began. The result is rounded to the nearest nanosecond.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_start` - Wall-clock time that corresponds to a timestamp of zero.
+    ///
+    /// # Return value
+    ///
+    /// The wall-clock time, or `None` if the timestamp has no value or the result is out of range.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let stream_start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    /// let (timestamp, _frame) = decoder.decode().unwrap();
+    /// println!("frame captured at {}", timestamp.to_datetime(stream_start).unwrap());
+    /// ```
+    #[cfg(feature = "chrono")]
+    pub fn to_datetime(
+        &self,
+        stream_start: chrono::DateTime<chrono::Utc>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        let nanos = i64::try_from(self.rounded(NANOSECONDS.denominator() as i128)?).ok()?;
+        stream_start.checked_add_signed(chrono::TimeDelta::nanoseconds(nanos))
+    }
+
+    /// Anchor the timestamp to the wall-clock time at which the stream started, like
+    /// [`Time::to_datetime`] but with the standard library clock. The result is rounded to the
+    /// nearest nanosecond.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_start` - Wall-clock time that corresponds to a timestamp of zero.
+    ///
+    /// # Return value
+    ///
+    /// The wall-clock time, or `None` if the timestamp has no value or the result is out of range.
+    pub fn to_system_time(&self, stream_start: SystemTime) -> Option<SystemTime> {
+        let nanos = self.rounded(NANOSECONDS.denominator() as i128)?;
+        let offset = Duration::from_nanos(u64::try_from(nanos.unsigned_abs()).ok()?);
+        if nanos < 0 {
+            stream_start.checked_sub(offset)
+        } else {
+            stream_start.checked_add(offset)
+        }
+    }
+
     /// Align the timestamp along another `time_base`.
     ///
     /// # Arguments
@@ -174,37 +295,227 @@ impl Time {
             time_base,
         }
     }
+
+    /// Align two timestamps for arithmetic. If the time bases differ, both timestamps are
+    /// converted to a time base that represents both exactly, or to the time base of `self` if
+    /// that does not fit.
+    ///
+    /// # Arguments
+    ///
+    /// * `rhs` - Right-hand side timestamp.
+    fn aligned_exactly_with(&self, rhs: &Time) -> Aligned {
+        if self.time_base == rhs.time_base {
+            return self.aligned_with(rhs);
+        }
+
+        match common_time_base(self.time_base, rhs.time_base) {
+            Some(time_base) => self.aligned_with_rational(time_base).aligned_with(rhs),
+            None => self.aligned_with(rhs),
+        }
+    }
+
+    /// Time value multiplied by the numerator of the time base, so that two times can be compared
+    /// by cross-multiplying with the denominators.
+    fn scaled(&self) -> Option<i128> {
+        self.time
+            .map(|time| time as i128 * self.time_base.numerator() as i128)
+    }
+
+    /// Number of `units_per_sec` units (like 1000 for milliseconds), rounded half away from zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `units_per_sec` - Number of units in a second.
+    fn rounded(&self, units_per_sec: i128) -> Option<i128> {
+        let den = self.time_base.denominator() as i128;
+        if den == 0 {
+            return None;
+        }
+        let units = self.scaled()? * units_per_sec * den.signum();
+        let den = den.abs();
+        Some((units.abs() + den / 2) / den * units.signum())
+    }
+}
+
+impl Add for Time {
+    type Output = Time;
+
+    /// Add two times. The result has no value if either of the times has no value.
+    fn add(self, rhs: Time) -> Time {
+        self.aligned_exactly_with(&rhs).add()
+    }
+}
+
+impl Sub for Time {
+    type Output = Time;
+
+    /// Subtract a time from another, which yields the duration between them. The result has no
+    /// value if either of the times has no value.
+    fn sub(self, rhs: Time) -> Time {
+        self.aligned_exactly_with(&rhs).subtract()
+    }
+}
+
+impl AddAssign for Time {
+    fn add_assign(&mut self, rhs: Time) {
+        *self = self.clone() + rhs;
+    }
+}
+
+impl SubAssign for Time {
+    fn sub_assign(&mut self, rhs: Time) {
+        *self = self.clone() - rhs;
+    }
+}
+
+impl PartialEq for Time {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Time {}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.scaled(), other.scaled()) {
+            (Some(lhs), Some(rhs)) => (lhs * other.time_base.denominator() as i128)
+                .cmp(&(rhs * self.time_base.denominator() as i128)),
+            (lhs, rhs) => lhs.is_some().cmp(&rhs.is_some()),
+        }
+    }
 }
 
 impl From<Duration> for Time {
-    /// Convert from a [`Duration`] to [`Time`].
+    /// Convert from a [`Duration`] to [`Time`]. The time is expressed in nanoseconds, so the
+    /// conversion is exact for durations up to `i64::MAX` nanoseconds (about 292 years). Longer
+    /// durations saturate to that.
     #[inline]
     fn from(duration: Duration) -> Self {
-        Time::from_secs_f64(duration.as_secs_f64())
+        Time {
+            time: Some(i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX)),
+            time_base: NANOSECONDS,
+        }
+    }
+}
+
+impl TryFrom<Time> for Duration {
+    type Error = Error;
+
+    /// Convert from a [`Time`] to a Rust-native [`Duration`], rounded to the nearest nanosecond.
+    /// This fails with [`Error::InvalidDuration`] if the time is negative or has no value.
+    fn try_from(timestamp: Time) -> Result<Self, Error> {
+        let nanos = timestamp
+            .rounded(NANOSECONDS.denominator() as i128)
+            .ok_or(Error::InvalidDuration)?;
+        let nanos = u64::try_from(nanos).map_err(|_| Error::InvalidDuration)?;
+        Ok(Duration::from_nanos(nanos))
+    }
+}
+
+/// Serialized form of [`Time`]: the time value and the time base as numerator and denominator.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TimeRepr {
+    time: Option<i64>,
+    time_base: (i32, i32),
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Time {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        TimeRepr {
+            time: self.time,
+            time_base: (self.time_base.numerator(), self.time_base.denominator()),
+        }
+        .serialize(serializer)
     }
 }
 
-impl From<Time> for Duration {
-    /// Convert from a [`Time`] to a Rust-native [`Duration`].
-    fn from(timestamp: Time) -> Self {
-        Duration::from_secs_f64(timestamp.as_secs_f64())
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Time {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let repr = TimeRepr::deserialize(deserializer)?;
+        if repr.time_base.1 == 0 {
+            return Err(serde::de::Error::custom("time base denominator is zero"));
+        }
+        Ok(Time::new(
+            repr.time,
+            AvRational::new(repr.time_base.0, repr.time_base.1),
+        ))
     }
 }
 
 impl std::fmt::Display for Time {
     /// Format [`Time`] as follows:
     ///
-    /// * If the inner value is not `None`: `time/time_base`.
+    /// * If the inner value is not `None`: `HH:MM:SS.mmm`, rounded to the nearest millisecond and
+    ///   preceded by `-` if the time is negative.
     /// * If the inner value is `None`: `none`.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        if let Some(time) = self.time {
-            let num = self.time_base.numerator() as i64 * time;
-            let den = self.time_base.denominator();
-            write!(f, "{num}/{den} secs")
+        match self.rounded(1000) {
+            Some(millis) => {
+                let sign = if millis < 0 { "-" } else { "" };
+                let millis = millis.abs();
+                write!(
+                    f,
+                    "{sign}{:02}:{:02}:{:02}.{:03}",
+                    millis / 3_600_000,
+                    millis / 60_000 % 60,
+                    millis / 1000 % 60,
+                    millis % 1000,
+                )
+            }
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// Find a time base in which times in both time bases can be expressed exactly.
+///
+/// # Arguments
+///
+/// * `lhs` - First time base.
+/// * `rhs` - Second time base.
+///
+/// # Return value
+///
+/// The common time base, or `None` if it does not fit in a rational of 32-bit integers.
+fn common_time_base(lhs: AvRational, rhs: AvRational) -> Option<AvRational> {
+    fn gcd(a: i64, b: i64) -> i64 {
+        if b == 0 {
+            a.abs()
         } else {
-            write!(f, "none")
+            gcd(b, a % b)
         }
     }
+
+    let (lhs, rhs) = (lhs.reduce(), rhs.reduce());
+    let (lhs_num, lhs_den) = (lhs.numerator() as i64, lhs.denominator() as i64);
+    let (rhs_num, rhs_den) = (rhs.numerator() as i64, rhs.denominator() as i64);
+    if lhs_num <= 0 || lhs_den <= 0 || rhs_num <= 0 || rhs_den <= 0 {
+        return None;
+    }
+
+    // The greatest common divisor of the numerators over the least common multiple of the
+    // denominators divides both time bases.
+    let num = gcd(lhs_num, rhs_num);
+    let den = lhs_den / gcd(lhs_den, rhs_den) * rhs_den;
+    Some(AvRational::new(
+        i32::try_from(num).ok()?,
+        i32::try_from(den).ok()?,
+    ))
 }
 
 /// This is a virtual object that represents two aligned times.
@@ -255,6 +566,114 @@ impl Aligned {
     }
 }
 
+/// Produces timestamps for live capture, like frames from a camera, so that they can be encoded
+/// with [`Encoder::encode`](crate::Encoder::encode).
+///
+/// Timestamps are derived from a monotonic clock that starts at the first timestamp. Without a
+/// frame rate, timestamps follow the clock with the precision of the time base. With a frame rate,
+/// timestamps are snapped to the nearest slot of an ideal fixed-rate grid, so that jitter in
+/// frame arrival does not end up in the output and the timestamps do not drift away from the
+/// clock. In both cases timestamps are strictly increasing: frames that arrive too soon get the
+/// next timestamp (or slot) after the previous one.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut timestamper = Timestamper::new(Some(30.0)).with_time_base(encoder.time_base());
+/// loop {
+///     let frame = camera.capture();
+///     encoder.encode(&frame, &timestamper.next()).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Timestamper {
+    frame_rate: Option<f64>,
+    time_base: AvRational,
+    start: Option<Instant>,
+    /// Previous timestamp in time base units, or previous grid slot if there is a frame rate.
+    last: Option<i64>,
+}
+
+impl Timestamper {
+    /// Create a timestamper. Timestamps have the ffmpeg global time base (microseconds) by
+    /// default, which is also the time base of the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate of the fixed-rate grid to snap timestamps to, or `None` to
+    ///   follow the clock.
+    pub fn new(frame_rate: Option<f64>) -> Self {
+        Self {
+            frame_rate: frame_rate.filter(|frame_rate| *frame_rate > 0.0),
+            time_base: TIME_BASE,
+            start: None,
+            last: None,
+        }
+    }
+
+    /// Set the time base of the timestamps, like the time base of the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base to use.
+    pub fn with_time_base(mut self, time_base: AvRational) -> Self {
+        self.time_base = time_base;
+        self
+    }
+
+    /// Produce the timestamp of a frame that arrives now. The first timestamp is zero.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Time {
+        self.next_at(Instant::now())
+    }
+
+    /// Start over, so that the next timestamp is zero again.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.last = None;
+    }
+
+    /// Produce the timestamp of a frame that arrives at the given instant.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Instant the frame arrived.
+    fn next_at(&mut self, now: Instant) -> Time {
+        let start = *self.start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+        let (num, den) = (
+            self.time_base.numerator() as i128,
+            self.time_base.denominator() as i128,
+        );
+
+        let value = match self.frame_rate {
+            Some(frame_rate) => {
+                let nearest_slot = (elapsed.as_secs_f64() * frame_rate).round() as i64;
+                let slot = match self.last {
+                    Some(last) => nearest_slot.max(last + 1),
+                    None => nearest_slot,
+                };
+                self.last = Some(slot);
+                // Computed from the slot number directly, so that rounding does not add up.
+                (slot as f64 * den as f64 / (num as f64 * frame_rate)).round() as i64
+            }
+            None => {
+                let nanos = elapsed.as_nanos() as i128;
+                // Round to the nearest unit of the time base.
+                let units = (2 * nanos * den + num * 1_000_000_000) / (2 * num * 1_000_000_000);
+                let value = match self.last {
+                    Some(last) => (units as i64).max(last + 1),
+                    None => units as i64,
+                };
+                self.last = Some(value);
+                value
+            }
+        };
+
+        Time::new(Some(value), self.time_base)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -276,13 +695,48 @@ mod tests {
         assert_eq!(time.into_value(), Some(6));
     }
 
+    #[test]
+    fn test_new_without_value() {
+        let time = Time::new(Some(AV_NOPTS_VALUE), AvRational::new(1, 90000));
+        assert!(!time.has_value());
+        assert_eq!(time.as_secs_f64(), 0.0);
+        assert_eq!(time.as_secs_checked(), None);
+        assert_eq!(time.as_millis(), 0);
+        assert_eq!(time.as_millis_checked(), None);
+    }
+
+    #[test]
+    fn test_millis() {
+        let time = Time::from_millis(1500);
+        assert_eq!(time.as_secs_f64(), 1.5);
+        assert_eq!(time.as_millis(), 1500);
+        assert_eq!(time.as_millis_checked(), Some(1500));
+
+        let time = Time::new(Some(1001), AvRational::new(1, 30000));
+        assert_eq!(time.as_millis(), 33);
+        assert_eq!(time.as_millis_checked(), Some(33));
+
+        let time = Time::new(Some(-3003), AvRational::new(1, 90000));
+        assert_eq!(time.as_millis(), -33);
+        assert_eq!(time.as_millis_checked(), None);
+        assert_eq!(time.as_secs_checked(), None);
+        assert_eq!(Time::new(Some(1), AvRational::new(1, 2000)).as_millis(), 1);
+        assert_eq!(
+            Time::new(Some(-1), AvRational::new(1, 2000)).as_millis(),
+            -1
+        );
+    }
+
     #[test]
     fn test_from_nth_of_a_second() {
         let time = Time::from_nth_of_a_second(4);
         assert!(time.has_value());
         assert_eq!(time.as_secs(), 0.25);
         assert_eq!(time.as_secs_f64(), 0.25);
-        assert_eq!(Duration::from(time), Duration::from_millis(250));
+        assert_eq!(
+            Duration::try_from(time).unwrap(),
+            Duration::from_millis(250)
+        );
     }
 
     #[test]
@@ -291,7 +745,10 @@ mod tests {
         assert!(time.has_value());
         assert_eq!(time.as_secs(), 2.5);
         assert_eq!(time.as_secs_f64(), 2.5);
-        assert_eq!(Duration::from(time), Duration::from_millis(2500));
+        assert_eq!(
+            Duration::try_from(time).unwrap(),
+            Duration::from_millis(2500)
+        );
     }
 
     #[test]
@@ -306,7 +763,10 @@ mod tests {
         let time = Time::from_units(3, 5);
         assert!(time.has_value());
         assert_eq!(time.as_secs(), 3.0 / 5.0);
-        assert_eq!(Duration::from(time), Duration::from_millis(600));
+        assert_eq!(
+            Duration::try_from(time).unwrap(),
+            Duration::from_millis(600)
+        );
     }
 
     #[test]
@@ -315,7 +775,7 @@ mod tests {
         assert!(time.has_value());
         assert_eq!(time.as_secs(), 0.0);
         assert_eq!(time.as_secs_f64(), 0.0);
-        assert_eq!(Duration::from(time), Duration::ZERO);
+        assert_eq!(Duration::try_from(time).unwrap(), Duration::ZERO);
         let time = Time::zero();
         assert_eq!(time.into_value(), Some(0));
     }
@@ -405,4 +865,300 @@ mod tests {
                 < 0.001
         );
     }
+
+    #[test]
+    fn test_add_sub_operators() {
+        let a = Time::new(Some(3), AvRational::new(1, 30));
+        let b = Time::new(Some(1), AvRational::new(1, 30));
+        assert_eq!(
+            (a.clone() + b.clone()).into_parts(),
+            (Some(4), AvRational::new(1, 30))
+        );
+        assert_eq!(
+            (a.clone() - b.clone()).into_parts(),
+            (Some(2), AvRational::new(1, 30))
+        );
+
+        // Frames at 30 and 25 frames per second meet in a time base of 1/150.
+        let c = Time::new(Some(1), AvRational::new(1, 25));
+        assert_eq!(
+            (a.clone() + c).into_parts(),
+            (Some(21), AvRational::new(1, 150))
+        );
+
+        let mut d = Time::zero();
+        d += a.clone();
+        d -= b;
+        assert_eq!(d, Time::from_nth_of_a_second(15));
+
+        assert!(!(a + Time::new(None, AvRational::new(1, 30))).has_value());
+    }
+
+    #[test]
+    fn test_add_does_not_drift() {
+        // Summing frame durations at 30000/1001 frames per second for an hour stays exact.
+        let frame_duration = Time::new(Some(1001), AvRational::new(1, 30000));
+        let mut time = Time::zero();
+        for _ in 0..(30 * 60 * 60) {
+            time += frame_duration.clone();
+        }
+        assert_eq!(
+            time,
+            Time::new(Some(1001 * 30 * 60 * 60), AvRational::new(1, 30000))
+        );
+    }
+
+    #[test]
+    fn test_add_properties() {
+        let time_bases = [
+            AvRational::new(1, 25),
+            AvRational::new(1, 30),
+            AvRational::new(1001, 30000),
+            AvRational::new(1, 90000),
+            AvRational::new(1, 48000),
+        ];
+        // Simple linear congruential generator, so that the test is deterministic.
+        let mut state = 0x2545_f491_u64;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            state >> 33
+        };
+        let mut random_time = || {
+            let time_base = time_bases[next() as usize % time_bases.len()];
+            Time::new(Some((next() % 1_000_000) as i64), time_base)
+        };
+
+        for _ in 0..1000 {
+            let (a, b, c) = (random_time(), random_time(), random_time());
+            assert_eq!(
+                (a.clone() + b.clone()) + c.clone(),
+                a.clone() + (b.clone() + c.clone())
+            );
+            assert_eq!(a.clone() + b.clone(), b.clone() + a.clone());
+            assert_eq!((a.clone() + b.clone()) - b.clone(), a);
+            assert_eq!(a.clone() - a.clone(), Time::zero());
+            assert!(a.clone() + b.clone() >= a);
+        }
+    }
+
+    #[test]
+    fn test_secs_f64_round_trip() {
+        for secs in [0.0, 0.001, 1.5, 59.999, 3600.25, 86400.0] {
+            assert_eq!(Time::from_secs_f64(secs).as_secs_f64(), secs);
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        let a = Time::new(Some(1), AvRational::new(1, 2));
+        let b = Time::new(Some(2), AvRational::new(1, 4));
+        let c = Time::new(Some(3), AvRational::new(1, 4));
+        let none = Time::new(None, AvRational::new(1, 4));
+        assert_eq!(a, b);
+        assert!(a < c);
+        assert!(c > b);
+        assert!(none < a);
+        assert_eq!(none, Time::new(None, AvRational::new(1, 90000)));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Time::zero().to_string(), "00:00:00.000");
+        assert_eq!(Time::from_secs_f64(3723.456).to_string(), "01:02:03.456");
+        assert_eq!(
+            Time::new(Some(1001), AvRational::new(1, 30000)).to_string(),
+            "00:00:00.033"
+        );
+        assert_eq!(Time::from_secs_f64(-1.5).to_string(), "-00:00:01.500");
+        assert_eq!(Time::from_secs_f64(360000.0).to_string(), "100:00:00.000");
+        assert_eq!(Time::new(None, AvRational::new(1, 30)).to_string(), "none");
+    }
+
+    #[test]
+    fn test_duration_round_trip() {
+        for time_base in [AvRational::new(1, 90000), AvRational::new(1, 1000000)] {
+            for time in [0, 1, 2999, 90001, 123_456_789, 8_100_000_000] {
+                let timestamp = Time::new(Some(time), time_base);
+                let duration = Duration::try_from(timestamp.clone()).unwrap();
+                let round_trip = Time::from(duration);
+                let error = (round_trip - timestamp).aligned_with_rational(NANOSECONDS);
+                assert!(error.into_value().unwrap().abs() <= 1);
+            }
+        }
+
+        let duration = Duration::new(12345, 678_901_234);
+        assert_eq!(Duration::try_from(Time::from(duration)).unwrap(), duration);
+
+        assert_eq!(Time::from(Duration::MAX).into_value(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_duration_try_from_invalid() {
+        assert!(matches!(
+            Duration::try_from(Time::from_secs_f64(-0.5)),
+            Err(Error::InvalidDuration)
+        ));
+        assert!(matches!(
+            Duration::try_from(Time::new(None, AvRational::new(1, 90000))),
+            Err(Error::InvalidDuration)
+        ));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_to_datetime() {
+        use chrono::{TimeZone, Utc};
+
+        let stream_start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let time = Time::new(Some(90000 * 90 + 45), AvRational::new(1, 90000));
+        assert_eq!(
+            time.to_datetime(stream_start),
+            Some(stream_start + chrono::TimeDelta::nanoseconds(90_000_500_000))
+        );
+        assert_eq!(
+            Time::from_secs_f64(-1.0).to_datetime(stream_start),
+            Some(Utc.with_ymd_and_hms(2024, 5, 1, 11, 59, 59).unwrap())
+        );
+        assert_eq!(
+            Time::new(None, AvRational::new(1, 90000)).to_datetime(stream_start),
+            None
+        );
+    }
+
+    #[test]
+    fn test_to_system_time() {
+        let stream_start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_564_800);
+        let time = Time::new(Some(90000 * 90 + 45), AvRational::new(1, 90000));
+        assert_eq!(
+            time.to_system_time(stream_start),
+            Some(stream_start + Duration::from_nanos(90_000_500_000))
+        );
+        assert_eq!(
+            Time::from_secs_f64(-1.0).to_system_time(stream_start),
+            Some(stream_start - Duration::from_secs(1))
+        );
+        assert_eq!(
+            Time::new(None, AvRational::new(1, 90000)).to_system_time(stream_start),
+            None
+        );
+    }
+
+    /// Simulate frames arriving at the given rate with jitter of up to 40% of the frame interval,
+    /// and with bursts of frames that arrive at the same time every now and then.
+    fn jittery_arrivals(frame_rate: f64, count: usize) -> Vec<Duration> {
+        let mut state: u64 = 0x853c_49e6_748f_ea9b;
+        let mut random = move || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+
+        let interval = 1.0 / frame_rate;
+        let mut arrivals: Vec<Duration> = (0..count)
+            .map(|i| {
+                let jitter = (random() - 0.5) * 0.8 * interval;
+                Duration::from_secs_f64((i as f64 * interval + jitter).max(0.0))
+            })
+            .collect();
+        for i in (100..count).step_by(250) {
+            // Three frames that were held up arrive together.
+            arrivals[i - 2] = arrivals[i];
+            arrivals[i - 1] = arrivals[i];
+        }
+        // Arrivals are monotonic, since they come from a monotonic clock.
+        for i in 1..count {
+            arrivals[i] = arrivals[i].max(arrivals[i - 1]);
+        }
+        arrivals
+    }
+
+    #[test]
+    fn test_timestamper_grid_with_jitter() {
+        let frame_rate = 30000.0 / 1001.0;
+        let start = Instant::now();
+        let mut timestamper = Timestamper::new(Some(frame_rate));
+
+        let arrivals = jittery_arrivals(frame_rate, 10_000);
+        let timestamps: Vec<Time> = arrivals
+            .iter()
+            .map(|arrival| timestamper.next_at(start + *arrival))
+            .collect();
+
+        assert_eq!(timestamps[0], Time::zero());
+        for (previous, timestamp) in timestamps.iter().zip(&timestamps[1..]) {
+            assert!(timestamp > previous);
+        }
+        let interval = 1.0 / frame_rate;
+        for (arrival, timestamp) in arrivals.iter().zip(&timestamps) {
+            // Each timestamp is a grid slot.
+            let slot = timestamp.as_secs_f64() / interval;
+            assert!((slot - slot.round()).abs() < 0.01);
+            // Timestamps stay within a few frames of the clock, even after bursts.
+            assert!((timestamp.as_secs_f64() - arrival.as_secs_f64()).abs() < 3.0 * interval);
+        }
+        // There is no drift over time: the last frame gets (about) its slot on the grid.
+        let last_slot = (timestamps.last().unwrap().as_secs_f64() / interval).round();
+        assert!(
+            (last_slot - (arrivals.len() - 1) as f64).abs() <= 2.0,
+            "{last_slot}"
+        );
+    }
+
+    #[test]
+    fn test_timestamper_wall_clock() {
+        let start = Instant::now();
+        let mut timestamper = Timestamper::new(None).with_time_base(AvRational::new(1, 90_000));
+
+        let arrivals = jittery_arrivals(60.0, 1_000);
+        let timestamps: Vec<Time> = arrivals
+            .iter()
+            .map(|arrival| timestamper.next_at(start + *arrival))
+            .collect();
+
+        for (previous, timestamp) in timestamps.iter().zip(&timestamps[1..]) {
+            assert!(timestamp > previous);
+        }
+        for (arrival, timestamp) in arrivals.iter().zip(&timestamps) {
+            // Frames in a burst are one time base unit apart.
+            let error = (timestamp.as_secs_f64() - arrival.as_secs_f64()).abs();
+            assert!(error <= 3.0 / 90_000.0);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_time_serde_round_trip() {
+        let time = Time::new(Some(3003), AvRational::new(1, 30000));
+        let json = serde_json::to_string(&time).unwrap();
+        assert_eq!(json, r#"{"time":3003,"time_base":[1,30000]}"#);
+        let restored: Time = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored.into_parts(),
+            (Some(3003), AvRational::new(1, 30000))
+        );
+
+        let restored: Time =
+            serde_json::from_str(r#"{"time":null,"time_base":[1,90000]}"#).unwrap();
+        assert!(!restored.has_value());
+        assert!(serde_json::from_str::<Time>(r#"{"time":1,"time_base":[1,0]}"#).is_err());
+    }
+
+    #[test]
+    fn test_timestamper_reset() {
+        let start = Instant::now();
+        let mut timestamper = Timestamper::new(Some(25.0));
+        assert_eq!(timestamper.next_at(start), Time::zero());
+        assert_eq!(
+            timestamper.next_at(start + Duration::from_millis(40)),
+            Time::from_secs_f64(0.04)
+        );
+        timestamper.reset();
+        assert_eq!(
+            timestamper.next_at(start + Duration::from_secs(1)),
+            Time::zero()
+        );
+    }
 }
@@ -0,0 +1,1748 @@
+extern crate ffmpeg_next as ffmpeg;
+
+use std::collections::HashMap;
+
+use ffmpeg::codec::Parameters as AvParameters;
+use ffmpeg::ffi::AV_NOPTS_VALUE;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
+use ffmpeg::Rational as AvRational;
+
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::framerate::FrameRateConverter;
+use crate::io::Reader;
+use crate::loudness::{measure_stream_loudness, normalization_gain, LoudnessNormalizer};
+use crate::options::Options;
+use crate::stream::{MappedStream, StreamInfo};
+use crate::subtitle::{SubtitleBurner, SubtitleSource};
+use crate::{
+    CancellationToken, Error, Locator, MovFlag, Packet, RawFrame, Resize, StreamMap, TagTarget,
+    Time, TimestampPolicy,
+};
+
+use crate::ffi::input_start_time;
+
+#[cfg(feature = "ndarray")]
+use crate::{ffi::convert_frame_to_ndarray, Frame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Re-encodes a video with new encoder settings, optionally modifying frames along the way.
+///
+/// Frames are resized to the size in the encoder settings. The audio streams in the source are
+/// copied into the destination without re-encoding (along with their metadata, like the language),
+/// unless audio copying is disabled. So are data streams (like KLV metadata), if the destination
+/// container supports them. Copied packets keep their timestamps, so the destination has the same
+/// audio and video synchronization as the source.
+///
+/// The metadata of the source (like the title, the creation time and the language of each
+/// stream) is copied into the destination, unless metadata is stripped (see
+/// [`Transcoder::preserve_metadata`]).
+///
+/// Audio and data streams that appear in the source after it was opened (like in live MPEG-TS
+/// sources, see [`crate::Reader::take_new_streams`]) are copied too, if they appear before the
+/// header of the destination is written, which happens when the first packet is written. Streams
+/// that appear after that are left out with a warning, since the destination container cannot
+/// take new streams anymore.
+///
+/// Errors are wrapped in [`Error::DecodeFailed`] or [`Error::EncodeFailed`], depending on which
+/// side they came from.
+///
+/// # Example
+///
+/// ```ignore
+/// Transcoder::new(
+///     &PathBuf::from("input.mkv").into(),
+///     &PathBuf::from("output.mp4").into(),
+///     EncoderSettings::for_h264_yuv420p(1280, 720, false),
+/// )
+/// .unwrap()
+/// .with_progress(|percentage| println!("{percentage:.1}%"))
+/// .run()
+/// .unwrap();
+/// ```
+pub struct Transcoder {
+    source: Locator,
+    decoder: Decoder<'static>,
+    output: TranscodeOutput,
+    copy_audio: bool,
+    copy_data: Option<bool>,
+    stream_map: Option<StreamMap>,
+    subtitles: Option<SubtitleSource>,
+    output_frame_rate: Option<f64>,
+    data_packet_hook: Option<DataPacketHookFn>,
+    metadata: MetadataFilter,
+    loudness_target: Option<f64>,
+}
+
+impl Transcoder {
+    /// Create a new transcoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Locator to read from.
+    /// * `dest` - Locator to write to.
+    /// * `settings` - Encoder settings to use.
+    pub fn new(source: &Locator, dest: &Locator, settings: Settings) -> Result<Self> {
+        let (width, height) = settings.size();
+        let decoder = Decoder::new_with_options_and_resize(
+            source,
+            &Options::default(),
+            Resize::Exact(width, height),
+        )
+        .map_err(decode_error)?;
+        let encoder = Encoder::new(dest, settings)
+            .map_err(encode_error)?
+            .interleaved();
+
+        Ok(Self {
+            source: source.clone(),
+            decoder,
+            output: TranscodeOutput {
+                encoder,
+                frame_hook: None,
+                subtitle_burner: None,
+                frame_rate_converter: None,
+                progress: None,
+                source_start: 0.0,
+                source_duration: None,
+            },
+            copy_audio: true,
+            copy_data: None,
+            stream_map: None,
+            subtitles: None,
+            output_frame_rate: None,
+            data_packet_hook: None,
+            metadata: MetadataFilter {
+                preserve: true,
+                strip_keys: Vec::new(),
+            },
+            loudness_target: None,
+        })
+    }
+
+    /// Set a callback that receives the progress as a percentage from 0 to 100. The progress is
+    /// based on the source duration, so the callback is never invoked if the source duration is
+    /// not known (for example for live streams).
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Progress callback.
+    pub fn with_progress(mut self, callback: impl FnMut(f32) + Send + 'static) -> Self {
+        self.output.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Set a hook that can modify each frame before it is encoded, along with its timestamp
+    /// (relative to the source stream). Frames are in `HWC` format.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Frame hook.
+    #[cfg(feature = "ndarray")]
+    pub fn with_frame_hook(mut self, hook: impl FnMut(&mut Frame, Time) + Send + 'static) -> Self {
+        self.output.frame_hook = Some(FrameHook::Frame(Box::new(hook)));
+        self
+    }
+
+    /// Set a hook that can modify each raw frame before it is encoded, along with its timestamp
+    /// (relative to the source stream). Frames are in RGB24 format. This is the raw variant of
+    /// `with_frame_hook`.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Frame hook.
+    pub fn with_raw_frame_hook(
+        mut self,
+        hook: impl FnMut(&mut RawFrame, Time) + Send + 'static,
+    ) -> Self {
+        self.output.frame_hook = Some(FrameHook::Raw(Box::new(hook)));
+        self
+    }
+
+    /// Enable or disable copying the audio streams of the source into the destination. This is
+    /// enabled by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether or not to copy audio.
+    pub fn copy_audio(mut self, enabled: bool) -> Self {
+        self.copy_audio = enabled;
+        self
+    }
+
+    /// Enable or disable copying the data streams of the source (like KLV metadata) into the
+    /// destination, packet for packet. Data packets are interleaved with the re-encoded video by
+    /// their timestamps.
+    ///
+    /// By default, data streams are copied if the destination container supports them, and left
+    /// out otherwise. When copying is enabled explicitly, running the transcoder fails with
+    /// [`Error::DataStreamsNotSupported`] if the container does not support them (like MP4). Use
+    /// MPEG-TS to keep KLV metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether or not to copy data streams.
+    pub fn copy_data(mut self, enabled: bool) -> Self {
+        self.copy_data = Some(enabled);
+        self
+    }
+
+    /// Set a hook that receives each packet of the data streams of the source (like KLV
+    /// metadata) along with the index of its stream, while the source is transcoded. This allows
+    /// parsing the metadata without reading the source a second time. The hook receives the
+    /// packets whether or not they are copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Data packet hook.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// transcoder
+    ///     .with_data_packet_hook(|stream_index, packet| {
+    ///         println!("{} bytes of metadata at {}", packet.data().len(), packet.pts());
+    ///     })
+    ///     .run()
+    ///     .unwrap();
+    /// ```
+    pub fn with_data_packet_hook(
+        mut self,
+        hook: impl FnMut(usize, &Packet) + Send + 'static,
+    ) -> Self {
+        self.data_packet_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Select the streams to copy into the destination with a stream map, instead of copying all
+    /// audio and data streams. This overrides [`Transcoder::copy_audio`] and
+    /// [`Transcoder::copy_data`].
+    ///
+    /// The re-encoded video stream is always output stream 0, followed by the other mapped
+    /// streams in map order (see [`StreamMap`]). The only video stream that can be mapped is the
+    /// one that is re-encoded, which determines its disposition.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_map` - Stream map to use.
+    pub fn with_stream_map(mut self, stream_map: StreamMap) -> Self {
+        self.stream_map = Some(stream_map);
+        self
+    }
+
+    /// Burn subtitles into the video ("hardcoded" subtitles), by rendering each cue onto the
+    /// frames in its time range before they are encoded. Cues are drawn on exactly the frames with
+    /// a timestamp from the start of the cue up to (not including) its end. Frames are passed to
+    /// the frame hook with the subtitles already on them.
+    ///
+    /// Subtitles are rendered with libass through the `subtitles` filter, with the default font
+    /// (and the styles of `.ass` files). Running the transcoder fails with
+    /// [`Error::SubtitleBurnInNotSupported`] if ffmpeg was built without it. Embedded subtitle
+    /// streams are read from the source a second time, so these require a source that can be
+    /// opened twice (like a file).
+    ///
+    /// # Arguments
+    ///
+    /// * `subtitles` - Subtitles to burn in.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Transcoder::new(
+    ///     &PathBuf::from("input.mkv").into(),
+    ///     &PathBuf::from("output.mp4").into(),
+    ///     EncoderSettings::for_h264_yuv420p(1280, 720, false),
+    /// )
+    /// .unwrap()
+    /// .burn_subtitles(SubtitleSource::File(PathBuf::from("input.en.srt")))
+    /// .run()
+    /// .unwrap();
+    /// ```
+    pub fn burn_subtitles(mut self, subtitles: SubtitleSource) -> Self {
+        self.subtitles = Some(subtitles);
+        self
+    }
+
+    /// Convert the video to a constant frame rate, by duplicating and dropping frames (like the
+    /// `fps` filter). Each output frame gets the source frame with the timestamp closest to it.
+    /// Output timestamps are computed from the source timestamps rather than by counting frames,
+    /// so variable frame rate sources do not drift from the copied audio. The output has as many
+    /// frames as fit in the duration of the source at the new frame rate.
+    ///
+    /// Frames are converted before they are passed to the frame hook, so the hook receives the
+    /// output frames (duplicated frames more than once).
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frames per second of the output. Running the transcoder fails with
+    ///   [`Error::InvalidOptions`] if it is not positive.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Transcoder::new(
+    ///     &PathBuf::from("input_24fps.mkv").into(),
+    ///     &PathBuf::from("output_30fps.mp4").into(),
+    ///     EncoderSettings::for_h264_yuv420p(1280, 720, false),
+    /// )
+    /// .unwrap()
+    /// .with_output_frame_rate(30.0)
+    /// .run()
+    /// .unwrap();
+    /// ```
+    pub fn with_output_frame_rate(mut self, frame_rate: f64) -> Self {
+        self.output_frame_rate = Some(frame_rate);
+        self
+    }
+
+    /// Normalize the loudness of the audio streams to a target (EBU R128), instead of copying
+    /// them. This takes two passes: each audio stream is first decoded in full to measure its
+    /// integrated loudness (see [`crate::measure_loudness`]), after which it is re-encoded along
+    /// with the video, with the gain that brings it to the target. A limiter keeps the gain from
+    /// making peaks clip, by limiting them to -1 dBFS. Streams that are silent are re-encoded
+    /// without gain.
+    ///
+    /// The audio is re-encoded with the codec of the source if the destination container
+    /// supports it, or with the default audio codec of the container otherwise. The source is
+    /// read a second time for the measurement, so it must be possible to open it twice (like a
+    /// file). Audio streams that appear in the source later on are copied as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_lufs` - Target integrated loudness in LUFS, like -23 for EBU R128.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Transcoder::new(
+    ///     &PathBuf::from("input.mkv").into(),
+    ///     &PathBuf::from("output.mkv").into(),
+    ///     EncoderSettings::for_h264_yuv420p(1280, 720, false),
+    /// )
+    /// .unwrap()
+    /// .normalize_loudness(-23.0)
+    /// .run()
+    /// .unwrap();
+    /// ```
+    pub fn normalize_loudness(mut self, target_lufs: f64) -> Self {
+        self.loudness_target = Some(target_lufs);
+        self
+    }
+
+    /// Set what to do with frames of the source whose timestamps are missing or go backwards,
+    /// which would make the muxer fail (see [`Decoder::with_timestamp_policy`]). With
+    /// [`TimestampPolicy::Repair`], the number of repaired timestamps is logged at the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - What to do with invalid timestamps.
+    pub fn with_timestamp_policy(mut self, policy: TimestampPolicy) -> Self {
+        self.decoder = self.decoder.with_timestamp_policy(policy);
+        self
+    }
+
+    /// Enable or disable copying the metadata of the source into the destination. This is enabled
+    /// by default. The metadata of the source as a whole (like the title and GPS coordinates
+    /// recorded by phones) goes into the destination as a whole, and the metadata of each stream
+    /// (like the language) into the stream it is transcoded or copied into.
+    ///
+    /// Keys that describe how the source was encoded rather than its contents (like `encoder`) are
+    /// left out, since the muxer writes its own. `creation_time` is copied, so that muxers that
+    /// store it in a field of their own (like the `mvhd` atom of MP4) write the time of the source
+    /// there. For MP4 and QuickTime, tags that these formats do not define are written as `mdta`
+    /// keys, since they would be dropped otherwise.
+    ///
+    /// When disabled, no metadata is copied at all, not even the language of the copied audio
+    /// streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether or not to copy metadata.
+    pub fn preserve_metadata(mut self, enabled: bool) -> Self {
+        self.metadata.preserve = enabled;
+        self
+    }
+
+    /// Do not copy any metadata of the source into the destination. This is the same as
+    /// `preserve_metadata(false)`.
+    pub fn strip_metadata(self) -> Self {
+        self.preserve_metadata(false)
+    }
+
+    /// Leave out the metadata entries with keys that match any of the given patterns, of the
+    /// source as a whole and of each stream. Patterns are matched against whole keys, ignoring
+    /// case, and may contain `*` (any number of characters) and `?` (any one character). Calling
+    /// this again adds to the patterns.
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - Key patterns to leave out.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Transcoder::new(
+    ///     &PathBuf::from("phone.mov").into(),
+    ///     &PathBuf::from("shared.mp4").into(),
+    ///     EncoderSettings::for_h264_yuv420p(1280, 720, false),
+    /// )
+    /// .unwrap()
+    /// .strip_keys(&["location", "com.apple.quicktime.*"])
+    /// .run()
+    /// .unwrap();
+    /// ```
+    pub fn strip_keys(mut self, patterns: &[&str]) -> Self {
+        self.metadata
+            .strip_keys
+            .extend(patterns.iter().map(|pattern| pattern.to_string()));
+        self
+    }
+
+    /// Transcode the whole source and finish the destination.
+    pub fn run(self) -> Result<()> {
+        self.run_with_cancel(&CancellationToken::new())
+    }
+
+    /// Transcode the whole source and finish the destination, and stop with [`Error::Cancelled`]
+    /// when the token is cancelled. Cancelling is checked between packets, and aborts a read that
+    /// is blocked as well.
+    ///
+    /// When cancelled, the frames that the encoder still holds are encoded and the destination is
+    /// finished (with its trailer written), so it is a valid file that ends where the transcode
+    /// stopped. If finishing fails too, the destination is left truncated. Either way, the source
+    /// and destination are closed when this returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Token to cancel the transcode with.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let token = CancellationToken::new();
+    /// let cancel = token.clone();
+    /// std::thread::spawn(move || {
+    ///     std::thread::sleep(Duration::from_secs(10));
+    ///     cancel.cancel();
+    /// });
+    /// let result = Transcoder::new(&source, &dest, settings).unwrap().run_with_cancel(&token);
+    /// ```
+    pub fn run_with_cancel(mut self, token: &CancellationToken) -> Result<()> {
+        let (mut decoder, mut reader, video_stream_index) = self.decoder.into_parts();
+        let _cancellation = reader.link_cancellation(token);
+        let decoder_time_base = decoder.time_base();
+        if let Some(frame_rate) = self.output_frame_rate {
+            self.output.frame_rate_converter =
+                Some(FrameRateConverter::new(frame_rate, decoder_time_base)?);
+        }
+        (self.output.source_start, self.output.source_duration) =
+            source_start_and_duration(&reader);
+
+        if let Some(subtitles) = self.subtitles.take() {
+            if let SubtitleSource::Stream(n) = subtitles {
+                let num_subtitle_streams = reader
+                    .input
+                    .streams()
+                    .filter(|stream| stream.parameters().medium() == AvMediaType::Subtitle)
+                    .count();
+                if n >= num_subtitle_streams {
+                    return Err(Error::InvalidSubtitleSource(format!(
+                        "source has no subtitle stream {n} ({num_subtitle_streams} subtitle \
+                         streams)"
+                    )));
+                }
+            }
+            let start_time = match input_start_time(&reader.input) {
+                AV_NOPTS_VALUE => 0,
+                start_time => start_time.rescale(TIME_BASE, decoder_time_base),
+            };
+            self.output.subtitle_burner = Some(SubtitleBurner::new(
+                &subtitles,
+                &self.source,
+                decoder_time_base,
+                start_time,
+            )?);
+        }
+
+        // Data streams that are not explicitly requested are left out if the destination
+        // container does not support them.
+        let data_optional = self.stream_map.is_none() && self.copy_data.is_none();
+        let copied_by_default = |parameters: &AvParameters| match parameters.medium() {
+            AvMediaType::Audio => self.copy_audio,
+            // Data streams without a known codec (like QuickTime timecode tracks) cannot be
+            // copied into another container.
+            AvMediaType::Data => {
+                self.copy_data != Some(false) && parameters.id() != ffmpeg::codec::Id::None
+            }
+            _ => false,
+        };
+        let has_stream_map = self.stream_map.is_some();
+        let mapped_streams = match self.stream_map.take() {
+            Some(stream_map) => stream_map.resolve(&reader.input)?,
+            None => reader
+                .input
+                .streams()
+                .filter(|stream| copied_by_default(&stream.parameters()))
+                .map(|stream| MappedStream {
+                    index: stream.index(),
+                    disposition: stream.disposition().bits(),
+                })
+                .collect(),
+        };
+        let mut data_stream_indices = reader.data_stream_indices();
+
+        if self.metadata.preserve {
+            copy_metadata(
+                &mut self.output.encoder,
+                &reader,
+                video_stream_index,
+                &self.metadata,
+            )
+            .map_err(encode_error)?;
+        }
+
+        // Maps source stream indices to destination stream indices of copied streams.
+        let mut copy_stream_indices = HashMap::new();
+        // Audio streams that are re-encoded to normalize their loudness, by source stream index.
+        let mut normalizers = HashMap::new();
+        for mapped_stream in mapped_streams {
+            if mapped_stream.index == video_stream_index {
+                self.output
+                    .encoder
+                    .set_video_stream_disposition(mapped_stream.disposition);
+                continue;
+            }
+
+            if reader
+                .input
+                .stream(mapped_stream.index)
+                .map(|stream| stream.parameters().medium())
+                == Some(AvMediaType::Video)
+            {
+                return Err(Error::InvalidStreamMap(format!(
+                    "video stream {} cannot be mapped, since only stream {video_stream_index} is \
+                     re-encoded",
+                    mapped_stream.index
+                )));
+            }
+            if let Some(target_lufs) = self.loudness_target {
+                let stream_info = mapped_stream_info(&reader, &mapped_stream, &self.metadata)?;
+                if stream_info.medium() == AvMediaType::Audio {
+                    let report = measure_stream_loudness(&self.source, mapped_stream.index)
+                        .map_err(decode_error)?;
+                    let normalizer = LoudnessNormalizer::new(
+                        &reader,
+                        stream_info,
+                        normalization_gain(&report, target_lufs),
+                        &mut self.output.encoder,
+                    )?;
+                    normalizers.insert(mapped_stream.index, normalizer);
+                    continue;
+                }
+            }
+            if let Some(writer_stream_index) = add_copy_stream(
+                &mut self.output.encoder,
+                &reader,
+                &mapped_stream,
+                &self.metadata,
+                data_optional,
+            )? {
+                copy_stream_indices.insert(mapped_stream.index, writer_stream_index);
+            }
+        }
+
+        loop {
+            if token.is_cancelled() {
+                return self.output.finish_cancelled();
+            }
+            let result = reader.read_any();
+            // Streams that appear in the source later on (like in live MPEG-TS sources) are copied
+            // like the streams that were there from the start, unless streams are mapped
+            // explicitly. They can only be added until the header of the destination is written.
+            for stream_index in reader.take_new_streams() {
+                let Some(stream) = reader.input.stream(stream_index) else {
+                    continue;
+                };
+                let parameters = stream.parameters();
+                if parameters.medium() == AvMediaType::Data {
+                    data_stream_indices.push(stream_index);
+                }
+                if has_stream_map || !copied_by_default(&parameters) {
+                    continue;
+                }
+                let mapped_stream = MappedStream {
+                    index: stream_index,
+                    disposition: stream.disposition().bits(),
+                };
+                match add_copy_stream(
+                    &mut self.output.encoder,
+                    &reader,
+                    &mapped_stream,
+                    &self.metadata,
+                    data_optional,
+                ) {
+                    Ok(Some(writer_stream_index)) => {
+                        copy_stream_indices.insert(stream_index, writer_stream_index);
+                    }
+                    Ok(None) => {}
+                    Err(Error::EncodeFailed(err))
+                        if matches!(*err, Error::HeaderAlreadyWritten) =>
+                    {
+                        tracing::warn!(
+                            target: "video",
+                            "leaving out stream {stream_index}, which appeared after the \
+                             destination header was written",
+                        );
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            match result {
+                Ok((stream_index, packet)) if stream_index == video_stream_index => {
+                    if let Some(frame) = decoder.decode_raw(packet).map_err(decode_error)? {
+                        self.output.encode(frame, decoder_time_base)?;
+                    }
+                }
+                Ok((stream_index, packet)) => {
+                    if let Some(hook) = self.data_packet_hook.as_mut() {
+                        if data_stream_indices.contains(&stream_index) {
+                            hook(stream_index, &packet);
+                        }
+                    }
+                    if let Some(normalizer) = normalizers.get_mut(&stream_index) {
+                        normalizer.push(packet, &mut self.output.encoder)?;
+                    } else if let Some(&writer_stream_index) =
+                        copy_stream_indices.get(&stream_index)
+                    {
+                        self.output
+                            .encoder
+                            .write_copy(packet, writer_stream_index)
+                            .map_err(encode_error)?;
+                    }
+                }
+                Err(Error::ReadExhausted) => break,
+                Err(_) if token.is_cancelled() => return self.output.finish_cancelled(),
+                Err(err) => return Err(decode_error(err)),
+            }
+        }
+
+        while let Some(frame) = decoder.drain_raw().map_err(decode_error)? {
+            self.output.encode(frame, decoder_time_base)?;
+        }
+        self.output.flush(decoder_time_base)?;
+        for normalizer in normalizers.values_mut() {
+            normalizer.finish(&mut self.output.encoder)?;
+        }
+        if decoder.timestamps_repaired() > 0 {
+            tracing::warn!(
+                target: "video",
+                "repaired {} invalid timestamps of the source",
+                decoder.timestamps_repaired(),
+            );
+        }
+
+        self.output.encoder.finish().map_err(encode_error)?;
+        if let (Some(progress), Some(_)) =
+            (self.output.progress.as_mut(), self.output.source_duration)
+        {
+            progress(100.0);
+        }
+
+        Ok(())
+    }
+}
+
+/// Which metadata entries of the source a [`Transcoder`] copies into the destination.
+struct MetadataFilter {
+    preserve: bool,
+    /// Patterns of keys to leave out (see [`Transcoder::strip_keys`]).
+    strip_keys: Vec<String>,
+}
+
+impl MetadataFilter {
+    /// Whether or not a metadata entry is copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the metadata entry.
+    fn keeps(&self, key: &str) -> bool {
+        self.preserve
+            && !self
+                .strip_keys
+                .iter()
+                .any(|pattern| glob_matches(pattern, key))
+    }
+}
+
+/// Copy the metadata of the source as a whole and of the video stream into the destination.
+/// Copied streams take their metadata along by themselves.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder of the destination.
+/// * `reader` - Reader of the source.
+/// * `video_stream_index` - Index of the video stream in the source.
+/// * `filter` - Which metadata entries to copy.
+fn copy_metadata(
+    encoder: &mut Encoder,
+    reader: &Reader,
+    video_stream_index: usize,
+    filter: &MetadataFilter,
+) -> Result<()> {
+    let global = reader
+        .input
+        .metadata()
+        .iter()
+        .filter(|(key, _)| filter.keeps(key))
+        .filter(|(key, _)| !ENCODING_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key)))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect::<Vec<_>>();
+    let video = reader
+        .input
+        .stream(video_stream_index)
+        .map(|stream| {
+            stream
+                .metadata()
+                .iter()
+                .filter(|(key, _)| filter.keeps(key))
+                .filter(|(key, _)| !is_stream_encoding_key(key))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if global
+        .iter()
+        .any(|(key, _)| !MOV_METADATA_KEYS.contains(&key.as_str()))
+    {
+        encoder.add_movflag(MovFlag::UseMetadataTags)?;
+    }
+    for (key, value) in global {
+        encoder.set_tag(TagTarget::Global, &key, &value)?;
+    }
+    let video_stream_index = encoder.video_stream_index();
+    for (key, value) in video {
+        encoder.set_tag(TagTarget::Stream(video_stream_index), &key, &value)?;
+    }
+
+    Ok(())
+}
+
+/// Metadata keys of the source as a whole that describe how it was written rather than its
+/// contents. The muxer writes its own values, if any.
+const ENCODING_KEYS: &[&str] = &[
+    "encoder",
+    "major_brand",
+    "minor_version",
+    "compatible_brands",
+];
+
+/// Global metadata keys that the MP4 and QuickTime muxers write without `use_metadata_tags`.
+const MOV_METADATA_KEYS: &[&str] = &[
+    "title",
+    "artist",
+    "author",
+    "album_artist",
+    "album",
+    "composer",
+    "date",
+    "comment",
+    "genre",
+    "copyright",
+    "grouping",
+    "lyrics",
+    "description",
+    "synopsis",
+    "show",
+    "episode_id",
+    "network",
+    "keywords",
+    "track",
+    "disc",
+    "compilation",
+    "location",
+    "creation_time",
+];
+
+/// Whether or not a metadata key of the video stream describes how the stream was encoded (like
+/// the statistics that Matroska muxers write), which no longer holds once it is re-encoded.
+///
+/// # Arguments
+///
+/// * `key` - Key of the metadata entry.
+fn is_stream_encoding_key(key: &str) -> bool {
+    [
+        "encoder",
+        "vendor_id",
+        "duration",
+        "bps",
+        "number_of_frames",
+        "number_of_bytes",
+    ]
+    .iter()
+    .any(|k| k.eq_ignore_ascii_case(key))
+        || glob_matches("_statistics_*", key)
+}
+
+/// Match a key against a pattern with `*` (any number of characters) and `?` (any one character)
+/// wildcards, ignoring case.
+///
+/// # Arguments
+///
+/// * `pattern` - Pattern to match.
+/// * `key` - Key to match against.
+fn glob_matches(pattern: &str, key: &str) -> bool {
+    let pattern = pattern.to_lowercase().chars().collect::<Vec<_>>();
+    let key = key.to_lowercase().chars().collect::<Vec<_>>();
+
+    // Position after the last `*` in the pattern and the key position it was matched up to, to
+    // backtrack to when the rest fails to match.
+    let (mut p, mut k) = (0, 0);
+    let mut star = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, k));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match star {
+                Some((star_p, star_k)) => {
+                    p = star_p;
+                    k = star_k + 1;
+                    star = Some((star_p, star_k + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Hook that can modify frames before they are encoded.
+enum FrameHook {
+    Raw(RawFrameHookFn),
+    #[cfg(feature = "ndarray")]
+    Frame(FrameHookFn),
+}
+
+type RawFrameHookFn = Box<dyn FnMut(&mut RawFrame, Time) + Send>;
+
+type DataPacketHookFn = Box<dyn FnMut(usize, &Packet) + Send>;
+
+#[cfg(feature = "ndarray")]
+type FrameHookFn = Box<dyn FnMut(&mut Frame, Time) + Send>;
+
+/// Encoding side of a [`Transcoder`].
+struct TranscodeOutput {
+    encoder: Encoder,
+    frame_hook: Option<FrameHook>,
+    subtitle_burner: Option<SubtitleBurner>,
+    frame_rate_converter: Option<FrameRateConverter<RawFrame>>,
+    progress: Option<Box<dyn FnMut(f32) + Send>>,
+    source_start: f64,
+    source_duration: Option<f64>,
+}
+
+impl TranscodeOutput {
+    /// Pass a decoded frame through the frame rate converter (if any), and encode the frames that
+    /// come out.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Decoded frame.
+    /// * `time_base` - Time base of the frame timestamp.
+    fn encode(&mut self, frame: RawFrame, time_base: AvRational) -> Result<()> {
+        let Some(frame_rate_converter) = self.frame_rate_converter.as_mut() else {
+            return self.encode_frame(frame, time_base);
+        };
+        let pts = frame.pts();
+        for (mut frame, pts) in frame_rate_converter.push(frame, pts) {
+            frame.set_pts(Some(pts));
+            self.encode_frame(frame, time_base)?;
+        }
+
+        Ok(())
+    }
+
+    /// Encode the last frames of the frame rate converter (if any).
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base of the frame timestamps.
+    fn flush(&mut self, time_base: AvRational) -> Result<()> {
+        let Some(frame_rate_converter) = self.frame_rate_converter.as_mut() else {
+            return Ok(());
+        };
+        for (mut frame, pts) in frame_rate_converter.flush() {
+            frame.set_pts(Some(pts));
+            self.encode_frame(frame, time_base)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finish the destination after the transcode was cancelled.
+    ///
+    /// # Return value
+    ///
+    /// Always [`Error::Cancelled`], whether or not finishing succeeded.
+    fn finish_cancelled(&mut self) -> Result<()> {
+        if let Err(err) = self.encoder.finish() {
+            tracing::warn!(target: "video", "finishing cancelled transcode failed: {err}");
+        }
+
+        Err(Error::Cancelled)
+    }
+
+    /// Pass a frame through the frame hook and encode it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    /// * `time_base` - Time base of the frame timestamp.
+    fn encode_frame(&mut self, mut frame: RawFrame, time_base: AvRational) -> Result<()> {
+        let timestamp = Time::new(frame.pts(), time_base);
+        if let Some(subtitle_burner) = self.subtitle_burner.as_mut() {
+            frame = subtitle_burner.burn(frame).map_err(encode_error)?;
+        }
+        match self.frame_hook.as_mut() {
+            #[cfg(feature = "ndarray")]
+            Some(FrameHook::Frame(hook)) => {
+                let mut frame = convert_frame_to_ndarray(&mut frame).map_err(decode_error)?;
+                hook(&mut frame, timestamp.clone());
+                self.encoder
+                    .encode(&frame, &timestamp)
+                    .map_err(encode_error)?;
+            }
+            frame_hook => {
+                if let Some(FrameHook::Raw(hook)) = frame_hook {
+                    hook(&mut frame, timestamp.clone());
+                }
+                frame.set_pts(
+                    timestamp
+                        .aligned_with_rational(self.encoder.time_base())
+                        .into_value(),
+                );
+                self.encoder.encode_raw(frame).map_err(encode_error)?;
+            }
+        }
+
+        if let (Some(progress), Some(source_duration)) =
+            (self.progress.as_mut(), self.source_duration)
+        {
+            progress(progress_percentage(
+                timestamp.as_secs_f64(),
+                self.source_start,
+                source_duration,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Add an output stream for a stream of the source that is copied without re-encoding.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder of the destination.
+/// * `reader` - Reader of the source.
+/// * `mapped_stream` - Stream of the source to copy, and its disposition.
+/// * `metadata` - Which metadata entries of the stream to copy.
+/// * `data_optional` - Whether or not to leave out data streams that the destination container
+///   does not support, instead of failing.
+///
+/// # Return value
+///
+/// Index of the output stream, or `None` if the stream was left out.
+fn add_copy_stream(
+    encoder: &mut Encoder,
+    reader: &Reader,
+    mapped_stream: &MappedStream,
+    metadata: &MetadataFilter,
+    data_optional: bool,
+) -> Result<Option<usize>> {
+    let stream_info = mapped_stream_info(reader, mapped_stream, metadata)?;
+    match encoder.add_copy_stream(stream_info) {
+        Ok(writer_stream_index) => Ok(Some(writer_stream_index)),
+        Err(Error::DataStreamsNotSupported(format)) if data_optional => {
+            tracing::warn!(
+                target: "video",
+                "leaving out data stream {} (not supported by {format})",
+                mapped_stream.index,
+            );
+            Ok(None)
+        }
+        Err(err) => Err(encode_error(err)),
+    }
+}
+
+/// Get the stream information of a mapped stream of the source, with its disposition and the
+/// metadata entries that are copied.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+/// * `mapped_stream` - Stream of the source, and its disposition.
+/// * `metadata` - Which metadata entries of the stream to copy.
+fn mapped_stream_info(
+    reader: &Reader,
+    mapped_stream: &MappedStream,
+    metadata: &MetadataFilter,
+) -> Result<StreamInfo> {
+    Ok(reader
+        .stream_info(mapped_stream.index)
+        .map_err(decode_error)?
+        .with_disposition(mapped_stream.disposition)
+        .retain_metadata(|key| metadata.keeps(key)))
+}
+
+/// Get the start time and duration of the source in seconds, as far as they are known.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the source.
+pub(crate) fn source_start_and_duration(reader: &Reader) -> (f64, Option<f64>) {
+    let to_secs = |value: i64| value as f64 / TIME_BASE.denominator() as f64;
+    let start = match input_start_time(&reader.input) {
+        AV_NOPTS_VALUE => 0.0,
+        start => to_secs(start),
+    };
+    let duration = match reader.input.duration() {
+        duration if duration > 0 => Some(to_secs(duration)),
+        _ => None,
+    };
+
+    (start, duration)
+}
+
+/// Compute the progress as a percentage from 0 to 100.
+///
+/// # Arguments
+///
+/// * `timestamp` - Timestamp of the last frame in seconds.
+/// * `start` - Start time of the source in seconds.
+/// * `duration` - Duration of the source in seconds.
+fn progress_percentage(timestamp: f64, start: f64, duration: f64) -> f32 {
+    ((timestamp - start) / duration * 100.0).clamp(0.0, 100.0) as f32
+}
+
+pub(crate) fn decode_error(err: Error) -> Error {
+    Error::DecodeFailed(Box::new(err))
+}
+
+pub(crate) fn encode_error(err: Error) -> Error {
+    Error::EncodeFailed(Box::new(err))
+}
+
+unsafe impl Send for Transcoder {}
+unsafe impl Sync for Transcoder {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, TempPath};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use ffmpeg::util::format::Pixel as AvPixel;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("location", "location"));
+        assert!(glob_matches("location", "LOCATION"));
+        assert!(!glob_matches("location", "location-eng"));
+        assert!(glob_matches(
+            "com.apple.quicktime.*",
+            "com.apple.quicktime.location.ISO6709"
+        ));
+        assert!(glob_matches("*.make", "com.apple.quicktime.make"));
+        assert!(!glob_matches("*.make", "com.apple.quicktime.model"));
+        assert!(glob_matches("a*b*c", "axxbyyc"));
+        assert!(!glob_matches("a*b*c", "axxbyy"));
+        assert!(glob_matches("???", "bps"));
+        assert!(!glob_matches("??", "bps"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn test_progress_percentage() {
+        assert_eq!(progress_percentage(5.0, 0.0, 10.0), 50.0);
+        assert_eq!(progress_percentage(6.4, 1.4, 10.0), 50.0);
+        assert_eq!(progress_percentage(0.0, 1.4, 10.0), 0.0);
+        assert_eq!(progress_percentage(12.0, 0.0, 10.0), 100.0);
+    }
+
+    /// Write a NUT file with one second of raw video at 30 fps and two PCM audio tracks with
+    /// language tags. Audio starts 200 ms after video.
+    fn write_source_with_audio(path: &std::path::Path) {
+        use ffmpeg::codec::{context::Context as AvContext, packet::Flags, Id as AvCodecId};
+        use ffmpeg::format::{sample::Type as AvSampleType, Sample as AvSample};
+        use ffmpeg::{codec::packet::Packet as AvPacket, ChannelLayout, Dictionary};
+
+        let mut output = ffmpeg::format::output(&path).unwrap();
+
+        let mut video = AvContext::new().encoder().video().unwrap();
+        video.set_width(64);
+        video.set_height(48);
+        video.set_format(AvPixel::RGB24);
+        video.set_time_base((1, 30));
+        let video = video
+            .open_as(ffmpeg::encoder::find(AvCodecId::RAWVIDEO))
+            .unwrap();
+        let mut stream = output
+            .add_stream(ffmpeg::encoder::find(AvCodecId::RAWVIDEO))
+            .unwrap();
+        stream.set_parameters(&video);
+        stream.set_time_base((1, 30));
+
+        for language in ["eng", "deu"] {
+            let mut audio = AvContext::new().encoder().audio().unwrap();
+            audio.set_rate(8000);
+            audio.set_channel_layout(ChannelLayout::MONO);
+            audio.set_channels(1);
+            audio.set_format(AvSample::I16(AvSampleType::Packed));
+            audio.set_time_base((1, 8000));
+            let audio = audio
+                .open_as(ffmpeg::encoder::find(AvCodecId::PCM_S16LE))
+                .unwrap();
+            let mut stream = output
+                .add_stream(ffmpeg::encoder::find(AvCodecId::PCM_S16LE))
+                .unwrap();
+            stream.set_parameters(&audio);
+            stream.set_time_base((1, 1000));
+            let mut metadata = Dictionary::new();
+            metadata.set("language", language);
+            stream.set_metadata(metadata);
+        }
+
+        output.write_header().unwrap();
+        let time_bases = output
+            .streams()
+            .map(|stream| stream.time_base())
+            .collect::<Vec<_>>();
+        let mut write = |stream_index: usize, size: usize, pts: i64, time_base: AvRational| {
+            let mut packet = AvPacket::copy(&vec![0; size]);
+            packet.set_stream(stream_index);
+            packet.set_pts(Some(pts));
+            packet.set_dts(Some(pts));
+            packet.set_duration(1);
+            packet.set_flags(Flags::KEY);
+            packet.rescale_ts(time_base, time_bases[stream_index]);
+            packet.write_interleaved(&mut output).unwrap();
+        };
+        for i in 0..30 {
+            write(0, 64 * 48 * 3, i, AvRational::new(1, 30));
+        }
+        // Packets of 100 ms (800 samples of two bytes).
+        for i in 2..10 {
+            write(1, 1600, i, AvRational::new(1, 10));
+            write(2, 1600, i, AvRational::new(1, 10));
+        }
+        output.write_trailer().unwrap();
+    }
+
+    /// Write a NUT file with three seconds of raw video at 30 fps and a stereo PCM audio track
+    /// with a 1 kHz sine at a peak level (in dBFS).
+    fn write_source_with_tone(path: &std::path::Path, level_db: f64) {
+        use ffmpeg::codec::{context::Context as AvContext, packet::Flags, Id as AvCodecId};
+        use ffmpeg::format::{sample::Type as AvSampleType, Sample as AvSample};
+        use ffmpeg::{codec::packet::Packet as AvPacket, ChannelLayout};
+
+        let mut output = ffmpeg::format::output(&path).unwrap();
+        let mut video = AvContext::new().encoder().video().unwrap();
+        video.set_width(64);
+        video.set_height(48);
+        video.set_format(AvPixel::RGB24);
+        video.set_time_base((1, 30));
+        let video = video
+            .open_as(ffmpeg::encoder::find(AvCodecId::RAWVIDEO))
+            .unwrap();
+        let mut stream = output
+            .add_stream(ffmpeg::encoder::find(AvCodecId::RAWVIDEO))
+            .unwrap();
+        stream.set_parameters(&video);
+        stream.set_time_base((1, 30));
+
+        let mut audio = AvContext::new().encoder().audio().unwrap();
+        audio.set_rate(48000);
+        audio.set_channel_layout(ChannelLayout::STEREO);
+        audio.set_channels(2);
+        audio.set_format(AvSample::I16(AvSampleType::Packed));
+        audio.set_time_base((1, 48000));
+        let audio = audio
+            .open_as(ffmpeg::encoder::find(AvCodecId::PCM_S16LE))
+            .unwrap();
+        let mut stream = output
+            .add_stream(ffmpeg::encoder::find(AvCodecId::PCM_S16LE))
+            .unwrap();
+        stream.set_parameters(&audio);
+        stream.set_time_base((1, 48000));
+
+        output.write_header().unwrap();
+        let time_bases = output
+            .streams()
+            .map(|stream| stream.time_base())
+            .collect::<Vec<_>>();
+        let mut write = |stream_index: usize, data: &[u8], pts: i64, time_base: AvRational| {
+            let mut packet = AvPacket::copy(data);
+            packet.set_stream(stream_index);
+            packet.set_pts(Some(pts));
+            packet.set_dts(Some(pts));
+            packet.set_duration(1);
+            packet.set_flags(Flags::KEY);
+            packet.rescale_ts(time_base, time_bases[stream_index]);
+            packet.write_interleaved(&mut output).unwrap();
+        };
+        let amplitude = 10f64.powf(level_db / 20.0) * i16::MAX as f64;
+        for i in 0..30 {
+            for j in 0..3 {
+                write(
+                    0,
+                    &vec![128; 64 * 48 * 3],
+                    i * 3 + j,
+                    AvRational::new(1, 30),
+                );
+            }
+            // Packets of 100 ms.
+            let samples = (i * 4800..(i + 1) * 4800)
+                .flat_map(|n| {
+                    let phase = 2.0 * std::f64::consts::PI * 1000.0 * n as f64 / 48000.0;
+                    let sample = (amplitude * phase.sin()).round() as i16;
+                    [sample, sample]
+                })
+                .flat_map(i16::to_le_bytes)
+                .collect::<Vec<_>>();
+            write(1, &samples, i, AvRational::new(1, 10));
+        }
+        output.write_trailer().unwrap();
+    }
+
+    #[test]
+    fn test_transcode_normalize_loudness() {
+        let dir = TempPath::new("transcode-loudness");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("source.nut");
+        write_source_with_tone(&source_path, -33.0);
+        let source: Locator = source_path.into();
+        let report = crate::measure_loudness(&source).unwrap();
+        assert!((report.integrated_lufs + 33.0).abs() < 0.5, "{report:?}");
+
+        // PCM is kept in Matroska, and encoded to AAC in MP4.
+        for (name, codec) in [("dest.mkv", "pcm_s16le"), ("dest.mp4", "aac")] {
+            let dest: Locator = dir.join(name).into();
+            Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+                .unwrap()
+                .normalize_loudness(-23.0)
+                .run()
+                .unwrap();
+
+            let reader = Reader::new(&dest).unwrap();
+            let audio = reader.best_audio_stream_index().unwrap();
+            assert_eq!(reader.stream_info(audio).unwrap().codec_name(), codec);
+            let report = crate::measure_loudness(&dest).unwrap();
+            assert!((report.integrated_lufs + 23.0).abs() < 0.5, "{report:?}");
+            assert!(report.true_peak < 0.0, "{report:?}");
+        }
+    }
+
+    /// Get the first audio timestamp minus the first video timestamp in seconds.
+    fn audio_video_offset(locator: &Locator) -> f64 {
+        let mut reader = Reader::new(locator).unwrap();
+        let video_stream_index = reader.best_video_stream_index().unwrap();
+        let (mut first_video, mut first_audio) = (None, None);
+        while first_video.is_none() || first_audio.is_none() {
+            let (stream_index, packet) = reader.read_any().unwrap();
+            let first = match stream_index == video_stream_index {
+                true => &mut first_video,
+                false => &mut first_audio,
+            };
+            first.get_or_insert(packet.pts().as_secs_f64());
+        }
+        first_audio.unwrap() - first_video.unwrap()
+    }
+
+    #[test]
+    fn test_transcode_copies_audio_streams() {
+        let dir = TempPath::new("transcode-audio");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("source.nut");
+        write_source_with_audio(&source_path);
+        let source: Locator = source_path.into();
+        let dest: Locator = dir.join("dest.mkv").into();
+
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .copy_audio(true)
+            .run()
+            .unwrap();
+
+        let reader = Reader::new(&dest).unwrap();
+        let languages = reader
+            .input
+            .streams()
+            .filter(|stream| stream.parameters().medium() == AvMediaType::Audio)
+            .map(|stream| stream.metadata().get("language").map(str::to_string))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            languages,
+            vec![Some("eng".to_string()), Some("deu".to_string())]
+        );
+        let source_offset = audio_video_offset(&source);
+        assert!((source_offset - 0.2).abs() < 0.01);
+        let report = crate::verify_sync(&source, &dest).unwrap();
+        assert!(report.in_sync, "{report}");
+    }
+
+    #[test]
+    fn test_transcode_with_frame_hook_and_progress() {
+        const NUM_FRAMES: usize = 30;
+
+        let dir = TempPath::new("transcode");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let dest: Locator = dir.join("dest.mp4").into();
+
+        testing::write_test_video(
+            &source,
+            (0..NUM_FRAMES).map(|i| testing::filled_frame(i as u8)),
+        );
+
+        let num_hooked = Arc::new(AtomicUsize::new(0));
+        let last_progress = Arc::new(Mutex::new(0.0));
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(32, 24, false))
+            .unwrap()
+            .with_raw_frame_hook({
+                let num_hooked = num_hooked.clone();
+                move |frame, _| {
+                    assert_eq!((frame.width(), frame.height()), (32, 24));
+                    num_hooked.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .with_progress({
+                let last_progress = last_progress.clone();
+                move |percentage| *last_progress.lock().unwrap() = percentage
+            })
+            .run()
+            .unwrap();
+        assert_eq!(num_hooked.load(Ordering::SeqCst), NUM_FRAMES);
+        assert_eq!(*last_progress.lock().unwrap(), 100.0);
+
+        let mut decoder = Decoder::new(&dest).unwrap();
+        assert_eq!(decoder.size(), (32, 24));
+        assert_eq!(
+            decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+            NUM_FRAMES
+        );
+    }
+
+    #[test]
+    fn test_transcode_cancelled_midway() {
+        const NUM_FRAMES: usize = 60;
+
+        let dir = TempPath::new("cancel");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let dest: Locator = dir.join("dest.mkv").into();
+
+        testing::write_test_video(&source, testing::frames(NUM_FRAMES));
+
+        let token = CancellationToken::new();
+        let result = Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .with_raw_frame_hook({
+                let token = token.clone();
+                let num_hooked = AtomicUsize::new(0);
+                move |_, _| {
+                    if num_hooked.fetch_add(1, Ordering::SeqCst) == 9 {
+                        token.cancel();
+                    }
+                }
+            })
+            .run_with_cancel(&token);
+        assert!(matches!(result, Err(Error::Cancelled)));
+
+        // The destination is finished, and ends where the transcode stopped.
+        let mut decoder = Decoder::new(&dest).unwrap();
+        let num_frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+        assert!((10..NUM_FRAMES).contains(&num_frames));
+
+        let mut decoder = Decoder::new(&source).unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(
+            decoder.count_frames_exact_with_cancel(&token),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            decoder.detect_scene_changes_with_cancel(0.1, &token),
+            Err(Error::Cancelled)
+        ));
+        // The decoder can be used again afterwards.
+        assert_eq!(decoder.count_frames_exact().unwrap(), NUM_FRAMES as u64);
+    }
+
+    #[test]
+    fn test_transcode_repairs_broken_timestamps() {
+        const NUM_FRAMES: usize = 30;
+
+        let dir = TempPath::new("timestamps");
+        std::fs::create_dir_all(&dir).unwrap();
+        let segment = dir.join("segment.ts");
+        let source: Locator = dir.join("source.ts").into();
+        let dest: Locator = dir.join("dest.mkv").into();
+
+        testing::write_test_video(&segment.clone().into(), testing::frames(NUM_FRAMES));
+        // Joining the bytes of two segments makes the timestamps jump back where the second one
+        // starts, like in files of cameras that restart recording.
+        let segment = std::fs::read(&segment).unwrap();
+        std::fs::write(dir.join("source.ts"), [segment.clone(), segment].concat()).unwrap();
+
+        let result = Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .with_timestamp_policy(TimestampPolicy::Reject)
+            .run();
+        match result {
+            Err(Error::DecodeFailed(err)) => assert!(matches!(
+                *err,
+                Error::InvalidTimestamp {
+                    frame_index: 30,
+                    ..
+                }
+            )),
+            result => panic!("expected invalid timestamp, got {result:?}"),
+        }
+
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .with_timestamp_policy(TimestampPolicy::Repair)
+            .run()
+            .unwrap();
+        // Decoding stops at the first timestamp that does not increase.
+        let mut decoder = Decoder::new(&dest)
+            .unwrap()
+            .with_timestamp_policy(TimestampPolicy::Reject);
+        let timestamps = decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .map(|frame| frame.unwrap().pts().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps.len(), 2 * NUM_FRAMES);
+
+        let mut decoder = Decoder::new(&source)
+            .unwrap()
+            .with_timestamp_policy(TimestampPolicy::Repair);
+        assert_eq!(
+            decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+            2 * NUM_FRAMES
+        );
+        assert_eq!(decoder.timestamps_repaired(), NUM_FRAMES as u64);
+    }
+
+    #[test]
+    fn test_transcode_preserves_metadata() {
+        use crate::WriterBuilder;
+
+        let dir = TempPath::new("transcode-meta");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mp4").into();
+
+        let mut encoder = Encoder::new_with_builder(
+            WriterBuilder::new(&source).movflags(&[MovFlag::UseMetadataTags]),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap();
+        encoder
+            .set_tag(TagTarget::Global, "com.example.camera", "rig-7")
+            .unwrap();
+        encoder
+            .set_tag(TagTarget::Global, "location", "+52.3700+004.8900/")
+            .unwrap();
+        encoder
+            .set_tag(TagTarget::Stream(0), "language", "fra")
+            .unwrap();
+        testing::encode_frames(&mut encoder, 0, testing::frames(10));
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        let transcode = |name: &str, configure: fn(Transcoder) -> Transcoder| {
+            let dest: Locator = dir.join(name).into();
+            configure(
+                Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false)).unwrap(),
+            )
+            .run()
+            .unwrap();
+            let decoder = Decoder::new(&dest).unwrap();
+            let tag = |target, key: &str| {
+                decoder
+                    .tags(target)
+                    .unwrap()
+                    .into_iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, value)| value)
+            };
+            (
+                tag(TagTarget::Global, "com.example.camera"),
+                tag(TagTarget::Global, "location"),
+                tag(TagTarget::Stream(0), "language"),
+            )
+        };
+
+        let (camera, location, language) = transcode("preserved.mp4", |t| t);
+        assert_eq!(camera.as_deref(), Some("rig-7"));
+        assert!(location.is_some());
+        assert_eq!(language.as_deref(), Some("fra"));
+
+        let (camera, location, language) = transcode("filtered.mp4", |t| t.strip_keys(&["LOC*"]));
+        assert_eq!(camera.as_deref(), Some("rig-7"));
+        assert_eq!(location, None);
+        assert_eq!(language.as_deref(), Some("fra"));
+
+        let (camera, location, language) = transcode("stripped.mp4", Transcoder::strip_metadata);
+        assert_eq!(camera, None);
+        assert_eq!(location, None);
+        assert_ne!(language.as_deref(), Some("fra"));
+    }
+
+    #[test]
+    fn test_transcode_with_output_frame_rate() {
+        const NUM_FRAMES: i64 = 48;
+
+        let dir = TempPath::new("transcode-fps");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let dest: Locator = dir.join("dest.mp4").into();
+
+        // Two seconds at 23.976 fps.
+        let mut encoder = Encoder::new(&source, Settings::for_h264_yuv420p(64, 48, false)).unwrap();
+        for i in 0..NUM_FRAMES {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 64, 48);
+            frame.data_mut(0).fill(i as u8);
+            frame.set_pts(
+                Time::new(Some(i * 1001), AvRational::new(1, 24000))
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value(),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .with_output_frame_rate(30.0)
+            .run()
+            .unwrap();
+
+        let mut decoder = Decoder::new(&dest).unwrap();
+        let timestamps = decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .map(|frame| frame.unwrap().pts().unwrap())
+            .collect::<Vec<_>>();
+        let expected = (NUM_FRAMES as f64 * 1001.0 / 24000.0 * 30.0).round() as usize;
+        assert_eq!(timestamps.len(), expected);
+        let time_base = decoder.time_base();
+        for (i, pts) in timestamps.iter().enumerate() {
+            let secs = Time::new(Some(*pts), time_base).as_secs_f64();
+            assert!((secs - i as f64 / 30.0).abs() < 0.002);
+        }
+
+        assert!(matches!(
+            Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+                .unwrap()
+                .with_output_frame_rate(0.0)
+                .run(),
+            Err(Error::InvalidOptions(_))
+        ));
+    }
+
+    /// Write an MPEG-TS file with one second of H.264 video at 30 fps and a KLV data stream with
+    /// a packet every 100 ms. Returns the payloads of the KLV packets.
+    fn write_ts_source_with_klv(dir: &std::path::Path) -> Vec<Vec<u8>> {
+        use ffmpeg::codec::{packet::Packet as AvPacket, Id as AvCodecId, Parameters};
+
+        let video_path = dir.join("video.mkv");
+        testing::write_test_video(
+            &video_path.clone().into(),
+            (0..30).map(|i| testing::filled_frame(i as u8)),
+        );
+
+        let mut reader = Reader::new(&video_path.into())
+            .unwrap()
+            .with_bitstream_filter(0, "h264_mp4toannexb")
+            .unwrap();
+        let mut output = ffmpeg::format::output(&dir.join("source.ts")).unwrap();
+        let mut video_stream = output
+            .add_stream(ffmpeg::encoder::find(AvCodecId::H264))
+            .unwrap();
+        let (_, video_parameters, video_time_base) = reader.stream_info(0).unwrap().into_parts();
+        video_stream.set_parameters(video_parameters);
+        let mut klv_parameters = Parameters::new();
+        unsafe {
+            let parameters = klv_parameters.as_mut_ptr();
+            (*parameters).codec_type = ffmpeg::ffi::AVMediaType::AVMEDIA_TYPE_DATA;
+            (*parameters).codec_id = ffmpeg::ffi::AVCodecID::AV_CODEC_ID_SMPTE_KLV;
+        }
+        output
+            .add_stream(ffmpeg::encoder::find(AvCodecId::None))
+            .unwrap()
+            .set_parameters(klv_parameters);
+        output.write_header().unwrap();
+        let time_bases = output
+            .streams()
+            .map(|stream| stream.time_base())
+            .collect::<Vec<_>>();
+
+        let payloads = (0..10u8)
+            .map(|i| {
+                // Universal label key of a UAS datalink local set, a BER length and the value.
+                let mut payload = vec![
+                    0x06, 0x0e, 0x2b, 0x34, 0x02, 0x0b, 0x01, 0x01, 0x0e, 0x01, 0x03, 0x01, 0x01,
+                    0x00, 0x00, 0x00, 0x04,
+                ];
+                payload.extend([0x41, 0x02, 0x00, i]);
+                payload
+            })
+            .collect::<Vec<_>>();
+        let mut klv_packets = payloads.iter().enumerate().map(|(i, payload)| {
+            let mut packet = AvPacket::copy(payload);
+            packet.set_stream(1);
+            packet.set_pts(Some(i as i64));
+            packet.set_dts(Some(i as i64));
+            packet.rescale_ts(AvRational::new(1, 10), time_bases[1]);
+            packet
+        });
+        let mut next_klv = klv_packets.next();
+        while let Ok(packet) = reader.read(0) {
+            let (mut packet, _) = packet.into_inner_parts();
+            packet.set_stream(0);
+            packet.set_position(-1);
+            packet.rescale_ts(video_time_base, time_bases[0]);
+            let dts = Time::new(packet.dts(), time_bases[0]);
+            while let Some(klv_packet) =
+                next_klv.take_if(|klv_packet| Time::new(klv_packet.dts(), time_bases[1]) <= dts)
+            {
+                klv_packet.write_interleaved(&mut output).unwrap();
+                next_klv = klv_packets.next();
+            }
+            packet.write_interleaved(&mut output).unwrap();
+        }
+        for klv_packet in next_klv.into_iter().chain(klv_packets) {
+            klv_packet.write_interleaved(&mut output).unwrap();
+        }
+        output.write_trailer().unwrap();
+
+        payloads
+    }
+
+    #[test]
+    fn test_transcode_copies_data_streams() {
+        let dir = TempPath::new("transcode-klv");
+        std::fs::create_dir_all(&dir).unwrap();
+        let payloads = write_ts_source_with_klv(&dir);
+        let source: Locator = dir.join("source.ts").into();
+        let dest: Locator = dir.join("dest.ts").into();
+
+        let hooked = Arc::new(Mutex::new(Vec::new()));
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(64, 48, false))
+            .unwrap()
+            .with_data_packet_hook({
+                let hooked = hooked.clone();
+                move |_, packet| hooked.lock().unwrap().push(packet.data().to_vec())
+            })
+            .run()
+            .unwrap();
+        assert_eq!(*hooked.lock().unwrap(), payloads);
+
+        let mut reader = Reader::new(&dest).unwrap();
+        let data_stream_indices = reader.data_stream_indices();
+        assert_eq!(data_stream_indices.len(), 1);
+        let mut copied = Vec::new();
+        while let Ok(packet) = reader.read(data_stream_indices[0]) {
+            copied.push(packet.data().to_vec());
+        }
+        assert_eq!(copied, payloads);
+
+        // MP4 cannot hold KLV: it is left out by default, and an error when asked for.
+        Transcoder::new(
+            &source,
+            &dir.join("dest.mp4").into(),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap()
+        .run()
+        .unwrap();
+        let result = Transcoder::new(
+            &source,
+            &dir.join("dest-klv.mp4").into(),
+            Settings::for_h264_yuv420p(64, 48, false),
+        )
+        .unwrap()
+        .copy_data(true)
+        .run();
+        assert!(matches!(
+            result,
+            Err(Error::EncodeFailed(err)) if matches!(*err, Error::DataStreamsNotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_transcode_burns_subtitles() {
+        const NUM_FRAMES: usize = 30;
+
+        let dir = TempPath::new("transcode-subs");
+        std::fs::create_dir_all(&dir).unwrap();
+        let source: Locator = dir.join("source.mkv").into();
+        let dest: Locator = dir.join("dest.mkv").into();
+        let subtitles = dir.join("subtitles.srt");
+        std::fs::write(
+            &subtitles,
+            "1\n00:00:00,500 --> 00:00:00,700\nHELLO WORLD\n\n",
+        )
+        .unwrap();
+
+        let mut encoder =
+            Encoder::new(&source, Settings::for_h264_yuv420p(320, 240, false)).unwrap();
+        for i in 0..NUM_FRAMES {
+            let mut frame = RawFrame::new(AvPixel::RGB24, 320, 240);
+            frame.data_mut(0).fill(0);
+            frame.set_pts(
+                Time::from_nth_of_a_second(30)
+                    .aligned_with_rational(encoder.time_base())
+                    .into_value()
+                    .map(|frame_duration| frame_duration * i as i64),
+            );
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        Transcoder::new(&source, &dest, Settings::for_h264_yuv420p(320, 240, false))
+            .unwrap()
+            .burn_subtitles(SubtitleSource::File(subtitles))
+            .run()
+            .unwrap();
+
+        let mut decoder = Decoder::new(&dest).unwrap();
+        for (i, frame) in decoder
+            .decode_raw_iter()
+            .take_while(Result::is_ok)
+            .enumerate()
+        {
+            let frame = frame.unwrap();
+            let has_text = frame.data(0).iter().any(|&value| value > 128);
+            // The cue covers frames 15 up to (not including) 21.
+            assert_eq!(has_text, (15..21).contains(&i), "frame {i}");
+        }
+    }
+}